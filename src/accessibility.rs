@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+
+// Color vision deficiency simulations applied after tonemapping, to check that palettes
+// still read for everyone
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorFilter {
+  None,
+  // no red cones
+  Protanopia,
+  // no green cones, the most common
+  Deuteranopia,
+  // no blue cones
+  Tritanopia,
+}
+
+impl ColorFilter {
+  pub fn next(self) -> Self {
+    match self {
+      ColorFilter::None => ColorFilter::Protanopia,
+      ColorFilter::Protanopia => ColorFilter::Deuteranopia,
+      ColorFilter::Deuteranopia => ColorFilter::Tritanopia,
+      ColorFilter::Tritanopia => ColorFilter::None,
+    }
+  }
+
+  // Linear RGB in, simulated linear RGB out, one row per output channel (Machado, Oliveira
+  // and Fernandes 2009, full severity)
+  pub fn rows(self) -> [[f32; 3]; 3] {
+    match self {
+      ColorFilter::None => [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+      ColorFilter::Protanopia => [
+        [0.152286, 1.052583, -0.204868],
+        [0.114503, 0.786281, 0.099216],
+        [-0.003882, -0.048116, 1.051998],
+      ],
+      ColorFilter::Deuteranopia => [
+        [0.367322, 0.860646, -0.227968],
+        [0.280085, 0.672501, 0.047413],
+        [-0.011820, 0.042940, 0.968881],
+      ],
+      ColorFilter::Tritanopia => [
+        [1.255528, -0.076749, -0.178779],
+        [-0.078411, 0.930809, 0.147602],
+        [0.004733, 0.691367, 0.303900],
+      ],
+    }
+  }
+
+  // rows() as the columns of a WGSL mat3x3, each padded to a vec4
+  pub fn columns(self) -> [[f32; 4]; 3] {
+    let rows = self.rows();
+    [0, 1, 2].map(|c| [rows[0][c], rows[1][c], rows[2][c], 0.0])
+  }
+}
+
+// The `[accessibility]` section of settings.toml
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccessibilitySettings {
+  // V cycles through them
+  pub color_filter: ColorFilter,
+  // outlined, brighter overlay text, H toggles it
+  pub high_contrast: bool,
+}
+
+impl Default for AccessibilitySettings {
+  fn default() -> Self {
+    Self {
+      color_filter: ColorFilter::None,
+      high_contrast: false,
+    }
+  }
+}
@@ -0,0 +1,217 @@
+use wgpu::{Adapter, Backends, Device, DeviceType, Instance, PowerPreference, Queue, Surface};
+
+// Name (case-insensitive substring) or index of the adapter to use
+pub const ADAPTER_ENV: &str = "WGPU_LEARN_ADAPTER";
+// "high" or "low"
+pub const POWER_ENV: &str = "WGPU_LEARN_POWER";
+// Comma separated list of vulkan, metal, dx12, dx11, gl, webgpu
+pub const BACKEND_ENV: &str = "WGPU_LEARN_BACKEND";
+
+pub fn parse_backends(value: &str) -> Result<Backends, String> {
+  value
+    .split(',')
+    .map(|name| match name.trim().to_lowercase().as_str() {
+      "vulkan" | "vk" => Ok(Backends::VULKAN),
+      "metal" => Ok(Backends::METAL),
+      "dx12" | "d3d12" => Ok(Backends::DX12),
+      "dx11" | "d3d11" => Ok(Backends::DX11),
+      "gl" | "opengl" | "gles" => Ok(Backends::GL),
+      "webgpu" => Ok(Backends::BROWSER_WEBGPU),
+      "all" => Ok(Backends::all()),
+      "primary" => Ok(Backends::PRIMARY),
+      other => Err(format!(
+        "unknown backend '{}', expected vulkan, metal, dx12, dx11, gl, webgpu, primary or all",
+        other
+      )),
+    })
+    .try_fold(Backends::empty(), |acc, b| b.map(|b| acc | b))
+}
+
+// Backends from `arg` (--backend <list>) / `WGPU_LEARN_BACKEND`, then `configured` from the
+// settings, all of them when none is set
+pub fn backends_from_env(arg: Option<&str>, configured: Option<&str>) -> Result<Backends, String> {
+  let value = arg
+    .map(str::to_string)
+    .or_else(|| std::env::var(BACKEND_ENV).ok())
+    .or_else(|| configured.map(str::to_string));
+  match value {
+    Some(value) => parse_backends(&value),
+    None => Ok(Backends::all()),
+  }
+}
+
+// Enumerates every adapter that can present to the surface and picks one,
+// preferring discrete GPUs unless low power was asked for. wgpu's own default is
+// LowPower, so it's not used here.
+pub struct AdapterPicker {
+  backends: Backends,
+  power_preference: PowerPreference,
+  adapter_override: Option<String>,
+}
+
+impl AdapterPicker {
+  pub fn new(backends: Backends) -> Self {
+    Self {
+      backends,
+      power_preference: PowerPreference::HighPerformance,
+      adapter_override: None,
+    }
+  }
+
+  // Takes the override from `adapter` (--adapter <name>) / `WGPU_LEARN_ADAPTER`
+  // and the power preference from `high_performance` / `WGPU_LEARN_POWER`
+  pub fn from_env(backends: Backends, adapter: Option<&str>, high_performance: bool) -> Self {
+    let adapter_override = adapter
+      .map(str::to_string)
+      .or_else(|| std::env::var(ADAPTER_ENV).ok());
+    let power_preference = if high_performance {
+      PowerPreference::HighPerformance
+    } else {
+      match std::env::var(POWER_ENV).as_deref() {
+        Ok("high") => PowerPreference::HighPerformance,
+        Ok("low") => PowerPreference::LowPower,
+        _ => PowerPreference::HighPerformance,
+      }
+    };
+
+    Self::new(backends)
+      .power_preference(power_preference)
+      .adapter_override(adapter_override)
+  }
+
+  pub fn power_preference(mut self, power_preference: PowerPreference) -> Self {
+    self.power_preference = power_preference;
+    self
+  }
+
+  pub fn adapter_override(mut self, adapter_override: Option<String>) -> Self {
+    self.adapter_override = adapter_override.filter(|s| !s.is_empty());
+    self
+  }
+
+  // Lower is better
+  fn rank(&self, device_type: DeviceType) -> u8 {
+    let (first, second) = match self.power_preference {
+      PowerPreference::LowPower => (DeviceType::IntegratedGpu, DeviceType::DiscreteGpu),
+      _ => (DeviceType::DiscreteGpu, DeviceType::IntegratedGpu),
+    };
+    match device_type {
+      t if t == first => 0,
+      t if t == second => 1,
+      DeviceType::VirtualGpu => 2,
+      DeviceType::Other => 3,
+      _ => 4,
+    }
+  }
+
+  #[cfg(not(target_arch = "wasm32"))]
+  pub async fn pick(&self, instance: &Instance, surface: &Surface) -> Option<Adapter> {
+    let mut adapters: Vec<Adapter> = instance
+      .enumerate_adapters(self.backends)
+      .filter(|adapter| {
+        let info = adapter.get_info();
+        let supported = adapter.is_surface_supported(surface);
+        log::info!(
+          "adapter: {} ({:?}, {:?}){}",
+          info.name,
+          info.backend,
+          info.device_type,
+          if supported { "" } else { " - can't present" }
+        );
+        supported
+      })
+      .collect();
+
+    if let Some(wanted) = &self.adapter_override {
+      let position = match wanted.parse::<usize>() {
+        Ok(index) if index < adapters.len() => Some(index),
+        _ => {
+          let wanted = wanted.to_lowercase();
+          adapters
+            .iter()
+            .position(|a| a.get_info().name.to_lowercase().contains(&wanted))
+        }
+      };
+      match position {
+        Some(index) => return Some(adapters.swap_remove(index)),
+        None => log::warn!("no adapter matches '{}', picking automatically", wanted),
+      }
+    }
+
+    adapters.sort_by_key(|a| self.rank(a.get_info().device_type));
+    let adapter = adapters.into_iter().next();
+    if let Some(adapter) = &adapter {
+      let info = adapter.get_info();
+      log::info!("using adapter: {} ({:?})", info.name, info.backend);
+    }
+    adapter
+  }
+
+  // Browsers don't expose adapter enumeration, fall back to asking for one
+  #[cfg(target_arch = "wasm32")]
+  pub async fn pick(&self, instance: &Instance, surface: &Surface) -> Option<Adapter> {
+    instance
+      .request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: self.power_preference,
+        compatible_surface: Some(surface),
+        force_fallback_adapter: false,
+      })
+      .await
+  }
+}
+
+// Optional features are only requested when the adapter has them
+pub async fn request_device(
+  adapter: &Adapter,
+) -> Result<(Device, Queue), wgpu::RequestDeviceError> {
+  let features = adapter.features()
+    & (wgpu::Features::TIMESTAMP_QUERY
+      | wgpu::Features::PIPELINE_STATISTICS_QUERY
+      | wgpu::Features::MULTI_DRAW_INDIRECT
+      | wgpu::Features::POLYGON_MODE_LINE
+      | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+      | wgpu::Features::PUSH_CONSTANTS
+      | wgpu::Features::TEXTURE_BINDING_ARRAY
+      | wgpu::Features::PARTIALLY_BOUND_BINDING_ARRAY
+      | wgpu::Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING);
+  // WebGL doesn't support all of wgpu's features, so if
+  // we're building for the web we'll have to disable some.
+  let mut limits = if cfg!(target_arch = "wasm32") {
+    wgpu::Limits::downlevel_webgl2_defaults()
+  } else {
+    wgpu::Limits::default()
+  };
+  // the defaults allow no push constants at all, see pipeline::supports_push_constants
+  if features.contains(wgpu::Features::PUSH_CONSTANTS) {
+    limits.max_push_constant_size = adapter.limits().max_push_constant_size.min(128);
+  }
+  // the texture arrays of pbr::bindless_capacity() count towards this
+  if features.contains(wgpu::Features::TEXTURE_BINDING_ARRAY) {
+    limits.max_sampled_textures_per_shader_stage =
+      adapter.limits().max_sampled_textures_per_shader_stage;
+  }
+  adapter
+    .request_device(
+      &wgpu::DeviceDescriptor {
+        features,
+        limits,
+        label: None,
+      },
+      None, // Trace path
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn discrete_first_unless_low_power() {
+    let picker = AdapterPicker::new(Backends::all());
+    assert!(picker.rank(DeviceType::DiscreteGpu) < picker.rank(DeviceType::IntegratedGpu));
+    let low_power = picker.power_preference(PowerPreference::LowPower);
+    assert!(low_power.rank(DeviceType::IntegratedGpu) < low_power.rank(DeviceType::DiscreteGpu));
+    assert!(low_power.rank(DeviceType::DiscreteGpu) < low_power.rank(DeviceType::Cpu));
+  }
+}
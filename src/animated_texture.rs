@@ -0,0 +1,245 @@
+use std::{
+  io::Cursor,
+  path::{Path, PathBuf},
+};
+
+use image::{
+  codecs::{gif::GifDecoder, png::PngDecoder},
+  imageops::FilterType,
+  AnimationDecoder, ImageFormat, RgbaImage,
+};
+use wgpu::{Device, Queue};
+
+use crate::{
+  mipmap::MipmapGenerator, quality::fit_texture_size, sampler::Samplers, texture::Texture,
+};
+
+// Frame rate of a directory of images, they don't say how long each one is
+pub const SEQUENCE_FPS: f32 = 12.0;
+// GIFs that ask for less are shown at 10 fps, as browsers do
+const MIN_GIF_DELAY: f32 = 0.02;
+const DEFAULT_GIF_DELAY: f32 = 0.1;
+
+pub struct AnimationFrame {
+  pub image: RgbaImage,
+  // seconds
+  pub duration: f32,
+}
+
+#[derive(Debug)]
+pub enum AnimationError {
+  Io(PathBuf, std::io::Error),
+  Image(PathBuf, image::ImageError),
+  // a directory without any images, or an animation without frames
+  NoFrames(PathBuf),
+}
+
+impl std::fmt::Display for AnimationError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      AnimationError::Io(path, e) => write!(f, "couldn't read {}: {}", path.display(), e),
+      AnimationError::Image(path, e) => write!(f, "couldn't decode {}: {}", path.display(), e),
+      AnimationError::NoFrames(path) => write!(f, "{} has no frames", path.display()),
+    }
+  }
+}
+
+impl std::error::Error for AnimationError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      AnimationError::Io(_, e) => Some(e),
+      AnimationError::Image(_, e) => Some(e),
+      AnimationError::NoFrames(_) => None,
+    }
+  }
+}
+
+// The frames of a GIF or APNG, a still image is a single frame
+pub fn decode(bytes: &[u8]) -> Result<Vec<AnimationFrame>, image::ImageError> {
+  let frames = match image::guess_format(bytes)? {
+    ImageFormat::Gif => GifDecoder::new(Cursor::new(bytes))?
+      .into_frames()
+      .collect_frames()?,
+    ImageFormat::Png if PngDecoder::new(Cursor::new(bytes))?.is_apng() => {
+      PngDecoder::new(Cursor::new(bytes))?
+        .apng()
+        .into_frames()
+        .collect_frames()?
+    }
+    _ => {
+      return Ok(vec![AnimationFrame {
+        image: image::load_from_memory(bytes)?.to_rgba8(),
+        duration: 1.0,
+      }])
+    }
+  };
+  Ok(
+    frames
+      .into_iter()
+      .map(|frame| {
+        let (numerator, denominator) = frame.delay().numer_denom_ms();
+        let seconds = numerator as f32 / denominator.max(1) as f32 / 1000.0;
+        AnimationFrame {
+          duration: if seconds < MIN_GIF_DELAY {
+            DEFAULT_GIF_DELAY
+          } else {
+            seconds
+          },
+          image: frame.into_buffer(),
+        }
+      })
+      .collect(),
+  )
+}
+
+// A GIF or APNG file, or a directory of images shown in the order of their names at
+// SEQUENCE_FPS
+pub fn load(path: impl AsRef<Path>) -> Result<Vec<AnimationFrame>, AnimationError> {
+  let path = path.as_ref();
+  let io_error = |e| AnimationError::Io(path.to_path_buf(), e);
+  let frames = if path.is_dir() {
+    let mut files: Vec<_> = std::fs::read_dir(path)
+      .map_err(io_error)?
+      .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+      .filter(|file| ImageFormat::from_path(file).is_ok())
+      .collect();
+    files.sort();
+    files
+      .iter()
+      .map(|file| {
+        let image = image::open(file).map_err(|e| AnimationError::Image(file.clone(), e))?;
+        Ok(AnimationFrame {
+          image: image.to_rgba8(),
+          duration: 1.0 / SEQUENCE_FPS,
+        })
+      })
+      .collect::<Result<_, _>>()?
+  } else {
+    let bytes = std::fs::read(path).map_err(io_error)?;
+    decode(&bytes).map_err(|e| AnimationError::Image(path.to_path_buf(), e))?
+  };
+  if frames.is_empty() {
+    return Err(AnimationError::NoFrames(path.to_path_buf()));
+  }
+  Ok(frames)
+}
+
+// A texture that flips through frames, looping. It's one texture the whole time with each
+// frame uploaded over the last, so bind groups made with its view (a PBR material's albedo,
+// anything else that samples a texture) show the animation without being rebuilt.
+pub struct AnimatedTexture {
+  texture: Texture,
+  // all the size of the first one, fitted to the max texture size
+  frames: Vec<RgbaImage>,
+  // when each frame is over, in seconds from the start of the loop
+  ends: Vec<f32>,
+  current: usize,
+}
+
+impl AnimatedTexture {
+  // sRGB like Texture::from_image, `frames` can't be empty
+  pub fn new(
+    device: &Device,
+    queue: &Queue,
+    mipmaps: &mut MipmapGenerator,
+    samplers: &Samplers,
+    frames: Vec<AnimationFrame>,
+    max_size: u32,
+    label: &str,
+  ) -> Self {
+    assert!(!frames.is_empty(), "an animated texture needs a frame");
+    // the first frame's size, downscaled like Texture::from_image would
+    let (width, height) = frames[0].image.dimensions();
+    let (width, height) = fit_texture_size(width, height, max_size);
+    let mut end = 0.0;
+    let ends = frames
+      .iter()
+      .map(|frame| {
+        end += frame.duration;
+        end
+      })
+      .collect();
+    let frames: Vec<_> = frames
+      .into_iter()
+      .map(|frame| match frame.image.dimensions() == (width, height) {
+        true => frame.image,
+        false => image::imageops::resize(&frame.image, width, height, FilterType::Triangle),
+      })
+      .collect();
+    let first = image::DynamicImage::ImageRgba8(frames[0].clone());
+    Self {
+      texture: Texture::from_image(device, queue, mipmaps, samplers, &first, max_size, label),
+      frames,
+      ends,
+      current: 0,
+    }
+  }
+
+  // Another view of the texture, e.g. for PbrTextures while the scene keeps this
+  pub fn create_view(&self) -> wgpu::TextureView {
+    self
+      .texture
+      .texture
+      .create_view(&wgpu::TextureViewDescriptor::default())
+  }
+
+  pub fn is_animated(&self) -> bool {
+    self.frames.len() > 1
+  }
+
+  // Seconds in one loop
+  pub fn duration(&self) -> f32 {
+    self.ends.last().copied().unwrap_or(0.0)
+  }
+
+  fn frame_at(&self, time: f64) -> usize {
+    let duration = self.duration() as f64;
+    if duration <= 0.0 {
+      return 0;
+    }
+    let time = time.rem_euclid(duration) as f32;
+    let frame = self.ends.partition_point(|&end| end <= time);
+    frame.min(self.frames.len() - 1)
+  }
+
+  // Shows the frame `time` seconds into the animation, uploading it and its mips if it's
+  // another one than shown. Returns whether it was.
+  pub fn set_time(
+    &mut self,
+    device: &Device,
+    queue: &Queue,
+    mipmaps: &mut MipmapGenerator,
+    time: f64,
+  ) -> bool {
+    let frame = self.frame_at(time);
+    if frame == self.current {
+      return false;
+    }
+    self.current = frame;
+    let image = &self.frames[frame];
+    let (width, height) = image.dimensions();
+    let texture = &self.texture.texture;
+    queue.write_texture(
+      texture.as_image_copy(),
+      image,
+      wgpu::ImageDataLayout {
+        offset: 0,
+        bytes_per_row: std::num::NonZeroU32::new(4 * width),
+        rows_per_image: std::num::NonZeroU32::new(height),
+      },
+      texture.size(),
+    );
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+      label: Some("Animated Texture Mipmap Encoder"),
+    });
+    mipmaps.generate(
+      device,
+      &mut encoder,
+      texture,
+      texture.format(),
+      self.texture.mip_level_count,
+    );
+    queue.submit(std::iter::once(encoder.finish()));
+    true
+  }
+}
@@ -0,0 +1,670 @@
+// Skeletons and the clips that move them, the CPU half of skinning. It follows glTF's skins
+// and animations: every joint has a local translation, rotation and scale under its parent,
+// a clip's channels key one of those of one joint, and a joint's matrix for the skinning
+// shader is its transform in the model times its inverse bind matrix, which takes the mesh
+// from where it was bound to the joint into the joint's space first. A clip can also key
+// the weights of the mesh's morph targets. The same clips play on a hierarchy of nodes
+// with NodeAnimation, which is what moves a glTF scene's models rather than a skin.
+
+use serde::{Deserialize, Serialize};
+
+use crate::math::{self, Mat4, Quat, Vec3};
+
+// A joint's transform relative to its parent, and an entity's, see ecs.rs
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, bevy_ecs::component::Component)]
+pub struct Transform {
+  pub translation: Vec3,
+  pub rotation: Quat,
+  pub scale: Vec3,
+}
+
+impl Transform {
+  pub const IDENTITY: Transform = Transform {
+    translation: [0.0; 3],
+    rotation: math::QUAT_IDENTITY,
+    scale: [1.0; 3],
+  };
+
+  pub fn from_translation(translation: Vec3) -> Self {
+    Self {
+      translation,
+      ..Self::IDENTITY
+    }
+  }
+
+  pub fn to_mat4(&self) -> Mat4 {
+    math::from_trs(self.translation, self.rotation, self.scale)
+  }
+
+  // `t` of the way to `other`, the rotation the shorter way round
+  pub fn lerp(&self, other: &Transform, t: f32) -> Transform {
+    Transform {
+      translation: lerp3(self.translation, other.translation, t),
+      rotation: math::quat_slerp(self.rotation, other.rotation, t),
+      scale: lerp3(self.scale, other.scale, t),
+    }
+  }
+}
+
+impl Default for Transform {
+  fn default() -> Self {
+    Self::IDENTITY
+  }
+}
+
+fn lerp3(a: Vec3, b: Vec3, t: f32) -> Vec3 {
+  math::add(a, math::scale(math::sub(b, a), t))
+}
+
+#[derive(Debug, Clone)]
+pub struct Joint {
+  pub name: String,
+  // an index into the skeleton's joints before this one, None for a root
+  pub parent: Option<usize>,
+  // where it is when no clip moves it
+  pub rest: Transform,
+}
+
+#[derive(Debug)]
+pub enum SkinError {
+  // glTF lets a child come first, the joints have to be sorted parents first here
+  ParentAfterChild { joint: usize, parent: usize },
+  // one inverse bind matrix per joint
+  InverseBindCount { joints: usize, matrices: usize },
+  TooManyJoints { joints: usize, max: usize },
+  // skin vertices or a morph target's deltas and mesh vertices don't pair up
+  VertexCount { mesh: usize, skin: usize },
+  TooManyMorphTargets { targets: usize, max: usize },
+}
+
+impl std::fmt::Display for SkinError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      SkinError::ParentAfterChild { joint, parent } => {
+        write!(f, "joint {} comes before its parent {}", joint, parent)
+      }
+      SkinError::InverseBindCount { joints, matrices } => write!(
+        f,
+        "{} inverse bind matrices for {} joints",
+        matrices, joints
+      ),
+      SkinError::TooManyJoints { joints, max } => {
+        write!(f, "{} joints, at most {} are supported", joints, max)
+      }
+      SkinError::VertexCount { mesh, skin } => {
+        write!(f, "{} skin vertices for a mesh of {} vertices", skin, mesh)
+      }
+      SkinError::TooManyMorphTargets { targets, max } => {
+        write!(
+          f,
+          "{} morph targets, at most {} are supported",
+          targets, max
+        )
+      }
+    }
+  }
+}
+
+impl std::error::Error for SkinError {}
+
+// The joints of a glTF skin, parents first
+#[derive(Debug, Clone)]
+pub struct Skeleton {
+  joints: Vec<Joint>,
+  inverse_bind: Vec<Mat4>,
+}
+
+impl Skeleton {
+  pub fn new(joints: Vec<Joint>, inverse_bind: Vec<Mat4>) -> Result<Self, SkinError> {
+    if joints.len() != inverse_bind.len() {
+      return Err(SkinError::InverseBindCount {
+        joints: joints.len(),
+        matrices: inverse_bind.len(),
+      });
+    }
+    for (joint, parent) in joints
+      .iter()
+      .enumerate()
+      .filter_map(|(i, joint)| Some((i, joint.parent?)))
+    {
+      if parent >= joint {
+        return Err(SkinError::ParentAfterChild { joint, parent });
+      }
+    }
+    Ok(Self {
+      joints,
+      inverse_bind,
+    })
+  }
+
+  // Bound where the joints rest, the inverse bind matrices undo their rest transforms
+  pub fn from_rest(joints: Vec<Joint>) -> Result<Self, SkinError> {
+    let placeholder = vec![math::IDENTITY; joints.len()];
+    let mut skeleton = Self::new(joints, placeholder)?;
+    skeleton.inverse_bind = skeleton
+      .global_transforms(&skeleton.rest_pose())
+      .iter()
+      .map(math::inverse)
+      .collect();
+    Ok(skeleton)
+  }
+
+  pub fn joints(&self) -> &[Joint] {
+    &self.joints
+  }
+
+  pub fn len(&self) -> usize {
+    self.joints.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.joints.is_empty()
+  }
+
+  pub fn find(&self, name: &str) -> Option<usize> {
+    self.joints.iter().position(|joint| joint.name == name)
+  }
+
+  pub fn rest_pose(&self) -> Vec<Transform> {
+    self.joints.iter().map(|joint| joint.rest).collect()
+  }
+
+  // Every joint's transform in the model, from the local ones of `pose`
+  pub fn global_transforms(&self, pose: &[Transform]) -> Vec<Mat4> {
+    let mut globals: Vec<Mat4> = Vec::with_capacity(self.joints.len());
+    for (joint, local) in self.joints.iter().zip(pose) {
+      let local = local.to_mat4();
+      globals.push(match joint.parent {
+        Some(parent) => math::mul_mat4(&globals[parent], &local),
+        None => local,
+      });
+    }
+    globals
+  }
+
+  // What the skinning shader blends, the rest pose gives identities
+  pub fn joint_matrices(&self, pose: &[Transform]) -> Vec<Mat4> {
+    self
+      .global_transforms(pose)
+      .iter()
+      .zip(&self.inverse_bind)
+      .map(|(global, inverse_bind)| math::mul_mat4(global, inverse_bind))
+      .collect()
+  }
+}
+
+// glTF's
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Interpolation {
+  // holds each key until the next
+  Step,
+  #[default]
+  Linear,
+  // Hermite splines: every key has three values, an in tangent, the value and an out
+  // tangent, the tangents in units per second
+  CubicSpline,
+}
+
+impl Interpolation {
+  fn values_per_key(self) -> usize {
+    match self {
+      Interpolation::CubicSpline => 3,
+      _ => 1,
+    }
+  }
+}
+
+// The keyed values of a channel, one per key time, or three for a cubic spline
+#[derive(Debug, Clone)]
+pub enum Keys {
+  Translation(Vec<Vec3>),
+  Rotation(Vec<Quat>),
+  Scale(Vec<Vec3>),
+}
+
+impl Keys {
+  fn len(&self) -> usize {
+    match self {
+      Keys::Translation(values) | Keys::Scale(values) => values.len(),
+      Keys::Rotation(values) => values.len(),
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct Channel {
+  // or the node, for a NodeAnimation
+  pub joint: usize,
+  // seconds, increasing
+  pub times: Vec<f32>,
+  pub keys: Keys,
+  pub interpolation: Interpolation,
+}
+
+impl Channel {
+  // Sets its part of the joint's transform at `time`, held before the first key and after
+  // the last
+  fn apply(&self, time: f32, transform: &mut Transform) {
+    let count = self
+      .times
+      .len()
+      .min(self.keys.len() / self.interpolation.values_per_key());
+    let Some(span) = keyframes(&self.times[..count], time, self.interpolation) else {
+      return;
+    };
+    match &self.keys {
+      Keys::Translation(values) => transform.translation = span.sample(values),
+      Keys::Rotation(values) if span.cubic => {
+        transform.rotation = normalize_quat(span.sample(values))
+      }
+      Keys::Rotation(values) => {
+        transform.rotation = math::quat_slerp(values[span.a], values[span.b], span.t)
+      }
+      Keys::Scale(values) => transform.scale = span.sample(values),
+    }
+  }
+}
+
+fn normalize_quat(q: Quat) -> Quat {
+  let length = q
+    .iter()
+    .map(|v| v * v)
+    .sum::<f32>()
+    .sqrt()
+    .max(f32::EPSILON);
+  q.map(|v| v / length)
+}
+
+// Where `time` falls between two keys
+struct Span {
+  a: usize,
+  b: usize,
+  // how far it is from the first to the second, 0 to 1
+  t: f32,
+  // seconds from the first to the second, what the tangents are scaled by
+  dt: f32,
+  cubic: bool,
+}
+
+impl Span {
+  // Between the keys of `values`, laid out in triplets for a cubic spline
+  fn sample<const N: usize>(&self, values: &[[f32; N]]) -> [f32; N] {
+    if self.cubic {
+      let (a, b) = (3 * self.a, 3 * self.b);
+      std::array::from_fn(|i| {
+        hermite(
+          values[a + 1][i],
+          values[a + 2][i],
+          values[b + 1][i],
+          values[b][i],
+          self.t,
+          self.dt,
+        )
+      })
+    } else {
+      let (a, b) = (values[self.a], values[self.b]);
+      std::array::from_fn(|i| a[i] + (b[i] - a[i]) * self.t)
+    }
+  }
+}
+
+// The cubic Hermite spline from `start` leaving it at `out_tangent`, to `end` arriving at
+// `in_tangent`
+fn hermite(start: f32, out_tangent: f32, end: f32, in_tangent: f32, t: f32, dt: f32) -> f32 {
+  let (t2, t3) = (t * t, t * t * t);
+  (2.0 * t3 - 3.0 * t2 + 1.0) * start
+    + (t3 - 2.0 * t2 + t) * dt * out_tangent
+    + (-2.0 * t3 + 3.0 * t2) * end
+    + (t3 - t2) * dt * in_tangent
+}
+
+// The keys around `time`, both the first or the last key outside the keys' times. None
+// without keys.
+fn keyframes(times: &[f32], time: f32, interpolation: Interpolation) -> Option<Span> {
+  let count = times.len();
+  let next = times.partition_point(|&key| key <= time);
+  let held = |key: usize| Span {
+    a: key,
+    b: key,
+    t: 0.0,
+    dt: 0.0,
+    cubic: interpolation == Interpolation::CubicSpline,
+  };
+  match next {
+    _ if count == 0 => None,
+    0 => Some(held(0)),
+    _ if next == count => Some(held(count - 1)),
+    _ => {
+      let (start, end) = (times[next - 1], times[next]);
+      let dt = end - start;
+      let t = match interpolation {
+        Interpolation::Step => 0.0,
+        _ => (time - start) / dt.max(f32::EPSILON),
+      };
+      Some(Span {
+        a: next - 1,
+        b: next,
+        t,
+        dt,
+        ..held(0)
+      })
+    }
+  }
+}
+
+// glTF's "weights" channel: the mesh's morph target weights, `targets` of them per key or
+// three times that for a cubic spline
+#[derive(Debug, Clone)]
+pub struct MorphChannel {
+  pub times: Vec<f32>,
+  pub targets: usize,
+  // key after key
+  pub weights: Vec<f32>,
+  pub interpolation: Interpolation,
+}
+
+impl MorphChannel {
+  fn apply(&self, time: f32, weights: &mut [f32]) {
+    let targets = self.targets.max(1);
+    let per_key = self.interpolation.values_per_key();
+    let count = self
+      .times
+      .len()
+      .min(self.weights.len() / (targets * per_key));
+    let Some(span) = keyframes(&self.times[..count], time, self.interpolation) else {
+      return;
+    };
+    // a cubic spline's key is every target's in tangent, then their values, then their out
+    // tangents
+    let values = |k: usize, part: usize| {
+      let start = (k * per_key + part) * targets;
+      &self.weights[start..start + targets]
+    };
+    let weights = weights.iter_mut().take(targets).enumerate();
+    if span.cubic {
+      for (i, weight) in weights {
+        *weight = hermite(
+          values(span.a, 1)[i],
+          values(span.a, 2)[i],
+          values(span.b, 1)[i],
+          values(span.b, 0)[i],
+          span.t,
+          span.dt,
+        );
+      }
+    } else {
+      for (i, weight) in weights {
+        let (a, b) = (values(span.a, 0)[i], values(span.b, 0)[i]);
+        *weight = a + (b - a) * span.t;
+      }
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct AnimationClip {
+  pub name: String,
+  pub channels: Vec<Channel>,
+  pub morph: Option<MorphChannel>,
+}
+
+impl AnimationClip {
+  pub fn new(name: &str, channels: Vec<Channel>) -> Self {
+    Self {
+      name: name.to_string(),
+      channels,
+      morph: None,
+    }
+  }
+
+  pub fn with_morph(mut self, morph: MorphChannel) -> Self {
+    self.morph = Some(morph);
+    self
+  }
+
+  // Seconds to its last key
+  pub fn duration(&self) -> f32 {
+    let morph = self.morph.iter().map(|morph| &morph.times);
+    self
+      .channels
+      .iter()
+      .map(|channel| &channel.times)
+      .chain(morph)
+      .filter_map(|times| times.last().copied())
+      .fold(0.0, f32::max)
+  }
+
+  // Keys the morph target `weights` at `time`, they stay as they are without a morph
+  // channel
+  pub fn sample_weights(&self, time: f32, weights: &mut [f32]) {
+    if let Some(morph) = &self.morph {
+      morph.apply(time, weights);
+    }
+  }
+
+  // Keys `pose` at `time`, joints none of its channels move keep what they had
+  pub fn sample(&self, time: f32, pose: &mut [Transform]) {
+    for channel in &self.channels {
+      if let Some(transform) = pose.get_mut(channel.joint) {
+        channel.apply(time, transform);
+      }
+    }
+  }
+}
+
+// The clip faded out of while cross_fade() blends to the next one
+#[derive(Debug, Clone, Copy)]
+struct Fade {
+  clip: usize,
+  time: f32,
+  elapsed: f32,
+  duration: f32,
+}
+
+// Plays clips of a skeleton: one at a time, or two while cross fading from one to the other.
+// Looping wraps a clip around, otherwise it stops at its end.
+#[derive(Debug, Clone)]
+pub struct AnimationPlayer {
+  clip: usize,
+  time: f32,
+  speed: f32,
+  playing: bool,
+  looping: bool,
+  fade: Option<Fade>,
+}
+
+impl AnimationPlayer {
+  // Playing `clip` from its start, looped
+  pub fn new(clip: usize) -> Self {
+    Self {
+      clip,
+      time: 0.0,
+      speed: 1.0,
+      playing: true,
+      looping: true,
+      fade: None,
+    }
+  }
+
+  pub fn clip(&self) -> usize {
+    self.clip
+  }
+
+  pub fn time(&self) -> f32 {
+    self.time
+  }
+
+  pub fn is_playing(&self) -> bool {
+    self.playing
+  }
+
+  pub fn is_looping(&self) -> bool {
+    self.looping
+  }
+
+  pub fn play(&mut self) {
+    self.playing = true;
+  }
+
+  pub fn pause(&mut self) {
+    self.playing = false;
+  }
+
+  // Returns whether it's playing now
+  pub fn toggle(&mut self) -> bool {
+    self.playing = !self.playing;
+    self.playing
+  }
+
+  pub fn set_looping(&mut self, looping: bool) {
+    self.looping = looping;
+  }
+
+  // 1 is the clip's own speed, negative plays it backwards
+  pub fn set_speed(&mut self, speed: f32) {
+    self.speed = speed;
+  }
+
+  pub fn seek(&mut self, time: f32) {
+    self.time = time;
+  }
+
+  // Switches to `clip` from its start, blending from the current one over `duration`
+  // seconds. 0 switches at once.
+  pub fn cross_fade(&mut self, clip: usize, duration: f32) {
+    self.fade = (duration > 0.0).then_some(Fade {
+      clip: self.clip,
+      time: self.time,
+      elapsed: 0.0,
+      duration,
+    });
+    self.clip = clip;
+    self.time = 0.0;
+  }
+
+  // Share of the current clip in the pose, below 1 while fading in
+  pub fn blend_weight(&self) -> f32 {
+    self
+      .fade
+      .map_or(1.0, |fade| (fade.elapsed / fade.duration).min(1.0))
+  }
+
+  // Moves on `dt` seconds, nothing moves while paused
+  pub fn advance(&mut self, clips: &[AnimationClip], dt: f32) {
+    if !self.playing {
+      return;
+    }
+    let step = dt * self.speed;
+    self.time = self.wrap(clips, self.clip, self.time + step);
+    if let Some(mut fade) = self.fade {
+      fade.time = self.wrap(clips, fade.clip, fade.time + step);
+      fade.elapsed += dt;
+      self.fade = (fade.elapsed < fade.duration).then_some(fade);
+    }
+    // the end of a clip that doesn't loop
+    if !self.looping
+      && clips
+        .get(self.clip)
+        .is_some_and(|clip| self.time >= clip.duration())
+    {
+      self.playing = false;
+    }
+  }
+
+  fn wrap(&self, clips: &[AnimationClip], clip: usize, time: f32) -> f32 {
+    let duration = clips.get(clip).map_or(0.0, AnimationClip::duration);
+    if duration <= 0.0 {
+      0.0
+    } else if self.looping {
+      time.rem_euclid(duration)
+    } else {
+      time.clamp(0.0, duration)
+    }
+  }
+
+  // The skeleton's local transforms now, from its rest pose
+  pub fn pose(&self, skeleton: &Skeleton, clips: &[AnimationClip]) -> Vec<Transform> {
+    let mut pose = skeleton.rest_pose();
+    if let Some(clip) = clips.get(self.clip) {
+      clip.sample(self.time, &mut pose);
+    }
+    let Some(fade) = self.fade else {
+      return pose;
+    };
+    let mut from = skeleton.rest_pose();
+    if let Some(clip) = clips.get(fade.clip) {
+      clip.sample(fade.time, &mut from);
+    }
+    let weight = self.blend_weight();
+    from
+      .iter()
+      .zip(&pose)
+      .map(|(from, to)| from.lerp(to, weight))
+      .collect()
+  }
+
+  // The morph target weights now, `defaults` where the clips don't key them
+  pub fn weights(&self, defaults: &[f32], clips: &[AnimationClip]) -> Vec<f32> {
+    let mut weights = defaults.to_vec();
+    if let Some(clip) = clips.get(self.clip) {
+      clip.sample_weights(self.time, &mut weights);
+    }
+    let Some(fade) = self.fade else {
+      return weights;
+    };
+    let mut from = defaults.to_vec();
+    if let Some(clip) = clips.get(fade.clip) {
+      clip.sample_weights(fade.time, &mut from);
+    }
+    let weight = self.blend_weight();
+    from
+      .iter()
+      .zip(&weights)
+      .map(|(from, to)| from + (to - from) * weight)
+      .collect()
+  }
+}
+
+// Clips playing on a hierarchy of nodes, a glTF scene's, instead of a skin's joints: a
+// channel's joint is one of the nodes, and they're a Skeleton whose inverse bind matrices
+// go unused. Scene::add_node_animation() moves models with them.
+#[derive(Debug, Clone)]
+pub struct NodeAnimation {
+  nodes: Skeleton,
+  clips: Vec<AnimationClip>,
+  player: AnimationPlayer,
+}
+
+impl NodeAnimation {
+  // Playing the first clip, looped
+  pub fn new(nodes: Vec<Joint>, clips: Vec<AnimationClip>) -> Result<Self, SkinError> {
+    Ok(Self {
+      nodes: Skeleton::from_rest(nodes)?,
+      clips,
+      player: AnimationPlayer::new(0),
+    })
+  }
+
+  pub fn nodes(&self) -> &Skeleton {
+    &self.nodes
+  }
+
+  pub fn clips(&self) -> &[AnimationClip] {
+    &self.clips
+  }
+
+  pub fn player(&self) -> &AnimationPlayer {
+    &self.player
+  }
+
+  pub fn player_mut(&mut self) -> &mut AnimationPlayer {
+    &mut self.player
+  }
+
+  // Moves on `dt` seconds, returns every node's transform in the world
+  pub fn update(&mut self, dt: f32) -> Vec<Mat4> {
+    self.player.advance(&self.clips, dt);
+    let pose = self.player.pose(&self.nodes, &self.clips);
+    self.nodes.global_transforms(&pose)
+  }
+}
@@ -0,0 +1,329 @@
+// Loads textures, models and shaders from files on a worker thread, hands out handles to
+// them and reloads them when their files change, for editing assets while the app runs.
+//
+// Loading the same file twice gives the same asset. It's kept while any handle to it is
+// alive and dropped with the last one at the next update(), which is also when finished
+// loads and reloads come in, so whatever was built from an asset is patched at a frame
+// boundary and never halfway through a frame.
+//
+// Files are read from disk directly rather than through Assets, a pack or the web can't
+// change under a running app.
+
+use std::{
+  any::{Any, TypeId},
+  collections::HashMap,
+  marker::PhantomData,
+  path::{Path, PathBuf},
+  sync::{
+    mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    Arc, Weak,
+  },
+  time::{Duration, Instant, SystemTime},
+};
+
+use crate::{mesh::Mesh, mesh_cache, shader_file::ShaderFile};
+
+// How often the watcher looks at the loaded files' modification times
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+// Something the manager can load, `load` runs on the worker thread
+pub trait Asset: Send + 'static + Sized {
+  fn load(path: &Path) -> Result<Self, String>;
+}
+
+pub struct TextureAsset(pub image::DynamicImage);
+
+impl Asset for TextureAsset {
+  fn load(path: &Path) -> Result<Self, String> {
+    image::open(path).map(Self).map_err(|e| e.to_string())
+  }
+}
+
+// The meshes of an OBJ file, processed and cached like at startup
+pub struct ModelAsset(pub Vec<Mesh>);
+
+impl Asset for ModelAsset {
+  fn load(path: &Path) -> Result<Self, String> {
+    mesh_cache::load_or_import(path)
+      .map(Self)
+      .map_err(|e| e.to_string())
+  }
+}
+
+// WGSL, GLSL or SPIR-V by the file's extension, compiling it is up to whoever uses it
+pub struct ShaderAsset(pub ShaderFile);
+
+impl Asset for ShaderAsset {
+  fn load(path: &Path) -> Result<Self, String> {
+    ShaderFile::load(path).map(Self).map_err(|e| e.to_string())
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AssetId(u64);
+
+// Keeps an asset loaded, clones share it
+pub struct Handle<T> {
+  id: AssetId,
+  refs: Arc<()>,
+  asset: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+  pub fn id(&self) -> AssetId {
+    self.id
+  }
+}
+
+impl<T> Clone for Handle<T> {
+  fn clone(&self) -> Self {
+    Self {
+      id: self.id,
+      refs: self.refs.clone(),
+      asset: PhantomData,
+    }
+  }
+}
+
+type LoadFn = fn(&Path) -> Result<Box<dyn Any + Send>, String>;
+
+fn load_boxed<T: Asset>(path: &Path) -> Result<Box<dyn Any + Send>, String> {
+  T::load(path).map(|asset| Box::new(asset) as Box<dyn Any + Send>)
+}
+
+enum Job {
+  Load {
+    id: AssetId,
+    path: PathBuf,
+    load: LoadFn,
+  },
+  // the last handle is gone, stop watching
+  Forget(AssetId),
+}
+
+struct Loaded {
+  id: AssetId,
+  result: Result<Box<dyn Any + Send>, String>,
+}
+
+struct Slot {
+  path: PathBuf,
+  type_id: TypeId,
+  refs: Weak<()>,
+  asset: Option<Box<dyn Any + Send>>,
+  error: Option<String>,
+  loads: u32,
+}
+
+pub struct AssetManager {
+  jobs: Sender<Job>,
+  loaded: Receiver<Loaded>,
+  slots: HashMap<AssetId, Slot>,
+  by_path: HashMap<(TypeId, PathBuf), AssetId>,
+  next_id: u64,
+}
+
+impl AssetManager {
+  pub fn new() -> Self {
+    let (jobs, job_receiver) = mpsc::channel();
+    let (loaded_sender, loaded) = mpsc::channel();
+    // ends once the manager is dropped and the jobs channel with it
+    std::thread::Builder::new()
+      .name("assets".to_string())
+      .spawn(move || run_worker(job_receiver, loaded_sender))
+      .expect("couldn't start the asset thread");
+    Self {
+      jobs,
+      loaded,
+      slots: HashMap::new(),
+      by_path: HashMap::new(),
+      next_id: 0,
+    }
+  }
+
+  // Starts loading `path` unless it's loaded already, get() has it once update() brought it
+  // in
+  pub fn load<T: Asset>(&mut self, path: impl AsRef<Path>) -> Handle<T> {
+    let path = path.as_ref().to_path_buf();
+    let key = (TypeId::of::<T>(), path.clone());
+    if let Some(&id) = self.by_path.get(&key) {
+      if let Some(refs) = self.slots[&id].refs.upgrade() {
+        return Handle {
+          id,
+          refs,
+          asset: PhantomData,
+        };
+      }
+    }
+
+    let id = AssetId(self.next_id);
+    self.next_id += 1;
+    let refs = Arc::new(());
+    self.slots.insert(
+      id,
+      Slot {
+        path: path.clone(),
+        type_id: TypeId::of::<T>(),
+        refs: Arc::downgrade(&refs),
+        asset: None,
+        error: None,
+        loads: 0,
+      },
+    );
+    self.by_path.insert(key, id);
+    let load = load_boxed::<T>;
+    // the worker only goes away with the manager
+    let _ = self.jobs.send(Job::Load { id, path, load });
+    Handle {
+      id,
+      refs,
+      asset: PhantomData,
+    }
+  }
+
+  // The latest successfully loaded version, a failed reload keeps the one before
+  pub fn get<T: Asset>(&self, handle: &Handle<T>) -> Option<&T> {
+    self
+      .slots
+      .get(&handle.id)
+      .and_then(|slot| slot.asset.as_ref())
+      .and_then(|asset| asset.downcast_ref())
+  }
+
+  // Why the last load failed, None once one succeeds
+  pub fn error<T>(&self, handle: &Handle<T>) -> Option<&str> {
+    self.slots.get(&handle.id).and_then(|s| s.error.as_deref())
+  }
+
+  pub fn path<T>(&self, handle: &Handle<T>) -> &Path {
+    &self.slots[&handle.id].path
+  }
+
+  // Whether any asset hasn't finished its first load
+  pub fn is_loading(&self) -> bool {
+    self
+      .slots
+      .values()
+      .any(|slot| slot.loads == 0 && slot.error.is_none())
+  }
+
+  pub fn len(&self) -> usize {
+    self.slots.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.slots.is_empty()
+  }
+
+  // Call once a frame. Drops the assets nothing has a handle to any more and takes in the
+  // finished loads, returns the assets that loaded or reloaded so whatever uses them can
+  // rebuild its GPU resources.
+  pub fn update(&mut self) -> Vec<AssetId> {
+    let unused: Vec<AssetId> = self
+      .slots
+      .iter()
+      .filter(|(_, slot)| slot.refs.strong_count() == 0)
+      .map(|(&id, _)| id)
+      .collect();
+    for id in unused {
+      let slot = self.slots.remove(&id).unwrap();
+      // unless load() already started over with a new asset for the path
+      let key = (slot.type_id, slot.path);
+      if self.by_path.get(&key) == Some(&id) {
+        self.by_path.remove(&key);
+      }
+      let _ = self.jobs.send(Job::Forget(id));
+    }
+
+    let mut changed = Vec::new();
+    for Loaded { id, result } in self.loaded.try_iter() {
+      // dropped while it was loading
+      let Some(slot) = self.slots.get_mut(&id) else {
+        continue;
+      };
+      match result {
+        Ok(asset) => {
+          slot.loads += 1;
+          let verb = if slot.loads == 1 {
+            "loaded"
+          } else {
+            "reloaded"
+          };
+          log::info!("{} {}", verb, slot.path.display());
+          slot.asset = Some(asset);
+          slot.error = None;
+          changed.push(id);
+        }
+        Err(e) => {
+          log::warn!("couldn't load {}: {}", slot.path.display(), e);
+          slot.error = Some(e);
+        }
+      }
+    }
+    changed
+  }
+}
+
+impl Default for AssetManager {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+struct Watched {
+  path: PathBuf,
+  load: LoadFn,
+  modified: Option<SystemTime>,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+  std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// Loads what's asked for and, in between, reloads the files whose modification time changed
+fn run_worker(jobs: Receiver<Job>, loaded: Sender<Loaded>) {
+  let mut watched: HashMap<AssetId, Watched> = HashMap::new();
+  let mut last_check = Instant::now();
+  loop {
+    match jobs.recv_timeout(WATCH_INTERVAL.saturating_sub(last_check.elapsed())) {
+      Ok(Job::Load { id, path, load }) => {
+        let modified = modified(&path);
+        let result = load(&path);
+        if loaded.send(Loaded { id, result }).is_err() {
+          return;
+        }
+        watched.insert(
+          id,
+          Watched {
+            path,
+            load,
+            modified,
+          },
+        );
+      }
+      Ok(Job::Forget(id)) => {
+        watched.remove(&id);
+      }
+      Err(RecvTimeoutError::Timeout) => {}
+      Err(RecvTimeoutError::Disconnected) => return,
+    }
+
+    if last_check.elapsed() < WATCH_INTERVAL {
+      continue;
+    }
+    last_check = Instant::now();
+    for (&id, file) in &mut watched {
+      // a missing file is most likely an editor in the middle of saving it, the old version
+      // stays until it's back
+      let modified = modified(&file.path);
+      if modified.is_none() || modified == file.modified {
+        continue;
+      }
+      file.modified = modified;
+      let result = (file.load)(&file.path);
+      if loaded.send(Loaded { id, result }).is_err() {
+        return;
+      }
+    }
+  }
+}
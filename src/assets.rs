@@ -0,0 +1,337 @@
+use std::{
+  collections::HashMap,
+  path::{Path, PathBuf},
+};
+
+// Loose assets during development, on the web it's resolved against the page URL
+pub const ASSET_DIR: &str = "res";
+// Release builds ship this instead of the res/ tree, see the pack_assets tool
+pub const PACK_FILE: &str = "assets.pack";
+
+const MAGIC: &[u8; 4] = b"WLPK";
+const PACK_VERSION: u32 = 1;
+const COMPRESSION_LEVEL: u8 = 6;
+
+#[derive(Debug)]
+pub enum AssetError {
+  NotFound(String),
+  Io(String, std::io::Error),
+  Corrupt(String),
+  // path and the message from the browser
+  Fetch(String, String),
+}
+
+impl std::fmt::Display for AssetError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      AssetError::NotFound(path) => write!(f, "asset {} not found", path),
+      AssetError::Io(path, e) => write!(f, "couldn't read {}: {}", path, e),
+      AssetError::Corrupt(path) => write!(f, "{} is corrupt", path),
+      AssetError::Fetch(path, message) => write!(f, "couldn't fetch {}: {}", path, message),
+    }
+  }
+}
+
+impl std::error::Error for AssetError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      AssetError::Io(_, e) => Some(e),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PackEntry {
+  offset: usize,
+  compressed_len: usize,
+  len: usize,
+}
+
+// A packed asset tree kept in memory, each file is deflated on its own so reads
+// only decompress what they need
+//
+//   magic, version: u32, entry count: u32
+//   per entry: path length: u32, path, offset: u64, compressed length: u64, length: u64
+//   compressed data, offsets are relative to its start
+pub struct PackArchive {
+  entries: HashMap<String, PackEntry>,
+  data: Vec<u8>,
+}
+
+impl PackArchive {
+  pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, AssetError> {
+    let corrupt = || AssetError::Corrupt(PACK_FILE.to_string());
+    let mut cursor = 0;
+    let mut take = |len: usize| -> Result<&[u8], AssetError> {
+      let slice = bytes.get(cursor..cursor + len).ok_or_else(corrupt)?;
+      cursor += len;
+      Ok(slice)
+    };
+    let u32_at = |b: &[u8]| u32::from_le_bytes(b.try_into().unwrap()) as usize;
+    let u64_at = |b: &[u8]| u64::from_le_bytes(b.try_into().unwrap()) as usize;
+
+    if take(4)? != MAGIC || u32_at(take(4)?) != PACK_VERSION as usize {
+      return Err(corrupt());
+    }
+    let count = u32_at(take(4)?);
+    let mut entries = HashMap::with_capacity(count);
+    for _ in 0..count {
+      let path_len = u32_at(take(4)?);
+      let path = String::from_utf8(take(path_len)?.to_vec()).map_err(|_| corrupt())?;
+      let entry = PackEntry {
+        offset: u64_at(take(8)?),
+        compressed_len: u64_at(take(8)?),
+        len: u64_at(take(8)?),
+      };
+      entries.insert(path, entry);
+    }
+
+    let data = bytes[cursor..].to_vec();
+    if entries
+      .values()
+      .any(|e| e.offset + e.compressed_len > data.len())
+    {
+      return Err(corrupt());
+    }
+    Ok(Self { entries, data })
+  }
+
+  pub fn contains(&self, path: &str) -> bool {
+    self.entries.contains_key(path)
+  }
+
+  pub fn paths(&self) -> impl Iterator<Item = &str> {
+    self.entries.keys().map(String::as_str)
+  }
+
+  pub fn read(&self, path: &str) -> Result<Vec<u8>, AssetError> {
+    let entry = self
+      .entries
+      .get(path)
+      .ok_or_else(|| AssetError::NotFound(path.to_string()))?;
+    let compressed = &self.data[entry.offset..entry.offset + entry.compressed_len];
+    let bytes = miniz_oxide::inflate::decompress_to_vec(compressed)
+      .map_err(|_| AssetError::Corrupt(path.to_string()))?;
+    if bytes.len() != entry.len {
+      return Err(AssetError::Corrupt(path.to_string()));
+    }
+    Ok(bytes)
+  }
+}
+
+// Every file under `dir`, as paths relative to it with `/` separators, sorted
+pub fn collect_files(dir: &Path) -> std::io::Result<Vec<String>> {
+  fn walk(root: &Path, dir: &Path, out: &mut Vec<String>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+      let path = entry?.path();
+      if path.is_dir() {
+        walk(root, &path, out)?;
+      } else {
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+        let parts: Vec<_> = relative
+          .components()
+          .map(|c| c.as_os_str().to_string_lossy())
+          .collect();
+        out.push(parts.join("/"));
+      }
+    }
+    Ok(())
+  }
+  let mut files = Vec::new();
+  walk(dir, dir, &mut files)?;
+  files.sort();
+  Ok(files)
+}
+
+// Builds an archive of everything under `dir`
+pub fn pack_dir(dir: &Path) -> std::io::Result<Vec<u8>> {
+  let files = collect_files(dir)?;
+  let mut header = Vec::new();
+  let mut data = Vec::new();
+  header.extend_from_slice(MAGIC);
+  header.extend_from_slice(&PACK_VERSION.to_le_bytes());
+  header.extend_from_slice(&(files.len() as u32).to_le_bytes());
+  for path in &files {
+    let bytes = std::fs::read(dir.join(path))?;
+    let compressed = miniz_oxide::deflate::compress_to_vec(&bytes, COMPRESSION_LEVEL);
+    header.extend_from_slice(&(path.len() as u32).to_le_bytes());
+    header.extend_from_slice(path.as_bytes());
+    header.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    header.extend_from_slice(&(compressed.len() as u64).to_le_bytes());
+    header.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+    data.extend_from_slice(&compressed);
+  }
+  header.extend_from_slice(&data);
+  Ok(header)
+}
+
+// Reported while an asset loads, `total` is None when the server doesn't send a length
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadProgress {
+  pub loaded: u64,
+  pub total: Option<u64>,
+}
+
+impl LoadProgress {
+  pub fn fraction(&self) -> Option<f32> {
+    self
+      .total
+      .filter(|&total| total > 0)
+      .map(|total| self.loaded as f32 / total as f32)
+  }
+}
+
+// Where assets are read from, paths are always relative with `/` separators
+pub enum Assets {
+  Dir(PathBuf),
+  Pack(PackArchive),
+  // base URL, each read is a fetch() of `<base>/<path>`
+  Http(String),
+}
+
+impl Assets {
+  // Native: the pack next to the executable (or in the working directory) when there is
+  // one, the res/ directory otherwise.
+  // Web: the pack fetched once from the page's directory, or per file fetches from res/.
+  pub async fn open() -> Self {
+    #[cfg(target_arch = "wasm32")]
+    {
+      match web::fetch(PACK_FILE, |_| {}).await {
+        Ok(bytes) => match PackArchive::from_bytes(bytes) {
+          Ok(pack) => {
+            log::info!("reading assets from {}", PACK_FILE);
+            return Assets::Pack(pack);
+          }
+          Err(e) => log::warn!("ignoring {}: {}", PACK_FILE, e),
+        },
+        Err(e) => log::info!("{}, fetching loose assets", e),
+      }
+      Assets::Http(ASSET_DIR.to_string())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+      let exe_dir = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf));
+      let candidates = exe_dir
+        .into_iter()
+        .map(|dir| dir.join(PACK_FILE))
+        .chain([PathBuf::from(PACK_FILE)]);
+      for path in candidates {
+        let Ok(bytes) = std::fs::read(&path) else {
+          continue;
+        };
+        match PackArchive::from_bytes(bytes) {
+          Ok(pack) => {
+            log::info!("reading assets from {}", path.display());
+            return Assets::Pack(pack);
+          }
+          Err(e) => log::warn!("ignoring {}: {}", path.display(), e),
+        }
+      }
+      Assets::Dir(PathBuf::from(ASSET_DIR))
+    }
+  }
+
+  pub async fn read(&self, path: &str) -> Result<Vec<u8>, AssetError> {
+    self.read_with_progress(path, |_| {}).await
+  }
+
+  // Local reads finish in one go and report once, fetches report every received chunk
+  pub async fn read_with_progress(
+    &self,
+    path: &str,
+    mut on_progress: impl FnMut(LoadProgress),
+  ) -> Result<Vec<u8>, AssetError> {
+    let bytes = match self {
+      Assets::Dir(dir) => std::fs::read(dir.join(path)).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => AssetError::NotFound(path.to_string()),
+        _ => AssetError::Io(path.to_string(), e),
+      })?,
+      Assets::Pack(pack) => pack.read(path)?,
+      #[cfg(target_arch = "wasm32")]
+      Assets::Http(base) => return web::fetch(&format!("{}/{}", base, path), on_progress).await,
+      #[cfg(not(target_arch = "wasm32"))]
+      Assets::Http(_) => {
+        return Err(AssetError::Fetch(
+          path.to_string(),
+          "fetching is only supported on the web".to_string(),
+        ))
+      }
+    };
+    let len = bytes.len() as u64;
+    on_progress(LoadProgress {
+      loaded: len,
+      total: Some(len),
+    });
+    Ok(bytes)
+  }
+
+  pub async fn read_to_string(&self, path: &str) -> Result<String, AssetError> {
+    String::from_utf8(self.read(path).await?).map_err(|_| AssetError::Corrupt(path.to_string()))
+  }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod web {
+  use js_sys::{Reflect, Uint8Array};
+  use wasm_bindgen::{JsCast, JsValue};
+  use wasm_bindgen_futures::JsFuture;
+
+  use super::{AssetError, LoadProgress};
+
+  fn message(value: JsValue) -> String {
+    value.as_string().unwrap_or_else(|| format!("{:?}", value))
+  }
+
+  // fetch() that streams the body so progress can be reported per chunk
+  pub async fn fetch(
+    url: &str,
+    mut on_progress: impl FnMut(LoadProgress),
+  ) -> Result<Vec<u8>, AssetError> {
+    let error = |value: JsValue| AssetError::Fetch(url.to_string(), message(value));
+    let window = web_sys::window().ok_or_else(|| error("no window".into()))?;
+    let response: web_sys::Response = JsFuture::from(window.fetch_with_str(url))
+      .await
+      .map_err(error)?
+      .dyn_into()
+      .map_err(error)?;
+    if response.status() == 404 {
+      return Err(AssetError::NotFound(url.to_string()));
+    }
+    if !response.ok() {
+      return Err(error(
+        format!("{} {}", response.status(), response.status_text()).into(),
+      ));
+    }
+
+    let total = response
+      .headers()
+      .get("content-length")
+      .ok()
+      .flatten()
+      .and_then(|len| len.parse().ok());
+    let Some(body) = response.body() else {
+      return Ok(Vec::new());
+    };
+    let reader: web_sys::ReadableStreamDefaultReader = body.get_reader().unchecked_into();
+
+    let mut bytes = Vec::with_capacity(total.unwrap_or(0) as usize);
+    loop {
+      let chunk = JsFuture::from(reader.read()).await.map_err(error)?;
+      let done = Reflect::get(&chunk, &"done".into()).map_err(error)?;
+      if done.as_bool().unwrap_or(true) {
+        break;
+      }
+      let value = Reflect::get(&chunk, &"value".into()).map_err(error)?;
+      bytes.extend(Uint8Array::new(&value).to_vec());
+      on_progress(LoadProgress {
+        loaded: bytes.len() as u64,
+        total,
+      });
+    }
+    Ok(bytes)
+  }
+}
@@ -0,0 +1,349 @@
+// Sound effects and background music. A Sound is decoded or synthesized once into samples,
+// and every play() mixes in a copy. With the `audio` feature they go out through rodio to
+// the default output device. Without it, or without a device to open, everything here still
+// works but stays silent, so nothing else has to check.
+
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
+use rodio::{buffer::SamplesBuffer, OutputStream, OutputStreamHandle, Sink, Source};
+
+const SAMPLE_RATE: u32 = 44100;
+// what the volume keys change it by
+const VOLUME_STEP: f32 = 0.1;
+// the built in loop, semitones above A3 with a rest for None
+const MELODY: [Option<i32>; 16] = [
+  Some(0),
+  Some(3),
+  Some(7),
+  Some(10),
+  Some(12),
+  None,
+  Some(10),
+  Some(7),
+  Some(5),
+  Some(8),
+  Some(12),
+  None,
+  Some(3),
+  Some(7),
+  Some(5),
+  None,
+];
+
+// The `[audio]` section of the config file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+  // master volume, 0 to 1
+  pub volume: f32,
+  // whether the background music starts with the app
+  pub music: bool,
+}
+
+impl Default for AudioSettings {
+  fn default() -> Self {
+    Self {
+      volume: 0.5,
+      music: false,
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum AudioError {
+  Io(std::io::Error),
+  Decode(String),
+  // built without the audio feature
+  Disabled,
+}
+
+impl std::fmt::Display for AudioError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      AudioError::Io(e) => write!(f, "couldn't read the sound: {}", e),
+      AudioError::Decode(e) => write!(f, "couldn't decode the sound: {}", e),
+      AudioError::Disabled => write!(f, "built without the audio feature"),
+    }
+  }
+}
+
+impl std::error::Error for AudioError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      AudioError::Io(e) => Some(e),
+      _ => None,
+    }
+  }
+}
+
+impl From<std::io::Error> for AudioError {
+  fn from(e: std::io::Error) -> Self {
+    AudioError::Io(e)
+  }
+}
+
+// Interleaved samples, -1 to 1
+#[derive(Debug, Clone)]
+pub struct Sound {
+  channels: u16,
+  sample_rate: u32,
+  samples: Arc<[f32]>,
+}
+
+impl Sound {
+  pub fn from_samples(channels: u16, sample_rate: u32, samples: Vec<f32>) -> Self {
+    Self {
+      channels: channels.max(1),
+      sample_rate,
+      samples: samples.into(),
+    }
+  }
+
+  // A WAV file
+  pub fn load(path: impl AsRef<Path>) -> Result<Self, AudioError> {
+    #[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
+    {
+      let file = std::io::BufReader::new(std::fs::File::open(path)?);
+      let decoder = rodio::Decoder::new(file).map_err(|e| AudioError::Decode(e.to_string()))?;
+      let (channels, sample_rate) = (decoder.channels(), decoder.sample_rate());
+      let samples = decoder.convert_samples().collect();
+      Ok(Self::from_samples(channels, sample_rate, samples))
+    }
+    #[cfg(not(all(feature = "audio", not(target_arch = "wasm32"))))]
+    {
+      let _ = path;
+      Err(AudioError::Disabled)
+    }
+  }
+
+  // A sine beep of `frequency` Hz dying away over `duration` seconds
+  pub fn tone(frequency: f32, duration: f32) -> Self {
+    let count = (duration * SAMPLE_RATE as f32) as usize;
+    let samples = (0..count)
+      .map(|i| {
+        let t = i as f32 / SAMPLE_RATE as f32;
+        let fade = (1.0 - t / duration).powi(3);
+        (std::f32::consts::TAU * frequency * t).sin() * fade
+      })
+      .collect();
+    Self::from_samples(1, SAMPLE_RATE, samples)
+  }
+
+  // tone()s one after another, `note` seconds each, semitones above A3 or silent for None
+  pub fn melody(notes: &[Option<i32>], note: f32) -> Self {
+    let rest = (note * SAMPLE_RATE as f32) as usize;
+    let samples = notes
+      .iter()
+      .flat_map(|semitones| match semitones {
+        Some(semitones) => {
+          let frequency = 220.0 * 2f32.powf(*semitones as f32 / 12.0);
+          Self::tone(frequency, note).samples.to_vec()
+        }
+        None => vec![0.0; rest],
+      })
+      .collect();
+    Self::from_samples(1, SAMPLE_RATE, samples)
+  }
+
+  pub fn channels(&self) -> u16 {
+    self.channels
+  }
+
+  pub fn sample_rate(&self) -> u32 {
+    self.sample_rate
+  }
+
+  // Seconds
+  pub fn duration(&self) -> f32 {
+    self.samples.len() as f32 / (self.channels as u32 * self.sample_rate.max(1)) as f32
+  }
+
+  #[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
+  fn source(&self) -> SamplesBuffer<f32> {
+    SamplesBuffer::new(self.channels, self.sample_rate, self.samples.to_vec())
+  }
+}
+
+// The open output device, and the sink of the music playing on it
+#[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
+struct Output {
+  // plays for as long as it's kept
+  _stream: OutputStream,
+  handle: OutputStreamHandle,
+  music: Option<Sink>,
+}
+
+pub struct Audio {
+  sounds: HashMap<String, Sound>,
+  volume: f32,
+  // the sound looping in the background
+  music: Option<String>,
+  #[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
+  output: Option<Output>,
+}
+
+impl Audio {
+  // With the built in sounds: "click" and the "music" loop
+  pub fn new(settings: &AudioSettings) -> Self {
+    #[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
+    let output = match OutputStream::try_default() {
+      Ok((stream, handle)) => Some(Output {
+        _stream: stream,
+        handle,
+        music: None,
+      }),
+      Err(e) => {
+        log::warn!("no sound: {}", e);
+        None
+      }
+    };
+    let mut audio = Self {
+      sounds: HashMap::new(),
+      volume: settings.volume.clamp(0.0, 1.0),
+      music: None,
+      #[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
+      output,
+    };
+    audio.add("click", Sound::tone(1320.0, 0.06));
+    audio.add("music", Sound::melody(&MELODY, 0.25));
+    if settings.music {
+      audio.play_music("music");
+    }
+    audio
+  }
+
+  // Whether sounds are heard, with the feature and a device
+  pub fn is_audible(&self) -> bool {
+    #[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
+    return self.output.is_some();
+    #[cfg(not(all(feature = "audio", not(target_arch = "wasm32"))))]
+    false
+  }
+
+  // Replaces any sound of the same name
+  pub fn add(&mut self, name: &str, sound: Sound) {
+    self.sounds.insert(name.to_string(), sound);
+  }
+
+  pub fn load(&mut self, name: &str, path: impl AsRef<Path>) -> Result<(), AudioError> {
+    let sound = Sound::load(path)?;
+    self.add(name, sound);
+    Ok(())
+  }
+
+  pub fn sound(&self, name: &str) -> Option<&Sound> {
+    self.sounds.get(name)
+  }
+
+  // Once, over whatever else is playing
+  pub fn play(&self, name: &str) {
+    let Some(sound) = self.sounds.get(name) else {
+      log::warn!("no sound named {}", name);
+      return;
+    };
+    #[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
+    if let Some(output) = &self.output {
+      match Sink::try_new(&output.handle) {
+        Ok(sink) => {
+          sink.set_volume(self.volume);
+          sink.append(sound.source());
+          // plays to its end on its own
+          sink.detach();
+        }
+        Err(e) => log::warn!("couldn't play {}: {}", name, e),
+      }
+    }
+    #[cfg(not(all(feature = "audio", not(target_arch = "wasm32"))))]
+    let _ = sound;
+  }
+
+  // Loops `name` in the background instead of the music before
+  pub fn play_music(&mut self, name: &str) {
+    let Some(sound) = self.sounds.get(name) else {
+      log::warn!("no sound named {}", name);
+      return;
+    };
+    #[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
+    if let Some(output) = &mut self.output {
+      match Sink::try_new(&output.handle) {
+        Ok(sink) => {
+          sink.set_volume(self.volume);
+          sink.append(sound.source().repeat_infinite());
+          // dropping the one before stops it
+          output.music = Some(sink);
+        }
+        Err(e) => log::warn!("couldn't play {}: {}", name, e),
+      }
+    }
+    #[cfg(not(all(feature = "audio", not(target_arch = "wasm32"))))]
+    let _ = sound;
+    self.music = Some(name.to_string());
+  }
+
+  pub fn stop_music(&mut self) {
+    #[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
+    if let Some(output) = &mut self.output {
+      output.music = None;
+    }
+    self.music = None;
+  }
+
+  pub fn music(&self) -> Option<&str> {
+    self.music.as_deref()
+  }
+
+  pub fn volume(&self) -> f32 {
+    self.volume
+  }
+
+  // Of everything, the music that's playing as well
+  pub fn set_volume(&mut self, volume: f32) {
+    self.volume = volume.clamp(0.0, 1.0);
+    #[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
+    if let Some(music) = self
+      .output
+      .as_ref()
+      .and_then(|output| output.music.as_ref())
+    {
+      music.set_volume(self.volume);
+    }
+  }
+
+  // What to save for next time
+  pub fn settings(&self) -> AudioSettings {
+    AudioSettings {
+      volume: self.volume,
+      music: self.music.is_some(),
+    }
+  }
+
+  // The actions in keymap.rs it handles, returns whether `action` is one
+  pub fn action(&mut self, action: &str) -> bool {
+    match action {
+      "music" => {
+        if self.music.is_some() {
+          self.stop_music();
+        } else {
+          self.play_music("music");
+        }
+        log::info!("music {}", if self.music.is_some() { "on" } else { "off" });
+      }
+      "volume_up" | "volume_down" => {
+        let step = if action == "volume_up" {
+          VOLUME_STEP
+        } else {
+          -VOLUME_STEP
+        };
+        self.set_volume(self.volume + step);
+        log::info!("volume {:.0}%", self.volume * 100.0);
+        // to hear how loud it is now
+        self.play("click");
+      }
+      _ => return false,
+    }
+    true
+  }
+}
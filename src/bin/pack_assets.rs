@@ -0,0 +1,49 @@
+// Bundles the asset tree into a single archive for release builds
+//
+//   cargo run --bin pack_assets -- [dir] [output]
+//
+// Ship the output next to the executable and Assets::open() picks it up instead of res/
+use std::{path::Path, process::ExitCode};
+
+use wgpu_learn::assets::{collect_files, pack_dir, PackArchive, ASSET_DIR, PACK_FILE};
+
+fn main() -> ExitCode {
+  let args: Vec<String> = std::env::args().skip(1).collect();
+  let dir = args.first().map_or(ASSET_DIR, String::as_str);
+  let output = args.get(1).map_or(PACK_FILE, String::as_str);
+
+  let packed = match pack_dir(Path::new(dir)) {
+    Ok(packed) => packed,
+    Err(e) => {
+      eprintln!("error: couldn't pack {}: {}", dir, e);
+      return ExitCode::FAILURE;
+    }
+  };
+  let files = collect_files(Path::new(dir)).unwrap_or_default();
+  let loose: u64 = files
+    .iter()
+    .filter_map(|f| std::fs::metadata(Path::new(dir).join(f)).ok())
+    .map(|m| m.len())
+    .sum();
+
+  // read it back before writing so a broken pack never gets shipped
+  if let Err(e) = PackArchive::from_bytes(packed.clone()) {
+    eprintln!("error: the archive doesn't read back: {}", e);
+    return ExitCode::FAILURE;
+  }
+  if let Err(e) = std::fs::write(output, &packed) {
+    eprintln!("error: couldn't write {}: {}", output, e);
+    return ExitCode::FAILURE;
+  }
+  for file in &files {
+    println!("{}", file);
+  }
+  println!(
+    "{} files, {} bytes -> {} bytes in {}",
+    files.len(),
+    loose,
+    packed.len(),
+    output
+  );
+  ExitCode::SUCCESS
+}
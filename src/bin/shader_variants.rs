@@ -0,0 +1,122 @@
+// Lists or compiles every PBR material shader variant ahead of time
+//
+//   cargo run --bin shader_variants -- list
+//   cargo run --bin shader_variants -- emit <dir>   write the preprocessed WGSL of each variant
+//   cargo run --bin shader_variants -- compile      build every pipeline for every blend mode
+//                                                   and report errors
+use std::process::ExitCode;
+
+use wgpu_learn::{
+  adapter::request_device,
+  hdr::HDR_FORMAT,
+  pipeline::{multisample_state, pbr_source, BlendMode, DrawData},
+  preprocessor::ShaderDefs,
+  render_settings::RenderSettings,
+  sampler::Samplers,
+  scene::Scene,
+  shader_variants::MaterialFeatures,
+};
+
+fn list() -> ExitCode {
+  for features in MaterialFeatures::all() {
+    let defs: Vec<String> = features
+      .defs(ShaderDefs::new())
+      .iter()
+      .map(|(name, value)| match value {
+        "" => name.to_string(),
+        value => format!("{}={}", name, value),
+      })
+      .collect();
+    println!("{:<40} {}", features.name(), defs.join(" "));
+  }
+  ExitCode::SUCCESS
+}
+
+// For DrawData::Uniform without the bindless arrays, what every adapter can run, at the
+// default msaa_samples
+fn emit(dir: &str) -> ExitCode {
+  if let Err(e) = std::fs::create_dir_all(dir) {
+    eprintln!("error: couldn't create {}: {}", dir, e);
+    return ExitCode::FAILURE;
+  }
+  let sample_count = RenderSettings::default().msaa_samples;
+  for features in MaterialFeatures::all() {
+    let path = format!("{}/pbr.{}.wgsl", dir, features.name());
+    let alpha_to_coverage =
+      multisample_state(sample_count, features.alpha_cutout).alpha_to_coverage_enabled;
+    let source = pbr_source(DrawData::Uniform, None, features, alpha_to_coverage);
+    match std::fs::write(&path, source) {
+      Ok(()) => println!("{}", path),
+      Err(e) => {
+        eprintln!("error: {}: {}", features.name(), e);
+        return ExitCode::FAILURE;
+      }
+    }
+  }
+  ExitCode::SUCCESS
+}
+
+// Through the scene's own PbrPipeline, so the variants are built the way the app builds them
+fn compile() -> ExitCode {
+  let instance = wgpu::Instance::default();
+  let Some(adapter) = pollster::block_on(instance.request_adapter(&Default::default())) else {
+    eprintln!("error: no GPU adapter available");
+    return ExitCode::FAILURE;
+  };
+  let (device, queue) = match pollster::block_on(request_device(&adapter)) {
+    Ok(device) => device,
+    Err(e) => {
+      eprintln!("error: {}", e);
+      return ExitCode::FAILURE;
+    }
+  };
+  let draw_data = DrawData::pick(&adapter, &device, false);
+  let mut scene = Scene::new(
+    &device,
+    &queue,
+    &Samplers::new(&adapter),
+    HDR_FORMAT,
+    RenderSettings::default().msaa_samples,
+    1024,
+    draw_data,
+  );
+  let variants = scene.pbr_mut().variants_mut();
+
+  let mut failed = 0;
+  let mut count = 0;
+  for features in MaterialFeatures::all() {
+    for blend in BlendMode::ALL {
+      let name = format!("{} {:?} ({:?})", features.name(), blend, draw_data);
+      count += 1;
+      // validation errors would otherwise only be logged by wgpu's default handler
+      device.push_error_scope(wgpu::ErrorFilter::Validation);
+      variants.get(&device, features, blend);
+      match pollster::block_on(device.pop_error_scope()) {
+        None => println!("ok      {}", name),
+        Some(e) => {
+          failed += 1;
+          println!("FAILED  {}\n{}", name, e);
+        }
+      }
+    }
+  }
+  println!("{} variants, {} failed", count, failed);
+  if failed == 0 {
+    ExitCode::SUCCESS
+  } else {
+    ExitCode::FAILURE
+  }
+}
+
+fn main() -> ExitCode {
+  let args: Vec<String> = std::env::args().skip(1).collect();
+  match args.first().map(String::as_str) {
+    Some("list") | None => list(),
+    Some("emit") if args.len() == 2 => emit(&args[1]),
+    Some("compile") => compile(),
+    _ => {
+      eprintln!("usage: shader_variants [list | emit <dir> | compile]");
+      ExitCode::FAILURE
+    }
+  }
+}
@@ -0,0 +1,323 @@
+use wgpu::{
+  util::DeviceExt, BindGroupLayout, CommandEncoder, Device, Queue, RenderPass, TextureFormat,
+  TextureView,
+};
+
+use crate::{
+  gpu_memory::Tracked, hdr::scene_depth_state, math::next_random, pipeline::multisample_state,
+  stats::count_draw,
+};
+
+const PARTICLES_PER_GROUP: u32 = 64;
+// Same seed every run so the simulation is reproducible
+const SEED: u32 = 0x2545_f491;
+
+// What boids do where the last frame's depth buffer has something drawn. They fly in screen
+// space, so anything in front of the sky counts, however far away it is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Collision {
+  // fly over it
+  #[default]
+  Off,
+  // bounce off its outline, keeping `restitution` of their speed
+  Bounce,
+  // start again at a random spot along the top of the screen, like rain
+  Kill,
+}
+
+impl Collision {
+  pub const ALL: [Collision; 3] = [Collision::Off, Collision::Bounce, Collision::Kill];
+}
+
+// Matches `SimParams` in boids.wgsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct SimParams {
+  delta_t: f32,
+  rule1_distance: f32,
+  rule2_distance: f32,
+  rule3_distance: f32,
+  rule1_scale: f32,
+  rule2_scale: f32,
+  rule3_scale: f32,
+  // Collision as its index in Collision::ALL
+  collision: u32,
+  restitution: f32,
+  _padding: [f32; 3],
+}
+
+// Flocking simulation on the GPU, ping-ponging between two particle buffers
+pub struct Boids {
+  params: SimParams,
+  params_buffer: Tracked<wgpu::Buffer>,
+  particle_buffers: [Tracked<wgpu::Buffer>; 2],
+  vertex_buffer: Tracked<wgpu::Buffer>,
+  bind_groups: [wgpu::BindGroup; 2],
+  // group 1 of the compute pass, the depth collided with
+  depth_layout: BindGroupLayout,
+  compute_pipeline: wgpu::ComputePipeline,
+  render_pipeline: wgpu::RenderPipeline,
+  // index of the buffer holding the latest state
+  current: usize,
+  count: u32,
+  enabled: bool,
+}
+
+impl Boids {
+  // `tick` is the simulated time per step in seconds
+  pub fn new(
+    device: &Device,
+    format: TextureFormat,
+    sample_count: u32,
+    tick: f32,
+    count: u32,
+  ) -> Self {
+    let params = SimParams {
+      // the original tuning assumed a 0.04 step at 60fps
+      delta_t: tick * 2.4,
+      rule1_distance: 0.1,
+      rule2_distance: 0.025,
+      rule3_distance: 0.025,
+      rule1_scale: 0.02,
+      rule2_scale: 0.05,
+      rule3_scale: 0.005,
+      collision: 0,
+      restitution: 0.6,
+      _padding: [0.0; 3],
+    };
+    let params_buffer = Tracked::buffer(device.create_buffer_init(
+      &wgpu::util::BufferInitDescriptor {
+        label: Some("Boids Params Buffer"),
+        contents: bytemuck::bytes_of(&params),
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+      },
+    ));
+
+    let mut seed = SEED;
+    let initial: Vec<f32> = (0..count)
+      .flat_map(|_| {
+        [
+          next_random(&mut seed) * 2.0 - 1.0,
+          next_random(&mut seed) * 2.0 - 1.0,
+          (next_random(&mut seed) * 2.0 - 1.0) * 0.1,
+          (next_random(&mut seed) * 2.0 - 1.0) * 0.1,
+        ]
+      })
+      .collect();
+    let particle_buffers = [0, 1].map(|i| {
+      Tracked::buffer(
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+          label: Some(&format!("Boids Particle Buffer {}", i)),
+          contents: bytemuck::cast_slice(&initial),
+          usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
+        }),
+      )
+    });
+
+    // a small triangle pointing up, rotated along the velocity in the shader
+    let vertex_buffer = Tracked::buffer(device.create_buffer_init(
+      &wgpu::util::BufferInitDescriptor {
+        label: Some("Boids Vertex Buffer"),
+        contents: bytemuck::cast_slice(&[-0.01f32, -0.02, 0.01, -0.02, 0.00, 0.02]),
+        usage: wgpu::BufferUsages::VERTEX,
+      },
+    ));
+
+    let shader = device.create_shader_module(wgpu::include_wgsl!("boids.wgsl"));
+    let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility: wgpu::ShaderStages::COMPUTE,
+      ty: wgpu::BindingType::Buffer {
+        ty: wgpu::BufferBindingType::Storage { read_only },
+        has_dynamic_offset: false,
+        min_binding_size: None,
+      },
+      count: None,
+    };
+    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Boids Bind Group Layout"),
+      entries: &[
+        wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::COMPUTE,
+          ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+          },
+          count: None,
+        },
+        storage_entry(1, true),
+        storage_entry(2, false),
+      ],
+    });
+    // bind group i reads buffer i and writes the other one
+    let bind_groups = [0, 1].map(|i| {
+      device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some(&format!("Boids Bind Group {}", i)),
+        layout: &layout,
+        entries: &[
+          wgpu::BindGroupEntry {
+            binding: 0,
+            resource: params_buffer.as_entire_binding(),
+          },
+          wgpu::BindGroupEntry {
+            binding: 1,
+            resource: particle_buffers[i].as_entire_binding(),
+          },
+          wgpu::BindGroupEntry {
+            binding: 2,
+            resource: particle_buffers[1 - i].as_entire_binding(),
+          },
+        ],
+      })
+    });
+
+    let depth_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Boids Depth Bind Group Layout"),
+      entries: &[wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        // a plain float texture, GLSL has no textureLoad for depth textures
+        ty: wgpu::BindingType::Texture {
+          sample_type: wgpu::TextureSampleType::Float { filterable: false },
+          view_dimension: wgpu::TextureViewDimension::D2,
+          multisampled: false,
+        },
+        count: None,
+      }],
+    });
+
+    let compute_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Boids Compute Pipeline Layout"),
+      bind_group_layouts: &[&layout, &depth_layout],
+      push_constant_ranges: &[],
+    });
+    let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+      label: Some("Boids Compute Pipeline"),
+      layout: Some(&compute_layout),
+      module: &shader,
+      entry_point: "cs_main",
+    });
+
+    let render_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Boids Render Pipeline Layout"),
+      bind_group_layouts: &[],
+      push_constant_ranges: &[],
+    });
+    let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+      label: Some("Boids Render Pipeline"),
+      layout: Some(&render_layout),
+      vertex: wgpu::VertexState {
+        module: &shader,
+        entry_point: "vs_main",
+        buffers: &[
+          wgpu::VertexBufferLayout {
+            array_stride: 4 * 4,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2],
+          },
+          wgpu::VertexBufferLayout {
+            array_stride: 2 * 4,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &wgpu::vertex_attr_array![2 => Float32x2],
+          },
+        ],
+      },
+      fragment: Some(wgpu::FragmentState {
+        module: &shader,
+        entry_point: "fs_main",
+        targets: &[Some(format.into())],
+      }),
+      primitive: wgpu::PrimitiveState::default(),
+      // only ever drawn in the main scene pass
+      depth_stencil: Some(scene_depth_state()),
+      multisample: multisample_state(sample_count, false),
+      multiview: None,
+    });
+
+    Self {
+      params,
+      params_buffer,
+      particle_buffers,
+      vertex_buffer,
+      bind_groups,
+      depth_layout,
+      compute_pipeline,
+      render_pipeline,
+      current: 0,
+      count,
+      enabled: false,
+    }
+  }
+
+  pub fn is_enabled(&self) -> bool {
+    self.enabled
+  }
+
+  pub fn toggle(&mut self) {
+    self.enabled = !self.enabled;
+  }
+
+  // How strongly the boids steer to their neighbours' center, away from each other and along
+  // their heading
+  pub fn set_rule_scales(&mut self, queue: &Queue, [cohesion, separation, alignment]: [f32; 3]) {
+    self.params.rule1_scale = cohesion;
+    self.params.rule2_scale = separation;
+    self.params.rule3_scale = alignment;
+    queue.write_buffer(&self.params_buffer, 0, bytemuck::bytes_of(&self.params));
+  }
+
+  pub fn set_collision(&mut self, queue: &Queue, collision: Collision, restitution: f32) {
+    self.params.collision = Collision::ALL
+      .iter()
+      .position(|&c| c == collision)
+      .unwrap_or(0) as u32;
+    self.params.restitution = restitution;
+    queue.write_buffer(&self.params_buffer, 0, bytemuck::bytes_of(&self.params));
+  }
+
+  // Advances the flock by `ticks` fixed steps, colliding with `depth`. That's the depth of
+  // the frame before, the one this frame draws is only cleared after.
+  pub fn step(
+    &mut self,
+    device: &Device,
+    encoder: &mut CommandEncoder,
+    ticks: u32,
+    depth: &TextureView,
+  ) {
+    if !self.enabled || ticks == 0 {
+      return;
+    }
+    // made per step since the depth belongs to whichever viewport is drawing
+    let depth_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Boids Depth Bind Group"),
+      layout: &self.depth_layout,
+      entries: &[wgpu::BindGroupEntry {
+        binding: 0,
+        resource: wgpu::BindingResource::TextureView(depth),
+      }],
+    });
+    let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+      label: Some("Boids Compute Pass"),
+    });
+    pass.set_pipeline(&self.compute_pipeline);
+    pass.set_bind_group(1, &depth_bind_group, &[]);
+    for _ in 0..ticks {
+      pass.set_bind_group(0, &self.bind_groups[self.current], &[]);
+      pass.dispatch_workgroups(self.count.div_ceil(PARTICLES_PER_GROUP), 1, 1);
+      self.current = 1 - self.current;
+    }
+  }
+
+  pub fn render<'a>(&'a self, pass: &mut RenderPass<'a>) {
+    if !self.enabled {
+      return;
+    }
+    pass.set_pipeline(&self.render_pipeline);
+    pass.set_vertex_buffer(0, self.particle_buffers[self.current].slice(..));
+    pass.set_vertex_buffer(1, self.vertex_buffer.slice(..));
+    count_draw();
+    pass.draw(0..3, 0..self.count);
+  }
+}
@@ -0,0 +1,165 @@
+struct Particle {
+    pos: vec2<f32>,
+    vel: vec2<f32>,
+};
+
+struct SimParams {
+    delta_t: f32,
+    rule1_distance: f32,
+    rule2_distance: f32,
+    rule3_distance: f32,
+    rule1_scale: f32,
+    rule2_scale: f32,
+    rule3_scale: f32,
+    // 0: off, 1: bounce, 2: kill, see Collision in boids.rs
+    collision: u32,
+    // share of the speed a bounce keeps
+    restitution: f32,
+};
+
+@group(0) @binding(0)
+var<uniform> params: SimParams;
+@group(0) @binding(1)
+var<storage, read> particles_src: array<Particle>;
+@group(0) @binding(2)
+var<storage, read_write> particles_dst: array<Particle>;
+// the scene's depth of the last frame, 1 where nothing was drawn
+@group(1) @binding(0)
+var depth_map: texture_2d<f32>;
+
+const COLLISION_BOUNCE: u32 = 1u;
+const COLLISION_KILL: u32 = 2u;
+// how far apart the depth is sampled around a hit to find which way is out, in ndc
+const NORMAL_OFFSET: f32 = 0.02;
+
+// The depth at `pos`, clip space y points up and texel rows down
+fn depth_at(pos: vec2<f32>) -> f32 {
+    let size = vec2<i32>(textureDimensions(depth_map));
+    let texel = vec2<i32>((pos * vec2<f32>(0.5, -0.5) + 0.5) * vec2<f32>(size));
+    return textureLoad(depth_map, clamp(texel, vec2<i32>(0), size - 1), 0).r;
+}
+
+// PCG, for respawn spots that differ per boid and per hit
+fn hash(input: u32) -> u32 {
+    let state = input * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// https://github.com/austinEng/Project6-Vulkan-Flocking/blob/master/data/shaders/computeparticles/particle.comp
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let total = arrayLength(&particles_src);
+    let index = global_invocation_id.x;
+    if index >= total {
+        return;
+    }
+
+    var v_pos = particles_src[index].pos;
+    var v_vel = particles_src[index].vel;
+
+    var c_mass = vec2<f32>(0.0, 0.0);
+    var c_vel = vec2<f32>(0.0, 0.0);
+    var col_vel = vec2<f32>(0.0, 0.0);
+    var c_mass_count = 0u;
+    var c_vel_count = 0u;
+
+    for (var i = 0u; i < total; i++) {
+        if i == index {
+            continue;
+        }
+        let pos = particles_src[i].pos;
+        let vel = particles_src[i].vel;
+
+        if distance(pos, v_pos) < params.rule1_distance {
+            c_mass += pos;
+            c_mass_count += 1u;
+        }
+        if distance(pos, v_pos) < params.rule2_distance {
+            col_vel -= pos - v_pos;
+        }
+        if distance(pos, v_pos) < params.rule3_distance {
+            c_vel += vel;
+            c_vel_count += 1u;
+        }
+    }
+    if c_mass_count > 0u {
+        c_mass = c_mass / f32(c_mass_count) - v_pos;
+    }
+    if c_vel_count > 0u {
+        c_vel /= f32(c_vel_count);
+    }
+
+    v_vel += c_mass * params.rule1_scale + col_vel * params.rule2_scale + c_vel * params.rule3_scale;
+    // clamp velocity for a more pleasing simulation
+    v_vel = normalize(v_vel) * clamp(length(v_vel), 0.0, 0.1);
+    let previous_pos = v_pos;
+    v_pos += v_vel * params.delta_t;
+
+    if params.collision != 0u && depth_at(v_pos) < 1.0 {
+        if params.collision == COLLISION_KILL {
+            let random = hash(index ^ bitcast<u32>(v_pos.x) ^ hash(bitcast<u32>(v_pos.y)));
+            v_pos = vec2<f32>(f32(random) / 4294967295.0 * 2.0 - 1.0, 1.0);
+            v_vel = vec2<f32>(0.0, -length(v_vel));
+        } else if depth_at(previous_pos) >= 1.0 {
+            // only coming in from outside, ones that started inside fly on until they're out.
+            // The depth grows towards the sky around the hit, that's the way out.
+            let gradient = vec2<f32>(
+                depth_at(v_pos + vec2<f32>(NORMAL_OFFSET, 0.0)) - depth_at(v_pos - vec2<f32>(NORMAL_OFFSET, 0.0)),
+                depth_at(v_pos + vec2<f32>(0.0, NORMAL_OFFSET)) - depth_at(v_pos - vec2<f32>(0.0, NORMAL_OFFSET))
+            );
+            var normal = -normalize(v_vel);
+            if length(gradient) > 0.0 {
+                normal = normalize(gradient);
+            }
+            if dot(v_vel, normal) < 0.0 {
+                v_vel = reflect(v_vel, normal) * params.restitution;
+            }
+            v_pos = previous_pos;
+        }
+    }
+
+    // wrap around boundary
+    if v_pos.x < -1.0 {
+        v_pos.x = 1.0;
+    }
+    if v_pos.x > 1.0 {
+        v_pos.x = -1.0;
+    }
+    if v_pos.y < -1.0 {
+        v_pos.y = 1.0;
+    }
+    if v_pos.y > 1.0 {
+        v_pos.y = -1.0;
+    }
+
+    particles_dst[index] = Particle(v_pos, v_vel);
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+};
+
+@vertex
+fn vs_main(
+    @location(0) particle_pos: vec2<f32>,
+    @location(1) particle_vel: vec2<f32>,
+    @location(2) position: vec2<f32>,
+) -> VertexOutput {
+    // point the triangle along the velocity
+    let angle = -atan2(particle_vel.x, particle_vel.y);
+    let pos = vec2<f32>(
+        position.x * cos(angle) - position.y * sin(angle),
+        position.x * sin(angle) + position.y * cos(angle)
+    );
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(pos + particle_pos, 0.0, 1.0);
+    out.color = vec3<f32>(normalize(abs(particle_vel)), 0.8);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(in.color, 1.0);
+}
@@ -0,0 +1,181 @@
+use std::cell::RefCell;
+
+use winit::{event::TouchPhase, event_loop::EventLoopProxy};
+
+use crate::{keymap::Trigger, screenshot::ScreenshotReply};
+
+// Requests from outside the event loop (the surrounding web page), applied by State
+// at the start of the next frame
+pub enum BridgeCommand {
+  // one of params::PARAMS
+  SetParam {
+    name: String,
+    value: f64,
+  },
+  // one of viewport::SHADER_VARIANTS
+  LoadScene(String),
+  Screenshot(ScreenshotReply),
+  // L-system rules for the terrain's plants, see LSystem::parse
+  SetPlantRules(String),
+  // one of keymap::ACTIONS and what sets it off from now on
+  Bind {
+    action: String,
+    triggers: Vec<Trigger>,
+  },
+  // a finger on the canvas, in physical pixels from its corner, see touch::listen
+  Touch {
+    id: u64,
+    phase: TouchPhase,
+    position: [f64; 2],
+  },
+}
+
+// The event loop owns State, so commands wait here until it picks them up. Wasm is
+// single threaded, so a thread local is all the synchronisation this needs.
+thread_local! {
+  static COMMANDS: RefCell<Vec<BridgeCommand>> = const { RefCell::new(Vec::new()) };
+  // wakes the event loop when it's waiting for input (power saver)
+  static WAKER: RefCell<Option<EventLoopProxy<()>>> = const { RefCell::new(None) };
+}
+
+pub fn set_waker(proxy: EventLoopProxy<()>) {
+  WAKER.with(|waker| *waker.borrow_mut() = Some(proxy));
+}
+
+pub fn send(command: BridgeCommand) {
+  // with rendering handed off to a worker there's no State here to apply them
+  #[cfg(target_arch = "wasm32")]
+  let Some(command) = crate::worker::forward(command) else {
+    return;
+  };
+  COMMANDS.with(|commands| commands.borrow_mut().push(command));
+  WAKER.with(|waker| {
+    if let Some(proxy) = waker.borrow().as_ref() {
+      let _ = proxy.send_event(());
+    }
+  });
+}
+
+pub fn take_commands() -> Vec<BridgeCommand> {
+  COMMANDS.with(|commands| std::mem::take(&mut *commands.borrow_mut()))
+}
+
+// The API exported to JavaScript, e.g.
+//
+//   import init, { set_param, load_scene, screenshot } from "./pkg/wgpu_learn.js";
+//   slider.oninput = () => set_param("exposure", slider.valueAsNumber);
+//   rules.onchange = () => set_plant_rules(rules.value);
+//   bind_action("swap_shader", "S, Mouse Right");
+//   img.src = URL.createObjectURL(new Blob([await screenshot()], { type: "image/png" }));
+#[cfg(target_arch = "wasm32")]
+mod js {
+  use js_sys::{Promise, Uint8Array};
+  use wasm_bindgen::prelude::*;
+
+  use super::{send, BridgeCommand};
+  use crate::{
+    keymap::{self, InputError, Trigger},
+    lsystem::LSystem,
+    params::ParamError,
+    viewport::SHADER_VARIANTS,
+  };
+
+  // Runs as soon as the module is instantiated, on the page and in a render worker
+  #[wasm_bindgen(start)]
+  pub fn start() {
+    if web_sys::window().is_none() {
+      // inside the worker, its script calls worker::worker_main
+      return;
+    }
+    if let Some(script) = crate::worker::requested_script() {
+      if !crate::worker::supported() {
+        log::info!("OffscreenCanvas isn't supported, rendering on the main thread");
+      } else {
+        match crate::worker::spawn(&script) {
+          Ok(()) => return,
+          Err(e) => log::warn!(
+            "couldn't start the render worker ({:?}), rendering on the main thread",
+            e
+          ),
+        }
+      }
+    }
+    wasm_bindgen_futures::spawn_local(async {
+      if let Err(e) = crate::run().await {
+        log::error!("{}", e);
+      }
+    });
+  }
+
+  #[wasm_bindgen]
+  pub fn set_param(name: &str, value: f64) -> Result<(), JsValue> {
+    if crate::params::find(name).is_none() {
+      return Err(ParamError::Unknown(name.to_string()).to_string().into());
+    }
+    send(BridgeCommand::SetParam {
+      name: name.to_string(),
+      value,
+    });
+    Ok(())
+  }
+
+  #[wasm_bindgen]
+  pub fn load_scene(name: &str) -> Result<(), JsValue> {
+    if !SHADER_VARIANTS.contains(&name) {
+      return Err(
+        format!(
+          "unknown scene {}, expected one of {:?}",
+          name, SHADER_VARIANTS
+        )
+        .into(),
+      );
+    }
+    send(BridgeCommand::LoadScene(name.to_string()));
+    Ok(())
+  }
+
+  // Throws if the rules don't parse, the plants keep their old ones
+  #[wasm_bindgen]
+  pub fn set_plant_rules(rules: &str) -> Result<(), JsValue> {
+    LSystem::parse(rules).map_err(|e| JsValue::from(e.to_string()))?;
+    send(BridgeCommand::SetPlantRules(rules.to_string()));
+    Ok(())
+  }
+
+  // `triggers` are separated by commas, e.g. "Tab, Mouse Right", and empty unbinds the
+  // action. Throws on unknown actions and keys.
+  #[wasm_bindgen]
+  pub fn bind_action(action: &str, triggers: &str) -> Result<(), JsValue> {
+    if keymap::find(action).is_none() {
+      return Err(
+        InputError::UnknownAction(action.to_string())
+          .to_string()
+          .into(),
+      );
+    }
+    let triggers = triggers
+      .split(',')
+      .filter(|t| !t.trim().is_empty())
+      .map(str::parse)
+      .collect::<Result<Vec<Trigger>, _>>()
+      .map_err(|e| JsValue::from(e.to_string()))?;
+    send(BridgeCommand::Bind {
+      action: action.to_string(),
+      triggers,
+    });
+    Ok(())
+  }
+
+  // Resolves to the PNG bytes of the next frame as a Uint8Array
+  #[wasm_bindgen]
+  pub fn screenshot() -> Promise {
+    Promise::new(&mut |resolve, reject| {
+      send(BridgeCommand::Screenshot(Box::new(move |result| {
+        let _ = match result {
+          Ok(png) => resolve.call1(&JsValue::NULL, &Uint8Array::from(png.as_slice())),
+          Err(e) => reject.call1(&JsValue::NULL, &e.to_string().into()),
+        };
+      })));
+    })
+  }
+}
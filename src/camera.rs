@@ -0,0 +1,115 @@
+use serde::{Deserialize, Serialize};
+
+use crate::math::{self, Mat4, Vec3};
+
+// Keeps the pitch away from straight up/down where look_at's up vector degenerates
+const MAX_PITCH: f32 = 1.5;
+// how close and far dolly() goes, the scene fits between them
+const MIN_DISTANCE: f32 = 1.0;
+const MAX_DISTANCE: f32 = 40.0;
+
+// The part of the picture a window shows. Usually all of it, on a wall of monitors every
+// window shows its own part of one picture as wide as the wall.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tile {
+  // width over height of the whole picture
+  pub aspect: f32,
+  // middle of the tile and half its size, in the whole picture's NDC (-1..1, y up)
+  pub center: [f32; 2],
+  pub half_size: [f32; 2],
+}
+
+impl Tile {
+  pub fn whole(aspect: f32) -> Self {
+    Self {
+      aspect,
+      center: [0.0, 0.0],
+      half_size: [1.0, 1.0],
+    }
+  }
+
+  // Takes clip space of the whole picture to the tile's, so the tile fills the window and
+  // the rest is clipped. Goes after the projection.
+  pub fn crop(&self) -> Mat4 {
+    let [x, y] = self.center;
+    let [width, height] = self.half_size;
+    math::mul_mat4(
+      &math::translation([-x / width, -y / height, 0.0]),
+      &math::scaling([1.0 / width, 1.0 / height, 1.0]),
+    )
+  }
+}
+
+// A camera circling `target`, turned with the arrow keys and moved in and out with W and S
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct OrbitCamera {
+  pub target: Vec3,
+  pub distance: f32,
+  // radians around +Y, 0 looks down -Z
+  pub yaw: f32,
+  // radians above the horizon
+  pub pitch: f32,
+  pub fov_y: f32,
+  pub near: f32,
+  pub far: f32,
+}
+
+impl Default for OrbitCamera {
+  fn default() -> Self {
+    Self {
+      target: [0.0, 0.5, 0.0],
+      distance: 8.0,
+      yaw: 0.6,
+      pitch: 0.45,
+      fov_y: std::f32::consts::FRAC_PI_4,
+      near: 0.1,
+      far: 100.0,
+    }
+  }
+}
+
+impl OrbitCamera {
+  pub fn orbit(&mut self, yaw: f32, pitch: f32) {
+    self.yaw = (self.yaw + yaw) % std::f32::consts::TAU;
+    self.pitch = (self.pitch + pitch).clamp(-MAX_PITCH, MAX_PITCH);
+  }
+
+  // Moves towards the target for negative `amount` and away for positive, as a share of the
+  // distance so it feels the same near and far
+  pub fn dolly(&mut self, amount: f32) {
+    self.distance = (self.distance * (1.0 + amount)).clamp(MIN_DISTANCE, MAX_DISTANCE);
+  }
+
+  pub fn eye(&self) -> Vec3 {
+    let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+    let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+    math::add(
+      self.target,
+      math::scale(
+        [sin_yaw * cos_pitch, sin_pitch, cos_yaw * cos_pitch],
+        self.distance,
+      ),
+    )
+  }
+
+  pub fn view(&self) -> Mat4 {
+    math::look_at(self.eye(), self.target, [0.0, 1.0, 0.0])
+  }
+
+  pub fn projection(&self, aspect: f32) -> Mat4 {
+    math::perspective(self.fov_y, aspect, self.near, self.far)
+  }
+
+  pub fn view_proj(&self, aspect: f32) -> Mat4 {
+    math::mul_mat4(&self.projection(aspect), &self.view())
+  }
+
+  // projection() cropped to `tile`, off center for all but the middle of a wall
+  pub fn tile_projection(&self, tile: &Tile) -> Mat4 {
+    math::mul_mat4(&tile.crop(), &self.projection(tile.aspect))
+  }
+
+  pub fn tile_view_proj(&self, tile: &Tile) -> Mat4 {
+    math::mul_mat4(&self.tile_projection(tile), &self.view())
+  }
+}
@@ -0,0 +1,245 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use crate::{
+  adapter::parse_backends,
+  config::Config,
+  viewport::SHADER_VARIANTS,
+  window_settings::{FullscreenMode, WindowSettings},
+};
+
+// Every option the app reads. The ones that change how it starts are used from here, the
+// rest are handed to State::new() and on to whatever reads them.
+#[derive(Debug, Clone, Default, PartialEq, Parser)]
+#[command(
+  name = "wgpu-learn",
+  after_help = "Options override settings.toml for this run."
+)]
+pub struct CliArgs {
+  // checked here, backends_from_env() turns it into Backends when the instance is created
+  #[arg(
+    long,
+    value_name = "LIST",
+    value_parser = parse_backend_list,
+    help = "wgpu backends to pick an adapter from, e.g. vulkan or gl,vulkan"
+  )]
+  pub backend: Option<String>,
+  #[arg(
+    long,
+    value_name = "NAME",
+    help = "the adapter whose name contains NAME"
+  )]
+  pub adapter: Option<String>,
+  #[arg(long, help = "prefer a discrete GPU")]
+  pub high_performance: bool,
+  // logical pixels
+  #[arg(
+    long,
+    value_name = "WxH",
+    value_parser = parse_size,
+    help = "window size in logical pixels"
+  )]
+  pub size: Option<(u32, u32)>,
+  #[arg(long, help = "borderless fullscreen")]
+  pub fullscreen: bool,
+  #[arg(
+    long,
+    value_name = "VARIANT",
+    value_parser = parse_shader,
+    help = "shader variant of the first window"
+  )]
+  pub shader: Option<&'static str>,
+  #[arg(
+    long,
+    help = "draw into a hidden window without vsync, quit after --frames. Needs a display"
+  )]
+  pub headless: bool,
+  #[arg(
+    long,
+    value_name = "N",
+    value_parser = clap::value_parser!(u32).range(1..),
+    help = "quit after N frames, 1 with --headless"
+  )]
+  pub frames: Option<u32>,
+  #[arg(
+    long,
+    value_name = "FILE.png",
+    help = "save the frame after the last one as a PNG before quitting"
+  )]
+  pub out: Option<PathBuf>,
+  #[arg(long, help = "step the simulation by a fixed amount every frame")]
+  pub deterministic: bool,
+  #[arg(
+    long,
+    value_name = "PATH",
+    help = "load a glTF or OBJ model into the scene"
+  )]
+  pub model: Option<PathBuf>,
+  #[arg(long, value_name = "FILE.ron", help = "load a scene saved with Home")]
+  pub scene: Option<String>,
+  #[arg(
+    long,
+    value_name = "PATH",
+    help = "a grayscale image to make the terrain from"
+  )]
+  pub heightmap: Option<String>,
+  #[arg(
+    long,
+    value_name = "PATH",
+    help = "the model's albedo, a GIF, APNG or a directory of images"
+  )]
+  pub albedo: Option<PathBuf>,
+  #[arg(long, help = "the albedo's alpha cuts holes in the model")]
+  pub cutout: bool,
+  #[arg(
+    long,
+    value_name = "PATH",
+    help = "the model's normal map, a path in res/ or the asset pack"
+  )]
+  pub normal_map: Option<String>,
+  #[arg(
+    long,
+    value_name = "N",
+    help = "stream /dev/videoN onto the model, built with the webcam feature"
+  )]
+  pub webcam: Option<usize>,
+  #[arg(
+    long,
+    value_name = "ADDRESS",
+    help = "send params and the camera to instances following this one"
+  )]
+  pub broadcast: Option<String>,
+  #[arg(
+    long,
+    value_name = "ADDRESS",
+    help = "follow the instance broadcasting at ADDRESS"
+  )]
+  pub follow: Option<String>,
+  #[arg(
+    long,
+    value_name = "FILE.rhai",
+    help = "run a scripted demo, reloaded when the file changes"
+  )]
+  pub script: Option<PathBuf>,
+}
+
+impl CliArgs {
+  // --help comes back as an error too, exit() shows it without failing
+  pub fn from_env() -> Result<Self, clap::Error> {
+    Self::try_parse()
+  }
+
+  // Frames to draw before quitting, None to keep going
+  pub fn frame_limit(&self) -> Option<u32> {
+    match (self.frames, self.headless || self.out.is_some()) {
+      (Some(frames), _) => Some(frames),
+      (None, true) => Some(1),
+      (None, false) => None,
+    }
+  }
+
+  // Puts the options over the settings from settings.toml. Anything here would be saved
+  // with the rest when a setting changes at runtime, so the window's size, fullscreen mode
+  // and --shader are left alone and go to window_settings() and State::new() instead.
+  pub fn apply(&self, settings: &mut Config) {
+    // never saved, State keeps vsync off for a window nobody sees
+    settings.window.visible = !self.headless;
+  }
+
+  // The settings of the first window
+  pub fn window_settings(&self, settings: &Config) -> WindowSettings {
+    let mut window = settings.window.clone();
+    if let Some((width, height)) = self.size {
+      window.width = width;
+      window.height = height;
+    }
+    if self.fullscreen {
+      window.fullscreen = FullscreenMode::Borderless;
+    }
+    window.visible = !self.headless;
+    window
+  }
+}
+
+fn parse_backend_list(list: &str) -> Result<String, String> {
+  parse_backends(list).map(|_| list.to_string())
+}
+
+fn parse_size(size: &str) -> Result<(u32, u32), String> {
+  size
+    .split_once(['x', 'X'])
+    .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+    .filter(|&(w, h)| w > 0 && h > 0)
+    .ok_or_else(|| "expected WIDTHxHEIGHT, e.g. 1280x720".to_string())
+}
+
+fn parse_shader(shader: &str) -> Result<&'static str, String> {
+  SHADER_VARIANTS
+    .iter()
+    .find(|&&v| v == shader)
+    .copied()
+    .ok_or_else(|| format!("expected one of {}", SHADER_VARIANTS.join(", ")))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(args: &[&str]) -> Result<CliArgs, clap::Error> {
+    CliArgs::try_parse_from(std::iter::once("wgpu-learn").chain(args.iter().copied()))
+  }
+
+  #[test]
+  fn size() {
+    assert_eq!(
+      parse(&["--size", "1280x720"]).unwrap().size,
+      Some((1280, 720))
+    );
+    assert_eq!(
+      parse(&["--size", "640X480"]).unwrap().size,
+      Some((640, 480))
+    );
+    for bad in ["1280", "0x720", "1280x", "wide x tall", "-1x5"] {
+      assert!(parse(&["--size", bad]).is_err(), "{}", bad);
+    }
+    assert!(parse(&["--size"]).is_err());
+  }
+
+  #[test]
+  fn frames() {
+    let cli = parse(&["--frames", "30"]).unwrap();
+    assert_eq!(cli.frames, Some(30));
+    assert_eq!(cli.frame_limit(), Some(30));
+    for bad in ["0", "-3", "many"] {
+      assert!(parse(&["--frames", bad]).is_err(), "{}", bad);
+    }
+    // --headless and --out stop after one unless told otherwise
+    assert_eq!(parse(&["--headless"]).unwrap().frame_limit(), Some(1));
+    assert_eq!(parse(&["--out", "a.png"]).unwrap().frame_limit(), Some(1));
+    assert_eq!(parse(&[]).unwrap().frame_limit(), None);
+  }
+
+  #[test]
+  fn shader() {
+    for variant in SHADER_VARIANTS {
+      assert_eq!(parse(&["--shader", variant]).unwrap().shader, Some(variant));
+    }
+    assert!(parse(&["--shader", "not-a-variant"]).is_err());
+    // only for this run, the saved variant stays what it was
+    let mut settings = Config::default();
+    settings.demo.last = Some(SHADER_VARIANTS[0].to_string());
+    parse(&["--shader", SHADER_VARIANTS[1]])
+      .unwrap()
+      .apply(&mut settings);
+    assert_eq!(settings.demo.last.as_deref(), Some(SHADER_VARIANTS[0]));
+  }
+
+  #[test]
+  fn unknown_options_are_rejected() {
+    assert!(parse(&["--sise", "1280x720"]).is_err());
+    assert!(parse(&["stray"]).is_err());
+    let help = parse(&["--help"]).unwrap_err();
+    assert_eq!(help.kind(), clap::error::ErrorKind::DisplayHelp);
+  }
+}
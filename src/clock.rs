@@ -0,0 +1,140 @@
+use instant::Instant;
+
+// Frames longer than this count as this long on the timelines, so a hitch (a breakpoint, a
+// window drag) doesn't throw everything ahead
+const MAX_FRAME_TIME: f32 = 0.25;
+
+// Time that can be paused and sped up or slowed down, driven by the wall clock
+#[derive(Debug, Clone)]
+pub struct Timeline {
+  paused: bool,
+  // seconds of this time per wall second
+  speed: f32,
+  // this time's seconds in the last frame, 0 while paused
+  dt: f32,
+  elapsed: f64,
+}
+
+impl Timeline {
+  fn new() -> Self {
+    Self {
+      paused: false,
+      speed: 1.0,
+      dt: 0.0,
+      elapsed: 0.0,
+    }
+  }
+
+  fn advance(&mut self, wall_dt: f32) {
+    self.dt = if self.paused {
+      0.0
+    } else {
+      wall_dt * self.speed
+    };
+    self.elapsed += self.dt as f64;
+  }
+
+  pub fn dt(&self) -> f32 {
+    self.dt
+  }
+
+  // Seconds since startup, not counting pauses
+  pub fn elapsed(&self) -> f64 {
+    self.elapsed
+  }
+
+  pub fn is_paused(&self) -> bool {
+    self.paused
+  }
+
+  pub fn set_paused(&mut self, paused: bool) {
+    self.paused = paused;
+  }
+
+  pub fn toggle_pause(&mut self) -> bool {
+    self.paused = !self.paused;
+    self.paused
+  }
+
+  pub fn speed(&self) -> f32 {
+    self.speed
+  }
+
+  pub fn set_speed(&mut self, speed: f32) {
+    self.speed = speed.max(0.0);
+  }
+}
+
+// The app's three times, advanced together once a frame:
+// - wall time goes on whatever happens, for the UI, the camera, stats and profiling
+// - simulation time is what the compute simulations (boids, the crowd) step through
+// - animation time plays textures, the sky and anything else that's only for show
+// Pausing or slowing one of the last two leaves the others running, so a paused simulation
+// can still be looked around in with the stats overlay updating.
+#[derive(Debug, Clone)]
+pub struct Clock {
+  last_frame: Instant,
+  wall_dt: f32,
+  wall_elapsed: f64,
+  simulation: Timeline,
+  animation: Timeline,
+}
+
+impl Clock {
+  pub fn new() -> Self {
+    Self {
+      last_frame: Instant::now(),
+      wall_dt: 0.0,
+      wall_elapsed: 0.0,
+      simulation: Timeline::new(),
+      animation: Timeline::new(),
+    }
+  }
+
+  // Call once at the start of a frame, returns the wall time since the last one
+  pub fn tick(&mut self) -> f32 {
+    let now = Instant::now();
+    let dt = (now - self.last_frame).as_secs_f32();
+    self.last_frame = now;
+    self.advance(dt)
+  }
+
+  // Like tick() with a frame of `dt` wall seconds, for stepping by a fixed amount
+  pub fn advance(&mut self, dt: f32) -> f32 {
+    self.wall_dt = dt;
+    self.wall_elapsed += dt as f64;
+    self.simulation.advance(dt.min(MAX_FRAME_TIME));
+    self.animation.advance(dt.min(MAX_FRAME_TIME));
+    dt
+  }
+
+  pub fn wall_dt(&self) -> f32 {
+    self.wall_dt
+  }
+
+  pub fn wall_elapsed(&self) -> f64 {
+    self.wall_elapsed
+  }
+
+  pub fn simulation(&self) -> &Timeline {
+    &self.simulation
+  }
+
+  pub fn simulation_mut(&mut self) -> &mut Timeline {
+    &mut self.simulation
+  }
+
+  pub fn animation(&self) -> &Timeline {
+    &self.animation
+  }
+
+  pub fn animation_mut(&mut self) -> &mut Timeline {
+    &mut self.animation
+  }
+}
+
+impl Default for Clock {
+  fn default() -> Self {
+    Self::new()
+  }
+}
@@ -0,0 +1,186 @@
+use wgpu::{CommandEncoder, Device};
+
+use crate::{
+  camera::{OrbitCamera, Tile},
+  gpu_memory::Tracked,
+  lighting::{PointLight, MAX_POINT_LIGHTS},
+  math::{self, Mat4},
+  preprocessor::{preprocess, ShaderDefs},
+  uploader::Upload,
+};
+
+// Screen tiles across and down, then depth slices. The shaders get them through
+// cluster_defs().
+pub const CLUSTER_COUNTS: [u32; 3] = [16, 9, 24];
+// Lights past this many in one cluster are dropped, closest first isn't guaranteed
+pub const MAX_CLUSTER_LIGHTS: u32 = 63;
+const CLUSTERS_PER_GROUP: u32 = 64;
+
+// Matches `ClusterParams` in clusters.wgsl and lighting.wgsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct ClusterParams {
+  view: Mat4,
+  inverse_projection: Mat4,
+  near: f32,
+  far: f32,
+  light_count: u32,
+  _padding: u32,
+}
+
+// The cluster counts as the #{CLUSTERS_X} and such of clusters.wgsl and lighting.wgsl
+pub fn cluster_defs(defs: ShaderDefs) -> ShaderDefs {
+  defs
+    .value("CLUSTERS_X", CLUSTER_COUNTS[0])
+    .value("CLUSTERS_Y", CLUSTER_COUNTS[1])
+    .value("CLUSTERS_Z", CLUSTER_COUNTS[2])
+    .value("MAX_CLUSTER_LIGHTS", MAX_CLUSTER_LIGHTS)
+}
+
+fn cluster_count() -> u32 {
+  CLUSTER_COUNTS.iter().product()
+}
+
+// Forward+ light culling: a compute pass lists the point lights touching each cluster of the
+// view frustum, so shading a fragment only loops over the few lights near it
+pub struct LightClusters {
+  params_buffer: Tracked<wgpu::Buffer>,
+  lights_buffer: Tracked<wgpu::Buffer>,
+  // per cluster the light count followed by MAX_CLUSTER_LIGHTS light indices
+  grid_buffer: Tracked<wgpu::Buffer>,
+  bind_group: wgpu::BindGroup,
+  pipeline: wgpu::ComputePipeline,
+}
+
+impl LightClusters {
+  pub fn new(device: &Device) -> Self {
+    let params_buffer = Tracked::buffer(device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Cluster Params Buffer"),
+      size: std::mem::size_of::<ClusterParams>() as wgpu::BufferAddress,
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    }));
+    let lights_buffer = Tracked::buffer(device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Point Light Buffer"),
+      size: (MAX_POINT_LIGHTS * std::mem::size_of::<PointLight>()) as wgpu::BufferAddress,
+      usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    }));
+    let grid_buffer = Tracked::buffer(device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Cluster Light Buffer"),
+      size: (cluster_count() * (MAX_CLUSTER_LIGHTS + 1) * 4) as wgpu::BufferAddress,
+      usage: wgpu::BufferUsages::STORAGE,
+      mapped_at_creation: false,
+    }));
+
+    let entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility: wgpu::ShaderStages::COMPUTE,
+      ty: wgpu::BindingType::Buffer {
+        ty,
+        has_dynamic_offset: false,
+        min_binding_size: None,
+      },
+      count: None,
+    };
+    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Light Culling Bind Group Layout"),
+      entries: &[
+        entry(0, wgpu::BufferBindingType::Uniform),
+        entry(1, wgpu::BufferBindingType::Storage { read_only: true }),
+        entry(2, wgpu::BufferBindingType::Storage { read_only: false }),
+      ],
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Light Culling Bind Group"),
+      layout: &layout,
+      entries: &[
+        wgpu::BindGroupEntry {
+          binding: 0,
+          resource: params_buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+          binding: 1,
+          resource: lights_buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+          binding: 2,
+          resource: grid_buffer.as_entire_binding(),
+        },
+      ],
+    });
+    let source = preprocess(
+      include_str!("clusters.wgsl"),
+      &cluster_defs(ShaderDefs::new()),
+    )
+    .expect("clusters.wgsl doesn't preprocess");
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+      label: Some("Light Culling Shader"),
+      source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Light Culling Pipeline Layout"),
+      bind_group_layouts: &[&layout],
+      push_constant_ranges: &[],
+    });
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+      label: Some("Light Culling Pipeline"),
+      layout: Some(&pipeline_layout),
+      module: &shader,
+      entry_point: "cs_cull",
+    });
+
+    Self {
+      params_buffer,
+      lights_buffer,
+      grid_buffer,
+      bind_group,
+      pipeline,
+    }
+  }
+
+  // The scene shaders read these as bindings 2 (lights), 3 (params) and 4 (clusters) of
+  // group 0
+  pub fn lights_buffer(&self) -> &wgpu::Buffer {
+    &self.lights_buffer
+  }
+
+  pub fn params_buffer(&self) -> &wgpu::Buffer {
+    &self.params_buffer
+  }
+
+  pub fn grid_buffer(&self) -> &wgpu::Buffer {
+    &self.grid_buffer
+  }
+
+  // Uploads the lights and the camera the clusters are cut from
+  pub fn update(
+    &self,
+    upload: &mut Upload,
+    camera: &OrbitCamera,
+    tile: &Tile,
+    lights: &[PointLight],
+  ) {
+    let lights = &lights[..lights.len().min(MAX_POINT_LIGHTS)];
+    let params = ClusterParams {
+      view: camera.view(),
+      inverse_projection: math::inverse(&camera.tile_projection(tile)),
+      near: camera.near,
+      far: camera.far,
+      light_count: lights.len() as u32,
+      _padding: 0,
+    };
+    upload.write(&self.params_buffer, 0, bytemuck::bytes_of(&params));
+    upload.write(&self.lights_buffer, 0, bytemuck::cast_slice(lights));
+  }
+
+  // Has to run after update() and before anything is shaded
+  pub fn cull(&self, encoder: &mut CommandEncoder) {
+    let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+      label: Some("Light Culling Pass"),
+    });
+    pass.set_pipeline(&self.pipeline);
+    pass.set_bind_group(0, &self.bind_group, &[]);
+    pass.dispatch_workgroups(cluster_count().div_ceil(CLUSTERS_PER_GROUP), 1, 1);
+  }
+}
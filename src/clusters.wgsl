@@ -0,0 +1,84 @@
+// Bins the point lights into the clusters of the view frustum: screen tiles split into
+// depth slices that get exponentially thicker away from the camera. The scene shaders then
+// only loop over the lights of the cluster a fragment falls in.
+
+// CLUSTER_COUNTS and MAX_CLUSTER_LIGHTS of clusters.rs, see cluster_defs()
+const CLUSTERS_X: u32 = #{CLUSTERS_X}u;
+const CLUSTERS_Y: u32 = #{CLUSTERS_Y}u;
+const CLUSTERS_Z: u32 = #{CLUSTERS_Z}u;
+const MAX_CLUSTER_LIGHTS: u32 = #{MAX_CLUSTER_LIGHTS}u;
+
+struct PointLight {
+    position: vec3<f32>,
+    range: f32,
+    color: vec3<f32>,
+    intensity: f32,
+};
+
+struct ClusterParams {
+    view: mat4x4<f32>,
+    inverse_projection: mat4x4<f32>,
+    near: f32,
+    far: f32,
+    light_count: u32,
+};
+
+@group(0) @binding(0)
+var<uniform> params: ClusterParams;
+@group(0) @binding(1)
+var<storage, read> point_lights: array<PointLight>;
+// per cluster the light count followed by MAX_CLUSTER_LIGHTS light indices
+@group(0) @binding(2)
+var<storage, read_write> cluster_lights: array<u32>;
+
+// View space point on the far plane behind a point of the screen
+fn far_point(ndc: vec2<f32>) -> vec3<f32> {
+    let point = params.inverse_projection * vec4<f32>(ndc, 1.0, 1.0);
+    return point.xyz / point.w;
+}
+
+fn slice_depth(slice: u32) -> f32 {
+    return params.near * pow(params.far / params.near, f32(slice) / f32(CLUSTERS_Z));
+}
+
+@compute @workgroup_size(64)
+fn cs_cull(@builtin(global_invocation_id) id: vec3<u32>) {
+    let cluster = id.x;
+    if cluster >= CLUSTERS_X * CLUSTERS_Y * CLUSTERS_Z {
+        return;
+    }
+    let x = cluster % CLUSTERS_X;
+    let y = cluster / CLUSTERS_X % CLUSTERS_Y;
+    let z = cluster / (CLUSTERS_X * CLUSTERS_Y);
+
+    // the cluster's bounding box in view space, from the tile's corners at both of its depths
+    let tile = vec2<f32>(f32(CLUSTERS_X), f32(CLUSTERS_Y));
+    let ndc_min = vec2<f32>(f32(x), f32(y)) / tile * 2.0 - 1.0;
+    let ndc_max = vec2<f32>(f32(x + 1u), f32(y + 1u)) / tile * 2.0 - 1.0;
+    let depths = vec2<f32>(slice_depth(z), slice_depth(z + 1u));
+    var box_min = vec3<f32>(1e30);
+    var box_max = vec3<f32>(-1e30);
+    for (var corner = 0u; corner < 4u; corner += 1u) {
+        let ndc = select(ndc_min, ndc_max, vec2<bool>((corner & 1u) != 0u, (corner & 2u) != 0u));
+        let point = far_point(ndc);
+        for (var i = 0u; i < 2u; i += 1u) {
+            // the camera looks down -Z
+            let at_depth = point * (depths[i] / -point.z);
+            box_min = min(box_min, at_depth);
+            box_max = max(box_max, at_depth);
+        }
+    }
+
+    let base = cluster * (MAX_CLUSTER_LIGHTS + 1u);
+    var count = 0u;
+    for (var i = 0u; i < params.light_count && count < MAX_CLUSTER_LIGHTS; i += 1u) {
+        let light = point_lights[i];
+        let center = (params.view * vec4<f32>(light.position, 1.0)).xyz;
+        let offset = clamp(center, box_min, box_max) - center;
+        if dot(offset, offset) <= light.range * light.range {
+            cluster_lights[base + 1u + count] = i;
+            count += 1u;
+        }
+    }
+    cluster_lights[base] = count;
+}
@@ -0,0 +1,76 @@
+// Bindings and helpers every scene shader shares: the globals and the mesh's vertex and
+// instance inputs. Pulled in with `#include "common.wgsl"`, lighting.wgsl includes it too.
+
+struct Globals {
+    // moved by a fraction of a pixel every frame while TAA is on
+    view_proj: mat4x4<f32>,
+    light_view_proj: mat4x4<f32>,
+    camera_position: vec4<f32>,
+    // clip space back to world space, for positions rebuilt from depth
+    inverse_view_proj: mat4x4<f32>,
+    // view_proj without the jitter, this frame's and last frame's, for motion vectors
+    unjittered_view_proj: mat4x4<f32>,
+    previous_view_proj: mat4x4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> globals: Globals;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) tangent: vec4<f32>,
+};
+
+// one per drawn object, stepped per instance
+struct InstanceInput {
+    @location(4) model_0: vec4<f32>,
+    @location(5) model_1: vec4<f32>,
+    @location(6) model_2: vec4<f32>,
+    @location(7) model_3: vec4<f32>,
+    @location(8) color: vec4<f32>,
+    // Blinn-Phong only, x: specular strength, y: shininess, z: the terrain's peak height,
+    // above 0 terrain_color() replaces the color, w: the terrain's LOD distance, see
+    // model_position()
+    // (pbr.wgsl takes its material from group 2 and its tint from DrawConstants)
+    @location(9) material: vec4<f32>,
+};
+
+fn instance_model(instance: InstanceInput) -> mat4x4<f32> {
+    return mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+}
+
+// The vertex's position in model space. The terrain's vertices slide onto the triangles of
+// the next coarser LOD as they get further from the camera, so chunks switching LOD don't
+// pop. Its uv holds the last LOD a vertex is part of in x and how far up it slides in y.
+fn model_position(vertex: VertexInput, instance: InstanceInput) -> vec3<f32> {
+    var position = vertex.position;
+    if instance.material.z > 0.0 && instance.material.w > 0.0 {
+        let world = instance_model(instance) * vec4<f32>(position, 1.0);
+        // where the chunks switch to the next LOD, it starts sliding 30% before that
+        let end = instance.material.w * exp2(vertex.uv.x);
+        let distance = length(world.xyz - globals.camera_position.xyz);
+        let morph = clamp((distance - end * 0.7) / (end * 0.3), 0.0, 1.0);
+        position.y += morph * vertex.uv.y;
+    }
+    return position;
+}
+
+// Texturing by height for the terrain: sand at the bottom, then grass, rock and snow at the
+// top, with rock wherever it's too steep for the rest. `peak` is the highest it goes, a bit
+// of per-pixel noise keeps the bands from following the contour lines exactly.
+fn terrain_color(position: vec3<f32>, normal: vec3<f32>, peak: f32) -> vec3<f32> {
+    let noise = fract(sin(dot(position.xz, vec2<f32>(12.9898, 78.233))) * 43758.5453);
+    let height = position.y / peak + (noise - 0.5) * 0.04;
+    let sand = vec3<f32>(0.76, 0.7, 0.5);
+    let grass = vec3<f32>(0.25, 0.45, 0.15);
+    let rock = vec3<f32>(0.42, 0.38, 0.34);
+    let snow = vec3<f32>(0.95, 0.95, 0.97);
+    var color = mix(sand, grass, smoothstep(0.04, 0.1, height));
+    color = mix(color, rock, smoothstep(0.45, 0.6, height));
+    color = mix(color, snow, smoothstep(0.75, 0.85, height));
+    // 0 on flat ground, 1 on a wall
+    let slope = 1.0 - normal.y;
+    color = mix(color, rock, smoothstep(0.25, 0.45, slope) * (1.0 - smoothstep(0.8, 0.9, height)));
+    return color * (0.92 + noise * 0.08);
+}
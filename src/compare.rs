@@ -0,0 +1,321 @@
+use std::sync::Arc;
+
+use wgpu::{
+  CommandEncoder, Device, Queue, RenderPass, RenderPipeline, SurfaceConfiguration, TextureView,
+};
+
+use crate::{
+  gpu_memory::Tracked,
+  hdr::HDR_FORMAT,
+  pipeline::{PipelineCache, RenderPipeConfig},
+  sampler::SamplerSettings,
+  stats::count_draw,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareMode {
+  Off,
+  SideBySide,
+  // A left of the split, B right of it, drag with the left mouse button
+  Wipe,
+}
+
+impl CompareMode {
+  pub fn next(self) -> Self {
+    match self {
+      CompareMode::Off => CompareMode::SideBySide,
+      CompareMode::SideBySide => CompareMode::Wipe,
+      CompareMode::Wipe => CompareMode::Off,
+    }
+  }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct CompareUniform {
+  split: f32,
+  mode: u32,
+  line_width: f32,
+  _padding: f32,
+}
+
+struct CaptureTarget {
+  _texture: Tracked<wgpu::Texture>,
+  view: TextureView,
+}
+
+impl CaptureTarget {
+  fn new(device: &Device, width: u32, height: u32, label: &str) -> Self {
+    let texture = Tracked::texture(device.create_texture(&wgpu::TextureDescriptor {
+      label: Some(label),
+      size: wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+      },
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format: HDR_FORMAT,
+      usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+      view_formats: &[],
+    }));
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    Self {
+      _texture: texture,
+      view,
+    }
+  }
+}
+
+// Renders the same frame with two configurations into textures and composites them
+// into the hdr target, so both sides go through the same exposure/tonemapping
+pub struct FrameCompare {
+  mode: CompareMode,
+  split: f32,
+  dragging: bool,
+  variants: [String; 2],
+  pipelines: [Arc<RenderPipeline>; 2],
+  targets: [CaptureTarget; 2],
+  sampler: wgpu::Sampler,
+  uniform_buffer: Tracked<wgpu::Buffer>,
+  layout: wgpu::BindGroupLayout,
+  bind_group: wgpu::BindGroup,
+  composite: RenderPipeline,
+}
+
+impl FrameCompare {
+  // `variants` are the shader variants (see pipeline::main_pipe) for side A and B
+  pub fn new(
+    device: &Device,
+    pipelines: &mut PipelineCache,
+    config: &SurfaceConfiguration,
+    variants: [&str; 2],
+  ) -> Self {
+    let (width, height) = (config.width.max(1), config.height.max(1));
+    let targets = [
+      CaptureTarget::new(device, width, height, "Compare Target A"),
+      CaptureTarget::new(device, width, height, "Compare Target B"),
+    ];
+    let pipelines =
+      variants.map(|v| pipelines.get(device, &RenderPipeConfig::new(HDR_FORMAT, None, v), None));
+
+    let sampler =
+      device.create_sampler(&SamplerSettings::linear().descriptor(Some("Compare Sampler")));
+    let uniform_buffer = Tracked::buffer(device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Compare Uniform Buffer"),
+      size: std::mem::size_of::<CompareUniform>() as u64,
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    }));
+
+    let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility: wgpu::ShaderStages::FRAGMENT,
+      ty: wgpu::BindingType::Texture {
+        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        view_dimension: wgpu::TextureViewDimension::D2,
+        multisampled: false,
+      },
+      count: None,
+    };
+    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Compare Bind Group Layout"),
+      entries: &[
+        texture_entry(0),
+        texture_entry(1),
+        wgpu::BindGroupLayoutEntry {
+          binding: 2,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+          count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+          binding: 3,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+          },
+          count: None,
+        },
+      ],
+    });
+    let bind_group = create_bind_group(device, &layout, &targets, &sampler, &uniform_buffer);
+
+    let shader = device.create_shader_module(wgpu::include_wgsl!("compare.wgsl"));
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Compare Pipeline Layout"),
+      bind_group_layouts: &[&layout],
+      push_constant_ranges: &[],
+    });
+    let composite = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+      label: Some("Compare Composite Pipeline"),
+      layout: Some(&pipeline_layout),
+      vertex: wgpu::VertexState {
+        module: &shader,
+        entry_point: "vs_main",
+        buffers: &[],
+      },
+      fragment: Some(wgpu::FragmentState {
+        module: &shader,
+        entry_point: "fs_main",
+        targets: &[Some(wgpu::ColorTargetState {
+          format: HDR_FORMAT,
+          blend: Some(wgpu::BlendState::REPLACE),
+          write_mask: wgpu::ColorWrites::ALL,
+        })],
+      }),
+      primitive: wgpu::PrimitiveState::default(),
+      depth_stencil: None,
+      multisample: wgpu::MultisampleState::default(),
+      multiview: None,
+    });
+
+    Self {
+      mode: CompareMode::Off,
+      split: 0.5,
+      dragging: false,
+      variants: variants.map(str::to_string),
+      pipelines,
+      targets,
+      sampler,
+      uniform_buffer,
+      layout,
+      bind_group,
+      composite,
+    }
+  }
+
+  pub fn resize(&mut self, device: &Device, width: u32, height: u32) {
+    self.targets = [
+      CaptureTarget::new(device, width, height, "Compare Target A"),
+      CaptureTarget::new(device, width, height, "Compare Target B"),
+    ];
+    self.bind_group = create_bind_group(
+      device,
+      &self.layout,
+      &self.targets,
+      &self.sampler,
+      &self.uniform_buffer,
+    );
+  }
+
+  pub fn mode(&self) -> CompareMode {
+    self.mode
+  }
+
+  pub fn is_active(&self) -> bool {
+    self.mode != CompareMode::Off
+  }
+
+  pub fn cycle_mode(&mut self) {
+    self.mode = self.mode.next();
+    log::info!(
+      "compare {:?}: {} | {}",
+      self.mode,
+      self.variants[0],
+      self.variants[1]
+    );
+  }
+
+  pub fn set_dragging(&mut self, dragging: bool) {
+    self.dragging = dragging && self.mode == CompareMode::Wipe;
+  }
+
+  pub fn is_dragging(&self) -> bool {
+    self.dragging
+  }
+
+  // `x` is the cursor position relative to the window width, 0..1
+  pub fn set_split(&mut self, x: f32) {
+    self.split = x.clamp(0.0, 1.0);
+  }
+
+  pub fn update(&self, queue: &Queue, width: u32) {
+    let uniform = CompareUniform {
+      split: self.split,
+      mode: self.mode as u32,
+      line_width: 1.0 / width.max(1) as f32,
+      _padding: 0.0,
+    };
+    queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+  }
+
+  pub fn pipeline(&self, side: usize) -> &RenderPipeline {
+    &self.pipelines[side]
+  }
+
+  // Pass rendering the scene for one side (0 = A, 1 = B) into its capture texture
+  pub fn begin_capture<'a>(
+    &'a self,
+    encoder: &'a mut CommandEncoder,
+    side: usize,
+    clear_color: wgpu::Color,
+  ) -> RenderPass<'a> {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+      label: Some("Compare Capture Pass"),
+      color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+        view: &self.targets[side].view,
+        resolve_target: None,
+        ops: wgpu::Operations {
+          load: wgpu::LoadOp::Clear(clear_color),
+          store: true,
+        },
+      })],
+      depth_stencil_attachment: None,
+    })
+  }
+
+  // Combines both captures into `output` according to the current mode
+  pub fn composite(&self, encoder: &mut CommandEncoder, output: &TextureView) {
+    let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+      label: Some("Compare Composite Pass"),
+      color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+        view: output,
+        resolve_target: None,
+        ops: wgpu::Operations {
+          load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+          store: true,
+        },
+      })],
+      depth_stencil_attachment: None,
+    });
+    pass.set_pipeline(&self.composite);
+    pass.set_bind_group(0, &self.bind_group, &[]);
+    count_draw();
+    pass.draw(0..3, 0..1);
+  }
+}
+
+fn create_bind_group(
+  device: &Device,
+  layout: &wgpu::BindGroupLayout,
+  targets: &[CaptureTarget; 2],
+  sampler: &wgpu::Sampler,
+  uniform_buffer: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+  device.create_bind_group(&wgpu::BindGroupDescriptor {
+    label: Some("Compare Bind Group"),
+    layout,
+    entries: &[
+      wgpu::BindGroupEntry {
+        binding: 0,
+        resource: wgpu::BindingResource::TextureView(&targets[0].view),
+      },
+      wgpu::BindGroupEntry {
+        binding: 1,
+        resource: wgpu::BindingResource::TextureView(&targets[1].view),
+      },
+      wgpu::BindGroupEntry {
+        binding: 2,
+        resource: wgpu::BindingResource::Sampler(sampler),
+      },
+      wgpu::BindGroupEntry {
+        binding: 3,
+        resource: uniform_buffer.as_entire_binding(),
+      },
+    ],
+  })
+}
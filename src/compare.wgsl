@@ -0,0 +1,57 @@
+struct CompareUniform {
+    // wipe position in 0..1 of the screen width
+    split: f32,
+    // 1 = side by side, 2 = wipe
+    mode: u32,
+    // width of the divider line in uv units
+    line_width: f32,
+    _padding: f32,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@group(0) @binding(0)
+var frame_a: texture_2d<f32>;
+@group(0) @binding(1)
+var frame_b: texture_2d<f32>;
+@group(0) @binding(2)
+var frame_sampler: sampler;
+@group(0) @binding(3)
+var<uniform> compare: CompareUniform;
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) in_vertex_index: u32,
+) -> VertexOutput {
+    var out: VertexOutput;
+    let x = f32(i32(in_vertex_index & 1u) * 4 - 1);
+    let y = f32(i32(in_vertex_index >> 1u) * 4 - 1);
+    out.uv = vec2<f32>(x + 1.0, 1.0 - y) * 0.5;
+    out.clip_position = vec4<f32>(x, y, 0.0, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let a = textureSample(frame_a, frame_sampler, in.uv);
+    let b = textureSample(frame_b, frame_sampler, in.uv);
+
+    if compare.mode == 1u {
+        // each half shows a whole frame at half size, letterboxed to keep the aspect ratio
+        let half_uv = vec2<f32>(fract(in.uv.x * 2.0), in.uv.y * 2.0 - 0.5);
+        let a_half = textureSample(frame_a, frame_sampler, half_uv);
+        let b_half = textureSample(frame_b, frame_sampler, half_uv);
+        if half_uv.y < 0.0 || half_uv.y > 1.0 {
+            return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+        }
+        return select(b_half, a_half, in.uv.x < 0.5);
+    }
+
+    if abs(in.uv.x - compare.split) < compare.line_width {
+        return vec4<f32>(1.0, 1.0, 1.0, 1.0);
+    }
+    return select(b, a, in.uv.x < compare.split);
+}
@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  accessibility::AccessibilitySettings,
+  audio::AudioSettings,
+  fog::FogSettings,
+  keymap::InputSettings,
+  net_sync::SyncSettings,
+  plants::PlantSettings,
+  power::PowerSettings,
+  quality::QualityOverrides,
+  raycast::PickingSettings,
+  render_settings::RenderSettings,
+  stats::StatsSettings,
+  storage::{SettingsStorage, StorageError},
+  terrain::TerrainSettings,
+  text::TextSettings,
+  water::WaterSettings,
+  window_settings::WindowSettings,
+};
+
+pub const CONFIG_PATH: &str = "settings.toml";
+
+// What was showing when the app last ran
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DemoSettings {
+  // shader variant of the primary window
+  pub last: Option<String>,
+}
+
+// Startup settings read from settings.toml (localStorage on the web), missing fields
+// keep their defaults
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+  pub window: WindowSettings,
+  pub quality: QualityOverrides,
+  pub text: TextSettings,
+  pub demo: DemoSettings,
+  pub power: PowerSettings,
+  pub render: RenderSettings,
+  pub plants: PlantSettings,
+  pub accessibility: AccessibilitySettings,
+  pub stats: StatsSettings,
+  pub picking: PickingSettings,
+  pub input: InputSettings,
+  pub sync: SyncSettings,
+  pub audio: AudioSettings,
+  pub terrain: TerrainSettings,
+  pub water: WaterSettings,
+  pub fog: FogSettings,
+  // values for params.rs' PARAMS by name, set at startup
+  pub params: BTreeMap<String, f64>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+  Serialize(toml::ser::Error),
+  Storage(StorageError),
+}
+
+impl std::fmt::Display for ConfigError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ConfigError::Serialize(e) => write!(f, "couldn't serialize the settings: {}", e),
+      ConfigError::Storage(e) => write!(f, "couldn't save the settings: {}", e),
+    }
+  }
+}
+
+impl std::error::Error for ConfigError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      ConfigError::Serialize(e) => Some(e),
+      ConfigError::Storage(e) => Some(e),
+    }
+  }
+}
+
+impl Config {
+  // Falls back to the defaults when nothing was stored or it can't be parsed
+  pub fn load(storage: &SettingsStorage) -> Self {
+    let Some(text) = storage.load() else {
+      log::info!("no {}, using default settings", storage.describe());
+      return Self::default();
+    };
+    match toml::from_str(&text) {
+      Ok(config) => config,
+      Err(e) => {
+        log::warn!(
+          "couldn't parse {}: {}, using default settings",
+          storage.describe(),
+          e
+        );
+        Self::default()
+      }
+    }
+  }
+
+  // Writes every setting back, comments in a hand edited settings.toml don't survive this
+  pub fn save(&self, storage: &SettingsStorage) -> Result<(), ConfigError> {
+    let text = toml::to_string_pretty(self).map_err(ConfigError::Serialize)?;
+    storage.save(&text).map_err(ConfigError::Storage)
+  }
+}
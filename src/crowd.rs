@@ -0,0 +1,419 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use wgpu::{Device, Queue, RenderPass};
+
+use crate::{
+  gpu_memory::Tracked,
+  math::{self, next_random},
+  mesh::Mesh,
+  raycast::{self, Aabb, Ray},
+  scene::{GpuMesh, SceneInstance},
+};
+
+// Same size as the scene's ground plane
+const HALF_EXTENT: f32 = 5.0;
+// Navigation cells per side
+const GRID: usize = 50;
+const CELL_SIZE: f32 = 2.0 * HALF_EXTENT / GRID as f32;
+const AGENT_COUNT: usize = 400;
+const AGENT_RADIUS: f32 = 0.08;
+const AGENT_HEIGHT: f32 = 0.35;
+// units per second
+const MAX_SPEED: f32 = 1.2;
+// how quickly agents turn towards where they want to go, per second
+const STEERING: f32 = 4.0;
+// agents closer than this push each other apart
+const SEPARATION: f32 = 3.0 * AGENT_RADIUS;
+// an agent this close to its goal picks another one
+const ARRIVED: f32 = 0.5;
+// Same seed every run so the crowd is reproducible
+const SEED: u32 = 0x68e3_1da4;
+// Where the agents walk to, each with its own flow field
+const GOALS: [[f32; 2]; 4] = [[-4.0, -4.0], [4.0, -4.0], [4.0, 4.0], [-4.0, 4.0]];
+// Path costs between neighbouring cells, integers so they fit in the heap
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+
+// Something the agents walk around, a circle on the ground
+#[derive(Debug, Clone, Copy)]
+pub struct Obstacle {
+  pub center: [f32; 2],
+  pub radius: f32,
+}
+
+// The ground split into square cells, the ones under obstacles blocked
+struct NavGrid {
+  blocked: Vec<bool>,
+}
+
+impl NavGrid {
+  fn new(obstacles: &[Obstacle]) -> Self {
+    let blocked = (0..GRID * GRID)
+      .map(|cell| {
+        let [x, z] = cell_center(cell);
+        obstacles.iter().any(|o| {
+          let (dx, dz) = (x - o.center[0], z - o.center[1]);
+          // agents keep their radius away from the obstacle
+          (dx * dx + dz * dz).sqrt() < o.radius + AGENT_RADIUS
+        })
+      })
+      .collect();
+    Self { blocked }
+  }
+
+  fn is_free(&self, position: [f32; 2]) -> bool {
+    cell_at(position).is_some_and(|cell| !self.blocked[cell])
+  }
+
+  // Free neighbours of `cell` with the cost to step there, diagonals only when they don't
+  // cut the corner of a blocked cell
+  fn neighbours(&self, cell: usize) -> impl Iterator<Item = (usize, u32)> + '_ {
+    let (x, z) = ((cell % GRID) as i32, (cell / GRID) as i32);
+    let free = move |dx: i32, dz: i32| {
+      let (nx, nz) = (x + dx, z + dz);
+      let inside = (0..GRID as i32).contains(&nx) && (0..GRID as i32).contains(&nz);
+      inside && !self.blocked[nz as usize * GRID + nx as usize]
+    };
+    (-1..=1)
+      .flat_map(|dz| (-1..=1).map(move |dx| (dx, dz)))
+      .filter(move |&(dx, dz)| {
+        (dx, dz) != (0, 0) && free(dx, dz) && (dx == 0 || dz == 0 || (free(dx, 0) && free(0, dz)))
+      })
+      .map(move |(dx, dz)| {
+        let cost = if dx == 0 || dz == 0 {
+          STRAIGHT_COST
+        } else {
+          DIAGONAL_COST
+        };
+        ((z + dz) as usize * GRID + (x + dx) as usize, cost)
+      })
+  }
+
+  // Dijkstra outwards from the goal, then every cell points at its cheapest neighbour. Cells
+  // that can't reach the goal, and the goal's own cell, get a zero vector.
+  fn flow_field(&self, goal: [f32; 2]) -> Vec<[f32; 2]> {
+    let mut costs = vec![u32::MAX; GRID * GRID];
+    let Some(start) = cell_at(goal).filter(|&cell| !self.blocked[cell]) else {
+      return vec![[0.0; 2]; GRID * GRID];
+    };
+    costs[start] = 0;
+    let mut open = BinaryHeap::from([Reverse((0, start))]);
+    while let Some(Reverse((cost, cell))) = open.pop() {
+      if cost > costs[cell] {
+        continue;
+      }
+      for (next, step) in self.neighbours(cell) {
+        if cost + step < costs[next] {
+          costs[next] = cost + step;
+          open.push(Reverse((cost + step, next)));
+        }
+      }
+    }
+
+    (0..GRID * GRID)
+      .map(|cell| {
+        let best = self
+          .neighbours(cell)
+          .min_by_key(|&(next, _)| costs[next])
+          .filter(|&(next, _)| costs[next] < costs[cell]);
+        match best {
+          Some((next, _)) => {
+            let ([x, z], [nx, nz]) = (cell_center(cell), cell_center(next));
+            normalize_2d([nx - x, nz - z])
+          }
+          None => [0.0; 2],
+        }
+      })
+      .collect()
+  }
+}
+
+fn cell_at(position: [f32; 2]) -> Option<usize> {
+  let x = ((position[0] + HALF_EXTENT) / CELL_SIZE).floor();
+  let z = ((position[1] + HALF_EXTENT) / CELL_SIZE).floor();
+  let range = 0.0..GRID as f32;
+  (range.contains(&x) && range.contains(&z)).then(|| z as usize * GRID + x as usize)
+}
+
+fn cell_center(cell: usize) -> [f32; 2] {
+  [
+    ((cell % GRID) as f32 + 0.5) * CELL_SIZE - HALF_EXTENT,
+    ((cell / GRID) as f32 + 0.5) * CELL_SIZE - HALF_EXTENT,
+  ]
+}
+
+fn normalize_2d([x, z]: [f32; 2]) -> [f32; 2] {
+  let length = (x * x + z * z).sqrt();
+  if length > 0.0 {
+    [x / length, z / length]
+  } else {
+    [0.0; 2]
+  }
+}
+
+struct Agent {
+  position: [f32; 2],
+  // before the last tick, drawn blended towards `position`
+  previous: [f32; 2],
+  velocity: [f32; 2],
+  // index into GOALS
+  goal: usize,
+  color: [f32; 4],
+}
+
+impl Agent {
+  // Where it's drawn `alpha` of the way through the last tick
+  fn blended(&self, alpha: f32) -> [f32; 2] {
+    [0, 1].map(|i| self.previous[i] + (self.position[i] - self.previous[i]) * alpha)
+  }
+}
+
+// Hundreds of capsules walking between the corners of the ground plane around the scene's
+// cubes. Each goal has a flow field over a navigation grid, the agents follow the field of
+// their goal and keep apart from each other, stepped on the fixed simulation tick.
+pub struct Crowd {
+  grid: NavGrid,
+  flow_fields: Vec<Vec<[f32; 2]>>,
+  agents: Vec<Agent>,
+  // agent indices per navigation cell, rebuilt every tick to find close agents quickly
+  buckets: Vec<Vec<usize>>,
+  mesh: GpuMesh,
+  // the capsule on the CPU for ray casts, and the box around it
+  shape: (Mesh, Aabb),
+  instance_buffer: Tracked<wgpu::Buffer>,
+  // the agents exactly where the last tick left them, for the interpolation debug view
+  tick_buffer: Tracked<wgpu::Buffer>,
+  rng: u32,
+  enabled: bool,
+  show_ticks: bool,
+  // how far between the last two ticks the agents are drawn
+  alpha: f32,
+}
+
+impl Crowd {
+  pub fn new(device: &Device, obstacles: &[Obstacle]) -> Self {
+    let grid = NavGrid::new(obstacles);
+    let flow_fields = GOALS.iter().map(|&goal| grid.flow_field(goal)).collect();
+    let mut rng = SEED;
+    let mut agents = Vec::with_capacity(AGENT_COUNT);
+    while agents.len() < AGENT_COUNT {
+      let position = [
+        (next_random(&mut rng) * 2.0 - 1.0) * HALF_EXTENT,
+        (next_random(&mut rng) * 2.0 - 1.0) * HALF_EXTENT,
+      ];
+      if !grid.is_free(position) {
+        continue;
+      }
+      let [r, g, b] = math::hue_to_rgb(next_random(&mut rng));
+      agents.push(Agent {
+        position,
+        previous: position,
+        velocity: [0.0; 2],
+        goal: (next_random(&mut rng) * GOALS.len() as f32) as usize % GOALS.len(),
+        color: [r, g, b, 1.0],
+      });
+    }
+
+    let capsule = Mesh::capsule(AGENT_RADIUS, AGENT_HEIGHT, 12);
+    let bounds = Aabb::from_mesh(&capsule);
+    Self {
+      grid,
+      flow_fields,
+      agents,
+      buckets: vec![Vec::new(); GRID * GRID],
+      mesh: GpuMesh::new(device, &capsule),
+      shape: (capsule, bounds),
+      instance_buffer: create_instance_buffer(device, "Crowd Instance Buffer"),
+      tick_buffer: create_instance_buffer(device, "Crowd Tick Buffer"),
+      rng,
+      enabled: false,
+      show_ticks: false,
+      alpha: 1.0,
+    }
+  }
+
+  pub fn is_enabled(&self) -> bool {
+    self.enabled
+  }
+
+  pub fn toggle(&mut self) -> bool {
+    self.enabled = !self.enabled;
+    self.enabled
+  }
+
+  // Whether draw_ticks() has anything to draw, I toggles it
+  pub fn toggle_ticks(&mut self) -> bool {
+    self.show_ticks = !self.show_ticks;
+    self.show_ticks
+  }
+
+  // Advances `ticks` steps of `tick` seconds and uploads the agents `alpha` of the way from
+  // before the last tick to after it, see SimulationStepper::alpha()
+  pub fn step(&mut self, queue: &Queue, ticks: u32, tick: f32, alpha: f32) {
+    if !self.enabled {
+      return;
+    }
+    for _ in 0..ticks {
+      self.tick(tick);
+    }
+    let instances = |position: &dyn Fn(&Agent) -> [f32; 2]| -> Vec<SceneInstance> {
+      self
+        .agents
+        .iter()
+        .map(|agent| {
+          let [x, z] = position(agent);
+          SceneInstance {
+            model: math::translation([x, 0.0, z]),
+            color: agent.color,
+            material: [0.3, 32.0, 0.0, 0.0],
+          }
+        })
+        .collect()
+    };
+    self.alpha = alpha;
+    let blended = instances(&|agent| agent.blended(alpha));
+    queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&blended));
+    if self.show_ticks {
+      let ticked = instances(&|agent| agent.position);
+      queue.write_buffer(&self.tick_buffer, 0, bytemuck::cast_slice(&ticked));
+    }
+  }
+
+  fn tick(&mut self, dt: f32) {
+    for agent in &mut self.agents {
+      agent.previous = agent.position;
+    }
+    for bucket in &mut self.buckets {
+      bucket.clear();
+    }
+    for (i, agent) in self.agents.iter().enumerate() {
+      if let Some(cell) = cell_at(agent.position) {
+        self.buckets[cell].push(i);
+      }
+    }
+
+    let velocities: Vec<[f32; 2]> = (0..self.agents.len()).map(|i| self.steer(i, dt)).collect();
+    for (agent, velocity) in self.agents.iter_mut().zip(velocities) {
+      let [x, z] = agent.position;
+      let next = [x + velocity[0] * dt, z + velocity[1] * dt];
+      // slide along blocked cells instead of stopping dead
+      agent.position = if self.grid.is_free(next) {
+        next
+      } else if self.grid.is_free([next[0], z]) {
+        [next[0], z]
+      } else if self.grid.is_free([x, next[1]]) {
+        [x, next[1]]
+      } else {
+        [x, z]
+      };
+      agent.velocity = velocity;
+
+      let goal = GOALS[agent.goal];
+      let (dx, dz) = (goal[0] - agent.position[0], goal[1] - agent.position[1]);
+      if dx * dx + dz * dz < ARRIVED * ARRIVED {
+        let others = GOALS.len() - 1;
+        let pick = (next_random(&mut self.rng) * others as f32) as usize % others;
+        agent.goal = (agent.goal + 1 + pick) % GOALS.len();
+      }
+    }
+  }
+
+  // The new velocity of agent `i`: towards its goal along the flow field, away from agents
+  // too close to it
+  fn steer(&self, i: usize, dt: f32) -> [f32; 2] {
+    let agent = &self.agents[i];
+    let [x, z] = agent.position;
+    let goal = GOALS[agent.goal];
+    let flow = cell_at(agent.position).map_or([0.0; 2], |cell| self.flow_fields[agent.goal][cell]);
+    // the goal's own cell has no direction, neither do cells cut off from it
+    let direction = if flow == [0.0; 2] {
+      normalize_2d([goal[0] - x, goal[1] - z])
+    } else {
+      flow
+    };
+
+    let mut push = [0.0f32; 2];
+    let (cx, cz) = (
+      ((x + HALF_EXTENT) / CELL_SIZE).floor() as i32,
+      ((z + HALF_EXTENT) / CELL_SIZE).floor() as i32,
+    );
+    for nz in cz - 1..=cz + 1 {
+      for nx in cx - 1..=cx + 1 {
+        if !(0..GRID as i32).contains(&nx) || !(0..GRID as i32).contains(&nz) {
+          continue;
+        }
+        for &j in &self.buckets[nz as usize * GRID + nx as usize] {
+          let other = self.agents[j].position;
+          let (dx, dz) = (x - other[0], z - other[1]);
+          let distance = (dx * dx + dz * dz).sqrt();
+          if j != i && distance > 0.0 && distance < SEPARATION {
+            let strength = (SEPARATION - distance) / SEPARATION / distance;
+            push[0] += dx * strength;
+            push[1] += dz * strength;
+          }
+        }
+      }
+    }
+
+    let desired = [
+      (direction[0] + 2.0 * push[0]) * MAX_SPEED,
+      (direction[1] + 2.0 * push[1]) * MAX_SPEED,
+    ];
+    let blend = (STEERING * dt).min(1.0);
+    let velocity = [
+      agent.velocity[0] + (desired[0] - agent.velocity[0]) * blend,
+      agent.velocity[1] + (desired[1] - agent.velocity[1]) * blend,
+    ];
+    let speed = (velocity[0] * velocity[0] + velocity[1] * velocity[1]).sqrt();
+    if speed > MAX_SPEED {
+      let scale = MAX_SPEED / speed;
+      [velocity[0] * scale, velocity[1] * scale]
+    } else {
+      velocity
+    }
+  }
+
+  // The closest agent `ray` hits where it's drawn, its index and the distance
+  pub fn ray_cast(&self, ray: &Ray) -> Option<(u32, f32)> {
+    let (mesh, bounds) = &self.shape;
+    self
+      .agents
+      .iter()
+      .enumerate()
+      .filter_map(|(i, agent)| {
+        let [x, z] = agent.blended(self.alpha);
+        let model = math::translation([x, 0.0, z]);
+        // the cheap world space box first, most agents are nowhere near the ray
+        bounds.transformed(&model).intersect(ray)?;
+        Some((
+          i as u32,
+          raycast::intersect_mesh(ray, mesh, bounds, &model)?,
+        ))
+      })
+      .min_by(|a, b| a.1.total_cmp(&b.1))
+  }
+
+  // With the scene pipeline or the shadow pipeline bound
+  pub fn draw<'a>(&'a self, pass: &mut RenderPass<'a>) {
+    pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+    self.mesh.draw(pass, self.agents.len() as u32);
+  }
+
+  // The agents at their last tick, with Scene's ghost pipeline bound
+  pub fn draw_ticks<'a>(&'a self, pass: &mut RenderPass<'a>) {
+    if !self.show_ticks {
+      return;
+    }
+    pass.set_vertex_buffer(1, self.tick_buffer.slice(..));
+    self.mesh.draw(pass, self.agents.len() as u32);
+  }
+}
+
+fn create_instance_buffer(device: &Device, label: &str) -> Tracked<wgpu::Buffer> {
+  Tracked::buffer(device.create_buffer(&wgpu::BufferDescriptor {
+    label: Some(label),
+    size: (AGENT_COUNT * std::mem::size_of::<SceneInstance>()) as wgpu::BufferAddress,
+    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+    mapped_at_creation: false,
+  }))
+}
@@ -0,0 +1,269 @@
+use wgpu::{util::DeviceExt, CommandEncoder, Device, Queue, RenderPass, TextureView};
+
+use crate::{
+  gpu_memory::Tracked,
+  math::{self, Mat4},
+  scene::{GpuMesh, SceneInstance},
+  stats::count_draw,
+  uploader::Upload,
+};
+
+const INSTANCES_PER_GROUP: u32 = 64;
+
+// Matches `CullingParams` in culling.wgsl, update() only rewrites the view_proj
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct CullingParams {
+  view_proj: Mat4,
+  radius: f32,
+  first: u32,
+  count: u32,
+  _padding: u32,
+}
+
+// GPU driven drawing of many instances of one mesh: cull() tests them against the camera
+// and a Hi-Z pyramid into a visibility bitmask and compacts the visible ones into a dense
+// list, counting them into the draw command. draw() issues that command without the CPU
+// ever reading back how many made it.
+pub struct GpuCulling {
+  params_buffer: Tracked<wgpu::Buffer>,
+  // the instances that passed, in the order they were in
+  visible_buffer: Tracked<wgpu::Buffer>,
+  command_buffer: Tracked<wgpu::Buffer>,
+  // a bit per instance, cleared before every cull
+  mask_buffer: Tracked<wgpu::Buffer>,
+  bind_group: wgpu::BindGroup,
+  // group 1, made every cull() for the pyramid it gets
+  hiz_layout: wgpu::BindGroupLayout,
+  // a 1x1 pyramid at the far plane for cull() without one
+  no_hiz: TextureView,
+  // the mask, the count of every word and the compaction, see culling.wgsl
+  cull_pipeline: wgpu::ComputePipeline,
+  scan_pipeline: wgpu::ComputePipeline,
+  compact_pipeline: wgpu::ComputePipeline,
+  count: u32,
+  multi_draw: bool,
+}
+
+impl GpuCulling {
+  // Culls the `count` SceneInstances from `first` on in `instances`, which needs STORAGE
+  // usage. `radius` bounds `mesh` around its origin, see Mesh::bounding_radius().
+  pub fn new(
+    device: &Device,
+    queue: &Queue,
+    mesh: &GpuMesh,
+    radius: f32,
+    instances: &wgpu::Buffer,
+    first: u32,
+    count: u32,
+  ) -> Self {
+    let params = CullingParams {
+      view_proj: math::IDENTITY,
+      radius,
+      first,
+      count,
+      _padding: 0,
+    };
+    let params_buffer = Tracked::buffer(device.create_buffer_init(
+      &wgpu::util::BufferInitDescriptor {
+        label: Some("Culling Params Buffer"),
+        contents: bytemuck::bytes_of(&params),
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+      },
+    ));
+    let visible_buffer = Tracked::buffer(device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Culling Visible Instance Buffer"),
+      size: (count.max(1) as usize * std::mem::size_of::<SceneInstance>()) as wgpu::BufferAddress,
+      usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::VERTEX,
+      mapped_at_creation: false,
+    }));
+    let command = wgpu::util::DrawIndexedIndirect {
+      vertex_count: mesh.index_count(),
+      instance_count: 0,
+      base_index: 0,
+      vertex_offset: 0,
+      base_instance: 0,
+    };
+    // the scan overwrites the instance count, the rest stays as it is
+    let command_buffer = Tracked::buffer(device.create_buffer_init(
+      &wgpu::util::BufferInitDescriptor {
+        label: Some("Culling Command Buffer"),
+        contents: command.as_bytes(),
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::INDIRECT,
+      },
+    ));
+    let words = count.div_ceil(32).max(1) as wgpu::BufferAddress;
+    let mask_buffer = Tracked::buffer(device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Culling Mask Buffer"),
+      size: words * 4,
+      usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    }));
+    let offset_buffer = Tracked::buffer(device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Culling Offset Buffer"),
+      size: words * 4,
+      usage: wgpu::BufferUsages::STORAGE,
+      mapped_at_creation: false,
+    }));
+
+    let entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility: wgpu::ShaderStages::COMPUTE,
+      ty: wgpu::BindingType::Buffer {
+        ty,
+        has_dynamic_offset: false,
+        min_binding_size: None,
+      },
+      count: None,
+    };
+    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Culling Bind Group Layout"),
+      entries: &[
+        entry(0, wgpu::BufferBindingType::Uniform),
+        entry(1, wgpu::BufferBindingType::Storage { read_only: true }),
+        entry(2, wgpu::BufferBindingType::Storage { read_only: false }),
+        entry(3, wgpu::BufferBindingType::Storage { read_only: false }),
+        entry(4, wgpu::BufferBindingType::Storage { read_only: false }),
+        entry(5, wgpu::BufferBindingType::Storage { read_only: false }),
+      ],
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Culling Bind Group"),
+      layout: &layout,
+      entries: &[
+        wgpu::BindGroupEntry {
+          binding: 0,
+          resource: params_buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+          binding: 1,
+          resource: instances.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+          binding: 2,
+          resource: visible_buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+          binding: 3,
+          resource: command_buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+          binding: 4,
+          resource: mask_buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+          binding: 5,
+          resource: offset_buffer.as_entire_binding(),
+        },
+      ],
+    });
+    let hiz_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Culling Hi-Z Layout"),
+      entries: &[wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Texture {
+          sample_type: wgpu::TextureSampleType::Float { filterable: false },
+          view_dimension: wgpu::TextureViewDimension::D2,
+          multisampled: false,
+        },
+        count: None,
+      }],
+    });
+    let no_hiz = device
+      .create_texture_with_data(
+        queue,
+        &wgpu::TextureDescriptor {
+          label: Some("Culling No Hi-Z"),
+          size: wgpu::Extent3d {
+            width: 1,
+            height: 1,
+            depth_or_array_layers: 1,
+          },
+          mip_level_count: 1,
+          sample_count: 1,
+          dimension: wgpu::TextureDimension::D2,
+          format: wgpu::TextureFormat::R32Float,
+          usage: wgpu::TextureUsages::TEXTURE_BINDING,
+          view_formats: &[],
+        },
+        bytemuck::bytes_of(&1.0f32),
+      )
+      .create_view(&wgpu::TextureViewDescriptor::default());
+    let shader = device.create_shader_module(wgpu::include_wgsl!("culling.wgsl"));
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Culling Pipeline Layout"),
+      bind_group_layouts: &[&layout, &hiz_layout],
+      push_constant_ranges: &[],
+    });
+    let pipeline = |label, entry_point| {
+      device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some(label),
+        layout: Some(&pipeline_layout),
+        module: &shader,
+        entry_point,
+      })
+    };
+
+    Self {
+      params_buffer,
+      visible_buffer,
+      command_buffer,
+      mask_buffer,
+      bind_group,
+      hiz_layout,
+      no_hiz,
+      cull_pipeline: pipeline("Culling Pipeline", "cs_cull"),
+      scan_pipeline: pipeline("Culling Scan Pipeline", "cs_scan"),
+      compact_pipeline: pipeline("Culling Compact Pipeline", "cs_compact"),
+      count,
+      multi_draw: device
+        .features()
+        .contains(wgpu::Features::MULTI_DRAW_INDIRECT),
+    }
+  }
+
+  // The camera to cull against, unjittered so TAA doesn't make instances at the edge flicker
+  pub fn update(&self, upload: &mut Upload, view_proj: Mat4) {
+    upload.write(&self.params_buffer, 0, bytemuck::bytes_of(&view_proj));
+  }
+
+  // Has to run after update() and before draw(). `hiz` is HiZ::view() built with the same
+  // camera, None only culls against the frustum.
+  pub fn cull(&self, device: &Device, encoder: &mut CommandEncoder, hiz: Option<&TextureView>) {
+    let hiz_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Culling Hi-Z Bind Group"),
+      layout: &self.hiz_layout,
+      entries: &[wgpu::BindGroupEntry {
+        binding: 0,
+        resource: wgpu::BindingResource::TextureView(hiz.unwrap_or(&self.no_hiz)),
+      }],
+    });
+    encoder.clear_buffer(&self.mask_buffer, 0, None);
+    let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+      label: Some("Culling Pass"),
+    });
+    pass.set_bind_group(0, &self.bind_group, &[]);
+    pass.set_bind_group(1, &hiz_bind_group, &[]);
+    let groups = self.count.div_ceil(INSTANCES_PER_GROUP);
+    pass.set_pipeline(&self.cull_pipeline);
+    pass.dispatch_workgroups(groups, 1, 1);
+    pass.set_pipeline(&self.scan_pipeline);
+    pass.dispatch_workgroups(1, 1, 1);
+    pass.set_pipeline(&self.compact_pipeline);
+    pass.dispatch_workgroups(groups, 1, 1);
+  }
+
+  // Draws `mesh` for the instances cull() kept. There's one command per mesh, batching
+  // several meshes into one buffer would let multi-draw issue them all in one call.
+  pub fn draw<'a>(&'a self, pass: &mut RenderPass<'a>, mesh: &'a GpuMesh) {
+    pass.set_vertex_buffer(1, self.visible_buffer.slice(..));
+    mesh.bind(pass);
+    count_draw();
+    if self.multi_draw {
+      pass.multi_draw_indexed_indirect(&self.command_buffer, 0, 1);
+    } else {
+      pass.draw_indexed_indirect(&self.command_buffer, 0);
+    }
+  }
+}
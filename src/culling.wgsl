@@ -0,0 +1,183 @@
+// Frustum and occlusion culling on the GPU in three dispatches. cs_cull tests every
+// instance's bounding sphere against the camera planes and the Hi-Z pyramid (hiz.rs) and
+// sets its bit in the visibility mask. cs_scan counts the bits to find where each word's
+// instances start, and writes the total into the draw command. cs_compact then copies the
+// visible instances into a dense list for the indirect draw to read, in the same order as
+// before culling.
+
+// Matches `SceneInstance` in scene.rs
+struct Instance {
+    model: mat4x4<f32>,
+    color: vec4<f32>,
+    material: vec4<f32>,
+};
+
+// Matches `CullingParams` in culling.rs
+struct CullingParams {
+    view_proj: mat4x4<f32>,
+    // of the mesh around its origin, before the model matrix scales it
+    radius: f32,
+    first: u32,
+    count: u32,
+};
+
+// Laid out like wgpu's DrawIndexedIndirect, the draw reads it straight from this buffer
+struct DrawCommand {
+    index_count: u32,
+    instance_count: u32,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+};
+
+// threads of the one workgroup cs_scan runs, each counts a run of the mask's words
+const SCAN_THREADS: u32 = 256u;
+
+@group(0) @binding(0)
+var<uniform> params: CullingParams;
+@group(0) @binding(1)
+var<storage, read> instances: array<Instance>;
+@group(0) @binding(2)
+var<storage, read_write> visible: array<Instance>;
+@group(0) @binding(3)
+var<storage, read_write> command: DrawCommand;
+// a bit per instance, cleared before cs_cull
+@group(0) @binding(4)
+var<storage, read_write> mask: array<atomic<u32>>;
+// per word of the mask, how many instances before it are visible
+@group(0) @binding(5)
+var<storage, read_write> offsets: array<u32>;
+
+var<workgroup> sums: array<u32, SCAN_THREADS>;
+
+// the farthest depth drawn under each texel, level by level. 1x1 at the far plane when
+// there's no prepass, which hides nothing.
+@group(1) @binding(0)
+var hiz: texture_2d<f32>;
+
+// Gribb-Hartmann: the planes are sums of the rows of view_proj, depth goes 0..1
+fn frustum_plane(i: u32) -> vec4<f32> {
+    let m = transpose(params.view_proj);
+    switch i {
+        case 0u: { return m[3] + m[0]; }
+        case 1u: { return m[3] - m[0]; }
+        case 2u: { return m[3] + m[1]; }
+        case 3u: { return m[3] - m[1]; }
+        case 4u: { return m[2]; }
+        default: { return m[3] - m[2]; }
+    }
+}
+
+// Whether the sphere is behind everything the prepass drew where it is on screen. It's
+// bounded by the screen rectangle and nearest depth of the box around it.
+fn is_occluded(center: vec3<f32>, radius: f32) -> bool {
+    var uv_min = vec2<f32>(1.0);
+    var uv_max = vec2<f32>(0.0);
+    var nearest = 1.0;
+    for (var i = 0u; i < 8u; i++) {
+        let corner = vec3<f32>(f32(i & 1u), f32((i >> 1u) & 1u), f32(i >> 2u)) * 2.0 - 1.0;
+        let clip = params.view_proj * vec4<f32>(center + corner * radius, 1.0);
+        // crossing the near plane, the corners don't bound it on screen any more
+        if clip.w <= 0.0 {
+            return false;
+        }
+        let ndc = clip.xyz / clip.w;
+        let uv = ndc.xy * vec2<f32>(0.5, -0.5) + 0.5;
+        uv_min = min(uv_min, uv);
+        uv_max = max(uv_max, uv);
+        nearest = min(nearest, ndc.z);
+    }
+    let size = vec2<i32>(textureDimensions(hiz, 0));
+    // a texel of slack either way, the pyramid halves the depth rounding down so its texels
+    // don't line up with the pixels exactly
+    let low = clamp(vec2<i32>(saturate(uv_min) * vec2<f32>(size)) - 1, vec2<i32>(0), size - 1);
+    let high = clamp(vec2<i32>(saturate(uv_max) * vec2<f32>(size)) + 1, vec2<i32>(0), size - 1);
+    // the first level where the rectangle is at most 2x2 texels. The pyramid is a full mip
+    // chain, there's another level as long as halving leaves a texel (GLSL ES can't ask).
+    let largest = max(size.x, size.y);
+    var level = 0u;
+    while (largest >> (level + 1u)) > 0 && any((high >> vec2<u32>(level)) - (low >> vec2<u32>(level)) > vec2<i32>(1)) {
+        level += 1u;
+    }
+    // each level's last texel covers the rest of the one below
+    let shift = vec2<u32>(level);
+    let last = max(size >> shift, vec2<i32>(1)) - 1;
+    let a = min(low >> shift, last);
+    let b = min(high >> shift, last);
+    let lod = i32(level);
+    let farthest = max(
+        max(textureLoad(hiz, a, lod).r, textureLoad(hiz, vec2<i32>(b.x, a.y), lod).r),
+        max(textureLoad(hiz, vec2<i32>(a.x, b.y), lod).r, textureLoad(hiz, b, lod).r)
+    );
+    return nearest > farthest;
+}
+
+@compute @workgroup_size(64)
+fn cs_cull(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= params.count {
+        return;
+    }
+    let instance = instances[params.first + id.x];
+    let center = instance.model[3].xyz;
+    let scale = max(length(instance.model[0].xyz), max(length(instance.model[1].xyz), length(instance.model[2].xyz)));
+    let radius = params.radius * scale;
+    for (var i = 0u; i < 6u; i++) {
+        let plane = frustum_plane(i);
+        if dot(plane.xyz, center) + plane.w < -radius * length(plane.xyz) {
+            return;
+        }
+    }
+    if is_occluded(center, radius) {
+        return;
+    }
+    atomicOr(&mask[id.x / 32u], 1u << (id.x % 32u));
+}
+
+// One workgroup for the whole mask: every thread counts a run of words, the runs' counts
+// are summed up in shared memory and the thread hands the sums out to its words
+@compute @workgroup_size(256)
+fn cs_scan(@builtin(local_invocation_index) thread: u32) {
+    let words = (params.count + 31u) / 32u;
+    let per_thread = (words + SCAN_THREADS - 1u) / SCAN_THREADS;
+    let start = min(thread * per_thread, words);
+    let end = min(start + per_thread, words);
+    var total = 0u;
+    for (var word = start; word < end; word++) {
+        total += countOneBits(atomicLoad(&mask[word]));
+    }
+    sums[thread] = total;
+    workgroupBarrier();
+    // Hillis-Steele, after it every thread has the count up to and including its run
+    for (var stride = 1u; stride < SCAN_THREADS; stride *= 2u) {
+        var sum = sums[thread];
+        if thread >= stride {
+            sum += sums[thread - stride];
+        }
+        workgroupBarrier();
+        sums[thread] = sum;
+        workgroupBarrier();
+    }
+    var offset = sums[thread] - total;
+    for (var word = start; word < end; word++) {
+        offsets[word] = offset;
+        offset += countOneBits(atomicLoad(&mask[word]));
+    }
+    if thread == SCAN_THREADS - 1u {
+        command.instance_count = sums[thread];
+    }
+}
+
+@compute @workgroup_size(64)
+fn cs_compact(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= params.count {
+        return;
+    }
+    let bits = atomicLoad(&mask[id.x / 32u]);
+    let bit = 1u << (id.x % 32u);
+    if (bits & bit) == 0u {
+        return;
+    }
+    // the visible instances before this one in its word
+    let slot = offsets[id.x / 32u] + countOneBits(bits & (bit - 1u));
+    visible[slot] = instances[params.first + id.x];
+}
@@ -0,0 +1,162 @@
+use wgpu::{BindGroup, BindGroupLayout, CommandEncoder, Device, Queue, TextureFormat, TextureView};
+
+use crate::{
+  gpu_memory::Tracked,
+  math::{self, Mat4, Vec3},
+  pipeline::debug_line_pipe,
+  raycast::Aabb,
+  stats::count_draw,
+};
+
+// Vertices the buffer starts with room for, it doubles whenever a frame needs more
+const INITIAL_CAPACITY: u64 = 1024;
+
+// Matches `LineVertex` in debug_line.wgsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DebugVertex {
+  position: Vec3,
+  color: [f32; 4],
+}
+
+impl DebugVertex {
+  const ATTRIBUTES: [wgpu::VertexAttribute; 2] =
+    wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x4];
+
+  pub fn layout() -> wgpu::VertexBufferLayout<'static> {
+    wgpu::VertexBufferLayout {
+      array_stride: std::mem::size_of::<DebugVertex>() as wgpu::BufferAddress,
+      step_mode: wgpu::VertexStepMode::Vertex,
+      attributes: &Self::ATTRIBUTES,
+    }
+  }
+}
+
+// Immediate mode debug drawing: anything can add lines while a frame is prepared and they're
+// drawn over the scene at the end of it. Nothing is kept, clear() starts the next frame empty.
+pub struct DebugDraw {
+  // two per line
+  vertices: Vec<DebugVertex>,
+  buffer: Tracked<wgpu::Buffer>,
+  // in vertices
+  capacity: u64,
+  pipeline: wgpu::RenderPipeline,
+}
+
+impl DebugDraw {
+  // `globals_layout` is group 0 of the scene pipelines
+  pub fn new(device: &Device, format: TextureFormat, globals_layout: &BindGroupLayout) -> Self {
+    Self {
+      vertices: Vec::new(),
+      buffer: create_buffer(device, INITIAL_CAPACITY),
+      capacity: INITIAL_CAPACITY,
+      pipeline: debug_line_pipe(device, format, globals_layout),
+    }
+  }
+
+  pub fn clear(&mut self) {
+    self.vertices.clear();
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.vertices.is_empty()
+  }
+
+  // Colors are linear and go through the tonemapper like the rest of the scene
+  pub fn line(&mut self, a: Vec3, b: Vec3, color: [f32; 4]) {
+    self.vertices.push(DebugVertex { position: a, color });
+    self.vertices.push(DebugVertex { position: b, color });
+  }
+
+  // The 12 edges of the box
+  pub fn aabb(&mut self, aabb: &Aabb, color: [f32; 4]) {
+    let corner = |i: usize| {
+      [0, 1, 2].map(|axis| {
+        if i & (1 << axis) == 0 {
+          aabb.min[axis]
+        } else {
+          aabb.max[axis]
+        }
+      })
+    };
+    for i in 0..8 {
+      // every corner to the neighbours that differ in one axis, each edge once
+      for axis in 0..3 {
+        if i & (1 << axis) == 0 {
+          self.line(corner(i), corner(i | 1 << axis), color);
+        }
+      }
+    }
+  }
+
+  // The x, y and z axes of `transform` in red, green and blue, as long as its scale
+  pub fn axes(&mut self, transform: &Mat4) {
+    let origin = math::transform_point(transform, [0.0; 3]);
+    let colors = [
+      [1.0, 0.1, 0.1, 1.0],
+      [0.1, 1.0, 0.1, 1.0],
+      [0.1, 0.2, 1.0, 1.0],
+    ];
+    for (axis, color) in colors.into_iter().enumerate() {
+      let mut tip = [0.0; 3];
+      tip[axis] = 1.0;
+      self.line(origin, math::transform_point(transform, tip), color);
+    }
+  }
+
+  // Draws the lines over `view`, tested against the scene's `depth_view` without writing it.
+  // `globals` is the scene's group 0.
+  pub fn render(
+    &mut self,
+    device: &Device,
+    queue: &Queue,
+    encoder: &mut CommandEncoder,
+    view: &TextureView,
+    depth_view: &TextureView,
+    globals: &BindGroup,
+  ) {
+    if self.vertices.is_empty() {
+      return;
+    }
+    let count = self.vertices.len() as u64;
+    if count > self.capacity {
+      self.capacity = count.next_power_of_two();
+      self.buffer = create_buffer(device, self.capacity);
+    }
+    queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&self.vertices));
+
+    let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+      label: Some("Debug Line Pass"),
+      color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+        view,
+        resolve_target: None,
+        ops: wgpu::Operations {
+          load: wgpu::LoadOp::Load,
+          store: true,
+        },
+      })],
+      depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+        view: depth_view,
+        depth_ops: Some(wgpu::Operations {
+          load: wgpu::LoadOp::Load,
+          store: true,
+        }),
+        stencil_ops: None,
+      }),
+    });
+    pass.set_pipeline(&self.pipeline);
+    pass.set_bind_group(0, globals, &[]);
+    pass.set_vertex_buffer(0, self.buffer.slice(..));
+    count_draw();
+    pass.draw(0..count as u32, 0..1);
+  }
+}
+
+fn create_buffer(device: &Device, capacity: u64) -> Tracked<wgpu::Buffer> {
+  Tracked::buffer(device.create_buffer(&wgpu::BufferDescriptor {
+    label: Some("Debug Line Buffer"),
+    size: capacity * std::mem::size_of::<DebugVertex>() as u64,
+    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+    mapped_at_creation: false,
+  }))
+}
@@ -0,0 +1,27 @@
+// Debug lines from DebugDraw, flat colors over the finished scene. Drawn after TAA, so with
+// the camera it didn't shift.
+
+#include "common.wgsl"
+
+struct LineVertex {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(vertex: LineVertex) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = globals.unjittered_view_proj * vec4<f32>(vertex.position, 1.0);
+    out.color = vertex.color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
@@ -0,0 +1,205 @@
+use wgpu::{BindGroupLayout, CommandEncoder, Device, RenderPass, TextureFormat, TextureView};
+
+use crate::{
+  gpu_memory::Tracked,
+  pipeline::{deferred_lighting_pipe, gbuffer_pipe},
+  ssao::SSAO_FORMAT,
+};
+
+// Albedo, world space normal and material (specular strength, shininess), one render target
+// each. Keep in sync with GBufferOutput in gbuffer.wgsl.
+pub const GBUFFER_FORMATS: [TextureFormat; 3] = [
+  TextureFormat::Rgba8UnormSrgb,
+  // normals are signed and need more than 8 bits to keep highlights smooth
+  TextureFormat::Rgba16Float,
+  TextureFormat::Rgba8Unorm,
+];
+const GBUFFER_LABELS: [&str; 3] = ["GBuffer Albedo", "GBuffer Normal", "GBuffer Material"];
+
+// The deferred path's render targets, sized like the viewport's hdr target whose depth
+// they share
+pub struct GBuffer {
+  views: [Tracked<TextureView>; 3],
+  // ambient occlusion straight out of the ssao pass and blurred
+  occlusion_views: [Tracked<TextureView>; 2],
+}
+
+impl GBuffer {
+  pub fn new(device: &Device, width: u32, height: u32) -> Self {
+    Self {
+      views: create_views(device, width, height),
+      occlusion_views: create_occlusion_views(device, width, height),
+    }
+  }
+
+  pub fn resize(&mut self, device: &Device, width: u32, height: u32) {
+    self.views = create_views(device, width, height);
+    self.occlusion_views = create_occlusion_views(device, width, height);
+  }
+
+  pub fn normal_view(&self) -> &TextureView {
+    &self.views[1]
+  }
+
+  pub fn raw_occlusion_view(&self) -> &TextureView {
+    &self.occlusion_views[0]
+  }
+
+  pub fn occlusion_view(&self) -> &TextureView {
+    &self.occlusion_views[1]
+  }
+
+  // Clears every target and `depth_view`, the scene's depth buffer
+  pub fn begin_pass<'a>(
+    &'a self,
+    encoder: &'a mut CommandEncoder,
+    depth_view: &'a TextureView,
+  ) -> RenderPass<'a> {
+    let attachment = |view| {
+      Some(wgpu::RenderPassColorAttachment {
+        view,
+        resolve_target: None,
+        ops: wgpu::Operations {
+          load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+          store: true,
+        },
+      })
+    };
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+      label: Some("GBuffer Pass"),
+      color_attachments: &[
+        attachment(&self.views[0]),
+        attachment(&self.views[1]),
+        attachment(&self.views[2]),
+      ],
+      depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+        view: depth_view,
+        depth_ops: Some(wgpu::Operations {
+          load: wgpu::LoadOp::Clear(1.0),
+          store: true,
+        }),
+        // the forward pass after the lighting keeps drawing into it
+        stencil_ops: Some(wgpu::Operations {
+          load: wgpu::LoadOp::Clear(0),
+          store: true,
+        }),
+      }),
+    })
+  }
+}
+
+// Pipelines of the deferred path: the G-buffer fill and the fullscreen lighting pass
+pub struct DeferredRenderer {
+  gbuffer_pipeline: wgpu::RenderPipeline,
+  lighting_pipeline: wgpu::RenderPipeline,
+  layout: BindGroupLayout,
+}
+
+impl DeferredRenderer {
+  // `globals_layout` and `shadow_layout` are groups 0 and 1 of the scene pipeline
+  pub fn new(
+    device: &Device,
+    format: TextureFormat,
+    globals_layout: &BindGroupLayout,
+    shadow_layout: &BindGroupLayout,
+  ) -> Self {
+    // every target is read with textureLoad, one texel per pixel, so no sampler
+    let texture_entry = |binding, sample_type| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility: wgpu::ShaderStages::FRAGMENT,
+      ty: wgpu::BindingType::Texture {
+        sample_type,
+        view_dimension: wgpu::TextureViewDimension::D2,
+        multisampled: false,
+      },
+      count: None,
+    };
+    let color = wgpu::TextureSampleType::Float { filterable: false };
+    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("GBuffer Layout"),
+      entries: &[
+        texture_entry(0, color),
+        texture_entry(1, color),
+        texture_entry(2, color),
+        texture_entry(3, color),
+        texture_entry(4, color),
+      ],
+    });
+    Self {
+      gbuffer_pipeline: gbuffer_pipe(device, globals_layout),
+      lighting_pipeline: deferred_lighting_pipe(
+        device,
+        format,
+        &[globals_layout, shadow_layout, &layout],
+      ),
+      layout,
+    }
+  }
+
+  pub fn gbuffer_pipeline(&self) -> &wgpu::RenderPipeline {
+    &self.gbuffer_pipeline
+  }
+
+  pub fn lighting_pipeline(&self) -> &wgpu::RenderPipeline {
+    &self.lighting_pipeline
+  }
+
+  // Group 2 of the lighting pass. Made per frame since the G-buffer and the depth belong to
+  // whichever viewport is drawing.
+  pub fn bind_group(
+    &self,
+    device: &Device,
+    gbuffer: &GBuffer,
+    depth_view: &TextureView,
+  ) -> wgpu::BindGroup {
+    let entry = |binding, view| wgpu::BindGroupEntry {
+      binding,
+      resource: wgpu::BindingResource::TextureView(view),
+    };
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("GBuffer Bind Group"),
+      layout: &self.layout,
+      entries: &[
+        entry(0, &gbuffer.views[0]),
+        entry(1, &gbuffer.views[1]),
+        entry(2, &gbuffer.views[2]),
+        entry(3, depth_view),
+        entry(4, gbuffer.occlusion_view()),
+      ],
+    })
+  }
+}
+
+fn create_view(
+  device: &Device,
+  label: &str,
+  width: u32,
+  height: u32,
+  format: TextureFormat,
+) -> Tracked<TextureView> {
+  let texture = device.create_texture(&wgpu::TextureDescriptor {
+    label: Some(label),
+    size: wgpu::Extent3d {
+      width,
+      height,
+      depth_or_array_layers: 1,
+    },
+    mip_level_count: 1,
+    sample_count: 1,
+    dimension: wgpu::TextureDimension::D2,
+    format,
+    usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+    view_formats: &[],
+  });
+  let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+  Tracked::view(&texture, view)
+}
+
+fn create_views(device: &Device, width: u32, height: u32) -> [Tracked<TextureView>; 3] {
+  [0, 1, 2].map(|i| create_view(device, GBUFFER_LABELS[i], width, height, GBUFFER_FORMATS[i]))
+}
+
+fn create_occlusion_views(device: &Device, width: u32, height: u32) -> [Tracked<TextureView>; 2] {
+  ["GBuffer Occlusion", "GBuffer Blurred Occlusion"]
+    .map(|label| create_view(device, label, width, height, SSAO_FORMAT))
+}
@@ -0,0 +1,54 @@
+// Second half of the deferred path: one fullscreen triangle lights every pixel the G-buffer
+// pass covered, with the same shading as scene.wgsl
+
+#include "lighting.wgsl"
+
+@group(2) @binding(0)
+var albedo_map: texture_2d<f32>;
+@group(2) @binding(1)
+var normal_map: texture_2d<f32>;
+@group(2) @binding(2)
+var material_map: texture_2d<f32>;
+// bound as a plain float texture, GLSL has no textureLoad for depth textures
+@group(2) @binding(3)
+var depth_map: texture_2d<f32>;
+// ambient occlusion from ssao.wgsl, cleared to white with it off
+@group(2) @binding(4)
+var occlusion_map: texture_2d<f32>;
+
+struct FullscreenOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+};
+
+// One triangle that covers the whole screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> FullscreenOutput {
+    var out: FullscreenOutput;
+    let x = f32(i32(in_vertex_index & 1u) * 4 - 1);
+    let y = f32(i32(in_vertex_index >> 1u) * 4 - 1);
+    out.clip_position = vec4<f32>(x, y, 0.0, 1.0);
+    out.ndc = vec2<f32>(x, y);
+    return out;
+}
+
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(in.clip_position.xy);
+    let depth = textureLoad(depth_map, pixel, 0).r;
+    // nothing was drawn here, the clear color and later the skybox show through
+    if depth >= 1.0 {
+        discard;
+    }
+    let world = globals.inverse_view_proj * vec4<f32>(in.ndc, depth, 1.0);
+    let position = world.xyz / world.w;
+
+    let albedo = textureLoad(albedo_map, pixel, 0).rgb;
+    let normal = normalize(textureLoad(normal_map, pixel, 0).xyz);
+    let stored = textureLoad(material_map, pixel, 0).xy;
+    let material = vec2<f32>(stored.x, stored.y * MAX_SHININESS);
+    let occlusion = textureLoad(occlusion_map, pixel, 0).r;
+    let light_position = globals.light_view_proj * vec4<f32>(position, 1.0);
+    let color = shade_blinn_phong(position, normal, albedo, material, light_position, occlusion);
+    return vec4<f32>(color, 1.0);
+}
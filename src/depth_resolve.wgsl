@@ -0,0 +1,29 @@
+// Resolves the multisampled depth of the main pass into the single sampled depth the passes
+// after it test against and read, see HdrPipeline::resolve_depth(). Drawn with a depth test
+// that always passes, so every pixel is written.
+
+// bound as a plain float texture, GLSL has no textureLoad for depth textures
+@group(0) @binding(0)
+var depth_map: texture_multisampled_2d<f32>;
+
+// One triangle that covers the whole screen, no vertex buffer needed
+@vertex
+fn vs_main(
+    @builtin(vertex_index) in_vertex_index: u32,
+) -> @builtin(position) vec4<f32> {
+    let x = f32(i32(in_vertex_index & 1u) * 4 - 1);
+    let y = f32(i32(in_vertex_index >> 1u) * 4 - 1);
+    return vec4<f32>(x, y, 0.0, 1.0);
+}
+
+// The nearest of the pixel's samples, so what's drawn after it doesn't show through the
+// edges of what's in front
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @builtin(frag_depth) f32 {
+    let pixel = vec2<i32>(position.xy);
+    var depth = 1.0;
+    for (var i = 0; i < #{SAMPLE_COUNT}; i = i + 1) {
+        depth = min(depth, textureLoad(depth_map, pixel, i).r);
+    }
+    return depth;
+}
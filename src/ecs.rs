@@ -0,0 +1,243 @@
+// The scene's objects as entities of a bevy_ecs World instead of fields of Scene. An entity
+// has a Transform, under its Parent's if it has one, and components saying what it is: a
+// MeshHandle and a Material for a model, a Light or a Camera. Scene::run_systems() runs the
+// systems once a frame, in order: spin, transform propagation into GlobalTransform, culling
+// against the active camera, then extraction, which gathers what the renderer needs into
+// RenderExtract for Scene to upload. The rest of Scene hasn't moved over yet.
+
+pub use bevy_ecs::entity::Entity;
+use bevy_ecs::prelude::*;
+use wgpu::Device;
+
+pub use crate::animation::Transform;
+use crate::{
+  camera::{OrbitCamera, Tile},
+  lighting::PointLight,
+  math::{self, Mat4, Vec3},
+  mesh::Mesh,
+  pbr::{PbrMaterial, PbrTextures},
+  raycast::Aabb,
+  scene::{ModelId, Scene},
+  scene_file::MeshRef,
+};
+
+// Deeper than this is taken for a loop of parents
+const MAX_DEPTH: usize = 64;
+const CAROUSEL_POSITION: Vec3 = [-3.0, 0.0, 3.0];
+const CAROUSEL_CARS: usize = 6;
+
+// The Transform after all of its parents', in the world
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct GlobalTransform(pub Mat4);
+
+impl Default for GlobalTransform {
+  fn default() -> Self {
+    Self(math::IDENTITY)
+  }
+}
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Parent(pub Entity);
+
+// The model Scene::spawn_model() added for it, with its mesh and instance buffer
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MeshHandle(pub ModelId);
+
+// Changing it uploads the new factors, the textures stay
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct Material(pub PbrMaterial);
+
+// The mesh's, before the transform
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct Bounds(pub Aabb);
+
+// Whether the active camera sees it, set by cull()
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Visibility(pub bool);
+
+// Never drawn, whatever the camera sees, see the inspector
+#[derive(Component, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Hidden;
+
+// A point light where the entity is, its own position is ignored
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct Light(pub PointLight);
+
+// Models the active camera can't see aren't drawn. Scene keeps one following its own
+// camera, for the primary window's tile.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Camera {
+  pub camera: OrbitCamera,
+  pub tile: Tile,
+  pub active: bool,
+}
+
+// Turns it around Y, radians per second
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct Spin(pub f32);
+
+// Seconds since the systems last ran, see Clock::animation
+#[derive(Resource, Debug, Clone, Copy, Default)]
+pub struct DeltaTime(pub f32);
+
+// What the renderer needs from the world, filled by extract()
+#[derive(Resource, Debug, Default)]
+pub struct RenderExtract {
+  // every model's transform and whether it's drawn
+  pub models: Vec<(ModelId, Mat4, bool)>,
+  // only the ones that changed since the last run
+  pub materials: Vec<(ModelId, PbrMaterial)>,
+  pub lights: Vec<PointLight>,
+}
+
+// An empty world with the resources the systems need
+pub fn world() -> World {
+  let mut world = World::new();
+  world.insert_resource(DeltaTime::default());
+  world.insert_resource(RenderExtract::default());
+  world
+}
+
+pub fn schedule() -> Schedule {
+  let mut schedule = Schedule::new();
+  schedule.add_systems((spin, propagate_transforms, cull, extract).chain());
+  schedule
+}
+
+// Whether any entity has a `T`
+pub fn any_with<T: Component>(world: &World) -> bool {
+  let Some(id) = world.component_id::<T>() else {
+    return false;
+  };
+  world
+    .archetypes()
+    .iter()
+    .any(|archetype| archetype.contains(id) && !archetype.is_empty())
+}
+
+fn spin(time: Res<DeltaTime>, mut query: Query<(&mut Transform, &Spin)>) {
+  for (mut transform, spin) in &mut query {
+    let turn = math::quat_axis_angle([0.0, 1.0, 0.0], spin.0 * time.0);
+    transform.rotation = math::quat_mul(turn, transform.rotation);
+  }
+}
+
+// Every entity walks up its own chain of parents, nothing says parents come first. Cheap
+// enough for the shallow hierarchies here.
+fn propagate_transforms(
+  locals: Query<(&Transform, Option<&Parent>)>,
+  mut globals: Query<(Entity, &mut GlobalTransform)>,
+) {
+  for (entity, mut global) in &mut globals {
+    let mut matrix = math::IDENTITY;
+    let mut next = Some(entity);
+    for _ in 0..MAX_DEPTH {
+      let Some((local, parent)) = next.and_then(|entity| locals.get(entity).ok()) else {
+        break;
+      };
+      matrix = math::mul_mat4(&local.to_mat4(), &matrix);
+      next = parent.map(|parent| parent.0);
+    }
+    global.0 = matrix;
+  }
+}
+
+fn cull(
+  cameras: Query<&Camera>,
+  mut models: Query<(&GlobalTransform, &Bounds, &mut Visibility, Option<&Hidden>)>,
+) {
+  let view_proj = cameras
+    .iter()
+    .find(|camera| camera.active)
+    .map(|camera| camera.camera.tile_view_proj(&camera.tile));
+  for (global, bounds, mut visibility, hidden) in &mut models {
+    let visible = hidden.is_none()
+      && view_proj.is_none_or(|view_proj| bounds.0.in_view(&math::mul_mat4(&view_proj, &global.0)));
+    // only a change marks it changed
+    if visibility.0 != visible {
+      visibility.0 = visible;
+    }
+  }
+}
+
+fn extract(
+  mut extract: ResMut<RenderExtract>,
+  models: Query<(&MeshHandle, &GlobalTransform, Option<&Visibility>)>,
+  materials: Query<(&MeshHandle, &Material), Changed<Material>>,
+  lights: Query<(&Light, &GlobalTransform)>,
+) {
+  let extract = &mut *extract;
+  extract.models.clear();
+  extract
+    .models
+    .extend(models.iter().map(|(mesh, global, visibility)| {
+      (mesh.0, global.0, visibility.is_none_or(|visible| visible.0))
+    }));
+  extract.materials.clear();
+  extract.materials.extend(
+    materials
+      .iter()
+      .map(|(mesh, material)| (mesh.0, material.0)),
+  );
+  extract.lights.clear();
+  extract.lights.extend(lights.iter().map(|(light, global)| {
+    let [x, y, z, _] = global.0[3];
+    PointLight {
+      position: [x, y, z],
+      ..light.0
+    }
+  }));
+}
+
+// A ring of cubes turning around a lamp, 2 toggles it. Scene::despawn() of the entity it
+// returns takes the rest with it.
+pub fn spawn_carousel(device: &Device, scene: &mut Scene) -> Entity {
+  let root = scene
+    .world_mut()
+    .spawn((
+      Transform::from_translation(CAROUSEL_POSITION),
+      GlobalTransform::default(),
+      Spin(0.6),
+    ))
+    .id();
+  let lamp = PointLight {
+    position: [0.0; 3],
+    range: 3.0,
+    color: [1.0, 0.85, 0.6],
+    intensity: 3.0,
+  };
+  scene.spawn_light(
+    lamp,
+    Transform::from_translation([0.0, 1.2, 0.0]),
+    Some(root),
+  );
+  let cube = MeshRef::Cube(0.2);
+  let mesh = Mesh::cube(0.2);
+  for i in 0..CAROUSEL_CARS {
+    let angle = i as f32 / CAROUSEL_CARS as f32 * std::f32::consts::TAU;
+    let [r, g, b] = math::hue_to_rgb(i as f32 / CAROUSEL_CARS as f32);
+    let material = PbrMaterial {
+      base_color: [r, g, b, 1.0],
+      metallic: 0.0,
+      roughness: 0.4,
+      ..Default::default()
+    };
+    // facing the way they go round
+    let transform = Transform {
+      translation: [1.3 * angle.cos(), 0.4, 1.3 * angle.sin()],
+      rotation: math::quat_axis_angle([0.0, 1.0, 0.0], -angle),
+      ..Transform::IDENTITY
+    };
+    let car = scene.spawn_model(
+      device,
+      &mesh,
+      &material,
+      PbrTextures::default(),
+      transform,
+      Some(root),
+    );
+    // so it's saved with the scene
+    scene.world_mut().entity_mut(car).insert(cube.clone());
+  }
+  root
+}
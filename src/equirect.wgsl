@@ -0,0 +1,48 @@
+// Resamples an equirectangular panorama into the six faces of a cube map
+
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+@group(0) @binding(1)
+var faces: texture_storage_2d_array<rgba16float, write>;
+
+const PI: f32 = 3.14159265359;
+
+// Same face order and orientation as the cube map sampler: +X, -X, +Y, -Y, +Z, -Z
+fn face_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    switch face {
+        case 0u: { return vec3<f32>(1.0, -uv.y, -uv.x); }
+        case 1u: { return vec3<f32>(-1.0, -uv.y, uv.x); }
+        case 2u: { return vec3<f32>(uv.x, 1.0, uv.y); }
+        case 3u: { return vec3<f32>(uv.x, -1.0, -uv.y); }
+        case 4u: { return vec3<f32>(uv.x, -uv.y, 1.0); }
+        default: { return vec3<f32>(-uv.x, -uv.y, -1.0); }
+    }
+}
+
+// float32 textures aren't filterable everywhere, so the bilinear filtering is done by hand
+fn sample_bilinear(uv: vec2<f32>) -> vec3<f32> {
+    let size = vec2<i32>(textureDimensions(source));
+    let pixel = uv * vec2<f32>(size) - 0.5;
+    let base = vec2<i32>(floor(pixel));
+    let t = fract(pixel);
+    // x wraps around the panorama, y clamps at the poles
+    let x0 = (base.x % size.x + size.x) % size.x;
+    let x1 = (x0 + 1) % size.x;
+    let y0 = clamp(base.y, 0, size.y - 1);
+    let y1 = clamp(base.y + 1, 0, size.y - 1);
+    let top = mix(textureLoad(source, vec2<i32>(x0, y0), 0).rgb, textureLoad(source, vec2<i32>(x1, y0), 0).rgb, t.x);
+    let bottom = mix(textureLoad(source, vec2<i32>(x0, y1), 0).rgb, textureLoad(source, vec2<i32>(x1, y1), 0).rgb, t.x);
+    return mix(top, bottom, t.y);
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = vec2<u32>(textureDimensions(faces));
+    if id.x >= size.x || id.y >= size.y {
+        return;
+    }
+    let uv = (vec2<f32>(id.xy) + 0.5) / vec2<f32>(size) * 2.0 - 1.0;
+    let dir = normalize(face_direction(id.z, uv));
+    let panorama_uv = vec2<f32>(atan2(dir.z, dir.x) / (2.0 * PI) + 0.5, acos(dir.y) / PI);
+    textureStore(faces, vec2<i32>(id.xy), i32(id.z), vec4<f32>(sample_bilinear(panorama_uv), 1.0));
+}
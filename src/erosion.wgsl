@@ -0,0 +1,285 @@
+// Grid based hydraulic and thermal erosion, after Mei et al. "Fast Hydraulic Erosion
+// Simulation and Visualization on GPU". One iteration is cs_flux, cs_water and cs_transport,
+// cs_mesh then turns the heights into scene vertices.
+
+struct Params {
+    resolution: u32,
+    // world units between two cells
+    cell_size: f32,
+    half_extent: f32,
+    dt: f32,
+    rain: f32,
+    // gravity * pipe cross section / pipe length
+    pipe: f32,
+    capacity: f32,
+    dissolve: f32,
+    deposit: f32,
+    evaporation: f32,
+    // height difference between neighbours above which material slides down
+    talus: f32,
+    thermal: f32,
+};
+
+struct Cell {
+    height: f32,
+    water: f32,
+    sediment: f32,
+    _padding: f32,
+};
+
+@group(0) @binding(0)
+var<uniform> params: Params;
+@group(0) @binding(1)
+var<storage, read> src: array<Cell>;
+@group(0) @binding(2)
+var<storage, read_write> dst: array<Cell>;
+// outflow towards -x, +x, -z and +z
+@group(0) @binding(3)
+var<storage, read_write> flux: array<vec4<f32>>;
+// MeshVertex, 12 floats each
+@group(0) @binding(4)
+var<storage, read_write> vertices: array<f32>;
+
+// Velocities above this many cells per second are clamped, thin films of water would
+// otherwise move at any speed
+const MAX_SPEED: f32 = 4.0;
+// Flat ground still erodes a little
+const MIN_TILT: f32 = 0.05;
+// Water shallower than this carries proportionally less, so damp ground doesn't erode
+const FULL_DEPTH: f32 = 0.01;
+// Levels of detail the terrain is drawn at, keep in sync with LODS in terrain.rs
+const LODS: i32 = 4;
+
+// Coordinates outside the grid read the nearest edge cell
+fn index(x: i32, z: i32) -> u32 {
+    let n = i32(params.resolution);
+    return u32(clamp(z, 0, n - 1) * n + clamp(x, 0, n - 1));
+}
+
+fn inside(x: i32, z: i32) -> bool {
+    let n = i32(params.resolution);
+    return x >= 0 && z >= 0 && x < n && z < n;
+}
+
+fn surface(x: i32, z: i32) -> f32 {
+    let cell = src[index(x, z)];
+    return cell.height + cell.water;
+}
+
+// Nothing flows in from outside the grid
+fn flux_at(x: i32, z: i32) -> vec4<f32> {
+    if !inside(x, z) {
+        return vec4<f32>(0.0);
+    }
+    return flux[index(x, z)];
+}
+
+// The last LOD the vertex is part of, and how far up it slides to lie on the next LOD's
+// triangles, like Heightmap::morph() in terrain.rs
+fn lod_morph(x: i32, z: i32) -> vec2<f32> {
+    var lod = 0;
+    while lod < LODS - 1 && ((x | z) & (1 << u32(lod))) == 0 {
+        lod += 1;
+    }
+    if lod == LODS - 1 {
+        return vec2<f32>(f32(lod), 0.0);
+    }
+    let step = 1 << u32(lod);
+    let coarse = 2 * step;
+    var ends: f32;
+    // halfway along the next LOD's edge, or its quad's diagonal from +x to +z
+    if x % coarse != 0 && z % coarse == 0 {
+        ends = src[index(x - step, z)].height + src[index(x + step, z)].height;
+    } else if x % coarse == 0 {
+        ends = src[index(x, z - step)].height + src[index(x, z + step)].height;
+    } else {
+        ends = src[index(x + step, z - step)].height + src[index(x - step, z + step)].height;
+    }
+    return vec2<f32>(f32(lod), ends / 2.0 - src[index(x, z)].height);
+}
+
+fn height_gradient(x: i32, z: i32) -> vec2<f32> {
+    let dx = src[index(x + 1, z)].height - src[index(x - 1, z)].height;
+    let dz = src[index(x, z + 1)].height - src[index(x, z - 1)].height;
+    return vec2<f32>(dx, dz) / (2.0 * params.cell_size);
+}
+
+// Water pressure pushes more water through the virtual pipes to each lower neighbour
+@compute @workgroup_size(8, 8)
+fn cs_flux(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= params.resolution || id.y >= params.resolution {
+        return;
+    }
+    let x = i32(id.x);
+    let z = i32(id.y);
+    let i = index(x, z);
+    let here = surface(x, z);
+    let below = vec4<f32>(surface(x - 1, z), surface(x + 1, z), surface(x, z - 1), surface(x, z + 1));
+    var out = max(flux[i] + params.dt * params.pipe * (here - below), vec4<f32>(0.0));
+
+    // the edges are walls
+    let last = i32(params.resolution) - 1;
+    out *= vec4<f32>(f32(x > 0), f32(x < last), f32(z > 0), f32(z < last));
+
+    // never send away more water than the cell holds
+    let total = (out.x + out.y + out.z + out.w) * params.dt;
+    let water = src[i].water;
+    if total > water {
+        out *= water / total;
+    }
+    flux[i] = out;
+}
+
+// Share of `water` that flows out this step
+fn leaving(water: f32, outflow: f32) -> f32 {
+    if water <= 0.0 {
+        return 0.0;
+    }
+    return min(outflow * params.dt / water, 1.0);
+}
+
+// Sediment arriving from the cell at x, z along with `towards_here` of its flux
+fn sediment_from(x: i32, z: i32, towards_here: f32) -> f32 {
+    if !inside(x, z) {
+        return 0.0;
+    }
+    let cell = src[index(x, z)];
+    return cell.sediment * leaving(cell.water, towards_here);
+}
+
+// Moves the water and the sediment in it, then dissolves or deposits sediment depending on how much the flow here
+// can carry
+@compute @workgroup_size(8, 8)
+fn cs_water(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= params.resolution || id.y >= params.resolution {
+        return;
+    }
+    let x = i32(id.x);
+    let z = i32(id.y);
+    let i = index(x, z);
+    let cell = src[i];
+    let out = flux[i];
+    let left = flux_at(x - 1, z);
+    let right = flux_at(x + 1, z);
+    let back = flux_at(x, z - 1);
+    let front = flux_at(x, z + 1);
+
+    let inflow = left.y + right.x + back.w + front.z;
+    let outflow = out.x + out.y + out.z + out.w;
+    let water = max(cell.water + params.dt * (inflow - outflow), 0.0);
+
+    // sediment travels with the water, whatever share of a cell's water leaves it takes the
+    // same share of its sediment along, so none is lost or made up on the way
+    var sediment = cell.sediment * (1.0 - leaving(cell.water, outflow))
+        + sediment_from(x - 1, z, left.y)
+        + sediment_from(x + 1, z, right.x)
+        + sediment_from(x, z - 1, back.w)
+        + sediment_from(x, z + 1, front.z);
+
+    // the average flow through the cell over the average depth
+    let mean_water = max((cell.water + water) * 0.5, 0.0001);
+    let through = vec2<f32>(left.y - out.x + out.y - right.x, back.w - out.z + out.w - front.z) * 0.5;
+    var velocity = through / mean_water;
+    let speed = length(velocity);
+    if speed > MAX_SPEED {
+        velocity *= MAX_SPEED / speed;
+    }
+
+    // sine of the slope angle, steep fast deep water carries the most
+    let gradient = height_gradient(x, z);
+    let tilt = max(length(gradient) / sqrt(1.0 + dot(gradient, gradient)), MIN_TILT);
+    let capacity = params.capacity * tilt * length(velocity) * saturate(water / FULL_DEPTH);
+
+    var height = cell.height;
+    if capacity > sediment {
+        let amount = params.dissolve * (capacity - sediment) * params.dt;
+        height -= amount;
+        sediment += amount;
+    } else {
+        let amount = params.deposit * (sediment - capacity) * params.dt;
+        height += amount;
+        sediment -= amount;
+    }
+
+    dst[i] = Cell(
+        height,
+        water * (1.0 - params.evaporation * params.dt),
+        sediment,
+        0.0,
+    );
+}
+
+// Lets too steep slopes crumble and rains on everything for the next iteration
+@compute @workgroup_size(8, 8)
+fn cs_transport(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= params.resolution || id.y >= params.resolution {
+        return;
+    }
+    let x = i32(id.x);
+    let z = i32(id.y);
+    let i = index(x, z);
+    let cell = src[i];
+
+    // each pair of neighbours trades the same amount in opposite directions, so material
+    // is only moved around
+    var height = cell.height;
+    // diagonals are further away and tolerate a bigger difference
+    var offsets = array<vec2<i32>, 8>(
+        vec2<i32>(-1, 0), vec2<i32>(1, 0), vec2<i32>(0, -1), vec2<i32>(0, 1),
+        vec2<i32>(-1, -1), vec2<i32>(1, -1), vec2<i32>(-1, 1), vec2<i32>(1, 1),
+    );
+    for (var n = 0; n < 8; n += 1) {
+        let neighbour = vec2<i32>(x, z) + offsets[n];
+        if !inside(neighbour.x, neighbour.y) {
+            continue;
+        }
+        let difference = src[index(neighbour.x, neighbour.y)].height - cell.height;
+        let excess = abs(difference) - params.talus * length(vec2<f32>(offsets[n]));
+        if excess > 0.0 {
+            height += sign(difference) * excess * params.thermal * params.dt;
+        }
+    }
+
+    dst[i] = Cell(
+        height,
+        cell.water + params.rain * params.dt,
+        cell.sediment,
+        0.0,
+    );
+}
+
+// Writes position, normal, uv and tangent of every grid vertex
+@compute @workgroup_size(8, 8)
+fn cs_mesh(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= params.resolution || id.y >= params.resolution {
+        return;
+    }
+    let x = i32(id.x);
+    let z = i32(id.y);
+    let i = index(x, z);
+    let position = vec3<f32>(
+        f32(x) * params.cell_size - params.half_extent,
+        src[i].height,
+        f32(z) * params.cell_size - params.half_extent,
+    );
+    // not a texture coordinate, see model_position() in common.wgsl
+    let uv = lod_morph(x, z);
+    let gradient = height_gradient(x, z);
+    let normal = normalize(vec3<f32>(-gradient.x, 1.0, -gradient.y));
+    let tangent = normalize(vec3<f32>(1.0, gradient.x, 0.0));
+
+    let base = i * 12u;
+    vertices[base] = position.x;
+    vertices[base + 1u] = position.y;
+    vertices[base + 2u] = position.z;
+    vertices[base + 3u] = normal.x;
+    vertices[base + 4u] = normal.y;
+    vertices[base + 5u] = normal.z;
+    vertices[base + 6u] = uv.x;
+    vertices[base + 7u] = uv.y;
+    vertices[base + 8u] = tangent.x;
+    vertices[base + 9u] = tangent.y;
+    vertices[base + 10u] = tangent.z;
+    // v runs along +z, which is cross(tangent, normal)
+    vertices[base + 11u] = -1.0;
+}
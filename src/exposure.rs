@@ -0,0 +1,284 @@
+use wgpu::{CommandEncoder, Device, Queue};
+
+use crate::{gpu_memory::Tracked, hdr::HdrPipeline};
+
+const HISTOGRAM_BINS: u64 = 256;
+const WORKGROUP_SIZE: u32 = 16;
+
+// Aperture / shutter / ISO triangle of a real camera
+#[derive(Debug, Clone, Copy)]
+pub struct PhysicalCamera {
+  // f-stop, e.g. 1.4, 2.8, 16
+  pub aperture: f32,
+  // seconds
+  pub shutter_speed: f32,
+  pub iso: f32,
+}
+
+impl Default for PhysicalCamera {
+  // roughly exposure 1.0, which suits a scene with colors in the 0..1 range
+  fn default() -> Self {
+    Self {
+      aperture: 1.4,
+      shutter_speed: 1.0 / 2.0,
+      iso: 400.0,
+    }
+  }
+}
+
+impl PhysicalCamera {
+  pub fn ev100(&self) -> f32 {
+    ((self.aperture * self.aperture) / self.shutter_speed * 100.0 / self.iso).log2()
+  }
+}
+
+// Scale applied to scene luminance for a given EV100 (Lagarde & de Rousiers, Moving Frostbite to PBR)
+pub fn exposure_from_ev100(ev100: f32) -> f32 {
+  1.0 / (1.2 * 2f32.powf(ev100))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExposureMode {
+  // EV100 comes from the physical camera settings
+  Manual,
+  // EV100 is metered from a luminance histogram of the hdr target
+  Auto,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ExposureSettings {
+  pub mode: ExposureMode,
+  pub camera: PhysicalCamera,
+  // EV offset, positive values brighten the image
+  pub compensation: f32,
+  // histogram range in log2 luminance
+  pub min_log_luminance: f32,
+  pub max_log_luminance: f32,
+  // higher adapts faster
+  pub adaptation_speed: f32,
+}
+
+impl Default for ExposureSettings {
+  fn default() -> Self {
+    Self {
+      mode: ExposureMode::Manual,
+      camera: PhysicalCamera::default(),
+      compensation: 0.0,
+      min_log_luminance: -8.0,
+      max_log_luminance: 4.0,
+      adaptation_speed: 1.5,
+    }
+  }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct ExposureParams {
+  min_log_luminance: f32,
+  log_luminance_range: f32,
+  adaptation: f32,
+  compensation: f32,
+}
+
+pub struct Exposure {
+  settings: ExposureSettings,
+  params_buffer: Tracked<wgpu::Buffer>,
+  histogram_buffer: Tracked<wgpu::Buffer>,
+  layout: wgpu::BindGroupLayout,
+  bind_group: wgpu::BindGroup,
+  histogram_pipeline: wgpu::ComputePipeline,
+  average_pipeline: wgpu::ComputePipeline,
+}
+
+impl Exposure {
+  pub fn new(device: &Device, queue: &Queue, hdr: &HdrPipeline) -> Self {
+    let settings = ExposureSettings::default();
+
+    let params_buffer = Tracked::buffer(device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Exposure Params Buffer"),
+      size: std::mem::size_of::<ExposureParams>() as u64,
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    }));
+    let histogram_buffer = Tracked::buffer(device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Luminance Histogram Buffer"),
+      size: HISTOGRAM_BINS * 4,
+      usage: wgpu::BufferUsages::STORAGE,
+      mapped_at_creation: false,
+    }));
+
+    let compute_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility: wgpu::ShaderStages::COMPUTE,
+      ty,
+      count: None,
+    };
+    let storage = wgpu::BindingType::Buffer {
+      ty: wgpu::BufferBindingType::Storage { read_only: false },
+      has_dynamic_offset: false,
+      min_binding_size: None,
+    };
+    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Exposure Bind Group Layout"),
+      entries: &[
+        compute_entry(
+          0,
+          wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+          },
+        ),
+        compute_entry(1, storage),
+        compute_entry(
+          2,
+          wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+          },
+        ),
+        compute_entry(3, storage),
+      ],
+    });
+    let bind_group = create_bind_group(device, &layout, hdr, &histogram_buffer, &params_buffer);
+
+    let shader = device.create_shader_module(wgpu::include_wgsl!("exposure.wgsl"));
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Exposure Pipeline Layout"),
+      bind_group_layouts: &[&layout],
+      push_constant_ranges: &[],
+    });
+    let histogram_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+      label: Some("Luminance Histogram Pipeline"),
+      layout: Some(&pipeline_layout),
+      module: &shader,
+      entry_point: "build_histogram",
+    });
+    let average_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+      label: Some("Luminance Average Pipeline"),
+      layout: Some(&pipeline_layout),
+      module: &shader,
+      entry_point: "average_histogram",
+    });
+
+    let exposure = exposure_from_ev100(settings.camera.ev100());
+    queue.write_buffer(
+      hdr.exposure_buffer(),
+      0,
+      bytemuck::cast_slice(&[exposure, 1.0]),
+    );
+
+    Self {
+      settings,
+      params_buffer,
+      histogram_buffer,
+      layout,
+      bind_group,
+      histogram_pipeline,
+      average_pipeline,
+    }
+  }
+
+  // The hdr texture is recreated on resize so the bind group has to follow
+  pub fn resize(&mut self, device: &Device, hdr: &HdrPipeline) {
+    self.bind_group = create_bind_group(
+      device,
+      &self.layout,
+      hdr,
+      &self.histogram_buffer,
+      &self.params_buffer,
+    );
+  }
+
+  pub fn settings(&self) -> &ExposureSettings {
+    &self.settings
+  }
+
+  pub fn settings_mut(&mut self) -> &mut ExposureSettings {
+    &mut self.settings
+  }
+
+  pub fn toggle_mode(&mut self) {
+    self.settings.mode = match self.settings.mode {
+      ExposureMode::Manual => ExposureMode::Auto,
+      ExposureMode::Auto => ExposureMode::Manual,
+    };
+    log::info!("exposure mode: {:?}", self.settings.mode);
+  }
+
+  // Uploads this frame's parameters, `dt` is in seconds and drives eye adaptation
+  pub fn update(&self, queue: &Queue, hdr: &HdrPipeline, dt: f32) {
+    let settings = &self.settings;
+    match settings.mode {
+      ExposureMode::Manual => {
+        let ev100 = settings.camera.ev100() - settings.compensation;
+        queue.write_buffer(
+          hdr.exposure_buffer(),
+          0,
+          bytemuck::bytes_of(&exposure_from_ev100(ev100)),
+        );
+      }
+      ExposureMode::Auto => {
+        let params = ExposureParams {
+          min_log_luminance: settings.min_log_luminance,
+          log_luminance_range: settings.max_log_luminance - settings.min_log_luminance,
+          adaptation: (1.0 - (-dt * settings.adaptation_speed).exp()).clamp(0.0, 1.0),
+          compensation: settings.compensation,
+        };
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::bytes_of(&params));
+      }
+    }
+  }
+
+  // Meters the hdr target, must run after the scene passes and before tonemapping
+  pub fn meter(&self, encoder: &mut CommandEncoder, hdr: &HdrPipeline) {
+    if self.settings.mode != ExposureMode::Auto {
+      return;
+    }
+    let (width, height) = hdr.size();
+    let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+      label: Some("Auto Exposure Pass"),
+    });
+    pass.set_bind_group(0, &self.bind_group, &[]);
+    pass.set_pipeline(&self.histogram_pipeline);
+    pass.dispatch_workgroups(
+      width.div_ceil(WORKGROUP_SIZE),
+      height.div_ceil(WORKGROUP_SIZE),
+      1,
+    );
+    pass.set_pipeline(&self.average_pipeline);
+    pass.dispatch_workgroups(1, 1, 1);
+  }
+}
+
+fn create_bind_group(
+  device: &Device,
+  layout: &wgpu::BindGroupLayout,
+  hdr: &HdrPipeline,
+  histogram_buffer: &wgpu::Buffer,
+  params_buffer: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+  device.create_bind_group(&wgpu::BindGroupDescriptor {
+    label: Some("Exposure Bind Group"),
+    layout,
+    entries: &[
+      wgpu::BindGroupEntry {
+        binding: 0,
+        resource: wgpu::BindingResource::TextureView(hdr.view()),
+      },
+      wgpu::BindGroupEntry {
+        binding: 1,
+        resource: histogram_buffer.as_entire_binding(),
+      },
+      wgpu::BindGroupEntry {
+        binding: 2,
+        resource: params_buffer.as_entire_binding(),
+      },
+      wgpu::BindGroupEntry {
+        binding: 3,
+        resource: hdr.exposure_buffer().as_entire_binding(),
+      },
+    ],
+  })
+}
@@ -0,0 +1,95 @@
+// Histogram based auto exposure, the average is written straight into the tonemapper's exposure buffer
+
+struct Params {
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    // how far to move towards the new average this frame, 0..1
+    adaptation: f32,
+    // EV offset applied on top of the metered value
+    compensation: f32,
+};
+
+struct Exposure {
+    exposure: f32,
+    avg_luminance: f32,
+};
+
+const BINS: u32 = 256u;
+
+@group(0) @binding(0)
+var hdr_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var<storage, read_write> histogram: array<atomic<u32>, 256>;
+@group(0) @binding(2)
+var<uniform> params: Params;
+@group(0) @binding(3)
+var<storage, read_write> exposure: Exposure;
+
+var<workgroup> local_bins: array<atomic<u32>, 256>;
+var<workgroup> weighted_bins: array<u32, 256>;
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+// bin 0 is reserved for (near) black pixels so they don't drag the average down
+fn bin_index(color: vec3<f32>) -> u32 {
+    let lum = luminance(color);
+    if lum < 0.005 {
+        return 0u;
+    }
+    let log_lum = clamp((log2(lum) - params.min_log_luminance) / params.log_luminance_range, 0.0, 1.0);
+    return u32(log_lum * 254.0 + 1.0);
+}
+
+@compute @workgroup_size(16, 16)
+fn build_histogram(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+) {
+    atomicStore(&local_bins[local_index], 0u);
+    workgroupBarrier();
+
+    let dimensions = vec2<u32>(textureDimensions(hdr_texture));
+    if global_id.x < dimensions.x && global_id.y < dimensions.y {
+        let color = textureLoad(hdr_texture, vec2<i32>(global_id.xy), 0).rgb;
+        atomicAdd(&local_bins[bin_index(color)], 1u);
+    }
+    workgroupBarrier();
+
+    atomicAdd(&histogram[local_index], atomicLoad(&local_bins[local_index]));
+}
+
+@compute @workgroup_size(256)
+fn average_histogram(@builtin(local_invocation_index) local_index: u32) {
+    let count = atomicLoad(&histogram[local_index]);
+    weighted_bins[local_index] = count * local_index;
+    workgroupBarrier();
+
+    // clear for the next frame
+    atomicStore(&histogram[local_index], 0u);
+
+    for (var cutoff = BINS >> 1u; cutoff > 0u; cutoff = cutoff >> 1u) {
+        if local_index < cutoff {
+            weighted_bins[local_index] += weighted_bins[local_index + cutoff];
+        }
+        workgroupBarrier();
+    }
+
+    if local_index == 0u {
+        let dimensions = vec2<u32>(textureDimensions(hdr_texture));
+        let pixels = f32(dimensions.x * dimensions.y);
+        // `count` is the black bin here, leave it out of the average
+        let lit_pixels = max(pixels - f32(count), 1.0);
+        let weighted_log_average = f32(weighted_bins[0]) / lit_pixels - 1.0;
+        let log_average = weighted_log_average / 254.0 * params.log_luminance_range + params.min_log_luminance;
+        let avg_luminance = exp2(log_average);
+
+        let adapted = exposure.avg_luminance + (avg_luminance - exposure.avg_luminance) * params.adaptation;
+        exposure.avg_luminance = adapted;
+
+        // saturation based metering: EV100 = log2(L * S / K) with S = 100 and K = 12.5
+        let ev100 = log2(max(adapted, 0.0001) * 100.0 / 12.5) - params.compensation;
+        exposure.exposure = 1.0 / (1.2 * exp2(ev100));
+    }
+}
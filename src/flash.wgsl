@@ -0,0 +1,15 @@
+// The latency probe's flash: as bright as the target goes, so a photodiode taped to the
+// screen sees the edge clearly
+
+// One triangle that covers the whole screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> @builtin(position) vec4<f32> {
+    let x = f32(i32(in_vertex_index & 1u) * 4 - 1);
+    let y = f32(i32(in_vertex_index >> 1u) * 4 - 1);
+    return vec4<f32>(x, y, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return vec4<f32>(1.0);
+}
@@ -0,0 +1,196 @@
+// Height fog with light shafts, raymarched per pixel over the finished scene. Every view ray
+// is stepped from the camera to the depth buffer through fog that thins out exponentially
+// with height. Each step looks the point up in the sun's shadow map, so the sunlight the fog
+// scatters towards the camera is cut off behind whatever casts a shadow.
+
+use serde::{Deserialize, Serialize};
+use wgpu::{util::DeviceExt, BindGroup, BindGroupLayout, CommandEncoder, Device, Queue};
+
+use crate::{gpu_memory::Tracked, hdr::HdrPipeline, pipeline::fog_pipe, stats::count_draw};
+
+// The `[fog]` section of the config file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FogSettings {
+  // whether it's shown when the app starts
+  pub enabled: bool,
+  // extinction per world unit at `base_height`
+  pub density: f32,
+  // how fast it thins out going up, the density halves every ln(2) / falloff units
+  pub height_falloff: f32,
+  pub base_height: f32,
+  // linear rgb albedo, the share of the light it scatters
+  pub color: [f32; 3],
+  // multiplies the sunlight scattered into the shafts
+  pub scattering: f32,
+  // Henyey-Greenstein g, above 0 the fog glows brighter looking towards the sun
+  pub anisotropy: f32,
+  // raymarch steps per pixel
+  pub steps: u32,
+  // world units rays go at most, the sky is fogged as if it were this far
+  pub max_distance: f32,
+}
+
+impl Default for FogSettings {
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      density: 0.04,
+      height_falloff: 0.5,
+      base_height: 0.0,
+      color: [0.8, 0.85, 0.9],
+      scattering: 1.0,
+      anisotropy: 0.6,
+      steps: 32,
+      max_distance: 60.0,
+    }
+  }
+}
+
+// Matches `FogParams` in fog.wgsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct FogUniform {
+  // rgb: albedo, a: density
+  color: [f32; 4],
+  // x: height falloff, y: base height, z: max distance, w: anisotropy
+  shape: [f32; 4],
+  // x: steps, y: scattering
+  march: [f32; 4],
+}
+
+impl FogUniform {
+  fn new(settings: &FogSettings) -> Self {
+    let [r, g, b] = settings.color;
+    Self {
+      color: [r, g, b, settings.density],
+      shape: [
+        settings.height_falloff,
+        settings.base_height,
+        settings.max_distance,
+        settings.anisotropy.clamp(-0.99, 0.99),
+      ],
+      march: [settings.steps.max(1) as f32, settings.scattering, 0.0, 0.0],
+    }
+  }
+}
+
+pub struct FogRenderer {
+  settings: FogSettings,
+  buffer: Tracked<wgpu::Buffer>,
+  layout: BindGroupLayout,
+  pipeline: wgpu::RenderPipeline,
+}
+
+impl FogRenderer {
+  // `globals_layout` and `shadow_layout` are groups 0 and 1 of the scene pipeline
+  pub fn new(
+    device: &Device,
+    format: wgpu::TextureFormat,
+    globals_layout: &BindGroupLayout,
+    shadow_layout: &BindGroupLayout,
+  ) -> Self {
+    let settings = FogSettings::default();
+    let buffer = Tracked::buffer(
+      device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Fog Buffer"),
+        contents: bytemuck::bytes_of(&FogUniform::new(&settings)),
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+      }),
+    );
+    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Fog Layout"),
+      entries: &[
+        wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+          },
+          count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+          binding: 1,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+          },
+          count: None,
+        },
+      ],
+    });
+    Self {
+      settings,
+      buffer,
+      pipeline: fog_pipe(device, format, &[globals_layout, shadow_layout, &layout]),
+      layout,
+    }
+  }
+
+  pub fn is_enabled(&self) -> bool {
+    self.settings.enabled
+  }
+
+  pub fn toggle(&mut self) -> bool {
+    self.settings.enabled = !self.settings.enabled;
+    self.settings.enabled
+  }
+
+  pub fn settings(&self) -> &FogSettings {
+    &self.settings
+  }
+
+  pub fn set_settings(&mut self, queue: &Queue, settings: &FogSettings) {
+    self.settings = settings.clone();
+    let uniform = FogUniform::new(&self.settings);
+    queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&uniform));
+  }
+
+  // Fogs `hdr`'s color by its depth. `globals` and `shadow` are the scene's groups 0 and 1.
+  pub fn render(
+    &self,
+    device: &Device,
+    encoder: &mut CommandEncoder,
+    hdr: &HdrPipeline,
+    globals: &BindGroup,
+    shadow: &BindGroup,
+  ) {
+    // made per frame since the depth belongs to whichever viewport is drawing
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Fog Bind Group"),
+      layout: &self.layout,
+      entries: &[
+        wgpu::BindGroupEntry {
+          binding: 0,
+          resource: self.buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+          binding: 1,
+          resource: wgpu::BindingResource::TextureView(hdr.depth_sample_view()),
+        },
+      ],
+    });
+    let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+      label: Some("Fog Pass"),
+      color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+        view: hdr.view(),
+        resolve_target: None,
+        ops: wgpu::Operations {
+          load: wgpu::LoadOp::Load,
+          store: true,
+        },
+      })],
+      depth_stencil_attachment: None,
+    });
+    pass.set_pipeline(&self.pipeline);
+    pass.set_bind_group(0, globals, &[]);
+    pass.set_bind_group(1, shadow, &[]);
+    pass.set_bind_group(2, &bind_group, &[]);
+    count_draw();
+    pass.draw(0..3, 0..1);
+  }
+}
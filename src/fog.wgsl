@@ -0,0 +1,101 @@
+// Height fog and light shafts, see fog.rs. One fullscreen triangle marches every pixel's
+// view ray up to the depth buffer and outputs the light the fog scattered into it in rgb and
+// how much of the scene still shows through in a, which the blend state multiplies the scene
+// color by.
+
+// the sun, its shadow map and the globals
+#include "lighting.wgsl"
+
+// keep in sync with FogUniform in fog.rs
+struct FogParams {
+    // rgb: albedo, a: density at the base height
+    color: vec4<f32>,
+    // x: height falloff, y: base height, z: max distance, w: anisotropy
+    shape: vec4<f32>,
+    // x: steps, y: scattering
+    march: vec4<f32>,
+};
+@group(2) @binding(0)
+var<uniform> fog: FogParams;
+// bound as a plain float texture, GLSL has no textureLoad for depth textures
+@group(2) @binding(1)
+var depth_map: texture_2d<f32>;
+
+const PI: f32 = 3.14159265;
+
+struct FullscreenOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+};
+
+// One triangle that covers the whole screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> FullscreenOutput {
+    var out: FullscreenOutput;
+    let x = f32(i32(in_vertex_index & 1u) * 4 - 1);
+    let y = f32(i32(in_vertex_index >> 1u) * 4 - 1);
+    out.clip_position = vec4<f32>(x, y, 0.0, 1.0);
+    out.ndc = vec2<f32>(x, y);
+    return out;
+}
+
+fn density_at(height: f32) -> f32 {
+    // capped so the exponent can't overflow far below the base height
+    return fog.color.a * exp(min(-fog.shape.x * (height - fog.shape.y), 20.0));
+}
+
+// Henyey-Greenstein, the share of light going `cos_theta` away from where it was heading
+fn phase(cos_theta: f32, g: f32) -> f32 {
+    let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+    return (1.0 - g * g) / (4.0 * PI * denominator * sqrt(denominator));
+}
+
+// One tap of the shadow map, the steps are too many for shadow_factor()'s 3x3
+fn sun_visibility(position: vec3<f32>) -> f32 {
+    let light_position = globals.light_view_proj * vec4<f32>(position, 1.0);
+    let ndc = light_position.xyz / light_position.w;
+    let uv = ndc.xy * vec2<f32>(0.5, -0.5) + 0.5;
+    if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || ndc.z > 1.0 {
+        return 1.0;
+    }
+    return textureSampleCompareLevel(shadow_map, shadow_sampler, uv, ndc.z);
+}
+
+// Jimenez' interleaved gradient noise. Offsets where each pixel's steps start, which turns
+// banding into noise TAA then averages away.
+fn step_offset(pixel: vec2<f32>) -> f32 {
+    return fract(52.9829189 * fract(dot(pixel, vec2<f32>(0.06711056, 0.00583715))));
+}
+
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let depth = textureLoad(depth_map, vec2<i32>(in.clip_position.xy), 0).r;
+    let world = globals.inverse_view_proj * vec4<f32>(in.ndc, depth, 1.0);
+    let camera = globals.camera_position.xyz;
+    let to_surface = world.xyz / world.w - camera;
+    let direction = normalize(to_surface);
+    var distance = min(length(to_surface), fog.shape.z);
+    // nothing drawn here, the sky is at max distance
+    if depth >= 1.0 {
+        distance = fog.shape.z;
+    }
+
+    let steps = u32(fog.march.x);
+    let step = distance / f32(steps);
+    let sun = lighting.sun_color.rgb * phase(dot(direction, lighting.sun_direction.xyz), fog.shape.w)
+        * fog.march.y;
+    var transmittance = 1.0;
+    var scattered = vec3<f32>(0.0);
+    var t = step * step_offset(in.clip_position.xy);
+    for (var i = 0u; i < steps; i += 1u) {
+        let position = camera + direction * t;
+        let density = density_at(position.y);
+        let light = fog.color.rgb * (lighting.ambient.rgb + sun * sun_visibility(position));
+        // the light scattered along the step, integrated against the fog thickening over it
+        let step_transmittance = exp(-density * step);
+        scattered += transmittance * light * (1.0 - step_transmittance);
+        transmittance *= step_transmittance;
+        t += step;
+    }
+    return vec4<f32>(scattered, transmittance);
+}
@@ -0,0 +1,216 @@
+// The passes of one frame with the resources each reads and writes, recorded on request
+// and written out as Graphviz DOT (`dot -Tsvg frame_graph.dot -o frame_graph.svg`) and
+// JSON for anything else that wants to draw it
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphPass {
+  pub name: String,
+  // attachments that are loaded instead of cleared count as read too
+  pub reads: Vec<String>,
+  pub writes: Vec<String>,
+}
+
+// Pass `to` reads `resource` last written by pass `from`, indices into FrameGraph::passes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dependency<'a> {
+  pub from: usize,
+  pub to: usize,
+  pub resource: &'a str,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FrameGraph {
+  passes: Vec<GraphPass>,
+}
+
+impl FrameGraph {
+  // Passes have to be added in the order they're encoded
+  pub fn pass(&mut self, name: &str, reads: &[&str], writes: &[&str]) {
+    let owned = |names: &[&str]| names.iter().map(|n| n.to_string()).collect();
+    self.passes.push(GraphPass {
+      name: name.to_string(),
+      reads: owned(reads),
+      writes: owned(writes),
+    });
+  }
+
+  pub fn passes(&self) -> &[GraphPass] {
+    &self.passes
+  }
+
+  // Every resource in the order it first shows up
+  pub fn resources(&self) -> Vec<&str> {
+    let mut resources: Vec<&str> = Vec::new();
+    for pass in &self.passes {
+      for resource in pass.reads.iter().chain(&pass.writes) {
+        if !resources.contains(&resource.as_str()) {
+          resources.push(resource);
+        }
+      }
+    }
+    resources
+  }
+
+  // Each read paired with the latest earlier write of the same resource, reads of
+  // something nothing wrote this frame (uploads, last frame's data) have none
+  pub fn dependencies(&self) -> Vec<Dependency<'_>> {
+    let mut dependencies = Vec::new();
+    for (to, pass) in self.passes.iter().enumerate() {
+      for resource in &pass.reads {
+        let writer = self.passes[..to]
+          .iter()
+          .rposition(|earlier| earlier.writes.contains(resource));
+        if let Some(from) = writer {
+          dependencies.push(Dependency { from, to, resource });
+        }
+      }
+    }
+    dependencies
+  }
+
+  // Passes as boxes, resources as ellipses with a new one for every write, so a resource
+  // several passes draw into (the hdr color) doesn't turn the graph into a loop
+  pub fn to_dot(&self) -> String {
+    let mut dot = String::from("digraph frame {\n  rankdir=LR;\n");
+    let resources = self.resources();
+    let index = |name: &str| resources.iter().position(|&r| r == name).unwrap_or(0);
+    // latest version of every resource, 0 is whatever it held before the frame
+    let mut versions = vec![0; resources.len()];
+    let mut declared = vec![Vec::new(); resources.len()];
+    let mut node = |dot: &mut String, resource: usize, version: u32| {
+      if !declared[resource].contains(&version) {
+        declared[resource].push(version);
+        *dot += &format!(
+          "  resource{}_{} [shape=ellipse, label={:?}];\n",
+          resource, version, resources[resource]
+        );
+      }
+      format!("resource{}_{}", resource, version)
+    };
+    for (i, pass) in self.passes.iter().enumerate() {
+      dot += &format!(
+        "  pass{} [shape=box, style=filled, fillcolor=lightblue, label={:?}];\n",
+        i, pass.name
+      );
+      for resource in &pass.reads {
+        let r = index(resource);
+        let read = node(&mut dot, r, versions[r]);
+        dot += &format!("  {} -> pass{};\n", read, i);
+      }
+      for resource in &pass.writes {
+        let r = index(resource);
+        versions[r] += 1;
+        let written = node(&mut dot, r, versions[r]);
+        dot += &format!("  pass{} -> {};\n", i, written);
+      }
+    }
+    dot += "}\n";
+    dot
+  }
+
+  pub fn to_json(&self) -> String {
+    let strings = |names: &[String]| {
+      names
+        .iter()
+        .map(|n| json_string(n))
+        .collect::<Vec<_>>()
+        .join(", ")
+    };
+    let passes: Vec<String> = self
+      .passes
+      .iter()
+      .map(|pass| {
+        format!(
+          "    {{\"name\": {}, \"reads\": [{}], \"writes\": [{}]}}",
+          json_string(&pass.name),
+          strings(&pass.reads),
+          strings(&pass.writes)
+        )
+      })
+      .collect();
+    let resources: Vec<String> = self
+      .resources()
+      .iter()
+      .map(|r| format!("    {}", json_string(r)))
+      .collect();
+    let dependencies: Vec<String> = self
+      .dependencies()
+      .iter()
+      .map(|d| {
+        format!(
+          "    {{\"from\": {}, \"to\": {}, \"resource\": {}}}",
+          json_string(&self.passes[d.from].name),
+          json_string(&self.passes[d.to].name),
+          json_string(d.resource)
+        )
+      })
+      .collect();
+    format!(
+      "{{\n  \"passes\": [\n{}\n  ],\n  \"resources\": [\n{}\n  ],\n  \"dependencies\": [\n{}\n  ]\n}}\n",
+      passes.join(",\n"),
+      resources.join(",\n"),
+      dependencies.join(",\n")
+    )
+  }
+}
+
+// `text` as a quoted JSON string, there's no serde_json here
+pub(crate) fn json_string(text: &str) -> String {
+  let mut quoted = String::with_capacity(text.len() + 2);
+  quoted.push('"');
+  for c in text.chars() {
+    match c {
+      '"' => quoted.push_str("\\\""),
+      '\\' => quoted.push_str("\\\\"),
+      '\n' => quoted.push_str("\\n"),
+      c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+      c => quoted.push(c),
+    }
+  }
+  quoted.push('"');
+  quoted
+}
+
+// Records the next frame's graph when asked to, recording is a no-op otherwise
+#[derive(Debug, Default)]
+pub struct FrameGraphRecorder {
+  graph: Option<FrameGraph>,
+  requested: bool,
+}
+
+impl FrameGraphRecorder {
+  // The graph of the next rendered frame comes out of finish_frame()
+  pub fn request(&mut self) {
+    self.requested = true;
+  }
+
+  pub fn begin_frame(&mut self) {
+    if std::mem::take(&mut self.requested) {
+      self.graph = Some(FrameGraph::default());
+    }
+  }
+
+  pub fn pass(&mut self, name: &str, reads: &[&str], writes: &[&str]) {
+    if let Some(graph) = &mut self.graph {
+      graph.pass(name, reads, writes);
+    }
+  }
+
+  pub fn finish_frame(&mut self) -> Option<FrameGraph> {
+    self.graph.take()
+  }
+}
+
+// Writes frame_graph.dot and frame_graph.json to the working directory, or logs them where
+// there's no file system to write to (the web)
+pub fn save_to_files(graph: &FrameGraph) {
+  for (path, contents) in [
+    ("frame_graph.dot", graph.to_dot()),
+    ("frame_graph.json", graph.to_json()),
+  ] {
+    match std::fs::write(path, &contents) {
+      Ok(()) => log::info!("saved {}", path),
+      Err(e) => log::warn!("couldn't write {} ({}):\n{}", path, e, contents),
+    }
+  }
+}
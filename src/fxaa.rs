@@ -0,0 +1,167 @@
+use wgpu::{CommandEncoder, Device, TextureFormat, TextureView};
+
+use crate::{
+  gpu_memory::Tracked, reflection::ShaderReflection, sampler::SamplerSettings, stats::count_draw,
+};
+
+// Post process anti-aliasing: the tonemapper draws into input_view() instead of the surface
+// and render() smooths that onto the surface. Cheaper than multisampling and works on WebGL.
+pub struct Fxaa {
+  view: Tracked<TextureView>,
+  sampler: wgpu::Sampler,
+  layout: wgpu::BindGroupLayout,
+  bind_group: wgpu::BindGroup,
+  shader: wgpu::ShaderModule,
+  pipeline_layout: wgpu::PipelineLayout,
+  pipeline: wgpu::RenderPipeline,
+  // of the surface, the input and the output share it
+  format: TextureFormat,
+}
+
+impl Fxaa {
+  pub fn new(device: &Device, format: TextureFormat, width: u32, height: u32) -> Self {
+    let view = create_view(device, format, width, height);
+    let sampler =
+      device.create_sampler(&SamplerSettings::linear().descriptor(Some("Fxaa Sampler")));
+    // the input texture and its sampler, as fxaa.wgsl declares them
+    let layout = ShaderReflection::from_wgsl(include_str!("fxaa.wgsl"))
+      .expect("fxaa.wgsl doesn't reflect")
+      .create_bind_group_layout(device, 0, "Fxaa Bind Group Layout");
+    let bind_group = create_bind_group(device, &layout, &view, &sampler);
+
+    let shader = device.create_shader_module(wgpu::include_wgsl!("fxaa.wgsl"));
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Fxaa Pipeline Layout"),
+      bind_group_layouts: &[&layout],
+      push_constant_ranges: &[],
+    });
+    let pipeline = create_pipeline(device, &pipeline_layout, &shader, format);
+
+    Self {
+      view,
+      sampler,
+      layout,
+      bind_group,
+      shader,
+      pipeline_layout,
+      pipeline,
+      format,
+    }
+  }
+
+  pub fn resize(&mut self, device: &Device, width: u32, height: u32) {
+    self.view = create_view(device, self.format, width, height);
+    self.bind_group = create_bind_group(device, &self.layout, &self.view, &self.sampler);
+  }
+
+  // Follows the surface to a new format, the input texture is recreated at `width` x `height`
+  pub fn set_format(&mut self, device: &Device, format: TextureFormat, width: u32, height: u32) {
+    if format == self.format {
+      return;
+    }
+    self.format = format;
+    self.pipeline = create_pipeline(device, &self.pipeline_layout, &self.shader, format);
+    self.resize(device, width, height);
+  }
+
+  // Where the tonemapper draws while fxaa is on
+  pub fn input_view(&self) -> &TextureView {
+    &self.view
+  }
+
+  // Anti-aliases input_view() onto `output`, normally the surface texture
+  pub fn render(&self, encoder: &mut CommandEncoder, output: &TextureView) {
+    let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+      label: Some("Fxaa Pass"),
+      color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+        view: output,
+        resolve_target: None,
+        ops: wgpu::Operations {
+          load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+          store: true,
+        },
+      })],
+      depth_stencil_attachment: None,
+    });
+    pass.set_pipeline(&self.pipeline);
+    pass.set_bind_group(0, &self.bind_group, &[]);
+    count_draw();
+    pass.draw(0..3, 0..1);
+  }
+}
+
+fn create_pipeline(
+  device: &Device,
+  layout: &wgpu::PipelineLayout,
+  shader: &wgpu::ShaderModule,
+  format: TextureFormat,
+) -> wgpu::RenderPipeline {
+  device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+    label: Some("Fxaa Pipeline"),
+    layout: Some(layout),
+    vertex: wgpu::VertexState {
+      module: shader,
+      entry_point: "vs_main",
+      buffers: &[],
+    },
+    fragment: Some(wgpu::FragmentState {
+      module: shader,
+      entry_point: "fs_main",
+      targets: &[Some(wgpu::ColorTargetState {
+        format,
+        blend: Some(wgpu::BlendState::REPLACE),
+        write_mask: wgpu::ColorWrites::ALL,
+      })],
+    }),
+    primitive: wgpu::PrimitiveState::default(),
+    depth_stencil: None,
+    multisample: wgpu::MultisampleState::default(),
+    multiview: None,
+  })
+}
+
+fn create_view(
+  device: &Device,
+  format: TextureFormat,
+  width: u32,
+  height: u32,
+) -> Tracked<TextureView> {
+  let texture = device.create_texture(&wgpu::TextureDescriptor {
+    label: Some("Fxaa Input Texture"),
+    size: wgpu::Extent3d {
+      width,
+      height,
+      depth_or_array_layers: 1,
+    },
+    mip_level_count: 1,
+    sample_count: 1,
+    dimension: wgpu::TextureDimension::D2,
+    format,
+    usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+    view_formats: &[],
+  });
+  let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+  Tracked::view(&texture, view)
+}
+
+fn create_bind_group(
+  device: &Device,
+  layout: &wgpu::BindGroupLayout,
+  view: &TextureView,
+  sampler: &wgpu::Sampler,
+) -> wgpu::BindGroup {
+  device.create_bind_group(&wgpu::BindGroupDescriptor {
+    label: Some("Fxaa Bind Group"),
+    layout,
+    entries: &[
+      wgpu::BindGroupEntry {
+        binding: 0,
+        resource: wgpu::BindingResource::TextureView(view),
+      },
+      wgpu::BindGroupEntry {
+        binding: 1,
+        resource: wgpu::BindingResource::Sampler(sampler),
+      },
+    ],
+  })
+}
@@ -0,0 +1,70 @@
+// Fast approximate anti-aliasing (Lottes, FXAA 3.11): finds edges from the luma of the
+// tonemapped image and blends along them, one fullscreen pass instead of extra samples
+
+// smallest and relative amount the search direction gets pushed away from zero
+const REDUCE_MIN: f32 = 0.0078125;
+const REDUCE_MUL: f32 = 0.125;
+// furthest the blend reaches along an edge, in pixels
+const SPAN_MAX: f32 = 8.0;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@group(0) @binding(0)
+var color_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var color_sampler: sampler;
+
+// One triangle that covers the whole screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let x = f32(i32(in_vertex_index & 1u) * 4 - 1);
+    let y = f32(i32(in_vertex_index >> 1u) * 4 - 1);
+    out.uv = vec2<f32>(x + 1.0, 1.0 - y) * 0.5;
+    out.clip_position = vec4<f32>(x, y, 0.0, 1.0);
+    return out;
+}
+
+// An sRGB target samples as linear colors, the square root brings them close to the
+// perceptual values the edge thresholds are tuned for
+fn luma(color: vec3<f32>) -> f32 {
+    return sqrt(dot(color, vec3<f32>(0.299, 0.587, 0.114)));
+}
+
+fn sample(uv: vec2<f32>) -> vec3<f32> {
+    return textureSampleLevel(color_texture, color_sampler, uv, 0.0).rgb;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(color_texture));
+    let color = textureSampleLevel(color_texture, color_sampler, in.uv, 0.0);
+    let luma_nw = luma(sample(in.uv + vec2<f32>(-1.0, -1.0) * texel));
+    let luma_ne = luma(sample(in.uv + vec2<f32>(1.0, -1.0) * texel));
+    let luma_sw = luma(sample(in.uv + vec2<f32>(-1.0, 1.0) * texel));
+    let luma_se = luma(sample(in.uv + vec2<f32>(1.0, 1.0) * texel));
+    let luma_m = luma(color.rgb);
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    // along the edge, across the luma gradient of the corners
+    var direction = vec2<f32>(
+        (luma_sw + luma_se) - (luma_nw + luma_ne),
+        (luma_nw + luma_sw) - (luma_ne + luma_se),
+    );
+    let reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+    let scale = 1.0 / (min(abs(direction.x), abs(direction.y)) + reduce);
+    direction = clamp(direction * scale, vec2<f32>(-SPAN_MAX), vec2<f32>(SPAN_MAX)) * texel;
+
+    let near = 0.5 * (sample(in.uv + direction * (1.0 / 3.0 - 0.5)) + sample(in.uv + direction * (2.0 / 3.0 - 0.5)));
+    let far = near * 0.5 + 0.25 * (sample(in.uv - direction * 0.5) + sample(in.uv + direction * 0.5));
+    // the wide blend went past the edge into something else, keep the narrow one
+    let luma_far = luma(far);
+    if luma_far < luma_min || luma_far > luma_max {
+        return vec4<f32>(near, color.a);
+    }
+    return vec4<f32>(far, color.a);
+}
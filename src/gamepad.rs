@@ -0,0 +1,51 @@
+use gilrs::{EventType, Gilrs};
+use winit::event::ElementState;
+
+use crate::keymap::InputMap;
+
+// Gamepads through gilrs. winit doesn't see them, so their events are polled once a frame
+// and go through the same InputMap as keys and mouse buttons.
+pub struct Gamepads {
+  gilrs: Gilrs,
+}
+
+impl Gamepads {
+  // None where gilrs can't get at gamepads, e.g. in a web worker. The ones already plugged
+  // in are told to `input` right away, gilrs only has events for the ones that come later.
+  pub fn new(input: &mut InputMap) -> Option<Self> {
+    let gilrs = match Gilrs::new() {
+      Ok(gilrs) => gilrs,
+      Err(e) => {
+        log::warn!("gamepads disabled: {}", e);
+        return None;
+      }
+    };
+    for (id, gamepad) in gilrs.gamepads() {
+      input.pad_connected(id, gamepad.name().to_string());
+    }
+    Some(Self { gilrs })
+  }
+
+  // The actions the gamepad events since the last call set off, in order
+  pub fn poll(&mut self, input: &mut InputMap) -> Vec<(&'static str, ElementState)> {
+    let mut actions = Vec::new();
+    while let Some(event) = self.gilrs.next_event() {
+      match event.event {
+        EventType::ButtonPressed(button, _) => {
+          actions.extend(input.pad_button(button, ElementState::Pressed))
+        }
+        EventType::ButtonReleased(button, _) => {
+          actions.extend(input.pad_button(button, ElementState::Released))
+        }
+        EventType::AxisChanged(axis, value, _) => actions.extend(input.pad_axis(axis, value)),
+        EventType::Connected => {
+          let name = self.gilrs.gamepad(event.id).name().to_string();
+          input.pad_connected(event.id, name);
+        }
+        EventType::Disconnected => actions.extend(input.pad_disconnected(event.id)),
+        _ => {}
+      }
+    }
+    actions
+  }
+}
@@ -0,0 +1,49 @@
+// First half of the deferred path: the scene meshes write what the lighting needs into the
+// G-buffer targets instead of a color, deferred.wgsl lights them afterwards
+
+#include "lighting.wgsl"
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_normal: vec3<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) material: vec2<f32>,
+    @location(3) world_position: vec3<f32>,
+    @location(4) peak: f32,
+};
+
+// keep in sync with GBUFFER_FORMATS in deferred.rs
+struct GBufferOutput {
+    @location(0) albedo: vec4<f32>,
+    @location(1) normal: vec4<f32>,
+    // x: specular strength, y: shininess / MAX_SHININESS
+    @location(2) material: vec4<f32>,
+};
+
+@vertex
+fn vs_main(vertex: VertexInput, instance: InstanceInput) -> VertexOutput {
+    let model = instance_model(instance);
+    let world = model * vec4<f32>(model_position(vertex, instance), 1.0);
+    var out: VertexOutput;
+    out.clip_position = globals.view_proj * world;
+    // fine as long as the models are only uniformly scaled
+    out.world_normal = (model * vec4<f32>(vertex.normal, 0.0)).xyz;
+    out.color = instance.color;
+    out.material = instance.material.xy;
+    out.world_position = world.xyz;
+    out.peak = instance.material.z;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> GBufferOutput {
+    var out: GBufferOutput;
+    let normal = normalize(in.world_normal);
+    out.albedo = in.color;
+    if in.peak > 0.0 {
+        out.albedo = vec4<f32>(terrain_color(in.world_position, normal, in.peak), in.color.a);
+    }
+    out.normal = vec4<f32>(normal, 0.0);
+    out.material = vec4<f32>(in.material.x, in.material.y / MAX_SHININESS, 0.0, 0.0);
+    return out;
+}
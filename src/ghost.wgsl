@@ -0,0 +1,31 @@
+// Ghosts of where the simulation has its objects at the last fixed tick, drawn over the
+// interpolated meshes to see how far behind the tick rendering is. Only the back faces of the
+// mesh pushed out along their normals: a thin rim while both line up, a bright silhouette
+// sticking out where they don't.
+
+#include "common.wgsl"
+
+// world units the outline sticks out
+const OUTLINE_WIDTH: f32 = 0.02;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(vertex: VertexInput, instance: InstanceInput) -> VertexOutput {
+    let model = instance_model(instance);
+    let normal = normalize((model * vec4<f32>(vertex.normal, 0.0)).xyz);
+    let world = model * vec4<f32>(vertex.position, 1.0) + vec4<f32>(normal * OUTLINE_WIDTH, 0.0);
+    var out: VertexOutput;
+    out.clip_position = globals.view_proj * world;
+    out.color = instance.color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // bright enough to stay visible through the tonemapper
+    return vec4<f32>(in.color.rgb * 4.0, 1.0);
+}
@@ -0,0 +1,101 @@
+use std::{
+  ops::Deref,
+  sync::atomic::{AtomicU64, Ordering},
+};
+
+// What the buffers and textures wrapped in Tracked take right now, for the stats
+static BUFFER_BYTES: AtomicU64 = AtomicU64::new(0);
+static TEXTURE_BYTES: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GpuMemory {
+  pub buffer_bytes: u64,
+  pub texture_bytes: u64,
+}
+
+// Bytes allocated by the live Tracked resources
+pub fn allocated() -> GpuMemory {
+  GpuMemory {
+    buffer_bytes: BUFFER_BYTES.load(Ordering::Relaxed),
+    texture_bytes: TEXTURE_BYTES.load(Ordering::Relaxed),
+  }
+}
+
+// Every mip level and sample, without whatever padding the driver adds
+pub fn texture_bytes(texture: &wgpu::Texture) -> u64 {
+  let info = texture.format().describe();
+  let (block_width, block_height) = info.block_dimensions;
+  let (width, height, layers) = (
+    texture.width(),
+    texture.height(),
+    texture.depth_or_array_layers(),
+  );
+  let level_bytes = |level: u32| {
+    let blocks_x = (width >> level).max(1).div_ceil(block_width as u32) as u64;
+    let blocks_y = (height >> level).max(1).div_ceil(block_height as u32) as u64;
+    // 3D textures shrink in depth too, array layers don't
+    let depth = match texture.dimension() {
+      wgpu::TextureDimension::D3 => (layers >> level).max(1),
+      _ => layers,
+    };
+    blocks_x * blocks_y * depth as u64 * info.block_size as u64
+  };
+  let bytes: u64 = (0..texture.mip_level_count()).map(level_bytes).sum();
+  bytes * texture.sample_count() as u64
+}
+
+// A buffer or texture counted in allocated() until it's dropped. Derefs to the resource, so
+// it goes wherever a &wgpu::Buffer or &wgpu::Texture does.
+#[derive(Debug)]
+pub struct Tracked<T> {
+  resource: T,
+  bytes: u64,
+  counter: &'static AtomicU64,
+}
+
+impl<T> Tracked<T> {
+  fn new(resource: T, bytes: u64, counter: &'static AtomicU64) -> Self {
+    counter.fetch_add(bytes, Ordering::Relaxed);
+    Self {
+      resource,
+      bytes,
+      counter,
+    }
+  }
+}
+
+impl Tracked<wgpu::Buffer> {
+  pub fn buffer(buffer: wgpu::Buffer) -> Self {
+    let bytes = buffer.size();
+    Self::new(buffer, bytes, &BUFFER_BYTES)
+  }
+}
+
+impl Tracked<wgpu::Texture> {
+  pub fn texture(texture: wgpu::Texture) -> Self {
+    let bytes = texture_bytes(&texture);
+    Self::new(texture, bytes, &TEXTURE_BYTES)
+  }
+}
+
+impl Tracked<wgpu::TextureView> {
+  // For owners that only keep a view, it keeps `texture` alive after the texture itself is
+  // dropped. Only one view of a texture should be tracked.
+  pub fn view(texture: &wgpu::Texture, view: wgpu::TextureView) -> Self {
+    Self::new(view, texture_bytes(texture), &TEXTURE_BYTES)
+  }
+}
+
+impl<T> Deref for Tracked<T> {
+  type Target = T;
+
+  fn deref(&self) -> &T {
+    &self.resource
+  }
+}
+
+impl<T> Drop for Tracked<T> {
+  fn drop(&mut self) {
+    self.counter.fetch_sub(self.bytes, Ordering::Relaxed);
+  }
+}
@@ -0,0 +1,85 @@
+// An endless grid on the ground plane like a modelling tool's viewport: every pixel's view
+// ray is intersected with y = 0 and the lines are drawn where it lands, a unit apart with a
+// brighter one every 10. The x axis is red and the z axis blue. Fades out with distance,
+// before the lines get too dense to alias.
+
+#include "common.wgsl"
+
+// world units from the camera where the fade starts and ends
+const FADE_START: f32 = 10.0;
+const FADE_END: f32 = 40.0;
+// pulls the grid's depth a little in front of the ground plane it lies on
+const DEPTH_BIAS: f32 = 0.00001;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+};
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+    @builtin(frag_depth) depth: f32,
+};
+
+// One triangle that covers the whole screen
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
+    let x = f32(i32(in_vertex_index & 1u) * 4 - 1);
+    let y = f32(i32(in_vertex_index >> 1u) * 4 - 1);
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(x, y, 0.0, 1.0);
+    out.ndc = vec2<f32>(x, y);
+    return out;
+}
+
+fn unproject(ndc: vec2<f32>, depth: f32) -> vec3<f32> {
+    let world = globals.inverse_view_proj * vec4<f32>(ndc, depth, 1.0);
+    return world.xyz / world.w;
+}
+
+// 1 on a line `spacing` apart, fading to 0 a pixel away from it. `pixel` is fwidth(coord),
+// taken by the caller since the GL backend would put a derivative here into the vertex
+// shader too.
+fn lines(coord: vec2<f32>, pixel: vec2<f32>, spacing: f32) -> f32 {
+    let distance = abs(fract(coord / spacing - 0.5) - 0.5) * spacing / pixel;
+    return 1.0 - min(min(distance.x, distance.y), 1.0);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    let near = unproject(in.ndc, 0.0);
+    let far = unproject(in.ndc, 1.0);
+    let t = -near.y / (far.y - near.y);
+    let position = near + (far - near) * t;
+    let coord = position.xz;
+
+    // the derivative before anything is discarded
+    let pixel = fwidth(coord);
+    let minor = lines(coord, pixel, 1.0);
+    let major = lines(coord, pixel, 10.0);
+    var color = vec3<f32>(0.35) * minor;
+    color = max(color, vec3<f32>(0.7) * major);
+    var alpha = max(minor * 0.4, major * 0.7);
+    // a line a pixel wide along each axis
+    if abs(coord.y) < pixel.y {
+        color = vec3<f32>(1.0, 0.15, 0.15);
+        alpha = 1.0;
+    }
+    if abs(coord.x) < pixel.x {
+        color = vec3<f32>(0.15, 0.3, 1.0);
+        alpha = 1.0;
+    }
+    let distance = length(position - globals.camera_position.xyz);
+    alpha *= 1.0 - smoothstep(FADE_START, FADE_END, distance);
+
+    // rays that never reach the plane, and the gaps between the lines so the sky behind them
+    // still gets drawn
+    if t < 0.0 || t > 1.0 || alpha < 0.01 {
+        discard;
+    }
+    let clip = globals.view_proj * vec4<f32>(position, 1.0);
+    var out: FragmentOutput;
+    out.color = vec4<f32>(color, alpha);
+    out.depth = max(clip.z / clip.w - DEPTH_BIAS, 0.0);
+    return out;
+}
@@ -0,0 +1,632 @@
+use wgpu::{
+  util::DeviceExt, Adapter, CommandEncoder, Device, SurfaceConfiguration, TextureFormat,
+  TextureView,
+};
+
+use crate::{
+  accessibility::ColorFilter,
+  gpu_memory::Tracked,
+  preprocessor::{preprocess, ShaderDefs},
+  sampler::SamplerSettings,
+  stats::count_draw,
+};
+
+// The scene renders into this float target and gets tonemapped onto the surface
+pub const HDR_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+// Depth of the scene passes, cleared to 1.0 so the skybox can fill whatever is left. The
+// stencil is cleared to 0 with it for effects that mask pixels, like the selection outline.
+pub const DEPTH_FORMAT: TextureFormat = TextureFormat::Depth24PlusStencil8;
+
+// `requested` samples per pixel for the main pass (the `[render]` msaa_samples), or 1 if the
+// hdr and depth formats can't have that many here. 4 works wherever MSAA does, the other
+// counts need TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES. Reading the samples back for the
+// depth resolve needs what compute shaders do, which WebGL doesn't have.
+pub fn msaa_sample_count(adapter: &Adapter, device: &Device, requested: u32) -> u32 {
+  if requested <= 1 {
+    return 1;
+  }
+  let flags = |format: TextureFormat| {
+    if device
+      .features()
+      .contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+    {
+      adapter.get_texture_format_features(format).flags
+    } else {
+      format.describe().guaranteed_format_features.flags
+    }
+  };
+  let supported = flags(HDR_FORMAT).sample_count_supported(requested)
+    && flags(HDR_FORMAT).contains(wgpu::TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE)
+    && flags(DEPTH_FORMAT).sample_count_supported(requested)
+    && adapter
+      .get_downlevel_capabilities()
+      .flags
+      .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS);
+  if !supported {
+    log::warn!("{}x MSAA isn't supported here, drawing without", requested);
+    return 1;
+  }
+  requested
+}
+
+// Depth state for pipelines drawing into the scene passes
+pub fn scene_depth_state() -> wgpu::DepthStencilState {
+  wgpu::DepthStencilState {
+    format: DEPTH_FORMAT,
+    depth_write_enabled: true,
+    // equal passes too, so 2D overlays at the same depth still draw in submission order
+    depth_compare: wgpu::CompareFunction::LessEqual,
+    stencil: wgpu::StencilState::default(),
+    bias: wgpu::DepthBiasState::default(),
+  }
+}
+
+// A stencil that runs `face` for front and back faces alike, comparing against the reference
+// the pass sets with set_stencil_reference(). Writing it needs a pass with stencil_ops, the
+// others only have a read only stencil.
+pub fn stencil_state(face: wgpu::StencilFaceState) -> wgpu::StencilState {
+  wgpu::StencilState {
+    front: face,
+    back: face,
+    read_mask: 0xff,
+    write_mask: 0xff,
+  }
+}
+
+// The main pass's attachments with MSAA on, resolved into the hdr texture and depth after it
+struct MsaaTargets {
+  color: Tracked<TextureView>,
+  depth: Tracked<TextureView>,
+  // the depth aspect of `depth` for the depth resolve to read
+  depth_bind_group: wgpu::BindGroup,
+}
+
+// Writes the nearest sample of each pixel of MsaaTargets::depth into the single sampled depth
+struct DepthResolve {
+  layout: wgpu::BindGroupLayout,
+  pipeline: wgpu::RenderPipeline,
+}
+
+pub struct HdrPipeline {
+  texture: Tracked<wgpu::Texture>,
+  view: TextureView,
+  depth_view: Tracked<TextureView>,
+  // only the depth of `depth_view`, a depth-stencil texture is sampled one aspect at a time
+  depth_sample_view: TextureView,
+  sampler: wgpu::Sampler,
+  // [exposure, avg_luminance], written by the exposure module and read by the tonemapper
+  exposure_buffer: Tracked<wgpu::Buffer>,
+  // mat3x3 the tonemapped color goes through, see set_color_filter()
+  filter_buffer: Tracked<wgpu::Buffer>,
+  layout: wgpu::BindGroupLayout,
+  bind_group: wgpu::BindGroup,
+  shader: wgpu::ShaderModule,
+  pipeline_layout: wgpu::PipelineLayout,
+  pipeline: wgpu::RenderPipeline,
+  // the surface format the tonemapper writes
+  output_format: TextureFormat,
+  // of the main pass, 1 without MSAA
+  sample_count: u32,
+  depth_resolve: Option<DepthResolve>,
+  msaa: Option<MsaaTargets>,
+  width: u32,
+  height: u32,
+}
+
+impl HdrPipeline {
+  // `sample_count` is the main pass's, see msaa_sample_count()
+  pub fn new(device: &Device, config: &SurfaceConfiguration, sample_count: u32) -> Self {
+    let (width, height) = (config.width.max(1), config.height.max(1));
+    let (texture, view) = create_texture(device, width, height);
+    let (depth_view, depth_sample_view) = create_depth_views(device, width, height);
+    let depth_resolve = (sample_count > 1).then(|| DepthResolve::new(device, sample_count));
+    let msaa = depth_resolve
+      .as_ref()
+      .map(|resolve| MsaaTargets::new(device, &resolve.layout, width, height, sample_count));
+
+    let sampler =
+      device.create_sampler(&SamplerSettings::nearest().descriptor(Some("Hdr Sampler")));
+
+    let exposure_buffer = Tracked::buffer(device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Exposure Buffer"),
+      size: 8,
+      usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    }));
+    let filter_buffer = Tracked::buffer(device.create_buffer_init(
+      &wgpu::util::BufferInitDescriptor {
+        label: Some("Color Filter Buffer"),
+        contents: bytemuck::cast_slice(&ColorFilter::None.columns()),
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+      },
+    ));
+
+    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Hdr Bind Group Layout"),
+      entries: &[
+        wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+          },
+          count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+          binding: 1,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+          count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+          binding: 2,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: true },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+          },
+          count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+          binding: 3,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+          },
+          count: None,
+        },
+      ],
+    });
+    let bind_group = create_bind_group(
+      device,
+      &layout,
+      &view,
+      &sampler,
+      &exposure_buffer,
+      &filter_buffer,
+    );
+
+    let shader = device.create_shader_module(wgpu::include_wgsl!("hdr.wgsl"));
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Hdr Pipeline Layout"),
+      bind_group_layouts: &[&layout],
+      push_constant_ranges: &[],
+    });
+    let pipeline = create_pipeline(device, &pipeline_layout, &shader, config.format);
+
+    Self {
+      texture,
+      view,
+      depth_view,
+      depth_sample_view,
+      sampler,
+      exposure_buffer,
+      filter_buffer,
+      layout,
+      bind_group,
+      shader,
+      pipeline_layout,
+      pipeline,
+      output_format: config.format,
+      sample_count,
+      depth_resolve,
+      msaa,
+      width,
+      height,
+    }
+  }
+
+  pub fn resize(&mut self, device: &Device, width: u32, height: u32) {
+    let (texture, view) = create_texture(device, width, height);
+    self.bind_group = create_bind_group(
+      device,
+      &self.layout,
+      &view,
+      &self.sampler,
+      &self.exposure_buffer,
+      &self.filter_buffer,
+    );
+    self.texture = texture;
+    self.view = view;
+    (self.depth_view, self.depth_sample_view) = create_depth_views(device, width, height);
+    if let Some(resolve) = &self.depth_resolve {
+      self.msaa = Some(MsaaTargets::new(
+        device,
+        &resolve.layout,
+        width,
+        height,
+        self.sample_count,
+      ));
+    }
+    self.width = width;
+    self.height = height;
+  }
+
+  // Rebuilds the tonemap pipeline when the surface it draws to changed format
+  pub fn set_output_format(&mut self, device: &Device, format: TextureFormat) {
+    if format == self.output_format {
+      return;
+    }
+    self.pipeline = create_pipeline(device, &self.pipeline_layout, &self.shader, format);
+    self.output_format = format;
+  }
+
+  pub fn output_format(&self) -> TextureFormat {
+    self.output_format
+  }
+
+  pub fn texture(&self) -> &wgpu::Texture {
+    &self.texture
+  }
+
+  // Render target for the scene passes, the main pass's resolved into it with MSAA on
+  pub fn view(&self) -> &TextureView {
+    &self.view
+  }
+
+  // Depth attachment matching view()
+  pub fn depth_view(&self) -> &TextureView {
+    &self.depth_view
+  }
+
+  // Color attachment of the main pass, view() itself without MSAA
+  pub fn scene_view(&self) -> &TextureView {
+    match &self.msaa {
+      Some(msaa) => &msaa.color,
+      None => &self.view,
+    }
+  }
+
+  // Where scene_view() is resolved to, None without MSAA
+  pub fn scene_resolve_target(&self) -> Option<&TextureView> {
+    self.msaa.as_ref().map(|_| &self.view)
+  }
+
+  // Depth attachment matching scene_view(), resolve_depth() brings it into depth_view()
+  pub fn scene_depth_view(&self) -> &TextureView {
+    match &self.msaa {
+      Some(msaa) => &msaa.depth,
+      None => &self.depth_view,
+    }
+  }
+
+  // After the main pass, so the passes after it test against and read what it drew. The
+  // stencil is cleared, it's only used within the main pass. Does nothing without MSAA.
+  pub fn resolve_depth(&self, encoder: &mut CommandEncoder) {
+    let (Some(resolve), Some(msaa)) = (&self.depth_resolve, &self.msaa) else {
+      return;
+    };
+    let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+      label: Some("Depth Resolve Pass"),
+      color_attachments: &[],
+      depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+        view: &self.depth_view,
+        depth_ops: Some(wgpu::Operations {
+          load: wgpu::LoadOp::Clear(1.0),
+          store: true,
+        }),
+        stencil_ops: Some(wgpu::Operations {
+          load: wgpu::LoadOp::Clear(0),
+          store: true,
+        }),
+      }),
+    });
+    pass.set_pipeline(&resolve.pipeline);
+    pass.set_bind_group(0, &msaa.depth_bind_group, &[]);
+    count_draw();
+    pass.draw(0..3, 0..1);
+  }
+
+  // The depth of depth_view() for shaders reading it, like the deferred lighting
+  pub fn depth_sample_view(&self) -> &TextureView {
+    &self.depth_sample_view
+  }
+
+  pub fn exposure_buffer(&self) -> &wgpu::Buffer {
+    &self.exposure_buffer
+  }
+
+  // Color vision simulation applied by tonemap() from the next submit on
+  pub fn set_color_filter(&self, queue: &wgpu::Queue, filter: ColorFilter) {
+    queue.write_buffer(
+      &self.filter_buffer,
+      0,
+      bytemuck::cast_slice(&filter.columns()),
+    );
+  }
+
+  pub fn size(&self) -> (u32, u32) {
+    (self.width, self.height)
+  }
+
+  // Of scene_view() and scene_depth_view(), what pipelines drawn in the main pass need
+  pub fn sample_count(&self) -> u32 {
+    self.sample_count
+  }
+
+  // Tonemaps the hdr target onto `output` (normally the surface texture)
+  pub fn tonemap(&self, encoder: &mut CommandEncoder, output: &TextureView) {
+    let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+      label: Some("Tonemap Pass"),
+      color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+        view: output,
+        resolve_target: None,
+        ops: wgpu::Operations {
+          load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+          store: true,
+        },
+      })],
+      depth_stencil_attachment: None,
+    });
+    pass.set_pipeline(&self.pipeline);
+    pass.set_bind_group(0, &self.bind_group, &[]);
+    count_draw();
+    pass.draw(0..3, 0..1);
+  }
+}
+
+fn create_pipeline(
+  device: &Device,
+  layout: &wgpu::PipelineLayout,
+  shader: &wgpu::ShaderModule,
+  format: TextureFormat,
+) -> wgpu::RenderPipeline {
+  device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+    label: Some("Tonemap Pipeline"),
+    layout: Some(layout),
+    vertex: wgpu::VertexState {
+      module: shader,
+      entry_point: "vs_main",
+      buffers: &[],
+    },
+    fragment: Some(wgpu::FragmentState {
+      module: shader,
+      entry_point: "fs_main",
+      targets: &[Some(wgpu::ColorTargetState {
+        format,
+        blend: Some(wgpu::BlendState::REPLACE),
+        write_mask: wgpu::ColorWrites::ALL,
+      })],
+    }),
+    primitive: wgpu::PrimitiveState::default(),
+    depth_stencil: None,
+    multisample: wgpu::MultisampleState::default(),
+    multiview: None,
+  })
+}
+
+fn create_texture(
+  device: &Device,
+  width: u32,
+  height: u32,
+) -> (Tracked<wgpu::Texture>, TextureView) {
+  let texture = Tracked::texture(device.create_texture(&wgpu::TextureDescriptor {
+    label: Some("Hdr Texture"),
+    size: wgpu::Extent3d {
+      width,
+      height,
+      depth_or_array_layers: 1,
+    },
+    mip_level_count: 1,
+    sample_count: 1,
+    dimension: wgpu::TextureDimension::D2,
+    format: HDR_FORMAT,
+    // TAA copies its resolved frame back in, the water copies it out to refract
+    usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+      | wgpu::TextureUsages::TEXTURE_BINDING
+      | wgpu::TextureUsages::COPY_SRC
+      | wgpu::TextureUsages::COPY_DST,
+    view_formats: &[],
+  }));
+  let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+  (texture, view)
+}
+
+// The attachment and its depth aspect
+fn create_depth_views(
+  device: &Device,
+  width: u32,
+  height: u32,
+) -> (Tracked<TextureView>, TextureView) {
+  let texture = device.create_texture(&wgpu::TextureDescriptor {
+    label: Some("Scene Depth Texture"),
+    size: wgpu::Extent3d {
+      width,
+      height,
+      depth_or_array_layers: 1,
+    },
+    mip_level_count: 1,
+    sample_count: 1,
+    dimension: wgpu::TextureDimension::D2,
+    format: DEPTH_FORMAT,
+    // the deferred lighting pass rebuilds positions from it
+    usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+    view_formats: &[],
+  });
+  let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+  let depth = texture.create_view(&wgpu::TextureViewDescriptor {
+    label: Some("Scene Depth Sample View"),
+    aspect: wgpu::TextureAspect::DepthOnly,
+    ..Default::default()
+  });
+  (Tracked::view(&texture, view), depth)
+}
+
+impl MsaaTargets {
+  fn new(
+    device: &Device,
+    layout: &wgpu::BindGroupLayout,
+    width: u32,
+    height: u32,
+    sample_count: u32,
+  ) -> Self {
+    let texture = |label, format, usage| {
+      device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+          width,
+          height,
+          depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage,
+        view_formats: &[],
+      })
+    };
+    let color = texture(
+      "Hdr Msaa Texture",
+      HDR_FORMAT,
+      wgpu::TextureUsages::RENDER_ATTACHMENT,
+    );
+    let depth = texture(
+      "Scene Msaa Depth Texture",
+      DEPTH_FORMAT,
+      wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+    );
+    let depth_sample_view = depth.create_view(&wgpu::TextureViewDescriptor {
+      label: Some("Scene Msaa Depth Sample View"),
+      aspect: wgpu::TextureAspect::DepthOnly,
+      ..Default::default()
+    });
+    let depth_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Depth Resolve Bind Group"),
+      layout,
+      entries: &[wgpu::BindGroupEntry {
+        binding: 0,
+        resource: wgpu::BindingResource::TextureView(&depth_sample_view),
+      }],
+    });
+    let color_view = color.create_view(&wgpu::TextureViewDescriptor::default());
+    let depth_view = depth.create_view(&wgpu::TextureViewDescriptor::default());
+    Self {
+      color: Tracked::view(&color, color_view),
+      depth: Tracked::view(&depth, depth_view),
+      depth_bind_group,
+    }
+  }
+}
+
+// a plain float texture like the fog's depth, GLSL can't textureLoad a depth texture
+const DEPTH_RESOLVE_ENTRY: wgpu::BindGroupLayoutEntry = wgpu::BindGroupLayoutEntry {
+  binding: 0,
+  visibility: wgpu::ShaderStages::FRAGMENT,
+  ty: wgpu::BindingType::Texture {
+    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+    view_dimension: wgpu::TextureViewDimension::D2,
+    multisampled: true,
+  },
+  count: None,
+};
+
+impl DepthResolve {
+  fn new(device: &Device, sample_count: u32) -> Self {
+    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Depth Resolve Layout"),
+      entries: &[DEPTH_RESOLVE_ENTRY],
+    });
+    let source = depth_resolve_source(sample_count);
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+      label: Some("Depth Resolve Shader"),
+      source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Depth Resolve Pipeline Layout"),
+      bind_group_layouts: &[&layout],
+      push_constant_ranges: &[],
+    });
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+      label: Some("Depth Resolve Pipeline"),
+      layout: Some(&pipeline_layout),
+      vertex: wgpu::VertexState {
+        module: &shader,
+        entry_point: "vs_main",
+        buffers: &[],
+      },
+      fragment: Some(wgpu::FragmentState {
+        module: &shader,
+        entry_point: "fs_main",
+        targets: &[],
+      }),
+      primitive: wgpu::PrimitiveState::default(),
+      depth_stencil: Some(wgpu::DepthStencilState {
+        format: DEPTH_FORMAT,
+        depth_write_enabled: true,
+        depth_compare: wgpu::CompareFunction::Always,
+        stencil: wgpu::StencilState::default(),
+        bias: wgpu::DepthBiasState::default(),
+      }),
+      multisample: wgpu::MultisampleState::default(),
+      multiview: None,
+    });
+    Self { layout, pipeline }
+  }
+}
+
+fn depth_resolve_source(sample_count: u32) -> String {
+  let defs = ShaderDefs::new().value("SAMPLE_COUNT", sample_count);
+  preprocess(include_str!("depth_resolve.wgsl"), &defs)
+    .expect("depth_resolve.wgsl doesn't preprocess")
+}
+
+fn create_bind_group(
+  device: &Device,
+  layout: &wgpu::BindGroupLayout,
+  view: &TextureView,
+  sampler: &wgpu::Sampler,
+  exposure_buffer: &wgpu::Buffer,
+  filter_buffer: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+  device.create_bind_group(&wgpu::BindGroupDescriptor {
+    label: Some("Hdr Bind Group"),
+    layout,
+    entries: &[
+      wgpu::BindGroupEntry {
+        binding: 0,
+        resource: wgpu::BindingResource::TextureView(view),
+      },
+      wgpu::BindGroupEntry {
+        binding: 1,
+        resource: wgpu::BindingResource::Sampler(sampler),
+      },
+      wgpu::BindGroupEntry {
+        binding: 2,
+        resource: exposure_buffer.as_entire_binding(),
+      },
+      wgpu::BindGroupEntry {
+        binding: 3,
+        resource: filter_buffer.as_entire_binding(),
+      },
+    ],
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::reflection::ShaderReflection;
+
+  #[test]
+  fn depth_resolve_shader() {
+    for sample_count in [2, 4, 8] {
+      let source = depth_resolve_source(sample_count);
+      assert!(
+        source.contains(&format!("< {}", sample_count)),
+        "{}",
+        source
+      );
+      let reflection =
+        ShaderReflection::from_wgsl(&source).expect("depth_resolve.wgsl doesn't validate");
+      reflection
+        .check_bind_group(0, &[DEPTH_RESOLVE_ENTRY])
+        .unwrap();
+    }
+  }
+}
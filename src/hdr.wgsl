@@ -0,0 +1,50 @@
+struct Exposure {
+    exposure: f32,
+    avg_luminance: f32,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@group(0) @binding(0)
+var hdr_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var hdr_sampler: sampler;
+@group(0) @binding(2)
+var<storage, read> exposure: Exposure;
+// color vision simulation, the identity unless one is picked
+@group(0) @binding(3)
+var<uniform> color_filter: mat3x3<f32>;
+
+// One triangle that covers the whole screen, no vertex buffer needed
+@vertex
+fn vs_main(
+    @builtin(vertex_index) in_vertex_index: u32,
+) -> VertexOutput {
+    var out: VertexOutput;
+    let x = f32(i32(in_vertex_index & 1u) * 4 - 1);
+    let y = f32(i32(in_vertex_index >> 1u) * 4 - 1);
+    out.uv = vec2<f32>(x + 1.0, 1.0 - y) * 0.5;
+    out.clip_position = vec4<f32>(x, y, 0.0, 1.0);
+    return out;
+}
+
+// Narkowicz 2015, ACES filmic curve fit
+fn aces_tonemap(x: vec3<f32>) -> vec3<f32> {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+    return clamp((x * (a * x + b)) / (x * (c * x + d) + e), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let hdr = textureSample(hdr_texture, hdr_sampler, in.uv);
+    // the surface is sRGB so the output stays linear here
+    let color = color_filter * aces_tonemap(hdr.rgb * exposure.exposure);
+    return vec4<f32>(saturate(color), hdr.a);
+}
@@ -0,0 +1,217 @@
+// Hierarchical Z for occlusion culling. A depth prepass draws what the camera saw last frame
+// into a depth target of its own, then a compute pass halves it level by level into a
+// pyramid where every texel holds the farthest depth of the pixels under it. GpuCulling
+// compares an object's nearest depth against the level where its bounds cover a few texels:
+// if it's farther than everything drawn there, it's hidden.
+
+use wgpu::{CommandEncoder, Device, RenderPass, TextureFormat, TextureView};
+
+use crate::{gpu_memory::Tracked, mipmap::mip_level_count};
+
+// 32 bits, so the pyramid's r32float holds exactly what was drawn
+pub const PREPASS_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+const PYRAMID_FORMAT: TextureFormat = TextureFormat::R32Float;
+// Matches the @workgroup_size of hiz.wgsl
+const WORKGROUP_SIZE: u32 = 8;
+
+pub fn prepass_depth_state() -> wgpu::DepthStencilState {
+  wgpu::DepthStencilState {
+    format: PREPASS_FORMAT,
+    depth_write_enabled: true,
+    depth_compare: wgpu::CompareFunction::LessEqual,
+    stencil: wgpu::StencilState::default(),
+    bias: wgpu::DepthBiasState::default(),
+  }
+}
+
+struct Targets {
+  depth_view: Tracked<TextureView>,
+  // every level, for the culling to pick one
+  pyramid_view: Tracked<TextureView>,
+  // level 0 reads the depth, every other level the one below it
+  bind_groups: Vec<wgpu::BindGroup>,
+  // of each level
+  sizes: Vec<(u32, u32)>,
+}
+
+// The prepass depth and its pyramid, sized like the viewport's hdr target. Level 0 is half
+// the depth's size.
+pub struct HiZ {
+  layout: wgpu::BindGroupLayout,
+  pipeline: wgpu::ComputePipeline,
+  targets: Targets,
+}
+
+impl HiZ {
+  pub fn new(device: &Device, width: u32, height: u32) -> Self {
+    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Hi-Z Bind Group Layout"),
+      entries: &[
+        wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::COMPUTE,
+          ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+          },
+          count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+          binding: 1,
+          visibility: wgpu::ShaderStages::COMPUTE,
+          ty: wgpu::BindingType::StorageTexture {
+            access: wgpu::StorageTextureAccess::WriteOnly,
+            format: PYRAMID_FORMAT,
+            view_dimension: wgpu::TextureViewDimension::D2,
+          },
+          count: None,
+        },
+      ],
+    });
+    let shader = device.create_shader_module(wgpu::include_wgsl!("hiz.wgsl"));
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Hi-Z Pipeline Layout"),
+      bind_group_layouts: &[&layout],
+      push_constant_ranges: &[],
+    });
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+      label: Some("Hi-Z Pipeline"),
+      layout: Some(&pipeline_layout),
+      module: &shader,
+      entry_point: "cs_main",
+    });
+    let targets = create_targets(device, &layout, width, height);
+    Self {
+      layout,
+      pipeline,
+      targets,
+    }
+  }
+
+  pub fn resize(&mut self, device: &Device, width: u32, height: u32) {
+    self.targets = create_targets(device, &self.layout, width, height);
+  }
+
+  // Clears the prepass depth to the far plane, for pipelines of prepass_depth_state()
+  pub fn begin_prepass<'a>(&'a self, encoder: &'a mut CommandEncoder) -> RenderPass<'a> {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+      label: Some("Depth Prepass"),
+      color_attachments: &[],
+      depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+        view: &self.targets.depth_view,
+        depth_ops: Some(wgpu::Operations {
+          load: wgpu::LoadOp::Clear(1.0),
+          store: true,
+        }),
+        stencil_ops: None,
+      }),
+    })
+  }
+
+  // Builds the pyramid from what the prepass drew, every level from the one below
+  pub fn build(&self, encoder: &mut CommandEncoder) {
+    let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+      label: Some("Hi-Z Pass"),
+    });
+    pass.set_pipeline(&self.pipeline);
+    for (bind_group, &(width, height)) in self.targets.bind_groups.iter().zip(&self.targets.sizes) {
+      pass.set_bind_group(0, bind_group, &[]);
+      pass.dispatch_workgroups(
+        width.div_ceil(WORKGROUP_SIZE),
+        height.div_ceil(WORKGROUP_SIZE),
+        1,
+      );
+    }
+  }
+
+  // Every level of the pyramid, as GpuCulling::cull() takes it
+  pub fn view(&self) -> &TextureView {
+    &self.targets.pyramid_view
+  }
+}
+
+fn create_targets(
+  device: &Device,
+  layout: &wgpu::BindGroupLayout,
+  width: u32,
+  height: u32,
+) -> Targets {
+  let depth = device.create_texture(&wgpu::TextureDescriptor {
+    label: Some("Depth Prepass Texture"),
+    size: wgpu::Extent3d {
+      width,
+      height,
+      depth_or_array_layers: 1,
+    },
+    mip_level_count: 1,
+    sample_count: 1,
+    dimension: wgpu::TextureDimension::D2,
+    format: PREPASS_FORMAT,
+    usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+    view_formats: &[],
+  });
+  let depth_view = Tracked::view(
+    &depth,
+    depth.create_view(&wgpu::TextureViewDescriptor::default()),
+  );
+
+  let (base_width, base_height) = ((width / 2).max(1), (height / 2).max(1));
+  let levels = mip_level_count(base_width, base_height);
+  let pyramid = device.create_texture(&wgpu::TextureDescriptor {
+    label: Some("Hi-Z Pyramid"),
+    size: wgpu::Extent3d {
+      width: base_width,
+      height: base_height,
+      depth_or_array_layers: 1,
+    },
+    mip_level_count: levels,
+    sample_count: 1,
+    dimension: wgpu::TextureDimension::D2,
+    format: PYRAMID_FORMAT,
+    usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+    view_formats: &[],
+  });
+  let level_view = |level| {
+    pyramid.create_view(&wgpu::TextureViewDescriptor {
+      base_mip_level: level,
+      mip_level_count: std::num::NonZeroU32::new(1),
+      ..Default::default()
+    })
+  };
+  let level_views: Vec<TextureView> = (0..levels).map(level_view).collect();
+  let bind_groups = (0..levels as usize)
+    .map(|level| {
+      let source = match level {
+        0 => &depth_view,
+        _ => &level_views[level - 1],
+      };
+      device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Hi-Z Bind Group"),
+        layout,
+        entries: &[
+          wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(source),
+          },
+          wgpu::BindGroupEntry {
+            binding: 1,
+            resource: wgpu::BindingResource::TextureView(&level_views[level]),
+          },
+        ],
+      })
+    })
+    .collect();
+  let sizes = (0..levels)
+    .map(|level| ((base_width >> level).max(1), (base_height >> level).max(1)))
+    .collect();
+  Targets {
+    depth_view,
+    pyramid_view: Tracked::view(
+      &pyramid,
+      pyramid.create_view(&wgpu::TextureViewDescriptor::default()),
+    ),
+    bind_groups,
+    sizes,
+  }
+}
@@ -0,0 +1,34 @@
+// One level of the Hi-Z pyramid, see hiz.rs. Every texel keeps the farthest depth of the
+// 2x2 texels below it, so whatever is nearer than it is in front of everything drawn there.
+
+// the prepass depth for level 0, the level below otherwise. Bound as a plain float texture,
+// GLSL has no textureLoad for depth textures.
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+@group(0) @binding(1)
+var destination: texture_storage_2d<r32float, write>;
+
+@compute @workgroup_size(8, 8)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = vec2<u32>(textureDimensions(destination));
+    if any(id.xy >= size) {
+        return;
+    }
+    let source_size = vec2<u32>(textureDimensions(source));
+    let start = id.xy * 2u;
+    var end = min(start + 1u, source_size - 1u);
+    // an odd size leaves a row or column over, the last texel takes it in too
+    if id.x == size.x - 1u {
+        end.x = source_size.x - 1u;
+    }
+    if id.y == size.y - 1u {
+        end.y = source_size.y - 1u;
+    }
+    var depth = 0.0;
+    for (var y = start.y; y <= end.y; y++) {
+        for (var x = start.x; x <= end.x; x++) {
+            depth = max(depth, textureLoad(source, vec2<i32>(vec2<u32>(x, y)), 0).r);
+        }
+    }
+    textureStore(destination, vec2<i32>(id.xy), vec4<f32>(depth, 0.0, 0.0, 0.0));
+}
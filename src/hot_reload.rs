@@ -0,0 +1,274 @@
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+use winit::window::WindowId;
+
+use crate::{
+  animated_texture::{self, AnimatedTexture},
+  asset_manager::{AssetManager, Handle, ModelAsset, ShaderAsset},
+  assets::Assets,
+  cli::CliArgs,
+  math,
+  mesh::Mesh,
+  mipmap::MipmapGenerator,
+  pbr::{PbrMaterial, PbrTextures},
+  pipeline::PipelineCache,
+  sampler::Samplers,
+  scene::{ModelId, Scene},
+  texture::Texture,
+  viewport::Viewport,
+};
+
+// Loaded models are scaled to this size and stood on the ground next to the cubes
+const MODEL_SIZE: f32 = 2.0;
+const MODEL_POSITION: [f32; 3] = [-2.5, 0.0, -2.0];
+
+// `--model path.obj` puts the model into the shadowed scene (M) with a default material,
+// `--normal-map path.png` adds a tangent space normal map to it and `--albedo` a color
+// texture, which can be a GIF, an APNG or a directory of frames to play. Built with the
+// webcam feature, `--webcam 0` shows /dev/video0 on it instead.
+//
+// The model loads on the asset thread and is put in the scene when it's ready, again
+// whenever its file changes. The normal map is read through Assets like the other
+// textures, so it's a path in res/ or the asset pack.
+struct ModelFiles {
+  meshes: Handle<ModelAsset>,
+  normal_map: Option<Arc<wgpu::TextureView>>,
+  // animated textures and webcams aren't reloaded, they stream anyway
+  albedo: Option<Arc<wgpu::TextureView>>,
+  // --cutout
+  cutout: bool,
+  // what's in the scene from the last version
+  placed: Vec<ModelId>,
+}
+
+// The files loaded through the asset manager, and putting what changed back in place
+pub struct HotReload {
+  manager: AssetManager,
+  model: Option<ModelFiles>,
+  // src/shader.wgsl of a debug build run from its checkout
+  shader: Option<Handle<ShaderAsset>>,
+  // the last version of it that compiled
+  shader_source: Option<Arc<str>>,
+}
+
+impl HotReload {
+  pub async fn new(
+    scene: &mut Scene,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    samplers: &Samplers,
+    assets: &Assets,
+    max_texture_size: u32,
+    cli: &CliArgs,
+  ) -> Self {
+    let mut manager = AssetManager::new();
+    let model = match &cli.model {
+      Some(path) => Some(ModelFiles {
+        meshes: manager.load(path),
+        normal_map: model_normal_map(device, queue, samplers, assets, max_texture_size, cli).await,
+        albedo: model_albedo(scene, device, queue, samplers, max_texture_size, cli),
+        cutout: cli.cutout,
+        placed: Vec::new(),
+      }),
+      None => None,
+    };
+    let shader_path = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader.wgsl");
+    let shader = (cfg!(debug_assertions) && Path::new(shader_path).exists())
+      .then(|| manager.load(shader_path));
+    Self {
+      manager,
+      model,
+      shader,
+      shader_source: None,
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.manager.is_empty()
+  }
+
+  // shader.wgsl as last edited, for new windows
+  pub fn shader_source(&self) -> Option<Arc<str>> {
+    self.shader_source.clone()
+  }
+
+  // Puts loaded and changed assets in place of the old ones, call between frames. Returns
+  // whether anything changed.
+  pub fn update(
+    &mut self,
+    scene: &mut Scene,
+    device: &wgpu::Device,
+    viewports: &mut HashMap<WindowId, Viewport>,
+    pipelines: &mut PipelineCache,
+  ) -> bool {
+    let changed = self.manager.update();
+    if let Some(model) = &mut self.model {
+      let model_changed = changed.contains(&model.meshes.id());
+      if let (true, Some(meshes)) = (model_changed, self.manager.get(&model.meshes)) {
+        let textures = PbrTextures {
+          albedo: model.albedo.clone(),
+          normal: model.normal_map.clone(),
+          ..Default::default()
+        };
+        for id in model.placed.drain(..) {
+          scene.remove_model(id);
+        }
+        let path = self.manager.path(&model.meshes);
+        model.placed = place_model(scene, device, path, &meshes.0, textures, model.cutout);
+      }
+    }
+
+    let shader = self.shader.as_ref().filter(|s| changed.contains(&s.id()));
+    let source = shader.and_then(|shader| self.manager.get(shader)?.0.wgsl());
+    if let Some(source) = source {
+      // wgpu would otherwise panic on a shader with a mistake in it
+      let source: Arc<str> = source.into();
+      device.push_error_scope(wgpu::ErrorFilter::Validation);
+      for viewport in viewports.values_mut() {
+        viewport.set_shader(device, pipelines, Some(source.clone()));
+      }
+      match pollster::block_on(device.pop_error_scope()) {
+        None => self.shader_source = Some(source),
+        Some(e) => {
+          log::warn!("keeping the last shader.wgsl that compiled: {}", e);
+          for viewport in viewports.values_mut() {
+            viewport.set_shader(device, pipelines, self.shader_source.clone());
+          }
+        }
+      }
+      // the last version's pipelines, and the broken ones
+      pipelines.evict_unused();
+    }
+    !changed.is_empty()
+  }
+}
+
+// The --normal-map, without the sRGB decode
+async fn model_normal_map(
+  device: &wgpu::Device,
+  queue: &wgpu::Queue,
+  samplers: &Samplers,
+  assets: &Assets,
+  max_size: u32,
+  cli: &CliArgs,
+) -> Option<Arc<wgpu::TextureView>> {
+  let path = cli.normal_map.as_deref()?;
+  let image = match assets.read(path).await {
+    Ok(bytes) => image::load_from_memory(&bytes).map_err(|e| e.to_string()),
+    Err(e) => Err(e.to_string()),
+  };
+  match image {
+    Ok(image) => {
+      let texture = Texture::from_image_linear(
+        device,
+        queue,
+        &mut MipmapGenerator::new(device),
+        samplers,
+        &image,
+        max_size,
+        "Model Normal Map",
+      );
+      Some(Arc::new(texture.view))
+    }
+    Err(e) => {
+      log::warn!("couldn't load {}: {}", path, e);
+      None
+    }
+  }
+}
+
+// The texture view --albedo or --webcam asks for, the textures keep playing in the scene
+fn model_albedo(
+  scene: &mut Scene,
+  device: &wgpu::Device,
+  queue: &wgpu::Queue,
+  samplers: &Samplers,
+  max_size: u32,
+  cli: &CliArgs,
+) -> Option<Arc<wgpu::TextureView>> {
+  let albedo = cli
+    .albedo
+    .as_ref()
+    .and_then(|path| match animated_texture::load(path) {
+      Ok(frames) => {
+        let texture = AnimatedTexture::new(
+          device,
+          queue,
+          &mut MipmapGenerator::new(device),
+          samplers,
+          frames,
+          max_size,
+          &path.to_string_lossy(),
+        );
+        let view = texture.create_view();
+        scene.add_animated_texture(texture);
+        Some(Arc::new(view))
+      }
+      Err(e) => {
+        log::warn!("{}", e);
+        None
+      }
+    });
+  #[cfg(all(feature = "webcam", target_os = "linux"))]
+  let albedo = match cli.webcam {
+    Some(index) => match crate::webcam::Webcam::open(device, index) {
+      Ok(webcam) => {
+        let view = webcam.create_view();
+        scene.add_webcam(webcam);
+        Some(Arc::new(view))
+      }
+      Err(e) => {
+        log::warn!("couldn't open webcam {}: {}", index, e);
+        albedo
+      }
+    },
+    None => albedo,
+  };
+  albedo
+}
+
+// Scales `meshes` to MODEL_SIZE and stands them next to the cubes
+fn place_model(
+  scene: &mut Scene,
+  device: &wgpu::Device,
+  path: &Path,
+  meshes: &[Mesh],
+  textures: PbrTextures,
+  cutout: bool,
+) -> Vec<ModelId> {
+  let (min, max) = meshes
+    .iter()
+    .filter(|mesh| !mesh.vertices.is_empty())
+    .map(Mesh::bounds)
+    .fold(([f32::MAX; 3], [f32::MIN; 3]), |(min, max), (a, b)| {
+      (
+        [0, 1, 2].map(|i| min[i].min(a[i])),
+        [0, 1, 2].map(|i| max[i].max(b[i])),
+      )
+    });
+  let extent = (0..3).map(|i| max[i] - min[i]).fold(0.0, f32::max);
+  if extent <= 0.0 {
+    log::warn!("{} has no vertices", path.display());
+    return Vec::new();
+  }
+  let scale = MODEL_SIZE / extent;
+  // centered over MODEL_POSITION with its lowest point on the ground
+  let offset = [
+    MODEL_POSITION[0] - (min[0] + max[0]) / 2.0 * scale,
+    MODEL_POSITION[1] - min[1] * scale,
+    MODEL_POSITION[2] - (min[2] + max[2]) / 2.0 * scale,
+  ];
+  let transform = math::mul_mat4(&math::translation(offset), &math::scaling([scale; 3]));
+  // --cutout for an --albedo whose alpha has holes in it, like leaves
+  let material = PbrMaterial {
+    metallic: 0.0,
+    roughness: 0.5,
+    alpha_cutout: cutout,
+    ..Default::default()
+  };
+  log::info!("placed {} meshes from {}", meshes.len(), path.display());
+  meshes
+    .iter()
+    .map(|mesh| scene.add_model(device, mesh, &material, textures.clone(), transform))
+    .collect()
+}
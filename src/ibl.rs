@@ -0,0 +1,354 @@
+use wgpu::{util::DeviceExt, Device, Queue, TextureView};
+
+use crate::{
+  gpu_memory::Tracked, hdr::HDR_FORMAT, mipmap::mip_level_count, sampler::SamplerSettings,
+};
+
+// The environment is resampled into this before filtering, with a full mip chain for
+// filtered importance sampling
+const RADIANCE_SIZE: u32 = 256;
+const IRRADIANCE_SIZE: u32 = 32;
+const SPECULAR_SIZE: u32 = 128;
+// roughness 0, 0.2, ... 1
+pub const SPECULAR_MIP_COUNT: u32 = 6;
+const BRDF_LUT_SIZE: u32 = 128;
+const IRRADIANCE_SAMPLES: u32 = 256;
+const SPECULAR_SAMPLES: u32 = 256;
+const BRDF_LUT_SAMPLES: u32 = 512;
+const WORKGROUP_SIZE: u32 = 8;
+
+// Matches `Params` in ibl.wgsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct IblParams {
+  roughness: f32,
+  sample_count: u32,
+  source_size: f32,
+  source_mip_count: f32,
+}
+
+// What PbrPipeline::set_environment takes
+pub struct EnvironmentMaps {
+  pub irradiance: Tracked<TextureView>,
+  pub specular: Tracked<TextureView>,
+  pub specular_mip_count: u32,
+}
+
+// Compute passes turning an environment cube into the maps the PBR shader samples
+pub struct IblBaker {
+  prefilter_layout: wgpu::BindGroupLayout,
+  downsample_layout: wgpu::BindGroupLayout,
+  lut_layout: wgpu::BindGroupLayout,
+  copy_pipeline: wgpu::ComputePipeline,
+  downsample_pipeline: wgpu::ComputePipeline,
+  irradiance_pipeline: wgpu::ComputePipeline,
+  specular_pipeline: wgpu::ComputePipeline,
+  lut_pipeline: wgpu::ComputePipeline,
+  sampler: wgpu::Sampler,
+}
+
+impl IblBaker {
+  pub fn new(device: &Device) -> Self {
+    let shader = device.create_shader_module(wgpu::include_wgsl!("ibl.wgsl"));
+    let entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility: wgpu::ShaderStages::COMPUTE,
+      ty,
+      count: None,
+    };
+    let uniform = wgpu::BindingType::Buffer {
+      ty: wgpu::BufferBindingType::Uniform,
+      has_dynamic_offset: false,
+      min_binding_size: None,
+    };
+    let storage = |view_dimension| wgpu::BindingType::StorageTexture {
+      access: wgpu::StorageTextureAccess::WriteOnly,
+      format: HDR_FORMAT,
+      view_dimension,
+    };
+    let texture = |view_dimension, filterable| wgpu::BindingType::Texture {
+      sample_type: wgpu::TextureSampleType::Float { filterable },
+      view_dimension,
+      multisampled: false,
+    };
+    let prefilter_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Ibl Prefilter Layout"),
+      entries: &[
+        entry(0, uniform),
+        entry(1, texture(wgpu::TextureViewDimension::Cube, true)),
+        entry(
+          2,
+          wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        ),
+        entry(3, storage(wgpu::TextureViewDimension::D2Array)),
+      ],
+    });
+    let downsample_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Ibl Downsample Layout"),
+      entries: &[
+        entry(3, storage(wgpu::TextureViewDimension::D2Array)),
+        entry(5, texture(wgpu::TextureViewDimension::D2Array, false)),
+      ],
+    });
+    let lut_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Ibl Brdf Lut Layout"),
+      entries: &[
+        entry(0, uniform),
+        entry(4, storage(wgpu::TextureViewDimension::D2)),
+      ],
+    });
+
+    let pipeline = |layout: &wgpu::BindGroupLayout, entry_point: &str| {
+      let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(&format!("Ibl {} Pipeline Layout", entry_point)),
+        bind_group_layouts: &[layout],
+        push_constant_ranges: &[],
+      });
+      device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some(&format!("Ibl {} Pipeline", entry_point)),
+        layout: Some(&pipeline_layout),
+        module: &shader,
+        entry_point,
+      })
+    };
+
+    Self {
+      copy_pipeline: pipeline(&prefilter_layout, "cs_copy"),
+      downsample_pipeline: pipeline(&downsample_layout, "cs_downsample"),
+      irradiance_pipeline: pipeline(&prefilter_layout, "cs_irradiance"),
+      specular_pipeline: pipeline(&prefilter_layout, "cs_specular"),
+      lut_pipeline: pipeline(&lut_layout, "cs_brdf_lut"),
+      prefilter_layout,
+      downsample_layout,
+      lut_layout,
+      sampler: device
+        .create_sampler(&SamplerSettings::trilinear().descriptor(Some("Ibl Source Sampler"))),
+    }
+  }
+
+  // The split sum BRDF's scale (red) and bias (green) for F0, by n_dot_v along x and
+  // roughness along y. Only depends on the BRDF, so one is enough for every environment.
+  pub fn brdf_lut(&self, device: &Device, queue: &Queue) -> Tracked<TextureView> {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+      label: Some("Ibl Brdf Lut"),
+      size: wgpu::Extent3d {
+        width: BRDF_LUT_SIZE,
+        height: BRDF_LUT_SIZE,
+        depth_or_array_layers: 1,
+      },
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format: HDR_FORMAT,
+      usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+      view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let params = self.params(device, 0.0, BRDF_LUT_SAMPLES, 0);
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Ibl Brdf Lut Bind Group"),
+      layout: &self.lut_layout,
+      entries: &[
+        wgpu::BindGroupEntry {
+          binding: 0,
+          resource: params.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+          binding: 4,
+          resource: wgpu::BindingResource::TextureView(&view),
+        },
+      ],
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+      label: Some("Ibl Brdf Lut Encoder"),
+    });
+    {
+      let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+        label: Some("Ibl Brdf Lut Pass"),
+      });
+      pass.set_pipeline(&self.lut_pipeline);
+      pass.set_bind_group(0, &bind_group, &[]);
+      let groups = BRDF_LUT_SIZE.div_ceil(WORKGROUP_SIZE);
+      pass.dispatch_workgroups(groups, groups, 1);
+    }
+    queue.submit(std::iter::once(encoder.finish()));
+    Tracked::view(&texture, view)
+  }
+
+  // Diffuse irradiance and roughness prefiltered specular cubes for `environment`, e.g.
+  // the skybox's cube
+  pub fn bake(&self, device: &Device, queue: &Queue, environment: &TextureView) -> EnvironmentMaps {
+    let radiance_mips = mip_level_count(RADIANCE_SIZE, RADIANCE_SIZE);
+    let radiance = create_cube(device, "Ibl Radiance", RADIANCE_SIZE, radiance_mips);
+    let irradiance = create_cube(device, "Ibl Irradiance", IRRADIANCE_SIZE, 1);
+    let specular = create_cube(device, "Ibl Specular", SPECULAR_SIZE, SPECULAR_MIP_COUNT);
+    let radiance_view = radiance.create_view(&wgpu::TextureViewDescriptor {
+      dimension: Some(wgpu::TextureViewDimension::Cube),
+      ..Default::default()
+    });
+
+    // (pipeline, bind group, face size) in the order they have to run
+    let mut dispatches = Vec::new();
+    let copy_params = self.params(device, 0.0, 0, 0);
+    dispatches.push((
+      &self.copy_pipeline,
+      self.prefilter_bind_group(device, &copy_params, environment, &level_view(&radiance, 0)),
+      RADIANCE_SIZE,
+    ));
+    for level in 1..radiance_mips {
+      let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Ibl Downsample Bind Group"),
+        layout: &self.downsample_layout,
+        entries: &[
+          wgpu::BindGroupEntry {
+            binding: 3,
+            resource: wgpu::BindingResource::TextureView(&level_view(&radiance, level)),
+          },
+          wgpu::BindGroupEntry {
+            binding: 5,
+            resource: wgpu::BindingResource::TextureView(&level_view(&radiance, level - 1)),
+          },
+        ],
+      });
+      dispatches.push((
+        &self.downsample_pipeline,
+        bind_group,
+        (RADIANCE_SIZE >> level).max(1),
+      ));
+    }
+    let irradiance_params = self.params(device, 1.0, IRRADIANCE_SAMPLES, radiance_mips);
+    dispatches.push((
+      &self.irradiance_pipeline,
+      self.prefilter_bind_group(
+        device,
+        &irradiance_params,
+        &radiance_view,
+        &level_view(&irradiance, 0),
+      ),
+      IRRADIANCE_SIZE,
+    ));
+    for level in 0..SPECULAR_MIP_COUNT {
+      let roughness = level as f32 / (SPECULAR_MIP_COUNT - 1) as f32;
+      let params = self.params(device, roughness, SPECULAR_SAMPLES, radiance_mips);
+      dispatches.push((
+        &self.specular_pipeline,
+        self.prefilter_bind_group(
+          device,
+          &params,
+          &radiance_view,
+          &level_view(&specular, level),
+        ),
+        (SPECULAR_SIZE >> level).max(1),
+      ));
+    }
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+      label: Some("Ibl Bake Encoder"),
+    });
+    {
+      let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+        label: Some("Ibl Bake Pass"),
+      });
+      for (pipeline, bind_group, size) in &dispatches {
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, bind_group, &[]);
+        let groups = size.div_ceil(WORKGROUP_SIZE);
+        pass.dispatch_workgroups(groups, groups, 6);
+      }
+    }
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let cube_view = |texture: &wgpu::Texture| {
+      let view = texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::Cube),
+        ..Default::default()
+      });
+      Tracked::view(texture, view)
+    };
+    EnvironmentMaps {
+      irradiance: cube_view(&irradiance),
+      specular: cube_view(&specular),
+      specular_mip_count: SPECULAR_MIP_COUNT,
+    }
+  }
+
+  // `source_mip_count` is 0 for passes that don't sample the radiance cube
+  fn params(
+    &self,
+    device: &Device,
+    roughness: f32,
+    sample_count: u32,
+    source_mip_count: u32,
+  ) -> wgpu::Buffer {
+    let params = IblParams {
+      roughness,
+      sample_count,
+      source_size: RADIANCE_SIZE as f32,
+      source_mip_count: source_mip_count as f32,
+    };
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Ibl Params Buffer"),
+      contents: bytemuck::bytes_of(&params),
+      usage: wgpu::BufferUsages::UNIFORM,
+    })
+  }
+
+  fn prefilter_bind_group(
+    &self,
+    device: &Device,
+    params: &wgpu::Buffer,
+    source: &TextureView,
+    destination: &TextureView,
+  ) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Ibl Prefilter Bind Group"),
+      layout: &self.prefilter_layout,
+      entries: &[
+        wgpu::BindGroupEntry {
+          binding: 0,
+          resource: params.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+          binding: 1,
+          resource: wgpu::BindingResource::TextureView(source),
+        },
+        wgpu::BindGroupEntry {
+          binding: 2,
+          resource: wgpu::BindingResource::Sampler(&self.sampler),
+        },
+        wgpu::BindGroupEntry {
+          binding: 3,
+          resource: wgpu::BindingResource::TextureView(destination),
+        },
+      ],
+    })
+  }
+}
+
+fn create_cube(device: &Device, label: &str, size: u32, mip_level_count: u32) -> wgpu::Texture {
+  device.create_texture(&wgpu::TextureDescriptor {
+    label: Some(label),
+    size: wgpu::Extent3d {
+      width: size,
+      height: size,
+      depth_or_array_layers: 6,
+    },
+    mip_level_count,
+    sample_count: 1,
+    dimension: wgpu::TextureDimension::D2,
+    format: HDR_FORMAT,
+    usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+    view_formats: &[],
+  })
+}
+
+// The six faces of one mip level, as a storage target or for textureLoad
+fn level_view(texture: &wgpu::Texture, level: u32) -> TextureView {
+  texture.create_view(&wgpu::TextureViewDescriptor {
+    dimension: Some(wgpu::TextureViewDimension::D2Array),
+    base_mip_level: level,
+    mip_level_count: std::num::NonZeroU32::new(1),
+    ..Default::default()
+  })
+}
@@ -0,0 +1,212 @@
+// Image based lighting preprocessing, run once per environment by ibl.rs:
+//   cs_copy        environment cube -> level 0 of the radiance cube
+//   cs_downsample  radiance level n - 1 -> level n
+//   cs_irradiance  radiance cube -> cosine convolved irradiance cube (diffuse)
+//   cs_specular    radiance cube -> GGX prefiltered radiance, one roughness per mip
+//   cs_brdf_lut    the split sum BRDF scale and bias, independent of the environment
+
+struct Params {
+    roughness: f32,
+    sample_count: u32,
+    // face size and mip count of the radiance cube being sampled
+    source_size: f32,
+    source_mip_count: f32,
+};
+
+@group(0) @binding(0)
+var<uniform> params: Params;
+@group(0) @binding(1)
+var source: texture_cube<f32>;
+@group(0) @binding(2)
+var source_sampler: sampler;
+@group(0) @binding(3)
+var destination: texture_storage_2d_array<rgba16float, write>;
+@group(0) @binding(4)
+var lut: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(5)
+var previous_level: texture_2d_array<f32>;
+
+const PI: f32 = 3.14159265359;
+
+// Same face order and orientation as the cube map sampler, see equirect.wgsl
+fn face_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    switch face {
+        case 0u: { return vec3<f32>(1.0, -uv.y, -uv.x); }
+        case 1u: { return vec3<f32>(-1.0, -uv.y, uv.x); }
+        case 2u: { return vec3<f32>(uv.x, 1.0, uv.y); }
+        case 3u: { return vec3<f32>(uv.x, -1.0, -uv.y); }
+        case 4u: { return vec3<f32>(uv.x, -uv.y, 1.0); }
+        default: { return vec3<f32>(-uv.x, -uv.y, -1.0); }
+    }
+}
+
+// Direction through the center of texel `id` of the destination
+fn destination_direction(id: vec3<u32>, size: vec2<u32>) -> vec3<f32> {
+    let uv = (vec2<f32>(id.xy) + 0.5) / vec2<f32>(size) * 2.0 - 1.0;
+    return normalize(face_direction(id.z, uv));
+}
+
+// Low discrepancy points in the unit square
+fn hammersley(i: u32, count: u32) -> vec2<f32> {
+    var bits = i;
+    bits = (bits << 16u) | (bits >> 16u);
+    bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+    bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+    bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+    bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+    return vec2<f32>(f32(i) / f32(count), f32(bits) * 2.3283064365386963e-10);
+}
+
+// Rotates a tangent space direction (z up) around `n`
+fn to_world(local: vec3<f32>, n: vec3<f32>) -> vec3<f32> {
+    var up = vec3<f32>(0.0, 0.0, 1.0);
+    if abs(n.z) > 0.999 {
+        up = vec3<f32>(1.0, 0.0, 0.0);
+    }
+    let tangent = normalize(cross(up, n));
+    let bitangent = cross(n, tangent);
+    return tangent * local.x + bitangent * local.y + n * local.z;
+}
+
+// Half vector around z distributed like GGX with alpha = roughness^2
+fn sample_ggx(xi: vec2<f32>, alpha: f32) -> vec3<f32> {
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (alpha * alpha - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    return vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+}
+
+fn distribution_ggx(n_dot_h: f32, alpha: f32) -> f32 {
+    let a2 = alpha * alpha;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+// Filtered importance sampling: a sample standing in for a large solid angle reads a
+// blurrier mip, which keeps bright spots in the environment from turning into speckles
+fn sample_lod(pdf: f32) -> f32 {
+    let sample_angle = 1.0 / (f32(params.sample_count) * pdf + 0.0001);
+    let texel_angle = 4.0 * PI / (6.0 * params.source_size * params.source_size);
+    return clamp(0.5 * log2(sample_angle / texel_angle) + 1.0, 0.0, params.source_mip_count - 1.0);
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn cs_copy(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = vec2<u32>(textureDimensions(destination));
+    if id.x >= size.x || id.y >= size.y {
+        return;
+    }
+    // 2x2 samples per texel, the environment is usually bigger than the radiance cube
+    var color = vec3<f32>(0.0);
+    for (var i = 0u; i < 4u; i += 1u) {
+        let offset = vec2<f32>(f32(i % 2u), f32(i / 2u)) * 0.5 + 0.25;
+        let uv = (vec2<f32>(id.xy) + offset) / vec2<f32>(size) * 2.0 - 1.0;
+        color += textureSampleLevel(source, source_sampler, face_direction(id.z, uv), 0.0).rgb;
+    }
+    textureStore(destination, vec2<i32>(id.xy), i32(id.z), vec4<f32>(color * 0.25, 1.0));
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn cs_downsample(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = vec2<u32>(textureDimensions(destination));
+    if id.x >= size.x || id.y >= size.y {
+        return;
+    }
+    let base = vec2<i32>(id.xy) * 2;
+    let layer = i32(id.z);
+    let color = textureLoad(previous_level, base, layer, 0)
+        + textureLoad(previous_level, base + vec2<i32>(1, 0), layer, 0)
+        + textureLoad(previous_level, base + vec2<i32>(0, 1), layer, 0)
+        + textureLoad(previous_level, base + vec2<i32>(1, 1), layer, 0);
+    textureStore(destination, vec2<i32>(id.xy), layer, color * 0.25);
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn cs_irradiance(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = vec2<u32>(textureDimensions(destination));
+    if id.x >= size.x || id.y >= size.y {
+        return;
+    }
+    let n = destination_direction(id, size);
+    // cosine weighted hemisphere samples, the pdf cancels the cosine and the 1 / PI
+    var irradiance = vec3<f32>(0.0);
+    for (var i = 0u; i < params.sample_count; i += 1u) {
+        let xi = hammersley(i, params.sample_count);
+        let phi = 2.0 * PI * xi.x;
+        let cos_theta = sqrt(1.0 - xi.y);
+        let sin_theta = sqrt(xi.y);
+        let local = vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+        let lod = sample_lod(cos_theta / PI);
+        irradiance += textureSampleLevel(source, source_sampler, to_world(local, n), lod).rgb;
+    }
+    textureStore(destination, vec2<i32>(id.xy), i32(id.z), vec4<f32>(irradiance / f32(params.sample_count), 1.0));
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn cs_specular(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = vec2<u32>(textureDimensions(destination));
+    if id.x >= size.x || id.y >= size.y {
+        return;
+    }
+    let n = destination_direction(id, size);
+    if params.roughness == 0.0 {
+        textureStore(destination, vec2<i32>(id.xy), i32(id.z), textureSampleLevel(source, source_sampler, n, 0.0));
+        return;
+    }
+    // view = normal = reflection, the usual split sum approximation
+    let alpha = params.roughness * params.roughness;
+    var color = vec3<f32>(0.0);
+    var weight = 0.0;
+    for (var i = 0u; i < params.sample_count; i += 1u) {
+        let h_local = sample_ggx(hammersley(i, params.sample_count), alpha);
+        let h = to_world(h_local, n);
+        let l = 2.0 * dot(n, h) * h - n;
+        let n_dot_l = dot(n, l);
+        if n_dot_l > 0.0 {
+            // with n = v the pdf of l is D(h) / 4
+            let lod = sample_lod(distribution_ggx(h_local.z, alpha) / 4.0);
+            color += textureSampleLevel(source, source_sampler, l, lod).rgb * n_dot_l;
+            weight += n_dot_l;
+        }
+    }
+    textureStore(destination, vec2<i32>(id.xy), i32(id.z), vec4<f32>(color / max(weight, 0.0001), 1.0));
+}
+
+// Smith's geometry term with the k = alpha / 2 remapping used for image based lighting
+fn geometry_smith_ibl(n_dot_v: f32, n_dot_l: f32, alpha: f32) -> f32 {
+    let k = alpha / 2.0;
+    let g_v = n_dot_v / (n_dot_v * (1.0 - k) + k);
+    let g_l = n_dot_l / (n_dot_l * (1.0 - k) + k);
+    return g_v * g_l;
+}
+
+// x: n_dot_v, y: roughness. Stores the scale and bias applied to F0.
+@compute @workgroup_size(8, 8, 1)
+fn cs_brdf_lut(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = vec2<u32>(textureDimensions(lut));
+    if id.x >= size.x || id.y >= size.y {
+        return;
+    }
+    let coords = (vec2<f32>(id.xy) + 0.5) / vec2<f32>(size);
+    let n_dot_v = coords.x;
+    let alpha = coords.y * coords.y;
+    let v = vec3<f32>(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+
+    var scale = 0.0;
+    var bias = 0.0;
+    for (var i = 0u; i < params.sample_count; i += 1u) {
+        let h = sample_ggx(hammersley(i, params.sample_count), alpha);
+        let l = 2.0 * dot(v, h) * h - v;
+        let n_dot_l = saturate(l.z);
+        let n_dot_h = saturate(h.z);
+        let v_dot_h = saturate(dot(v, h));
+        if n_dot_l > 0.0 {
+            let visibility = geometry_smith_ibl(n_dot_v, n_dot_l, alpha) * v_dot_h / (n_dot_h * n_dot_v);
+            let fresnel = pow(1.0 - v_dot_h, 5.0);
+            scale += (1.0 - fresnel) * visibility;
+            bias += fresnel * visibility;
+        }
+    }
+    let count = f32(params.sample_count);
+    textureStore(lut, vec2<i32>(id.xy), vec4<f32>(scale / count, bias / count, 0.0, 1.0));
+}
@@ -0,0 +1,30 @@
+// Entity ids instead of colors for picking, see picking.rs. The id of a draw's first
+// instance comes from the uniform, the others count up from it.
+
+#include "common.wgsl"
+
+@group(1) @binding(0)
+var<uniform> first_id: vec4<u32>;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) @interpolate(flat) id: u32,
+};
+
+@vertex
+fn vs_main(
+    vertex: VertexInput,
+    instance: InstanceInput,
+    @builtin(instance_index) instance_index: u32,
+) -> VertexOutput {
+    var out: VertexOutput;
+    // the pixel under the cursor, not wherever TAA's jitter moved it this frame
+    out.clip_position = globals.unjittered_view_proj * instance_model(instance) * vec4<f32>(model_position(vertex, instance), 1.0);
+    out.id = first_id.x + instance_index;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) u32 {
+    return in.id;
+}
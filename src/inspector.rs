@@ -0,0 +1,309 @@
+// A panel in the overlay for looking at and changing the world's entities while the app
+// runs, ` shows it. It lists the entities as a tree, children indented under their parents,
+// with one of them selected. The selection follows picking both ways: clicking a model
+// selects its entity, and selecting an entity with a model outlines it. The selected one's
+// transform, material and visibility are listed below the tree as fields, one of them
+// selected too, that the keys nudge up and down a step at a time.
+
+use bevy_ecs::world::World;
+
+use crate::{
+  ecs::{self, Hidden, Light, Material, MeshHandle, Parent, Spin, Transform},
+  math,
+  scene::{ModelId, Scene},
+};
+
+// How far a field goes per press
+const MOVE_STEP: f32 = 0.1;
+const TURN_STEP: f32 = std::f32::consts::PI / 12.0;
+const SCALE_STEP: f32 = 1.1;
+const FACTOR_STEP: f32 = 0.05;
+const AXES: [char; 3] = ['x', 'y', 'z'];
+const CHANNELS: [char; 3] = ['r', 'g', 'b'];
+
+// What can be changed about an entity
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+  Translation(usize),
+  // turns it around its own axis, there's no angle to show for that
+  Rotation(usize),
+  // all three axes together
+  Scale,
+  BaseColor(usize),
+  Metallic,
+  Roughness,
+  Visible,
+}
+
+#[derive(Debug, Default)]
+pub struct Inspector {
+  visible: bool,
+  selected: Option<ecs::Entity>,
+  // index into the selected entity's fields()
+  field: usize,
+}
+
+impl Inspector {
+  pub fn is_visible(&self) -> bool {
+    self.visible
+  }
+
+  // Shows or hides the panel, returns whether it's shown now
+  pub fn toggle_visible(&mut self) -> bool {
+    self.visible = !self.visible;
+    self.visible
+  }
+
+  pub fn selected(&self) -> Option<ecs::Entity> {
+    self.selected
+  }
+
+  // Selects `entity` and outlines its model, if it has one
+  pub fn select(&mut self, scene: &mut Scene, entity: Option<ecs::Entity>) {
+    if entity != self.selected {
+      self.field = 0;
+    }
+    self.selected = entity;
+    let model = entity
+      .and_then(|entity| scene.world().get::<MeshHandle>(entity))
+      .map(|mesh| mesh.0);
+    scene.select(model);
+  }
+
+  // The entity of a model that was picked, the scene has already outlined it
+  pub fn picked(&mut self, scene: &Scene, model: Option<ModelId>) {
+    let world = scene.world();
+    let entity = model.and_then(|model| {
+      world
+        .iter_entities()
+        .find(|entity| {
+          entity
+            .get::<MeshHandle>()
+            .is_some_and(|mesh| mesh.0 == model)
+        })
+        .map(|entity| entity.id())
+    });
+    if entity != self.selected {
+      self.field = 0;
+    }
+    self.selected = entity;
+  }
+
+  // Picks an entity or a field and changes it, only while the panel is shown. Returns
+  // whether `action` is one of the inspector's in keymap.rs.
+  pub fn action(&mut self, scene: &mut Scene, action: &str) -> bool {
+    if !self.visible {
+      return false;
+    }
+    let tree = tree(scene.world());
+    let position = self
+      .selected
+      .and_then(|selected| tree.iter().position(|&(_, entity)| entity == selected));
+    let fields = self
+      .selected
+      .filter(|_| position.is_some())
+      .map(|entity| fields(scene.world(), entity))
+      .unwrap_or_default();
+    match action {
+      "previous_node" | "next_node" if !tree.is_empty() => {
+        let count = tree.len();
+        let next = match (position, action) {
+          (None, _) => 0,
+          (Some(i), "previous_node") => (i + count - 1) % count,
+          (Some(i), _) => (i + 1) % count,
+        };
+        self.select(scene, Some(tree[next].1));
+      }
+      "previous_field" | "next_field" if !fields.is_empty() => {
+        let count = fields.len();
+        self.field = if action == "previous_field" {
+          (self.field + count - 1) % count
+        } else {
+          (self.field + 1) % count
+        };
+      }
+      "decrease_field" | "increase_field" => {
+        let (Some(entity), Some(&field)) = (self.selected, fields.get(self.field)) else {
+          return true;
+        };
+        let sign = if action == "increase_field" {
+          1.0
+        } else {
+          -1.0
+        };
+        change(scene.world_mut(), entity, field, sign);
+      }
+      "previous_node" | "next_node" | "previous_field" | "next_field" => {}
+      _ => return false,
+    }
+    true
+  }
+
+  // The tree with the selected entity marked, and its fields with the selected one marked
+  pub fn overlay(&self, scene: &Scene) -> String {
+    let world = scene.world();
+    let mut text = String::from("inspector (5/6 entity, 7/8 field, 9/0 change)");
+    let tree = tree(world);
+    if tree.is_empty() {
+      text += "\n  no entities, 2 and 3 spawn some";
+    }
+    for &(depth, entity) in &tree {
+      text += &format!(
+        "\n{} {}{} {}",
+        if Some(entity) == self.selected {
+          '>'
+        } else {
+          ' '
+        },
+        "  ".repeat(depth),
+        kind(world, entity),
+        entity.index()
+      );
+    }
+    let Some(entity) = self
+      .selected
+      .filter(|&entity| world.get_entity(entity).is_some())
+    else {
+      return text;
+    };
+    text += &format!("\n{} {}", kind(world, entity), entity.index());
+    for (i, &field) in fields(world, entity).iter().enumerate() {
+      text += &format!(
+        "\n{} {}",
+        if i == self.field { '>' } else { ' ' },
+        describe(world, entity, field)
+      );
+    }
+    text
+  }
+}
+
+// Every entity with a Transform but the cameras, parents first with how deep they are
+fn tree(world: &World) -> Vec<(usize, ecs::Entity)> {
+  let mut entities: Vec<(ecs::Entity, Option<ecs::Entity>)> = world
+    .iter_entities()
+    .filter(|entity| entity.contains::<Transform>() && !entity.contains::<ecs::Camera>())
+    .map(|entity| (entity.id(), entity.get::<Parent>().map(|parent| parent.0)))
+    .collect();
+  entities.sort();
+  let mut tree = Vec::with_capacity(entities.len());
+  // an entity whose parent isn't listed goes with the roots
+  let mut stack: Vec<(usize, ecs::Entity)> = entities
+    .iter()
+    .filter(|(_, parent)| parent.is_none_or(|parent| !entities.iter().any(|e| e.0 == parent)))
+    .rev()
+    .map(|&(entity, _)| (0, entity))
+    .collect();
+  while let Some((depth, entity)) = stack.pop() {
+    tree.push((depth, entity));
+    stack.extend(
+      entities
+        .iter()
+        .rev()
+        .filter(|(_, parent)| *parent == Some(entity))
+        .map(|&(child, _)| (depth + 1, child)),
+    );
+  }
+  tree
+}
+
+fn kind(world: &World, entity: ecs::Entity) -> &'static str {
+  if world.get::<MeshHandle>(entity).is_some() {
+    "model"
+  } else if world.get::<Light>(entity).is_some() {
+    "light"
+  } else if world.get::<Spin>(entity).is_some() {
+    "spinner"
+  } else {
+    "group"
+  }
+}
+
+fn fields(world: &World, entity: ecs::Entity) -> Vec<Field> {
+  let mut fields: Vec<Field> = (0..3).map(Field::Translation).collect();
+  fields.extend((0..3).map(Field::Rotation));
+  fields.push(Field::Scale);
+  if world.get::<Material>(entity).is_some() {
+    fields.extend((0..3).map(Field::BaseColor));
+    fields.extend([Field::Metallic, Field::Roughness]);
+  }
+  if world.get::<MeshHandle>(entity).is_some() {
+    fields.push(Field::Visible);
+  }
+  fields
+}
+
+fn describe(world: &World, entity: ecs::Entity, field: Field) -> String {
+  let transform = world.get::<Transform>(entity);
+  let material = world.get::<Material>(entity).map(|material| material.0);
+  match field {
+    Field::Translation(axis) => format!(
+      "position {} {:.2}",
+      AXES[axis],
+      transform.map_or(0.0, |t| t.translation[axis])
+    ),
+    Field::Rotation(axis) => format!("turn around {}", AXES[axis]),
+    Field::Scale => format!("scale {:.2}", transform.map_or(1.0, |t| t.scale[0])),
+    Field::BaseColor(channel) => format!(
+      "base color {} {:.2}",
+      CHANNELS[channel],
+      material.map_or(0.0, |m| m.base_color[channel])
+    ),
+    Field::Metallic => format!("metallic {:.2}", material.map_or(0.0, |m| m.metallic)),
+    Field::Roughness => format!("roughness {:.2}", material.map_or(0.0, |m| m.roughness)),
+    Field::Visible => format!(
+      "visible {}",
+      if world.get::<Hidden>(entity).is_some() {
+        "no"
+      } else {
+        "yes"
+      }
+    ),
+  }
+}
+
+// A step of `field` up for a `sign` of 1, down for -1. The systems take it from there: a
+// changed Transform moves the model, a changed Material uploads its factors.
+fn change(world: &mut World, entity: ecs::Entity, field: Field, sign: f32) {
+  let Some(mut entity) = world.get_entity_mut(entity) else {
+    return;
+  };
+  match field {
+    Field::Translation(axis) => {
+      if let Some(mut transform) = entity.get_mut::<Transform>() {
+        transform.translation[axis] += sign * MOVE_STEP;
+      }
+    }
+    Field::Rotation(axis) => {
+      if let Some(mut transform) = entity.get_mut::<Transform>() {
+        let mut around = [0.0; 3];
+        around[axis] = 1.0;
+        let turn = math::quat_axis_angle(around, sign * TURN_STEP);
+        transform.rotation = math::quat_mul(transform.rotation, turn);
+      }
+    }
+    Field::Scale => {
+      if let Some(mut transform) = entity.get_mut::<Transform>() {
+        let factor = SCALE_STEP.powf(sign);
+        transform.scale = transform.scale.map(|s| s * factor);
+      }
+    }
+    Field::BaseColor(_) | Field::Metallic | Field::Roughness => {
+      if let Some(mut material) = entity.get_mut::<Material>() {
+        let factor = match field {
+          Field::BaseColor(channel) => &mut material.0.base_color[channel],
+          Field::Metallic => &mut material.0.metallic,
+          _ => &mut material.0.roughness,
+        };
+        *factor = (*factor + sign * FACTOR_STEP).clamp(0.0, 1.0);
+      }
+    }
+    Field::Visible => {
+      if entity.contains::<Hidden>() {
+        entity.remove::<Hidden>();
+      } else {
+        entity.insert(Hidden);
+      }
+    }
+  }
+}
@@ -0,0 +1,848 @@
+use std::collections::BTreeMap;
+
+use gilrs::{Axis, Button, GamepadId};
+use serde::{Deserialize, Serialize};
+use winit::event::{ElementState, KeyboardInput, MouseButton, VirtualKeyCode, WindowEvent};
+
+// short names for the table below
+use KeyContext::{CursorGrab, Global, Inspector, PassList, Scene};
+use Trigger::{Key, Mouse, Pad, Stick};
+use VirtualKeyCode as K;
+
+// How far a stick has to be pushed for the actions bound to it to count as pressed
+const STICK_PRESS: f32 = 0.5;
+
+// A key, mouse button, gamepad button or a stick pushed one way. In settings.toml they're
+// written as the key's name ("Tab", "F5", "Back"), "Mouse" and the button ("Mouse Left",
+// "Mouse 4"), or "Pad" and gilrs' name of the button or axis with the direction
+// ("Pad South", "Pad LeftStickX-").
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Trigger {
+  Key(VirtualKeyCode),
+  Mouse(MouseButton),
+  Pad(Button),
+  Stick { axis: Axis, positive: bool },
+}
+
+impl Trigger {
+  // As the help lists it
+  pub fn label(self) -> String {
+    match self {
+      Trigger::Key(VirtualKeyCode::Back) => "Backspace".to_string(),
+      Trigger::Key(VirtualKeyCode::Return) => "Enter".to_string(),
+      Trigger::Key(VirtualKeyCode::Equals) => "=".to_string(),
+      Trigger::Key(VirtualKeyCode::Minus) => "-".to_string(),
+      Trigger::Key(key) => format!("{:?}", key),
+      Trigger::Mouse(MouseButton::Other(button)) => format!("mouse {}", button),
+      Trigger::Mouse(button) => format!("{:?} click", button).to_lowercase(),
+      Trigger::Pad(button) => format!("pad {:?}", button),
+      Trigger::Stick { axis, positive } => format!("pad {:?}{}", axis, sign(positive)),
+    }
+  }
+}
+
+impl std::fmt::Display for Trigger {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Trigger::Key(key) => write!(f, "{:?}", key),
+      Trigger::Mouse(MouseButton::Other(button)) => write!(f, "Mouse {}", button),
+      Trigger::Mouse(button) => write!(f, "Mouse {:?}", button),
+      Trigger::Pad(button) => write!(f, "Pad {:?}", button),
+      Trigger::Stick { axis, positive } => write!(f, "Pad {:?}{}", axis, sign(*positive)),
+    }
+  }
+}
+
+fn sign(positive: bool) -> char {
+  if positive {
+    '+'
+  } else {
+    '-'
+  }
+}
+
+// gilrs' serde names are the variant names too
+fn from_name<T: serde::de::DeserializeOwned>(name: &str) -> Option<T> {
+  toml::Value::String(name.to_string()).try_into().ok()
+}
+
+impl std::str::FromStr for Trigger {
+  type Err = InputError;
+
+  fn from_str(text: &str) -> Result<Self, Self::Err> {
+    let text = text.trim();
+    let invalid = || InputError::Trigger(text.to_string());
+    if let Some(button) = text.strip_prefix("Mouse ") {
+      let button = match button.trim() {
+        "Left" => MouseButton::Left,
+        "Right" => MouseButton::Right,
+        "Middle" => MouseButton::Middle,
+        other => MouseButton::Other(other.parse().map_err(|_| invalid())?),
+      };
+      return Ok(Trigger::Mouse(button));
+    }
+    if let Some(name) = text.strip_prefix("Pad ") {
+      let name = name.trim();
+      let stick = |axis: &str, positive| from_name(axis).map(|axis| Stick { axis, positive });
+      let trigger = match (name.strip_suffix('+'), name.strip_suffix('-')) {
+        (Some(axis), _) => stick(axis, true),
+        (_, Some(axis)) => stick(axis, false),
+        _ => from_name(name).map(Pad),
+      };
+      return trigger.ok_or_else(invalid);
+    }
+    // winit's serde names are the variant names, a string value deserializes into them
+    from_name(text).map(Key).ok_or_else(invalid)
+  }
+}
+
+impl TryFrom<String> for Trigger {
+  type Error = InputError;
+
+  fn try_from(text: String) -> Result<Self, Self::Error> {
+    text.parse()
+  }
+}
+
+impl From<Trigger> for String {
+  fn from(trigger: Trigger) -> Self {
+    trigger.to_string()
+  }
+}
+
+// When an action does something. The contexts that aren't always on come first, a trigger
+// bound in several of them goes to the first one that's on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum KeyContext {
+  // while the cursor is grabbed to look around, so Escape lets it go rather than quitting
+  CursorGrab,
+  // while the pass list is shown
+  PassList,
+  // while the inspector is shown
+  Inspector,
+  // while the shadowed scene is on
+  Scene,
+  // any time, in whichever window has the focus
+  Global,
+}
+
+impl KeyContext {
+  pub const ALL: [KeyContext; 5] = [
+    KeyContext::Global,
+    KeyContext::Scene,
+    KeyContext::PassList,
+    KeyContext::Inspector,
+    KeyContext::CursorGrab,
+  ];
+
+  fn title(self) -> &'static str {
+    match self {
+      KeyContext::Global => "keys (F1 hides)",
+      KeyContext::Scene => "scene (M)",
+      KeyContext::PassList => "pass list (F7)",
+      KeyContext::Inspector => "inspector (`)",
+      KeyContext::CursorGrab => "cursor grabbed (Tab in the scene)",
+    }
+  }
+}
+
+// Something the app does, set off by any of its triggers
+#[derive(Debug, Clone, Copy)]
+pub struct Action {
+  pub name: &'static str,
+  pub context: KeyContext,
+  // until settings.toml or bind() say otherwise
+  pub triggers: &'static [Trigger],
+  pub description: &'static str,
+}
+
+const fn action(
+  name: &'static str,
+  context: KeyContext,
+  triggers: &'static [Trigger],
+  description: &'static str,
+) -> Action {
+  Action {
+    name,
+    context,
+    triggers,
+    description,
+  }
+}
+
+const fn stick(axis: Axis, positive: bool) -> Trigger {
+  Stick { axis, positive }
+}
+
+// Every action there is, the help overlay lists them in this order
+pub const ACTIONS: &[Action] = &[
+  action(
+    "help",
+    Global,
+    &[Key(K::F1), Pad(Button::Select)],
+    "this help",
+  ),
+  action("quit", Global, &[Key(K::Escape)], "quit"),
+  action("new_window", Global, &[Key(K::N)], "open another window"),
+  action(
+    "swap_shader",
+    Global,
+    &[Key(K::Tab), Pad(Button::East)],
+    "next shader variant of the window",
+  ),
+  action(
+    "rainbow_shader",
+    Global,
+    &[Key(K::Space), Pad(Button::South)],
+    "the window's shader while held, rainbow once let go",
+  ),
+  action(
+    "pick",
+    Global,
+    &[Mouse(MouseButton::Left)],
+    "pick what's under the cursor in the scene",
+  ),
+  action(
+    "toggle_scene",
+    Global,
+    &[Key(K::M), Pad(Button::Start)],
+    "shadowed scene",
+  ),
+  action("toggle_boids", Global, &[Key(K::B)], "boids"),
+  action(
+    "step_mode",
+    Global,
+    &[Key(K::P)],
+    "real time or deterministic simulation steps",
+  ),
+  action(
+    "fast_forward",
+    Global,
+    &[Key(K::F)],
+    "fast forward the simulation",
+  ),
+  action(
+    "pause_simulation",
+    Global,
+    &[Key(K::Pause), Key(K::Period)],
+    "pause the simulation",
+  ),
+  action(
+    "pause_animation",
+    Global,
+    &[Key(K::Comma)],
+    "pause animated textures and the sky",
+  ),
+  action("render_path", Global, &[Key(K::G)], "next render path"),
+  action("toggle_ssao", Global, &[Key(K::O)], "ambient occlusion"),
+  action("antialiasing", Global, &[Key(K::X)], "next antialiasing"),
+  action("quality_tier", Global, &[Key(K::Q)], "next quality tier"),
+  action(
+    "toggle_anisotropy",
+    Global,
+    &[Key(K::A)],
+    "anisotropic filtering",
+  ),
+  action(
+    "exposure_mode",
+    Global,
+    &[Key(K::E)],
+    "auto or manual exposure",
+  ),
+  action(
+    "exposure_up",
+    Global,
+    &[Key(K::Equals)],
+    "exposure compensation up",
+  ),
+  action(
+    "exposure_down",
+    Global,
+    &[Key(K::Minus)],
+    "exposure compensation down",
+  ),
+  action("music", Global, &[Key(K::Key4)], "background music"),
+  action("volume_up", Global, &[Key(K::RBracket)], "volume up"),
+  action("volume_down", Global, &[Key(K::LBracket)], "volume down"),
+  action(
+    "compare_mode",
+    Global,
+    &[Key(K::C)],
+    "next shader comparison mode",
+  ),
+  action(
+    "color_filter",
+    Global,
+    &[Key(K::V)],
+    "next color blindness filter",
+  ),
+  action("high_contrast", Global, &[Key(K::H)], "high contrast"),
+  action(
+    "toggle_exclusive_fullscreen",
+    Global,
+    &[Key(K::F10)],
+    "exclusive fullscreen",
+  ),
+  action(
+    "toggle_fullscreen",
+    Global,
+    &[Key(K::F11)],
+    "borderless fullscreen",
+  ),
+  action("vsync", Global, &[Key(K::F6)], "vsync"),
+  action("screenshot", Global, &[Key(K::F12)], "screenshot"),
+  action("frame_graph", Global, &[Key(K::F9)], "save the frame graph"),
+  action("pass_list", Global, &[Key(K::F7)], "pass list"),
+  action(
+    "latency_probe",
+    Global,
+    &[Key(K::F8)],
+    "next latency probe mode",
+  ),
+  action(
+    "orbit_left",
+    Scene,
+    &[Key(K::Left), stick(Axis::LeftStickX, false)],
+    "orbit the camera left",
+  ),
+  action(
+    "orbit_right",
+    Scene,
+    &[Key(K::Right), stick(Axis::LeftStickX, true)],
+    "orbit the camera right",
+  ),
+  action(
+    "orbit_up",
+    Scene,
+    &[Key(K::Up), stick(Axis::LeftStickY, true)],
+    "orbit the camera up",
+  ),
+  action(
+    "orbit_down",
+    Scene,
+    &[Key(K::Down), stick(Axis::LeftStickY, false)],
+    "orbit the camera down",
+  ),
+  action(
+    "move_forward",
+    Scene,
+    &[Key(K::W), stick(Axis::RightStickY, true)],
+    "move the camera towards its target",
+  ),
+  action(
+    "move_back",
+    Scene,
+    &[Key(K::S), stick(Axis::RightStickY, false)],
+    "move the camera away from its target",
+  ),
+  action(
+    "split_screen",
+    Scene,
+    &[Key(K::F5)],
+    "two player split screen",
+  ),
+  action(
+    "p2_orbit_left",
+    Scene,
+    &[Key(K::Numpad4)],
+    "orbit the second player's camera left",
+  ),
+  action(
+    "p2_orbit_right",
+    Scene,
+    &[Key(K::Numpad6)],
+    "orbit the second player's camera right",
+  ),
+  action(
+    "p2_orbit_up",
+    Scene,
+    &[Key(K::Numpad8)],
+    "orbit the second player's camera up",
+  ),
+  action(
+    "p2_orbit_down",
+    Scene,
+    &[Key(K::Numpad2)],
+    "orbit the second player's camera down",
+  ),
+  action(
+    "p2_move_forward",
+    Scene,
+    &[Key(K::NumpadAdd)],
+    "move the second player's camera towards its target",
+  ),
+  action(
+    "p2_move_back",
+    Scene,
+    &[Key(K::NumpadSubtract)],
+    "move the second player's camera away from its target",
+  ),
+  action("toggle_terrain", Scene, &[Key(K::T)], "terrain"),
+  action("erode", Scene, &[Key(K::R)], "erode the terrain"),
+  action("reset_terrain", Scene, &[Key(K::Back)], "reset the terrain"),
+  action("toggle_water", Scene, &[Key(K::Semicolon)], "water"),
+  action("toggle_fog", Scene, &[Key(K::Apostrophe)], "volumetric fog"),
+  action("toggle_crowd", Scene, &[Key(K::K)], "crowd"),
+  action("toggle_skinning", Scene, &[Key(K::U)], "skinned meshes"),
+  action(
+    "play_animation",
+    Scene,
+    &[Key(K::Y)],
+    "play or pause the skinned meshes' clips",
+  ),
+  action(
+    "next_animation",
+    Scene,
+    &[Key(K::Z)],
+    "cross fade to the next clip",
+  ),
+  action("loop_animation", Scene, &[Key(K::D)], "loop the clips"),
+  action(
+    "orrery",
+    Scene,
+    &[Key(K::Key1)],
+    "planets moved by keyframed nodes",
+  ),
+  action(
+    "carousel",
+    Scene,
+    &[Key(K::Key2)],
+    "entities turning around a lamp",
+  ),
+  action(
+    "drop_cubes",
+    Scene,
+    &[Key(K::Key3)],
+    "cubes tumbling onto the ground, with the physics feature",
+  ),
+  action(
+    "save_scene",
+    Scene,
+    &[Key(K::Home)],
+    "save the scene to scene.ron",
+  ),
+  action(
+    "load_scene",
+    Scene,
+    &[Key(K::End)],
+    "load the scene from scene.ron",
+  ),
+  action(
+    "inspector",
+    Scene,
+    &[Key(K::Grave)],
+    "look at and change the entities",
+  ),
+  action(
+    "security_camera",
+    Scene,
+    &[Key(K::J)],
+    "a monitor showing a second camera",
+  ),
+  action(
+    "tick_outlines",
+    Scene,
+    &[Key(K::I)],
+    "crowd simulation tick outlines",
+  ),
+  action(
+    "point_lights",
+    Scene,
+    &[Key(K::L)],
+    "a few or a few hundred point lights",
+  ),
+  action("gizmos", Scene, &[Key(K::F2)], "gizmos"),
+  action("wireframe", Scene, &[Key(K::F3)], "wireframe"),
+  action("grid", Scene, &[Key(K::F4)], "ground grid"),
+  action(
+    "grab_cursor",
+    Scene,
+    &[Key(K::Tab)],
+    "hide the cursor and look around with the mouse",
+  ),
+  action(
+    "previous_pass",
+    PassList,
+    &[Key(K::PageUp)],
+    "select the pass above",
+  ),
+  action(
+    "next_pass",
+    PassList,
+    &[Key(K::PageDown)],
+    "select the pass below",
+  ),
+  action(
+    "switch_pass",
+    PassList,
+    &[Key(K::Return)],
+    "switch the selected pass",
+  ),
+  action(
+    "previous_node",
+    Inspector,
+    &[Key(K::Key5)],
+    "select the entity above",
+  ),
+  action(
+    "next_node",
+    Inspector,
+    &[Key(K::Key6)],
+    "select the entity below",
+  ),
+  action(
+    "previous_field",
+    Inspector,
+    &[Key(K::Key7)],
+    "select the field above",
+  ),
+  action(
+    "next_field",
+    Inspector,
+    &[Key(K::Key8)],
+    "select the field below",
+  ),
+  action(
+    "decrease_field",
+    Inspector,
+    &[Key(K::Key9)],
+    "step the selected field down",
+  ),
+  action(
+    "increase_field",
+    Inspector,
+    &[Key(K::Key0)],
+    "step the selected field up",
+  ),
+  action(
+    "release_cursor",
+    CursorGrab,
+    &[Key(K::Escape), Key(K::Tab)],
+    "show the cursor again",
+  ),
+];
+
+pub fn find(name: &str) -> Option<&'static Action> {
+  ACTIONS.iter().find(|action| action.name == name)
+}
+
+#[derive(Debug)]
+pub enum InputError {
+  UnknownAction(String),
+  // not a key, mouse button or gamepad name
+  Trigger(String),
+}
+
+impl std::fmt::Display for InputError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      InputError::UnknownAction(name) => write!(f, "unknown action {}", name),
+      InputError::Trigger(text) => write!(
+        f,
+        "{:?} isn't a key or button, expected e.g. \"Tab\", \"Mouse Left\" or \"Pad South\"",
+        text
+      ),
+    }
+  }
+}
+
+impl std::error::Error for InputError {}
+
+// The `[input]` section of settings.toml
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputSettings {
+  // how far from the middle a stick has to be before it counts at all, 0..1
+  pub dead_zone: f32,
+  // triggers by action name, replacing the defaults of the actions listed. Last, toml
+  // can't have plain values after a table.
+  pub bindings: BTreeMap<String, Vec<Trigger>>,
+}
+
+impl Default for InputSettings {
+  fn default() -> Self {
+    Self {
+      dead_zone: 0.15,
+      bindings: BTreeMap::new(),
+    }
+  }
+}
+
+// The other axis of the same stick, the dead zone is round rather than a cross
+fn partner(axis: Axis) -> Option<Axis> {
+  match axis {
+    Axis::LeftStickX => Some(Axis::LeftStickY),
+    Axis::LeftStickY => Some(Axis::LeftStickX),
+    Axis::RightStickX => Some(Axis::RightStickY),
+    Axis::RightStickY => Some(Axis::RightStickX),
+    _ => None,
+  }
+}
+
+// Turns window events into actions and keeps track of what's held down. Events come in
+// through event(), the frame's update() asks pressed() and the like.
+#[derive(Debug)]
+pub struct InputMap {
+  // the triggers of every action, in ACTIONS order
+  bindings: Vec<Vec<Trigger>>,
+  // down right now
+  held: Vec<Trigger>,
+  // since the last end_frame()
+  just_pressed: Vec<&'static str>,
+  just_released: Vec<&'static str>,
+  // the last value of every gamepad axis that moved, -1..1 and before the dead zone
+  axes: Vec<(Axis, f32)>,
+  dead_zone: f32,
+  // connected gamepads with their names
+  gamepads: Vec<(GamepadId, String)>,
+}
+
+impl InputMap {
+  // The defaults with `settings`' bindings over them, unknown actions are skipped
+  pub fn new(settings: &InputSettings) -> Self {
+    let mut map = Self {
+      bindings: ACTIONS.iter().map(|a| a.triggers.to_vec()).collect(),
+      held: Vec::new(),
+      just_pressed: Vec::new(),
+      just_released: Vec::new(),
+      axes: Vec::new(),
+      dead_zone: settings.dead_zone.clamp(0.0, 0.99),
+      gamepads: Vec::new(),
+    };
+    for (action, triggers) in &settings.bindings {
+      if let Err(e) = map.bind(action, triggers.clone()) {
+        log::warn!("{}", e);
+      }
+    }
+    map
+  }
+
+  fn index(action: &str) -> Result<usize, InputError> {
+    ACTIONS
+      .iter()
+      .position(|a| a.name == action)
+      .ok_or_else(|| InputError::UnknownAction(action.to_string()))
+  }
+
+  // Replaces the triggers of `action`, none leaves it unbound
+  pub fn bind(&mut self, action: &str, triggers: Vec<Trigger>) -> Result<(), InputError> {
+    let index = Self::index(action)?;
+    self.bindings[index] = triggers;
+    Ok(())
+  }
+
+  pub fn triggers(&self, action: &str) -> &[Trigger] {
+    Self::index(action).map_or(&[], |index| &self.bindings[index])
+  }
+
+  // The actions a key or button going down or up sets off, in the order of their contexts.
+  // Key repeats of a held key set off nothing, and losing the focus releases everything.
+  pub fn event(&mut self, event: &WindowEvent) -> Vec<(&'static str, ElementState)> {
+    let (trigger, state) = match event {
+      WindowEvent::KeyboardInput {
+        input:
+          KeyboardInput {
+            state,
+            virtual_keycode: Some(key),
+            ..
+          },
+        ..
+      } => (Trigger::Key(*key), *state),
+      WindowEvent::MouseInput { state, button, .. } => (Trigger::Mouse(*button), *state),
+      WindowEvent::Focused(false) => {
+        return std::mem::take(&mut self.held)
+          .into_iter()
+          .flat_map(|trigger| self.record(trigger, ElementState::Released))
+          .collect()
+      }
+      _ => return Vec::new(),
+    };
+    self.change(trigger, state)
+  }
+
+  // Like event() for a gamepad button, which gilrs reports instead of winit
+  pub fn pad_button(
+    &mut self,
+    button: Button,
+    state: ElementState,
+  ) -> Vec<(&'static str, ElementState)> {
+    self.change(Trigger::Pad(button), state)
+  }
+
+  // A gamepad axis moved to `value`, the actions of a stick pushed past halfway are pressed
+  pub fn pad_axis(&mut self, axis: Axis, value: f32) -> Vec<(&'static str, ElementState)> {
+    match self.axes.iter_mut().find(|(a, _)| *a == axis) {
+      Some((_, v)) => *v = value,
+      None => self.axes.push((axis, value)),
+    }
+    // the other axis of the stick goes in and out of the dead zone with this one
+    let axes = [Some(axis), partner(axis)];
+    let mut actions = Vec::new();
+    for axis in axes.into_iter().flatten() {
+      for positive in [true, false] {
+        let trigger = Trigger::Stick { axis, positive };
+        let state = if self.stick(axis, positive) > STICK_PRESS {
+          ElementState::Pressed
+        } else {
+          ElementState::Released
+        };
+        actions.extend(self.change(trigger, state));
+      }
+    }
+    actions
+  }
+
+  pub fn pad_connected(&mut self, id: GamepadId, name: String) {
+    log::info!("gamepad connected: {}", name);
+    self.gamepads.retain(|(other, _)| *other != id);
+    self.gamepads.push((id, name));
+  }
+
+  // Releases whatever is held on any gamepad, gilrs doesn't say which pad a button was on
+  // once it's gone
+  pub fn pad_disconnected(&mut self, id: GamepadId) -> Vec<(&'static str, ElementState)> {
+    if let Some(index) = self.gamepads.iter().position(|(other, _)| *other == id) {
+      let (_, name) = self.gamepads.remove(index);
+      log::info!("gamepad disconnected: {}", name);
+    }
+    self.axes.clear();
+    let pad_triggers: Vec<_> = self
+      .held
+      .iter()
+      .copied()
+      .filter(|t| matches!(t, Trigger::Pad(_) | Trigger::Stick { .. }))
+      .collect();
+    pad_triggers
+      .into_iter()
+      .flat_map(|trigger| self.change(trigger, ElementState::Released))
+      .collect()
+  }
+
+  // Names of the gamepads plugged in
+  pub fn gamepads(&self) -> impl Iterator<Item = &str> {
+    self.gamepads.iter().map(|(_, name)| name.as_str())
+  }
+
+  // How far the stick is pushed in one direction of `axis`, 0..1 with the dead zone taken
+  // out so it still starts at 0 right past it
+  fn stick(&self, axis: Axis, positive: bool) -> f32 {
+    let value = |axis| {
+      self
+        .axes
+        .iter()
+        .find(|(a, _)| *a == axis)
+        .map_or(0.0, |&(_, v)| v)
+    };
+    let v = value(axis);
+    let length = match partner(axis) {
+      Some(other) => v.hypot(value(other)),
+      None => v.abs(),
+    };
+    if length <= self.dead_zone {
+      return 0.0;
+    }
+    let scaled = v / length * ((length - self.dead_zone) / (1.0 - self.dead_zone)).min(1.0);
+    if positive {
+      scaled.max(0.0)
+    } else {
+      (-scaled).max(0.0)
+    }
+  }
+
+  // Records `trigger` going down or up, nothing if it already was
+  fn change(&mut self, trigger: Trigger, state: ElementState) -> Vec<(&'static str, ElementState)> {
+    let held = self.held.iter().position(|&t| t == trigger);
+    match (state, held) {
+      (ElementState::Pressed, None) => self.held.push(trigger),
+      (ElementState::Released, Some(index)) => {
+        self.held.swap_remove(index);
+      }
+      _ => return Vec::new(),
+    }
+    self.record(trigger, state)
+  }
+
+  fn record(&mut self, trigger: Trigger, state: ElementState) -> Vec<(&'static str, ElementState)> {
+    let mut actions: Vec<_> = ACTIONS
+      .iter()
+      .zip(&self.bindings)
+      .filter(|(_, triggers)| triggers.contains(&trigger))
+      .map(|(action, _)| action)
+      .collect();
+    actions.sort_by_key(|action| action.context);
+    let changed = match state {
+      ElementState::Pressed => &mut self.just_pressed,
+      ElementState::Released => &mut self.just_released,
+    };
+    changed.extend(actions.iter().map(|action| action.name));
+    actions
+      .into_iter()
+      .map(|action| (action.name, state))
+      .collect()
+  }
+
+  // Whether any of the action's triggers is down
+  pub fn pressed(&self, action: &str) -> bool {
+    self.triggers(action).iter().any(|t| self.held.contains(t))
+  }
+
+  // How much the action is held, 0..1: 1 for a key or button down and as far as the stick is
+  // pushed for sticks
+  pub fn value(&self, action: &str) -> f32 {
+    self
+      .triggers(action)
+      .iter()
+      .map(|&trigger| match trigger {
+        Trigger::Stick { axis, positive } => self.stick(axis, positive),
+        _ => self.held.contains(&trigger) as u8 as f32,
+      })
+      .fold(0.0, f32::max)
+  }
+
+  // Whether the action went down since the last end_frame(), even if it's up again
+  pub fn just_pressed(&self, action: &str) -> bool {
+    self.just_pressed.contains(&action)
+  }
+
+  pub fn just_released(&self, action: &str) -> bool {
+    self.just_released.contains(&action)
+  }
+
+  // Call at the end of update(), the next frame starts with nothing just pressed
+  pub fn end_frame(&mut self) {
+    self.just_pressed.clear();
+    self.just_released.clear();
+  }
+
+  // The overlay lines, one heading per context and a line per action with its current
+  // triggers. The contexts `active` says are off are marked so.
+  pub fn help(&self, active: impl Fn(KeyContext) -> bool) -> String {
+    let mut text = String::new();
+    for context in KeyContext::ALL {
+      if !text.is_empty() {
+        text += "\n";
+      }
+      text += context.title();
+      if !active(context) {
+        text += " - off";
+      }
+      for (action, triggers) in ACTIONS.iter().zip(&self.bindings) {
+        if action.context != context {
+          continue;
+        }
+        let labels: Vec<_> = triggers.iter().map(|t| t.label()).collect();
+        let labels = if labels.is_empty() {
+          "unbound".to_string()
+        } else {
+          labels.join(" ")
+        };
+        text += &format!("\n  {}  {}", labels, action.description);
+      }
+    }
+    for name in self.gamepads() {
+      text += &format!("\ngamepad: {}", name);
+    }
+    text
+  }
+}
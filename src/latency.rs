@@ -0,0 +1,180 @@
+use std::collections::VecDeque;
+
+use instant::Instant;
+use wgpu::{CommandEncoder, Device, TextureFormat, TextureView};
+use winit::window::WindowId;
+
+use crate::{
+  pipeline::flash_pipe,
+  render_region::{PixelRect, RegionPass},
+  stats::count_draw,
+};
+
+// Latencies kept for the statistics, older ones drop out
+const MAX_SAMPLES: usize = 200;
+// Side of the flashed square in the top right corner, in pixels
+const QUAD_SIZE: u32 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlashMode {
+  Off,
+  // a small square, enough to see the measurement work
+  Quad,
+  // the whole window, for a photodiode to check the numbers end to end
+  FullScreen,
+}
+
+impl FlashMode {
+  pub fn next(self) -> Self {
+    match self {
+      FlashMode::Off => FlashMode::Quad,
+      FlashMode::Quad => FlashMode::FullScreen,
+      FlashMode::FullScreen => FlashMode::Off,
+    }
+  }
+}
+
+// Click to present latency: a click flashes the next frame of its window white and the time
+// from the click to that frame's present() is recorded. Only covers the app's side, the
+// compositor and the display add their own on top, which the full screen flash can measure.
+pub struct LatencyProbe {
+  mode: FlashMode,
+  // the click waiting for a frame to show it
+  pending: Option<(WindowId, Instant)>,
+  // the click the frame being recorded shows
+  flashing: Option<(WindowId, Instant)>,
+  // milliseconds, oldest first
+  samples: VecDeque<f32>,
+  pipeline: Option<(TextureFormat, wgpu::RenderPipeline)>,
+}
+
+impl Default for LatencyProbe {
+  fn default() -> Self {
+    Self {
+      mode: FlashMode::Off,
+      pending: None,
+      flashing: None,
+      samples: VecDeque::with_capacity(MAX_SAMPLES),
+      pipeline: None,
+    }
+  }
+}
+
+impl LatencyProbe {
+  pub fn is_active(&self) -> bool {
+    self.mode != FlashMode::Off
+  }
+
+  // Off, quad, full screen and off again, every mode starts with no samples
+  pub fn next_mode(&mut self) -> FlashMode {
+    self.mode = self.mode.next();
+    self.pending = None;
+    self.flashing = None;
+    self.samples.clear();
+    self.mode
+  }
+
+  // A mouse button went down in `window`, clicks while one is still waiting are ignored
+  pub fn click(&mut self, window: WindowId) {
+    if self.is_active() && self.pending.is_none() && self.flashing.is_none() {
+      self.pending = Some((window, Instant::now()));
+    }
+  }
+
+  // Draws the flash over `view` when `window` has a click waiting, last thing before the
+  // frame is submitted. Returns whether it did.
+  pub fn render(
+    &mut self,
+    device: &Device,
+    encoder: &mut CommandEncoder,
+    window: WindowId,
+    view: &TextureView,
+    format: TextureFormat,
+    (width, height): (u32, u32),
+  ) -> bool {
+    match self.pending {
+      Some((pending, _)) if pending == window => {}
+      _ => return false,
+    }
+    self.flashing = self.pending.take();
+
+    if !matches!(&self.pipeline, Some((f, _)) if *f == format) {
+      self.pipeline = Some((format, flash_pipe(device, format)));
+    }
+    let Some((_, pipeline)) = &self.pipeline else {
+      return false;
+    };
+    let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+      label: Some("Latency Flash Pass"),
+      color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+        view,
+        resolve_target: None,
+        ops: wgpu::Operations {
+          load: wgpu::LoadOp::Load,
+          store: true,
+        },
+      })],
+      depth_stencil_attachment: None,
+    });
+    let mut pass = RegionPass::new(&mut pass, (width, height));
+    if self.mode == FlashMode::Quad {
+      let size = QUAD_SIZE.min(width).min(height);
+      let corner = PixelRect {
+        x: width - size,
+        y: 0,
+        width: size,
+        height: size,
+      };
+      if let Err(e) = pass.set_scissor_rect(corner) {
+        log::warn!("latency flash: {}", e);
+        return false;
+      }
+    }
+    pass.set_pipeline(pipeline);
+    count_draw();
+    pass.draw(0..3, 0..1);
+    true
+  }
+
+  // Call right after `window`'s present(), finishes the measurement of a flashed frame
+  pub fn presented(&mut self, window: WindowId) {
+    match self.flashing {
+      Some((flashing, _)) if flashing == window => {}
+      _ => return,
+    }
+    let Some((_, clicked)) = self.flashing.take() else {
+      return;
+    };
+    let latency = clicked.elapsed().as_secs_f32() * 1000.0;
+    if self.samples.len() == MAX_SAMPLES {
+      self.samples.pop_front();
+    }
+    self.samples.push_back(latency);
+    log::info!("click to present {:.1}ms | {}", latency, self.summary());
+  }
+
+  // Distribution of the samples so far
+  pub fn summary(&self) -> String {
+    if self.samples.is_empty() {
+      return "no samples, click to measure".to_string();
+    }
+    let mut sorted: Vec<f32> = self.samples.iter().copied().collect();
+    sorted.sort_by(f32::total_cmp);
+    let percentile = |p: f32| sorted[((sorted.len() - 1) as f32 * p).round() as usize];
+    let mean = sorted.iter().sum::<f32>() / sorted.len() as f32;
+    format!(
+      "n {} | min {:.1} median {:.1} mean {:.1} p95 {:.1} max {:.1} ms",
+      sorted.len(),
+      sorted[0],
+      percentile(0.5),
+      mean,
+      percentile(0.95),
+      sorted[sorted.len() - 1]
+    )
+  }
+
+  // The overlay lines while a mode is on
+  pub fn overlay(&self) -> String {
+    format!("latency ({:?}, F8)\n{}", self.mode, self.summary())
+  }
+}
@@ -0,0 +1,98 @@
+pub mod accessibility;
+pub mod adapter;
+pub mod animated_texture;
+pub mod animation;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod asset_manager;
+pub mod assets;
+pub mod audio;
+pub mod boids;
+pub mod bridge;
+pub mod camera;
+pub mod cli;
+pub mod clock;
+pub mod clusters;
+pub mod compare;
+pub mod config;
+pub mod crowd;
+pub mod culling;
+pub mod debug_draw;
+pub mod deferred;
+pub mod ecs;
+pub mod exposure;
+pub mod fog;
+pub mod frame_graph;
+pub mod fxaa;
+pub mod gamepad;
+pub mod gpu_memory;
+pub mod hdr;
+pub mod hiz;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod hot_reload;
+pub mod ibl;
+pub mod inspector;
+pub mod keymap;
+pub mod latency;
+pub mod lighting;
+pub mod lsystem;
+pub mod math;
+pub mod mesh;
+pub mod mesh_cache;
+pub mod mipmap;
+pub mod net_sync;
+pub mod orrery;
+pub mod params;
+pub mod pass_toggles;
+pub mod pbr;
+#[cfg(feature = "physics")]
+pub mod physics;
+pub mod picking;
+pub mod pipeline;
+pub mod plants;
+pub mod power;
+pub mod preprocessor;
+pub mod profiler;
+pub mod quality;
+pub mod raycast;
+pub mod reflection;
+pub mod render_region;
+pub mod render_settings;
+pub mod render_target;
+pub mod sampler;
+pub mod scene;
+pub mod scene_file;
+pub mod screenshot;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod scripting;
+pub mod security_camera;
+pub mod shader_file;
+pub mod shader_variants;
+pub mod shadow;
+pub mod shaping;
+pub mod simulation;
+pub mod skinning;
+pub mod skybox;
+pub mod sort;
+pub mod split_screen;
+pub mod ssao;
+pub mod state;
+pub mod stats;
+pub mod storage;
+pub mod taa;
+pub mod terrain;
+pub mod text;
+pub mod texture;
+pub mod texture_atlas;
+pub mod touch;
+pub mod tween;
+pub mod uploader;
+pub mod video_wall;
+pub mod viewport;
+pub mod water;
+#[cfg(all(feature = "webcam", target_os = "linux"))]
+pub mod webcam;
+pub mod window_runner;
+pub mod window_settings;
+pub mod worker;
+
+pub use window_runner::run;
@@ -0,0 +1,119 @@
+use serde::{Deserialize, Serialize};
+
+use crate::math::{self, next_random, Vec3};
+
+// Size of the point light storage buffer, lights past it are ignored
+pub const MAX_POINT_LIGHTS: usize = 1024;
+
+// Matches `PointLight` in lighting.wgsl and clusters.wgsl
+#[repr(C)]
+#[derive(
+  Debug, Clone, Copy, PartialEq, Serialize, Deserialize, bytemuck::Pod, bytemuck::Zeroable,
+)]
+pub struct PointLight {
+  pub position: Vec3,
+  // the light fades out to nothing at this distance
+  pub range: f32,
+  pub color: [f32; 3],
+  pub intensity: f32,
+}
+
+// Matches `Lighting` in lighting.wgsl, the point lights go to LightClusters
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightingUniform {
+  ambient: [f32; 4],
+  // xyz: unit vector towards the sun
+  sun_direction: [f32; 4],
+  sun_color: [f32; 4],
+}
+
+// One shadow casting directional light (the sun) plus any number of point lights
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Lighting {
+  pub ambient: [f32; 3],
+  sun_direction: Vec3,
+  pub sun_color: [f32; 3],
+  point_lights: Vec<PointLight>,
+}
+
+impl Default for Lighting {
+  fn default() -> Self {
+    let point = |position: Vec3, color: [f32; 3]| PointLight {
+      position,
+      range: 4.0,
+      color,
+      intensity: 4.0,
+    };
+    Self {
+      ambient: [0.08, 0.09, 0.12],
+      sun_direction: math::normalize([-0.6, 1.0, 0.4]),
+      sun_color: [2.0, 1.9, 1.75],
+      point_lights: vec![
+        point([-1.5, 1.0, 3.0], [1.0, 0.5, 0.2]),
+        point([3.0, 1.5, 0.5], [0.2, 0.5, 1.0]),
+        point([-2.5, 1.2, -2.5], [0.8, 0.2, 0.9]),
+      ],
+    }
+  }
+}
+
+impl Lighting {
+  pub fn sun_direction(&self) -> Vec3 {
+    self.sun_direction
+  }
+
+  pub fn set_sun_direction(&mut self, towards_sun: Vec3) {
+    self.sun_direction = math::normalize(towards_sun);
+  }
+
+  pub fn point_lights(&self) -> &[PointLight] {
+    &self.point_lights
+  }
+
+  // Returns false when all MAX_POINT_LIGHTS slots are taken
+  pub fn add_point_light(&mut self, light: PointLight) -> bool {
+    if self.point_lights.len() >= MAX_POINT_LIGHTS {
+      return false;
+    }
+    self.point_lights.push(light);
+    true
+  }
+
+  pub fn clear_point_lights(&mut self) {
+    self.point_lights.clear();
+  }
+
+  // Replaces the point lights with `count` small lights of random colors hovering over a
+  // `radius` sized disc around the origin
+  pub fn scatter_point_lights(&mut self, count: usize, radius: f32, seed: u32) {
+    let mut rng = seed.max(1);
+    self.point_lights = (0..count.min(MAX_POINT_LIGHTS))
+      .map(|_| {
+        let angle = next_random(&mut rng) * std::f32::consts::TAU;
+        // square root so they spread evenly over the disc instead of bunching in the middle
+        let distance = next_random(&mut rng).sqrt() * radius;
+        let height = 0.2 + next_random(&mut rng) * 1.3;
+        // a fully saturated hue, so no light comes out grey
+        let color = math::hue_to_rgb(next_random(&mut rng));
+        PointLight {
+          position: [angle.cos() * distance, height, angle.sin() * distance],
+          range: 1.5,
+          color,
+          intensity: 2.0,
+        }
+      })
+      .collect();
+  }
+
+  pub fn uniform(&self) -> LightingUniform {
+    let [ax, ay, az] = self.ambient;
+    let [dx, dy, dz] = self.sun_direction;
+    let [r, g, b] = self.sun_color;
+    LightingUniform {
+      ambient: [ax, ay, az, 0.0],
+      sun_direction: [dx, dy, dz, 0.0],
+      sun_color: [r, g, b, 0.0],
+    }
+  }
+}
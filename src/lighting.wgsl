@@ -0,0 +1,120 @@
+// The sun, its shadow map and the clustered point lights, with the Blinn-Phong shading
+// the forward and the deferred path share. Pulled in with `#include "lighting.wgsl"`.
+
+#include "common.wgsl"
+
+struct PointLight {
+    position: vec3<f32>,
+    range: f32,
+    color: vec3<f32>,
+    intensity: f32,
+};
+
+struct Lighting {
+    ambient: vec4<f32>,
+    // xyz: unit vector towards the sun
+    sun_direction: vec4<f32>,
+    sun_color: vec4<f32>,
+};
+@group(0) @binding(1)
+var<uniform> lighting: Lighting;
+
+// Filled in by the light culling pass in clusters.wgsl, the counts are defined by
+// clusters.rs
+const CLUSTERS_X: u32 = #{CLUSTERS_X}u;
+const CLUSTERS_Y: u32 = #{CLUSTERS_Y}u;
+const CLUSTERS_Z: u32 = #{CLUSTERS_Z}u;
+const MAX_CLUSTER_LIGHTS: u32 = #{MAX_CLUSTER_LIGHTS}u;
+
+struct ClusterParams {
+    view: mat4x4<f32>,
+    inverse_projection: mat4x4<f32>,
+    near: f32,
+    far: f32,
+    light_count: u32,
+};
+@group(0) @binding(2)
+var<storage, read> point_lights: array<PointLight>;
+@group(0) @binding(3)
+var<uniform> clusters: ClusterParams;
+// per cluster the light count followed by MAX_CLUSTER_LIGHTS indices into point_lights
+@group(0) @binding(4)
+var<storage, read> cluster_lights: array<u32>;
+
+// Blinn-Phong exponents are stored divided by this in the G-buffer's 8 bit material target
+const MAX_SHININESS: f32 = 256.0;
+
+@group(1) @binding(0)
+var shadow_map: texture_depth_2d;
+@group(1) @binding(1)
+var shadow_sampler: sampler_comparison;
+
+// 3x3 taps of the comparison sampler, each already a bilinear blend of 4 depth tests
+fn shadow_factor(light_position: vec4<f32>) -> f32 {
+    let ndc = light_position.xyz / light_position.w;
+    // clip space y points up, texture v points down
+    let uv = ndc.xy * vec2<f32>(0.5, -0.5) + 0.5;
+    if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || ndc.z > 1.0 {
+        return 1.0;
+    }
+    let texel = 1.0 / vec2<f32>(textureDimensions(shadow_map));
+    var lit = 0.0;
+    for (var y = -1; y <= 1; y += 1) {
+        for (var x = -1; x <= 1; x += 1) {
+            let offset = vec2<f32>(f32(x), f32(y)) * texel;
+            lit += textureSampleCompareLevel(shadow_map, shadow_sampler, uv + offset, ndc.z);
+        }
+    }
+    return lit / 9.0;
+}
+
+// Where the lights of the cluster around `position` start in cluster_lights
+fn cluster_start(position: vec3<f32>) -> u32 {
+    let clip = globals.view_proj * vec4<f32>(position, 1.0);
+    let counts = vec2<f32>(f32(CLUSTERS_X), f32(CLUSTERS_Y));
+    let tile = clamp((clip.xy / clip.w * 0.5 + 0.5) * counts, vec2<f32>(0.0), counts - 1.0);
+    // clip.w is the distance along the view direction, the slices are spaced exponentially
+    let depth = log(max(clip.w, clusters.near) / clusters.near) / log(clusters.far / clusters.near);
+    let slice = min(u32(depth * f32(CLUSTERS_Z)), CLUSTERS_Z - 1u);
+    let cluster = (slice * CLUSTERS_Y + u32(tile.y)) * CLUSTERS_X + u32(tile.x);
+    return cluster * (MAX_CLUSTER_LIGHTS + 1u);
+}
+
+// Inverse square falloff, windowed so it reaches exactly 0 at `range`
+fn attenuation(distance: f32, range: f32) -> f32 {
+    let window = saturate(1.0 - pow(distance / range, 4.0));
+    return window * window / (distance * distance + 1.0);
+}
+
+// Diffuse + specular from one light, `to_light` and `to_camera` are unit vectors
+fn blinn_phong(normal: vec3<f32>, to_light: vec3<f32>, to_camera: vec3<f32>, albedo: vec3<f32>, material: vec2<f32>) -> vec3<f32> {
+    let n_dot_l = dot(normal, to_light);
+    if n_dot_l <= 0.0 {
+        return vec3<f32>(0.0);
+    }
+    // the half vector stands in for Phong's reflection vector, cheaper and rounder highlights
+    let half_dir = normalize(to_light + to_camera);
+    let specular = material.x * pow(max(dot(normal, half_dir), 0.0), material.y);
+    return albedo * n_dot_l + vec3<f32>(specular);
+}
+
+// Ambient, the shadowed sun and the point lights on one surface point, shared by the forward
+// and the deferred path. `occlusion` only darkens the ambient term, direct light has its own
+// shadows.
+fn shade_blinn_phong(position: vec3<f32>, normal: vec3<f32>, albedo: vec3<f32>, material: vec2<f32>, light_position: vec4<f32>, occlusion: f32) -> vec3<f32> {
+    let to_camera = normalize(globals.camera_position.xyz - position);
+
+    var color = lighting.ambient.rgb * albedo * occlusion;
+    let sun = blinn_phong(normal, lighting.sun_direction.xyz, to_camera, albedo, material);
+    color += lighting.sun_color.rgb * sun * shadow_factor(light_position);
+
+    let start = cluster_start(position);
+    for (var i = 0u; i < min(cluster_lights[start], MAX_CLUSTER_LIGHTS); i += 1u) {
+        let light = point_lights[cluster_lights[start + 1u + i]];
+        let offset = light.position - position;
+        let distance = length(offset);
+        let lit = blinn_phong(normal, offset / distance, to_camera, albedo, material);
+        color += light.color * light.intensity * attenuation(distance, light.range) * lit;
+    }
+    return color;
+}
@@ -0,0 +1,285 @@
+use crate::{
+  math::{self, next_random, Vec3},
+  mesh::{Mesh, MeshVertex},
+};
+
+// Expansion stops early once the string gets this long, a few iterations too many would
+// otherwise eat all memory
+const MAX_SYMBOLS: usize = 200_000;
+
+// `symbol` is replaced by `replacement` every iteration
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+  pub symbol: char,
+  pub replacement: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LSystemError {
+  MissingAxiom,
+  // the offending rule, it should look like `F=FF`
+  InvalidRule(String),
+  UnbalancedBrackets(String),
+}
+
+impl std::fmt::Display for LSystemError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      LSystemError::MissingAxiom => write!(f, "the L-system has no axiom"),
+      LSystemError::InvalidRule(rule) => {
+        write!(f, "invalid rule {:?}, expected something like F=FF", rule)
+      }
+      LSystemError::UnbalancedBrackets(text) => write!(f, "unbalanced brackets in {:?}", text),
+    }
+  }
+}
+
+impl std::error::Error for LSystemError {}
+
+// A bracketed L-system. A symbol with several rules picks one at random every time it's
+// rewritten, so different seeds grow different plants from the same rules.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LSystem {
+  pub axiom: String,
+  pub rules: Vec<Rule>,
+}
+
+impl LSystem {
+  // The axiom followed by the rules, separated by `;` or new lines:
+  //
+  //   X; X=F[+X][-X]FX; F=FF
+  pub fn parse(text: &str) -> Result<Self, LSystemError> {
+    let mut parts = text
+      .split([';', '\n'])
+      .map(str::trim)
+      .filter(|part| !part.is_empty());
+    let axiom = parts.next().ok_or(LSystemError::MissingAxiom)?;
+    if axiom.contains('=') {
+      return Err(LSystemError::MissingAxiom);
+    }
+    check_brackets(axiom)?;
+    let rules = parts
+      .map(|part| {
+        let (symbol, replacement) = part
+          .split_once('=')
+          .ok_or_else(|| LSystemError::InvalidRule(part.to_string()))?;
+        let mut symbol_chars = symbol.trim().chars();
+        let (Some(symbol), None) = (symbol_chars.next(), symbol_chars.next()) else {
+          return Err(LSystemError::InvalidRule(part.to_string()));
+        };
+        let replacement = replacement.trim();
+        check_brackets(replacement)?;
+        Ok(Rule {
+          symbol,
+          replacement: replacement.to_string(),
+        })
+      })
+      .collect::<Result<_, _>>()?;
+    Ok(Self {
+      axiom: axiom.to_string(),
+      rules,
+    })
+  }
+
+  // Rewrites every symbol in parallel `iterations` times
+  pub fn expand(&self, iterations: u32, seed: u32) -> String {
+    let mut rng = seed.max(1);
+    let mut current = self.axiom.clone();
+    for iteration in 0..iterations {
+      let mut next = String::with_capacity(current.len() * 2);
+      for symbol in current.chars() {
+        let matching: Vec<&Rule> = self.rules.iter().filter(|r| r.symbol == symbol).collect();
+        if matching.is_empty() {
+          next.push(symbol);
+        } else {
+          let pick = (next_random(&mut rng) * matching.len() as f32) as usize;
+          next.push_str(&matching[pick.min(matching.len() - 1)].replacement);
+        }
+      }
+      if next.len() > MAX_SYMBOLS {
+        log::warn!(
+          "L-system grew past {} symbols, stopping after {} of {} iterations",
+          MAX_SYMBOLS,
+          iteration,
+          iterations
+        );
+        break;
+      }
+      current = next;
+    }
+    current
+  }
+}
+
+fn check_brackets(text: &str) -> Result<(), LSystemError> {
+  let mut depth = 0i32;
+  for symbol in text.chars() {
+    match symbol {
+      '[' => depth += 1,
+      ']' => depth -= 1,
+      _ => {}
+    }
+    if depth < 0 {
+      break;
+    }
+  }
+  if depth != 0 {
+    return Err(LSystemError::UnbalancedBrackets(text.to_string()));
+  }
+  Ok(())
+}
+
+// How the turtle draws an expanded L-system
+#[derive(Debug, Clone, Copy)]
+pub struct TurtleSettings {
+  pub angle_degrees: f32,
+  // random extra turn of up to this much either way on every rotation
+  pub jitter_degrees: f32,
+  // branch radius at the root, relative to the height of the plant
+  pub radius: f32,
+  // radius of a branch relative to its parent
+  pub radius_decay: f32,
+  // corners around each branch segment
+  pub sides: u32,
+}
+
+impl Default for TurtleSettings {
+  fn default() -> Self {
+    Self {
+      angle_degrees: 25.0,
+      jitter_degrees: 8.0,
+      radius: 0.02,
+      radius_decay: 0.7,
+      sides: 6,
+    }
+  }
+}
+
+#[derive(Clone, Copy)]
+struct Turtle {
+  position: Vec3,
+  heading: Vec3,
+  left: Vec3,
+  up: Vec3,
+  radius: f32,
+}
+
+impl Turtle {
+  fn rotate(&mut self, axis: Vec3, angle: f32) {
+    self.heading = rotate(self.heading, axis, angle);
+    self.left = rotate(self.left, axis, angle);
+    self.up = rotate(self.up, axis, angle);
+  }
+}
+
+// Rodrigues' rotation of `v` around the unit vector `axis`
+fn rotate(v: Vec3, axis: Vec3, angle: f32) -> Vec3 {
+  let (sin, cos) = angle.sin_cos();
+  let along = math::scale(axis, math::dot(axis, v) * (1.0 - cos));
+  math::add(
+    math::add(math::scale(v, cos), math::scale(math::cross(axis, v), sin)),
+    along,
+  )
+}
+
+// Turtle graphics in 3D, starting at the origin heading up +Y:
+//   F G   draw a branch segment forward       f   move forward without drawing
+//   + -   turn left / right                   & ^ pitch down / up
+//   \ /   roll left / right                   |   turn around
+//   [ ]   start / end a thinner side branch   !   thin the current branch
+// Anything else, like the X placeholders many plants use, is skipped. The mesh is scaled to
+// be one unit tall.
+pub fn build_mesh(symbols: &str, settings: &TurtleSettings, seed: u32) -> Mesh {
+  let mut rng = seed.max(1);
+  let mut turtle = Turtle {
+    position: [0.0; 3],
+    heading: [0.0, 1.0, 0.0],
+    left: [-1.0, 0.0, 0.0],
+    up: [0.0, 0.0, 1.0],
+    radius: settings.radius,
+  };
+  let mut stack = Vec::new();
+  // the turtle at the start of each branch segment and where the segment ends
+  let mut segments = Vec::new();
+  let angle = settings.angle_degrees.to_radians();
+  let jitter = settings.jitter_degrees.to_radians();
+
+  for symbol in symbols.chars() {
+    let mut turn = || angle + (next_random(&mut rng) * 2.0 - 1.0) * jitter;
+    match symbol {
+      'F' | 'G' => {
+        let end = math::add(turtle.position, turtle.heading);
+        segments.push((turtle, end));
+        turtle.position = end;
+      }
+      'f' => turtle.position = math::add(turtle.position, turtle.heading),
+      '+' => turtle.rotate(turtle.up, turn()),
+      '-' => turtle.rotate(turtle.up, -turn()),
+      '&' => turtle.rotate(turtle.left, turn()),
+      '^' => turtle.rotate(turtle.left, -turn()),
+      '\\' => turtle.rotate(turtle.heading, turn()),
+      '/' => turtle.rotate(turtle.heading, -turn()),
+      '|' => turtle.rotate(turtle.up, std::f32::consts::PI),
+      '[' => {
+        stack.push(turtle);
+        turtle.radius *= settings.radius_decay;
+      }
+      ']' => {
+        if let Some(saved) = stack.pop() {
+          turtle = saved;
+        }
+      }
+      '!' => turtle.radius *= settings.radius_decay,
+      _ => {}
+    }
+  }
+
+  let top = segments
+    .iter()
+    .flat_map(|(turtle, end)| [turtle.position[1], end[1]])
+    .fold(0.0f32, f32::max);
+  let scale = if top > 0.0 { 1.0 / top } else { 1.0 };
+  let mut mesh = Mesh {
+    name: "plant".to_string(),
+    vertices: Vec::new(),
+    lods: vec![Vec::new()],
+  };
+  for (turtle, end) in &segments {
+    push_segment(
+      &mut mesh,
+      turtle,
+      math::scale(*end, scale),
+      scale,
+      settings.sides.max(3),
+    );
+  }
+  mesh.generate_tangents();
+  mesh
+}
+
+// An open cylinder from the turtle to `end`, its rings spanned by the turtle's left and up.
+// The turtle's position is multiplied by `scale`, its radius isn't.
+fn push_segment(mesh: &mut Mesh, turtle: &Turtle, end: Vec3, scale: f32, sides: u32) {
+  let first = mesh.vertices.len() as u32;
+  for (v, center) in [(1.0, math::scale(turtle.position, scale)), (0.0, end)] {
+    // one extra column so the texture coordinates wrap around cleanly
+    for side in 0..=sides {
+      let theta = side as f32 / sides as f32 * std::f32::consts::TAU;
+      let normal = math::add(
+        math::scale(turtle.left, theta.cos()),
+        math::scale(turtle.up, theta.sin()),
+      );
+      mesh.vertices.push(MeshVertex {
+        position: math::add(center, math::scale(normal, turtle.radius)),
+        normal,
+        uv: [side as f32 / sides as f32, v],
+        tangent: [0.0; 4],
+      });
+    }
+  }
+  let ring = sides + 1;
+  for side in 0..sides {
+    let (bottom, top) = (first + side, first + ring + side);
+    mesh.lods[0].extend([bottom, bottom + 1, top + 1, bottom, top + 1, top]);
+  }
+}
@@ -1,8 +1,8 @@
-mod pipeline;
-mod state;
-mod window_runner;
-use window_runner::run;
+use wgpu_learn::run;
 
 fn main() {
-  pollster::block_on(run());
+  if let Err(e) = pollster::block_on(run()) {
+    eprintln!("error: {}", e);
+    std::process::exit(1);
+  }
 }
@@ -0,0 +1,251 @@
+// Just enough vector and matrix math for the demos. Matrices are column major
+// (m[column][row]) like WGSL's mat4x4, with wgpu's 0..1 clip space depth.
+
+pub type Vec3 = [f32; 3];
+pub type Mat4 = [[f32; 4]; 4];
+// [x, y, z, w] like glTF's rotations, unit length
+pub type Quat = [f32; 4];
+
+pub const IDENTITY: Mat4 = [
+  [1.0, 0.0, 0.0, 0.0],
+  [0.0, 1.0, 0.0, 0.0],
+  [0.0, 0.0, 1.0, 0.0],
+  [0.0, 0.0, 0.0, 1.0],
+];
+
+pub const QUAT_IDENTITY: Quat = [0.0, 0.0, 0.0, 1.0];
+
+pub fn add(a: Vec3, b: Vec3) -> Vec3 {
+  [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+pub fn sub(a: Vec3, b: Vec3) -> Vec3 {
+  [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+pub fn scale(a: Vec3, s: f32) -> Vec3 {
+  [a[0] * s, a[1] * s, a[2] * s]
+}
+
+pub fn dot(a: Vec3, b: Vec3) -> f32 {
+  a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+pub fn cross(a: Vec3, b: Vec3) -> Vec3 {
+  [
+    a[1] * b[2] - a[2] * b[1],
+    a[2] * b[0] - a[0] * b[2],
+    a[0] * b[1] - a[1] * b[0],
+  ]
+}
+
+pub fn length(a: Vec3) -> f32 {
+  dot(a, a).sqrt()
+}
+
+// Zero length vectors come back as +Z rather than NaN
+pub fn normalize(a: Vec3) -> Vec3 {
+  let length = length(a);
+  if length > 0.0 {
+    scale(a, 1.0 / length)
+  } else {
+    [0.0, 0.0, 1.0]
+  }
+}
+
+pub fn mul_mat4(a: &Mat4, b: &Mat4) -> Mat4 {
+  let mut out = [[0.0; 4]; 4];
+  for (column, out_column) in out.iter_mut().enumerate() {
+    for (row, value) in out_column.iter_mut().enumerate() {
+      *value = (0..4).map(|k| a[k][row] * b[column][k]).sum();
+    }
+  }
+  out
+}
+
+// Gauss-Jordan elimination with partial pivoting. Singular matrices come back as IDENTITY
+// rather than NaN.
+pub fn inverse(m: &Mat4) -> Mat4 {
+  let mut a = *m;
+  let mut out = IDENTITY;
+  for column in 0..4 {
+    let pivot = (column..4)
+      .max_by(|&i, &j| a[i][column].abs().total_cmp(&a[j][column].abs()))
+      .unwrap_or(column);
+    if a[pivot][column].abs() < f32::EPSILON {
+      return IDENTITY;
+    }
+    a.swap(column, pivot);
+    out.swap(column, pivot);
+    let scale = 1.0 / a[column][column];
+    for k in 0..4 {
+      a[column][k] *= scale;
+      out[column][k] *= scale;
+    }
+    for row in 0..4 {
+      if row == column {
+        continue;
+      }
+      let factor = a[row][column];
+      for k in 0..4 {
+        a[row][k] -= factor * a[column][k];
+        out[row][k] -= factor * out[column][k];
+      }
+    }
+  }
+  out
+}
+
+pub fn transform_point(m: &Mat4, p: Vec3) -> Vec3 {
+  let v = [p[0], p[1], p[2], 1.0];
+  let out: [f32; 4] = std::array::from_fn(|row| (0..4).map(|k| m[k][row] * v[k]).sum());
+  scale([out[0], out[1], out[2]], 1.0 / out[3])
+}
+
+pub fn translation(t: Vec3) -> Mat4 {
+  let mut m = IDENTITY;
+  m[3] = [t[0], t[1], t[2], 1.0];
+  m
+}
+
+pub fn scaling(s: Vec3) -> Mat4 {
+  [
+    [s[0], 0.0, 0.0, 0.0],
+    [0.0, s[1], 0.0, 0.0],
+    [0.0, 0.0, s[2], 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+  ]
+}
+
+pub fn rotation_x(angle: f32) -> Mat4 {
+  let (sin, cos) = angle.sin_cos();
+  [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, cos, sin, 0.0],
+    [0.0, -sin, cos, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+  ]
+}
+
+pub fn rotation_y(angle: f32) -> Mat4 {
+  let (sin, cos) = angle.sin_cos();
+  [
+    [cos, 0.0, -sin, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [sin, 0.0, cos, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+  ]
+}
+
+// `angle` radians around `axis`, counter-clockwise looking down the axis
+pub fn quat_axis_angle(axis: Vec3, angle: f32) -> Quat {
+  let (sin, cos) = (angle * 0.5).sin_cos();
+  let [x, y, z] = scale(normalize(axis), sin);
+  [x, y, z, cos]
+}
+
+// `b`'s rotation, then `a`'s
+pub fn quat_mul(a: Quat, b: Quat) -> Quat {
+  let [ax, ay, az, aw] = a;
+  let [bx, by, bz, bw] = b;
+  [
+    aw * bx + ax * bw + ay * bz - az * by,
+    aw * by - ax * bz + ay * bw + az * bx,
+    aw * bz + ax * by - ay * bx + az * bw,
+    aw * bw - ax * bx - ay * by - az * bz,
+  ]
+}
+
+// The shorter way round from `a` to `b`, straight lerp when they're nearly the same where
+// the angle is too small to divide by
+pub fn quat_slerp(a: Quat, b: Quat, t: f32) -> Quat {
+  let mut cos = (0..4).map(|i| a[i] * b[i]).sum::<f32>();
+  let b = if cos < 0.0 {
+    cos = -cos;
+    b.map(|v| -v)
+  } else {
+    b
+  };
+  let (wa, wb) = if cos > 0.9995 {
+    (1.0 - t, t)
+  } else {
+    let angle = cos.acos();
+    let sin = angle.sin();
+    (((1.0 - t) * angle).sin() / sin, (t * angle).sin() / sin)
+  };
+  let q: Quat = std::array::from_fn(|i| a[i] * wa + b[i] * wb);
+  let length = (0..4).map(|i| q[i] * q[i]).sum::<f32>().sqrt();
+  q.map(|v| v / length)
+}
+
+// Scales, then rotates, then translates, a glTF node's local transform
+pub fn from_trs(translation: Vec3, rotation: Quat, scale: Vec3) -> Mat4 {
+  let [x, y, z, w] = rotation;
+  let (x2, y2, z2) = (x + x, y + y, z + z);
+  let (xx, yy, zz) = (x * x2, y * y2, z * z2);
+  let (xy, xz, yz) = (x * y2, x * z2, y * z2);
+  let (wx, wy, wz) = (w * x2, w * y2, w * z2);
+  let [sx, sy, sz] = scale;
+  let [tx, ty, tz] = translation;
+  [
+    [(1.0 - yy - zz) * sx, (xy + wz) * sx, (xz - wy) * sx, 0.0],
+    [(xy - wz) * sy, (1.0 - xx - zz) * sy, (yz + wx) * sy, 0.0],
+    [(xz + wy) * sz, (yz - wx) * sz, (1.0 - xx - yy) * sz, 0.0],
+    [tx, ty, tz, 1.0],
+  ]
+}
+
+// Right handed view matrix looking from `eye` at `target`
+pub fn look_at(eye: Vec3, target: Vec3, up: Vec3) -> Mat4 {
+  let f = normalize(sub(target, eye));
+  let s = normalize(cross(f, up));
+  let u = cross(s, f);
+  [
+    [s[0], u[0], -f[0], 0.0],
+    [s[1], u[1], -f[1], 0.0],
+    [s[2], u[2], -f[2], 0.0],
+    [-dot(s, eye), -dot(u, eye), dot(f, eye), 1.0],
+  ]
+}
+
+pub fn perspective(fov_y: f32, aspect: f32, near: f32, far: f32) -> Mat4 {
+  let f = 1.0 / (fov_y / 2.0).tan();
+  [
+    [f / aspect, 0.0, 0.0, 0.0],
+    [0.0, f, 0.0, 0.0],
+    [0.0, 0.0, far / (near - far), -1.0],
+    [0.0, 0.0, near * far / (near - far), 0.0],
+  ]
+}
+
+pub fn orthographic(left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32) -> Mat4 {
+  [
+    [2.0 / (right - left), 0.0, 0.0, 0.0],
+    [0.0, 2.0 / (top - bottom), 0.0, 0.0],
+    [0.0, 0.0, 1.0 / (near - far), 0.0],
+    [
+      (left + right) / (left - right),
+      (top + bottom) / (bottom - top),
+      near / (near - far),
+      1.0,
+    ],
+  ]
+}
+
+// xorshift32, 0..1. Good enough for scattering things around, `state` must not be 0.
+pub fn next_random(state: &mut u32) -> f32 {
+  *state ^= *state << 13;
+  *state ^= *state >> 17;
+  *state ^= *state << 5;
+  *state as f32 / u32::MAX as f32
+}
+
+// Fully saturated color of `hue`, 0..1 going red, green, blue and back to red
+pub fn hue_to_rgb(hue: f32) -> [f32; 3] {
+  let h = hue.rem_euclid(1.0) * 6.0;
+  [
+    ((h - 3.0).abs() - 1.0).clamp(0.0, 1.0),
+    (2.0 - (h - 2.0).abs()).clamp(0.0, 1.0),
+    (2.0 - (h - 4.0).abs()).clamp(0.0, 1.0),
+  ]
+}
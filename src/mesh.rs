@@ -0,0 +1,427 @@
+use std::{collections::HashMap, path::Path};
+
+use crate::math::{add, cross, dot, length, normalize, scale, sub};
+
+// Lods past the first are dropped when they don't shrink the index count by at least this much
+const MIN_LOD_REDUCTION: f32 = 0.75;
+// Grid resolution of the first simplified lod, halved for every level after it
+const LOD_GRID: u32 = 64;
+pub const MAX_LODS: usize = 4;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MeshVertex {
+  pub position: [f32; 3],
+  pub normal: [f32; 3],
+  pub uv: [f32; 2],
+  // xyz along +u, w is the handedness of the bitangent
+  pub tangent: [f32; 4],
+}
+
+impl MeshVertex {
+  pub const ATTRIBUTES: [wgpu::VertexAttribute; 4] =
+    wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3, 2 => Float32x2, 3 => Float32x4];
+
+  pub fn layout() -> wgpu::VertexBufferLayout<'static> {
+    wgpu::VertexBufferLayout {
+      array_stride: std::mem::size_of::<MeshVertex>() as wgpu::BufferAddress,
+      step_mode: wgpu::VertexStepMode::Vertex,
+      attributes: &Self::ATTRIBUTES,
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum MeshError {
+  Io(std::io::Error),
+  Import(tobj::LoadError),
+}
+
+impl std::fmt::Display for MeshError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      MeshError::Io(e) => write!(f, "{}", e),
+      MeshError::Import(e) => write!(f, "couldn't import the mesh: {}", e),
+    }
+  }
+}
+
+impl std::error::Error for MeshError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      MeshError::Io(e) => Some(e),
+      MeshError::Import(e) => Some(e),
+    }
+  }
+}
+
+// Indexed triangle mesh, every lod indexes into the same vertices
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mesh {
+  pub name: String,
+  pub vertices: Vec<MeshVertex>,
+  // lods[0] is the full detail mesh
+  pub lods: Vec<Vec<u32>>,
+}
+
+impl Mesh {
+  // Loads every model in an .obj file without any processing, see `process`
+  pub fn import_obj(path: impl AsRef<Path>) -> Result<Vec<Mesh>, MeshError> {
+    let (models, _materials) = tobj::load_obj(
+      path.as_ref(),
+      &tobj::LoadOptions {
+        triangulate: true,
+        single_index: true,
+        ..Default::default()
+      },
+    )
+    .map_err(MeshError::Import)?;
+
+    Ok(
+      models
+        .into_iter()
+        .map(|model| {
+          let mesh = model.mesh;
+          let has_normals = !mesh.normals.is_empty();
+          let vertices = (0..mesh.positions.len() / 3)
+            .map(|i| MeshVertex {
+              position: [
+                mesh.positions[i * 3],
+                mesh.positions[i * 3 + 1],
+                mesh.positions[i * 3 + 2],
+              ],
+              normal: if has_normals {
+                [
+                  mesh.normals[i * 3],
+                  mesh.normals[i * 3 + 1],
+                  mesh.normals[i * 3 + 2],
+                ]
+              } else {
+                [0.0; 3]
+              },
+              // obj puts v = 0 at the bottom, wgpu at the top
+              uv: if mesh.texcoords.is_empty() {
+                [0.0; 2]
+              } else {
+                [mesh.texcoords[i * 2], 1.0 - mesh.texcoords[i * 2 + 1]]
+              },
+              tangent: [0.0; 4],
+            })
+            .collect();
+          let mut mesh = Mesh {
+            name: model.name,
+            vertices,
+            lods: vec![mesh.indices],
+          };
+          if !has_normals {
+            mesh.generate_normals();
+          }
+          mesh
+        })
+        .collect(),
+    )
+  }
+
+  // Axis aligned cube centered on the origin, each face has its own vertices for flat normals
+  pub fn cube(half_extent: f32) -> Mesh {
+    // (normal, u axis), v = normal x u keeps every face counter clockwise from outside
+    let faces: [([f32; 3], [f32; 3]); 6] = [
+      ([1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+      ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+      ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0]),
+      ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0]),
+      ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0]),
+      ([0.0, 0.0, -1.0], [-1.0, 0.0, 0.0]),
+    ];
+    let mut mesh = Mesh {
+      name: "cube".to_string(),
+      vertices: Vec::with_capacity(24),
+      lods: vec![Vec::with_capacity(36)],
+    };
+    for (normal, u) in faces {
+      mesh.push_quad(normal, u, cross(normal, u), half_extent);
+    }
+    mesh.generate_tangents();
+    mesh
+  }
+
+  // Square in the XZ plane facing +Y
+  pub fn plane(half_extent: f32) -> Mesh {
+    let mut mesh = Mesh {
+      name: "plane".to_string(),
+      vertices: Vec::with_capacity(4),
+      lods: vec![Vec::with_capacity(6)],
+    };
+    mesh.push_quad(
+      [0.0, 0.0, 0.0],
+      [1.0, 0.0, 0.0],
+      [0.0, 0.0, -1.0],
+      half_extent,
+    );
+    mesh.generate_tangents();
+    mesh
+  }
+
+  // Upright capsule standing on the origin, `height` tall including both caps
+  pub fn capsule(radius: f32, height: f32, sides: u32) -> Mesh {
+    Self::segmented_capsule(radius, height, sides, 1)
+  }
+
+  // capsule() with its cylinder cut into `segments` rings high, for meshes that bend along
+  // it like a skinned one
+  pub fn segmented_capsule(radius: f32, height: f32, sides: u32, segments: u32) -> Mesh {
+    let cap_rings = (sides / 4).max(2);
+    let segments = segments.max(1);
+    let mut mesh = Mesh {
+      name: "capsule".to_string(),
+      vertices: Vec::new(),
+      lods: vec![Vec::new()],
+    };
+    // the bottom cap's rings, the cylinder's between the two equators, then the top cap's
+    let cylinder = height - 2.0 * radius;
+    let rings = (0..=cap_rings)
+      .map(|k| (k as f32 / cap_rings as f32 - 1.0, radius))
+      .chain((1..segments).map(|k| (0.0, radius + cylinder * k as f32 / segments as f32)))
+      .chain((0..=cap_rings).map(|k| (k as f32 / cap_rings as f32, height - radius)));
+    for (latitude, center) in rings {
+      let (sin_phi, cos_phi) = (latitude * std::f32::consts::FRAC_PI_2).sin_cos();
+      // one extra column so the texture coordinates wrap around cleanly
+      for side in 0..=sides {
+        let theta = side as f32 / sides as f32 * std::f32::consts::TAU;
+        let normal = [cos_phi * theta.cos(), sin_phi, cos_phi * theta.sin()];
+        let position = add([0.0, center, 0.0], scale(normal, radius));
+        mesh.vertices.push(MeshVertex {
+          position,
+          normal,
+          uv: [side as f32 / sides as f32, 1.0 - position[1] / height],
+          tangent: [0.0; 4],
+        });
+      }
+    }
+    let columns = sides + 1;
+    for ring in 0..2 * cap_rings + segments {
+      for side in 0..sides {
+        let (bottom, top) = (ring * columns + side, (ring + 1) * columns + side);
+        mesh.lods[0].extend([bottom, top + 1, bottom + 1, bottom, top, top + 1]);
+      }
+    }
+    mesh.generate_tangents();
+    mesh
+  }
+
+  // A quad centered on `center * half_extent` spanning the u and v axes
+  fn push_quad(&mut self, center: [f32; 3], u: [f32; 3], v: [f32; 3], half_extent: f32) {
+    let normal = normalize(cross(u, v));
+    let first = self.vertices.len() as u32;
+    for (x, y) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+      let position = add(center, add(scale(u, x), scale(v, y)));
+      self.vertices.push(MeshVertex {
+        position: scale(position, half_extent),
+        normal,
+        uv: [x * 0.5 + 0.5, 0.5 - y * 0.5],
+        tangent: [0.0; 4],
+      });
+    }
+    self.lods[0].extend([0, 1, 2, 0, 2, 3].map(|i| first + i));
+  }
+
+  // The import work worth caching: welding, tangents and lods
+  pub fn process(&mut self) {
+    self.weld();
+    self.generate_tangents();
+    self.build_lods(MAX_LODS);
+  }
+
+  // Merges bit-identical vertices, exporters often write one vertex per face corner
+  pub fn weld(&mut self) {
+    let mut unique: HashMap<[u32; 12], u32> = HashMap::new();
+    let mut vertices = Vec::new();
+    let remap: Vec<u32> = self
+      .vertices
+      .iter()
+      .map(|v| {
+        let key: [u32; 12] = bytemuck::cast(*v);
+        *unique.entry(key).or_insert_with(|| {
+          vertices.push(*v);
+          vertices.len() as u32 - 1
+        })
+      })
+      .collect();
+    for lod in &mut self.lods {
+      for index in lod.iter_mut() {
+        *index = remap[*index as usize];
+      }
+    }
+    self.vertices = vertices;
+  }
+
+  // Smooth normals from the area weighted face normals
+  pub fn generate_normals(&mut self) {
+    let mut normals = vec![[0.0f32; 3]; self.vertices.len()];
+    for tri in self.lods[0].chunks_exact(3) {
+      let [a, b, c] = [0, 1, 2].map(|i| self.vertices[tri[i] as usize].position);
+      let face = cross(sub(b, a), sub(c, a));
+      for &i in tri {
+        normals[i as usize] = add(normals[i as usize], face);
+      }
+    }
+    for (vertex, normal) in self.vertices.iter_mut().zip(normals) {
+      vertex.normal = normalize(normal);
+    }
+  }
+
+  // Per vertex tangents in the spirit of mikktspace: every triangle's uv gradient is
+  // projected onto the vertex normal's plane and weighted by the corner angle, so the
+  // result doesn't depend on how the surface was triangulated. Vertices shared by mirrored
+  // and unmirrored triangles are split, one copy for each bitangent sign. Only lods[0] is
+  // remapped, so call this before build_lods.
+  pub fn generate_tangents(&mut self) {
+    // [sum for sign +1, sum for sign -1] per vertex
+    let mut sums = vec![[None::<[f32; 3]>; 2]; self.vertices.len()];
+    // which of the two sums each corner went into, None for triangles without usable uvs
+    let mut corner_slots = vec![None; self.lods[0].len()];
+    for (t, tri) in self.lods[0].chunks_exact(3).enumerate() {
+      let [v0, v1, v2] = [0, 1, 2].map(|i| self.vertices[tri[i] as usize]);
+      let (e1, e2) = (sub(v1.position, v0.position), sub(v2.position, v0.position));
+      let (du1, dv1) = (v1.uv[0] - v0.uv[0], v1.uv[1] - v0.uv[1]);
+      let (du2, dv2) = (v2.uv[0] - v0.uv[0], v2.uv[1] - v0.uv[1]);
+      let det = du1 * dv2 - du2 * dv1;
+      if det.abs() < f32::EPSILON {
+        continue;
+      }
+      let r = 1.0 / det;
+      let s = scale(sub(scale(e1, dv2), scale(e2, dv1)), r);
+      let b = scale(sub(scale(e2, du1), scale(e1, du2)), r);
+
+      for k in 0..3 {
+        let vertex = &self.vertices[tri[k] as usize];
+        let n = vertex.normal;
+        let t_projected = sub(s, scale(n, dot(n, s)));
+        if dot(t_projected, t_projected) < f32::EPSILON {
+          continue;
+        }
+        let slot = usize::from(dot(cross(n, s), b) < 0.0);
+        let to_next = normalize(sub(
+          self.vertices[tri[(k + 1) % 3] as usize].position,
+          vertex.position,
+        ));
+        let to_prev = normalize(sub(
+          self.vertices[tri[(k + 2) % 3] as usize].position,
+          vertex.position,
+        ));
+        let angle = dot(to_next, to_prev).clamp(-1.0, 1.0).acos();
+        let weighted = scale(normalize(t_projected), angle);
+        let sum = &mut sums[tri[k] as usize][slot];
+        *sum = Some(sum.map_or(weighted, |sum| add(sum, weighted)));
+        corner_slots[t * 3 + k] = Some(slot);
+      }
+    }
+
+    // the negative sign moves to a copy when a vertex has both
+    let mut copies = vec![None; self.vertices.len()];
+    for (i, [positive, negative]) in sums.iter().enumerate() {
+      if positive.is_some() && negative.is_some() {
+        copies[i] = Some(self.vertices.len() as u32);
+        self.vertices.push(self.vertices[i]);
+      }
+    }
+    for (index, slot) in self.lods[0].iter_mut().zip(&corner_slots) {
+      if let (Some(1), Some(copy)) = (slot, copies[*index as usize]) {
+        *index = copy;
+      }
+    }
+
+    for (i, [positive, negative]) in sums.into_iter().enumerate() {
+      let (t, w) = match (positive, negative) {
+        (None, Some(negative)) => (negative, -1.0),
+        (positive, _) => (positive.unwrap_or([0.0; 3]), 1.0),
+      };
+      let vertex = &mut self.vertices[i];
+      vertex.tangent = tangent_frame(vertex.normal, t, w);
+      if let (Some(copy), Some(negative)) = (copies[i], negative) {
+        let copy = &mut self.vertices[copy as usize];
+        copy.tangent = tangent_frame(copy.normal, negative, -1.0);
+      }
+    }
+  }
+
+  // Vertex clustering: snap every vertex to a grid cell and keep one vertex per cell,
+  // triangles that collapse are dropped. Each level uses a grid half as fine.
+  pub fn build_lods(&mut self, levels: usize) {
+    self.lods.truncate(1);
+    let (min, max) = self.bounds();
+    let extent = sub(max, min);
+    let mut grid = LOD_GRID;
+    while self.lods.len() < levels && grid > 1 {
+      let previous_len = self.lods.last().map_or(0, Vec::len);
+      let cell = extent.map(|e| e.max(f32::EPSILON) / grid as f32);
+      let mut representative: HashMap<[u32; 3], u32> = HashMap::new();
+      let remap: Vec<u32> = self
+        .vertices
+        .iter()
+        .enumerate()
+        .map(|(i, v)| {
+          let key = [0, 1, 2].map(|axis| ((v.position[axis] - min[axis]) / cell[axis]) as u32);
+          *representative.entry(key).or_insert(i as u32)
+        })
+        .collect();
+      let lod: Vec<u32> = self.lods[0]
+        .chunks_exact(3)
+        .map(|tri| {
+          [
+            remap[tri[0] as usize],
+            remap[tri[1] as usize],
+            remap[tri[2] as usize],
+          ]
+        })
+        .filter(|tri| tri[0] != tri[1] && tri[1] != tri[2] && tri[0] != tri[2])
+        .flatten()
+        .collect();
+
+      grid /= 2;
+      if lod.is_empty() {
+        break;
+      }
+      if (lod.len() as f32) <= previous_len as f32 * MIN_LOD_REDUCTION {
+        self.lods.push(lod);
+      }
+    }
+  }
+
+  pub fn bounds(&self) -> ([f32; 3], [f32; 3]) {
+    self
+      .vertices
+      .iter()
+      .fold(([f32::MAX; 3], [f32::MIN; 3]), |(min, max), v| {
+        (
+          [0, 1, 2].map(|i| min[i].min(v.position[i])),
+          [0, 1, 2].map(|i| max[i].max(v.position[i])),
+        )
+      })
+  }
+
+  // Radius of the sphere around the origin that holds every vertex
+  pub fn bounding_radius(&self) -> f32 {
+    self
+      .vertices
+      .iter()
+      .map(|v| length(v.position))
+      .fold(0.0, f32::max)
+  }
+}
+
+// Normalized tangent with its bitangent sign, any perpendicular will do for vertices
+// without uvs
+fn tangent_frame(normal: [f32; 3], sum: [f32; 3], sign: f32) -> [f32; 4] {
+  let mut t = sum;
+  if dot(t, t) < f32::EPSILON {
+    let axis = if normal[0].abs() < 0.9 {
+      [1.0, 0.0, 0.0]
+    } else {
+      [0.0, 1.0, 0.0]
+    };
+    t = sub(axis, scale(normal, dot(normal, axis)));
+  }
+  let t = normalize(t);
+  [t[0], t[1], t[2], sign]
+}
@@ -0,0 +1,219 @@
+use std::{
+  ffi::OsString,
+  path::{Path, PathBuf},
+};
+
+use crate::mesh::{Mesh, MeshError, MeshVertex};
+
+const MAGIC: &[u8; 4] = b"WLMC";
+// Bump whenever Mesh::process or the layout below changes, old caches get rebuilt
+const CACHE_VERSION: u32 = 2;
+
+// Processed meshes are written next to the source as `<file>.meshcache`:
+//
+//   magic, version: u32, source hash: u64, mesh count: u32
+//   per mesh: name length: u32, name, vertex count: u32, lod count: u32,
+//             index count per lod: u32..., vertices, indices of every lod
+//
+// All integers are little endian.
+pub fn cache_path(source: &Path) -> PathBuf {
+  let mut name = OsString::from(source.as_os_str());
+  name.push(".meshcache");
+  PathBuf::from(name)
+}
+
+// FNV-1a, stable across builds unlike std's hasher
+pub fn hash_source(bytes: &[u8]) -> u64 {
+  bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+    (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+  })
+}
+
+// Returns the cached meshes when the cache matches the source, otherwise imports,
+// processes and rewrites the cache
+pub fn load_or_import(path: impl AsRef<Path>) -> Result<Vec<Mesh>, MeshError> {
+  let path = path.as_ref();
+  let hash = hash_source(&std::fs::read(path).map_err(MeshError::Io)?);
+  let cache = cache_path(path);
+
+  if let Ok(bytes) = std::fs::read(&cache) {
+    match decode(&bytes, hash) {
+      Some(meshes) => {
+        log::info!("loaded {} from {}", path.display(), cache.display());
+        return Ok(meshes);
+      }
+      None => log::info!("{} is stale, reimporting", cache.display()),
+    }
+  }
+
+  let mut meshes = Mesh::import_obj(path)?;
+  for mesh in &mut meshes {
+    mesh.process();
+  }
+  // a failed write only costs the next launch another import
+  if let Err(e) = std::fs::write(&cache, encode(&meshes, hash)) {
+    log::warn!("couldn't write {}: {}", cache.display(), e);
+  }
+  Ok(meshes)
+}
+
+pub fn encode(meshes: &[Mesh], source_hash: u64) -> Vec<u8> {
+  let mut out = Vec::new();
+  out.extend_from_slice(MAGIC);
+  out.extend_from_slice(&CACHE_VERSION.to_le_bytes());
+  out.extend_from_slice(&source_hash.to_le_bytes());
+  out.extend_from_slice(&(meshes.len() as u32).to_le_bytes());
+  for mesh in meshes {
+    out.extend_from_slice(&(mesh.name.len() as u32).to_le_bytes());
+    out.extend_from_slice(mesh.name.as_bytes());
+    out.extend_from_slice(&(mesh.vertices.len() as u32).to_le_bytes());
+    out.extend_from_slice(&(mesh.lods.len() as u32).to_le_bytes());
+    for lod in &mesh.lods {
+      out.extend_from_slice(&(lod.len() as u32).to_le_bytes());
+    }
+    for vertex in &mesh.vertices {
+      for value in bytemuck::cast::<_, [f32; 12]>(*vertex) {
+        out.extend_from_slice(&value.to_le_bytes());
+      }
+    }
+    for index in mesh.lods.iter().flatten() {
+      out.extend_from_slice(&index.to_le_bytes());
+    }
+  }
+  out
+}
+
+// None when the data is truncated, from another version or from a different source
+pub fn decode(bytes: &[u8], source_hash: u64) -> Option<Vec<Mesh>> {
+  let mut reader = Reader { bytes };
+  if reader.take(4)? != MAGIC || reader.u32()? != CACHE_VERSION || reader.u64()? != source_hash {
+    return None;
+  }
+
+  let count = reader.u32()?;
+  let mut meshes = Vec::new();
+  for _ in 0..count {
+    let name_len = reader.u32()? as usize;
+    let name = String::from_utf8(reader.take(name_len)?.to_vec()).ok()?;
+    let vertex_count = reader.u32()? as usize;
+    let lod_count = reader.u32()? as usize;
+    let lod_lens = (0..lod_count)
+      .map(|_| reader.u32().map(|len| len as usize))
+      .collect::<Option<Vec<_>>>()?;
+
+    let vertices = (0..vertex_count)
+      .map(|_| {
+        let mut values = [0.0f32; 12];
+        for value in &mut values {
+          *value = reader.f32()?;
+        }
+        Some(bytemuck::cast::<_, MeshVertex>(values))
+      })
+      .collect::<Option<Vec<_>>>()?;
+    let lods = lod_lens
+      .into_iter()
+      .map(|len| (0..len).map(|_| reader.u32()).collect::<Option<Vec<_>>>())
+      .collect::<Option<Vec<_>>>()?;
+    if lods.iter().flatten().any(|&i| i as usize >= vertex_count) {
+      return None;
+    }
+    meshes.push(Mesh {
+      name,
+      vertices,
+      lods,
+    });
+  }
+  Some(meshes)
+}
+
+struct Reader<'a> {
+  bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+  fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+    if self.bytes.len() < len {
+      return None;
+    }
+    let (head, rest) = self.bytes.split_at(len);
+    self.bytes = rest;
+    Some(head)
+  }
+
+  fn u32(&mut self) -> Option<u32> {
+    Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+  }
+
+  fn u64(&mut self) -> Option<u64> {
+    Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+  }
+
+  fn f32(&mut self) -> Option<f32> {
+    Some(f32::from_le_bytes(self.take(4)?.try_into().ok()?))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const HASH: u64 = 0x1234_5678_9abc_def0;
+
+  fn meshes() -> Vec<Mesh> {
+    let mut capsule = Mesh::capsule(0.5, 2.0, 8);
+    capsule.process();
+    vec![Mesh::cube(1.0), capsule]
+  }
+
+  #[test]
+  fn round_trip() {
+    let meshes = meshes();
+    assert!(meshes[1].lods.len() > 1);
+    assert_eq!(decode(&encode(&meshes, HASH), HASH), Some(meshes));
+    assert_eq!(decode(&encode(&[], HASH), HASH), Some(Vec::new()));
+  }
+
+  #[test]
+  fn stale_caches_are_rejected() {
+    let bytes = encode(&meshes(), HASH);
+    // another source
+    assert_eq!(decode(&bytes, HASH + 1), None);
+    // another version
+    let mut old = bytes.clone();
+    old[4..8].copy_from_slice(&(CACHE_VERSION - 1).to_le_bytes());
+    assert_eq!(decode(&old, HASH), None);
+    // not a cache at all
+    let mut other = bytes;
+    other[..4].copy_from_slice(b"OBJ ");
+    assert_eq!(decode(&other, HASH), None);
+  }
+
+  #[test]
+  fn corrupt_caches_are_rejected() {
+    let mesh = Mesh::plane(1.0);
+    let bytes = encode(std::slice::from_ref(&mesh), HASH);
+    for len in 0..bytes.len() {
+      assert_eq!(decode(&bytes[..len], HASH), None, "truncated to {}", len);
+    }
+    // the last index points past the vertices
+    let mut out_of_range = bytes.clone();
+    let end = out_of_range.len();
+    out_of_range[end - 4..].copy_from_slice(&(mesh.vertices.len() as u32).to_le_bytes());
+    assert_eq!(decode(&out_of_range, HASH), None);
+    // the name isn't UTF-8, it starts after the header and the name's length
+    let mut bad_name = bytes;
+    bad_name[24] = 0xff;
+    assert_eq!(decode(&bad_name, HASH), None);
+  }
+
+  #[test]
+  fn hash_is_stable() {
+    // the FNV-1a test vectors, a different hash would make every cache stale
+    assert_eq!(hash_source(b""), 0xcbf2_9ce4_8422_2325);
+    assert_eq!(hash_source(b"a"), 0xaf63_dc4c_8601_ec8c);
+    assert_eq!(
+      cache_path(Path::new("models/cube.obj")),
+      PathBuf::from("models/cube.obj.meshcache")
+    );
+  }
+}
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+
+use wgpu::{CommandEncoder, Device, TextureFormat};
+
+use crate::{sampler::SamplerSettings, stats::count_draw};
+
+// Levels needed to go from `width` x `height` down to 1x1
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+  32 - width.max(height).max(1).leading_zeros()
+}
+
+// Fills the mip chain of a texture by rendering each level from the one above it
+pub struct MipmapGenerator {
+  shader: wgpu::ShaderModule,
+  layout: wgpu::BindGroupLayout,
+  sampler: wgpu::Sampler,
+  // one pipeline per texture format, they're small and formats repeat a lot
+  pipelines: HashMap<TextureFormat, wgpu::RenderPipeline>,
+}
+
+impl MipmapGenerator {
+  pub fn new(device: &Device) -> Self {
+    let shader = device.create_shader_module(wgpu::include_wgsl!("blit.wgsl"));
+    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Mipmap Bind Group Layout"),
+      entries: &[
+        wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+          },
+          count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+          binding: 1,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+          count: None,
+        },
+      ],
+    });
+    let sampler =
+      device.create_sampler(&SamplerSettings::linear().descriptor(Some("Mipmap Sampler")));
+    Self {
+      shader,
+      layout,
+      sampler,
+      pipelines: HashMap::new(),
+    }
+  }
+
+  // `texture` needs TEXTURE_BINDING and RENDER_ATTACHMENT usage and level 0 already uploaded
+  pub fn generate(
+    &mut self,
+    device: &Device,
+    encoder: &mut CommandEncoder,
+    texture: &wgpu::Texture,
+    format: TextureFormat,
+    mip_count: u32,
+  ) {
+    if mip_count < 2 {
+      return;
+    }
+    let layout = &self.layout;
+    let shader = &self.shader;
+    let pipeline = self
+      .pipelines
+      .entry(format)
+      .or_insert_with(|| create_pipeline(device, layout, shader, format));
+
+    let views: Vec<_> = (0..mip_count)
+      .map(|level| {
+        texture.create_view(&wgpu::TextureViewDescriptor {
+          label: Some("Mip View"),
+          base_mip_level: level,
+          mip_level_count: std::num::NonZeroU32::new(1),
+          ..Default::default()
+        })
+      })
+      .collect();
+
+    for level in 1..mip_count as usize {
+      let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Mipmap Bind Group"),
+        layout: &self.layout,
+        entries: &[
+          wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(&views[level - 1]),
+          },
+          wgpu::BindGroupEntry {
+            binding: 1,
+            resource: wgpu::BindingResource::Sampler(&self.sampler),
+          },
+        ],
+      });
+      let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Mipmap Pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+          view: &views[level],
+          resolve_target: None,
+          ops: wgpu::Operations {
+            load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+            store: true,
+          },
+        })],
+        depth_stencil_attachment: None,
+      });
+      pass.set_pipeline(pipeline);
+      pass.set_bind_group(0, &bind_group, &[]);
+      count_draw();
+      pass.draw(0..3, 0..1);
+    }
+  }
+}
+
+fn create_pipeline(
+  device: &Device,
+  layout: &wgpu::BindGroupLayout,
+  shader: &wgpu::ShaderModule,
+  format: TextureFormat,
+) -> wgpu::RenderPipeline {
+  let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+    label: Some("Mipmap Pipeline Layout"),
+    bind_group_layouts: &[layout],
+    push_constant_ranges: &[],
+  });
+  device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+    label: Some("Mipmap Pipeline"),
+    layout: Some(&pipeline_layout),
+    vertex: wgpu::VertexState {
+      module: shader,
+      entry_point: "vs_main",
+      buffers: &[],
+    },
+    fragment: Some(wgpu::FragmentState {
+      module: shader,
+      entry_point: "fs_main",
+      targets: &[Some(format.into())],
+    }),
+    primitive: wgpu::PrimitiveState::default(),
+    depth_stencil: None,
+    multisample: wgpu::MultisampleState::default(),
+    multiview: None,
+  })
+}
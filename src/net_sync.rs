@@ -0,0 +1,395 @@
+use std::{
+  io::{self, Read, Write},
+  net::{TcpListener, TcpStream, ToSocketAddrs},
+  time::Duration,
+};
+
+use instant::Instant;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  camera::OrbitCamera,
+  math::Vec3,
+  params::{Params, PARAMS},
+};
+
+// How long a follower waits between attempts to reach the broadcaster
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(2);
+// Connecting blocks the frame, so it can't take long
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(100);
+// A follower with this much unsent is too slow to keep and gets dropped
+const MAX_PENDING: usize = 1 << 20;
+// How near a follower's camera has to get to be put right on the broadcaster's
+const ARRIVED: f32 = 1e-4;
+
+// The part an instance plays in syncing its params and camera with others, e.g. a presenter
+// and the screens following it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncRole {
+  Off,
+  // listens on the address and sends every change to whoever connects
+  Broadcast,
+  // connects to the broadcaster at the address and does what it's told
+  Follow,
+}
+
+// The `[sync]` section of settings.toml, `--broadcast address` and `--follow address`
+// override it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncSettings {
+  pub role: SyncRole,
+  pub address: String,
+  // seconds a follower's camera takes to get most of the way to where the broadcaster's
+  // is, hides that updates come in bursts over the network. 0 jumps right there.
+  pub smoothing: f32,
+}
+
+impl Default for SyncSettings {
+  fn default() -> Self {
+    Self {
+      role: SyncRole::Off,
+      address: "127.0.0.1:7878".to_string(),
+      smoothing: 0.1,
+    }
+  }
+}
+
+impl SyncSettings {
+  // With `--broadcast address` or `--follow address` from the command line over the file's
+  pub fn from_args(&self, broadcast: Option<&str>, follow: Option<&str>) -> Self {
+    let mut settings = self.clone();
+    for (address, role) in [(broadcast, SyncRole::Broadcast), (follow, SyncRole::Follow)] {
+      if let Some(address) = address {
+        settings.role = role;
+        settings.address = address.to_string();
+      }
+    }
+    settings
+  }
+}
+
+// Where an orbit camera is, without its lens
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct CameraPose {
+  target: Vec3,
+  distance: f32,
+  yaw: f32,
+  pitch: f32,
+}
+
+impl CameraPose {
+  fn of(camera: &OrbitCamera) -> Self {
+    Self {
+      target: camera.target,
+      distance: camera.distance,
+      yaw: camera.yaw,
+      pitch: camera.pitch,
+    }
+  }
+
+  // `t` of the way from `camera` to this pose, the yaw the short way round. Returns whether
+  // the camera moved, it's put right on the pose once it's close enough.
+  fn ease(&self, camera: &mut OrbitCamera, t: f32) -> bool {
+    if CameraPose::of(camera) == *self {
+      return false;
+    }
+    let lerp = |a: f32, b: f32| a + (b - a) * t;
+    let tau = std::f32::consts::TAU;
+    let turn =
+      (self.yaw - camera.yaw + std::f32::consts::PI).rem_euclid(tau) - std::f32::consts::PI;
+    camera.yaw = (camera.yaw + turn * t) % tau;
+    camera.pitch = lerp(camera.pitch, self.pitch);
+    camera.distance = lerp(camera.distance, self.distance);
+    camera.target = [0, 1, 2].map(|i| lerp(camera.target[i], self.target[i]));
+    let close = |a: f32, b: f32| (a - b).abs() < ARRIVED;
+    let arrived = close(turn * (1.0 - t), 0.0)
+      && close(camera.pitch, self.pitch)
+      && close(camera.distance, self.distance)
+      && (0..3).all(|i| close(camera.target[i], self.target[i]));
+    if arrived {
+      camera.target = self.target;
+      camera.distance = self.distance;
+      camera.yaw = self.yaw;
+      camera.pitch = self.pitch;
+    }
+    true
+  }
+}
+
+// One line each on the wire
+#[derive(Debug, Clone, PartialEq)]
+enum SyncMessage {
+  // `param name=value`, as Params::assign takes it
+  Param(String),
+  // `camera yaw pitch distance x y z`
+  Camera(CameraPose),
+}
+
+impl SyncMessage {
+  fn parse(line: &str) -> Option<Self> {
+    let (kind, rest) = line.trim().split_once(' ')?;
+    match kind {
+      "param" => Some(SyncMessage::Param(rest.to_string())),
+      "camera" => {
+        let numbers: Vec<f32> = rest
+          .split_whitespace()
+          .map(|n| n.parse().ok())
+          .collect::<Option<_>>()?;
+        let [yaw, pitch, distance, x, y, z] = numbers[..] else {
+          return None;
+        };
+        Some(SyncMessage::Camera(CameraPose {
+          target: [x, y, z],
+          distance,
+          yaw,
+          pitch,
+        }))
+      }
+      _ => None,
+    }
+  }
+
+  fn line(&self) -> String {
+    match self {
+      SyncMessage::Param(assignment) => format!("param {}\n", assignment),
+      SyncMessage::Camera(pose) => {
+        let [x, y, z] = pose.target;
+        format!(
+          "camera {} {} {} {} {} {}\n",
+          pose.yaw, pose.pitch, pose.distance, x, y, z
+        )
+      }
+    }
+  }
+}
+
+struct Connection {
+  stream: TcpStream,
+  // written as far as the socket took it
+  pending: Vec<u8>,
+}
+
+impl Connection {
+  fn new(stream: TcpStream) -> io::Result<Self> {
+    stream.set_nonblocking(true)?;
+    stream.set_nodelay(true)?;
+    Ok(Self {
+      stream,
+      pending: Vec::new(),
+    })
+  }
+
+  // Errors when the other side is gone or can't keep up
+  fn flush(&mut self) -> io::Result<()> {
+    while !self.pending.is_empty() {
+      match self.stream.write(&self.pending) {
+        Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+        Ok(written) => {
+          self.pending.drain(..written);
+        }
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+        Err(e) => return Err(e),
+      }
+    }
+    if self.pending.len() > MAX_PENDING {
+      return Err(io::Error::other("too far behind"));
+    }
+    Ok(())
+  }
+}
+
+// Keeps instances in step over TCP: the broadcaster sends what changed each frame, the
+// followers apply it. Params are set as they come in, the camera eases towards the pose.
+pub struct NetworkSync {
+  peer: Peer,
+}
+
+enum Peer {
+  Broadcaster {
+    listener: TcpListener,
+    followers: Vec<Connection>,
+    // what the followers were last told, in PARAMS order
+    params: Vec<f64>,
+    camera: Option<CameraPose>,
+  },
+  Follower {
+    address: String,
+    connection: Option<Connection>,
+    // read but not a whole line yet
+    received: Vec<u8>,
+    next_attempt: Instant,
+    camera: Option<CameraPose>,
+    smoothing: f32,
+  },
+}
+
+impl NetworkSync {
+  // None when `settings` say not to sync
+  pub fn start(settings: &SyncSettings) -> io::Result<Option<Self>> {
+    let peer = match settings.role {
+      SyncRole::Off => return Ok(None),
+      SyncRole::Broadcast => {
+        let listener = TcpListener::bind(&settings.address)?;
+        listener.set_nonblocking(true)?;
+        log::info!("broadcasting params and camera on {}", settings.address);
+        Peer::Broadcaster {
+          listener,
+          followers: Vec::new(),
+          params: Vec::new(),
+          camera: None,
+        }
+      }
+      SyncRole::Follow => Peer::Follower {
+        address: settings.address.clone(),
+        connection: None,
+        received: Vec::new(),
+        next_attempt: Instant::now(),
+        camera: None,
+        smoothing: settings.smoothing,
+      },
+    };
+    Ok(Some(Self { peer }))
+  }
+
+  // Once a frame: the broadcaster sends `params` and `camera` where they changed, a
+  // follower sets them from what came in. `dt` is the frame time in seconds. Returns whether
+  // a follower changed anything, the broadcaster never does.
+  pub fn update(&mut self, params: &mut Params, camera: &mut OrbitCamera, dt: f32) -> bool {
+    match &mut self.peer {
+      Peer::Broadcaster {
+        listener,
+        followers,
+        params: sent_params,
+        camera: sent_camera,
+      } => {
+        let values: Vec<f64> = PARAMS
+          .iter()
+          .map(|spec| params.get(spec.name).unwrap_or(spec.default))
+          .collect();
+        let pose = CameraPose::of(camera);
+        let mut changes: Vec<_> = PARAMS
+          .iter()
+          .zip(&values)
+          .enumerate()
+          .filter(|&(i, (_, value))| sent_params.get(i) != Some(value))
+          .map(|(_, (spec, value))| SyncMessage::Param(format!("{}={}", spec.name, value)))
+          .collect();
+        if *sent_camera != Some(pose) {
+          changes.push(SyncMessage::Camera(pose));
+        }
+        let changes: String = changes.iter().map(SyncMessage::line).collect();
+
+        // newcomers get everything, the rest only what changed
+        let everything: String = PARAMS
+          .iter()
+          .zip(&values)
+          .map(|(spec, value)| SyncMessage::Param(format!("{}={}", spec.name, value)))
+          .chain(std::iter::once(SyncMessage::Camera(pose)))
+          .map(|message| message.line())
+          .collect();
+        while let Ok((stream, address)) = listener.accept() {
+          match Connection::new(stream) {
+            Ok(mut connection) => {
+              log::info!("{} is following", address);
+              connection.pending.extend(everything.as_bytes());
+              followers.push(connection);
+            }
+            Err(e) => log::warn!("couldn't set up {}: {}", address, e),
+          }
+        }
+        followers.retain_mut(|follower| {
+          follower.pending.extend(changes.as_bytes());
+          match follower.flush() {
+            Ok(()) => true,
+            Err(e) => {
+              log::info!("dropped a follower: {}", e);
+              false
+            }
+          }
+        });
+        *sent_params = values;
+        *sent_camera = Some(pose);
+        false
+      }
+      Peer::Follower {
+        address,
+        connection,
+        received,
+        next_attempt,
+        camera: target,
+        smoothing,
+      } => {
+        if connection.is_none() && Instant::now() >= *next_attempt {
+          *next_attempt = Instant::now() + RECONNECT_INTERVAL;
+          match connect(address) {
+            Ok(connected) => {
+              log::info!("following {}", address);
+              received.clear();
+              *connection = Some(connected);
+            }
+            Err(e) => log::debug!("couldn't reach {}: {}", address, e),
+          }
+        }
+        if let Some(open) = connection {
+          if let Err(e) = receive(&mut open.stream, received) {
+            log::info!("lost {}: {}", address, e);
+            *connection = None;
+          }
+        }
+        // whole lines only, the rest waits for the next frame
+        let end = received
+          .iter()
+          .rposition(|&b| b == b'\n')
+          .map_or(0, |i| i + 1);
+        let lines: Vec<u8> = received.drain(..end).collect();
+        let mut changed = !lines.is_empty();
+        for line in String::from_utf8_lossy(&lines).lines() {
+          match SyncMessage::parse(line) {
+            Some(SyncMessage::Param(assignment)) => {
+              if let Err(e) = params.assign(&assignment) {
+                log::warn!("{}", e);
+              }
+            }
+            Some(SyncMessage::Camera(pose)) => *target = Some(pose),
+            None => log::warn!("ignoring sync message {:?}", line),
+          }
+        }
+        if let Some(pose) = target {
+          // the same share of the way each second whatever the frame rate
+          let t = match *smoothing > 0.0 {
+            true => 1.0 - (-dt / *smoothing).exp(),
+            false => 1.0,
+          };
+          changed |= pose.ease(camera, t);
+        }
+        changed
+      }
+    }
+  }
+}
+
+fn connect(address: &str) -> io::Result<Connection> {
+  let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no address");
+  for address in address.to_socket_addrs()? {
+    match TcpStream::connect_timeout(&address, CONNECT_TIMEOUT) {
+      Ok(stream) => return Connection::new(stream),
+      Err(e) => last_error = e,
+    }
+  }
+  Err(last_error)
+}
+
+// Appends whatever arrived to `received`, errors when the broadcaster hung up
+fn receive(stream: &mut TcpStream, received: &mut Vec<u8>) -> io::Result<()> {
+  let mut buffer = [0; 4096];
+  loop {
+    match stream.read(&mut buffer) {
+      Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+      Ok(read) => received.extend_from_slice(&buffer[..read]),
+      Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+      Err(e) => return Err(e),
+    }
+  }
+}
@@ -0,0 +1,138 @@
+// A little clockwork solar system moved by a NodeAnimation, 1 toggles it. The nodes are
+// laid out like a glTF scene's: the planet hangs off the sun and the moon off a pivot under
+// the planet, so each one only keys its own motion and inherits the rest. Its one clip
+// uses every interpolation: the sun's and the pivot's turns are linear, the planet bobs on
+// a cubic spline and the moon blinks between two sizes on step keys.
+
+use std::f32::consts::{PI, TAU};
+
+use wgpu::Device;
+
+use crate::{
+  animation::{AnimationClip, Channel, Interpolation, Joint, Keys, NodeAnimation, Transform},
+  math::{self, Quat, Vec3},
+  mesh::Mesh,
+  pbr::{PbrMaterial, PbrTextures},
+  scene::{ModelId, Scene},
+};
+
+const CENTER: Vec3 = [3.0, 1.5, -2.0];
+// seconds the clip takes, one turn of the sun
+const YEAR: f32 = 8.0;
+const PLANET_DISTANCE: f32 = 1.6;
+const BOB_HEIGHT: f32 = 0.4;
+const MOON_DISTANCE: f32 = 0.5;
+// moon orbits per year
+const MONTHS: u32 = 4;
+
+pub struct Orrery {
+  models: Vec<ModelId>,
+}
+
+impl Orrery {
+  // Adds the sun, planet and moon to `scene`, remove() takes them out again
+  pub fn new(device: &Device, scene: &mut Scene) -> Self {
+    let mut add = |half_extent: f32, base_color: [f32; 4]| {
+      let material = PbrMaterial {
+        base_color,
+        metallic: 0.0,
+        roughness: 0.5,
+        ..Default::default()
+      };
+      let mesh = Mesh::cube(half_extent);
+      scene.add_model(
+        device,
+        &mesh,
+        &material,
+        PbrTextures::default(),
+        math::IDENTITY,
+      )
+    };
+    let sun = add(0.35, [1.0, 0.7, 0.2, 1.0]);
+    let planet = add(0.18, [0.2, 0.4, 1.0, 1.0]);
+    let moon = add(0.08, [0.8, 0.8, 0.8, 1.0]);
+    let animation =
+      NodeAnimation::new(nodes(), vec![clip()]).expect("the orrery's nodes come parents first");
+    // the moon's pivot only turns it
+    scene.add_node_animation(animation, vec![Some(sun), Some(planet), None, Some(moon)]);
+    Self {
+      models: vec![sun, planet, moon],
+    }
+  }
+
+  // The animation goes with its models
+  pub fn remove(self, scene: &mut Scene) {
+    for model in self.models {
+      scene.remove_model(model);
+    }
+  }
+}
+
+fn nodes() -> Vec<Joint> {
+  let node = |name: &str, parent: Option<usize>, translation: Vec3| Joint {
+    name: name.to_string(),
+    parent,
+    rest: Transform::from_translation(translation),
+  };
+  vec![
+    node("sun", None, CENTER),
+    node("planet", Some(0), [PLANET_DISTANCE, 0.0, 0.0]),
+    node("moon pivot", Some(1), [0.0; 3]),
+    node("moon", Some(2), [MOON_DISTANCE, 0.0, 0.0]),
+  ]
+}
+
+// `turns` around Y over the year, a key every quarter turn so slerp goes the right way
+fn spin(node: usize, turns: u32) -> Channel {
+  let quarters = 4 * turns;
+  let (times, rotations): (Vec<f32>, Vec<Quat>) = (0..=quarters)
+    .map(|k| {
+      let share = k as f32 / quarters as f32;
+      let angle = TAU * turns as f32 * share;
+      (YEAR * share, math::quat_axis_angle([0.0, 1.0, 0.0], angle))
+    })
+    .unzip();
+  Channel {
+    joint: node,
+    times,
+    keys: Keys::Rotation(rotations),
+    interpolation: Interpolation::Linear,
+  }
+}
+
+fn clip() -> AnimationClip {
+  // a sine wave through five keys, its slope as the tangents
+  let bob = (0..=4).map(|k| {
+    let phase = k as f32 * PI / 2.0;
+    let height = BOB_HEIGHT * phase.sin();
+    let slope = BOB_HEIGHT * phase.cos() * TAU / YEAR;
+    let tangent = [0.0, slope, 0.0];
+    [tangent, [PLANET_DISTANCE, height, 0.0], tangent]
+  });
+  let months = 2 * MONTHS as usize;
+  AnimationClip::new(
+    "orbit",
+    vec![
+      spin(0, 1),
+      Channel {
+        joint: 1,
+        times: (0..=4).map(|k| k as f32 * YEAR / 4.0).collect(),
+        keys: Keys::Translation(bob.flatten().collect()),
+        interpolation: Interpolation::CubicSpline,
+      },
+      spin(2, MONTHS),
+      Channel {
+        joint: 3,
+        times: (0..=months)
+          .map(|k| k as f32 * YEAR / months as f32)
+          .collect(),
+        keys: Keys::Scale(
+          (0..=months)
+            .map(|k| if k % 2 == 0 { [1.0; 3] } else { [0.6; 3] })
+            .collect(),
+        ),
+        interpolation: Interpolation::Step,
+      },
+    ],
+  )
+}
@@ -0,0 +1,29 @@
+// Outline around the selected model. vs_mask draws the model as it is to mark its pixels in
+// the stencil, vs_outline a copy pushed out along its normals that's only drawn where the
+// stencil isn't marked, leaving a rim around the silhouette.
+
+#include "common.wgsl"
+
+// world units the outline sticks out
+const OUTLINE_WIDTH: f32 = 0.03;
+// bright enough to stay visible through the tonemapper
+const OUTLINE_COLOR: vec3<f32> = vec3<f32>(4.0, 2.4, 0.4);
+
+@vertex
+fn vs_mask(vertex: VertexInput, instance: InstanceInput) -> @builtin(position) vec4<f32> {
+    let model = instance_model(instance);
+    return globals.view_proj * model * vec4<f32>(vertex.position, 1.0);
+}
+
+@vertex
+fn vs_outline(vertex: VertexInput, instance: InstanceInput) -> @builtin(position) vec4<f32> {
+    let model = instance_model(instance);
+    let normal = normalize((model * vec4<f32>(vertex.normal, 0.0)).xyz);
+    let world = model * vec4<f32>(vertex.position, 1.0) + vec4<f32>(normal * OUTLINE_WIDTH, 0.0);
+    return globals.view_proj * world;
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return vec4<f32>(OUTLINE_COLOR, 1.0);
+}
@@ -0,0 +1,365 @@
+use std::collections::BTreeMap;
+
+use crate::{fog::FogSettings, plants::PlantSettings, taa::HISTORY_WEIGHT, water::WaterSettings};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamKind {
+  Float,
+  // whole numbers, set values are rounded
+  Int,
+  // 0 or 1, anything but 0 is 1
+  Bool,
+}
+
+// A named value things can be tuned with, from the config file, the web page or anywhere
+// else that has a name and a number
+#[derive(Debug, Clone, Copy)]
+pub struct ParamSpec {
+  pub name: &'static str,
+  pub kind: ParamKind,
+  // set values are clamped into it
+  pub min: f64,
+  pub max: f64,
+  pub default: f64,
+  pub description: &'static str,
+}
+
+impl ParamSpec {
+  const fn new(
+    name: &'static str,
+    kind: ParamKind,
+    (min, max): (f64, f64),
+    default: f64,
+    description: &'static str,
+  ) -> Self {
+    Self {
+      name,
+      kind,
+      min,
+      max,
+      default,
+      description,
+    }
+  }
+
+  // `value` as this param stores it
+  fn coerce(&self, value: f64) -> f64 {
+    match self.kind {
+      ParamKind::Float => value.clamp(self.min, self.max),
+      ParamKind::Int => value.round().clamp(self.min, self.max),
+      ParamKind::Bool => (value != 0.0) as u8 as f64,
+    }
+  }
+}
+
+// Every param there is. The ones for state that lives elsewhere get the current value with
+// Params::sync(), the defaults here are only what they start as before that.
+pub const PARAMS: [ParamSpec; 22] = [
+  ParamSpec::new(
+    "exposure",
+    ParamKind::Float,
+    (-10.0, 10.0),
+    0.0,
+    "EV compensation of the primary window",
+  ),
+  ParamSpec::new("boids", ParamKind::Bool, (0.0, 1.0), 0.0, "shows the flock"),
+  ParamSpec::new(
+    "boids_cohesion",
+    ParamKind::Float,
+    (0.0, 0.2),
+    0.02,
+    "how strongly boids steer to their neighbours' center",
+  ),
+  ParamSpec::new(
+    "boids_separation",
+    ParamKind::Float,
+    (0.0, 0.5),
+    0.05,
+    "how strongly boids keep away from each other",
+  ),
+  ParamSpec::new(
+    "boids_alignment",
+    ParamKind::Float,
+    (0.0, 0.05),
+    0.005,
+    "how strongly boids match their neighbours' heading",
+  ),
+  ParamSpec::new(
+    "boids_collision",
+    ParamKind::Int,
+    (0.0, 2.0),
+    0.0,
+    "what boids do on the scene's geometry: 0 fly over, 1 bounce off, 2 respawn at the top",
+  ),
+  ParamSpec::new(
+    "boids_restitution",
+    ParamKind::Float,
+    (0.0, 1.0),
+    0.6,
+    "share of their speed boids keep bouncing off the scene",
+  ),
+  ParamSpec::new(
+    "taa_history",
+    ParamKind::Float,
+    (0.0, 0.98),
+    HISTORY_WEIGHT as f64,
+    "share of the history in TAA, higher is smoother but slower to react",
+  ),
+  ParamSpec::new(
+    "anisotropy",
+    ParamKind::Bool,
+    (0.0, 1.0),
+    1.0,
+    "anisotropic texture filtering",
+  ),
+  ParamSpec::new(
+    "erosion",
+    ParamKind::Int,
+    (0.0, 1_000_000.0),
+    0.0,
+    "runs this many more erosion iterations on the terrain",
+  ),
+  ParamSpec::new(
+    "plant_angle",
+    ParamKind::Float,
+    (0.0, 180.0),
+    25.0,
+    "degrees per turn of the terrain's plants",
+  ),
+  ParamSpec::new(
+    "plant_iterations",
+    ParamKind::Int,
+    (0.0, 8.0),
+    4.0,
+    "L-system iterations of the terrain's plants",
+  ),
+  ParamSpec::new(
+    "plant_count",
+    ParamKind::Int,
+    (0.0, 1000.0),
+    60.0,
+    "plants on the terrain",
+  ),
+  ParamSpec::new(
+    "wave_scale",
+    ParamKind::Float,
+    (0.01, 4.0),
+    0.4,
+    "repeats of the water's normal map per world unit",
+  ),
+  ParamSpec::new(
+    "wave_speed",
+    ParamKind::Float,
+    (0.0, 0.5),
+    0.03,
+    "how fast the water's waves scroll",
+  ),
+  ParamSpec::new(
+    "wave_strength",
+    ParamKind::Float,
+    (0.0, 2.0),
+    0.6,
+    "how far the water's waves tilt its normals, 0 is a mirror",
+  ),
+  ParamSpec::new(
+    "fog_density",
+    ParamKind::Float,
+    (0.0, 1.0),
+    0.04,
+    "extinction per unit of the volumetric fog at its base height",
+  ),
+  ParamSpec::new(
+    "fog_scattering",
+    ParamKind::Float,
+    (0.0, 8.0),
+    1.0,
+    "sunlight the fog scatters into its light shafts",
+  ),
+  ParamSpec::new(
+    "simulation_speed",
+    ParamKind::Float,
+    (0.0, 8.0),
+    1.0,
+    "simulated seconds per second in real time steps",
+  ),
+  ParamSpec::new(
+    "simulation_paused",
+    ParamKind::Bool,
+    (0.0, 1.0),
+    0.0,
+    "stops the simulation, the UI and camera keep going",
+  ),
+  ParamSpec::new(
+    "animation_speed",
+    ParamKind::Float,
+    (0.0, 8.0),
+    1.0,
+    "pace of animated textures and the sky",
+  ),
+  ParamSpec::new(
+    "animation_paused",
+    ParamKind::Bool,
+    (0.0, 1.0),
+    0.0,
+    "stops animated textures and the sky",
+  ),
+];
+
+pub fn find(name: &str) -> Option<&'static ParamSpec> {
+  PARAMS.iter().find(|spec| spec.name == name)
+}
+
+#[derive(Debug)]
+pub enum ParamError {
+  Unknown(String),
+  // an assignment that isn't `name=value`
+  Syntax(String),
+  Value { name: String, value: String },
+}
+
+impl std::fmt::Display for ParamError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ParamError::Unknown(name) => write!(
+        f,
+        "unknown param {}, expected one of {:?}",
+        name,
+        PARAMS.map(|spec| spec.name)
+      ),
+      ParamError::Syntax(text) => write!(f, "expected name=value, got {:?}", text),
+      ParamError::Value { name, value } => write!(f, "{:?} isn't a value for {}", value, name),
+    }
+  }
+}
+
+impl std::error::Error for ParamError {}
+
+// The current value of every param. Whoever sets one doesn't need to know what uses it: the
+// users read them every frame or act on take_changed().
+#[derive(Debug)]
+pub struct Params {
+  values: Vec<f64>,
+  // set since the last take_changed(), in PARAMS order
+  changed: Vec<bool>,
+}
+
+impl Default for Params {
+  fn default() -> Self {
+    Self {
+      values: PARAMS.iter().map(|spec| spec.default).collect(),
+      changed: vec![false; PARAMS.len()],
+    }
+  }
+}
+
+impl Params {
+  fn index(name: &str) -> Result<usize, ParamError> {
+    PARAMS
+      .iter()
+      .position(|spec| spec.name == name)
+      .ok_or_else(|| ParamError::Unknown(name.to_string()))
+  }
+
+  // Clamped into the param's range, returns what it was set to. Setting the same value again
+  // still counts as a change, e.g. for "erosion" to run again.
+  pub fn set(&mut self, name: &str, value: f64) -> Result<f64, ParamError> {
+    let index = Self::index(name)?;
+    let value = PARAMS[index].coerce(value);
+    self.values[index] = value;
+    self.changed[index] = true;
+    Ok(value)
+  }
+
+  // Parses `name=value`, as typed anywhere params are set from text. Bools also take true,
+  // false, on and off.
+  pub fn assign(&mut self, assignment: &str) -> Result<f64, ParamError> {
+    let (name, value) = assignment
+      .split_once('=')
+      .ok_or_else(|| ParamError::Syntax(assignment.to_string()))?;
+    let (name, value) = (name.trim(), value.trim());
+    let spec = find(name).ok_or_else(|| ParamError::Unknown(name.to_string()))?;
+    let parsed = match (spec.kind, value) {
+      (ParamKind::Bool, "true" | "on") => Some(1.0),
+      (ParamKind::Bool, "false" | "off") => Some(0.0),
+      _ => value.parse().ok(),
+    };
+    let parsed = parsed.ok_or_else(|| ParamError::Value {
+      name: name.to_string(),
+      value: value.to_string(),
+    })?;
+    self.set(name, parsed)
+  }
+
+  // The values a `[params]` table of settings.toml sets at startup, unknown names are skipped
+  pub fn set_all(&mut self, values: &BTreeMap<String, f64>) {
+    for (name, &value) in values {
+      if let Err(e) = self.set(name, value) {
+        log::warn!("{}", e);
+      }
+    }
+  }
+
+  // Takes on the value of state that lives elsewhere, without counting it as a change
+  pub fn sync(&mut self, name: &str, value: f64) {
+    if let Ok(index) = Self::index(name) {
+      self.values[index] = PARAMS[index].coerce(value);
+    }
+  }
+
+  // The plant params in `plants`, the rules and seed have no params
+  pub fn sync_plants(&mut self, plants: &PlantSettings) {
+    self.sync("plant_angle", plants.angle as f64);
+    self.sync("plant_iterations", plants.iterations as f64);
+    self.sync("plant_count", plants.count as f64);
+  }
+
+  // The wave params in `water`
+  pub fn sync_water(&mut self, water: &WaterSettings) {
+    self.sync("wave_scale", water.wave_scale as f64);
+    self.sync("wave_speed", water.wave_speed as f64);
+    self.sync("wave_strength", water.wave_strength as f64);
+  }
+
+  // The fog params in `fog`
+  pub fn sync_fog(&mut self, fog: &FogSettings) {
+    self.sync("fog_density", fog.density as f64);
+    self.sync("fog_scattering", fog.scattering as f64);
+  }
+
+  pub fn get(&self, name: &str) -> Option<f64> {
+    Self::index(name).ok().map(|index| self.values[index])
+  }
+
+  // The typed getters panic on names that aren't in PARAMS, those are bugs
+  pub fn float(&self, name: &str) -> f32 {
+    self.expect(name) as f32
+  }
+
+  pub fn int(&self, name: &str) -> u32 {
+    self.expect(name).max(0.0) as u32
+  }
+
+  pub fn flag(&self, name: &str) -> bool {
+    self.expect(name) != 0.0
+  }
+
+  fn expect(&self, name: &str) -> f64 {
+    self
+      .get(name)
+      .unwrap_or_else(|| panic!("no param named {}", name))
+  }
+
+  // Names of the params set since the last call
+  pub fn take_changed(&mut self) -> Vec<&'static str> {
+    let changed = PARAMS
+      .iter()
+      .zip(&mut self.changed)
+      .filter(|(_, changed)| **changed)
+      .map(|(spec, changed)| {
+        *changed = false;
+        spec.name
+      })
+      .collect();
+    changed
+  }
+}
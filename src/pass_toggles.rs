@@ -0,0 +1,74 @@
+use crate::profiler::PassTiming;
+
+// Runtime switches for the passes of a frame, to bisect which one causes an artifact or
+// costs the time. Passes register themselves the first time they ask whether they run.
+#[derive(Debug, Default)]
+pub struct PassToggles {
+  // in the order they were first drawn, with whether they run
+  passes: Vec<(String, bool)>,
+  selected: usize,
+  visible: bool,
+}
+
+impl PassToggles {
+  // Whether `name` should be drawn this frame, new passes start enabled
+  pub fn enabled(&mut self, name: &str) -> bool {
+    match self.passes.iter().find(|(pass, _)| pass == name) {
+      Some((_, enabled)) => *enabled,
+      None => {
+        self.passes.push((name.to_string(), true));
+        true
+      }
+    }
+  }
+
+  pub fn is_visible(&self) -> bool {
+    self.visible
+  }
+
+  // Shows or hides the list in the overlay, returns whether it's shown now
+  pub fn toggle_visible(&mut self) -> bool {
+    self.visible = !self.visible;
+    self.visible
+  }
+
+  // Picks a pass and switches it, only while the list is shown. Returns whether `action` is
+  // one of the pass list's in keymap.rs.
+  pub fn action(&mut self, action: &str) -> bool {
+    if !self.visible || self.passes.is_empty() {
+      return false;
+    }
+    let count = self.passes.len();
+    match action {
+      "previous_pass" => self.selected = (self.selected + count - 1) % count,
+      "next_pass" => self.selected = (self.selected + 1) % count,
+      "switch_pass" => {
+        let (name, enabled) = &mut self.passes[self.selected];
+        *enabled = !*enabled;
+        log::info!("{} pass {}", name, if *enabled { "on" } else { "off" });
+      }
+      _ => return false,
+    }
+    true
+  }
+
+  // One line per pass with its checkbox and gpu time, `timings` as the profiler reports
+  // them. The overlay font isn't monospaced, so the names go last.
+  pub fn overlay(&self, timings: &[PassTiming]) -> String {
+    let mut text = String::from("passes (page up/down, enter)");
+    for (i, (name, enabled)) in self.passes.iter().enumerate() {
+      let time = match timings.iter().find(|t| &t.label == name) {
+        Some(timing) => format!("{:.3}ms", timing.gpu_ms),
+        None => "-".to_string(),
+      };
+      text += &format!(
+        "\n{} [{}] {} {}",
+        if i == self.selected { '>' } else { ' ' },
+        if *enabled { 'x' } else { ' ' },
+        time,
+        name
+      );
+    }
+    text
+  }
+}
@@ -0,0 +1,911 @@
+use std::{num::NonZeroU32, ops::Range, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use wgpu::{
+  util::DeviceExt, BindGroupLayout, Device, Queue, RenderPass, RenderPipeline, TextureFormat,
+};
+
+use crate::{
+  gpu_memory::Tracked,
+  ibl::{EnvironmentMaps, IblBaker},
+  pipeline::{BlendMode, DrawConstants, DrawData, DRAW_CONSTANTS_SIZE},
+  reflection::ShaderReflection,
+  sampler::{SamplerSettings, Samplers, MAX_ANISOTROPY},
+  shader_variants::{MaterialFeatures, ShaderVariants},
+  uploader::Upload,
+};
+
+// Stand-in environment until a real one is set: a flat color roughly matching
+// Lighting's default ambient
+const FALLBACK_ENVIRONMENT: [u8; 4] = [20, 23, 31, 255];
+// Models the storage buffer of DrawData::Storage has room for, the ones past it aren't drawn
+pub const MAX_STORAGE_DRAWS: u32 = 1024;
+// Materials the bindless texture arrays hold at most, the ones past them aren't drawn
+pub const MAX_BINDLESS_MATERIALS: u32 = 1024;
+// Below this many the arrays aren't worth it and every material gets its own bind group
+const MIN_BINDLESS_MATERIALS: u32 = 64;
+// What the fragment stage has besides the material: environment, shadow maps and such
+const OTHER_TEXTURES: u32 = 16;
+// Indexing texture arrays by a material that differs between pixels, with fewer of them
+// bound than the arrays have room for
+const BINDLESS_FEATURES: wgpu::Features = wgpu::Features::TEXTURE_BINDING_ARRAY
+  .union(wgpu::Features::PARTIALLY_BOUND_BINDING_ARRAY)
+  .union(wgpu::Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING);
+
+// glTF 2.0 metallic-roughness factors, each multiplies its texture. The defaults are glTF's.
+// With a transparent `blend` the base color's alpha is the coverage, with `alpha_cutout`
+// it cuts holes where it's below a half, like glTF's MASK.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PbrMaterial {
+  pub base_color: [f32; 4],
+  pub metallic: f32,
+  pub roughness: f32,
+  pub normal_scale: f32,
+  pub occlusion_strength: f32,
+  pub blend: BlendMode,
+  #[serde(default)]
+  pub alpha_cutout: bool,
+}
+
+impl Default for PbrMaterial {
+  fn default() -> Self {
+    Self {
+      base_color: [1.0; 4],
+      metallic: 1.0,
+      roughness: 1.0,
+      normal_scale: 1.0,
+      occlusion_strength: 1.0,
+      blend: BlendMode::Opaque,
+      alpha_cutout: false,
+    }
+  }
+}
+
+// Matches `MaterialFactors` in pbr.wgsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct MaterialFactors {
+  base_color: [f32; 4],
+  metallic: f32,
+  roughness: f32,
+  normal_scale: f32,
+  occlusion_strength: f32,
+}
+
+const FACTORS_SIZE: wgpu::BufferAddress = std::mem::size_of::<MaterialFactors>() as _;
+
+impl MaterialFactors {
+  fn new(material: &PbrMaterial) -> Self {
+    Self {
+      base_color: material.base_color,
+      metallic: material.metallic,
+      roughness: material.roughness,
+      normal_scale: material.normal_scale,
+      occlusion_strength: material.occlusion_strength,
+    }
+  }
+}
+
+// Texture slots of a material. Empty slots get a 1x1 texture that leaves the factors as
+// they are. Albedo is expected to be sRGB (Texture::from_image), the rest linear
+// (Texture::from_image_linear). Shared, the bindless bind group is made again with every
+// material's views whenever one is added.
+#[derive(Default, Clone)]
+pub struct PbrTextures {
+  pub albedo: Option<Arc<wgpu::TextureView>>,
+  pub normal: Option<Arc<wgpu::TextureView>>,
+  // glTF packing: roughness in green, metallic in blue
+  pub metallic_roughness: Option<Arc<wgpu::TextureView>>,
+  // red channel
+  pub occlusion: Option<Arc<wgpu::TextureView>>,
+}
+
+// Matches `Environment` in pbr.wgsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct EnvironmentParams {
+  intensity: f32,
+  specular_mip_count: f32,
+  _padding: [f32; 2],
+}
+
+// A material's bind group, or its slot in the bindless one, and the pipeline variant it's
+// drawn with. With DrawData::Uniform it also holds the DrawConstants of the model drawn
+// with it, so every model gets a material of its own.
+pub struct MaterialBinding {
+  slot: MaterialSlot,
+  draw_buffer: Option<Tracked<wgpu::Buffer>>,
+  blend: BlendMode,
+  features: MaterialFeatures,
+  pipeline: Arc<RenderPipeline>,
+}
+
+enum MaterialSlot {
+  // a bind group of its own with its factors
+  Own {
+    bind_group: wgpu::BindGroup,
+    factors: Tracked<wgpu::Buffer>,
+  },
+  // the index of its factors and textures in the bindless arrays
+  Bindless(u32),
+  // the arrays were full, it isn't drawn
+  Full,
+}
+
+impl MaterialBinding {
+  pub fn blend(&self) -> BlendMode {
+    self.blend
+  }
+
+  pub fn features(&self) -> MaterialFeatures {
+    self.features
+  }
+
+  // What goes in the DrawConstants of a model with this material
+  pub fn index(&self) -> u32 {
+    match self.slot {
+      MaterialSlot::Bindless(index) => index,
+      MaterialSlot::Own { .. } | MaterialSlot::Full => 0,
+    }
+  }
+}
+
+// Every material in one bind group, bound once for all of the models
+struct Bindless {
+  capacity: u32,
+  // by slot, None for a free one
+  textures: Vec<Option<PbrTextures>>,
+  factors: Vec<MaterialFactors>,
+  factors_buffer: Tracked<wgpu::Buffer>,
+  bind_group: wgpu::BindGroup,
+}
+
+// How many materials one bind group can hold, None without the features for texture arrays
+// or room for enough of them. DrawData::Uniform keeps a model's DrawConstants in its
+// material's bind group, so every model needs its own then anyway.
+pub fn bindless_capacity(device: &Device, draw_data: DrawData) -> Option<u32> {
+  if !device.features().contains(BINDLESS_FEATURES) || draw_data == DrawData::Uniform {
+    return None;
+  }
+  let textures = device.limits().max_sampled_textures_per_shader_stage;
+  let capacity = (textures.saturating_sub(OTHER_TEXTURES) / 4).min(MAX_BINDLESS_MATERIALS);
+  (capacity >= MIN_BINDLESS_MATERIALS).then_some(capacity)
+}
+
+// The metallic-roughness pipelines with their material and environment bind groups
+pub struct PbrPipeline {
+  // one for each material feature combination and blend mode in use
+  variants: ShaderVariants,
+  draw_data: DrawData,
+  // None where every material has a bind group of its own
+  bindless: Option<Bindless>,
+  // every model's DrawConstants with DrawData::Storage, bound in all the materials
+  draws: Option<Tracked<wgpu::Buffer>>,
+  material_layout: BindGroupLayout,
+  environment_layout: BindGroupLayout,
+  material_sampler: wgpu::Sampler,
+  white_srgb: wgpu::TextureView,
+  white_linear: wgpu::TextureView,
+  flat_normal: wgpu::TextureView,
+  environment_buffer: Tracked<wgpu::Buffer>,
+  environment_sampler: wgpu::Sampler,
+  environment: wgpu::BindGroup,
+  ibl_baker: IblBaker,
+  brdf_lut: Tracked<wgpu::TextureView>,
+  // the last bake_environment()'s maps, the bind group would keep them alive on its own but
+  // they'd drop out of the memory stats
+  baked_environment: Option<EnvironmentMaps>,
+}
+
+impl PbrPipeline {
+  // `globals_layout` and `shadow_layout` are groups 0 and 1 of the scene pipeline, `format`
+  // and `sample_count` the main pass's
+  #[allow(clippy::too_many_arguments)]
+  pub fn new(
+    device: &Device,
+    queue: &Queue,
+    samplers: &Samplers,
+    format: TextureFormat,
+    sample_count: u32,
+    globals_layout: &BindGroupLayout,
+    shadow_layout: &BindGroupLayout,
+    draw_data: DrawData,
+  ) -> Self {
+    let capacity = bindless_capacity(device, draw_data);
+    let texture_array_entry = |binding, view_dimension, count| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility: wgpu::ShaderStages::FRAGMENT,
+      ty: wgpu::BindingType::Texture {
+        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        view_dimension,
+        multisampled: false,
+      },
+      count,
+    };
+    let texture_entry =
+      |binding, view_dimension| texture_array_entry(binding, view_dimension, None);
+    let uniform_entry = wgpu::BindGroupLayoutEntry {
+      binding: 0,
+      visibility: wgpu::ShaderStages::FRAGMENT,
+      ty: wgpu::BindingType::Buffer {
+        ty: wgpu::BufferBindingType::Uniform,
+        has_dynamic_offset: false,
+        min_binding_size: None,
+      },
+      count: None,
+    };
+    let sampler_entry = |binding| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility: wgpu::ShaderStages::FRAGMENT,
+      ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+      count: None,
+    };
+    let d2 = wgpu::TextureViewDimension::D2;
+    let mut material_entries = match capacity {
+      Some(capacity) => {
+        let count = NonZeroU32::new(capacity);
+        vec![
+          wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+              ty: wgpu::BufferBindingType::Storage { read_only: true },
+              has_dynamic_offset: false,
+              min_binding_size: wgpu::BufferSize::new(FACTORS_SIZE),
+            },
+            count: None,
+          },
+          texture_array_entry(1, d2, count),
+          texture_array_entry(2, d2, count),
+          texture_array_entry(3, d2, count),
+          texture_array_entry(4, d2, count),
+          sampler_entry(5),
+        ]
+      }
+      None => vec![
+        uniform_entry,
+        texture_entry(1, d2),
+        texture_entry(2, d2),
+        texture_entry(3, d2),
+        texture_entry(4, d2),
+        sampler_entry(5),
+      ],
+    };
+    let draws_binding = match draw_data {
+      DrawData::PushConstants => None,
+      DrawData::Uniform => Some(wgpu::BufferBindingType::Uniform),
+      DrawData::Storage => Some(wgpu::BufferBindingType::Storage { read_only: true }),
+    };
+    if let Some(ty) = draws_binding {
+      material_entries.push(wgpu::BindGroupLayoutEntry {
+        binding: 6,
+        visibility: wgpu::ShaderStages::VERTEX,
+        ty: wgpu::BindingType::Buffer {
+          ty,
+          has_dynamic_offset: false,
+          min_binding_size: wgpu::BufferSize::new(DRAW_CONSTANTS_SIZE as u64),
+        },
+        count: None,
+      });
+    }
+    let draws = (draw_data == DrawData::Storage).then(|| {
+      Tracked::buffer(device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Pbr Draws Buffer"),
+        size: (MAX_STORAGE_DRAWS * DRAW_CONSTANTS_SIZE) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+      }))
+    });
+    let material_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Pbr Material Layout"),
+      entries: &material_entries,
+    });
+    let cube = wgpu::TextureViewDimension::Cube;
+    let environment_entries = [
+      uniform_entry,
+      texture_entry(1, cube),
+      texture_entry(2, cube),
+      sampler_entry(3),
+      texture_entry(4, d2),
+    ];
+    let environment_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Pbr Environment Layout"),
+      entries: &environment_entries,
+    });
+    let layouts = [
+      globals_layout,
+      shadow_layout,
+      &material_layout,
+      &environment_layout,
+    ];
+    let variants = ShaderVariants::new(device, format, sample_count, &layouts, draw_data, capacity);
+    // a mistake in the entries above shows up here rather than when the first model is drawn
+    if cfg!(debug_assertions) {
+      for features in MaterialFeatures::all() {
+        let reflection = ShaderReflection::from_wgsl(&variants.source(features))
+          .expect("pbr.wgsl doesn't reflect");
+        for (group, entries) in [(2, &material_entries[..]), (3, &environment_entries[..])] {
+          if let Err(e) = reflection.check_bind_group(group, entries) {
+            panic!("pbr.wgsl and PbrPipeline's layouts differ: {}", e);
+          }
+        }
+      }
+    }
+
+    let material_sampler = samplers.create(
+      device,
+      "Pbr Material Sampler",
+      &SamplerSettings::anisotropic(MAX_ANISOTROPY),
+    );
+    let white_srgb = solid_texture(
+      device,
+      queue,
+      [255; 4],
+      TextureFormat::Rgba8UnormSrgb,
+      "Pbr White",
+    );
+    let white_linear = solid_texture(
+      device,
+      queue,
+      [255; 4],
+      TextureFormat::Rgba8Unorm,
+      "Pbr White Linear",
+    );
+    // +Z in tangent space
+    let flat_normal = solid_texture(
+      device,
+      queue,
+      [128, 128, 255, 255],
+      TextureFormat::Rgba8Unorm,
+      "Pbr Flat Normal",
+    );
+
+    let environment_buffer = Tracked::buffer(device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Pbr Environment Buffer"),
+      size: std::mem::size_of::<EnvironmentParams>() as wgpu::BufferAddress,
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    }));
+    // clamped so the BRDF lookup table doesn't wrap at its edges
+    let environment_sampler = device.create_sampler(
+      &SamplerSettings::trilinear()
+        .with_address_mode(wgpu::AddressMode::ClampToEdge)
+        .descriptor(Some("Pbr Environment Sampler")),
+    );
+    let ibl_baker = IblBaker::new(device);
+    let brdf_lut = ibl_baker.brdf_lut(device, queue);
+    let fallback_cube = solid_cube(device, queue, FALLBACK_ENVIRONMENT);
+    let environment = create_environment(
+      device,
+      &environment_layout,
+      &environment_buffer,
+      &fallback_cube,
+      &fallback_cube,
+      &environment_sampler,
+      &brdf_lut,
+    );
+    let params = EnvironmentParams {
+      intensity: 1.0,
+      specular_mip_count: 1.0,
+      _padding: [0.0; 2],
+    };
+    queue.write_buffer(&environment_buffer, 0, bytemuck::bytes_of(&params));
+
+    let mut pipeline = Self {
+      variants,
+      draw_data,
+      bindless: None,
+      draws,
+      material_layout,
+      environment_layout,
+      material_sampler,
+      white_srgb,
+      white_linear,
+      flat_normal,
+      environment_buffer,
+      environment_sampler,
+      environment,
+      ibl_baker,
+      brdf_lut,
+      baked_environment: None,
+    };
+    pipeline.bindless = capacity.map(|capacity| {
+      let factors_buffer = Tracked::buffer(device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Pbr Bindless Factors Buffer"),
+        size: capacity as wgpu::BufferAddress * FACTORS_SIZE,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+      }));
+      let bind_group = pipeline.create_bindless_group(device, &[], &factors_buffer);
+      Bindless {
+        capacity,
+        textures: Vec::new(),
+        factors: Vec::new(),
+        factors_buffer,
+        bind_group,
+      }
+    });
+    pipeline
+  }
+
+  // The bindless bind group with `textures` in the slots they're at, the fallbacks where
+  // there are none
+  fn create_bindless_group(
+    &self,
+    device: &Device,
+    textures: &[Option<PbrTextures>],
+    factors: &wgpu::Buffer,
+  ) -> wgpu::BindGroup {
+    // an array can't be bound empty
+    let slots = textures.len().max(1);
+    let views = |slot: fn(&PbrTextures) -> Option<&wgpu::TextureView>, fallback| {
+      (0..slots)
+        .map(|i| {
+          textures
+            .get(i)
+            .and_then(Option::as_ref)
+            .and_then(slot)
+            .unwrap_or(fallback)
+        })
+        .collect::<Vec<_>>()
+    };
+    let albedo = views(|t| t.albedo.as_deref(), &self.white_srgb);
+    let normal = views(|t| t.normal.as_deref(), &self.flat_normal);
+    let metallic_roughness = views(|t| t.metallic_roughness.as_deref(), &self.white_linear);
+    let occlusion = views(|t| t.occlusion.as_deref(), &self.white_linear);
+    let mut entries = vec![
+      wgpu::BindGroupEntry {
+        binding: 0,
+        resource: factors.as_entire_binding(),
+      },
+      wgpu::BindGroupEntry {
+        binding: 1,
+        resource: wgpu::BindingResource::TextureViewArray(&albedo),
+      },
+      wgpu::BindGroupEntry {
+        binding: 2,
+        resource: wgpu::BindingResource::TextureViewArray(&normal),
+      },
+      wgpu::BindGroupEntry {
+        binding: 3,
+        resource: wgpu::BindingResource::TextureViewArray(&metallic_roughness),
+      },
+      wgpu::BindGroupEntry {
+        binding: 4,
+        resource: wgpu::BindingResource::TextureViewArray(&occlusion),
+      },
+      wgpu::BindGroupEntry {
+        binding: 5,
+        resource: wgpu::BindingResource::Sampler(&self.material_sampler),
+      },
+    ];
+    if let Some(draws) = &self.draws {
+      entries.push(wgpu::BindGroupEntry {
+        binding: 6,
+        resource: draws.as_entire_binding(),
+      });
+    }
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Pbr Bindless Bind Group"),
+      layout: &self.material_layout,
+      entries: &entries,
+    })
+  }
+
+  // `draw` is what the model starts out with, see set_draw_constants() and upload_draws()
+  pub fn create_material(
+    &mut self,
+    device: &Device,
+    material: &PbrMaterial,
+    textures: PbrTextures,
+    draw: &DrawConstants,
+  ) -> MaterialBinding {
+    let features = MaterialFeatures {
+      normal_mapping: textures.normal.is_some(),
+      alpha_cutout: material.alpha_cutout,
+    };
+    let pipeline = self.variants.get(device, features, material.blend);
+    if self.bindless.is_some() {
+      return self.create_bindless_material(device, material, textures, features, pipeline);
+    }
+    let buffer = Tracked::buffer(
+      device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Pbr Material Buffer"),
+        contents: bytemuck::bytes_of(&MaterialFactors::new(material)),
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+      }),
+    );
+    let draw_buffer = (self.draw_data == DrawData::Uniform).then(|| {
+      Tracked::buffer(
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+          label: Some("Pbr Draw Buffer"),
+          contents: bytemuck::bytes_of(draw),
+          usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        }),
+      )
+    });
+    let mut entries = vec![
+      wgpu::BindGroupEntry {
+        binding: 0,
+        resource: buffer.as_entire_binding(),
+      },
+      slot_entry(1, textures.albedo.as_deref(), &self.white_srgb),
+      slot_entry(2, textures.normal.as_deref(), &self.flat_normal),
+      slot_entry(
+        3,
+        textures.metallic_roughness.as_deref(),
+        &self.white_linear,
+      ),
+      slot_entry(4, textures.occlusion.as_deref(), &self.white_linear),
+      wgpu::BindGroupEntry {
+        binding: 5,
+        resource: wgpu::BindingResource::Sampler(&self.material_sampler),
+      },
+    ];
+    if let Some(draws) = draw_buffer.as_ref().or(self.draws.as_ref()) {
+      entries.push(wgpu::BindGroupEntry {
+        binding: 6,
+        resource: draws.as_entire_binding(),
+      });
+    }
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Pbr Material Bind Group"),
+      layout: &self.material_layout,
+      entries: &entries,
+    });
+    MaterialBinding {
+      slot: MaterialSlot::Own {
+        bind_group,
+        factors: buffer,
+      },
+      draw_buffer,
+      blend: material.blend,
+      features,
+      pipeline,
+    }
+  }
+
+  // Takes the first free slot and makes the bind group again with the material's textures
+  // in it, the factors go up with upload_materials()
+  fn create_bindless_material(
+    &mut self,
+    device: &Device,
+    material: &PbrMaterial,
+    textures: PbrTextures,
+    features: MaterialFeatures,
+    pipeline: Arc<RenderPipeline>,
+  ) -> MaterialBinding {
+    let bindless = self
+      .bindless
+      .as_mut()
+      .expect("create_material() checks for the bindless arrays");
+    let free = bindless.textures.iter().position(Option::is_none);
+    let index = match free {
+      Some(index) => index,
+      None if bindless.textures.len() < bindless.capacity as usize => {
+        bindless.textures.push(None);
+        bindless.factors.push(MaterialFactors::default());
+        bindless.textures.len() - 1
+      }
+      None => {
+        log::warn!(
+          "no room for more than {} materials, the rest aren't drawn",
+          bindless.capacity
+        );
+        return MaterialBinding {
+          slot: MaterialSlot::Full,
+          draw_buffer: None,
+          blend: material.blend,
+          features,
+          pipeline,
+        };
+      }
+    };
+    bindless.textures[index] = Some(textures);
+    bindless.factors[index] = MaterialFactors::new(material);
+    self.rebuild_bindless(device);
+    MaterialBinding {
+      slot: MaterialSlot::Bindless(index as u32),
+      draw_buffer: None,
+      blend: material.blend,
+      features,
+      pipeline,
+    }
+  }
+
+  // After the bindless slots changed
+  fn rebuild_bindless(&mut self, device: &Device) {
+    let Some(bindless) = &self.bindless else {
+      return;
+    };
+    let bind_group =
+      self.create_bindless_group(device, &bindless.textures, &bindless.factors_buffer);
+    if let Some(bindless) = &mut self.bindless {
+      bindless.bind_group = bind_group;
+    }
+  }
+
+  // Frees a removed model's slot in the bindless arrays. Its textures stay bound until the
+  // next material takes a slot.
+  pub fn release_material(&mut self, material: MaterialBinding) {
+    if let (Some(bindless), MaterialSlot::Bindless(index)) = (&mut self.bindless, material.slot) {
+      bindless.textures[index as usize] = None;
+    }
+  }
+
+  // New factors, blend mode and cutout for a material, its textures stay
+  pub fn set_material(
+    &mut self,
+    device: &Device,
+    queue: &Queue,
+    binding: &mut MaterialBinding,
+    material: &PbrMaterial,
+  ) {
+    let factors = MaterialFactors::new(material);
+    match (&binding.slot, &mut self.bindless) {
+      (
+        MaterialSlot::Own {
+          factors: buffer, ..
+        },
+        _,
+      ) => {
+        queue.write_buffer(buffer, 0, bytemuck::bytes_of(&factors));
+      }
+      (MaterialSlot::Bindless(index), Some(bindless)) => {
+        bindless.factors[*index as usize] = factors;
+      }
+      _ => {}
+    }
+    let features = MaterialFeatures {
+      alpha_cutout: material.alpha_cutout,
+      ..binding.features
+    };
+    if (binding.features, binding.blend) != (features, material.blend) {
+      binding.features = features;
+      binding.blend = material.blend;
+      binding.pipeline = self.variants.get(device, features, material.blend);
+    }
+  }
+
+  // The bindless materials' factors, once a frame before they're drawn
+  pub fn upload_materials(&self, upload: &mut Upload) {
+    if let Some(bindless) = &self.bindless {
+      let factors = bytemuck::cast_slice(&bindless.factors);
+      upload.write(&bindless.factors_buffer, 0, factors);
+    }
+  }
+
+  // For a model that moved with DrawData::Uniform, the other ways take the constants at
+  // upload_draws() or bind_model()
+  pub fn set_draw_constants(
+    &self,
+    queue: &Queue,
+    material: &MaterialBinding,
+    draw: &DrawConstants,
+  ) {
+    if let Some(buffer) = &material.draw_buffer {
+      queue.write_buffer(buffer, 0, bytemuck::bytes_of(draw));
+    }
+  }
+
+  // Every model's DrawConstants in the order of their bind_model() `index` for
+  // DrawData::Storage, once a frame before they're drawn
+  pub fn upload_draws(&self, upload: &mut Upload, draws: &[DrawConstants]) {
+    let Some(buffer) = &self.draws else {
+      return;
+    };
+    let count = draws.len().min(MAX_STORAGE_DRAWS as usize);
+    upload.write(buffer, 0, bytemuck::cast_slice(&draws[..count]));
+  }
+
+  pub fn draw_data(&self) -> DrawData {
+    self.draw_data
+  }
+
+  // Whether the materials share one bind group, see bindless_capacity()
+  pub fn is_bindless(&self) -> bool {
+    self.bindless.is_some()
+  }
+
+  // Sets up the draw of the `index`th model after bind(), its material's pipeline included,
+  // and returns the instances to draw it with, None when it doesn't fit in the storage
+  // buffer or its material in the bindless arrays. `draw` is only used when it's pushed.
+  pub fn bind_model<'a>(
+    &'a self,
+    pass: &mut RenderPass<'a>,
+    material: &'a MaterialBinding,
+    draw: &DrawConstants,
+    index: u32,
+  ) -> Option<Range<u32>> {
+    match &material.slot {
+      MaterialSlot::Own { bind_group, .. } => pass.set_bind_group(2, bind_group, &[]),
+      // bind() set the one bind group they're all in
+      MaterialSlot::Bindless(_) => {}
+      MaterialSlot::Full => return None,
+    }
+    pass.set_pipeline(&material.pipeline);
+    match self.draw_data {
+      DrawData::PushConstants => {
+        pass.set_push_constants(wgpu::ShaderStages::VERTEX, 0, bytemuck::bytes_of(draw));
+        Some(0..1)
+      }
+      DrawData::Uniform => Some(0..1),
+      DrawData::Storage => (index < MAX_STORAGE_DRAWS).then(|| index..index + 1),
+    }
+  }
+
+  // Image based lighting hook: `irradiance` lights the diffuse part, `specular` holds the
+  // radiance prefiltered for roughness 0 to 1 across its `specular_mip_count` levels
+  pub fn set_environment(
+    &mut self,
+    device: &Device,
+    queue: &Queue,
+    irradiance: &wgpu::TextureView,
+    specular: &wgpu::TextureView,
+    specular_mip_count: u32,
+    intensity: f32,
+  ) {
+    self.baked_environment = None;
+    self.environment = create_environment(
+      device,
+      &self.environment_layout,
+      &self.environment_buffer,
+      irradiance,
+      specular,
+      &self.environment_sampler,
+      &self.brdf_lut,
+    );
+    let params = EnvironmentParams {
+      intensity,
+      specular_mip_count: specular_mip_count.max(1) as f32,
+      _padding: [0.0; 2],
+    };
+    queue.write_buffer(&self.environment_buffer, 0, bytemuck::bytes_of(&params));
+  }
+
+  // Prefilters `environment`, e.g. the skybox's cube, and lights with the result
+  pub fn bake_environment(
+    &mut self,
+    device: &Device,
+    queue: &Queue,
+    environment: &wgpu::TextureView,
+    intensity: f32,
+  ) {
+    let maps = self.ibl_baker.bake(device, queue, environment);
+    self.set_environment(
+      device,
+      queue,
+      &maps.irradiance,
+      &maps.specular,
+      maps.specular_mip_count,
+      intensity,
+    );
+    self.baked_environment = Some(maps);
+  }
+
+  // Group 3 of the PBR pipelines, for other passes lit by the same environment
+  pub fn environment_layout(&self) -> &BindGroupLayout {
+    &self.environment_layout
+  }
+
+  pub fn environment(&self) -> &wgpu::BindGroup {
+    &self.environment
+  }
+
+  // The pipeline variants, e.g. to compile them all up front
+  pub fn variants_mut(&mut self) -> &mut ShaderVariants {
+    &mut self.variants
+  }
+
+  // Sets the environment, groups 0 and 1 are left to the caller and bind_model() sets the
+  // pipeline and group 2 for every model, unless they're all bindless and 2 is set here once
+  pub fn bind<'a>(&'a self, pass: &mut RenderPass<'a>) {
+    if let Some(bindless) = &self.bindless {
+      pass.set_bind_group(2, &bindless.bind_group, &[]);
+    }
+    pass.set_bind_group(3, &self.environment, &[]);
+  }
+}
+
+fn slot_entry<'a>(
+  binding: u32,
+  slot: Option<&'a wgpu::TextureView>,
+  fallback: &'a wgpu::TextureView,
+) -> wgpu::BindGroupEntry<'a> {
+  wgpu::BindGroupEntry {
+    binding,
+    resource: wgpu::BindingResource::TextureView(slot.unwrap_or(fallback)),
+  }
+}
+
+fn create_environment(
+  device: &Device,
+  layout: &BindGroupLayout,
+  buffer: &Tracked<wgpu::Buffer>,
+  irradiance: &wgpu::TextureView,
+  specular: &wgpu::TextureView,
+  sampler: &wgpu::Sampler,
+  brdf_lut: &wgpu::TextureView,
+) -> wgpu::BindGroup {
+  device.create_bind_group(&wgpu::BindGroupDescriptor {
+    label: Some("Pbr Environment Bind Group"),
+    layout,
+    entries: &[
+      wgpu::BindGroupEntry {
+        binding: 0,
+        resource: buffer.as_entire_binding(),
+      },
+      wgpu::BindGroupEntry {
+        binding: 1,
+        resource: wgpu::BindingResource::TextureView(irradiance),
+      },
+      wgpu::BindGroupEntry {
+        binding: 2,
+        resource: wgpu::BindingResource::TextureView(specular),
+      },
+      wgpu::BindGroupEntry {
+        binding: 3,
+        resource: wgpu::BindingResource::Sampler(sampler),
+      },
+      wgpu::BindGroupEntry {
+        binding: 4,
+        resource: wgpu::BindingResource::TextureView(brdf_lut),
+      },
+    ],
+  })
+}
+
+fn solid_texture(
+  device: &Device,
+  queue: &Queue,
+  rgba: [u8; 4],
+  format: TextureFormat,
+  label: &str,
+) -> wgpu::TextureView {
+  device
+    .create_texture_with_data(
+      queue,
+      &wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+          width: 1,
+          height: 1,
+          depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+      },
+      &rgba,
+    )
+    .create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+fn solid_cube(device: &Device, queue: &Queue, rgba: [u8; 4]) -> wgpu::TextureView {
+  device
+    .create_texture_with_data(
+      queue,
+      &wgpu::TextureDescriptor {
+        label: Some("Pbr Fallback Environment"),
+        size: wgpu::Extent3d {
+          width: 1,
+          height: 1,
+          depth_or_array_layers: 6,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+      },
+      &rgba.repeat(6),
+    )
+    .create_view(&wgpu::TextureViewDescriptor {
+      dimension: Some(wgpu::TextureViewDimension::Cube),
+      ..Default::default()
+    })
+}
@@ -0,0 +1,274 @@
+// Metallic-roughness PBR following the glTF 2.0 material model: GGX distribution,
+// Smith-Schlick visibility and Schlick's Fresnel, with ambient light from the environment
+// cube maps in group 3 (the image based lighting hook). Specialised per material by
+// shader_variants.rs:
+//   NORMAL_MAPPING        perturbs the normal with the tangent space normal map, without it
+//                         the mesh's normal is used as it is and the map isn't sampled
+//   ALPHA_CUTOUT          the base color's alpha cuts holes, tested against ALPHA_CUTOFF
+//                         or with ALPHA_TO_COVERAGE (MSAA only) turned into the coverage
+
+#include "lighting.wgsl"
+
+struct MaterialFactors {
+    base_color: vec4<f32>,
+    metallic: f32,
+    roughness: f32,
+    normal_scale: f32,
+    occlusion_strength: f32,
+};
+#ifdef BINDLESS
+// every material's, DrawConstants' material picks one of them and of each texture array
+@group(2) @binding(0)
+var<storage, read> materials: array<MaterialFactors>;
+@group(2) @binding(1)
+var albedo_maps: binding_array<texture_2d<f32>, #{BINDLESS}>;
+@group(2) @binding(2)
+var normal_maps: binding_array<texture_2d<f32>, #{BINDLESS}>;
+@group(2) @binding(3)
+var metallic_roughness_maps: binding_array<texture_2d<f32>, #{BINDLESS}>;
+@group(2) @binding(4)
+var occlusion_maps: binding_array<texture_2d<f32>, #{BINDLESS}>;
+#else
+@group(2) @binding(0)
+var<uniform> factors: MaterialFactors;
+// sRGB, rgb: albedo, a: alpha
+@group(2) @binding(1)
+var albedo_map: texture_2d<f32>;
+// linear, tangent space
+@group(2) @binding(2)
+var normal_map: texture_2d<f32>;
+// linear, glTF packing: g: roughness, b: metallic
+@group(2) @binding(3)
+var metallic_roughness_map: texture_2d<f32>;
+// linear, r: ambient occlusion
+@group(2) @binding(4)
+var occlusion_map: texture_2d<f32>;
+#endif
+@group(2) @binding(5)
+var material_sampler: sampler;
+
+// The model's transform and tint, see DrawData in pipeline.rs for the ways they come in
+struct DrawConstants {
+    model: mat4x4<f32>,
+    tint: vec4<f32>,
+    // x: the material's slot with BINDLESS. GL's push constants need a uniform for every
+    // word they set and take no u32, so no padding and signed.
+    material: vec4<i32>,
+};
+#ifdef PUSH_CONSTANTS
+var<push_constant> draw: DrawConstants;
+#else
+#ifdef STORAGE_DRAWS
+// every model's, indexed by the draw's instance
+@group(2) @binding(6)
+var<storage, read> draws: array<DrawConstants>;
+#else
+@group(2) @binding(6)
+var<uniform> draw: DrawConstants;
+#endif
+#endif
+
+struct Environment {
+    // x: intensity, y: mip count of the specular cube
+    params: vec4<f32>,
+};
+@group(3) @binding(0)
+var<uniform> environment: Environment;
+// cosine convolved radiance for diffuse ambient
+@group(3) @binding(1)
+var irradiance_map: texture_cube<f32>;
+// radiance prefiltered for increasing roughness down the mip chain
+@group(3) @binding(2)
+var specular_map: texture_cube<f32>;
+@group(3) @binding(3)
+var environment_sampler: sampler;
+// x: n_dot_v, y: roughness
+@group(3) @binding(4)
+var brdf_lut: texture_2d<f32>;
+
+const PI: f32 = 3.14159265;
+// Alpha below this is a hole in cutout materials
+const ALPHA_CUTOFF: f32 = 0.5;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) world_tangent: vec4<f32>,
+    @location(3) uv: vec2<f32>,
+    @location(4) light_position: vec4<f32>,
+    @location(5) tint: vec4<f32>,
+    @location(6) @interpolate(flat) material: u32,
+};
+
+@vertex
+fn vs_main(vertex: VertexInput, @builtin(instance_index) instance_index: u32) -> VertexOutput {
+#ifdef STORAGE_DRAWS
+    let draw = draws[instance_index];
+#endif
+    let model = draw.model;
+    let world = model * vec4<f32>(vertex.position, 1.0);
+    var out: VertexOutput;
+    out.clip_position = globals.view_proj * world;
+    out.world_position = world.xyz;
+    // fine as long as the models are only uniformly scaled
+    out.world_normal = (model * vec4<f32>(vertex.normal, 0.0)).xyz;
+    out.world_tangent = vec4<f32>((model * vec4<f32>(vertex.tangent.xyz, 0.0)).xyz, vertex.tangent.w);
+    out.uv = vertex.uv;
+    out.light_position = globals.light_view_proj * world;
+    out.tint = draw.tint;
+    out.material = u32(draw.material.x);
+    return out;
+}
+
+fn distribution_ggx(n_dot_h: f32, alpha: f32) -> f32 {
+    let alpha2 = alpha * alpha;
+    let d = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    return alpha2 / (PI * d * d);
+}
+
+// Smith-Schlick G divided by the 4 n.l n.v of the specular term
+fn visibility_smith(n_dot_v: f32, n_dot_l: f32, alpha: f32) -> f32 {
+    let k = alpha / 2.0;
+    let g_v = n_dot_v / (n_dot_v * (1.0 - k) + k);
+    let g_l = n_dot_l / (n_dot_l * (1.0 - k) + k);
+    return g_v * g_l / max(4.0 * n_dot_v * n_dot_l, 0.0001);
+}
+
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (1.0 - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+struct Surface {
+    normal: vec3<f32>,
+    to_camera: vec3<f32>,
+    albedo: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+    f0: vec3<f32>,
+};
+
+// Outgoing radiance for one light of unit intensity arriving from `to_light`
+fn brdf(surface: Surface, to_light: vec3<f32>) -> vec3<f32> {
+    let n_dot_l = dot(surface.normal, to_light);
+    if n_dot_l <= 0.0 {
+        return vec3<f32>(0.0);
+    }
+    let half_dir = normalize(to_light + surface.to_camera);
+    let n_dot_v = max(dot(surface.normal, surface.to_camera), 0.0001);
+    let n_dot_h = max(dot(surface.normal, half_dir), 0.0);
+    let alpha = surface.roughness * surface.roughness;
+
+    let fresnel = fresnel_schlick(max(dot(half_dir, surface.to_camera), 0.0), surface.f0);
+    let specular = fresnel * distribution_ggx(n_dot_h, alpha) * visibility_smith(n_dot_v, n_dot_l, alpha);
+    // metals have no diffuse, what isn't reflected is absorbed
+    let diffuse = (1.0 - fresnel) * (1.0 - surface.metallic) * surface.albedo / PI;
+    return (diffuse + specular) * n_dot_l;
+}
+
+// The split sum environment BRDF, scale and bias for F0 baked by ibl.wgsl
+fn environment_brdf(f0: vec3<f32>, roughness: f32, n_dot_v: f32) -> vec3<f32> {
+    let ab = textureSampleLevel(brdf_lut, environment_sampler, vec2<f32>(n_dot_v, roughness), 0.0).rg;
+    return f0 * ab.x + ab.y;
+}
+
+fn ambient(surface: Surface) -> vec3<f32> {
+    let n_dot_v = max(dot(surface.normal, surface.to_camera), 0.0001);
+    let irradiance = textureSample(irradiance_map, environment_sampler, surface.normal).rgb;
+    let reflected = reflect(-surface.to_camera, surface.normal);
+    let level = surface.roughness * (environment.params.y - 1.0);
+    let radiance = textureSampleLevel(specular_map, environment_sampler, reflected, level).rgb;
+
+    let specular = radiance * environment_brdf(surface.f0, surface.roughness, n_dot_v);
+    let diffuse = irradiance * surface.albedo * (1.0 - surface.metallic);
+    return (diffuse + specular) * environment.params.x;
+}
+
+// What the material's textures hold at `uv`
+struct MaterialSample {
+    albedo: vec4<f32>,
+    normal: vec3<f32>,
+    metallic_roughness: vec4<f32>,
+    occlusion: f32,
+};
+
+#ifdef BINDLESS
+fn sample_material(material: u32, uv: vec2<f32>) -> MaterialSample {
+    var out: MaterialSample;
+    out.albedo = textureSample(albedo_maps[material], material_sampler, uv);
+#ifdef NORMAL_MAPPING
+    out.normal = textureSample(normal_maps[material], material_sampler, uv).xyz;
+#endif
+    out.metallic_roughness = textureSample(metallic_roughness_maps[material], material_sampler, uv);
+    out.occlusion = textureSample(occlusion_maps[material], material_sampler, uv).r;
+    return out;
+}
+#else
+// one material per bind group, `material` is always 0
+fn sample_material(material: u32, uv: vec2<f32>) -> MaterialSample {
+    var out: MaterialSample;
+    out.albedo = textureSample(albedo_map, material_sampler, uv);
+#ifdef NORMAL_MAPPING
+    out.normal = textureSample(normal_map, material_sampler, uv).xyz;
+#endif
+    out.metallic_roughness = textureSample(metallic_roughness_map, material_sampler, uv);
+    out.occlusion = textureSample(occlusion_map, material_sampler, uv).r;
+    return out;
+}
+#endif
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+#ifdef BINDLESS
+    let factors = materials[in.material];
+#endif
+    let textures = sample_material(in.material, in.uv);
+    var base_color = textures.albedo * factors.base_color * in.tint;
+#ifdef ALPHA_CUTOUT
+#ifdef ALPHA_TO_COVERAGE
+    // sharpen alpha to about a pixel wide ramp around the cutoff, the coverage mask then
+    // gives crisp but antialiased edges instead of a blurry fade
+    base_color.a = saturate((base_color.a - ALPHA_CUTOFF) / max(fwidth(base_color.a), 0.0001) + 0.5);
+#else
+    if base_color.a < ALPHA_CUTOFF {
+        discard;
+    }
+    base_color.a = 1.0;
+#endif
+#endif
+    let metallic_roughness = textures.metallic_roughness;
+    let occlusion = textures.occlusion;
+
+    let n = normalize(in.world_normal);
+    var surface: Surface;
+#ifdef NORMAL_MAPPING
+    let tangent_normal = textures.normal * 2.0 - 1.0;
+    let t = normalize(in.world_tangent.xyz - n * dot(n, in.world_tangent.xyz));
+    let b = cross(n, t) * in.world_tangent.w;
+    let scaled = vec3<f32>(tangent_normal.xy * factors.normal_scale, tangent_normal.z);
+    surface.normal = normalize(mat3x3<f32>(t, b, n) * scaled);
+#else
+    surface.normal = n;
+#endif
+    surface.to_camera = normalize(globals.camera_position.xyz - in.world_position);
+    surface.albedo = base_color.rgb;
+    surface.metallic = saturate(factors.metallic * metallic_roughness.b);
+    // very low roughness turns highlights into single pixel sparkles
+    surface.roughness = clamp(factors.roughness * metallic_roughness.g, 0.045, 1.0);
+    // dielectrics reflect about 4% head on, metals tint the reflection with their albedo
+    surface.f0 = mix(vec3<f32>(0.04), surface.albedo, surface.metallic);
+
+    var color = lighting.sun_color.rgb * brdf(surface, lighting.sun_direction.xyz)
+        * shadow_factor(in.light_position);
+    let start = cluster_start(in.world_position);
+    for (var i = 0u; i < min(cluster_lights[start], MAX_CLUSTER_LIGHTS); i += 1u) {
+        let light = point_lights[cluster_lights[start + 1u + i]];
+        let offset = light.position - in.world_position;
+        let distance = length(offset);
+        let radiance = light.color * light.intensity * attenuation(distance, light.range);
+        color += radiance * brdf(surface, offset / distance);
+    }
+    let ao = mix(1.0, occlusion, factors.occlusion_strength);
+    color += ambient(surface) * ao;
+    return vec4<f32>(color, base_color.a);
+}
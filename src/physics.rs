@@ -0,0 +1,269 @@
+// Rigid bodies from rapier3d, built with the `physics` feature. The world steps with the
+// simulation's fixed ticks like the crowd does, and every body linked to an entity moves it:
+// its Transform gets the body's position blended `alpha` of the way from the tick before,
+// and the ecs systems carry that to the model's instance buffer. Bodies get box colliders
+// around their mesh's bounds, good enough for the cubes of the demo 3 drops.
+
+use rapier3d::{na, prelude::*};
+use wgpu::Device;
+
+use crate::{
+  ecs::{self, Transform},
+  math::{self, Vec3},
+  mesh::Mesh,
+  pbr::{PbrMaterial, PbrTextures},
+  raycast::Aabb,
+  scene::Scene,
+  scene_file::MeshRef,
+};
+
+const GRAVITY: Vec3 = [0.0, -9.81, 0.0];
+// the demo's ground slab, its top at y = 0 under the scene's grid
+const GROUND_HALF_EXTENT: f32 = 20.0;
+const DROPPED_CUBES: usize = 12;
+// half extent
+const DROPPED_CUBE_SIZE: f32 = 0.25;
+
+// An entity following a body
+struct Link {
+  body: RigidBodyHandle,
+  entity: ecs::Entity,
+  // where the body was before the last tick
+  previous: Isometry<Real>,
+}
+
+pub struct Physics {
+  gravity: Vector<Real>,
+  integration: IntegrationParameters,
+  pipeline: PhysicsPipeline,
+  islands: IslandManager,
+  broad_phase: BroadPhase,
+  narrow_phase: NarrowPhase,
+  bodies: RigidBodySet,
+  colliders: ColliderSet,
+  impulse_joints: ImpulseJointSet,
+  multibody_joints: MultibodyJointSet,
+  ccd: CCDSolver,
+  links: Vec<Link>,
+  // the dropped cubes and the ground under them while they're out
+  demo: Option<(Vec<ecs::Entity>, RigidBodyHandle)>,
+}
+
+impl Default for Physics {
+  fn default() -> Self {
+    Self {
+      gravity: vector![GRAVITY[0], GRAVITY[1], GRAVITY[2]],
+      integration: IntegrationParameters::default(),
+      pipeline: PhysicsPipeline::new(),
+      islands: IslandManager::new(),
+      broad_phase: BroadPhase::new(),
+      narrow_phase: NarrowPhase::new(),
+      bodies: RigidBodySet::new(),
+      colliders: ColliderSet::new(),
+      impulse_joints: ImpulseJointSet::new(),
+      multibody_joints: MultibodyJointSet::new(),
+      ccd: CCDSolver::new(),
+      links: Vec::new(),
+      demo: None,
+    }
+  }
+}
+
+impl Physics {
+  pub fn bodies(&self) -> &RigidBodySet {
+    &self.bodies
+  }
+
+  pub fn bodies_mut(&mut self) -> &mut RigidBodySet {
+    &mut self.bodies
+  }
+
+  // Whether any linked body is awake and needs simulation ticks, sleeping ones don't move
+  pub fn is_running(&self) -> bool {
+    self.links.iter().any(|link| {
+      self
+        .bodies
+        .get(link.body)
+        .is_some_and(|body| body.is_dynamic() && !body.is_sleeping())
+    })
+  }
+
+  pub fn add_body(&mut self, body: RigidBody, colliders: Vec<Collider>) -> RigidBodyHandle {
+    let handle = self.bodies.insert(body);
+    for collider in colliders {
+      self
+        .colliders
+        .insert_with_parent(collider, handle, &mut self.bodies);
+    }
+    handle
+  }
+
+  // Takes out the body with its colliders, and the link of the entity following it
+  pub fn remove_body(&mut self, body: RigidBodyHandle) {
+    self.bodies.remove(
+      body,
+      &mut self.islands,
+      &mut self.colliders,
+      &mut self.impulse_joints,
+      &mut self.multibody_joints,
+      true,
+    );
+    self.links.retain(|link| link.body != body);
+  }
+
+  // Moves `entity` with `body` from the next sync() on. The entity has to be a root, its
+  // Transform is taken for one in the world.
+  pub fn link(&mut self, body: RigidBodyHandle, entity: ecs::Entity) {
+    let Some(previous) = self.bodies.get(body).map(|body| *body.position()) else {
+      return;
+    };
+    self.links.push(Link {
+      body,
+      entity,
+      previous,
+    });
+  }
+
+  // Scene::spawn_model() with a body of `body`'s kind where `transform` puts it, colliding
+  // as the box around the mesh. Its scale only goes to the collider.
+  #[allow(clippy::too_many_arguments)]
+  pub fn spawn_model(
+    &mut self,
+    device: &Device,
+    scene: &mut Scene,
+    mesh: &Mesh,
+    material: &PbrMaterial,
+    textures: PbrTextures,
+    transform: Transform,
+    body: RigidBodyBuilder,
+  ) -> (ecs::Entity, RigidBodyHandle) {
+    let entity = scene.spawn_model(device, mesh, material, textures, transform, None);
+    let collider = aabb_collider(&Aabb::from_mesh(mesh), transform.scale);
+    let body = body.position(to_isometry(&transform)).build();
+    let handle = self.add_body(body, vec![collider]);
+    self.link(handle, entity);
+    (entity, handle)
+  }
+
+  // Runs `ticks` fixed steps of `tick` seconds
+  pub fn step(&mut self, ticks: u32, tick: f32) {
+    self.integration.dt = tick;
+    for _ in 0..ticks {
+      for link in &mut self.links {
+        if let Some(body) = self.bodies.get(link.body) {
+          link.previous = *body.position();
+        }
+      }
+      self.pipeline.step(
+        &self.gravity,
+        &self.integration,
+        &mut self.islands,
+        &mut self.broad_phase,
+        &mut self.narrow_phase,
+        &mut self.bodies,
+        &mut self.colliders,
+        &mut self.impulse_joints,
+        &mut self.multibody_joints,
+        &mut self.ccd,
+        None,
+        &(),
+        &(),
+      );
+    }
+  }
+
+  // Moves the linked entities `alpha` of the way from the tick before the last to the last
+  pub fn sync(&self, world: &mut bevy_ecs::world::World, alpha: f32) {
+    for link in &self.links {
+      let Some(body) = self.bodies.get(link.body) else {
+        continue;
+      };
+      let Some(mut transform) = world.get_mut::<Transform>(link.entity) else {
+        continue;
+      };
+      let position = link.previous.lerp_slerp(body.position(), alpha);
+      transform.translation = position.translation.vector.into();
+      transform.rotation = position.rotation.coords.into();
+    }
+  }
+
+  // Whether the cubes of toggle_demo() are out
+  pub fn has_demo(&self) -> bool {
+    self.demo.is_some()
+  }
+
+  // 3, a dozen cubes dropped onto a ground slab, or taken away again
+  pub fn toggle_demo(&mut self, device: &Device, scene: &mut Scene) {
+    if let Some((entities, ground)) = self.demo.take() {
+      for entity in entities {
+        let link = self.links.iter().find(|link| link.entity == entity);
+        if let Some(body) = link.map(|link| link.body) {
+          self.remove_body(body);
+        }
+        scene.despawn(entity);
+      }
+      self.remove_body(ground);
+      log::info!("physics demo off");
+      return;
+    }
+    let ground = RigidBodyBuilder::fixed()
+      .translation(vector![0.0, -0.5, 0.0])
+      .build();
+    let slab = ColliderBuilder::cuboid(GROUND_HALF_EXTENT, 0.5, GROUND_HALF_EXTENT).build();
+    let ground = self.add_body(ground, vec![slab]);
+    let cube = Mesh::cube(DROPPED_CUBE_SIZE);
+    let mut rng = 7;
+    let entities = (0..DROPPED_CUBES)
+      .map(|i| {
+        let [r, g, b] = math::hue_to_rgb(math::next_random(&mut rng));
+        let material = PbrMaterial {
+          base_color: [r, g, b, 1.0],
+          metallic: 0.0,
+          roughness: 0.5,
+          ..Default::default()
+        };
+        // a loose column, tipped a little so they tumble
+        let mut spread = || (math::next_random(&mut rng) - 0.5) * 0.6;
+        let transform = Transform {
+          translation: [2.5 + spread(), 2.0 + 0.8 * i as f32, 2.5 + spread()],
+          rotation: math::quat_axis_angle([spread(), 1.0, spread()], spread() * 3.0),
+          ..Transform::IDENTITY
+        };
+        let body = RigidBodyBuilder::dynamic();
+        let (entity, _) = self.spawn_model(
+          device,
+          scene,
+          &cube,
+          &material,
+          PbrTextures::default(),
+          transform,
+          body,
+        );
+        // saved with the scene, without its body
+        let mesh = MeshRef::Cube(DROPPED_CUBE_SIZE);
+        scene.world_mut().entity_mut(entity).insert(mesh);
+        entity
+      })
+      .collect();
+    self.demo = Some((entities, ground));
+    log::info!("physics demo on");
+  }
+}
+
+// A box collider around `bounds` scaled by `scale`, in the body's space
+pub fn aabb_collider(bounds: &Aabb, scale: Vec3) -> Collider {
+  let half: Vec3 = std::array::from_fn(|i| 0.5 * (bounds.max[i] - bounds.min[i]) * scale[i]);
+  let center: Vec3 = std::array::from_fn(|i| 0.5 * (bounds.max[i] + bounds.min[i]) * scale[i]);
+  ColliderBuilder::cuboid(half[0], half[1], half[2])
+    .translation(center.into())
+    .build()
+}
+
+// Where `transform` puts a body, without its scale
+fn to_isometry(transform: &Transform) -> Isometry<Real> {
+  let [x, y, z, w] = transform.rotation;
+  Isometry::from_parts(
+    transform.translation.into(),
+    na::UnitQuaternion::from_quaternion(na::Quaternion::new(w, x, y, z)),
+  )
+}
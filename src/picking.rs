@@ -0,0 +1,301 @@
+use std::sync::{Arc, Mutex};
+
+use wgpu::{
+  util::DeviceExt, BindGroupLayout, CommandEncoder, Device, RenderPass, TextureFormat, TextureView,
+};
+
+use crate::{gpu_memory::Tracked, hdr::DEPTH_FORMAT, pipeline::id_pipe};
+
+// One id per pixel, 0 where nothing was drawn
+pub const ID_FORMAT: TextureFormat = TextureFormat::R32Uint;
+// Uniform slots for the ids of the draws, the first ones are the fixed kinds and the rest
+// one per model. Models past the last slot share it.
+const SLOT_COUNT: u32 = 64;
+const MODEL_SLOT: u32 = 4;
+// entity kind in the top byte of an id, the index below it
+const KIND_SHIFT: u32 = 24;
+const INDEX_MASK: u32 = (1 << KIND_SHIFT) - 1;
+
+// Something in the shadowed scene the cursor can land on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Entity {
+  Ground,
+  Cube(u32),
+  // one of the crowd's agents
+  Agent(u32),
+  // the terrain and its plants
+  Terrain,
+  // added with Scene::add_model, in that order
+  Model(u32),
+}
+
+impl Entity {
+  // What the id pass writes for it
+  pub fn id(self) -> u32 {
+    let (kind, index) = match self {
+      Entity::Ground => (1, 0),
+      Entity::Cube(i) => (2, i),
+      Entity::Agent(i) => (3, i),
+      Entity::Terrain => (4, 0),
+      Entity::Model(i) => (5, i),
+    };
+    kind << KIND_SHIFT | (index & INDEX_MASK)
+  }
+
+  pub fn from_id(id: u32) -> Option<Self> {
+    let index = id & INDEX_MASK;
+    match id >> KIND_SHIFT {
+      1 => Some(Entity::Ground),
+      2 => Some(Entity::Cube(index)),
+      3 => Some(Entity::Agent(index)),
+      4 => Some(Entity::Terrain),
+      5 => Some(Entity::Model(index)),
+      _ => None,
+    }
+  }
+
+  // The uniform slot holding the id of the draw's first instance
+  fn slot(self) -> u32 {
+    match self {
+      Entity::Ground => 0,
+      Entity::Cube(_) => 1,
+      Entity::Agent(_) => 2,
+      Entity::Terrain => 3,
+      Entity::Model(i) => (MODEL_SLOT + i).min(SLOT_COUNT - 1),
+    }
+  }
+}
+
+struct Targets {
+  ids: Tracked<wgpu::Texture>,
+  id_view: TextureView,
+  depth_view: Tracked<TextureView>,
+  size: (u32, u32),
+}
+
+// The read back pixel, `mapped` is filled in by the map_async callback
+struct Readback {
+  buffer: wgpu::Buffer,
+  mapped: Arc<Mutex<Option<Result<(), wgpu::BufferAsyncError>>>>,
+}
+
+// Mouse picking with an id buffer: the scene is drawn again writing entity ids instead of
+// colors, only at the clicked pixel, and that one pixel is read back
+pub struct Picker {
+  targets: Option<Targets>,
+  pipeline: wgpu::RenderPipeline,
+  bind_group: wgpu::BindGroup,
+  // bytes between the slots, the uniform offset alignment
+  stride: u32,
+  readback: Option<Readback>,
+}
+
+impl Picker {
+  // `globals_layout` is group 0 of the scene pipelines
+  pub fn new(device: &Device, globals_layout: &BindGroupLayout) -> Self {
+    let stride = device.limits().min_uniform_buffer_offset_alignment;
+    let mut slots = vec![0u8; (stride * SLOT_COUNT) as usize];
+    let fixed = [
+      Entity::Ground,
+      Entity::Cube(0),
+      Entity::Agent(0),
+      Entity::Terrain,
+    ];
+    let models = (0..SLOT_COUNT - MODEL_SLOT).map(Entity::Model);
+    for entity in fixed.into_iter().chain(models) {
+      let offset = (entity.slot() * stride) as usize;
+      slots[offset..offset + 4].copy_from_slice(&entity.id().to_le_bytes());
+    }
+    let buffer = Tracked::buffer(
+      device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Picking Id Buffer"),
+        contents: &slots,
+        usage: wgpu::BufferUsages::UNIFORM,
+      }),
+    );
+    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Picking Bind Group Layout"),
+      entries: &[wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::VERTEX,
+        ty: wgpu::BindingType::Buffer {
+          ty: wgpu::BufferBindingType::Uniform,
+          has_dynamic_offset: true,
+          min_binding_size: wgpu::BufferSize::new(16),
+        },
+        count: None,
+      }],
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Picking Bind Group"),
+      layout: &layout,
+      entries: &[wgpu::BindGroupEntry {
+        binding: 0,
+        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+          buffer: &buffer,
+          offset: 0,
+          size: wgpu::BufferSize::new(16),
+        }),
+      }],
+    });
+
+    Self {
+      targets: None,
+      pipeline: id_pipe(device, globals_layout, &layout),
+      bind_group,
+      stride,
+      readback: None,
+    }
+  }
+
+  // Makes sure the targets have the size of the frame, call before begin_pass()
+  pub fn prepare(&mut self, device: &Device, size: (u32, u32)) {
+    if self.targets.as_ref().is_some_and(|t| t.size == size) {
+      return;
+    }
+    let create = |label, format| {
+      device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+          width: size.0,
+          height: size.1,
+          depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+      })
+    };
+    let ids = Tracked::texture(create("Picking Id Texture", ID_FORMAT));
+    let depth = create("Picking Depth Texture", DEPTH_FORMAT);
+    self.targets = Some(Targets {
+      id_view: ids.create_view(&wgpu::TextureViewDescriptor::default()),
+      depth_view: Tracked::view(
+        &depth,
+        depth.create_view(&wgpu::TextureViewDescriptor::default()),
+      ),
+      ids,
+      size,
+    });
+  }
+
+  // The id pass with the pipeline set, only `pixel` gets drawn. Group 0 is left to the caller,
+  // bind() picks the id of every draw.
+  pub fn begin_pass<'a>(
+    &'a self,
+    encoder: &'a mut CommandEncoder,
+    pixel: (u32, u32),
+  ) -> RenderPass<'a> {
+    let targets = self
+      .targets
+      .as_ref()
+      .expect("Picker::prepare wasn't called");
+    let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+      label: Some("Picking Pass"),
+      color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+        view: &targets.id_view,
+        resolve_target: None,
+        ops: wgpu::Operations {
+          load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+          store: true,
+        },
+      })],
+      depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+        view: &targets.depth_view,
+        depth_ops: Some(wgpu::Operations {
+          load: wgpu::LoadOp::Clear(1.0),
+          store: false,
+        }),
+        stencil_ops: None,
+      }),
+    });
+    pass.set_scissor_rect(pixel.0, pixel.1, 1, 1);
+    pass.set_pipeline(&self.pipeline);
+    pass
+  }
+
+  // The draws that follow write `entity`'s id, plus their instance index
+  pub fn bind<'a>(&'a self, pass: &mut RenderPass<'a>, entity: Entity) {
+    pass.set_bind_group(1, &self.bind_group, &[entity.slot() * self.stride]);
+  }
+
+  // Copies `pixel` out after the pass, submit the encoder and then call map()
+  pub fn copy(&mut self, device: &Device, encoder: &mut CommandEncoder, pixel: (u32, u32)) {
+    let Some(targets) = &self.targets else {
+      return;
+    };
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Picking Readback Buffer"),
+      size: 4,
+      usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+      mapped_at_creation: false,
+    });
+    encoder.copy_texture_to_buffer(
+      wgpu::ImageCopyTexture {
+        texture: &targets.ids,
+        mip_level: 0,
+        origin: wgpu::Origin3d {
+          x: pixel.0,
+          y: pixel.1,
+          z: 0,
+        },
+        aspect: wgpu::TextureAspect::All,
+      },
+      wgpu::ImageCopyBuffer {
+        buffer: &buffer,
+        layout: wgpu::ImageDataLayout {
+          offset: 0,
+          // a single row, but it still has to be aligned
+          bytes_per_row: std::num::NonZeroU32::new(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT),
+          rows_per_image: None,
+        },
+      },
+      wgpu::Extent3d {
+        width: 1,
+        height: 1,
+        depth_or_array_layers: 1,
+      },
+    );
+    self.readback = Some(Readback {
+      buffer,
+      mapped: Arc::new(Mutex::new(None)),
+    });
+  }
+
+  pub fn is_in_flight(&self) -> bool {
+    self.readback.is_some()
+  }
+
+  // Call after queue.submit
+  pub fn map(&self) {
+    let Some(readback) = &self.readback else {
+      return;
+    };
+    let mapped = readback.mapped.clone();
+    readback
+      .buffer
+      .slice(..)
+      .map_async(wgpu::MapMode::Read, move |result| {
+        *mapped.lock().unwrap() = Some(result);
+      });
+  }
+
+  // What was under the pixel once the readback finished, None while it's still in flight
+  pub fn try_finish(&mut self) -> Option<Option<Entity>> {
+    let result = self.readback.as_ref()?.mapped.lock().unwrap().take()?;
+    let readback = self.readback.take()?;
+    if let Err(e) = result {
+      log::warn!("couldn't read the picked pixel back: {}", e);
+      return Some(None);
+    }
+    let id = {
+      let data = readback.buffer.slice(..).get_mapped_range();
+      u32::from_le_bytes([data[0], data[1], data[2], data[3]])
+    };
+    readback.buffer.unmap();
+    Some(Entity::from_id(id))
+  }
+}
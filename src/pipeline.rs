@@ -1,58 +1,1303 @@
-use wgpu::{Device, RenderPipeline, SurfaceConfiguration};
+use std::{collections::HashMap, sync::Arc};
 
-pub fn render_pipe(
+use serde::{Deserialize, Serialize};
+use wgpu::{Adapter, BindGroupLayout, Device, PipelineLayout, RenderPipeline, TextureFormat};
+
+use crate::{
+  clusters::cluster_defs,
+  debug_draw::DebugVertex,
+  deferred::GBUFFER_FORMATS,
+  hdr::{scene_depth_state, stencil_state, HDR_FORMAT},
+  hiz::prepass_depth_state,
+  math::Mat4,
+  mesh::MeshVertex,
+  picking::ID_FORMAT,
+  preprocessor::{preprocess, ShaderDefs},
+  scene::SceneInstance,
+  shader_variants::MaterialFeatures,
+  shadow::shadow_depth_state,
+  skinning::SkinVertex,
+  ssao::SSAO_FORMAT,
+  taa::VELOCITY_FORMAT,
+};
+
+// How a pipeline's color is combined with what's already in the target. Everything but
+// Opaque is transparent: drawn after the opaque meshes, back to front.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BlendMode {
+  #[default]
+  Opaque,
+  // color * alpha + target * (1 - alpha)
+  AlphaBlend,
+  // color * alpha + target, for glows and fire, the order doesn't matter
+  Additive,
+  // color + target * (1 - alpha), the color is already multiplied by its alpha
+  Premultiplied,
+}
+
+impl BlendMode {
+  pub const ALL: [BlendMode; 4] = [
+    BlendMode::Opaque,
+    BlendMode::AlphaBlend,
+    BlendMode::Additive,
+    BlendMode::Premultiplied,
+  ];
+
+  pub fn state(self) -> wgpu::BlendState {
+    match self {
+      BlendMode::Opaque => wgpu::BlendState::REPLACE,
+      BlendMode::AlphaBlend => wgpu::BlendState::ALPHA_BLENDING,
+      BlendMode::Additive => wgpu::BlendState {
+        color: wgpu::BlendComponent {
+          src_factor: wgpu::BlendFactor::SrcAlpha,
+          dst_factor: wgpu::BlendFactor::One,
+          operation: wgpu::BlendOperation::Add,
+        },
+        // leaves the target's alpha as it was
+        alpha: wgpu::BlendComponent {
+          src_factor: wgpu::BlendFactor::Zero,
+          dst_factor: wgpu::BlendFactor::One,
+          operation: wgpu::BlendOperation::Add,
+        },
+      },
+      BlendMode::Premultiplied => wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+    }
+  }
+
+  pub fn is_transparent(self) -> bool {
+    self != BlendMode::Opaque
+  }
+
+  // Where it is in ALL
+  pub fn index(self) -> usize {
+    self as usize
+  }
+}
+
+// Everything a pipeline of shader.wgsl is built from apart from the device and the shader
+// module, so pipelines can be checked without a GPU and PipelineCache can tell them apart
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RenderPipeConfig {
+  pub format: TextureFormat,
+  // Some for pipelines drawn into a pass with a depth attachment
+  pub depth: Option<wgpu::DepthStencilState>,
+  // shader fn names in shader.wgsl
+  pub vertex_entry: String,
+  pub fragment_entry: String,
+  pub blend: Option<wgpu::BlendState>,
+  pub primitive: wgpu::PrimitiveState,
+  pub multisample: wgpu::MultisampleState,
+}
+
+impl RenderPipeConfig {
+  // `shader_color` picks the vs_ and fs_ pair of shader.wgsl
+  pub fn new(
+    format: TextureFormat,
+    depth: Option<wgpu::DepthStencilState>,
+    shader_color: &str,
+  ) -> Self {
+    Self {
+      format,
+      depth,
+      vertex_entry: format!("vs_{}", shader_color),
+      fragment_entry: format!("fs_{}", shader_color),
+      blend: Some(wgpu::BlendState::REPLACE),
+      primitive: wgpu::PrimitiveState {
+        topology: wgpu::PrimitiveTopology::TriangleList, // every three vertices will correspond to one triangle
+        strip_index_format: None,
+        front_face: wgpu::FrontFace::Ccw, // given triangle is facing forward or not
+        cull_mode: Some(wgpu::Face::Back), // Triangles that are not considered facing forward are culled (not included in the render
+
+        // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
+        polygon_mode: wgpu::PolygonMode::Fill,
+        // Requires Features::DEPTH_CLIP_CONTROL
+        unclipped_depth: false,
+        // Requires Features::CONSERVATIVE_RASTERIZATION
+        conservative: false,
+      },
+      // Multisampling is ADVANCED topic
+      multisample: wgpu::MultisampleState {
+        count: 1,
+        mask: !0, // specifies which samples should be active. In this case, we are using all of them..
+        alpha_to_coverage_enabled: false, // anti-aliasing
+      },
+    }
+  }
+
+  pub fn build(&self, device: &Device) -> RenderPipeline {
+    self.build_from_source(device, include_str!("shader.wgsl"))
+  }
+
+  // Transparent modes still test depth but don't write it, so whatever is drawn behind
+  // them afterwards isn't hidden
+  pub fn with_blend(mut self, mode: BlendMode) -> Self {
+    self.blend = Some(mode.state());
+    if let (true, Some(depth)) = (mode.is_transparent(), &mut self.depth) {
+      depth.depth_write_enabled = false;
+    }
+    self
+  }
+
+  // Runs `face` on the depth attachment's stencil, see stencil_state(). Does nothing
+  // without a depth attachment.
+  pub fn with_stencil(mut self, face: wgpu::StencilFaceState) -> Self {
+    if let Some(depth) = &mut self.depth {
+      depth.stencil = stencil_state(face);
+    }
+    self
+  }
+
+  // For an MSAA target, what the attachments of the pass it's drawn in have
+  pub fn with_sample_count(mut self, count: u32) -> Self {
+    self.multisample.count = count;
+    self
+  }
+
+  // build() with a different shader.wgsl, e.g. one being edited while the app runs
+  pub fn build_from_source(&self, device: &Device, source: &str) -> RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+      label: Some("shader.wgsl"),
+      source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+    let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Render Pipeline Layout"),
+      bind_group_layouts: &[],
+      push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+      label: Some("Render Pipeline"),
+      layout: Some(&render_pipeline_layout),
+      vertex: wgpu::VertexState {
+        module: &shader,
+        entry_point: &self.vertex_entry,
+        buffers: &[], //  We're specifying the vertices in the vertex shader itself, so we'll leave this empty.
+      },
+      // fragment is optional
+      fragment: Some(wgpu::FragmentState {
+        module: &shader,
+        entry_point: &self.fragment_entry,
+        targets: &[Some(wgpu::ColorTargetState {
+          format: self.format,
+          blend: self.blend,
+          write_mask: wgpu::ColorWrites::ALL,
+        })],
+      }),
+      primitive: self.primitive,
+      depth_stencil: self.depth.clone(),
+      multisample: self.multisample,
+      multiview: None, // how many array layers the render attachments can have
+    })
+  }
+}
+
+// Pipelines built from a RenderPipeConfig and the shader.wgsl it was built from, handed out
+// again for the same pair. Switching a window's shader variant back and forth, the
+// comparison view and new windows then don't compile anything that was compiled before.
+#[derive(Default)]
+pub struct PipelineCache {
+  pipelines: HashMap<(RenderPipeConfig, Option<Arc<str>>), Arc<RenderPipeline>>,
+}
+
+impl PipelineCache {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  // `source` is a shader.wgsl other than the built in one, e.g. one being edited
+  pub fn get(
+    &mut self,
+    device: &Device,
+    config: &RenderPipeConfig,
+    source: Option<&Arc<str>>,
+  ) -> Arc<RenderPipeline> {
+    let key = (config.clone(), source.cloned());
+    self
+      .pipelines
+      .entry(key)
+      .or_insert_with(|| {
+        let pipeline = match source {
+          Some(source) => config.build_from_source(device, source),
+          None => config.build(device),
+        };
+        Arc::new(pipeline)
+      })
+      .clone()
+  }
+
+  // Drops the pipelines only the cache still holds, e.g. the ones of a shader.wgsl that's
+  // been edited since. Returns how many went.
+  pub fn evict_unused(&mut self) -> usize {
+    let count = self.pipelines.len();
+    self
+      .pipelines
+      .retain(|_, pipeline| Arc::strong_count(pipeline) > 1);
+    count - self.pipelines.len()
+  }
+
+  pub fn len(&self) -> usize {
+    self.pipelines.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.pipelines.is_empty()
+  }
+}
+
+// What every window's main pass draws with, into its hdr target and depth buffer, and what
+// main_pipe() builds. `shader_color` is one of the SHADER_VARIANTS or "rainbow",
+// `sample_count` the hdr target's.
+pub fn main_pipe_config(shader_color: &str, sample_count: u32) -> RenderPipeConfig {
+  RenderPipeConfig::new(HDR_FORMAT, Some(scene_depth_state()), shader_color)
+    .with_sample_count(sample_count)
+}
+
+// `source` is another version of shader.wgsl, the built in one when it's None
+pub fn main_pipe(
+  device: &Device,
+  pipelines: &mut PipelineCache,
+  shader_color: &str,
+  sample_count: u32,
+  source: Option<&Arc<str>>,
+) -> Arc<RenderPipeline> {
+  pipelines.get(
+    device,
+    &main_pipe_config(shader_color, sample_count),
+    source,
+  )
+}
+
+// Alpha to coverage only does something with more than one sample, so it's switched off
+// at sample count 1 and shaders are expected to alpha test instead
+pub fn multisample_state(sample_count: u32, alpha_to_coverage: bool) -> wgpu::MultisampleState {
+  wgpu::MultisampleState {
+    count: sample_count,
+    mask: !0,
+    alpha_to_coverage_enabled: alpha_to_coverage && sample_count > 1,
+  }
+}
+
+// Scene shaders include common.wgsl or lighting.wgsl, see preprocessor.rs. The cluster
+// counts lighting.wgsl needs are defined for them.
+fn scene_shader(device: &Device, label: &str, source: &str) -> wgpu::ShaderModule {
+  let source = match preprocess(source, &cluster_defs(ShaderDefs::new())) {
+    Ok(source) => source,
+    Err(e) => panic!("{} doesn't preprocess: {}", label, e),
+  };
+  device.create_shader_module(wgpu::ShaderModuleDescriptor {
+    label: Some(label),
+    source: wgpu::ShaderSource::Wgsl(source.into()),
+  })
+}
+
+// pbr.wgsl as pbr_pipe() compiles it, for checking layouts against it. `bindless` is how
+// many materials its texture arrays hold, see pbr::bindless_capacity(). Cutouts are alpha
+// tested unless `alpha_to_coverage`, see multisample_state().
+pub fn pbr_source(
+  draw_data: DrawData,
+  bindless: Option<u32>,
+  features: MaterialFeatures,
+  alpha_to_coverage: bool,
+) -> String {
+  let mut defs = cluster_defs(features.defs(draw_data.defs()));
+  if features.alpha_cutout && alpha_to_coverage {
+    defs = defs.flag("ALPHA_TO_COVERAGE");
+  }
+  if let Some(capacity) = bindless {
+    defs = defs.value("BINDLESS", capacity);
+  }
+  preprocess(include_str!("pbr.wgsl"), &defs).expect("pbr.wgsl doesn't preprocess")
+}
+
+// Lit scene meshes, drawn into the hdr target in the main pass, `sample_count` is its. Group 0
+// holds the scene globals and group 1 the shadow map. `polygon_mode` other than Fill needs
+// Features::POLYGON_MODE_LINE.
+pub fn scene_pipe(
+  device: &Device,
+  format: TextureFormat,
+  sample_count: u32,
+  globals_layout: &BindGroupLayout,
+  shadow_layout: &BindGroupLayout,
+  polygon_mode: wgpu::PolygonMode,
+) -> RenderPipeline {
+  let shader = scene_shader(device, "Scene Shader", include_str!("scene.wgsl"));
+  let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+    label: Some("Scene Pipeline Layout"),
+    bind_group_layouts: &[globals_layout, shadow_layout],
+    push_constant_ranges: &[],
+  });
+  device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+    label: Some("Scene Pipeline"),
+    layout: Some(&layout),
+    vertex: wgpu::VertexState {
+      module: &shader,
+      entry_point: "vs_main",
+      buffers: &[MeshVertex::layout(), SceneInstance::layout()],
+    },
+    fragment: Some(wgpu::FragmentState {
+      module: &shader,
+      entry_point: "fs_main",
+      targets: &[Some(wgpu::ColorTargetState {
+        format,
+        blend: Some(wgpu::BlendState::REPLACE),
+        write_mask: wgpu::ColorWrites::ALL,
+      })],
+    }),
+    primitive: wgpu::PrimitiveState {
+      cull_mode: Some(wgpu::Face::Back),
+      polygon_mode,
+      ..Default::default()
+    },
+    depth_stencil: Some(scene_depth_state()),
+    multisample: multisample_state(sample_count, false),
+    multiview: None,
+  })
+}
+
+// Skinned meshes shaded like scene_pipe's, with the joint matrices in group 2. The skin's
+// joints and weights come in a third vertex buffer.
+pub fn skinned_pipe(
+  device: &Device,
+  format: TextureFormat,
+  sample_count: u32,
+  globals_layout: &BindGroupLayout,
+  shadow_layout: &BindGroupLayout,
+  joints_layout: &BindGroupLayout,
+) -> RenderPipeline {
+  let shader = scene_shader(device, "Skinned Shader", include_str!("skinning.wgsl"));
+  let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+    label: Some("Skinned Pipeline Layout"),
+    bind_group_layouts: &[globals_layout, shadow_layout, joints_layout],
+    push_constant_ranges: &[],
+  });
+  device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+    label: Some("Skinned Pipeline"),
+    layout: Some(&layout),
+    vertex: wgpu::VertexState {
+      module: &shader,
+      entry_point: "vs_main",
+      buffers: &[
+        MeshVertex::layout(),
+        SceneInstance::layout(),
+        SkinVertex::layout(),
+      ],
+    },
+    fragment: Some(wgpu::FragmentState {
+      module: &shader,
+      entry_point: "fs_main",
+      targets: &[Some(wgpu::ColorTargetState {
+        format,
+        blend: Some(wgpu::BlendState::REPLACE),
+        write_mask: wgpu::ColorWrites::ALL,
+      })],
+    }),
+    primitive: wgpu::PrimitiveState {
+      cull_mode: Some(wgpu::Face::Back),
+      ..Default::default()
+    },
+    depth_stencil: Some(scene_depth_state()),
+    multisample: multisample_state(sample_count, false),
+    multiview: None,
+  })
+}
+
+// The scene meshes again for the deferred path, writing surface attributes into the
+// G-buffer's render targets (one per entry of GBUFFER_FORMATS) instead of a lit color
+pub fn gbuffer_pipe(device: &Device, globals_layout: &BindGroupLayout) -> RenderPipeline {
+  let shader = scene_shader(device, "GBuffer Shader", include_str!("gbuffer.wgsl"));
+  let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+    label: Some("GBuffer Pipeline Layout"),
+    bind_group_layouts: &[globals_layout],
+    push_constant_ranges: &[],
+  });
+  // multiple render targets, @location(n) in the fragment output goes to target n
+  let targets = GBUFFER_FORMATS.map(|format| {
+    Some(wgpu::ColorTargetState {
+      format,
+      blend: None,
+      write_mask: wgpu::ColorWrites::ALL,
+    })
+  });
+  device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+    label: Some("GBuffer Pipeline"),
+    layout: Some(&layout),
+    vertex: wgpu::VertexState {
+      module: &shader,
+      entry_point: "vs_main",
+      buffers: &[MeshVertex::layout(), SceneInstance::layout()],
+    },
+    fragment: Some(wgpu::FragmentState {
+      module: &shader,
+      entry_point: "fs_main",
+      targets: &targets,
+    }),
+    primitive: wgpu::PrimitiveState {
+      cull_mode: Some(wgpu::Face::Back),
+      ..Default::default()
+    },
+    depth_stencil: Some(scene_depth_state()),
+    multisample: wgpu::MultisampleState::default(),
+    multiview: None,
+  })
+}
+
+// Fullscreen lighting pass of the deferred path. Groups 0 and 1 are the same as
+// scene_pipe's, 2 is the G-buffer.
+pub fn deferred_lighting_pipe(
   device: &Device,
-  config: &SurfaceConfiguration,
-  shader_color: String,
+  format: TextureFormat,
+  bind_group_layouts: &[&BindGroupLayout; 3],
 ) -> RenderPipeline {
-  let shader = device.create_shader_module(wgpu::include_wgsl!("shader.wgsl"));
-  let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-    label: Some("Render Pipeline Layout"),
-    bind_group_layouts: &[],
+  let shader = scene_shader(device, "Deferred Shader", include_str!("deferred.wgsl"));
+  let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+    label: Some("Deferred Lighting Pipeline Layout"),
+    bind_group_layouts,
     push_constant_ranges: &[],
   });
+  device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+    label: Some("Deferred Lighting Pipeline"),
+    layout: Some(&layout),
+    vertex: wgpu::VertexState {
+      module: &shader,
+      entry_point: "vs_main",
+      buffers: &[],
+    },
+    fragment: Some(wgpu::FragmentState {
+      module: &shader,
+      entry_point: "fs_main",
+      targets: &[Some(wgpu::ColorTargetState {
+        format,
+        blend: Some(wgpu::BlendState::REPLACE),
+        write_mask: wgpu::ColorWrites::ALL,
+      })],
+    }),
+    primitive: wgpu::PrimitiveState::default(),
+    // reads the scene depth as a texture, so it can't be attached too
+    depth_stencil: None,
+    multisample: wgpu::MultisampleState::default(),
+    multiview: None,
+  })
+}
 
-  let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-    label: Some("Render Pipeline"),
-    layout: Some(&render_pipeline_layout),
+// The fog over the finished scene, see fog.wgsl. Groups 0 and 1 are the same as scene_pipe's,
+// 2 is the fog's own. Its alpha is what's left of the scene color, the blend multiplies by it.
+pub fn fog_pipe(
+  device: &Device,
+  format: TextureFormat,
+  bind_group_layouts: &[&BindGroupLayout; 3],
+) -> RenderPipeline {
+  let shader = scene_shader(device, "Fog Shader", include_str!("fog.wgsl"));
+  let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+    label: Some("Fog Pipeline Layout"),
+    bind_group_layouts,
+    push_constant_ranges: &[],
+  });
+  device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+    label: Some("Fog Pipeline"),
+    layout: Some(&layout),
     vertex: wgpu::VertexState {
       module: &shader,
-      entry_point: &format!("vs_{}", shader_color), //  shader fn name
-      buffers: &[], //  We're specifying the vertices in the vertex shader itself, so we'll leave this empty.
+      entry_point: "vs_main",
+      buffers: &[],
     },
-    // fragment is optional
     fragment: Some(wgpu::FragmentState {
       module: &shader,
-      entry_point: &format!("fs_{}", shader_color), //in the shader file
+      entry_point: "fs_main",
       targets: &[Some(wgpu::ColorTargetState {
-        format: config.format,
+        format,
+        blend: Some(wgpu::BlendState {
+          color: wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::SrcAlpha,
+            operation: wgpu::BlendOperation::Add,
+          },
+          alpha: wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::Zero,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+          },
+        }),
+        write_mask: wgpu::ColorWrites::ALL,
+      })],
+    }),
+    primitive: wgpu::PrimitiveState::default(),
+    // reads the scene depth as a texture, so it can't be attached too
+    depth_stencil: None,
+    multisample: wgpu::MultisampleState::default(),
+    multiview: None,
+  })
+}
+
+// Motion vectors of the scene meshes for TAA, over the depth the scene passes left behind.
+// Group 0 is the same as scene_pipe's.
+pub fn velocity_pipe(device: &Device, globals_layout: &BindGroupLayout) -> RenderPipeline {
+  let shader = scene_shader(device, "Velocity Shader", include_str!("velocity.wgsl"));
+  let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+    label: Some("Velocity Pipeline Layout"),
+    bind_group_layouts: &[globals_layout],
+    push_constant_ranges: &[],
+  });
+  device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+    label: Some("Velocity Pipeline"),
+    layout: Some(&layout),
+    vertex: wgpu::VertexState {
+      module: &shader,
+      entry_point: "vs_main",
+      buffers: &[MeshVertex::layout(), SceneInstance::layout()],
+    },
+    fragment: Some(wgpu::FragmentState {
+      module: &shader,
+      entry_point: "fs_main",
+      targets: &[Some(wgpu::ColorTargetState {
+        format: VELOCITY_FORMAT,
         blend: Some(wgpu::BlendState::REPLACE),
         write_mask: wgpu::ColorWrites::ALL,
       })],
     }),
+    primitive: wgpu::PrimitiveState {
+      cull_mode: Some(wgpu::Face::Back),
+      ..Default::default()
+    },
+    // only the surfaces that ended up in front, the depth is already final
+    depth_stencil: Some(wgpu::DepthStencilState {
+      depth_write_enabled: false,
+      ..scene_depth_state()
+    }),
+    multisample: wgpu::MultisampleState::default(),
+    multiview: None,
+  })
+}
+
+// Outlines of the simulation's tick positions, see ghost.wgsl. Only the back faces, tested
+// against the scene's depth without writing it.
+pub fn ghost_pipe(
+  device: &Device,
+  format: TextureFormat,
+  sample_count: u32,
+  globals_layout: &BindGroupLayout,
+) -> RenderPipeline {
+  let shader = scene_shader(device, "Ghost Shader", include_str!("ghost.wgsl"));
+  let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+    label: Some("Ghost Pipeline Layout"),
+    bind_group_layouts: &[globals_layout],
+    push_constant_ranges: &[],
+  });
+  device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+    label: Some("Ghost Pipeline"),
+    layout: Some(&layout),
+    vertex: wgpu::VertexState {
+      module: &shader,
+      entry_point: "vs_main",
+      buffers: &[MeshVertex::layout(), SceneInstance::layout()],
+    },
+    fragment: Some(wgpu::FragmentState {
+      module: &shader,
+      entry_point: "fs_main",
+      targets: &[Some(wgpu::ColorTargetState {
+        format,
+        blend: Some(wgpu::BlendState::REPLACE),
+        write_mask: wgpu::ColorWrites::ALL,
+      })],
+    }),
+    primitive: wgpu::PrimitiveState {
+      cull_mode: Some(wgpu::Face::Front),
+      ..Default::default()
+    },
+    depth_stencil: Some(wgpu::DepthStencilState {
+      depth_write_enabled: false,
+      ..scene_depth_state()
+    }),
+    multisample: multisample_state(sample_count, false),
+    multiview: None,
+  })
+}
+
+// The selection outline of outline.wgsl, group 0 is the same as scene_pipe's. The first
+// pipeline marks the model's pixels in the stencil with the pass's reference value, the
+// second draws the outline around them but not over them.
+pub fn outline_pipes(
+  device: &Device,
+  format: TextureFormat,
+  sample_count: u32,
+  globals_layout: &BindGroupLayout,
+) -> [RenderPipeline; 2] {
+  let shader = scene_shader(device, "Outline Shader", include_str!("outline.wgsl"));
+  let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+    label: Some("Outline Pipeline Layout"),
+    bind_group_layouts: &[globals_layout],
+    push_constant_ranges: &[],
+  });
+  let pipe = |label, vertex_entry, write_mask, depth_compare, stencil| {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+      label: Some(label),
+      layout: Some(&layout),
+      vertex: wgpu::VertexState {
+        module: &shader,
+        entry_point: vertex_entry,
+        buffers: &[MeshVertex::layout(), SceneInstance::layout()],
+      },
+      fragment: Some(wgpu::FragmentState {
+        module: &shader,
+        entry_point: "fs_main",
+        targets: &[Some(wgpu::ColorTargetState {
+          format,
+          blend: Some(wgpu::BlendState::REPLACE),
+          write_mask,
+        })],
+      }),
+      primitive: wgpu::PrimitiveState {
+        cull_mode: Some(wgpu::Face::Back),
+        ..Default::default()
+      },
+      depth_stencil: Some(wgpu::DepthStencilState {
+        depth_write_enabled: false,
+        depth_compare,
+        stencil: stencil_state(stencil),
+        ..scene_depth_state()
+      }),
+      multisample: multisample_state(sample_count, false),
+      multiview: None,
+    })
+  };
+  // all of the model, also where it's hidden, so the outline doesn't cross it there
+  let mask = pipe(
+    "Outline Mask Pipeline",
+    "vs_mask",
+    wgpu::ColorWrites::empty(),
+    wgpu::CompareFunction::Always,
+    wgpu::StencilFaceState {
+      compare: wgpu::CompareFunction::Always,
+      fail_op: wgpu::StencilOperation::Keep,
+      depth_fail_op: wgpu::StencilOperation::Keep,
+      pass_op: wgpu::StencilOperation::Replace,
+    },
+  );
+  let outline = pipe(
+    "Outline Pipeline",
+    "vs_outline",
+    wgpu::ColorWrites::ALL,
+    scene_depth_state().depth_compare,
+    wgpu::StencilFaceState {
+      compare: wgpu::CompareFunction::NotEqual,
+      fail_op: wgpu::StencilOperation::Keep,
+      depth_fail_op: wgpu::StencilOperation::Keep,
+      pass_op: wgpu::StencilOperation::Keep,
+    },
+  );
+  [mask, outline]
+}
+
+// The endless ground grid, see grid.wgsl. A fullscreen triangle writing the depth of where it
+// hits the ground, group 0 is the same as scene_pipe's.
+pub fn grid_pipe(
+  device: &Device,
+  format: TextureFormat,
+  sample_count: u32,
+  globals_layout: &BindGroupLayout,
+) -> RenderPipeline {
+  let shader = scene_shader(device, "Grid Shader", include_str!("grid.wgsl"));
+  let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+    label: Some("Grid Pipeline Layout"),
+    bind_group_layouts: &[globals_layout],
+    push_constant_ranges: &[],
+  });
+  device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+    label: Some("Grid Pipeline"),
+    layout: Some(&layout),
+    vertex: wgpu::VertexState {
+      module: &shader,
+      entry_point: "vs_main",
+      buffers: &[],
+    },
+    fragment: Some(wgpu::FragmentState {
+      module: &shader,
+      entry_point: "fs_main",
+      targets: &[Some(wgpu::ColorTargetState {
+        format,
+        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+        write_mask: wgpu::ColorWrites::ALL,
+      })],
+    }),
+    primitive: wgpu::PrimitiveState::default(),
+    depth_stencil: Some(scene_depth_state()),
+    multisample: multisample_state(sample_count, false),
+    multiview: None,
+  })
+}
+
+// The water plane, see water.wgsl. A quad made up in the vertex shader, seen from both
+// sides. `layouts` are the globals, the water's own group and PbrPipeline's environment.
+pub fn water_pipe(
+  device: &Device,
+  format: TextureFormat,
+  layouts: &[&BindGroupLayout],
+) -> RenderPipeline {
+  let shader = scene_shader(device, "Water Shader", include_str!("water.wgsl"));
+  let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+    label: Some("Water Pipeline Layout"),
+    bind_group_layouts: layouts,
+    push_constant_ranges: &[],
+  });
+  device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+    label: Some("Water Pipeline"),
+    layout: Some(&layout),
+    vertex: wgpu::VertexState {
+      module: &shader,
+      entry_point: "vs_main",
+      buffers: &[],
+    },
+    fragment: Some(wgpu::FragmentState {
+      module: &shader,
+      entry_point: "fs_main",
+      targets: &[Some(wgpu::ColorTargetState {
+        format,
+        blend: None,
+        write_mask: wgpu::ColorWrites::ALL,
+      })],
+    }),
+    primitive: wgpu::PrimitiveState::default(),
+    depth_stencil: Some(scene_depth_state()),
+    multisample: wgpu::MultisampleState::default(),
+    multiview: None,
+  })
+}
 
+// DebugDraw's lines, see debug_line.wgsl. Tested against the scene's depth without writing
+// it, group 0 is the same as scene_pipe's.
+pub fn debug_line_pipe(
+  device: &Device,
+  format: TextureFormat,
+  globals_layout: &BindGroupLayout,
+) -> RenderPipeline {
+  let shader = scene_shader(device, "Debug Line Shader", include_str!("debug_line.wgsl"));
+  let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+    label: Some("Debug Line Pipeline Layout"),
+    bind_group_layouts: &[globals_layout],
+    push_constant_ranges: &[],
+  });
+  device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+    label: Some("Debug Line Pipeline"),
+    layout: Some(&layout),
+    vertex: wgpu::VertexState {
+      module: &shader,
+      entry_point: "vs_main",
+      buffers: &[DebugVertex::layout()],
+    },
+    fragment: Some(wgpu::FragmentState {
+      module: &shader,
+      entry_point: "fs_main",
+      targets: &[Some(wgpu::ColorTargetState {
+        format,
+        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+        write_mask: wgpu::ColorWrites::ALL,
+      })],
+    }),
     primitive: wgpu::PrimitiveState {
-      topology: wgpu::PrimitiveTopology::TriangleList, // every three vertices will correspond to one triangle
-      strip_index_format: None,
-      front_face: wgpu::FrontFace::Ccw, // given triangle is facing forward or not
-      cull_mode: Some(wgpu::Face::Back), // Triangles that are not considered facing forward are culled (not included in the render
+      topology: wgpu::PrimitiveTopology::LineList,
+      ..Default::default()
+    },
+    depth_stencil: Some(wgpu::DepthStencilState {
+      depth_write_enabled: false,
+      ..scene_depth_state()
+    }),
+    multisample: wgpu::MultisampleState::default(),
+    multiview: None,
+  })
+}
 
-      // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-      polygon_mode: wgpu::PolygonMode::Fill,
-      // Requires Features::DEPTH_CLIP_CONTROL
-      unclipped_depth: false,
-      // Requires Features::CONSERVATIVE_RASTERIZATION
-      conservative: false,
+// Entity ids of the scene meshes for picking, group 1 is the id of the draw
+pub fn id_pipe(
+  device: &Device,
+  globals_layout: &BindGroupLayout,
+  id_layout: &BindGroupLayout,
+) -> RenderPipeline {
+  let shader = scene_shader(device, "Id Shader", include_str!("id.wgsl"));
+  let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+    label: Some("Id Pipeline Layout"),
+    bind_group_layouts: &[globals_layout, id_layout],
+    push_constant_ranges: &[],
+  });
+  device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+    label: Some("Id Pipeline"),
+    layout: Some(&layout),
+    vertex: wgpu::VertexState {
+      module: &shader,
+      entry_point: "vs_main",
+      buffers: &[MeshVertex::layout(), SceneInstance::layout()],
     },
+    fragment: Some(wgpu::FragmentState {
+      module: &shader,
+      entry_point: "fs_main",
+      targets: &[Some(wgpu::ColorTargetState {
+        format: ID_FORMAT,
+        blend: None,
+        write_mask: wgpu::ColorWrites::ALL,
+      })],
+    }),
+    primitive: wgpu::PrimitiveState {
+      cull_mode: Some(wgpu::Face::Back),
+      ..Default::default()
+    },
+    depth_stencil: Some(scene_depth_state()),
+    multisample: wgpu::MultisampleState::default(),
+    multiview: None,
+  })
+}
+
+// Ambient occlusion of the deferred path from the G-buffer normals and the depth. Groups 0
+// and 1 are the same as scene_pipe's, 2 is the ssao inputs.
+pub fn ssao_pipe(device: &Device, bind_group_layouts: &[&BindGroupLayout; 3]) -> RenderPipeline {
+  let shader = scene_shader(device, "Ssao Shader", include_str!("ssao.wgsl"));
+  fullscreen_pipe(device, "Ssao", &shader, bind_group_layouts, SSAO_FORMAT)
+}
+
+pub fn ssao_blur_pipe(device: &Device, occlusion_layout: &BindGroupLayout) -> RenderPipeline {
+  let shader = device.create_shader_module(wgpu::include_wgsl!("ssao_blur.wgsl"));
+  fullscreen_pipe(
+    device,
+    "Ssao Blur",
+    &shader,
+    &[occlusion_layout],
+    SSAO_FORMAT,
+  )
+}
 
+// Plain white over the whole target, a scissor rect cuts it down to the latency probe's quad
+pub fn flash_pipe(device: &Device, format: TextureFormat) -> RenderPipeline {
+  let shader = device.create_shader_module(wgpu::include_wgsl!("flash.wgsl"));
+  fullscreen_pipe(device, "Flash", &shader, &[], format)
+}
+
+// One triangle over the whole target and no depth, vs_main and fs_main of `shader`
+fn fullscreen_pipe(
+  device: &Device,
+  label: &str,
+  shader: &wgpu::ShaderModule,
+  bind_group_layouts: &[&BindGroupLayout],
+  format: TextureFormat,
+) -> RenderPipeline {
+  let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+    label: Some(&format!("{} Pipeline Layout", label)),
+    bind_group_layouts,
+    push_constant_ranges: &[],
+  });
+  device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+    label: Some(&format!("{} Pipeline", label)),
+    layout: Some(&layout),
+    vertex: wgpu::VertexState {
+      module: shader,
+      entry_point: "vs_main",
+      buffers: &[],
+    },
+    fragment: Some(wgpu::FragmentState {
+      module: shader,
+      entry_point: "fs_main",
+      targets: &[Some(wgpu::ColorTargetState {
+        format,
+        blend: Some(wgpu::BlendState::REPLACE),
+        write_mask: wgpu::ColorWrites::ALL,
+      })],
+    }),
+    primitive: wgpu::PrimitiveState::default(),
     depth_stencil: None,
-    // Multisampling is ADVANCED topic
-    multisample: wgpu::MultisampleState {
-      count: 1,
-      mask: !0, // specifies which samples should be active. In this case, we are using all of them..
-      alpha_to_coverage_enabled: false, // anti-aliasing
+    multisample: wgpu::MultisampleState::default(),
+    multiview: None,
+  })
+}
+
+// The same meshes seen from the light, writing depth only into the shadow map
+pub fn shadow_pipe(device: &Device, globals_layout: &BindGroupLayout) -> RenderPipeline {
+  let shader = scene_shader(device, "Scene Shader", include_str!("scene.wgsl"));
+  let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+    label: Some("Shadow Pipeline Layout"),
+    bind_group_layouts: &[globals_layout],
+    push_constant_ranges: &[],
+  });
+  device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+    label: Some("Shadow Pipeline"),
+    layout: Some(&layout),
+    vertex: wgpu::VertexState {
+      module: &shader,
+      entry_point: "vs_shadow",
+      buffers: &[MeshVertex::layout(), SceneInstance::layout()],
     },
-    multiview: None, // how many array layers the render attachments can have
+    // no color target, the rasterizer only writes depth
+    fragment: None,
+    primitive: wgpu::PrimitiveState {
+      cull_mode: Some(wgpu::Face::Back),
+      ..Default::default()
+    },
+    depth_stencil: Some(shadow_depth_state()),
+    multisample: wgpu::MultisampleState::default(),
+    multiview: None,
+  })
+}
+
+// The same meshes seen from the camera, writing depth only for the Hi-Z pyramid
+pub fn prepass_pipe(device: &Device, globals_layout: &BindGroupLayout) -> RenderPipeline {
+  let shader = scene_shader(device, "Scene Shader", include_str!("scene.wgsl"));
+  let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+    label: Some("Depth Prepass Pipeline Layout"),
+    bind_group_layouts: &[globals_layout],
+    push_constant_ranges: &[],
   });
-  render_pipeline
+  device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+    label: Some("Depth Prepass Pipeline"),
+    layout: Some(&layout),
+    vertex: wgpu::VertexState {
+      module: &shader,
+      entry_point: "vs_prepass",
+      buffers: &[MeshVertex::layout(), SceneInstance::layout()],
+    },
+    fragment: None,
+    primitive: wgpu::PrimitiveState {
+      cull_mode: Some(wgpu::Face::Back),
+      ..Default::default()
+    },
+    depth_stencil: Some(prepass_depth_state()),
+    multisample: wgpu::MultisampleState::default(),
+    multiview: None,
+  })
+}
+
+// What a single draw needs of its object, matches `DrawConstants` in pbr.wgsl. Small
+// enough for the 128 bytes of push constants every device supporting them has.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DrawConstants {
+  pub model: Mat4,
+  pub tint: [f32; 4],
+  // the material's slot in the bindless arrays, see MaterialBinding::index(). The shader
+  // reads it and the padding as a vec4<i32>, the same for any slot there is.
+  pub material: u32,
+  _padding: [u32; 3],
+}
+
+impl DrawConstants {
+  pub fn new(model: Mat4, tint: [f32; 4], material: u32) -> Self {
+    Self {
+      model,
+      tint,
+      material,
+      _padding: [0; 3],
+    }
+  }
+}
+
+pub const DRAW_CONSTANTS_SIZE: u32 = std::mem::size_of::<DrawConstants>() as u32;
+// the least a device with push constants allows
+const _: () = assert!(DRAW_CONSTANTS_SIZE <= 128);
+
+// The push constant range of pipelines taking DrawConstants that way
+pub const DRAW_PUSH_CONSTANTS: wgpu::PushConstantRange = wgpu::PushConstantRange {
+  stages: wgpu::ShaderStages::VERTEX,
+  range: 0..DRAW_CONSTANTS_SIZE,
+};
+
+// How a pipeline taking DrawConstants gets those of each draw
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrawData {
+  // pushed right before the draw
+  PushConstants,
+  // a uniform buffer per object bound with the rest of its bindings, where there are no
+  // push constants (WebGPU)
+  Uniform,
+  // every object's in one storage buffer, each draw's first instance is the index of its
+  // own. Nothing to set between draws, so the draws could come from the GPU.
+  Storage,
+}
+
+impl DrawData {
+  // Storage when it's asked for and vertex shaders can read storage buffers, otherwise
+  // push constants where there are any. GL's instance_index doesn't count from the draw's
+  // first instance, so storage is out there too.
+  pub fn pick(adapter: &Adapter, device: &Device, storage: bool) -> Self {
+    let vertex_storage = adapter
+      .get_downlevel_capabilities()
+      .flags
+      .contains(wgpu::DownlevelFlags::VERTEX_STORAGE)
+      && device.limits().max_storage_buffers_per_shader_stage > 0
+      && adapter.get_info().backend != wgpu::Backend::Gl;
+    let push_constants = device.features().contains(wgpu::Features::PUSH_CONSTANTS)
+      && device.limits().max_push_constant_size >= DRAW_CONSTANTS_SIZE;
+    if storage && vertex_storage {
+      DrawData::Storage
+    } else if push_constants {
+      DrawData::PushConstants
+    } else {
+      DrawData::Uniform
+    }
+  }
+
+  // What the shader is preprocessed with, see pbr.wgsl
+  pub fn defs(self) -> ShaderDefs {
+    match self {
+      DrawData::PushConstants => ShaderDefs::new().flag("PUSH_CONSTANTS"),
+      DrawData::Uniform => ShaderDefs::new(),
+      DrawData::Storage => ShaderDefs::new().flag("STORAGE_DRAWS"),
+    }
+  }
+}
+
+// The layout every pbr_pipe() variant shares. Groups 0 and 1 are the same as
+// scene_pipe's, 2 is the material and 3 the environment. Unless `draw_data` pushes them
+// the DrawConstants are binding 6 of the material.
+pub fn pbr_layout(
+  device: &Device,
+  bind_group_layouts: &[&BindGroupLayout; 4],
+  draw_data: DrawData,
+) -> PipelineLayout {
+  let push_constant_ranges: &[wgpu::PushConstantRange] = match draw_data {
+    DrawData::PushConstants => &[DRAW_PUSH_CONSTANTS],
+    DrawData::Uniform | DrawData::Storage => &[],
+  };
+  device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+    label: Some("Pbr Pipeline Layout"),
+    bind_group_layouts,
+    push_constant_ranges,
+  })
+}
+
+// glTF style metallic-roughness meshes, the default for loaded models. `source` is one of
+// pbr_source()'s variants, see shader_variants.rs. A transparent `blend` doesn't write
+// depth.
+pub fn pbr_pipe(
+  device: &Device,
+  format: TextureFormat,
+  layout: &PipelineLayout,
+  source: &str,
+  multisample: wgpu::MultisampleState,
+  blend: BlendMode,
+) -> RenderPipeline {
+  let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+    label: Some("Pbr Shader"),
+    source: wgpu::ShaderSource::Wgsl(source.into()),
+  });
+  device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+    label: Some("Pbr Pipeline"),
+    layout: Some(layout),
+    vertex: wgpu::VertexState {
+      module: &shader,
+      entry_point: "vs_main",
+      buffers: &[MeshVertex::layout()],
+    },
+    fragment: Some(wgpu::FragmentState {
+      module: &shader,
+      entry_point: "fs_main",
+      targets: &[Some(wgpu::ColorTargetState {
+        format,
+        blend: Some(blend.state()),
+        write_mask: wgpu::ColorWrites::ALL,
+      })],
+    }),
+    primitive: wgpu::PrimitiveState {
+      cull_mode: Some(wgpu::Face::Back),
+      ..Default::default()
+    },
+    depth_stencil: Some(wgpu::DepthStencilState {
+      depth_write_enabled: !blend.is_transparent(),
+      ..scene_depth_state()
+    }),
+    multisample,
+    multiview: None,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{hdr::DEPTH_FORMAT, reflection::ShaderReflection, viewport::SHADER_VARIANTS};
+
+  // What the main pipeline was before it moved out of the window setup, a change here
+  // should be on purpose
+  #[test]
+  fn main_pipe_config_is_unchanged() {
+    let config = main_pipe_config("main", 1);
+    assert_eq!(config.format, HDR_FORMAT);
+    assert_eq!(config.vertex_entry, "vs_main");
+    assert_eq!(config.fragment_entry, "fs_main");
+    assert_eq!(config.blend, Some(wgpu::BlendState::REPLACE));
+    let depth = config.depth.expect("the main pass has a depth attachment");
+    assert_eq!(depth.format, DEPTH_FORMAT);
+    assert!(depth.depth_write_enabled);
+    assert_eq!(depth.depth_compare, wgpu::CompareFunction::LessEqual);
+    assert_eq!(
+      config.primitive,
+      wgpu::PrimitiveState {
+        topology: wgpu::PrimitiveTopology::TriangleList,
+        strip_index_format: None,
+        front_face: wgpu::FrontFace::Ccw,
+        cull_mode: Some(wgpu::Face::Back),
+        polygon_mode: wgpu::PolygonMode::Fill,
+        unclipped_depth: false,
+        conservative: false,
+      }
+    );
+    assert_eq!(config.multisample, wgpu::MultisampleState::default());
+  }
+
+  // An MSAA target gets a pipeline of its own out of PipelineCache
+  #[test]
+  fn sample_count_is_in_the_key() {
+    let single = main_pipe_config("main", 1);
+    let msaa = main_pipe_config("main", 4);
+    assert_eq!(msaa.multisample.count, 4);
+    assert_ne!(msaa, single);
+    assert_eq!(
+      RenderPipeConfig {
+        multisample: single.multisample,
+        ..msaa
+      },
+      single
+    );
+  }
+
+  // Transparent modes keep the depth test but stop writing depth, Opaque changes nothing
+  #[test]
+  fn blend_modes() {
+    let config = || RenderPipeConfig::new(HDR_FORMAT, Some(scene_depth_state()), "main");
+    assert_eq!(config().with_blend(BlendMode::Opaque), config());
+    for mode in BlendMode::ALL {
+      assert_eq!(BlendMode::ALL[mode.index()], mode);
+      let blended = config().with_blend(mode);
+      assert_eq!(blended.blend, Some(mode.state()));
+      let depth = blended.depth.unwrap();
+      assert_eq!(
+        depth.depth_write_enabled,
+        !mode.is_transparent(),
+        "{:?}",
+        mode
+      );
+      assert_eq!(depth.depth_compare, scene_depth_state().depth_compare);
+    }
+  }
+
+  // The stencil goes on the depth attachment and leaves the depth test alone
+  #[test]
+  fn stencil() {
+    let face = wgpu::StencilFaceState {
+      compare: wgpu::CompareFunction::Equal,
+      fail_op: wgpu::StencilOperation::Keep,
+      depth_fail_op: wgpu::StencilOperation::Keep,
+      pass_op: wgpu::StencilOperation::IncrementClamp,
+    };
+    let config = RenderPipeConfig::new(HDR_FORMAT, Some(scene_depth_state()), "main");
+    let depth = config.clone().with_stencil(face).depth.unwrap();
+    assert_eq!(depth.stencil.front, face);
+    assert_eq!(depth.stencil.back, face);
+    assert!(!depth.stencil.is_read_only());
+    assert_eq!(
+      wgpu::DepthStencilState {
+        stencil: wgpu::StencilState::default(),
+        ..depth
+      },
+      config.depth.unwrap()
+    );
+    let no_depth = RenderPipeConfig::new(HDR_FORMAT, None, "main");
+    assert_eq!(no_depth.clone().with_stencil(face), no_depth);
+  }
+
+  // Every way of getting the DrawConstants comes out of pbr.wgsl with only its own binding
+  #[test]
+  fn pbr_draw_constants() {
+    let declarations = [
+      (DrawData::PushConstants, "var<push_constant> draw"),
+      (DrawData::Uniform, "var<uniform> draw"),
+      (DrawData::Storage, "var<storage, read> draws"),
+    ];
+    for (draw_data, _) in declarations {
+      let shader = pbr_source(draw_data, None, MaterialFeatures::default(), false);
+      for (other, declaration) in declarations {
+        let expected = other == draw_data;
+        assert_eq!(shader.contains(declaration), expected, "{:?}", draw_data);
+      }
+    }
+  }
+
+  // Their includes resolve and every #{NAME} they use is defined
+  #[test]
+  fn scene_shaders_preprocess() {
+    let sources = [
+      include_str!("scene.wgsl"),
+      include_str!("gbuffer.wgsl"),
+      include_str!("deferred.wgsl"),
+      include_str!("fog.wgsl"),
+      include_str!("velocity.wgsl"),
+      include_str!("ghost.wgsl"),
+      include_str!("grid.wgsl"),
+      include_str!("debug_line.wgsl"),
+      include_str!("id.wgsl"),
+      include_str!("outline.wgsl"),
+      include_str!("ssao.wgsl"),
+      include_str!("pbr.wgsl"),
+      include_str!("skinning.wgsl"),
+      include_str!("water.wgsl"),
+    ];
+    for source in sources {
+      let shader = preprocess(source, &cluster_defs(ShaderDefs::new())).unwrap();
+      assert!(shader.contains("var<uniform> globals: Globals;"));
+      assert_eq!(shader.matches("struct Globals").count(), 1);
+    }
+  }
+
+  // The vertex buffers each mesh pipeline is made with give its vertex shader what it reads
+  #[test]
+  fn vertex_buffers_match_shaders() {
+    let mesh = [MeshVertex::layout(), SceneInstance::layout()];
+    let skinned = [
+      MeshVertex::layout(),
+      SceneInstance::layout(),
+      SkinVertex::layout(),
+    ];
+    let pipelines: [(&str, &str, &[wgpu::VertexBufferLayout]); 9] = [
+      (include_str!("scene.wgsl"), "vs_main", &mesh),
+      (include_str!("scene.wgsl"), "vs_shadow", &mesh),
+      (include_str!("scene.wgsl"), "vs_prepass", &mesh),
+      (include_str!("gbuffer.wgsl"), "vs_main", &mesh),
+      (include_str!("velocity.wgsl"), "vs_main", &mesh),
+      (include_str!("ghost.wgsl"), "vs_main", &mesh),
+      (include_str!("id.wgsl"), "vs_main", &mesh),
+      (include_str!("skinning.wgsl"), "vs_main", &skinned),
+      (
+        include_str!("debug_line.wgsl"),
+        "vs_main",
+        &[DebugVertex::layout()],
+      ),
+    ];
+    for (source, entry_point, buffers) in pipelines {
+      let source = preprocess(source, &cluster_defs(ShaderDefs::new())).unwrap();
+      let reflection = ShaderReflection::from_wgsl(&source).unwrap();
+      reflection
+        .check_vertex_buffers(entry_point, buffers)
+        .unwrap();
+    }
+    for draw_data in [
+      DrawData::PushConstants,
+      DrawData::Uniform,
+      DrawData::Storage,
+    ] {
+      for bindless in [None, Some(64)] {
+        for features in MaterialFeatures::all() {
+          for alpha_to_coverage in [false, true] {
+            let source = pbr_source(draw_data, bindless, features, alpha_to_coverage);
+            let reflection = ShaderReflection::from_wgsl(&source).unwrap();
+            reflection
+              .check_vertex_buffers("vs_main", &[MeshVertex::layout()])
+              .unwrap();
+          }
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn main_pipe_entry_points_exist() {
+    let source = include_str!("shader.wgsl");
+    for variant in SHADER_VARIANTS {
+      let config = main_pipe_config(variant, 1);
+      for entry in [&config.vertex_entry, &config.fragment_entry] {
+        assert!(source.contains(&format!("fn {}(", entry)), "{}", entry);
+      }
+    }
+  }
 }
@@ -0,0 +1,216 @@
+use serde::{Deserialize, Serialize};
+use wgpu::{util::DeviceExt, CommandEncoder, Device, RenderPass};
+
+use crate::{
+  gpu_memory::Tracked,
+  lsystem::{build_mesh, LSystem, LSystemError, TurtleSettings},
+  math::{self, next_random},
+  scene::{GpuMesh, SceneInstance},
+};
+
+// Differently grown meshes from the same rules, each plant picks one
+const VARIANTS: u32 = 4;
+const PLANTS_PER_GROUP: u32 = 64;
+// Plants only grow this far in from the terrain's edge, it slopes down to nothing there
+const BORDER: f32 = 0.15;
+// world units
+const MIN_HEIGHT: f32 = 0.4;
+const MAX_HEIGHT: f32 = 0.9;
+
+// The `[plants]` section of settings.toml, also editable through the web bridge
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlantSettings {
+  // see LSystem::parse, symbols with several rules make every variant different
+  pub rules: String,
+  // degrees per turn
+  pub angle: f32,
+  pub iterations: u32,
+  pub count: u32,
+  // the variants and where the plants grow
+  pub seed: u32,
+}
+
+impl Default for PlantSettings {
+  fn default() -> Self {
+    Self {
+      rules: "X; X=F[&+X][&-X]/FX; X=F[^X]//[&X]FX; X=F[&X]\\[&X]FX; F=FF".to_string(),
+      angle: 25.0,
+      iterations: 4,
+      count: 60,
+      seed: 7,
+    }
+  }
+}
+
+// L-system plants scattered over the terrain, each one a random variant, size, turn and
+// shade. A compute pass keeps them standing on the terrain while it erodes.
+pub struct Plants {
+  variants: Vec<GpuMesh>,
+  // instances are grouped by variant, the first counts[0] use variants[0] and so on
+  counts: Vec<u32>,
+  instance_buffer: Tracked<wgpu::Buffer>,
+  bind_group: wgpu::BindGroup,
+  pipeline: wgpu::ComputePipeline,
+  // at least 1, storage buffers can't be empty
+  allocated: u32,
+  dirty: bool,
+}
+
+impl Plants {
+  // `terrain_vertices` is the terrain's vertex buffer, a `resolution` by `resolution` grid
+  pub fn new(
+    device: &Device,
+    terrain_vertices: &wgpu::Buffer,
+    resolution: u32,
+    settings: &PlantSettings,
+  ) -> Result<Self, LSystemError> {
+    let lsystem = LSystem::parse(&settings.rules)?;
+    let turtle = TurtleSettings {
+      angle_degrees: settings.angle,
+      ..Default::default()
+    };
+    let variants = (0..VARIANTS)
+      .map(|variant| {
+        let seed = settings
+          .seed
+          .wrapping_mul(VARIANTS)
+          .wrapping_add(variant + 1);
+        let symbols = lsystem.expand(settings.iterations, seed);
+        GpuMesh::new(device, &build_mesh(&symbols, &turtle, seed))
+      })
+      .collect();
+    let counts: Vec<u32> = (0..VARIANTS)
+      .map(|v| (v + 1) * settings.count / VARIANTS - v * settings.count / VARIANTS)
+      .collect();
+
+    let allocated = settings.count.max(1);
+    let mut rng = settings.seed.max(1);
+    let mut anchors = Vec::with_capacity(allocated as usize);
+    let mut instances = Vec::with_capacity(allocated as usize);
+    for _ in 0..allocated {
+      let mut cell = || {
+        let t = BORDER + next_random(&mut rng) * (1.0 - 2.0 * BORDER);
+        (t * (resolution - 1) as f32).round() as u32
+      };
+      let (x, z) = (cell(), cell());
+      anchors.push(z * resolution + x);
+      let height = MIN_HEIGHT + next_random(&mut rng) * (MAX_HEIGHT - MIN_HEIGHT);
+      let yaw = next_random(&mut rng) * std::f32::consts::TAU;
+      let shade = next_random(&mut rng);
+      instances.push(SceneInstance {
+        // the translation is filled in by cs_place
+        model: math::mul_mat4(&math::rotation_y(yaw), &math::scaling([height; 3])),
+        color: [0.3 + 0.15 * shade, 0.45 + 0.1 * shade, 0.15, 1.0],
+        material: [0.1, 16.0, 0.0, 0.0],
+      });
+    }
+    let anchor_buffer = Tracked::buffer(device.create_buffer_init(
+      &wgpu::util::BufferInitDescriptor {
+        label: Some("Plant Anchor Buffer"),
+        contents: bytemuck::cast_slice(&anchors),
+        usage: wgpu::BufferUsages::STORAGE,
+      },
+    ));
+    let instance_buffer = Tracked::buffer(device.create_buffer_init(
+      &wgpu::util::BufferInitDescriptor {
+        label: Some("Plant Instance Buffer"),
+        contents: bytemuck::cast_slice(&instances),
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
+      },
+    ));
+
+    let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility: wgpu::ShaderStages::COMPUTE,
+      ty: wgpu::BindingType::Buffer {
+        ty: wgpu::BufferBindingType::Storage { read_only },
+        has_dynamic_offset: false,
+        min_binding_size: None,
+      },
+      count: None,
+    };
+    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Plant Bind Group Layout"),
+      entries: &[
+        storage_entry(0, true),
+        storage_entry(1, true),
+        storage_entry(2, false),
+      ],
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Plant Bind Group"),
+      layout: &layout,
+      entries: &[
+        wgpu::BindGroupEntry {
+          binding: 0,
+          resource: terrain_vertices.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+          binding: 1,
+          resource: anchor_buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+          binding: 2,
+          resource: instance_buffer.as_entire_binding(),
+        },
+      ],
+    });
+    let shader = device.create_shader_module(wgpu::include_wgsl!("plants.wgsl"));
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Plant Pipeline Layout"),
+      bind_group_layouts: &[&layout],
+      push_constant_ranges: &[],
+    });
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+      label: Some("Plant Place Pipeline"),
+      layout: Some(&pipeline_layout),
+      module: &shader,
+      entry_point: "cs_place",
+    });
+
+    Ok(Self {
+      variants,
+      counts,
+      instance_buffer,
+      bind_group,
+      pipeline,
+      allocated,
+      dirty: true,
+    })
+  }
+
+  // The terrain moved, the plants follow on the next place()
+  pub fn follow_terrain(&mut self) {
+    self.dirty = true;
+  }
+
+  // Puts the plants back on the terrain if it changed since the last time
+  pub fn place(&mut self, encoder: &mut CommandEncoder) {
+    if !std::mem::take(&mut self.dirty) {
+      return;
+    }
+    let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+      label: Some("Plant Place Pass"),
+    });
+    pass.set_pipeline(&self.pipeline);
+    pass.set_bind_group(0, &self.bind_group, &[]);
+    pass.dispatch_workgroups(self.allocated.div_ceil(PLANTS_PER_GROUP), 1, 1);
+  }
+
+  // With the scene pipeline or the shadow pipeline bound
+  pub fn draw<'a>(&'a self, pass: &mut RenderPass<'a>) {
+    let stride = std::mem::size_of::<SceneInstance>() as wgpu::BufferAddress;
+    let mut first = 0;
+    for (mesh, &count) in self.variants.iter().zip(&self.counts) {
+      if count == 0 {
+        continue;
+      }
+      // offsetting the binding instead of the instance range, WebGL has no base instance
+      let start = first as wgpu::BufferAddress * stride;
+      pass.set_vertex_buffer(1, self.instance_buffer.slice(start..));
+      mesh.draw(pass, count);
+      first += count;
+    }
+  }
+}
@@ -0,0 +1,30 @@
+// Moves every plant onto the terrain vertex it grows from, run after the erosion changed
+// the heights
+
+// Matches SceneInstance in scene.rs
+struct Instance {
+    model: mat4x4<f32>,
+    color: vec4<f32>,
+    material: vec4<f32>,
+};
+
+// MeshVertex, 12 floats each, position first
+@group(0) @binding(0)
+var<storage, read> vertices: array<f32>;
+// index of the terrain vertex under each plant
+@group(0) @binding(1)
+var<storage, read> anchors: array<u32>;
+@group(0) @binding(2)
+var<storage, read_write> instances: array<Instance>;
+
+// Plants start a little below the surface so they don't float over slopes
+const SINK: f32 = 0.03;
+
+@compute @workgroup_size(64)
+fn cs_place(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= arrayLength(&anchors) {
+        return;
+    }
+    let base = anchors[id.x] * 12u;
+    instances[id.x].model[3] = vec4<f32>(vertices[base], vertices[base + 1u] - SINK, vertices[base + 2u], 1.0);
+}
@@ -0,0 +1,117 @@
+use std::{collections::HashSet, time::Duration};
+
+use instant::Instant;
+use serde::{Deserialize, Serialize};
+use winit::window::WindowId;
+
+// Frames keep coming for this long after the last input, so exposure can settle
+const SETTLE_TIME: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PowerMode {
+  // redraw as fast as the present mode allows
+  Performance,
+  // Fifo, throttled when unfocused and idle while nothing moves
+  Saver,
+  // for viewer/editor style use: Fifo and no frames at all unless input, animation or
+  // State::invalidate asks for one, focus doesn't matter
+  OnDemand,
+}
+
+// The `[power]` section of the config file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PowerSettings {
+  pub mode: PowerMode,
+  // frame rate in saver mode while none of the windows has focus
+  pub unfocused_fps: u32,
+}
+
+impl Default for PowerSettings {
+  fn default() -> Self {
+    Self {
+      mode: PowerMode::Performance,
+      unfocused_fps: 10,
+    }
+  }
+}
+
+// How the event loop should schedule the next redraw
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FramePacing {
+  Continuous,
+  // at most one frame per interval
+  Throttled(Duration),
+  // nothing changes on screen, only redraw when an event asks for it
+  OnDemand,
+}
+
+// Tracks focus and activity to decide the frame pacing in saver mode
+pub struct PowerSaver {
+  settings: PowerSettings,
+  focused: HashSet<WindowId>,
+  last_activity: Instant,
+  // something changed without any input, e.g. an embedder edited the scene
+  dirty: bool,
+}
+
+impl PowerSaver {
+  pub fn new(settings: PowerSettings) -> Self {
+    Self {
+      settings,
+      focused: HashSet::new(),
+      last_activity: Instant::now(),
+      dirty: true,
+    }
+  }
+
+  // Whether frames may stop, true for both Saver and OnDemand
+  pub fn is_saving(&self) -> bool {
+    self.settings.mode != PowerMode::Performance
+  }
+
+  // Windows start out unfocused until winit says otherwise
+  pub fn set_focused(&mut self, window_id: WindowId, focused: bool) {
+    if focused {
+      self.focused.insert(window_id);
+    } else {
+      self.focused.remove(&window_id);
+    }
+    self.activity();
+  }
+
+  pub fn window_closed(&mut self, window_id: WindowId) {
+    self.focused.remove(&window_id);
+  }
+
+  // Input or anything else that changes what's on screen
+  pub fn activity(&mut self) {
+    self.last_activity = Instant::now();
+  }
+
+  // Asks for one more frame even though nothing happened
+  pub fn invalidate(&mut self) {
+    self.dirty = true;
+  }
+
+  // Called after a frame was rendered, clears the invalidation
+  pub fn frame_drawn(&mut self) {
+    self.dirty = false;
+  }
+
+  // `animating` is whether anything moves on its own, e.g. the simulation
+  pub fn pacing(&self, animating: bool) -> FramePacing {
+    if !self.is_saving() || self.dirty {
+      return FramePacing::Continuous;
+    }
+    if !animating && self.last_activity.elapsed() > SETTLE_TIME {
+      return FramePacing::OnDemand;
+    }
+    if self.settings.mode == PowerMode::Saver && self.focused.is_empty() {
+      let fps = self.settings.unfocused_fps.max(1);
+      return FramePacing::Throttled(Duration::from_secs(1) / fps);
+    }
+    FramePacing::Continuous
+  }
+}
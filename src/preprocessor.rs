@@ -0,0 +1,403 @@
+use std::collections::BTreeMap;
+
+// Compile-time defines for a shader. Flags only need to exist for `#ifdef`,
+// values are pasted wherever the source says `#{NAME}`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ShaderDefs {
+  defs: BTreeMap<String, String>,
+}
+
+impl ShaderDefs {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn flag(mut self, name: &str) -> Self {
+    self.defs.insert(name.to_string(), String::new());
+    self
+  }
+
+  pub fn value(mut self, name: &str, value: impl ToString) -> Self {
+    self.defs.insert(name.to_string(), value.to_string());
+    self
+  }
+
+  pub fn is_defined(&self, name: &str) -> bool {
+    self.defs.contains_key(name)
+  }
+
+  pub fn get(&self, name: &str) -> Option<&str> {
+    self.defs.get(name).map(String::as_str)
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+    self.defs.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PreprocessError {
+  UnknownDirective {
+    line: usize,
+    directive: String,
+  },
+  MissingName {
+    line: usize,
+  },
+  UnexpectedElse {
+    line: usize,
+  },
+  UnexpectedEndif {
+    line: usize,
+  },
+  UnterminatedIf {
+    line: usize,
+  },
+  UndefinedValue {
+    line: usize,
+    name: String,
+  },
+  MissingInclude {
+    line: usize,
+  },
+  UnknownInclude {
+    line: usize,
+    name: String,
+  },
+  // something wrong inside an included file, `line` is the #include's
+  InInclude {
+    line: usize,
+    name: String,
+    error: Box<PreprocessError>,
+  },
+}
+
+impl std::fmt::Display for PreprocessError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      PreprocessError::UnknownDirective { line, directive } => {
+        write!(f, "line {}: unknown directive #{}", line, directive)
+      }
+      PreprocessError::MissingName { line } => write!(f, "line {}: #ifdef needs a name", line),
+      PreprocessError::UnexpectedElse { line } => write!(f, "line {}: #else without #ifdef", line),
+      PreprocessError::UnexpectedEndif { line } => {
+        write!(f, "line {}: #endif without #ifdef", line)
+      }
+      PreprocessError::UnterminatedIf { line } => {
+        write!(f, "line {}: #ifdef is never closed", line)
+      }
+      PreprocessError::UndefinedValue { line, name } => {
+        write!(f, "line {}: #{{{}}} isn't defined", line, name)
+      }
+      PreprocessError::MissingInclude { line } => {
+        write!(f, "line {}: #include needs a \"file.wgsl\"", line)
+      }
+      PreprocessError::UnknownInclude { line, name } => {
+        write!(f, "line {}: there's no {} to include", line, name)
+      }
+      PreprocessError::InInclude { line, name, error } => {
+        write!(f, "line {}: in {}, {}", line, name, error)
+      }
+    }
+  }
+}
+
+impl std::error::Error for PreprocessError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      PreprocessError::InInclude { error, .. } => Some(error.as_ref()),
+      _ => None,
+    }
+  }
+}
+
+// The files `#include "name.wgsl"` can pull in, built into the binary like the shaders
+// including them
+const INCLUDES: &[(&str, &str)] = &[
+  ("common.wgsl", include_str!("common.wgsl")),
+  ("lighting.wgsl", include_str!("lighting.wgsl")),
+];
+
+struct Branch {
+  // line of the opening #ifdef, for error messages
+  line: usize,
+  // whether the enclosing branch is emitted at all
+  parent_active: bool,
+  taken: bool,
+  seen_else: bool,
+}
+
+// Resolves #include "file.wgsl", #ifdef / #ifndef / #else / #endif and #{NAME}
+// substitutions. Removed lines are kept blank so naga's errors point at the right line of
+// the source, past an #include they're off by the length of what it pulled in.
+pub fn preprocess(source: &str, defs: &ShaderDefs) -> Result<String, PreprocessError> {
+  let mut out = String::with_capacity(source.len());
+  preprocess_into(source, defs, INCLUDES, &mut Vec::new(), &mut out)?;
+  Ok(out)
+}
+
+// A file is only pulled in the first time it's included, so two files can include the
+// same one (and each other) without declaring anything twice. `includes` is INCLUDES
+// outside the tests.
+fn preprocess_into(
+  source: &str,
+  defs: &ShaderDefs,
+  includes: &[(&'static str, &'static str)],
+  included: &mut Vec<&'static str>,
+  out: &mut String,
+) -> Result<(), PreprocessError> {
+  let mut stack: Vec<Branch> = Vec::new();
+
+  for (index, text) in source.lines().enumerate() {
+    let line = index + 1;
+    let active = stack.last().is_none_or(|b| b.parent_active && b.taken);
+    let trimmed = text.trim_start();
+
+    if let Some(directive) = trimmed.strip_prefix('#').filter(|d| !d.starts_with('{')) {
+      let mut words = directive.split_whitespace();
+      match words.next().unwrap_or("") {
+        keyword @ ("ifdef" | "ifndef") => {
+          let name = words.next().ok_or(PreprocessError::MissingName { line })?;
+          stack.push(Branch {
+            line,
+            parent_active: active,
+            taken: defs.is_defined(name) == (keyword == "ifdef"),
+            seen_else: false,
+          });
+        }
+        "else" => match stack.last_mut() {
+          Some(branch) if !branch.seen_else => {
+            branch.taken = !branch.taken;
+            branch.seen_else = true;
+          }
+          _ => return Err(PreprocessError::UnexpectedElse { line }),
+        },
+        "endif" => {
+          stack
+            .pop()
+            .ok_or(PreprocessError::UnexpectedEndif { line })?;
+        }
+        "include" if !active => {}
+        "include" => {
+          let name = words
+            .next()
+            .and_then(|word| word.strip_prefix('"')?.strip_suffix('"'))
+            .ok_or(PreprocessError::MissingInclude { line })?;
+          let &(name, text) =
+            includes
+              .iter()
+              .find(|(n, _)| *n == name)
+              .ok_or(PreprocessError::UnknownInclude {
+                line,
+                name: name.to_string(),
+              })?;
+          if !included.contains(&name) {
+            included.push(name);
+            preprocess_into(text, defs, includes, included, out).map_err(|error| {
+              PreprocessError::InInclude {
+                line,
+                name: name.to_string(),
+                error: Box::new(error),
+              }
+            })?;
+          }
+        }
+        other => {
+          return Err(PreprocessError::UnknownDirective {
+            line,
+            directive: other.to_string(),
+          })
+        }
+      }
+    } else if active {
+      substitute(text, defs, line, out)?;
+    }
+    out.push('\n');
+  }
+
+  match stack.pop() {
+    Some(branch) => Err(PreprocessError::UnterminatedIf { line: branch.line }),
+    None => Ok(()),
+  }
+}
+
+fn substitute(
+  mut text: &str,
+  defs: &ShaderDefs,
+  line: usize,
+  out: &mut String,
+) -> Result<(), PreprocessError> {
+  while let Some(start) = text.find("#{") {
+    out.push_str(&text[..start]);
+    let rest = &text[start + 2..];
+    let end = rest.find('}').ok_or(PreprocessError::UndefinedValue {
+      line,
+      name: rest.to_string(),
+    })?;
+    let name = &rest[..end];
+    let value = defs.get(name).ok_or(PreprocessError::UndefinedValue {
+      line,
+      name: name.to_string(),
+    })?;
+    out.push_str(value);
+    text = &rest[end + 1..];
+  }
+  out.push_str(text);
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // a.wgsl and b.wgsl include each other
+  const TEST_INCLUDES: &[(&str, &str)] = &[
+    ("a.wgsl", "#include \"b.wgsl\"\nfn a() {}"),
+    ("b.wgsl", "#include \"a.wgsl\"\nfn b() {}"),
+    ("broken.wgsl", "#endif"),
+  ];
+
+  fn run(source: &str, defs: &ShaderDefs) -> Result<String, PreprocessError> {
+    let mut out = String::new();
+    preprocess_into(source, defs, TEST_INCLUDES, &mut Vec::new(), &mut out)?;
+    Ok(out)
+  }
+
+  // The lines that are left, without the blanks keeping the line numbers
+  fn code(source: &str, defs: &ShaderDefs) -> Vec<String> {
+    run(source, defs)
+      .unwrap()
+      .lines()
+      .filter(|l| !l.trim().is_empty())
+      .map(str::to_string)
+      .collect()
+  }
+
+  #[test]
+  fn ifdef() {
+    let source = "#ifdef A\na\n#ifndef B\nnot_b\n#else\nb\n#endif\n#else\nnot_a\n#endif\nafter";
+    assert_eq!(code(source, &ShaderDefs::new()), ["not_a", "after"]);
+    assert_eq!(
+      code(source, &ShaderDefs::new().flag("A")),
+      ["a", "not_b", "after"]
+    );
+    let both = ShaderDefs::new().flag("A").flag("B");
+    assert_eq!(code(source, &both), ["a", "b", "after"]);
+    // removed lines stay blank
+    assert_eq!(run(source, &both).unwrap().lines().count(), 11);
+  }
+
+  #[test]
+  fn values() {
+    let defs = ShaderDefs::new().value("SIZE", 8).value("NAME", "x");
+    assert_eq!(
+      code("var<private> #{NAME}: array<f32, #{SIZE}>;", &defs),
+      ["var<private> x: array<f32, 8>;"]
+    );
+    assert_eq!(
+      run("let a = #{MISSING};", &defs),
+      Err(PreprocessError::UndefinedValue {
+        line: 1,
+        name: "MISSING".to_string()
+      })
+    );
+  }
+
+  #[test]
+  fn unbalanced() {
+    let defs = ShaderDefs::new();
+    assert_eq!(
+      run("a\n#ifdef A\nb", &defs),
+      Err(PreprocessError::UnterminatedIf { line: 2 })
+    );
+    assert_eq!(
+      run("#endif", &defs),
+      Err(PreprocessError::UnexpectedEndif { line: 1 })
+    );
+    assert_eq!(
+      run("#ifdef A\n#else\n#else\n#endif", &defs),
+      Err(PreprocessError::UnexpectedElse { line: 3 })
+    );
+    assert_eq!(
+      run("#ifdef", &defs),
+      Err(PreprocessError::MissingName { line: 1 })
+    );
+    assert_eq!(
+      run("#define A", &defs),
+      Err(PreprocessError::UnknownDirective {
+        line: 1,
+        directive: "define".to_string()
+      })
+    );
+  }
+
+  #[test]
+  fn include() {
+    let defs = ShaderDefs::new();
+    assert_eq!(
+      code("#include \"b.wgsl\"\nfn main() {}", &defs),
+      ["fn a() {}", "fn b() {}", "fn main() {}"]
+    );
+    // pulled in once however often it's included
+    assert_eq!(
+      code("#include \"a.wgsl\"\n#include \"a.wgsl\"", &defs),
+      ["fn b() {}", "fn a() {}"]
+    );
+    // not at all inside a branch that's left out
+    assert!(code("#ifdef A\n#include \"a.wgsl\"\n#endif", &defs).is_empty());
+  }
+
+  #[test]
+  fn include_cycle() {
+    // a.wgsl includes b.wgsl, which includes a.wgsl again, which is skipped
+    let out = code("#include \"a.wgsl\"", &ShaderDefs::new());
+    assert_eq!(out, ["fn b() {}", "fn a() {}"]);
+  }
+
+  #[test]
+  fn include_errors() {
+    let defs = ShaderDefs::new();
+    assert_eq!(
+      run("#include \"missing.wgsl\"", &defs),
+      Err(PreprocessError::UnknownInclude {
+        line: 1,
+        name: "missing.wgsl".to_string()
+      })
+    );
+    for bad in ["#include", "#include missing.wgsl", "#include \"a.wgsl"] {
+      assert_eq!(
+        run(bad, &defs),
+        Err(PreprocessError::MissingInclude { line: 1 }),
+        "{}",
+        bad
+      );
+    }
+    // errors inside the include point at the #include
+    assert_eq!(
+      run("\n#include \"broken.wgsl\"", &defs),
+      Err(PreprocessError::InInclude {
+        line: 2,
+        name: "broken.wgsl".to_string(),
+        error: Box::new(PreprocessError::UnexpectedEndif { line: 1 })
+      })
+    );
+  }
+
+  // The shaders' own includes, the way the pipelines use them
+  #[test]
+  fn built_in_includes() {
+    let defs = ShaderDefs::new()
+      .value("CLUSTERS_X", 1)
+      .value("CLUSTERS_Y", 1)
+      .value("CLUSTERS_Z", 1)
+      .value("MAX_CLUSTER_LIGHTS", 1);
+    let out = preprocess(
+      "#include \"lighting.wgsl\"\n#include \"common.wgsl\"",
+      &defs,
+    )
+    .unwrap();
+    // lighting.wgsl already pulled in common.wgsl
+    assert_eq!(out.matches("struct Globals {").count(), 1);
+    assert!(out.contains("const CLUSTERS_X: u32 = 1u;"));
+  }
+}
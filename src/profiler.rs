@@ -0,0 +1,389 @@
+use std::sync::{
+  atomic::{AtomicBool, Ordering},
+  Arc,
+};
+
+use wgpu::{CommandEncoder, Device, Queue, RenderPass};
+
+use crate::gpu_memory::Tracked;
+
+// Each pass takes two timestamps (start + end)
+const MAX_PASSES: u32 = 32;
+const QUERY_SIZE: u64 = std::mem::size_of::<u64>() as u64;
+// Each render pass gets one statistics query
+const MAX_STATISTICS_PASSES: u32 = 8;
+// Vertex invocations, clipper primitives out, fragment invocations
+const STATISTICS_PER_QUERY: u64 = 3;
+
+#[derive(Debug, Clone)]
+pub struct PassTiming {
+  pub label: String,
+  pub gpu_ms: f64,
+}
+
+// Handle returned by begin_pass so the matching end_pass writes into the right slot
+#[derive(Debug, Clone, Copy)]
+pub struct PassScope(Option<u32>);
+
+// Resolves a query set into a mappable buffer and reads it back a frame or so later without stalling
+struct QueryReadback {
+  resolve_buffer: Tracked<wgpu::Buffer>,
+  readback_buffer: Tracked<wgpu::Buffer>,
+  // set by the map_async callback once the readback buffer can be read
+  mapped: Arc<AtomicBool>,
+  in_flight: bool,
+}
+
+impl QueryReadback {
+  fn new(device: &Device, label: &str, size: u64) -> Self {
+    let resolve_buffer = Tracked::buffer(device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some(&format!("{} Resolve Buffer", label)),
+      size,
+      usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+      mapped_at_creation: false,
+    }));
+    let readback_buffer = Tracked::buffer(device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some(&format!("{} Readback Buffer", label)),
+      size,
+      usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+      mapped_at_creation: false,
+    }));
+    Self {
+      resolve_buffer,
+      readback_buffer,
+      mapped: Arc::new(AtomicBool::new(false)),
+      in_flight: false,
+    }
+  }
+
+  fn resolve(
+    &self,
+    encoder: &mut CommandEncoder,
+    query_set: &wgpu::QuerySet,
+    count: u32,
+    values: u64,
+  ) {
+    encoder.resolve_query_set(query_set, 0..count, &self.resolve_buffer, 0);
+    encoder.copy_buffer_to_buffer(
+      &self.resolve_buffer,
+      0,
+      &self.readback_buffer,
+      0,
+      count as u64 * values * QUERY_SIZE,
+    );
+  }
+
+  // Call after queue.submit
+  fn map(&mut self) {
+    self.in_flight = true;
+    let mapped = self.mapped.clone();
+    self
+      .readback_buffer
+      .slice(..)
+      .map_async(wgpu::MapMode::Read, move |result| {
+        if result.is_ok() {
+          mapped.store(true, Ordering::Release);
+        }
+      });
+  }
+
+  // Returns the raw query values once the previous map has finished
+  fn try_read(&mut self) -> Option<Vec<u64>> {
+    if !self.in_flight || !self.mapped.load(Ordering::Acquire) {
+      return None;
+    }
+    let values = {
+      let data = self.readback_buffer.slice(..).get_mapped_range();
+      data
+        .chunks_exact(QUERY_SIZE as usize)
+        .map(|c| u64::from_le_bytes(c.try_into().unwrap()))
+        .collect()
+    };
+    self.readback_buffer.unmap();
+    self.mapped.store(false, Ordering::Release);
+    self.in_flight = false;
+    Some(values)
+  }
+}
+
+struct TimestampQueries {
+  query_set: wgpu::QuerySet,
+  readback: QueryReadback,
+  // nanoseconds per timestamp tick
+  period: f32,
+  // labels of the passes currently sitting in the readback buffer
+  in_flight: Vec<String>,
+}
+
+pub struct GpuProfiler {
+  // None when the adapter doesn't support Features::TIMESTAMP_QUERY
+  queries: Option<TimestampQueries>,
+  labels: Vec<String>,
+  recording: bool,
+  last_frame: Vec<PassTiming>,
+}
+
+impl GpuProfiler {
+  pub fn new(device: &Device, queue: &Queue) -> Self {
+    if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+      log::info!("TIMESTAMP_QUERY not supported, GPU profiling disabled");
+      return Self::disabled();
+    }
+
+    let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+      label: Some("Profiler Query Set"),
+      ty: wgpu::QueryType::Timestamp,
+      count: MAX_PASSES * 2,
+    });
+    let readback = QueryReadback::new(device, "Profiler", MAX_PASSES as u64 * 2 * QUERY_SIZE);
+
+    Self {
+      queries: Some(TimestampQueries {
+        query_set,
+        readback,
+        period: queue.get_timestamp_period(),
+        in_flight: Vec::new(),
+      }),
+      labels: Vec::new(),
+      recording: false,
+      last_frame: Vec::new(),
+    }
+  }
+
+  pub fn disabled() -> Self {
+    Self {
+      queries: None,
+      labels: Vec::new(),
+      recording: false,
+      last_frame: Vec::new(),
+    }
+  }
+
+  pub fn is_enabled(&self) -> bool {
+    self.queries.is_some()
+  }
+
+  // Timings of the most recent frame whose queries have been read back
+  pub fn last_frame(&self) -> &[PassTiming] {
+    &self.last_frame
+  }
+
+  // Polls the device (without blocking) so finished readbacks can be collected
+  pub fn begin_frame(&mut self, device: &Device) {
+    self.labels.clear();
+    self.recording = false;
+    let Some(queries) = self.queries.as_mut() else {
+      return;
+    };
+
+    device.poll(wgpu::Maintain::Poll);
+
+    if let Some(ticks) = queries.readback.try_read() {
+      self.last_frame = std::mem::take(&mut queries.in_flight)
+        .into_iter()
+        .enumerate()
+        .map(|(i, label)| {
+          let elapsed = ticks[i * 2 + 1].wrapping_sub(ticks[i * 2]);
+          PassTiming {
+            label,
+            gpu_ms: elapsed as f64 * queries.period as f64 / 1_000_000.0,
+          }
+        })
+        .collect();
+
+      for timing in &self.last_frame {
+        log::debug!("gpu {}: {:.3}ms", timing.label, timing.gpu_ms);
+      }
+    }
+
+    // the readback buffer is still waiting on the previous frame, skip this one
+    self.recording = !queries.readback.in_flight;
+  }
+
+  pub fn begin_pass(&mut self, encoder: &mut CommandEncoder, label: &str) -> PassScope {
+    let Some(queries) = self.queries.as_ref() else {
+      return PassScope(None);
+    };
+    if !self.recording || self.labels.len() as u32 >= MAX_PASSES {
+      return PassScope(None);
+    }
+    let index = self.labels.len() as u32;
+    self.labels.push(label.to_string());
+    encoder.write_timestamp(&queries.query_set, index * 2);
+    PassScope(Some(index))
+  }
+
+  pub fn end_pass(&mut self, encoder: &mut CommandEncoder, scope: PassScope) {
+    if let (Some(queries), PassScope(Some(index))) = (self.queries.as_ref(), scope) {
+      encoder.write_timestamp(&queries.query_set, index * 2 + 1);
+    }
+  }
+
+  // Call once all passes are recorded, before the encoder is finished
+  pub fn resolve(&mut self, encoder: &mut CommandEncoder) {
+    let Some(queries) = self.queries.as_ref() else {
+      return;
+    };
+    if !self.recording || self.labels.is_empty() {
+      return;
+    }
+    let count = self.labels.len() as u32 * 2;
+    queries
+      .readback
+      .resolve(encoder, &queries.query_set, count, 1);
+  }
+
+  // Call after queue.submit so the copy into the readback buffer has been queued
+  pub fn end_frame(&mut self) {
+    let Some(queries) = self.queries.as_mut() else {
+      return;
+    };
+    if !self.recording || self.labels.is_empty() {
+      return;
+    }
+    queries.in_flight = std::mem::take(&mut self.labels);
+    queries.readback.map();
+  }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PipelineCounters {
+  pub vertex_invocations: u64,
+  pub clipper_primitives: u64,
+  pub fragment_invocations: u64,
+}
+
+struct StatisticsQueries {
+  query_set: wgpu::QuerySet,
+  readback: QueryReadback,
+  in_flight: u32,
+}
+
+// Counts vertex/primitive/fragment work per frame with Features::PIPELINE_STATISTICS_QUERY
+pub struct PipelineStatistics {
+  queries: Option<StatisticsQueries>,
+  used: u32,
+  recording: bool,
+  last_frame: Option<PipelineCounters>,
+}
+
+impl PipelineStatistics {
+  pub fn new(device: &Device) -> Self {
+    if !device
+      .features()
+      .contains(wgpu::Features::PIPELINE_STATISTICS_QUERY)
+    {
+      log::info!("PIPELINE_STATISTICS_QUERY not supported, pipeline statistics disabled");
+      return Self {
+        queries: None,
+        used: 0,
+        recording: false,
+        last_frame: None,
+      };
+    }
+
+    // the results of one query come back in bit order of the enabled types
+    let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+      label: Some("Pipeline Statistics Query Set"),
+      ty: wgpu::QueryType::PipelineStatistics(
+        wgpu::PipelineStatisticsTypes::VERTEX_SHADER_INVOCATIONS
+          | wgpu::PipelineStatisticsTypes::CLIPPER_PRIMITIVES_OUT
+          | wgpu::PipelineStatisticsTypes::FRAGMENT_SHADER_INVOCATIONS,
+      ),
+      count: MAX_STATISTICS_PASSES,
+    });
+    let readback = QueryReadback::new(
+      device,
+      "Pipeline Statistics",
+      MAX_STATISTICS_PASSES as u64 * STATISTICS_PER_QUERY * QUERY_SIZE,
+    );
+
+    Self {
+      queries: Some(StatisticsQueries {
+        query_set,
+        readback,
+        in_flight: 0,
+      }),
+      used: 0,
+      recording: false,
+      last_frame: None,
+    }
+  }
+
+  pub fn is_enabled(&self) -> bool {
+    self.queries.is_some()
+  }
+
+  // Summed over every render pass of the most recent frame that has been read back
+  pub fn last_frame(&self) -> Option<PipelineCounters> {
+    self.last_frame
+  }
+
+  pub fn begin_frame(&mut self, device: &Device) {
+    self.used = 0;
+    self.recording = false;
+    let Some(queries) = self.queries.as_mut() else {
+      return;
+    };
+
+    device.poll(wgpu::Maintain::Poll);
+
+    if let Some(values) = queries.readback.try_read() {
+      let counters = values
+        .chunks_exact(STATISTICS_PER_QUERY as usize)
+        .take(queries.in_flight as usize)
+        .fold(PipelineCounters::default(), |acc, v| PipelineCounters {
+          vertex_invocations: acc.vertex_invocations + v[0],
+          clipper_primitives: acc.clipper_primitives + v[1],
+          fragment_invocations: acc.fragment_invocations + v[2],
+        });
+      log::debug!("pipeline statistics: {:?}", counters);
+      self.last_frame = Some(counters);
+    }
+
+    self.recording = !queries.readback.in_flight;
+  }
+
+  // Queries can't be nested, so call this once at the top of a render pass
+  pub fn begin_pass<'a>(&'a mut self, pass: &mut RenderPass<'a>) -> PassScope {
+    let Some(queries) = self.queries.as_ref() else {
+      return PassScope(None);
+    };
+    if !self.recording || self.used >= MAX_STATISTICS_PASSES {
+      return PassScope(None);
+    }
+    let index = self.used;
+    self.used += 1;
+    pass.begin_pipeline_statistics_query(&queries.query_set, index);
+    PassScope(Some(index))
+  }
+
+  pub fn end_pass(pass: &mut RenderPass, scope: PassScope) {
+    if scope.0.is_some() {
+      pass.end_pipeline_statistics_query();
+    }
+  }
+
+  pub fn resolve(&mut self, encoder: &mut CommandEncoder) {
+    let Some(queries) = self.queries.as_ref() else {
+      return;
+    };
+    if !self.recording || self.used == 0 {
+      return;
+    }
+    queries
+      .readback
+      .resolve(encoder, &queries.query_set, self.used, STATISTICS_PER_QUERY);
+  }
+
+  pub fn end_frame(&mut self) {
+    let Some(queries) = self.queries.as_mut() else {
+      return;
+    };
+    if !self.recording || self.used == 0 {
+      return;
+    }
+    queries.in_flight = self.used;
+    queries.readback.map();
+  }
+}
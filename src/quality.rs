@@ -0,0 +1,138 @@
+use std::borrow::Cow;
+
+use image::{imageops::FilterType, DynamicImage, GenericImageView};
+use serde::{Deserialize, Serialize};
+use wgpu::{AdapterInfo, DeviceType, Limits};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QualityTier {
+  Low,
+  Medium,
+  High,
+}
+
+impl QualityTier {
+  pub fn next(self) -> Self {
+    match self {
+      QualityTier::Low => QualityTier::Medium,
+      QualityTier::Medium => QualityTier::High,
+      QualityTier::High => QualityTier::Low,
+    }
+  }
+}
+
+// Resource sizes chosen at startup so the demos fit in the GPU's memory
+#[derive(Debug, Clone, Copy)]
+pub struct QualitySettings {
+  pub tier: QualityTier,
+  // largest width/height loaded textures get downscaled to
+  pub max_texture_size: u32,
+  pub shadow_map_size: u32,
+  pub particle_count: u32,
+}
+
+// Anything set in the `[quality]` section of the config file wins over the detected values
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QualityOverrides {
+  pub tier: Option<QualityTier>,
+  pub max_texture_size: Option<u32>,
+  pub shadow_map_size: Option<u32>,
+  pub particle_count: Option<u32>,
+}
+
+impl QualitySettings {
+  pub fn for_tier(tier: QualityTier) -> Self {
+    let (max_texture_size, shadow_map_size, particle_count) = match tier {
+      QualityTier::Low => (1024, 1024, 512),
+      QualityTier::Medium => (2048, 2048, 1500),
+      QualityTier::High => (4096, 4096, 4096),
+    };
+    Self {
+      tier,
+      max_texture_size,
+      shadow_map_size,
+      particle_count,
+    }
+  }
+
+  // wgpu doesn't report VRAM, so the device type and limits stand in as memory hints:
+  // integrated and software adapters share (or are) system memory and get a lower tier
+  pub fn detect(info: &AdapterInfo, limits: &Limits) -> QualityTier {
+    match info.device_type {
+      DeviceType::DiscreteGpu if limits.max_texture_dimension_2d >= 16384 => QualityTier::High,
+      DeviceType::DiscreteGpu | DeviceType::VirtualGpu => QualityTier::Medium,
+      DeviceType::IntegratedGpu if limits.max_texture_dimension_2d >= 16384 => QualityTier::Medium,
+      _ => QualityTier::Low,
+    }
+  }
+
+  pub fn auto_configure(info: &AdapterInfo, limits: &Limits, overrides: &QualityOverrides) -> Self {
+    let tier = overrides.tier.unwrap_or_else(|| Self::detect(info, limits));
+    let mut settings = Self::for_tier(tier);
+
+    if let Some(size) = overrides.max_texture_size {
+      settings.max_texture_size = size;
+    }
+    if let Some(size) = overrides.shadow_map_size {
+      settings.shadow_map_size = size;
+    }
+    if let Some(count) = overrides.particle_count {
+      settings.particle_count = count;
+    }
+
+    // never go past what the device can actually create
+    settings.max_texture_size = settings
+      .max_texture_size
+      .clamp(1, limits.max_texture_dimension_2d);
+    settings.shadow_map_size = settings
+      .shadow_map_size
+      .clamp(1, limits.max_texture_dimension_2d);
+    // each particle is a vec2 position + vec2 velocity
+    let max_particles = limits.max_storage_buffer_binding_size / 16;
+    settings.particle_count = settings.particle_count.clamp(1, max_particles);
+
+    log::info!("quality: {:?}", settings);
+    settings
+  }
+}
+
+// `width`x`height` scaled down to fit in `max_size` keeping its aspect, as it is if it fits
+pub fn fit_texture_size(width: u32, height: u32, max_size: u32) -> (u32, u32) {
+  let longest = width.max(height);
+  if longest <= max_size {
+    return (width, height);
+  }
+  let scale = |side: u32| (side as u64 * max_size as u64 + longest as u64 / 2) / longest as u64;
+  (scale(width).max(1) as u32, scale(height).max(1) as u32)
+}
+
+// `image` downscaled to fit in `max_size` (QualitySettings::max_texture_size) before it's
+// uploaded, borrowed if it already does
+pub fn fit_texture(image: &DynamicImage, max_size: u32) -> Cow<'_, DynamicImage> {
+  let (width, height) = image.dimensions();
+  match fit_texture_size(width, height, max_size) {
+    size if size == (width, height) => Cow::Borrowed(image),
+    (width, height) => Cow::Owned(image.resize_exact(width, height, FilterType::Triangle)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn textures_fit_the_max_size() {
+    assert_eq!(fit_texture_size(512, 256, 1024), (512, 256));
+    assert_eq!(fit_texture_size(1024, 1024, 1024), (1024, 1024));
+    assert_eq!(fit_texture_size(4096, 2048, 1024), (1024, 512));
+    assert_eq!(fit_texture_size(1000, 3000, 1024), (341, 1024));
+    // a sliver keeps a pixel
+    assert_eq!(fit_texture_size(8192, 2, 1024), (1024, 1));
+
+    let image = DynamicImage::new_rgba8(300, 100);
+    assert!(matches!(fit_texture(&image, 300), Cow::Borrowed(_)));
+    assert_eq!(fit_texture(&image, 150).dimensions(), (150, 50));
+  }
+}
@@ -0,0 +1,192 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  math::{self, cross, dot, normalize, sub, Mat4, Vec3},
+  mesh::Mesh,
+};
+
+// How clicks find what they landed on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PickMethod {
+  // the scene is drawn again as ids and the pixel read back, exact but a frame late
+  IdBuffer,
+  // a ray against the meshes on the CPU, answers right away
+  RayCast,
+}
+
+// The `[picking]` section of settings.toml
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PickingSettings {
+  pub method: PickMethod,
+}
+
+impl Default for PickingSettings {
+  fn default() -> Self {
+    Self {
+      method: PickMethod::IdBuffer,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray {
+  pub origin: Vec3,
+  // normalized
+  pub direction: Vec3,
+}
+
+impl Ray {
+  pub fn new(origin: Vec3, direction: Vec3) -> Self {
+    Self {
+      origin,
+      direction: normalize(direction),
+    }
+  }
+
+  // Through the point `ndc` of the screen (x right, y up, -1..1), from the near plane to
+  // the far plane of the camera `view_proj` was made from
+  pub fn from_screen(view_proj: &Mat4, ndc: [f32; 2]) -> Self {
+    let inverse = math::inverse(view_proj);
+    let near = math::transform_point(&inverse, [ndc[0], ndc[1], 0.0]);
+    let far = math::transform_point(&inverse, [ndc[0], ndc[1], 1.0]);
+    Self::new(near, sub(far, near))
+  }
+
+  pub fn at(&self, distance: f32) -> Vec3 {
+    math::add(self.origin, math::scale(self.direction, distance))
+  }
+
+  // The same ray in the space `model` maps from. The direction isn't renormalized, so
+  // distances along it stay the ones of the original ray.
+  fn into_local(self, model: &Mat4) -> Self {
+    let inverse = math::inverse(model);
+    let origin = math::transform_point(&inverse, self.origin);
+    let tip = math::transform_point(&inverse, math::add(self.origin, self.direction));
+    Self {
+      origin,
+      direction: sub(tip, origin),
+    }
+  }
+}
+
+// Axis aligned bounding box
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+  pub min: Vec3,
+  pub max: Vec3,
+}
+
+impl Aabb {
+  pub fn from_mesh(mesh: &Mesh) -> Self {
+    let (min, max) = mesh.bounds();
+    Self { min, max }
+  }
+
+  // The box around this one's corners after `model`
+  pub fn transformed(&self, model: &Mat4) -> Self {
+    let corners = (0..8).map(|i| {
+      let pick = |axis: usize| {
+        if i & (1 << axis) == 0 {
+          self.min[axis]
+        } else {
+          self.max[axis]
+        }
+      };
+      math::transform_point(model, [pick(0), pick(1), pick(2)])
+    });
+    corners.fold(
+      Self {
+        min: [f32::MAX; 3],
+        max: [f32::MIN; 3],
+      },
+      |b, p| Self {
+        min: [0, 1, 2].map(|i| b.min[i].min(p[i])),
+        max: [0, 1, 2].map(|i| b.max[i].max(p[i])),
+      },
+    )
+  }
+
+  // Distance along `ray` to where it enters the box, 0 when it starts inside
+  pub fn intersect(&self, ray: &Ray) -> Option<f32> {
+    let (mut near, mut far) = (0.0f32, f32::MAX);
+    for axis in 0..3 {
+      // a zero direction gives infinities, which the comparisons handle
+      let inverse = 1.0 / ray.direction[axis];
+      let a = (self.min[axis] - ray.origin[axis]) * inverse;
+      let b = (self.max[axis] - ray.origin[axis]) * inverse;
+      near = near.max(a.min(b));
+      far = far.min(a.max(b));
+      if near > far {
+        return None;
+      }
+    }
+    Some(near)
+  }
+
+  // False once all of the box's corners are past the same side of clip space, some boxes
+  // that miss it near its corners still pass
+  pub fn in_view(&self, model_view_proj: &Mat4) -> bool {
+    let m = model_view_proj;
+    let corners: Vec<[f32; 4]> = (0..8)
+      .map(|i| {
+        let pick = |axis: usize| {
+          if i & (1 << axis) == 0 {
+            self.min[axis]
+          } else {
+            self.max[axis]
+          }
+        };
+        let [x, y, z] = [pick(0), pick(1), pick(2)];
+        std::array::from_fn(|row| m[0][row] * x + m[1][row] * y + m[2][row] * z + m[3][row])
+      })
+      .collect();
+    let planes: [fn(&[f32; 4]) -> bool; 6] = [
+      |c| c[0] < -c[3],
+      |c| c[0] > c[3],
+      |c| c[1] < -c[3],
+      |c| c[1] > c[3],
+      // wgpu's depth goes from 0 to 1
+      |c| c[2] < 0.0,
+      |c| c[2] > c[3],
+    ];
+    !planes.iter().any(|outside| corners.iter().all(outside))
+  }
+}
+
+// Moller-Trumbore, distance along `ray` to where it crosses the triangle from either side
+pub fn intersect_triangle(ray: &Ray, [a, b, c]: [Vec3; 3]) -> Option<f32> {
+  let (ab, ac) = (sub(b, a), sub(c, a));
+  let p = cross(ray.direction, ac);
+  let determinant = dot(ab, p);
+  if determinant.abs() < f32::EPSILON {
+    return None;
+  }
+  let inverse = 1.0 / determinant;
+  let to_origin = sub(ray.origin, a);
+  let u = dot(to_origin, p) * inverse;
+  if !(0.0..=1.0).contains(&u) {
+    return None;
+  }
+  let q = cross(to_origin, ab);
+  let v = dot(ray.direction, q) * inverse;
+  if v < 0.0 || u + v > 1.0 {
+    return None;
+  }
+  let distance = dot(ac, q) * inverse;
+  (distance >= 0.0).then_some(distance)
+}
+
+// Closest hit of `ray` on `mesh` placed by `model`, the box is checked before any triangle
+pub fn intersect_mesh(ray: &Ray, mesh: &Mesh, bounds: &Aabb, model: &Mat4) -> Option<f32> {
+  let local = ray.into_local(model);
+  bounds.intersect(&local)?;
+  mesh.lods[0]
+    .chunks_exact(3)
+    .filter_map(|t| {
+      let corner = |i: usize| mesh.vertices[t[i] as usize].position;
+      intersect_triangle(&local, [corner(0), corner(1), corner(2)])
+    })
+    .min_by(f32::total_cmp)
+}
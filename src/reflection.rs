@@ -0,0 +1,553 @@
+// What a shader expects from the Rust side, read from the shader itself with naga: the
+// bindings of each group with the stages that use them, and the vertex inputs of each
+// vertex entry point. Bind group layouts can be made from it instead of written out by
+// hand, and the ones still written by hand (shared between pipelines, or with sizes and
+// filtering the shader can't tell) can be checked against it before the pipeline is made,
+// a mismatch otherwise only shows up as a validation error at draw time.
+
+use std::{collections::BTreeMap, num::NonZeroU32};
+
+use wgpu::{
+  BindGroupLayout, BindGroupLayoutEntry, BindingType, Device, ShaderStages, TextureFormat,
+  VertexBufferLayout, VertexFormat,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReflectedBinding {
+  pub name: Option<String>,
+  pub ty: BindingType,
+  // the stages whose entry points use it
+  pub visibility: ShaderStages,
+  pub count: Option<NonZeroU32>,
+}
+
+// A location of a vertex entry point, `components` is 1 for scalars
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VertexInput {
+  pub location: u32,
+  pub kind: naga::ScalarKind,
+  pub components: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReflectError {
+  Parse(String),
+  Invalid(String),
+  // a binding of a type with no wgpu layout, e.g. a runtime sized binding array
+  Unsupported {
+    group: u32,
+    binding: u32,
+  },
+  UnknownEntryPoint(String),
+  MissingBinding {
+    group: u32,
+    binding: u32,
+  },
+  BindingType {
+    group: u32,
+    binding: u32,
+    shader: BindingType,
+    layout: BindingType,
+  },
+  Visibility {
+    group: u32,
+    binding: u32,
+    shader: ShaderStages,
+    layout: ShaderStages,
+  },
+  MissingVertexInput {
+    entry_point: String,
+    location: u32,
+  },
+  VertexFormat {
+    entry_point: String,
+    location: u32,
+    shader: VertexInput,
+    layout: VertexFormat,
+  },
+}
+
+impl std::fmt::Display for ReflectError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ReflectError::Parse(e) => write!(f, "shader doesn't parse: {}", e),
+      ReflectError::Invalid(e) => write!(f, "shader isn't valid: {}", e),
+      ReflectError::Unsupported { group, binding } => {
+        write!(f, "@group({}) @binding({}) has no layout", group, binding)
+      }
+      ReflectError::UnknownEntryPoint(name) => write!(f, "no vertex entry point {}", name),
+      ReflectError::MissingBinding { group, binding } => write!(
+        f,
+        "the shader uses @group({}) @binding({}), the layout doesn't have it",
+        group, binding
+      ),
+      ReflectError::BindingType {
+        group,
+        binding,
+        shader,
+        layout,
+      } => write!(
+        f,
+        "@group({}) @binding({}) is a {:?} in the shader but a {:?} in the layout",
+        group, binding, shader, layout
+      ),
+      ReflectError::Visibility {
+        group,
+        binding,
+        shader,
+        layout,
+      } => write!(
+        f,
+        "@group({}) @binding({}) is used in {:?}, the layout only has it in {:?}",
+        group, binding, shader, layout
+      ),
+      ReflectError::MissingVertexInput {
+        entry_point,
+        location,
+      } => write!(
+        f,
+        "{} reads @location({}), no vertex buffer has it",
+        entry_point, location
+      ),
+      ReflectError::VertexFormat {
+        entry_point,
+        location,
+        shader,
+        layout,
+      } => write!(
+        f,
+        "{} reads @location({}) as {} {:?} components, the vertex buffer has {:?}",
+        entry_point, location, shader.components, shader.kind, layout
+      ),
+    }
+  }
+}
+
+impl std::error::Error for ReflectError {}
+
+#[derive(Debug, Clone, Default)]
+pub struct ShaderReflection {
+  // by group, then binding
+  groups: BTreeMap<u32, BTreeMap<u32, ReflectedBinding>>,
+  vertex_inputs: BTreeMap<String, Vec<VertexInput>>,
+}
+
+impl ShaderReflection {
+  pub fn from_wgsl(source: &str) -> Result<Self, ReflectError> {
+    let module = naga::front::wgsl::parse_str(source)
+      .map_err(|e| ReflectError::Parse(e.emit_to_string(source)))?;
+    Self::from_module(&module)
+  }
+
+  // A module from any of naga's front ends, see ShaderFile::to_module()
+  pub fn from_module(module: &naga::Module) -> Result<Self, ReflectError> {
+    // which globals each entry point uses comes out of validation
+    let info = naga::valid::Validator::new(
+      naga::valid::ValidationFlags::all(),
+      naga::valid::Capabilities::all(),
+    )
+    .validate(module)
+    .map_err(|e| ReflectError::Invalid(e.into_inner().to_string()))?;
+
+    let mut layouter = naga::proc::Layouter::default();
+    layouter
+      .update(&module.types, &module.constants)
+      .map_err(|e| ReflectError::Invalid(e.to_string()))?;
+
+    let mut reflection = Self::default();
+    for (handle, global) in module.global_variables.iter() {
+      let Some(binding) = &global.binding else {
+        continue;
+      };
+      let mut visibility = ShaderStages::NONE;
+      for (index, entry_point) in module.entry_points.iter().enumerate() {
+        if !info.get_entry_point(index)[handle].is_empty() {
+          visibility |= stage(entry_point.stage);
+        }
+      }
+      let unsupported = ReflectError::Unsupported {
+        group: binding.group,
+        binding: binding.binding,
+      };
+      let (ty, count) = match module.types[global.ty].inner {
+        naga::TypeInner::BindingArray { base, size } => {
+          let count = match size {
+            naga::ArraySize::Constant(size) => constant_u32(module, size),
+            naga::ArraySize::Dynamic => None,
+          };
+          let Some(count) = count.and_then(NonZeroU32::new) else {
+            return Err(unsupported);
+          };
+          (base, Some(count))
+        }
+        _ => (global.ty, None),
+      };
+      let ty = binding_type(module, &layouter, global.space, ty).ok_or(unsupported)?;
+      reflection.groups.entry(binding.group).or_default().insert(
+        binding.binding,
+        ReflectedBinding {
+          name: global.name.clone(),
+          ty,
+          visibility,
+          count,
+        },
+      );
+    }
+
+    for entry_point in &module.entry_points {
+      if entry_point.stage != naga::ShaderStage::Vertex {
+        continue;
+      }
+      let mut inputs = Vec::new();
+      for argument in &entry_point.function.arguments {
+        match &module.types[argument.ty].inner {
+          // a struct of inputs, like VertexInput
+          naga::TypeInner::Struct { members, .. } => {
+            for member in members {
+              inputs.extend(vertex_input(module, member.binding.as_ref(), member.ty));
+            }
+          }
+          _ => inputs.extend(vertex_input(module, argument.binding.as_ref(), argument.ty)),
+        }
+      }
+      inputs.sort_by_key(|input| input.location);
+      reflection
+        .vertex_inputs
+        .insert(entry_point.name.clone(), inputs);
+    }
+    Ok(reflection)
+  }
+
+  pub fn binding(&self, group: u32, binding: u32) -> Option<&ReflectedBinding> {
+    self.groups.get(&group)?.get(&binding)
+  }
+
+  // One past the highest group the shader has
+  pub fn group_count(&self) -> u32 {
+    self.groups.keys().next_back().map_or(0, |group| group + 1)
+  }
+
+  // The layout of `group` as the shader declares it, in binding order. Bindings no entry
+  // point uses are left out, and float textures are taken to be filterable.
+  pub fn bind_group_layout_entries(&self, group: u32) -> Vec<BindGroupLayoutEntry> {
+    let Some(bindings) = self.groups.get(&group) else {
+      return Vec::new();
+    };
+    bindings
+      .iter()
+      .filter(|(_, reflected)| !reflected.visibility.is_empty())
+      .map(|(&binding, reflected)| BindGroupLayoutEntry {
+        binding,
+        visibility: reflected.visibility,
+        ty: reflected.ty,
+        count: reflected.count,
+      })
+      .collect()
+  }
+
+  pub fn create_bind_group_layout(
+    &self,
+    device: &Device,
+    group: u32,
+    label: &str,
+  ) -> BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some(label),
+      entries: &self.bind_group_layout_entries(group),
+    })
+  }
+
+  // Every binding of `group` the shader uses has to be in `entries`, of a type that fits
+  // and visible to the stages using it. Extra entries are fine, pipelines often share a
+  // group and each only use some of it.
+  pub fn check_bind_group(
+    &self,
+    group: u32,
+    entries: &[BindGroupLayoutEntry],
+  ) -> Result<(), ReflectError> {
+    let Some(bindings) = self.groups.get(&group) else {
+      return Ok(());
+    };
+    for (&binding, reflected) in bindings {
+      if reflected.visibility.is_empty() {
+        continue;
+      }
+      let Some(entry) = entries.iter().find(|entry| entry.binding == binding) else {
+        return Err(ReflectError::MissingBinding { group, binding });
+      };
+      if !fits(&reflected.ty, &entry.ty) {
+        return Err(ReflectError::BindingType {
+          group,
+          binding,
+          shader: reflected.ty,
+          layout: entry.ty,
+        });
+      }
+      if !entry.visibility.contains(reflected.visibility) {
+        return Err(ReflectError::Visibility {
+          group,
+          binding,
+          shader: reflected.visibility,
+          layout: entry.visibility,
+        });
+      }
+    }
+    Ok(())
+  }
+
+  pub fn vertex_inputs(&self, entry_point: &str) -> Option<&[VertexInput]> {
+    self.vertex_inputs.get(entry_point).map(Vec::as_slice)
+  }
+
+  // Every location `entry_point` reads has to come from one of `buffers`, with as many
+  // components of the same kind
+  pub fn check_vertex_buffers(
+    &self,
+    entry_point: &str,
+    buffers: &[VertexBufferLayout],
+  ) -> Result<(), ReflectError> {
+    let inputs = self
+      .vertex_inputs(entry_point)
+      .ok_or_else(|| ReflectError::UnknownEntryPoint(entry_point.to_string()))?;
+    for &input in inputs {
+      let attribute = buffers
+        .iter()
+        .flat_map(|buffer| buffer.attributes)
+        .find(|attribute| attribute.shader_location == input.location);
+      let Some(attribute) = attribute else {
+        return Err(ReflectError::MissingVertexInput {
+          entry_point: entry_point.to_string(),
+          location: input.location,
+        });
+      };
+      if vertex_format_shape(attribute.format) != (input.kind, input.components) {
+        return Err(ReflectError::VertexFormat {
+          entry_point: entry_point.to_string(),
+          location: input.location,
+          shader: input,
+          layout: attribute.format,
+        });
+      }
+    }
+    Ok(())
+  }
+}
+
+fn stage(stage: naga::ShaderStage) -> ShaderStages {
+  match stage {
+    naga::ShaderStage::Vertex => ShaderStages::VERTEX,
+    naga::ShaderStage::Fragment => ShaderStages::FRAGMENT,
+    naga::ShaderStage::Compute => ShaderStages::COMPUTE,
+  }
+}
+
+fn constant_u32(module: &naga::Module, constant: naga::Handle<naga::Constant>) -> Option<u32> {
+  match module.constants[constant].inner {
+    naga::ConstantInner::Scalar {
+      value: naga::ScalarValue::Uint(value),
+      ..
+    } => u32::try_from(value).ok(),
+    naga::ConstantInner::Scalar {
+      value: naga::ScalarValue::Sint(value),
+      ..
+    } => u32::try_from(value).ok(),
+    _ => None,
+  }
+}
+
+fn binding_type(
+  module: &naga::Module,
+  layouter: &naga::proc::Layouter,
+  space: naga::AddressSpace,
+  ty: naga::Handle<naga::Type>,
+) -> Option<BindingType> {
+  match space {
+    naga::AddressSpace::Uniform => Some(BindingType::Buffer {
+      ty: wgpu::BufferBindingType::Uniform,
+      has_dynamic_offset: false,
+      min_binding_size: wgpu::BufferSize::new(layouter[ty].size as u64),
+    }),
+    naga::AddressSpace::Storage { access } => Some(BindingType::Buffer {
+      ty: wgpu::BufferBindingType::Storage {
+        read_only: !access.contains(naga::StorageAccess::STORE),
+      },
+      has_dynamic_offset: false,
+      // a runtime sized array's size is one element
+      min_binding_size: wgpu::BufferSize::new(layouter[ty].size as u64),
+    }),
+    naga::AddressSpace::Handle => match module.types[ty].inner {
+      naga::TypeInner::Sampler { comparison: true } => {
+        Some(BindingType::Sampler(wgpu::SamplerBindingType::Comparison))
+      }
+      naga::TypeInner::Sampler { comparison: false } => {
+        Some(BindingType::Sampler(wgpu::SamplerBindingType::Filtering))
+      }
+      naga::TypeInner::Image {
+        dim,
+        arrayed,
+        class,
+      } => {
+        let view_dimension = match (dim, arrayed) {
+          (naga::ImageDimension::D1, _) => wgpu::TextureViewDimension::D1,
+          (naga::ImageDimension::D2, false) => wgpu::TextureViewDimension::D2,
+          (naga::ImageDimension::D2, true) => wgpu::TextureViewDimension::D2Array,
+          (naga::ImageDimension::D3, _) => wgpu::TextureViewDimension::D3,
+          (naga::ImageDimension::Cube, false) => wgpu::TextureViewDimension::Cube,
+          (naga::ImageDimension::Cube, true) => wgpu::TextureViewDimension::CubeArray,
+        };
+        match class {
+          naga::ImageClass::Sampled { kind, multi } => Some(BindingType::Texture {
+            sample_type: match kind {
+              naga::ScalarKind::Sint => wgpu::TextureSampleType::Sint,
+              naga::ScalarKind::Uint => wgpu::TextureSampleType::Uint,
+              _ => wgpu::TextureSampleType::Float { filterable: true },
+            },
+            view_dimension,
+            multisampled: multi,
+          }),
+          naga::ImageClass::Depth { multi } => Some(BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Depth,
+            view_dimension,
+            multisampled: multi,
+          }),
+          naga::ImageClass::Storage { format, access } => Some(BindingType::StorageTexture {
+            access: match (
+              access.contains(naga::StorageAccess::LOAD),
+              access.contains(naga::StorageAccess::STORE),
+            ) {
+              (true, true) => wgpu::StorageTextureAccess::ReadWrite,
+              (true, false) => wgpu::StorageTextureAccess::ReadOnly,
+              _ => wgpu::StorageTextureAccess::WriteOnly,
+            },
+            format: storage_format(format),
+            view_dimension,
+          }),
+        }
+      }
+      _ => None,
+    },
+    _ => None,
+  }
+}
+
+// Whether a layout entry of type `layout` can be bound where the shader has `shader`.
+// Sizes and filtering are the layout's business.
+fn fits(shader: &BindingType, layout: &BindingType) -> bool {
+  use wgpu::{SamplerBindingType as S, TextureSampleType as T};
+  match (shader, layout) {
+    (BindingType::Buffer { ty: a, .. }, BindingType::Buffer { ty: b, .. }) => a == b,
+    (BindingType::Sampler(a), BindingType::Sampler(b)) => {
+      (*a == S::Comparison) == (*b == S::Comparison)
+    }
+    (
+      BindingType::Texture {
+        sample_type: a,
+        view_dimension: a_dimension,
+        multisampled: a_multi,
+      },
+      BindingType::Texture {
+        sample_type: b,
+        view_dimension: b_dimension,
+        multisampled: b_multi,
+      },
+    ) => {
+      let sample_types = matches!((a, b), (T::Float { .. }, T::Float { .. })) || a == b;
+      sample_types && a_dimension == b_dimension && a_multi == b_multi
+    }
+    (a @ BindingType::StorageTexture { .. }, b @ BindingType::StorageTexture { .. }) => a == b,
+    _ => false,
+  }
+}
+
+fn vertex_input(
+  module: &naga::Module,
+  binding: Option<&naga::Binding>,
+  ty: naga::Handle<naga::Type>,
+) -> Option<VertexInput> {
+  let Some(&naga::Binding::Location { location, .. }) = binding else {
+    return None;
+  };
+  let (kind, components) = match module.types[ty].inner {
+    naga::TypeInner::Scalar { kind, .. } => (kind, 1),
+    naga::TypeInner::Vector { kind, size, .. } => (kind, size as u32),
+    _ => return None,
+  };
+  Some(VertexInput {
+    location,
+    kind,
+    components,
+  })
+}
+
+// What a vertex attribute of `format` looks like to the shader
+fn vertex_format_shape(format: VertexFormat) -> (naga::ScalarKind, u32) {
+  use naga::ScalarKind::{Float, Sint, Uint};
+  use VertexFormat as V;
+  match format {
+    V::Uint8x2 | V::Uint16x2 | V::Uint32x2 => (Uint, 2),
+    V::Uint8x4 | V::Uint16x4 | V::Uint32x4 => (Uint, 4),
+    V::Uint32 => (Uint, 1),
+    V::Uint32x3 => (Uint, 3),
+    V::Sint8x2 | V::Sint16x2 | V::Sint32x2 => (Sint, 2),
+    V::Sint8x4 | V::Sint16x4 | V::Sint32x4 => (Sint, 4),
+    V::Sint32 => (Sint, 1),
+    V::Sint32x3 => (Sint, 3),
+    V::Float32 | V::Float64 => (Float, 1),
+    V::Float32x3 | V::Float64x3 => (Float, 3),
+    V::Unorm8x2 | V::Snorm8x2 | V::Unorm16x2 | V::Snorm16x2 => (Float, 2),
+    V::Float16x2 | V::Float32x2 | V::Float64x2 => (Float, 2),
+    V::Unorm8x4 | V::Snorm8x4 | V::Unorm16x4 | V::Snorm16x4 => (Float, 4),
+    V::Float16x4 | V::Float32x4 | V::Float64x4 => (Float, 4),
+  }
+}
+
+// naga and wgpu name the storage texture formats the same
+fn storage_format(format: naga::StorageFormat) -> TextureFormat {
+  macro_rules! same_names {
+    ($($name:ident),* $(,)?) => {
+      match format {
+        $(naga::StorageFormat::$name => TextureFormat::$name,)*
+      }
+    };
+  }
+  same_names!(
+    R8Unorm,
+    R8Snorm,
+    R8Uint,
+    R8Sint,
+    R16Uint,
+    R16Sint,
+    R16Float,
+    Rg8Unorm,
+    Rg8Snorm,
+    Rg8Uint,
+    Rg8Sint,
+    R32Uint,
+    R32Sint,
+    R32Float,
+    Rg16Uint,
+    Rg16Sint,
+    Rg16Float,
+    Rgba8Unorm,
+    Rgba8Snorm,
+    Rgba8Uint,
+    Rgba8Sint,
+    Rgb10a2Unorm,
+    Rg11b10Float,
+    Rg32Uint,
+    Rg32Sint,
+    Rg32Float,
+    Rgba16Uint,
+    Rgba16Sint,
+    Rgba16Float,
+    Rgba32Uint,
+    Rgba32Sint,
+    Rgba32Float,
+    R16Unorm,
+    R16Snorm,
+    Rg16Unorm,
+    Rg16Snorm,
+    Rgba16Unorm,
+    Rgba16Snorm,
+  )
+}
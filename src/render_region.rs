@@ -0,0 +1,176 @@
+// Drawing into part of a render target, like a mini-map in a corner or one player's half of
+// a split screen. A Region is a share of the target, so it keeps its place when the window
+// is resized, and a PixelRect is what it comes to at one size. RegionPass sets them on a
+// render pass after checking them against the target's current size: wgpu would fail the
+// whole pass over a scissor rect that reaches outside it.
+
+use std::ops::{Deref, DerefMut};
+
+use wgpu::RenderPass;
+
+// Shares of the target's width and height from its top left corner, 0 to 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Region {
+  pub x: f32,
+  pub y: f32,
+  pub width: f32,
+  pub height: f32,
+}
+
+impl Region {
+  pub const WHOLE: Region = Region::new(0.0, 0.0, 1.0, 1.0);
+
+  pub const fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+    Self {
+      x,
+      y,
+      width,
+      height,
+    }
+  }
+
+  // The pixels it covers of a target of `size`, cut down to the target. Edges are rounded
+  // so regions next to each other share them without a gap or an overlap.
+  pub fn to_pixels(&self, (width, height): (u32, u32)) -> PixelRect {
+    let edge = |share: f32, size: u32| (share.clamp(0.0, 1.0) * size as f32).round() as u32;
+    let (left, right) = (edge(self.x, width), edge(self.x + self.width, width));
+    let (top, bottom) = (edge(self.y, height), edge(self.y + self.height, height));
+    PixelRect {
+      x: left,
+      y: top,
+      width: right.saturating_sub(left),
+      height: bottom.saturating_sub(top),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelRect {
+  pub x: u32,
+  pub y: u32,
+  pub width: u32,
+  pub height: u32,
+}
+
+impl PixelRect {
+  pub fn whole((width, height): (u32, u32)) -> Self {
+    Self {
+      x: 0,
+      y: 0,
+      width,
+      height,
+    }
+  }
+
+  // Width over height, for the projection of a camera drawing into it
+  pub fn aspect(&self) -> f32 {
+    self.width as f32 / self.height.max(1) as f32
+  }
+
+  // Whether wgpu takes it as a viewport or scissor rect of a target of `size`
+  pub fn check(&self, size: (u32, u32)) -> Result<(), RegionError> {
+    if self.width == 0 || self.height == 0 {
+      return Err(RegionError::Empty(*self));
+    }
+    let fits =
+      |start: u32, length: u32, size: u32| start.checked_add(length).is_some_and(|end| end <= size);
+    if !fits(self.x, self.width, size.0) || !fits(self.y, self.height, size.1) {
+      return Err(RegionError::OutOfBounds { rect: *self, size });
+    }
+    Ok(())
+  }
+}
+
+#[derive(Debug)]
+pub enum RegionError {
+  Empty(PixelRect),
+  OutOfBounds { rect: PixelRect, size: (u32, u32) },
+}
+
+impl std::fmt::Display for RegionError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      RegionError::Empty(rect) => write!(f, "{:?} covers no pixels", rect),
+      RegionError::OutOfBounds { rect, size } => {
+        write!(
+          f,
+          "{:?} reaches outside the {}x{} target",
+          rect, size.0, size.1
+        )
+      }
+    }
+  }
+}
+
+impl std::error::Error for RegionError {}
+
+// A render pass into a target of a known size, that only takes viewports and scissor rects
+// inside it. Anything else goes to the RenderPass it derefs to.
+pub struct RegionPass<'a, 'p> {
+  pass: &'p mut RenderPass<'a>,
+  size: (u32, u32),
+}
+
+impl<'a, 'p> RegionPass<'a, 'p> {
+  // `size` is the target's as it is now, e.g. after the window was resized
+  pub fn new(pass: &'p mut RenderPass<'a>, size: (u32, u32)) -> Self {
+    Self { pass, size }
+  }
+
+  pub fn size(&self) -> (u32, u32) {
+    self.size
+  }
+
+  // Maps clip space onto `rect`, with the full depth range. What's drawn can still spill
+  // out of it, unless the scissor rect is set as well.
+  pub fn set_viewport(&mut self, rect: PixelRect) -> Result<(), RegionError> {
+    rect.check(self.size)?;
+    let PixelRect {
+      x,
+      y,
+      width,
+      height,
+    } = rect;
+    self
+      .pass
+      .set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+    Ok(())
+  }
+
+  // Only pixels inside `rect` get drawn
+  pub fn set_scissor_rect(&mut self, rect: PixelRect) -> Result<(), RegionError> {
+    rect.check(self.size)?;
+    self
+      .pass
+      .set_scissor_rect(rect.x, rect.y, rect.width, rect.height);
+    Ok(())
+  }
+
+  // Both the viewport and the scissor rect, returns the pixels `region` came to
+  pub fn set_region(&mut self, region: Region) -> Result<PixelRect, RegionError> {
+    let rect = region.to_pixels(self.size);
+    self.set_viewport(rect)?;
+    self.set_scissor_rect(rect)?;
+    Ok(rect)
+  }
+
+  // Back to drawing over the whole target
+  pub fn reset(&mut self) {
+    // only fails for a target without pixels, nothing gets drawn into that either way
+    let _ = self.set_region(Region::WHOLE);
+  }
+}
+
+impl<'a> Deref for RegionPass<'a, '_> {
+  type Target = RenderPass<'a>;
+
+  fn deref(&self) -> &RenderPass<'a> {
+    self.pass
+  }
+}
+
+impl<'a> DerefMut for RegionPass<'a, '_> {
+  fn deref_mut(&mut self) -> &mut RenderPass<'a> {
+    self.pass
+  }
+}
@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderPath {
+  // every mesh is lit while it's drawn
+  Forward,
+  // meshes only fill the G-buffer, a fullscreen pass lights each pixel once
+  Deferred,
+}
+
+impl RenderPath {
+  pub fn next(self) -> Self {
+    match self {
+      RenderPath::Forward => RenderPath::Deferred,
+      RenderPath::Deferred => RenderPath::Forward,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AntiAliasing {
+  None,
+  // a post pass smoothing edges found in the tonemapped image
+  Fxaa,
+  // jittered frames averaged over time, only the shadowed scene is jittered
+  Taa,
+}
+
+impl AntiAliasing {
+  pub fn next(self) -> Self {
+    match self {
+      AntiAliasing::None => AntiAliasing::Fxaa,
+      AntiAliasing::Fxaa => AntiAliasing::Taa,
+      AntiAliasing::Taa => AntiAliasing::None,
+    }
+  }
+}
+
+// The `[render]` section of settings.toml
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderSettings {
+  // wgpu backends to pick an adapter from, e.g. "vulkan" or "gl,vulkan", all of them when
+  // unset. --backend and WGPU_LEARN_BACKEND take precedence.
+  pub backend: Option<String>,
+  // how the shadowed scene is lit, G switches at runtime
+  pub path: RenderPath,
+  // screen space ambient occlusion on the deferred path, O toggles it
+  pub ssao: bool,
+  // X switches at runtime
+  pub antialiasing: AntiAliasing,
+  // frustum culls the scene's cubes in a compute pass and draws them indirectly
+  pub gpu_culling: bool,
+  // with gpu_culling, also culls the cubes hidden behind a depth prepass of last frame's
+  pub occlusion_culling: bool,
+  // the models' transforms in one storage buffer their shader indexes instead of set per
+  // draw, where vertex shaders can read storage buffers. Read at startup.
+  pub storage_instances: bool,
+  // samples per pixel of the main pass, 1 for no MSAA. 4 works wherever MSAA does. Only on
+  // the forward path, read at startup.
+  pub msaa_samples: u32,
+}
+
+impl Default for RenderSettings {
+  fn default() -> Self {
+    Self {
+      backend: None,
+      path: RenderPath::Forward,
+      ssao: false,
+      antialiasing: AntiAliasing::None,
+      gpu_culling: true,
+      occlusion_culling: true,
+      storage_instances: false,
+      msaa_samples: 1,
+    }
+  }
+}
@@ -0,0 +1,206 @@
+// An offscreen color and depth target that the scene passes draw into and a material then
+// samples, for a security camera's monitor, a portal or a mirror. The color texture can be
+// bound as well as drawn into, and copied out for a screenshot of it.
+
+use wgpu::{CommandEncoder, Device, RenderPass, TextureFormat, TextureView};
+
+use crate::{gpu_memory::Tracked, hdr::DEPTH_FORMAT};
+
+// How big the target is for a window of some size
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResizePolicy {
+  // the same whatever the window's size, a monitor's screen
+  Fixed { width: u32, height: u32 },
+  // a share of the window's size, e.g. 0.5 for a mirror at half the resolution
+  Scaled(f32),
+}
+
+impl ResizePolicy {
+  // At least a pixel either way
+  pub fn size(self, (width, height): (u32, u32)) -> (u32, u32) {
+    let (width, height) = match self {
+      ResizePolicy::Fixed { width, height } => (width, height),
+      ResizePolicy::Scaled(scale) => (
+        (width as f32 * scale).round() as u32,
+        (height as f32 * scale).round() as u32,
+      ),
+    };
+    (width.max(1), height.max(1))
+  }
+}
+
+pub struct RenderTarget {
+  label: String,
+  format: TextureFormat,
+  // of the pass, the color is resolved into `texture` when it's more than 1
+  sample_count: u32,
+  policy: ResizePolicy,
+  size: (u32, u32),
+  texture: Tracked<wgpu::Texture>,
+  view: TextureView,
+  // what's drawn into with MSAA
+  msaa_view: Option<Tracked<TextureView>>,
+  depth_view: Tracked<TextureView>,
+}
+
+impl RenderTarget {
+  // `window` is the size the policy scales, it's ignored by a fixed one. `sample_count` is
+  // the pipelines', e.g. Scene::sample_count() for a view of the scene.
+  pub fn new(
+    device: &Device,
+    label: &str,
+    format: TextureFormat,
+    sample_count: u32,
+    policy: ResizePolicy,
+    window: (u32, u32),
+  ) -> Self {
+    let size = policy.size(window);
+    let (texture, view, msaa_view, depth_view) =
+      create_textures(device, label, format, sample_count, size);
+    Self {
+      label: label.to_string(),
+      format,
+      sample_count,
+      policy,
+      size,
+      texture,
+      view,
+      msaa_view,
+      depth_view,
+    }
+  }
+
+  // Call when the window's size changes. Returns whether the textures were made again, the
+  // bind groups holding the old view, like a material's, then have to be made again too.
+  pub fn resize(&mut self, device: &Device, window: (u32, u32)) -> bool {
+    let size = self.policy.size(window);
+    if size == self.size {
+      return false;
+    }
+    (self.texture, self.view, self.msaa_view, self.depth_view) =
+      create_textures(device, &self.label, self.format, self.sample_count, size);
+    self.size = size;
+    true
+  }
+
+  // The color, to bind in a material after drawing into it
+  pub fn view(&self) -> &TextureView {
+    &self.view
+  }
+
+  pub fn depth_view(&self) -> &TextureView {
+    &self.depth_view
+  }
+
+  pub fn texture(&self) -> &wgpu::Texture {
+    &self.texture
+  }
+
+  pub fn format(&self) -> TextureFormat {
+    self.format
+  }
+
+  pub fn size(&self) -> (u32, u32) {
+    self.size
+  }
+
+  pub fn aspect(&self) -> f32 {
+    self.size.0 as f32 / self.size.1 as f32
+  }
+
+  // A pass into the color and depth, clearing the color to `clear`, the depth to 1.0 and
+  // the stencil to 0 like a window's main pass. For pipelines of format() and DEPTH_FORMAT
+  // at the sample count it was made with.
+  pub fn begin_pass<'a>(
+    &'a self,
+    encoder: &'a mut CommandEncoder,
+    clear: wgpu::Color,
+  ) -> RenderPass<'a> {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+      label: Some(&self.label),
+      color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+        view: self.msaa_view.as_deref().unwrap_or(&self.view),
+        resolve_target: self.msaa_view.as_ref().map(|_| &self.view),
+        ops: wgpu::Operations {
+          load: wgpu::LoadOp::Clear(clear),
+          store: true,
+        },
+      })],
+      depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+        view: &self.depth_view,
+        depth_ops: Some(wgpu::Operations {
+          load: wgpu::LoadOp::Clear(1.0),
+          store: true,
+        }),
+        stencil_ops: Some(wgpu::Operations {
+          load: wgpu::LoadOp::Clear(0),
+          store: true,
+        }),
+      }),
+    })
+  }
+}
+
+fn create_textures(
+  device: &Device,
+  label: &str,
+  format: TextureFormat,
+  sample_count: u32,
+  (width, height): (u32, u32),
+) -> (
+  Tracked<wgpu::Texture>,
+  TextureView,
+  Option<Tracked<TextureView>>,
+  Tracked<TextureView>,
+) {
+  let size = wgpu::Extent3d {
+    width,
+    height,
+    depth_or_array_layers: 1,
+  };
+  let texture = device.create_texture(&wgpu::TextureDescriptor {
+    label: Some(label),
+    size,
+    mip_level_count: 1,
+    sample_count: 1,
+    dimension: wgpu::TextureDimension::D2,
+    format,
+    usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+      | wgpu::TextureUsages::TEXTURE_BINDING
+      | wgpu::TextureUsages::COPY_SRC,
+    view_formats: &[],
+  });
+  let msaa_view = (sample_count > 1).then(|| {
+    let msaa = device.create_texture(&wgpu::TextureDescriptor {
+      label: Some(&format!("{} Msaa", label)),
+      size,
+      mip_level_count: 1,
+      sample_count,
+      dimension: wgpu::TextureDimension::D2,
+      format,
+      usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+      view_formats: &[],
+    });
+    let view = msaa.create_view(&wgpu::TextureViewDescriptor::default());
+    Tracked::view(&msaa, view)
+  });
+  // only ever drawn into, nothing samples it
+  let depth = device.create_texture(&wgpu::TextureDescriptor {
+    label: Some(&format!("{} Depth", label)),
+    size,
+    mip_level_count: 1,
+    sample_count,
+    dimension: wgpu::TextureDimension::D2,
+    format: DEPTH_FORMAT,
+    usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+    view_formats: &[],
+  });
+  let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+  let depth_view = depth.create_view(&wgpu::TextureViewDescriptor::default());
+  (
+    Tracked::texture(texture),
+    view,
+    msaa_view,
+    Tracked::view(&depth, depth_view),
+  )
+}
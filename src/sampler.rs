@@ -0,0 +1,177 @@
+use std::num::NonZeroU8;
+
+use wgpu::{Adapter, AddressMode, CompareFunction, Device, FilterMode};
+
+// Largest clamp wgpu accepts
+pub const MAX_ANISOTROPY: u8 = 16;
+
+// Everything that goes into a sampler, turned into a descriptor by Samplers::create so
+// anisotropy only gets requested where the adapter supports it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SamplerSettings {
+  pub mag_filter: FilterMode,
+  pub min_filter: FilterMode,
+  pub mipmap_filter: FilterMode,
+  // u, v, w
+  pub address_modes: [AddressMode; 3],
+  // 1 turns anisotropic filtering off, only applies when every filter is linear
+  pub anisotropy_clamp: u8,
+  // Some for comparison samplers, e.g. shadow maps
+  pub compare: Option<CompareFunction>,
+}
+
+impl Default for SamplerSettings {
+  fn default() -> Self {
+    Self::trilinear()
+  }
+}
+
+impl SamplerSettings {
+  // Linear within and between mip levels, repeating, for textures on geometry
+  pub fn trilinear() -> Self {
+    Self {
+      mag_filter: FilterMode::Linear,
+      min_filter: FilterMode::Linear,
+      mipmap_filter: FilterMode::Linear,
+      address_modes: [AddressMode::Repeat; 3],
+      anisotropy_clamp: 1,
+      compare: None,
+    }
+  }
+
+  // Trilinear plus up to `clamp` anisotropic samples, keeps floors sharp at grazing angles
+  pub fn anisotropic(clamp: u8) -> Self {
+    Self {
+      anisotropy_clamp: clamp,
+      ..Self::trilinear()
+    }
+  }
+
+  // Bilinear without mip levels, clamped, for atlases and screen space lookups
+  pub fn linear() -> Self {
+    Self {
+      mag_filter: FilterMode::Linear,
+      min_filter: FilterMode::Linear,
+      mipmap_filter: FilterMode::Nearest,
+      address_modes: [AddressMode::ClampToEdge; 3],
+      anisotropy_clamp: 1,
+      compare: None,
+    }
+  }
+
+  // Single texel lookups, for full screen passes sampling at pixel centers
+  pub fn nearest() -> Self {
+    Self {
+      mag_filter: FilterMode::Nearest,
+      min_filter: FilterMode::Nearest,
+      mipmap_filter: FilterMode::Nearest,
+      address_modes: [AddressMode::ClampToEdge; 3],
+      anisotropy_clamp: 1,
+      compare: None,
+    }
+  }
+
+  // Hardware filtered depth comparisons (PCF) for shadow maps
+  pub fn shadow() -> Self {
+    Self {
+      mag_filter: FilterMode::Linear,
+      min_filter: FilterMode::Linear,
+      mipmap_filter: FilterMode::Nearest,
+      address_modes: [AddressMode::ClampToEdge; 3],
+      anisotropy_clamp: 1,
+      compare: Some(CompareFunction::LessEqual),
+    }
+  }
+
+  pub fn with_address_mode(self, mode: AddressMode) -> Self {
+    Self {
+      address_modes: [mode; 3],
+      ..self
+    }
+  }
+
+  fn is_linear(&self) -> bool {
+    self.mag_filter == FilterMode::Linear
+      && self.min_filter == FilterMode::Linear
+      && self.mipmap_filter == FilterMode::Linear
+  }
+
+  // wgpu only takes powers of two up to 16, anything else is rounded down
+  fn valid_anisotropy(&self) -> Option<NonZeroU8> {
+    let clamp = self.anisotropy_clamp.min(MAX_ANISOTROPY);
+    if clamp <= 1 || !self.is_linear() {
+      return None;
+    }
+    NonZeroU8::new(1 << (7 - clamp.leading_zeros()))
+  }
+
+  pub fn descriptor<'a>(&self, label: Option<&'a str>) -> wgpu::SamplerDescriptor<'a> {
+    let [address_mode_u, address_mode_v, address_mode_w] = self.address_modes;
+    wgpu::SamplerDescriptor {
+      label,
+      address_mode_u,
+      address_mode_v,
+      address_mode_w,
+      mag_filter: self.mag_filter,
+      min_filter: self.min_filter,
+      mipmap_filter: self.mipmap_filter,
+      compare: self.compare,
+      anisotropy_clamp: self.valid_anisotropy(),
+      ..Default::default()
+    }
+  }
+}
+
+// Creates samplers with anisotropy gated on adapter support, and lets it be switched
+// off at runtime to compare the quality. Samplers created before a toggle keep their
+// settings, whoever owns them has to recreate them (see Texture::refresh_sampler).
+pub struct Samplers {
+  anisotropy_supported: bool,
+  anisotropy_enabled: bool,
+}
+
+impl Samplers {
+  pub fn new(adapter: &Adapter) -> Self {
+    let anisotropy_supported = adapter
+      .get_downlevel_capabilities()
+      .flags
+      .contains(wgpu::DownlevelFlags::ANISOTROPIC_FILTERING);
+    if !anisotropy_supported {
+      log::info!("anisotropic filtering isn't supported by this adapter");
+    }
+    Self {
+      anisotropy_supported,
+      anisotropy_enabled: anisotropy_supported,
+    }
+  }
+
+  pub fn anisotropy_supported(&self) -> bool {
+    self.anisotropy_supported
+  }
+
+  pub fn anisotropy_enabled(&self) -> bool {
+    self.anisotropy_enabled
+  }
+
+  // Returns whether anisotropy is on afterwards, stays off on unsupported adapters
+  pub fn toggle_anisotropy(&mut self) -> bool {
+    self.anisotropy_enabled = self.anisotropy_supported && !self.anisotropy_enabled;
+    self.anisotropy_enabled
+  }
+
+  // The settings as they'll actually be used
+  pub fn effective(&self, settings: &SamplerSettings) -> SamplerSettings {
+    if self.anisotropy_enabled {
+      *settings
+    } else {
+      SamplerSettings {
+        anisotropy_clamp: 1,
+        ..*settings
+      }
+    }
+  }
+
+  pub fn create(&self, device: &Device, label: &str, settings: &SamplerSettings) -> wgpu::Sampler {
+    device.create_sampler(&self.effective(settings).descriptor(Some(label)))
+  }
+}
@@ -0,0 +1,1622 @@
+use std::{borrow::Cow, ops::Range};
+
+use bevy_ecs::{schedule::Schedule, world::World};
+use wgpu::{util::DeviceExt, CommandEncoder, Device, Queue, RenderPass, TextureFormat};
+
+use crate::{
+  animated_texture::AnimatedTexture,
+  animation::NodeAnimation,
+  camera::{OrbitCamera, Tile},
+  clusters::LightClusters,
+  crowd::{Crowd, Obstacle},
+  culling::GpuCulling,
+  debug_draw::DebugDraw,
+  deferred::{DeferredRenderer, GBuffer},
+  ecs::{
+    self, Bounds, GlobalTransform, Light, Material, MeshHandle, Parent, Transform, Visibility,
+  },
+  fog::FogRenderer,
+  gpu_memory::Tracked,
+  hdr::HdrPipeline,
+  hiz::HiZ,
+  keymap::InputMap,
+  lighting::{Lighting, LightingUniform, PointLight},
+  math::{self, Mat4, Vec3},
+  mesh::Mesh,
+  mipmap::MipmapGenerator,
+  pbr::{MaterialBinding, PbrMaterial, PbrPipeline, PbrTextures},
+  picking::{Entity, Picker},
+  pipeline::{
+    ghost_pipe, grid_pipe, outline_pipes, prepass_pipe, scene_pipe, shadow_pipe, velocity_pipe,
+    DrawConstants, DrawData,
+  },
+  raycast::{self, Aabb, Ray},
+  sampler::Samplers,
+  shadow::{light_view_proj, ShadowMap},
+  skinning::Skinning,
+  ssao::SsaoRenderer,
+  stats::count_draw,
+  terrain::Terrain,
+  uploader::Upload,
+  water::Water,
+};
+
+// What moves the scene's camera: orbit left, right, up and down, move forward and back
+pub const CAMERA_ACTIONS: [&str; 6] = [
+  "orbit_left",
+  "orbit_right",
+  "orbit_up",
+  "orbit_down",
+  "move_forward",
+  "move_back",
+];
+
+// Bounding sphere of the scene, the shadow map covers exactly this much
+const SCENE_CENTER: Vec3 = [0.0, 0.0, 0.0];
+const SCENE_RADIUS: f32 = 7.5;
+// radians per second the camera orbits while an arrow key is held
+const ORBIT_SPEED: f32 = 1.5;
+// share of its distance per second the camera moves in and out by
+const DOLLY_SPEED: f32 = 1.0;
+// Erosion iterations queued by one press of R
+const EROSION_BATCH: u32 = 500;
+// Point lights L scatters over the scene, to see the clustered culling keep up
+const SCATTERED_LIGHTS: usize = 256;
+const SCATTER_SEED: u32 = 0x1b87_3593;
+
+// Per instance data, the model matrix goes in as four vec4 columns
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SceneInstance {
+  pub model: Mat4,
+  pub color: [f32; 4],
+  // x: specular strength, y: Blinn-Phong shininess exponent
+  pub material: [f32; 4],
+}
+
+impl SceneInstance {
+  const ATTRIBUTES: [wgpu::VertexAttribute; 6] = wgpu::vertex_attr_array![
+    4 => Float32x4, 5 => Float32x4, 6 => Float32x4, 7 => Float32x4, 8 => Float32x4, 9 => Float32x4
+  ];
+
+  pub fn layout() -> wgpu::VertexBufferLayout<'static> {
+    wgpu::VertexBufferLayout {
+      array_stride: std::mem::size_of::<SceneInstance>() as wgpu::BufferAddress,
+      step_mode: wgpu::VertexStepMode::Instance,
+      attributes: &Self::ATTRIBUTES,
+    }
+  }
+}
+
+// Matches `Globals` in scene.wgsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct SceneGlobals {
+  view_proj: Mat4,
+  light_view_proj: Mat4,
+  camera_position: [f32; 4],
+  inverse_view_proj: Mat4,
+  unjittered_view_proj: Mat4,
+  previous_view_proj: Mat4,
+}
+
+// Sub-pixel shift of the projection for temporal anti-aliasing, see Taa::jitter()
+#[derive(Debug, Clone, Copy)]
+pub struct CameraJitter {
+  // in NDC units
+  pub offset: [f32; 2],
+  // the unjittered view_proj of the frame before, motion vectors are measured from it
+  pub previous_view_proj: Mat4,
+}
+
+// A mesh uploaded to vertex and index buffers
+pub struct GpuMesh {
+  vertex_buffer: Tracked<wgpu::Buffer>,
+  index_buffer: Tracked<wgpu::Buffer>,
+  index_count: u32,
+}
+
+impl GpuMesh {
+  pub fn new(device: &Device, mesh: &Mesh) -> Self {
+    Self::with_usage(device, mesh, wgpu::BufferUsages::empty())
+  }
+
+  // `usage` is added to the vertex buffer's, e.g. STORAGE for meshes a compute shader writes
+  pub fn with_usage(device: &Device, mesh: &Mesh, usage: wgpu::BufferUsages) -> Self {
+    let vertex_buffer = Tracked::buffer(device.create_buffer_init(
+      &wgpu::util::BufferInitDescriptor {
+        label: Some(&format!("{} Vertex Buffer", mesh.name)),
+        contents: bytemuck::cast_slice(&mesh.vertices),
+        usage: wgpu::BufferUsages::VERTEX | usage,
+      },
+    ));
+    let index_buffer = Tracked::buffer(device.create_buffer_init(
+      &wgpu::util::BufferInitDescriptor {
+        label: Some(&format!("{} Index Buffer", mesh.name)),
+        contents: bytemuck::cast_slice(&mesh.lods[0]),
+        usage: wgpu::BufferUsages::INDEX,
+      },
+    ));
+    Self {
+      vertex_buffer,
+      index_buffer,
+      index_count: mesh.lods[0].len() as u32,
+    }
+  }
+
+  pub fn vertex_buffer(&self) -> &wgpu::Buffer {
+    &self.vertex_buffer
+  }
+
+  pub fn index_count(&self) -> u32 {
+    self.index_count
+  }
+
+  // Sets the vertex and index buffers, for draws issued some other way than draw()
+  pub fn bind<'a>(&'a self, pass: &mut RenderPass<'a>) {
+    pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+    pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+  }
+
+  // Draws the first `instances` of the instance buffer bound to slot 1
+  pub fn draw<'a>(&'a self, pass: &mut RenderPass<'a>, instances: u32) {
+    self.draw_instances(pass, 0..instances);
+  }
+
+  // For shaders indexing their own data by instance_index, not on WebGL
+  pub fn draw_instances<'a>(&'a self, pass: &mut RenderPass<'a>, instances: Range<u32>) {
+    self.bind(pass);
+    count_draw();
+    pass.draw_indexed(0..self.index_count, 0, instances);
+  }
+}
+
+// A loaded mesh drawn with the PBR pipeline
+// Names a model added with Scene::add_model, unlike its Entity::Model index it doesn't change
+// when models before it are removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ModelId(u32);
+
+struct SceneModel {
+  id: ModelId,
+  mesh: GpuMesh,
+  material: MaterialBinding,
+  // a single SceneInstance
+  instance_buffer: Tracked<wgpu::Buffer>,
+  // for ray casts
+  shape: RayShape,
+  transform: Mat4,
+  // false while an entity's model is out of the active ecs::Camera's view
+  visible: bool,
+}
+
+// The scene seen by another camera than its own, for drawing it more than once a frame like
+// split screen does. Group 0 with the camera's own globals and light clusters, the lighting
+// is the scene's. Made with Scene::create_view().
+pub struct SceneView {
+  globals_buffer: Tracked<wgpu::Buffer>,
+  clusters: LightClusters,
+  bind_group: wgpu::BindGroup,
+  // the camera's position, the transparent models are sorted by it
+  eye: Vec3,
+  // not drawn in this view, like a monitor showing what the view sees
+  hidden: Option<ModelId>,
+}
+
+impl SceneView {
+  // None to draw every model
+  pub fn hide_model(&mut self, model: Option<ModelId>) {
+    self.hidden = model;
+  }
+}
+
+// A mesh kept on the CPU for ray casts, with its bounds
+struct RayShape {
+  mesh: Mesh,
+  bounds: Aabb,
+}
+
+impl RayShape {
+  fn new(mesh: Mesh) -> Self {
+    Self {
+      bounds: Aabb::from_mesh(&mesh),
+      mesh,
+    }
+  }
+
+  fn intersect(&self, ray: &Ray, model: &Mat4) -> Option<f32> {
+    raycast::intersect_mesh(ray, &self.mesh, &self.bounds, model)
+  }
+}
+
+// A few cubes on a ground plane, lit by a shadow casting sun and some point lights. Toggled with M,
+// T swaps the cubes and plane for an erodible terrain
+// A NodeAnimation and the models its nodes move, None for a node without one
+struct AnimatedNodes {
+  animation: NodeAnimation,
+  models: Vec<Option<ModelId>>,
+}
+
+pub struct Scene {
+  cube: GpuMesh,
+  plane: GpuMesh,
+  instance_buffer: Tracked<wgpu::Buffer>,
+  cube_count: u32,
+  // the plane and the cubes for ray casts, the cubes' transforms in the instance buffer's order
+  plane_shape: RayShape,
+  cube_shape: RayShape,
+  cube_transforms: Vec<Mat4>,
+  // the cubes as the camera sees them, drawn from its list while gpu_culling is set
+  culling: GpuCulling,
+  gpu_culling: bool,
+  globals_buffer: Tracked<wgpu::Buffer>,
+  lighting_buffer: Tracked<wgpu::Buffer>,
+  globals_bind_group: wgpu::BindGroup,
+  clusters: LightClusters,
+  shadow: ShadowMap,
+  pipeline: wgpu::RenderPipeline,
+  // to build `pipeline` again for the wireframe
+  format: TextureFormat,
+  // of the main pass the scene is drawn in, see HdrPipeline::sample_count()
+  sample_count: u32,
+  globals_layout: wgpu::BindGroupLayout,
+  wireframe: bool,
+  shadow_pipeline: wgpu::RenderPipeline,
+  prepass_pipeline: wgpu::RenderPipeline,
+  velocity_pipeline: wgpu::RenderPipeline,
+  ghost_pipeline: wgpu::RenderPipeline,
+  grid_pipeline: wgpu::RenderPipeline,
+  // the stencil mask and the outline around the selected model
+  outline_pipelines: [wgpu::RenderPipeline; 2],
+  // F4
+  show_grid: bool,
+  picker: Picker,
+  debug: DebugDraw,
+  // the bounds and axes of the scene's objects in debug(), F2 toggles them
+  show_gizmos: bool,
+  pbr: PbrPipeline,
+  deferred: DeferredRenderer,
+  ssao: SsaoRenderer,
+  // '
+  fog: FogRenderer,
+  models: Vec<SceneModel>,
+  next_model: u32,
+  // outlined, picking a model selects it
+  selected: Option<ModelId>,
+  // the animated textures the models' materials use
+  animations: Vec<AnimatedTexture>,
+  mipmaps: MipmapGenerator,
+  #[cfg(all(feature = "webcam", target_os = "linux"))]
+  webcams: Vec<crate::webcam::Webcam>,
+  terrain: Terrain,
+  // ;
+  water: Water,
+  crowd: Crowd,
+  // U
+  skinning: Skinning,
+  node_animations: Vec<AnimatedNodes>,
+  // entities, see ecs.rs
+  world: World,
+  systems: Schedule,
+  // follows `camera`
+  camera_entity: ecs::Entity,
+  // the Light entities', after the lighting's own
+  entity_lights: Vec<PointLight>,
+  camera: OrbitCamera,
+  lighting: Lighting,
+  enabled: bool,
+}
+
+impl Scene {
+  pub fn new(
+    device: &Device,
+    queue: &Queue,
+    samplers: &Samplers,
+    format: TextureFormat,
+    sample_count: u32,
+    shadow_map_size: u32,
+    draw_data: DrawData,
+  ) -> Self {
+    // the plane comes first, everything after it is a cube
+    let cube =
+      |position: Vec3, size: f32, angle: f32, color: [f32; 3], shininess: f32| SceneInstance {
+        model: math::mul_mat4(
+          &math::translation(position),
+          &math::mul_mat4(&math::rotation_y(angle), &math::scaling([size; 3])),
+        ),
+        color: [color[0], color[1], color[2], 1.0],
+        material: [0.5, shininess, 0.0, 0.0],
+      };
+    let instances = [
+      SceneInstance {
+        model: math::IDENTITY,
+        color: [0.6, 0.6, 0.6, 1.0],
+        material: [0.1, 8.0, 0.0, 0.0],
+      },
+      cube([0.0, 1.0, 0.0], 1.0, 0.4, [0.8, 0.3, 0.2], 32.0),
+      cube([-2.5, 0.5, 1.5], 0.5, -0.3, [0.2, 0.6, 0.8], 64.0),
+      cube([2.0, 0.75, -2.0], 0.75, 1.1, [0.3, 0.8, 0.3], 16.0),
+      cube([1.5, 0.35, 2.5], 0.35, 0.0, [0.9, 0.8, 0.2], 128.0),
+    ];
+    let instance_buffer = Tracked::buffer(device.create_buffer_init(
+      &wgpu::util::BufferInitDescriptor {
+        label: Some("Scene Instance Buffer"),
+        contents: bytemuck::cast_slice(&instances),
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
+      },
+    ));
+    let cube_mesh = Mesh::cube(1.0);
+    let plane_mesh = Mesh::plane(5.0);
+    let cube = GpuMesh::new(device, &cube_mesh);
+    let culling = GpuCulling::new(
+      device,
+      queue,
+      &cube,
+      cube_mesh.bounding_radius(),
+      &instance_buffer,
+      1,
+      instances.len() as u32 - 1,
+    );
+    // the crowd walks around the cubes, a circle around each one's footprint
+    let obstacles: Vec<Obstacle> = instances[1..]
+      .iter()
+      .map(|cube| Obstacle {
+        center: [cube.model[3][0], cube.model[3][2]],
+        radius: math::length([cube.model[0][0], cube.model[0][1], cube.model[0][2]])
+          * std::f32::consts::SQRT_2,
+      })
+      .collect();
+
+    let globals_buffer = Tracked::buffer(device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Scene Globals Buffer"),
+      size: std::mem::size_of::<SceneGlobals>() as wgpu::BufferAddress,
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    }));
+    let lighting_buffer = Tracked::buffer(device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Scene Lighting Buffer"),
+      size: std::mem::size_of::<LightingUniform>() as wgpu::BufferAddress,
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    }));
+    let uniform_entry = |binding| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+      ty: wgpu::BindingType::Buffer {
+        ty: wgpu::BufferBindingType::Uniform,
+        has_dynamic_offset: false,
+        min_binding_size: None,
+      },
+      count: None,
+    };
+    let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility: wgpu::ShaderStages::FRAGMENT,
+      ty: wgpu::BindingType::Buffer {
+        ty: wgpu::BufferBindingType::Storage { read_only: true },
+        has_dynamic_offset: false,
+        min_binding_size: None,
+      },
+      count: None,
+    };
+    let globals_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Scene Globals Layout"),
+      entries: &[
+        uniform_entry(0),
+        uniform_entry(1),
+        storage_entry(2),
+        uniform_entry(3),
+        storage_entry(4),
+      ],
+    });
+    let clusters = LightClusters::new(device);
+    let globals_bind_group = create_globals_bind_group(
+      device,
+      &globals_layout,
+      &globals_buffer,
+      &lighting_buffer,
+      &clusters,
+    );
+
+    let shadow = ShadowMap::new(device, shadow_map_size);
+    let pipeline = scene_pipe(
+      device,
+      format,
+      sample_count,
+      &globals_layout,
+      shadow.layout(),
+      wgpu::PolygonMode::Fill,
+    );
+    let shadow_pipeline = shadow_pipe(device, &globals_layout);
+    let prepass_pipeline = prepass_pipe(device, &globals_layout);
+    let velocity_pipeline = velocity_pipe(device, &globals_layout);
+    let ghost_pipeline = ghost_pipe(device, format, sample_count, &globals_layout);
+    let grid_pipeline = grid_pipe(device, format, sample_count, &globals_layout);
+    let outline_pipelines = outline_pipes(device, format, sample_count, &globals_layout);
+    let picker = Picker::new(device, &globals_layout);
+    let debug = DebugDraw::new(device, format, &globals_layout);
+    let deferred = DeferredRenderer::new(device, format, &globals_layout, shadow.layout());
+    let ssao = SsaoRenderer::new(device, queue, &globals_layout, shadow.layout());
+    let fog = FogRenderer::new(device, format, &globals_layout, shadow.layout());
+    let pbr = PbrPipeline::new(
+      device,
+      queue,
+      samplers,
+      format,
+      sample_count,
+      &globals_layout,
+      shadow.layout(),
+      draw_data,
+    );
+    let skinning = Skinning::new(
+      device,
+      format,
+      sample_count,
+      &globals_layout,
+      shadow.layout(),
+    );
+    let mut mipmaps = MipmapGenerator::new(device);
+    let water = Water::new(
+      device,
+      queue,
+      &mut mipmaps,
+      format,
+      &globals_layout,
+      pbr.environment_layout(),
+    );
+    let camera = OrbitCamera::default();
+    let mut world = ecs::world();
+    let camera_entity = world
+      .spawn(ecs::Camera {
+        camera,
+        tile: Tile::whole(1.0),
+        active: true,
+      })
+      .id();
+
+    Self {
+      cube,
+      plane: GpuMesh::new(device, &plane_mesh),
+      plane_shape: RayShape::new(plane_mesh),
+      cube_shape: RayShape::new(cube_mesh),
+      cube_transforms: instances[1..].iter().map(|i| i.model).collect(),
+      instance_buffer,
+      cube_count: instances.len() as u32 - 1,
+      culling,
+      gpu_culling: false,
+      globals_buffer,
+      lighting_buffer,
+      globals_bind_group,
+      clusters,
+      shadow,
+      pipeline,
+      format,
+      sample_count,
+      globals_layout,
+      wireframe: false,
+      shadow_pipeline,
+      prepass_pipeline,
+      velocity_pipeline,
+      ghost_pipeline,
+      grid_pipeline,
+      outline_pipelines,
+      show_grid: false,
+      picker,
+      debug,
+      show_gizmos: false,
+      pbr,
+      deferred,
+      ssao,
+      fog,
+      models: Vec::new(),
+      next_model: 0,
+      selected: None,
+      animations: Vec::new(),
+      mipmaps,
+      #[cfg(all(feature = "webcam", target_os = "linux"))]
+      webcams: Vec::new(),
+      terrain: Terrain::new(device),
+      water,
+      crowd: Crowd::new(device, &obstacles),
+      skinning,
+      node_animations: Vec::new(),
+      world,
+      systems: ecs::schedule(),
+      camera_entity,
+      entity_lights: Vec::new(),
+      camera,
+      lighting: Lighting::default(),
+      enabled: false,
+    }
+  }
+
+  pub fn is_enabled(&self) -> bool {
+    self.enabled
+  }
+
+  pub fn toggle(&mut self) -> bool {
+    self.enabled = !self.enabled;
+    self.enabled
+  }
+
+  pub fn is_wireframe(&self) -> bool {
+    self.wireframe
+  }
+
+  // What a target render_view() draws into needs, like the main pass
+  pub fn sample_count(&self) -> u32 {
+    self.sample_count
+  }
+
+  // Draws the forward path's meshes as their triangles' edges, or solid again. Needs
+  // Features::POLYGON_MODE_LINE, returns whether the wireframe is on.
+  pub fn toggle_wireframe(&mut self, device: &Device) -> bool {
+    if !device
+      .features()
+      .contains(wgpu::Features::POLYGON_MODE_LINE)
+    {
+      log::warn!("the adapter can't draw wireframes");
+      return false;
+    }
+    self.wireframe = !self.wireframe;
+    let polygon_mode = if self.wireframe {
+      wgpu::PolygonMode::Line
+    } else {
+      wgpu::PolygonMode::Fill
+    };
+    self.pipeline = scene_pipe(
+      device,
+      self.format,
+      self.sample_count,
+      &self.globals_layout,
+      self.shadow.layout(),
+      polygon_mode,
+    );
+    self.wireframe
+  }
+
+  pub fn camera(&self) -> &OrbitCamera {
+    &self.camera
+  }
+
+  pub fn camera_mut(&mut self) -> &mut OrbitCamera {
+    &mut self.camera
+  }
+
+  pub fn pbr(&self) -> &PbrPipeline {
+    &self.pbr
+  }
+
+  // For PbrPipeline::set_environment
+  pub fn pbr_mut(&mut self) -> &mut PbrPipeline {
+    &mut self.pbr
+  }
+
+  // Adds a mesh (e.g. from mesh_cache::load_or_import) shaded with the PBR pipeline
+  pub fn add_model(
+    &mut self,
+    device: &Device,
+    mesh: &Mesh,
+    material: &PbrMaterial,
+    textures: PbrTextures,
+    transform: Mat4,
+  ) -> ModelId {
+    let instance = SceneInstance {
+      model: transform,
+      color: [1.0; 4],
+      material: [0.0; 4],
+    };
+    let id = ModelId(self.next_model);
+    self.next_model += 1;
+    // the index only matters to bindless materials, which don't keep the constants
+    let draw = model_constants(transform, 0);
+    self.models.push(SceneModel {
+      id,
+      mesh: GpuMesh::new(device, mesh),
+      material: self.pbr.create_material(device, material, textures, &draw),
+      instance_buffer: Tracked::buffer(device.create_buffer_init(
+        &wgpu::util::BufferInitDescriptor {
+          label: Some(&format!("{} Instance Buffer", mesh.name)),
+          contents: bytemuck::bytes_of(&instance),
+          usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        },
+      )),
+      shape: RayShape::new(mesh.clone()),
+      transform,
+      visible: true,
+    });
+    id
+  }
+
+  pub fn world(&self) -> &World {
+    &self.world
+  }
+
+  pub fn world_mut(&mut self) -> &mut World {
+    &mut self.world
+  }
+
+  // add_model() for an entity, `transform` under `parent`'s. Moving the entity moves the
+  // model from the next run_systems() on.
+  pub fn spawn_model(
+    &mut self,
+    device: &Device,
+    mesh: &Mesh,
+    material: &PbrMaterial,
+    textures: PbrTextures,
+    transform: Transform,
+    parent: Option<ecs::Entity>,
+  ) -> ecs::Entity {
+    let id = self.add_model(device, mesh, material, textures, transform.to_mat4());
+    let mut entity = self.world.spawn((
+      transform,
+      GlobalTransform::default(),
+      MeshHandle(id),
+      Material(*material),
+      Bounds(Aabb::from_mesh(mesh)),
+      Visibility(true),
+    ));
+    if let Some(parent) = parent {
+      entity.insert(Parent(parent));
+    }
+    entity.id()
+  }
+
+  // A point light that moves with the entity, added to the lighting's own
+  pub fn spawn_light(
+    &mut self,
+    light: PointLight,
+    transform: Transform,
+    parent: Option<ecs::Entity>,
+  ) -> ecs::Entity {
+    let mut entity = self
+      .world
+      .spawn((transform, GlobalTransform::default(), Light(light)));
+    if let Some(parent) = parent {
+      entity.insert(Parent(parent));
+    }
+    entity.id()
+  }
+
+  // Takes out `entity`, its children and their models. Returns whether it was there.
+  pub fn despawn(&mut self, entity: ecs::Entity) -> bool {
+    let mut children = self.world.query::<(ecs::Entity, &Parent)>();
+    let children: Vec<ecs::Entity> = children
+      .iter(&self.world)
+      .filter(|(_, parent)| parent.0 == entity)
+      .map(|(child, _)| child)
+      .collect();
+    for child in children {
+      self.despawn(child);
+    }
+    if let Some(&MeshHandle(id)) = self.world.get::<MeshHandle>(entity) {
+      self.remove_model(id);
+    }
+    self.world.despawn(entity)
+  }
+
+  // Runs the entities' systems `dt` seconds on, culling them and the terrain's chunks for
+  // the scene's camera seen through `tile`, and uploads what moved or changed
+  pub fn run_systems(&mut self, device: &Device, queue: &Queue, tile: &Tile, dt: f32) {
+    if !self.enabled {
+      return;
+    }
+    if let Some(mut camera) = self.world.get_mut::<ecs::Camera>(self.camera_entity) {
+      camera.camera = self.camera;
+      camera.tile = *tile;
+    }
+    if self.terrain.is_enabled() {
+      self
+        .terrain
+        .cull(&self.camera.tile_view_proj(tile), self.camera.eye());
+    }
+    self.world.resource_mut::<ecs::DeltaTime>().0 = dt;
+    self.systems.run(&mut self.world);
+    let extract = std::mem::take(&mut *self.world.resource_mut::<ecs::RenderExtract>());
+    for &(id, transform, visible) in &extract.models {
+      let Some(model) = self.models.iter_mut().find(|model| model.id == id) else {
+        continue;
+      };
+      model.visible = visible;
+      if model.transform != transform {
+        self.set_model_transform(queue, id, transform);
+      }
+    }
+    for (id, material) in &extract.materials {
+      if let Some(model) = self.models.iter_mut().find(|model| model.id == *id) {
+        self
+          .pbr
+          .set_material(device, queue, &mut model.material, material);
+      }
+    }
+    self.entity_lights = extract.lights;
+  }
+
+  // The lighting's point lights and the Light entities'
+  fn point_lights(&self) -> Cow<'_, [PointLight]> {
+    if self.entity_lights.is_empty() {
+      Cow::Borrowed(self.lighting.point_lights())
+    } else {
+      Cow::Owned([self.lighting.point_lights(), &self.entity_lights].concat())
+    }
+  }
+
+  // Returns whether there was such a model. The ones after it move down an Entity::Model
+  // index.
+  pub fn remove_model(&mut self, id: ModelId) -> bool {
+    let Some(index) = self.models.iter().position(|model| model.id == id) else {
+      return false;
+    };
+    let model = self.models.remove(index);
+    self.pbr.release_material(model.material);
+    // animations with none of their models left
+    self.node_animations.retain(|nodes| {
+      nodes
+        .models
+        .iter()
+        .flatten()
+        .any(|id| self.models.iter().any(|model| model.id == *id))
+    });
+    true
+  }
+
+  // Moves the models with the nodes at the same index of `animation`, from the next
+  // advance_animations() on. It goes once all of them are removed.
+  pub fn add_node_animation(&mut self, animation: NodeAnimation, models: Vec<Option<ModelId>>) {
+    self
+      .node_animations
+      .push(AnimatedNodes { animation, models });
+  }
+
+  // None to outline nothing
+  pub fn select(&mut self, model: Option<ModelId>) {
+    self.selected = model;
+  }
+
+  pub fn selected(&self) -> Option<ModelId> {
+    self.selected
+  }
+
+  // The model at Entity::Model `index`
+  pub fn model_id(&self, index: usize) -> Option<ModelId> {
+    self.models.get(index).map(|model| model.id)
+  }
+
+  pub fn set_model_transform(&mut self, queue: &Queue, id: ModelId, transform: Mat4) {
+    let Some(model) = self.models.iter_mut().find(|model| model.id == id) else {
+      return;
+    };
+    model.transform = transform;
+    let instance = SceneInstance {
+      model: transform,
+      color: [1.0; 4],
+      material: [0.0; 4],
+    };
+    queue.write_buffer(&model.instance_buffer, 0, bytemuck::bytes_of(&instance));
+    let draw = model_constants(transform, model.material.index());
+    self.pbr.set_draw_constants(queue, &model.material, &draw);
+  }
+
+  // Keeps `texture` playing with animate(), its views go in the models' PbrTextures
+  pub fn add_animated_texture(&mut self, texture: AnimatedTexture) {
+    self.animations.push(texture);
+  }
+
+  // Keeps the webcam's texture streaming with animate(), like add_animated_texture()
+  #[cfg(all(feature = "webcam", target_os = "linux"))]
+  pub fn add_webcam(&mut self, webcam: crate::webcam::Webcam) {
+    self.webcams.push(webcam);
+  }
+
+  // Whether any animated texture has more than one frame to show, a skinned mesh or node
+  // animation is playing a clip or an entity spins
+  pub fn is_animating(&self) -> bool {
+    #[cfg(all(feature = "webcam", target_os = "linux"))]
+    if self.enabled && !self.webcams.is_empty() {
+      return true;
+    }
+    self.enabled
+      && (self.animations.iter().any(AnimatedTexture::is_animated)
+        || self.skinning.is_playing()
+        || ecs::any_with::<ecs::Spin>(&self.world)
+        || self
+          .node_animations
+          .iter()
+          .any(|nodes| nodes.animation.player().is_playing()))
+  }
+
+  // Shows the frames of the animated textures for `time` seconds, see
+  // Clock::animation, and the newest frames of the webcams
+  pub fn animate(&mut self, device: &Device, queue: &Queue, time: f64) {
+    if !self.enabled {
+      return;
+    }
+    for texture in &mut self.animations {
+      texture.set_time(device, queue, &mut self.mipmaps, time);
+    }
+    self.water.set_time(queue, time as f32);
+    #[cfg(all(feature = "webcam", target_os = "linux"))]
+    for webcam in &self.webcams {
+      webcam.update(queue);
+    }
+  }
+
+  // Plays the skinned meshes' and the node animations' clips on by `dt` seconds
+  pub fn advance_animations(&mut self, queue: &Queue, dt: f32) {
+    if !self.enabled {
+      return;
+    }
+    self.skinning.advance(dt);
+    let mut moved = Vec::new();
+    for nodes in &mut self.node_animations {
+      let transforms = nodes.animation.update(dt);
+      moved.extend(
+        nodes
+          .models
+          .iter()
+          .zip(transforms)
+          .filter_map(|(id, transform)| Some(((*id)?, transform))),
+      );
+    }
+    for (id, transform) in moved {
+      self.set_model_transform(queue, id, transform);
+    }
+  }
+
+  pub fn skinning(&self) -> &Skinning {
+    &self.skinning
+  }
+
+  pub fn skinning_mut(&mut self) -> &mut Skinning {
+    &mut self.skinning
+  }
+
+  pub fn terrain(&self) -> &Terrain {
+    &self.terrain
+  }
+
+  pub fn terrain_mut(&mut self) -> &mut Terrain {
+    &mut self.terrain
+  }
+
+  pub fn fog(&self) -> &FogRenderer {
+    &self.fog
+  }
+
+  pub fn fog_mut(&mut self) -> &mut FogRenderer {
+    &mut self.fog
+  }
+
+  pub fn water(&self) -> &Water {
+    &self.water
+  }
+
+  pub fn water_mut(&mut self) -> &mut Water {
+    &mut self.water
+  }
+
+  // Whether the crowd is showing and needs simulation ticks
+  pub fn is_crowd_walking(&self) -> bool {
+    self.enabled && self.crowd.is_enabled() && !self.terrain.is_enabled()
+  }
+
+  // Advances the crowd by `ticks` fixed steps of `tick` seconds, drawn `alpha` of the way
+  // through the last one
+  pub fn step_crowd(&mut self, queue: &Queue, ticks: u32, tick: f32, alpha: f32) {
+    if self.is_crowd_walking() {
+      self.crowd.step(queue, ticks, tick, alpha);
+    }
+  }
+
+  pub fn lighting(&self) -> &Lighting {
+    &self.lighting
+  }
+
+  pub fn lighting_mut(&mut self) -> &mut Lighting {
+    &mut self.lighting
+  }
+
+  pub fn shadow_map(&self) -> &ShadowMap {
+    &self.shadow
+  }
+
+  pub fn set_shadow_map_size(&mut self, device: &Device, size: u32) {
+    self.shadow.resize(device, size);
+  }
+
+  // The scene's actions in keymap.rs, other than the camera's which move_camera() reads.
+  // Returns whether the action is one of them.
+  pub fn action(&mut self, action: &str) -> bool {
+    match action {
+      "toggle_terrain" => {
+        let enabled = self.terrain.toggle();
+        log::info!("terrain {}", if enabled { "on" } else { "off" });
+      }
+      "erode" if self.terrain.is_enabled() => {
+        self.terrain.erode(EROSION_BATCH);
+        log::info!(
+          "eroding {} more iterations, {} so far",
+          EROSION_BATCH,
+          self.terrain.iterations()
+        );
+      }
+      "reset_terrain" if self.terrain.is_enabled() => self.terrain.reset(),
+      "toggle_water" => {
+        let enabled = self.water.toggle();
+        log::info!("water {}", if enabled { "on" } else { "off" });
+      }
+      "toggle_fog" => {
+        let enabled = self.fog.toggle();
+        log::info!("fog {}", if enabled { "on" } else { "off" });
+      }
+      "toggle_crowd" => {
+        let enabled = self.crowd.toggle();
+        log::info!("crowd {}", if enabled { "on" } else { "off" });
+      }
+      "tick_outlines" => {
+        let enabled = self.crowd.toggle_ticks();
+        log::info!(
+          "simulation tick outlines {}",
+          if enabled { "on" } else { "off" }
+        );
+      }
+      "grid" => {
+        self.show_grid = !self.show_grid;
+        log::info!("ground grid {}", if self.show_grid { "on" } else { "off" });
+      }
+      "gizmos" => {
+        self.show_gizmos = !self.show_gizmos;
+        log::info!("gizmos {}", if self.show_gizmos { "on" } else { "off" });
+      }
+      "point_lights" => {
+        if self.lighting.point_lights().len() == SCATTERED_LIGHTS {
+          self.lighting.clear_point_lights();
+          for light in Lighting::default().point_lights() {
+            self.lighting.add_point_light(*light);
+          }
+        } else {
+          self
+            .lighting
+            .scatter_point_lights(SCATTERED_LIGHTS, SCENE_RADIUS * 0.6, SCATTER_SEED);
+        }
+        log::info!("{} point lights", self.lighting.point_lights().len());
+      }
+      _ if self.skinning.action(action) => {}
+      _ => return false,
+    }
+    true
+  }
+
+  // Orbits and moves the camera for as long as its actions are held, `dt` in seconds
+  pub fn move_camera(&mut self, input: &InputMap, dt: f32) {
+    steer_camera(&mut self.camera, input, &CAMERA_ACTIONS, dt);
+  }
+
+  // Uploads the camera for a target with this aspect ratio and the lights, call before
+  // recording the passes
+  // The camera's view_proj without any jitter
+  pub fn view_proj(&self, tile: &Tile) -> Mat4 {
+    self.camera.tile_view_proj(tile)
+  }
+
+  // Uploads the camera and lights, `jitter` while TAA is on
+  pub fn update(&self, upload: &mut Upload, tile: &Tile, jitter: Option<&CameraJitter>) {
+    let globals = self.camera_globals(&self.camera, tile, jitter);
+    upload.write(&self.globals_buffer, 0, bytemuck::bytes_of(&globals));
+    upload.write(
+      &self.lighting_buffer,
+      0,
+      bytemuck::bytes_of(&self.lighting.uniform()),
+    );
+    self
+      .clusters
+      .update(upload, &self.camera, tile, &self.point_lights());
+    self.culling.update(upload, globals.unjittered_view_proj);
+    self.skinning.upload(upload);
+    if self.pbr.draw_data() == DrawData::Storage && !self.models.is_empty() {
+      let draws: Vec<DrawConstants> = self
+        .models
+        .iter()
+        .map(|model| model_constants(model.transform, model.material.index()))
+        .collect();
+      self.pbr.upload_draws(upload, &draws);
+    }
+    self.pbr.upload_materials(upload);
+  }
+
+  // A view for another camera, see update_view()
+  pub fn create_view(&self, device: &Device) -> SceneView {
+    let globals_buffer = Tracked::buffer(device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Scene View Globals Buffer"),
+      size: std::mem::size_of::<SceneGlobals>() as wgpu::BufferAddress,
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    }));
+    let clusters = LightClusters::new(device);
+    let bind_group = create_globals_bind_group(
+      device,
+      &self.globals_layout,
+      &globals_buffer,
+      &self.lighting_buffer,
+      &clusters,
+    );
+    SceneView {
+      globals_buffer,
+      clusters,
+      bind_group,
+      eye: self.camera.eye(),
+      hidden: None,
+    }
+  }
+
+  // Uploads `camera` into `view` for a target with `tile`, after update() which uploads the
+  // lighting they share
+  pub fn update_view(
+    &self,
+    upload: &mut Upload,
+    view: &mut SceneView,
+    camera: &OrbitCamera,
+    tile: &Tile,
+  ) {
+    let globals = self.camera_globals(camera, tile, None);
+    upload.write(&view.globals_buffer, 0, bytemuck::bytes_of(&globals));
+    view
+      .clusters
+      .update(upload, camera, tile, &self.point_lights());
+    view.eye = camera.eye();
+  }
+
+  // cull_lights() for `view`'s camera
+  pub fn cull_view_lights(&self, encoder: &mut CommandEncoder, view: &SceneView) {
+    view.clusters.cull(encoder);
+  }
+
+  fn camera_globals(
+    &self,
+    camera: &OrbitCamera,
+    tile: &Tile,
+    jitter: Option<&CameraJitter>,
+  ) -> SceneGlobals {
+    let [x, y, z] = camera.eye();
+    let unjittered_view_proj = camera.tile_view_proj(tile);
+    let (view_proj, previous_view_proj) = match jitter {
+      Some(jitter) => {
+        let [jx, jy] = jitter.offset;
+        let shift = math::translation([jx, jy, 0.0]);
+        (
+          math::mul_mat4(&shift, &unjittered_view_proj),
+          jitter.previous_view_proj,
+        )
+      }
+      None => (unjittered_view_proj, unjittered_view_proj),
+    };
+    SceneGlobals {
+      view_proj,
+      // only the sun casts shadows
+      light_view_proj: light_view_proj(self.lighting.sun_direction(), SCENE_CENTER, SCENE_RADIUS),
+      camera_position: [x, y, z, 1.0],
+      inverse_view_proj: math::inverse(&view_proj),
+      unjittered_view_proj,
+      previous_view_proj,
+    }
+  }
+
+  // Culls the cubes against the camera uploaded by update() on the GPU, has to run before
+  // the camera passes while set_gpu_culling() is on. With `hiz` built from
+  // render_depth_prepass() it also culls the ones hidden behind what that drew.
+  pub fn cull_objects(&self, device: &Device, encoder: &mut CommandEncoder, hiz: Option<&HiZ>) {
+    self.culling.cull(device, encoder, hiz.map(HiZ::view));
+  }
+
+  // Draws the depth of what the camera saw last frame into `hiz`'s prepass, for HiZ::build()
+  // and cull_objects() after it. Cubes culled last frame are left out, so they can't hide
+  // anything, only be hidden.
+  pub fn render_depth_prepass(&self, encoder: &mut CommandEncoder, hiz: &HiZ) {
+    let mut pass = hiz.begin_prepass(encoder);
+    pass.set_pipeline(&self.prepass_pipeline);
+    pass.set_bind_group(0, &self.globals_bind_group, &[]);
+    self.draw_meshes(&mut pass, true);
+    for model in &self.models {
+      pass.set_vertex_buffer(1, model.instance_buffer.slice(..));
+      model.mesh.draw(&mut pass, 1);
+    }
+  }
+
+  // Whether the camera passes draw cull_objects()' list or every cube, the shadow pass
+  // always draws them all
+  pub fn set_gpu_culling(&mut self, enabled: bool) {
+    self.gpu_culling = enabled;
+  }
+
+  // Bins the point lights into the clusters of the camera uploaded by update(), has to
+  // run before the passes shading the scene
+  pub fn cull_lights(&self, encoder: &mut CommandEncoder) {
+    self.clusters.cull(encoder);
+  }
+
+  // Fills the shadow map, has to run before the pass calling render()
+  pub fn render_shadows(&self, encoder: &mut CommandEncoder) {
+    let mut pass = self.shadow.begin_pass(encoder);
+    pass.set_pipeline(&self.shadow_pipeline);
+    pass.set_bind_group(0, &self.globals_bind_group, &[]);
+    self.draw_meshes(&mut pass, false);
+    for model in &self.models {
+      pass.set_vertex_buffer(1, model.instance_buffer.slice(..));
+      model.mesh.draw(&mut pass, 1);
+    }
+  }
+
+  pub fn render<'a>(&'a self, pass: &mut RenderPass<'a>) {
+    self.render_with(pass, &self.globals_bind_group, self.gpu_culling);
+    self.render_models_with(
+      pass,
+      &self.globals_bind_group,
+      self.camera.eye(),
+      None,
+      true,
+    );
+  }
+
+  // render() and render_grid() seen through `view`'s camera. Every cube and model is drawn,
+  // cull_objects() and run_systems() only cull for the scene's own camera.
+  pub fn render_view<'a>(&'a self, pass: &mut RenderPass<'a>, view: &'a SceneView) {
+    self.render_with(pass, &view.bind_group, false);
+    self.render_models_with(pass, &view.bind_group, view.eye, view.hidden, false);
+    self.render_grid_with(pass, &view.bind_group);
+  }
+
+  fn render_with<'a>(
+    &'a self,
+    pass: &mut RenderPass<'a>,
+    globals: &'a wgpu::BindGroup,
+    culled: bool,
+  ) {
+    pass.set_pipeline(&self.pipeline);
+    pass.set_bind_group(0, globals, &[]);
+    pass.set_bind_group(1, self.shadow.bind_group(), &[]);
+    self.draw_meshes(pass, culled);
+  }
+
+  // The ground grid when it's on, after the meshes so they hide it
+  pub fn render_grid<'a>(&'a self, pass: &mut RenderPass<'a>) {
+    self.render_grid_with(pass, &self.globals_bind_group);
+  }
+
+  fn render_grid_with<'a>(&'a self, pass: &mut RenderPass<'a>, globals: &'a wgpu::BindGroup) {
+    if !self.show_grid {
+      return;
+    }
+    pass.set_pipeline(&self.grid_pipeline);
+    pass.set_bind_group(0, globals, &[]);
+    count_draw();
+    pass.draw(0..3, 0..1);
+  }
+
+  // The water over the finished opaque scene in `hdr`, refracting its color. Needs the
+  // scene's depth, so after the skybox and before anything resolving the frame.
+  pub fn render_water(&mut self, device: &Device, encoder: &mut CommandEncoder, hdr: &HdrPipeline) {
+    if !self.water.is_enabled() {
+      return;
+    }
+    self.water.render(
+      device,
+      encoder,
+      hdr,
+      &self.globals_bind_group,
+      self.pbr.environment(),
+    );
+  }
+
+  // The fog over everything in `hdr` so far, by its depth and lit through the shadow map
+  pub fn render_fog(&self, device: &Device, encoder: &mut CommandEncoder, hdr: &HdrPipeline) {
+    if !self.fog.is_enabled() {
+      return;
+    }
+    self.fog.render(
+      device,
+      encoder,
+      hdr,
+      &self.globals_bind_group,
+      self.shadow.bind_group(),
+    );
+  }
+
+  // Deferred alternative to render() for everything but the models: fills `gbuffer` and
+  // the depth of `hdr`, then lights it into `hdr`'s color cleared to `clear`, with ambient
+  // occlusion when `ssao` is set. The models still need drawing with render_models() in a
+  // pass loading both.
+  pub fn render_deferred(
+    &self,
+    device: &Device,
+    encoder: &mut CommandEncoder,
+    gbuffer: &GBuffer,
+    hdr: &HdrPipeline,
+    clear: wgpu::Color,
+    ssao: bool,
+  ) {
+    {
+      let mut pass = gbuffer.begin_pass(encoder, hdr.depth_view());
+      pass.set_pipeline(self.deferred.gbuffer_pipeline());
+      pass.set_bind_group(0, &self.globals_bind_group, &[]);
+      self.draw_meshes(&mut pass, self.gpu_culling);
+    }
+
+    if ssao {
+      self.ssao.render(
+        device,
+        encoder,
+        gbuffer,
+        hdr.depth_sample_view(),
+        &self.globals_bind_group,
+        self.shadow.bind_group(),
+      );
+    } else {
+      self.ssao.clear(encoder, gbuffer);
+    }
+    let gbuffer_bind_group = self
+      .deferred
+      .bind_group(device, gbuffer, hdr.depth_sample_view());
+    let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+      label: Some("Deferred Lighting Pass"),
+      color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+        view: hdr.view(),
+        resolve_target: None,
+        ops: wgpu::Operations {
+          load: wgpu::LoadOp::Clear(clear),
+          store: true,
+        },
+      })],
+      depth_stencil_attachment: None,
+    });
+    pass.set_pipeline(self.deferred.lighting_pipeline());
+    pass.set_bind_group(0, &self.globals_bind_group, &[]);
+    pass.set_bind_group(1, self.shadow.bind_group(), &[]);
+    pass.set_bind_group(2, &gbuffer_bind_group, &[]);
+    count_draw();
+    pass.draw(0..3, 0..1);
+  }
+
+  // Motion vectors of the meshes and models into `velocity`, tested against the depth the
+  // scene passes left in `depth_view`
+  pub fn render_velocity(
+    &self,
+    encoder: &mut CommandEncoder,
+    velocity: &wgpu::TextureView,
+    depth_view: &wgpu::TextureView,
+  ) {
+    let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+      label: Some("Velocity Pass"),
+      color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+        view: velocity,
+        resolve_target: None,
+        ops: wgpu::Operations {
+          // the sky and anything else not drawn here stands still
+          load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+          store: true,
+        },
+      })],
+      depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+        view: depth_view,
+        depth_ops: Some(wgpu::Operations {
+          load: wgpu::LoadOp::Load,
+          store: true,
+        }),
+        stencil_ops: None,
+      }),
+    });
+    pass.set_pipeline(&self.velocity_pipeline);
+    pass.set_bind_group(0, &self.globals_bind_group, &[]);
+    self.draw_meshes(&mut pass, self.gpu_culling);
+    for model in &self.models {
+      pass.set_vertex_buffer(1, model.instance_buffer.slice(..));
+      model.mesh.draw(&mut pass, 1);
+    }
+  }
+
+  // Outlines where the simulation has the crowd at its last tick, around the interpolated
+  // agents. For the pass after render() or render_models(), with the same targets.
+  pub fn render_ghosts<'a>(&'a self, pass: &mut RenderPass<'a>) {
+    if !self.is_crowd_walking() {
+      return;
+    }
+    pass.set_pipeline(&self.ghost_pipeline);
+    pass.set_bind_group(0, &self.globals_bind_group, &[]);
+    self.crowd.draw_ticks(pass);
+  }
+
+  // Draws the entity ids at `pixel` of a frame of `size` for pick_result(), submit the
+  // encoder and call map_pick() after it. The camera is the one uploaded by update().
+  pub fn pick(
+    &mut self,
+    device: &Device,
+    encoder: &mut CommandEncoder,
+    size: (u32, u32),
+    pixel: (u32, u32),
+  ) {
+    self.picker.prepare(device, size);
+    {
+      let mut pass = self.picker.begin_pass(encoder, pixel);
+      pass.set_bind_group(0, &self.globals_bind_group, &[]);
+      if self.terrain.is_enabled() {
+        self.picker.bind(&mut pass, Entity::Terrain);
+        self.terrain.draw(&mut pass, false);
+      } else {
+        let stride = std::mem::size_of::<SceneInstance>() as wgpu::BufferAddress;
+        self.picker.bind(&mut pass, Entity::Ground);
+        pass.set_vertex_buffer(1, self.instance_buffer.slice(..stride));
+        self.plane.draw(&mut pass, 1);
+        self.picker.bind(&mut pass, Entity::Cube(0));
+        pass.set_vertex_buffer(1, self.instance_buffer.slice(stride..));
+        self.cube.draw(&mut pass, self.cube_count);
+        if self.crowd.is_enabled() {
+          self.picker.bind(&mut pass, Entity::Agent(0));
+          self.crowd.draw(&mut pass);
+        }
+      }
+      for (i, model) in self.models.iter().enumerate() {
+        self.picker.bind(&mut pass, Entity::Model(i as u32));
+        pass.set_vertex_buffer(1, model.instance_buffer.slice(..));
+        model.mesh.draw(&mut pass, 1);
+      }
+    }
+    self.picker.copy(device, encoder, pixel);
+  }
+
+  // Through the point `ndc` (-1..1, y up) of a window showing `tile`, for ray_cast()
+  pub fn camera_ray(&self, tile: &Tile, ndc: [f32; 2]) -> Ray {
+    Ray::from_screen(&self.camera.tile_view_proj(tile), ndc)
+  }
+
+  // The closest thing `ray` hits and how far along it, on the CPU. The same entities as
+  // pick() apart from the terrain, which isn't tested.
+  pub fn ray_cast(&self, ray: &Ray) -> Option<(Entity, f32)> {
+    let mut hits: Vec<(Entity, f32)> = Vec::new();
+    if !self.terrain.is_enabled() {
+      hits.extend(
+        self
+          .plane_shape
+          .intersect(ray, &math::IDENTITY)
+          .map(|d| (Entity::Ground, d)),
+      );
+      hits.extend(
+        self
+          .cube_transforms
+          .iter()
+          .enumerate()
+          .filter_map(|(i, model)| {
+            let distance = self.cube_shape.intersect(ray, model)?;
+            Some((Entity::Cube(i as u32), distance))
+          }),
+      );
+      if self.crowd.is_enabled() {
+        hits.extend(self.crowd.ray_cast(ray).map(|(i, d)| (Entity::Agent(i), d)));
+      }
+    }
+    hits.extend(self.models.iter().enumerate().filter_map(|(i, model)| {
+      let distance = model.shape.intersect(ray, &model.transform)?;
+      Some((Entity::Model(i as u32), distance))
+    }));
+    hits.into_iter().min_by(|a, b| a.1.total_cmp(&b.1))
+  }
+
+  // Lines drawn over this frame, on top of the gizmos
+  pub fn debug(&mut self) -> &mut DebugDraw {
+    &mut self.debug
+  }
+
+  // Starts the frame's debug lines over, with the gizmos when they're on. Once per frame
+  // before anything adds to debug().
+  pub fn update_debug(&mut self) {
+    self.debug.clear();
+    if !self.show_gizmos {
+      return;
+    }
+    self.debug.axes(&math::IDENTITY);
+    if !self.terrain.is_enabled() {
+      for model in &self.cube_transforms {
+        let bounds = self.cube_shape.bounds.transformed(model);
+        self.debug.aabb(&bounds, [1.0, 1.0, 0.2, 1.0]);
+      }
+    }
+    for model in &self.models {
+      let bounds = model.shape.bounds.transformed(&model.transform);
+      self.debug.aabb(&bounds, [0.2, 1.0, 1.0, 1.0]);
+      self.debug.axes(&model.transform);
+    }
+  }
+
+  // The debug lines over the finished hdr color, tested against its depth
+  pub fn render_debug(
+    &mut self,
+    device: &Device,
+    queue: &Queue,
+    encoder: &mut CommandEncoder,
+    view: &wgpu::TextureView,
+    depth_view: &wgpu::TextureView,
+  ) {
+    self.debug.render(
+      device,
+      queue,
+      encoder,
+      view,
+      depth_view,
+      &self.globals_bind_group,
+    );
+  }
+
+  // Starts reading back what pick() drew, after the submit
+  pub fn map_pick(&self) {
+    self.picker.map();
+  }
+
+  pub fn is_picking(&self) -> bool {
+    self.picker.is_in_flight()
+  }
+
+  // Some once the picked pixel is back, holding what was there if anything
+  pub fn pick_result(&mut self) -> Option<Option<Entity>> {
+    self.picker.try_finish()
+  }
+
+  // The skinned meshes and the PBR models, render() includes them. The opaque models go
+  // first, then the transparent
+  // ones from the farthest to the nearest, so each blends over what's behind it.
+  pub fn render_models<'a>(&'a self, pass: &mut RenderPass<'a>) {
+    self.render_models_with(
+      pass,
+      &self.globals_bind_group,
+      self.camera.eye(),
+      None,
+      true,
+    );
+  }
+
+  // Leaves out the `hidden` model, and the entities' the scene's camera doesn't see with
+  // `culled`
+  fn render_models_with<'a>(
+    &'a self,
+    pass: &mut RenderPass<'a>,
+    globals: &'a wgpu::BindGroup,
+    eye: Vec3,
+    hidden: Option<ModelId>,
+    culled: bool,
+  ) {
+    // with the scene's own shading, before the PBR pipeline takes over
+    pass.set_bind_group(0, globals, &[]);
+    pass.set_bind_group(1, self.shadow.bind_group(), &[]);
+    self.skinning.draw(pass);
+    if self.models.is_empty() {
+      return;
+    }
+    self.pbr.bind(pass);
+    pass.set_bind_group(0, globals, &[]);
+    pass.set_bind_group(1, self.shadow.bind_group(), &[]);
+    // by the centers of their bounds, good enough for meshes that don't overlap
+    let distance = |model: &SceneModel| {
+      let bounds = model.shape.bounds.transformed(&model.transform);
+      let center = math::scale(math::add(bounds.min, bounds.max), 0.5);
+      math::length(math::sub(center, eye))
+    };
+    let mut transparent = Vec::new();
+    for (i, model) in self.models.iter().enumerate() {
+      if Some(model.id) == hidden || (culled && !model.visible) {
+        continue;
+      }
+      if model.material.blend().is_transparent() {
+        transparent.push((distance(model), i));
+      } else {
+        self.draw_model(pass, i);
+      }
+    }
+    transparent.sort_by(|a, b| b.0.total_cmp(&a.0));
+    for (_, i) in transparent {
+      self.draw_model(pass, i);
+    }
+    if self.selected != hidden {
+      self.draw_outline(pass, globals);
+    }
+  }
+
+  // Around the selected model, over whatever was drawn so far. Needs a pass that clears
+  // the stencil and can write it.
+  fn draw_outline<'a>(&'a self, pass: &mut RenderPass<'a>, globals: &'a wgpu::BindGroup) {
+    let Some(model) = self
+      .selected
+      .and_then(|id| self.models.iter().find(|model| model.id == id))
+    else {
+      return;
+    };
+    let [mask, outline] = &self.outline_pipelines;
+    pass.set_stencil_reference(1);
+    pass.set_bind_group(0, globals, &[]);
+    pass.set_vertex_buffer(1, model.instance_buffer.slice(..));
+    pass.set_pipeline(mask);
+    model.mesh.draw(pass, 1);
+    pass.set_pipeline(outline);
+    model.mesh.draw(pass, 1);
+  }
+
+  // The `i`th model after PbrPipeline::bind(), `i` is also its place in the storage buffer
+  fn draw_model<'a>(&'a self, pass: &mut RenderPass<'a>, i: usize) {
+    let model = &self.models[i];
+    let draw = model_constants(model.transform, model.material.index());
+    if let Some(instances) = self.pbr.bind_model(pass, &model.material, &draw, i as u32) {
+      model.mesh.draw_instances(pass, instances);
+    }
+  }
+
+  // `culled` draws the cubes cull_objects() kept and the terrain chunks run_systems() kept
+  // instead of all of them
+  fn draw_meshes<'a>(&'a self, pass: &mut RenderPass<'a>, culled: bool) {
+    if self.terrain.is_enabled() {
+      self.terrain.draw(pass, culled);
+      return;
+    }
+    // offsetting the binding instead of the instance range, WebGL has no base instance
+    let stride = std::mem::size_of::<SceneInstance>() as wgpu::BufferAddress;
+    pass.set_vertex_buffer(1, self.instance_buffer.slice(..stride));
+    self.plane.draw(pass, 1);
+    if culled {
+      self.culling.draw(pass, &self.cube);
+    } else {
+      pass.set_vertex_buffer(1, self.instance_buffer.slice(stride..));
+      self.cube.draw(pass, self.cube_count);
+    }
+    if self.crowd.is_enabled() {
+      self.crowd.draw(pass);
+    }
+  }
+}
+
+// The PBR pipeline's per draw data of a model, its instance buffer has the same for the
+// other passes. `material` is its MaterialBinding::index().
+fn model_constants(transform: Mat4, material: u32) -> DrawConstants {
+  DrawConstants::new(transform, [1.0; 4], material)
+}
+
+// Orbits and moves `camera` for as long as `actions` are held, in CAMERA_ACTIONS' order
+pub fn steer_camera(camera: &mut OrbitCamera, input: &InputMap, actions: &[&str; 6], dt: f32) {
+  let [left, right, up, down, forward, back] = *actions;
+  // sticks turn as far as they're pushed
+  let axis = |positive, negative| input.value(positive) - input.value(negative);
+  let yaw = axis(right, left);
+  let pitch = axis(up, down);
+  camera.orbit(yaw * ORBIT_SPEED * dt, pitch * ORBIT_SPEED * dt);
+  camera.dolly(axis(back, forward) * DOLLY_SPEED * dt);
+}
+
+// Group 0 of the scene pipelines: a camera's globals and light clusters and the lighting
+fn create_globals_bind_group(
+  device: &Device,
+  layout: &wgpu::BindGroupLayout,
+  globals_buffer: &wgpu::Buffer,
+  lighting_buffer: &wgpu::Buffer,
+  clusters: &LightClusters,
+) -> wgpu::BindGroup {
+  device.create_bind_group(&wgpu::BindGroupDescriptor {
+    label: Some("Scene Globals Bind Group"),
+    layout,
+    entries: &[
+      wgpu::BindGroupEntry {
+        binding: 0,
+        resource: globals_buffer.as_entire_binding(),
+      },
+      wgpu::BindGroupEntry {
+        binding: 1,
+        resource: lighting_buffer.as_entire_binding(),
+      },
+      wgpu::BindGroupEntry {
+        binding: 2,
+        resource: clusters.lights_buffer().as_entire_binding(),
+      },
+      wgpu::BindGroupEntry {
+        binding: 3,
+        resource: clusters.params_buffer().as_entire_binding(),
+      },
+      wgpu::BindGroupEntry {
+        binding: 4,
+        resource: clusters.grid_buffer().as_entire_binding(),
+      },
+    ],
+  })
+}
@@ -0,0 +1,56 @@
+// Blinn-Phong shaded meshes lit by the sun (with its shadow map) and the point lights of
+// their cluster
+
+#include "lighting.wgsl"
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) light_position: vec4<f32>,
+    @location(3) color: vec4<f32>,
+    @location(4) material: vec2<f32>,
+    @location(5) peak: f32,
+};
+
+@vertex
+fn vs_main(vertex: VertexInput, instance: InstanceInput) -> VertexOutput {
+    let model = instance_model(instance);
+    let world = model * vec4<f32>(model_position(vertex, instance), 1.0);
+    var out: VertexOutput;
+    out.clip_position = globals.view_proj * world;
+    out.world_position = world.xyz;
+    // fine as long as the models are only uniformly scaled
+    out.world_normal = (model * vec4<f32>(vertex.normal, 0.0)).xyz;
+    out.light_position = globals.light_view_proj * world;
+    out.color = instance.color;
+    out.material = instance.material.xy;
+    out.peak = instance.material.z;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let normal = normalize(in.world_normal);
+    var base = in.color.rgb;
+    if in.peak > 0.0 {
+        base = terrain_color(in.world_position, normal, in.peak);
+    }
+    let color = shade_blinn_phong(in.world_position, normal, base, in.material, in.light_position, 1.0);
+    return vec4<f32>(color, in.color.a);
+}
+
+// Depth-only pass from the light, no fragment shader
+@vertex
+fn vs_shadow(vertex: VertexInput, instance: InstanceInput) -> @builtin(position) vec4<f32> {
+    let model = instance_model(instance);
+    return globals.light_view_proj * model * vec4<f32>(model_position(vertex, instance), 1.0);
+}
+
+// Depth-only pass from the camera for the Hi-Z pyramid (hiz.rs), unjittered like the
+// culling that reads it
+@vertex
+fn vs_prepass(vertex: VertexInput, instance: InstanceInput) -> @builtin(position) vec4<f32> {
+    let model = instance_model(instance);
+    return globals.unjittered_view_proj * model * vec4<f32>(model_position(vertex, instance), 1.0);
+}
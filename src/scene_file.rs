@@ -0,0 +1,273 @@
+// Snapshots of the scene in RON: the camera, the lighting and the world's entities as a list
+// of nodes, each with its Transform, its parent's index in the list and what it is. Meshes
+// aren't written out, a model node says how to make its mesh again with a MeshRef, so only
+// models spawned with one are saved with their mesh. Models added outside the world (the
+// orrery, the boxes) aren't part of it. Home saves scene.ron, End and --scene load one.
+
+use std::path::Path;
+
+use bevy_ecs::component::Component;
+use serde::{Deserialize, Serialize};
+use wgpu::Device;
+
+use crate::{
+  camera::OrbitCamera,
+  ecs::{self, Hidden, Light, Material, MeshHandle, Parent, Spin, Transform},
+  lighting::{Lighting, PointLight},
+  mesh::{Mesh, MeshError},
+  pbr::{PbrMaterial, PbrTextures},
+  scene::Scene,
+};
+
+pub const SCENE_PATH: &str = "scene.ron";
+
+#[derive(Debug)]
+pub enum SceneFileError {
+  Io(std::io::Error),
+  Serialize(ron::Error),
+  Parse(ron::error::SpannedError),
+  Mesh(MeshError),
+  // an .obj without as many models as the index asks for
+  MissingMesh(String, usize),
+  // parents have to come before their children
+  InvalidParent { node: usize, parent: usize },
+}
+
+impl std::fmt::Display for SceneFileError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      SceneFileError::Io(e) => write!(f, "{}", e),
+      SceneFileError::Serialize(e) => write!(f, "couldn't serialize the scene: {}", e),
+      SceneFileError::Parse(e) => write!(f, "couldn't parse the scene: {}", e),
+      SceneFileError::Mesh(e) => write!(f, "couldn't load a mesh of the scene: {}", e),
+      SceneFileError::MissingMesh(path, index) => write!(f, "{} has no model {}", path, index),
+      SceneFileError::InvalidParent { node, parent } => {
+        write!(
+          f,
+          "node {}'s parent {} doesn't come before it",
+          node, parent
+        )
+      }
+    }
+  }
+}
+
+impl std::error::Error for SceneFileError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      SceneFileError::Io(e) => Some(e),
+      SceneFileError::Serialize(e) => Some(e),
+      SceneFileError::Parse(e) => Some(e),
+      SceneFileError::Mesh(e) => Some(e),
+      _ => None,
+    }
+  }
+}
+
+// How a model's mesh was made, kept on its entity so the scene can be saved
+#[derive(Component, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MeshRef {
+  // Mesh::cube() and Mesh::plane() of that half extent
+  Cube(f32),
+  Plane(f32),
+  Capsule {
+    radius: f32,
+    height: f32,
+    sides: u32,
+  },
+  // one of the models in an .obj file, processed
+  Obj {
+    path: String,
+    index: usize,
+  },
+}
+
+impl MeshRef {
+  pub fn load(&self) -> Result<Mesh, SceneFileError> {
+    Ok(match self {
+      MeshRef::Cube(half_extent) => Mesh::cube(*half_extent),
+      MeshRef::Plane(half_extent) => Mesh::plane(*half_extent),
+      MeshRef::Capsule {
+        radius,
+        height,
+        sides,
+      } => Mesh::capsule(*radius, *height, *sides),
+      MeshRef::Obj { path, index } => {
+        let mut meshes = Mesh::import_obj(path).map_err(SceneFileError::Mesh)?;
+        if *index >= meshes.len() {
+          return Err(SceneFileError::MissingMesh(path.clone(), *index));
+        }
+        let mut mesh = meshes.swap_remove(*index);
+        mesh.process();
+        mesh
+      }
+    })
+  }
+}
+
+// An entity of the world, with what it was spawned with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Node {
+  // index of the parent node, before this one
+  #[serde(default)]
+  pub parent: Option<usize>,
+  pub transform: Transform,
+  #[serde(default)]
+  pub mesh: Option<MeshRef>,
+  // of the mesh, the textures are left out
+  #[serde(default)]
+  pub material: Option<PbrMaterial>,
+  #[serde(default)]
+  pub light: Option<PointLight>,
+  // radians per second, see ecs::Spin
+  #[serde(default)]
+  pub spin: Option<f32>,
+  // see ecs::Hidden
+  #[serde(default)]
+  pub hidden: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SceneFile {
+  pub camera: OrbitCamera,
+  pub lighting: Lighting,
+  pub nodes: Vec<Node>,
+}
+
+impl SceneFile {
+  // What `scene` looks like now
+  pub fn capture(scene: &Scene) -> Self {
+    let world = scene.world();
+    // parents first, by how deep they are
+    let depth = |mut entity: ecs::Entity| {
+      let mut depth = 0;
+      while let Some(parent) = world.get::<Parent>(entity) {
+        entity = parent.0;
+        depth += 1;
+      }
+      depth
+    };
+    let mut entities: Vec<(usize, ecs::Entity)> = world
+      .iter_entities()
+      .filter(|entity| entity.contains::<Transform>() && !entity.contains::<ecs::Camera>())
+      .map(|entity| (depth(entity.id()), entity.id()))
+      .collect();
+    entities.sort_by_key(|&(depth, _)| depth);
+    let index = |entity: ecs::Entity| entities.iter().position(|&(_, e)| e == entity);
+    let nodes = entities
+      .iter()
+      .map(|&(_, id)| {
+        let entity = world.entity(id);
+        let mesh = entity.get::<MeshRef>().cloned();
+        if mesh.is_none() && entity.contains::<MeshHandle>() {
+          log::warn!("a model without a MeshRef is saved without its mesh");
+        }
+        Node {
+          parent: entity.get::<Parent>().and_then(|parent| index(parent.0)),
+          transform: *entity.get::<Transform>().unwrap(),
+          material: mesh
+            .as_ref()
+            .and(entity.get::<Material>())
+            .map(|material| material.0),
+          mesh,
+          light: entity.get::<Light>().map(|light| light.0),
+          spin: entity.get::<Spin>().map(|spin| spin.0),
+          hidden: entity.contains::<Hidden>(),
+        }
+      })
+      .collect();
+    Self {
+      camera: *scene.camera(),
+      lighting: scene.lighting().clone(),
+      nodes,
+    }
+  }
+
+  // Puts the camera, the lighting and the nodes in place of the scene's, despawning the
+  // entities there were. Returns the new entities in the order of the nodes. Nothing changes
+  // when a mesh can't be loaded.
+  pub fn apply(
+    &self,
+    device: &Device,
+    scene: &mut Scene,
+  ) -> Result<Vec<ecs::Entity>, SceneFileError> {
+    // the same mesh is only loaded once
+    let mut meshes: Vec<(&MeshRef, Mesh)> = Vec::new();
+    for (i, node) in self.nodes.iter().enumerate() {
+      if let Some(parent) = node.parent.filter(|&parent| parent >= i) {
+        return Err(SceneFileError::InvalidParent { node: i, parent });
+      }
+      if let Some(mesh) = &node.mesh {
+        if !meshes.iter().any(|(loaded, _)| *loaded == mesh) {
+          meshes.push((mesh, mesh.load()?));
+        }
+      }
+    }
+
+    let world = scene.world();
+    let roots: Vec<ecs::Entity> = world
+      .iter_entities()
+      .filter(|entity| entity.contains::<Transform>() && !entity.contains::<Parent>())
+      .filter(|entity| !entity.contains::<ecs::Camera>())
+      .map(|entity| entity.id())
+      .collect();
+    for root in roots {
+      scene.despawn(root);
+    }
+    *scene.camera_mut() = self.camera;
+    *scene.lighting_mut() = self.lighting.clone();
+
+    let mut entities: Vec<ecs::Entity> = Vec::with_capacity(self.nodes.len());
+    for node in &self.nodes {
+      let parent = node.parent.map(|parent| entities[parent]);
+      let entity = match (&node.mesh, node.light) {
+        (Some(mesh_ref), _) => {
+          let (_, mesh) = meshes
+            .iter()
+            .find(|(loaded, _)| *loaded == mesh_ref)
+            .unwrap();
+          let material = node.material.unwrap_or_default();
+          let textures = PbrTextures::default();
+          let entity = scene.spawn_model(device, mesh, &material, textures, node.transform, parent);
+          scene
+            .world_mut()
+            .entity_mut(entity)
+            .insert(mesh_ref.clone());
+          if let Some(light) = node.light {
+            scene.world_mut().entity_mut(entity).insert(Light(light));
+          }
+          entity
+        }
+        (None, Some(light)) => scene.spawn_light(light, node.transform, parent),
+        (None, None) => {
+          let mut entity = scene
+            .world_mut()
+            .spawn((node.transform, ecs::GlobalTransform::default()));
+          if let Some(parent) = parent {
+            entity.insert(Parent(parent));
+          }
+          entity.id()
+        }
+      };
+      if let Some(spin) = node.spin {
+        scene.world_mut().entity_mut(entity).insert(Spin(spin));
+      }
+      if node.hidden {
+        scene.world_mut().entity_mut(entity).insert(Hidden);
+      }
+      entities.push(entity);
+    }
+    Ok(entities)
+  }
+
+  pub fn load(path: impl AsRef<Path>) -> Result<Self, SceneFileError> {
+    let text = std::fs::read_to_string(path).map_err(SceneFileError::Io)?;
+    ron::from_str(&text).map_err(SceneFileError::Parse)
+  }
+
+  pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SceneFileError> {
+    let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+      .map_err(SceneFileError::Serialize)?;
+    std::fs::write(path, text).map_err(SceneFileError::Io)
+  }
+}
@@ -0,0 +1,203 @@
+use std::{
+  path::{Path, PathBuf},
+  sync::{Arc, Mutex},
+};
+
+use image::ImageEncoder;
+use wgpu::{CommandEncoder, Device, TextureFormat};
+
+use crate::{fxaa::Fxaa, hdr::HdrPipeline};
+
+// Called with the PNG bytes once the frame has been read back
+pub type ScreenshotReply = Box<dyn FnOnce(Result<Vec<u8>, ScreenshotError>)>;
+
+#[derive(Debug)]
+pub enum ScreenshotError {
+  Map(wgpu::BufferAsyncError),
+  Encode(image::ImageError),
+  Unavailable(&'static str),
+}
+
+impl std::fmt::Display for ScreenshotError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ScreenshotError::Map(e) => write!(f, "couldn't read the frame back: {}", e),
+      ScreenshotError::Encode(e) => write!(f, "couldn't encode the screenshot: {}", e),
+      ScreenshotError::Unavailable(reason) => write!(f, "{}", reason),
+    }
+  }
+}
+
+impl std::error::Error for ScreenshotError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      ScreenshotError::Map(e) => Some(e),
+      ScreenshotError::Encode(e) => Some(e),
+      ScreenshotError::Unavailable(_) => None,
+    }
+  }
+}
+
+// A tonemapped copy of the hdr target on its way back to the CPU. The surface texture
+// can't be copied from, so the capture tonemaps into its own texture of the same format.
+pub struct Screenshot {
+  buffer: wgpu::Buffer,
+  width: u32,
+  height: u32,
+  // rows in the buffer are padded to COPY_BYTES_PER_ROW_ALIGNMENT
+  padded_row: u32,
+  // bgra surfaces need their channels swapped for the png
+  bgra: bool,
+  // filled in by the map_async callback
+  mapped: Arc<Mutex<Option<Result<(), wgpu::BufferAsyncError>>>>,
+}
+
+impl Screenshot {
+  // Records the tonemap + copy, submit the encoder and then call map(). With `fxaa` the
+  // frame's tonemapped input to it gets anti-aliased into the copy instead.
+  pub fn record(
+    device: &Device,
+    encoder: &mut CommandEncoder,
+    hdr: &HdrPipeline,
+    fxaa: Option<&Fxaa>,
+    format: TextureFormat,
+  ) -> Self {
+    let (width, height) = hdr.size();
+    let size = wgpu::Extent3d {
+      width,
+      height,
+      depth_or_array_layers: 1,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+      label: Some("Screenshot Texture"),
+      size,
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format,
+      usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+      view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    match fxaa {
+      Some(fxaa) => fxaa.render(encoder, &view),
+      None => hdr.tonemap(encoder, &view),
+    }
+
+    let padded_row = (width * 4).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Screenshot Buffer"),
+      size: padded_row as u64 * height as u64,
+      usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+      mapped_at_creation: false,
+    });
+    encoder.copy_texture_to_buffer(
+      wgpu::ImageCopyTexture {
+        texture: &texture,
+        mip_level: 0,
+        origin: wgpu::Origin3d::ZERO,
+        aspect: wgpu::TextureAspect::All,
+      },
+      wgpu::ImageCopyBuffer {
+        buffer: &buffer,
+        layout: wgpu::ImageDataLayout {
+          offset: 0,
+          bytes_per_row: std::num::NonZeroU32::new(padded_row),
+          rows_per_image: None,
+        },
+      },
+      size,
+    );
+
+    Self {
+      buffer,
+      width,
+      height,
+      padded_row,
+      bgra: matches!(
+        format,
+        TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb
+      ),
+      mapped: Arc::new(Mutex::new(None)),
+    }
+  }
+
+  // Call after queue.submit
+  pub fn map(&self) {
+    let mapped = self.mapped.clone();
+    self
+      .buffer
+      .slice(..)
+      .map_async(wgpu::MapMode::Read, move |result| {
+        *mapped.lock().unwrap() = Some(result);
+      });
+  }
+
+  // The encoded PNG once the readback has finished, None while it's still in flight
+  pub fn try_finish(&self) -> Option<Result<Vec<u8>, ScreenshotError>> {
+    let result = self.mapped.lock().unwrap().take()?;
+    Some(
+      result
+        .map_err(ScreenshotError::Map)
+        .and_then(|()| self.encode()),
+    )
+  }
+
+  fn encode(&self) -> Result<Vec<u8>, ScreenshotError> {
+    let mut pixels = Vec::with_capacity((self.width * self.height * 4) as usize);
+    {
+      let data = self.buffer.slice(..).get_mapped_range();
+      for row in data.chunks_exact(self.padded_row as usize) {
+        pixels.extend_from_slice(&row[..(self.width * 4) as usize]);
+      }
+    }
+    self.buffer.unmap();
+    if self.bgra {
+      for pixel in pixels.chunks_exact_mut(4) {
+        pixel.swap(0, 2);
+      }
+    }
+
+    let mut png = Vec::new();
+    image::codecs::png::PngEncoder::new(&mut png)
+      .write_image(&pixels, self.width, self.height, image::ColorType::Rgba8)
+      .map_err(ScreenshotError::Encode)?;
+    Ok(png)
+  }
+}
+
+// Reply for native captures, writes the next free screenshot-<n>.png in the working directory
+pub fn save_to_file() -> ScreenshotReply {
+  Box::new(|result| {
+    let path = (0..)
+      .map(|n| format!("screenshot-{}.png", n))
+      .find(|path| !std::path::Path::new(path).exists())
+      .unwrap();
+    write_png(Path::new(&path), result);
+  })
+}
+
+// Reply writing to `path`, then calling `done` with whether it was written
+pub fn save_to(path: PathBuf, done: impl FnOnce(bool) + 'static) -> ScreenshotReply {
+  Box::new(move |result| done(write_png(&path, result)))
+}
+
+fn write_png(path: &Path, result: Result<Vec<u8>, ScreenshotError>) -> bool {
+  let png = match result {
+    Ok(png) => png,
+    Err(e) => {
+      log::warn!("{}", e);
+      return false;
+    }
+  };
+  match std::fs::write(path, png) {
+    Ok(()) => {
+      log::info!("saved {}", path.display());
+      true
+    }
+    Err(e) => {
+      log::warn!("couldn't write {}: {}", path.display(), e);
+      false
+    }
+  }
+}
@@ -0,0 +1,373 @@
+// Demos written in Rhai (https://rhai.rs) instead of Rust, run with `--script demo.rhai`.
+// The file is reloaded whenever it changes, so a demo can be edited while it runs.
+//
+// A script defines any of these, `this` is a map that keeps its state between calls:
+//
+//   fn init() { this.t = 0.0; this.cube = create("cube", 0.0, 1.0, 0.0); }
+//   fn update(dt) { this.t += dt; move_to(this.cube, this.t.sin(), 1.0, 0.0); }
+//   fn on_action(name) { if name == "toggle_boids" { set_param("boids_cohesion", 0.1); } }
+//   fn on_pick(kind, id) { if kind == "entity" { destroy(id); } }
+//
+// and can call
+//
+//   create(shape, x, y, z), create(shape, x, y, z, r, g, b)
+//                         adds a "cube", "capsule" or "plane" in the scene, returns its id
+//   move_to(id, x, y, z), set_scale(id, scale), destroy(id)
+//   set_param(name, value), param(name)   the params in params.rs
+//   time()                seconds of animation time, see Clock
+//
+// Numbers passed to these have to be floats, `1.0` rather than `1`. update() gets the
+// animation time's step, so pausing the animation pauses the demo. Reloading destroys what
+// the last version created and calls init() again.
+
+use std::{
+  cell::{Cell, RefCell},
+  collections::HashMap,
+  path::Path,
+  rc::Rc,
+};
+
+use rhai::{CallFnOptions, Dynamic, Engine, FuncArgs, AST};
+use wgpu::{Device, Queue};
+
+use crate::{
+  asset_manager::{Asset, AssetManager, Handle},
+  clock::Timeline,
+  math,
+  mesh::Mesh,
+  params::{Params, PARAMS},
+  pbr::{PbrMaterial, PbrTextures},
+  picking::Entity,
+  scene::{ModelId, Scene},
+};
+
+pub struct ScriptAsset(pub String);
+
+impl Asset for ScriptAsset {
+  fn load(path: &Path) -> Result<Self, String> {
+    std::fs::read_to_string(path)
+      .map(Self)
+      .map_err(|e| e.to_string())
+  }
+}
+
+// What the script asked for, done after it returns
+enum Command {
+  Spawn {
+    id: i64,
+    shape: String,
+    position: [f32; 3],
+    color: [f32; 3],
+  },
+  MoveTo(i64, [f32; 3]),
+  SetScale(i64, f32),
+  Destroy(i64),
+  SetParam(String, f64),
+}
+
+// Shared with the functions registered on the engine
+#[derive(Default)]
+struct Shared {
+  commands: RefCell<Vec<Command>>,
+  // the params' values before the script runs
+  params: RefCell<HashMap<&'static str, f64>>,
+  time: Cell<f64>,
+  next_id: Cell<i64>,
+}
+
+enum ScriptEvent {
+  Action(&'static str),
+  Picked(Entity),
+}
+
+struct ScriptEntity {
+  model: ModelId,
+  position: [f32; 3],
+  scale: f32,
+}
+
+pub struct ScriptHost {
+  engine: Engine,
+  assets: AssetManager,
+  source: Handle<ScriptAsset>,
+  // None until the script first compiles
+  ast: Option<AST>,
+  this: Dynamic,
+  shared: Rc<Shared>,
+  entities: HashMap<i64, ScriptEntity>,
+  events: Vec<ScriptEvent>,
+}
+
+impl ScriptHost {
+  pub fn new(path: impl AsRef<Path>) -> Self {
+    let shared = Rc::new(Shared::default());
+    let mut engine = Engine::new();
+    engine.on_print(|text| log::info!("script: {}", text));
+    engine.on_debug(|text, _, position| log::debug!("script {}: {}", position, text));
+
+    let s = shared.clone();
+    let create = move |shape: &str, x: f64, y: f64, z: f64, color: [f64; 3]| -> i64 {
+      let id = s.next_id.get();
+      s.next_id.set(id + 1);
+      s.commands.borrow_mut().push(Command::Spawn {
+        id,
+        shape: shape.to_string(),
+        position: [x as f32, y as f32, z as f32],
+        color: color.map(|c| c as f32),
+      });
+      id
+    };
+    let create = Rc::new(create);
+    let white = create.clone();
+    engine.register_fn("create", move |shape: &str, x: f64, y: f64, z: f64| {
+      white(shape, x, y, z, [1.0; 3])
+    });
+    engine.register_fn(
+      "create",
+      move |shape: &str, x: f64, y: f64, z: f64, r: f64, g: f64, b: f64| {
+        create(shape, x, y, z, [r, g, b])
+      },
+    );
+    let s = shared.clone();
+    engine.register_fn("move_to", move |id: i64, x: f64, y: f64, z: f64| {
+      let position = [x as f32, y as f32, z as f32];
+      s.commands.borrow_mut().push(Command::MoveTo(id, position));
+    });
+    let s = shared.clone();
+    engine.register_fn("set_scale", move |id: i64, scale: f64| {
+      s.commands
+        .borrow_mut()
+        .push(Command::SetScale(id, scale as f32));
+    });
+    let s = shared.clone();
+    engine.register_fn("destroy", move |id: i64| {
+      s.commands.borrow_mut().push(Command::Destroy(id));
+    });
+    let s = shared.clone();
+    engine.register_fn("set_param", move |name: &str, value: f64| {
+      let command = Command::SetParam(name.to_string(), value);
+      s.commands.borrow_mut().push(command);
+    });
+    let s = shared.clone();
+    engine.register_fn("param", move |name: &str| -> f64 {
+      s.params.borrow().get(name).copied().unwrap_or(0.0)
+    });
+    let s = shared.clone();
+    engine.register_fn("time", move || s.time.get());
+
+    let mut assets = AssetManager::new();
+    let source = assets.load(path);
+    Self {
+      engine,
+      assets,
+      source,
+      ast: None,
+      this: Dynamic::from_map(Default::default()),
+      shared,
+      entities: HashMap::new(),
+      events: Vec::new(),
+    }
+  }
+
+  // `action` from keymap.rs happened, on_action() gets it with the next update()
+  pub fn action(&mut self, action: &'static str) {
+    self.events.push(ScriptEvent::Action(action));
+  }
+
+  pub fn picked(&mut self, entity: Entity) {
+    self.events.push(ScriptEvent::Picked(entity));
+  }
+
+  // Whether the script moves things every frame
+  pub fn is_animating(&self) -> bool {
+    self.has_fn("update")
+  }
+
+  // Call once a frame: reloads the script when it changed, then runs its event handlers
+  // and update() and applies what they asked for. Returns whether it did anything.
+  pub fn update(
+    &mut self,
+    scene: &mut Scene,
+    device: &Device,
+    queue: &Queue,
+    params: &mut Params,
+    time: &Timeline,
+  ) -> bool {
+    *self.shared.params.borrow_mut() = PARAMS
+      .iter()
+      .filter_map(|spec| Some((spec.name, params.get(spec.name)?)))
+      .collect();
+    self.shared.time.set(time.elapsed());
+
+    let reloaded = !self.assets.update().is_empty();
+    if reloaded {
+      self.reload(scene);
+    }
+    for event in std::mem::take(&mut self.events) {
+      match event {
+        ScriptEvent::Action(name) => self.call("on_action", (name.to_string(),)),
+        ScriptEvent::Picked(entity) => {
+          let (kind, id) = self.describe(scene, entity);
+          self.call("on_pick", (kind.to_string(), id));
+        }
+      }
+    }
+    if !time.is_paused() {
+      self.call("update", (time.dt() as f64,));
+    }
+    let commands = std::mem::take(&mut *self.shared.commands.borrow_mut());
+    let changed = reloaded || !commands.is_empty();
+    for command in commands {
+      self.apply(command, scene, device, queue, params);
+    }
+    changed
+  }
+
+  fn reload(&mut self, scene: &mut Scene) {
+    let Some(source) = self.assets.get(&self.source) else {
+      return;
+    };
+    let path = self.assets.path(&self.source).display().to_string();
+    let ast = match self.engine.compile(&source.0) {
+      Ok(ast) => ast,
+      Err(e) => {
+        log::warn!("{}: {}, keeping the last version that compiled", path, e);
+        return;
+      }
+    };
+    for (_, entity) in self.entities.drain() {
+      scene.remove_model(entity.model);
+    }
+    self.shared.commands.borrow_mut().clear();
+    self.this = Dynamic::from_map(Default::default());
+    // the top level statements, before any of its functions
+    if let Err(e) = self.engine.run_ast(&ast) {
+      log::warn!("{}: {}", path, e);
+    }
+    self.ast = Some(ast);
+    self.call("init", ());
+  }
+
+  fn has_fn(&self, name: &str) -> bool {
+    self
+      .ast
+      .as_ref()
+      .is_some_and(|ast| ast.iter_functions().any(|f| f.name == name))
+  }
+
+  // Calls `name` with `this` bound to the script's state, unless the script doesn't have it
+  fn call(&mut self, name: &str, args: impl FuncArgs) {
+    let Some(ast) = &self.ast else {
+      return;
+    };
+    if !self.has_fn(name) {
+      return;
+    }
+    let options = CallFnOptions::new()
+      .eval_ast(false)
+      .bind_this_ptr(&mut self.this);
+    let mut scope = rhai::Scope::new();
+    let result = self
+      .engine
+      .call_fn_with_options::<Dynamic>(options, &mut scope, ast, name, args);
+    if let Err(e) = result {
+      log::warn!("script {}(): {}", name, e);
+    }
+  }
+
+  // How on_pick() sees `entity`, the script's own ones by the id create() returned
+  fn describe(&self, scene: &Scene, entity: Entity) -> (&'static str, i64) {
+    match entity {
+      Entity::Ground => ("ground", 0),
+      Entity::Cube(i) => ("cube", i as i64),
+      Entity::Agent(i) => ("agent", i as i64),
+      Entity::Terrain => ("terrain", 0),
+      Entity::Model(i) => {
+        let model = scene.model_id(i as usize);
+        let created = self
+          .entities
+          .iter()
+          .find(|(_, entity)| Some(entity.model) == model);
+        match created {
+          Some((&id, _)) => ("entity", id),
+          None => ("model", i as i64),
+        }
+      }
+    }
+  }
+
+  fn apply(
+    &mut self,
+    command: Command,
+    scene: &mut Scene,
+    device: &Device,
+    queue: &Queue,
+    params: &mut Params,
+  ) {
+    match command {
+      Command::Spawn {
+        id,
+        shape,
+        position,
+        color,
+      } => {
+        let mesh = match shape.as_str() {
+          "cube" => Mesh::cube(0.5),
+          "capsule" => Mesh::capsule(0.25, 1.0, 16),
+          "plane" => Mesh::plane(0.5),
+          _ => {
+            log::warn!(
+              "script: can't create a {}, only a cube, capsule or plane",
+              shape
+            );
+            return;
+          }
+        };
+        let material = PbrMaterial {
+          base_color: [color[0], color[1], color[2], 1.0],
+          metallic: 0.0,
+          roughness: 0.5,
+          ..Default::default()
+        };
+        let transform = math::translation(position);
+        let model = scene.add_model(device, &mesh, &material, PbrTextures::default(), transform);
+        let entity = ScriptEntity {
+          model,
+          position,
+          scale: 1.0,
+        };
+        self.entities.insert(id, entity);
+      }
+      Command::MoveTo(id, position) => {
+        if let Some(entity) = self.entities.get_mut(&id) {
+          entity.position = position;
+          Self::place(scene, queue, entity);
+        }
+      }
+      Command::SetScale(id, scale) => {
+        if let Some(entity) = self.entities.get_mut(&id) {
+          entity.scale = scale;
+          Self::place(scene, queue, entity);
+        }
+      }
+      Command::Destroy(id) => {
+        if let Some(entity) = self.entities.remove(&id) {
+          scene.remove_model(entity.model);
+        }
+      }
+      Command::SetParam(name, value) => {
+        if let Err(e) = params.set(&name, value) {
+          log::warn!("script: {}", e);
+        }
+      }
+    }
+  }
+
+  fn place(scene: &mut Scene, queue: &Queue, entity: &ScriptEntity) {
+    let transform = math::mul_mat4(
+      &math::translation(entity.position),
+      &math::scaling([entity.scale; 3]),
+    );
+    scene.set_model_transform(queue, entity.model, transform);
+  }
+}
@@ -0,0 +1,120 @@
+// A monitor in the scene showing what a second camera sees, J toggles it. The camera draws
+// the scene into a RenderTarget before the main pass, then the monitor's material samples it
+// as its albedo like any other texture. Its own view leaves the monitor out: a pass can't
+// draw into the texture it samples.
+
+use std::sync::Arc;
+
+use wgpu::{CommandEncoder, Device};
+
+use crate::{
+  camera::{OrbitCamera, Tile},
+  hdr::HDR_FORMAT,
+  math,
+  mesh::Mesh,
+  pbr::{PbrMaterial, PbrTextures},
+  render_target::{RenderTarget, ResizePolicy},
+  scene::{ModelId, Scene, SceneView},
+  uploader::Upload,
+};
+
+// The screen's pixels, 16:9 like the monitor
+const SCREEN: ResizePolicy = ResizePolicy::Fixed {
+  width: 256,
+  height: 144,
+};
+// where the monitor hangs, facing +Z, and its half width
+const MONITOR_POSITION: math::Vec3 = [0.0, 2.5, -4.0];
+const MONITOR_HALF_WIDTH: f32 = 0.8;
+// radians the camera sweeps to either side of its yaw, and how fast
+const SWEEP: f32 = 0.8;
+const SWEEP_SPEED: f64 = 0.4;
+
+pub struct SecurityCamera {
+  target: RenderTarget,
+  view: SceneView,
+  camera: OrbitCamera,
+  // the yaw it sweeps around
+  yaw: f32,
+  monitor: ModelId,
+}
+
+impl SecurityCamera {
+  // Adds the monitor to `scene`, remove() takes it out again
+  pub fn new(device: &Device, scene: &mut Scene) -> Self {
+    let target = RenderTarget::new(
+      device,
+      "Security Camera",
+      HDR_FORMAT,
+      scene.sample_count(),
+      SCREEN,
+      (1, 1),
+    );
+    // the plane faces +Y with its -Z edge at the top of the texture, standing it up turns
+    // that edge up
+    let half_height = MONITOR_HALF_WIDTH / target.aspect();
+    let transform = math::mul_mat4(
+      &math::translation(MONITOR_POSITION),
+      &math::mul_mat4(
+        &math::scaling([MONITOR_HALF_WIDTH, half_height, 1.0]),
+        &math::rotation_x(std::f32::consts::FRAC_PI_2),
+      ),
+    );
+    let material = PbrMaterial {
+      metallic: 0.0,
+      roughness: 0.9,
+      ..Default::default()
+    };
+    // a view of its own for the material to keep, the fixed size target is never made again
+    let view = target
+      .texture()
+      .create_view(&wgpu::TextureViewDescriptor::default());
+    let textures = PbrTextures {
+      albedo: Some(Arc::new(view)),
+      ..Default::default()
+    };
+    let monitor = scene.add_model(device, &Mesh::plane(1.0), &material, textures, transform);
+    let mut view = scene.create_view(device);
+    view.hide_model(Some(monitor));
+    // from the other side of the scene than the default camera, a bit higher
+    let camera = OrbitCamera {
+      yaw: std::f32::consts::PI + 0.6,
+      pitch: 0.6,
+      ..*scene.camera()
+    };
+    Self {
+      target,
+      view,
+      camera,
+      yaw: camera.yaw,
+      monitor,
+    }
+  }
+
+  pub fn remove(self, scene: &mut Scene) {
+    scene.remove_model(self.monitor);
+  }
+
+  pub fn target(&self) -> &RenderTarget {
+    &self.target
+  }
+
+  pub fn camera(&self) -> &OrbitCamera {
+    &self.camera
+  }
+
+  // Sweeps the camera and uploads it, after Scene::update()
+  pub fn update(&mut self, scene: &Scene, upload: &mut Upload, time: f64) {
+    self.camera.yaw = self.yaw + SWEEP * (time * SWEEP_SPEED).sin() as f32;
+    let tile = Tile::whole(self.target.aspect());
+    scene.update_view(upload, &mut self.view, &self.camera, &tile);
+  }
+
+  // Draws the scene into the target, before the passes drawing the monitor. Needs the
+  // shadow map rendered already.
+  pub fn render(&self, scene: &Scene, encoder: &mut CommandEncoder, clear: wgpu::Color) {
+    scene.cull_view_lights(encoder, &self.view);
+    let mut pass = self.target.begin_pass(encoder, clear);
+    scene.render_view(&mut pass, &self.view);
+  }
+}
@@ -0,0 +1,196 @@
+// Shaders read from files, so ones from OpenGL and Vulkan projects can be used as they are.
+// The extension says what's in the file:
+//
+//   .wgsl                 WGSL, like the built in shaders
+//   .vert, .frag, .comp   GLSL 440 or newer, one stage per file, entry point "main"
+//                         (also as .vert.glsl and such)
+//   .spv                  SPIR-V compiled ahead of time, e.g. by glslc or glslangValidator
+//
+// GLSL and SPIR-V go through naga's front ends here rather than in wgpu, which would panic
+// on a file it can't parse, and come out as the same kind of module as WGSL, so they work
+// on every backend and are validated the same way.
+
+use std::{
+  borrow::Cow,
+  path::{Path, PathBuf},
+};
+
+use wgpu::{Device, ShaderModule};
+
+// First word of every SPIR-V module
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShaderFile {
+  Wgsl(String),
+  Glsl {
+    source: String,
+    stage: naga::ShaderStage,
+  },
+  SpirV(Vec<u32>),
+}
+
+#[derive(Debug)]
+pub enum ShaderFileError {
+  Io(PathBuf, std::io::Error),
+  UnknownExtension(PathBuf),
+  NotUtf8(PathBuf),
+  // not a whole number of words or without the magic number
+  NotSpirV(PathBuf),
+  // naga's front end couldn't make sense of it, one message per problem
+  Parse(Vec<String>),
+}
+
+impl std::fmt::Display for ShaderFileError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ShaderFileError::Io(path, e) => write!(f, "couldn't read {}: {}", path.display(), e),
+      ShaderFileError::UnknownExtension(path) => write!(
+        f,
+        "{}: expected a .wgsl, .vert, .frag, .comp or .spv file",
+        path.display()
+      ),
+      ShaderFileError::NotUtf8(path) => write!(f, "{} isn't UTF-8 text", path.display()),
+      ShaderFileError::NotSpirV(path) => write!(f, "{} isn't SPIR-V", path.display()),
+      ShaderFileError::Parse(errors) => write!(f, "{}", errors.join("\n")),
+    }
+  }
+}
+
+impl std::error::Error for ShaderFileError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      ShaderFileError::Io(_, e) => Some(e),
+      _ => None,
+    }
+  }
+}
+
+// What a file holds going by its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShaderFormat {
+  Wgsl,
+  Glsl(naga::ShaderStage),
+  SpirV,
+}
+
+impl ShaderFormat {
+  // None when it's not a shader
+  pub fn from_path(path: &Path) -> Option<Self> {
+    let extension = |path: &Path| path.extension()?.to_str().map(str::to_ascii_lowercase);
+    let mut ext = extension(path)?;
+    // shader.vert.glsl says the stage before the .glsl
+    if ext == "glsl" {
+      ext = extension(Path::new(path.file_stem()?))?;
+    }
+    match ext.as_str() {
+      "wgsl" => Some(ShaderFormat::Wgsl),
+      "vert" => Some(ShaderFormat::Glsl(naga::ShaderStage::Vertex)),
+      "frag" => Some(ShaderFormat::Glsl(naga::ShaderStage::Fragment)),
+      "comp" => Some(ShaderFormat::Glsl(naga::ShaderStage::Compute)),
+      "spv" => Some(ShaderFormat::SpirV),
+      _ => None,
+    }
+  }
+}
+
+impl ShaderFile {
+  pub fn load(path: &Path) -> Result<Self, ShaderFileError> {
+    let bytes = std::fs::read(path).map_err(|e| ShaderFileError::Io(path.to_path_buf(), e))?;
+    Self::from_bytes(path, bytes)
+  }
+
+  // The contents of a file at `path`, which only needs to have the right extension
+  pub fn from_bytes(path: &Path, bytes: Vec<u8>) -> Result<Self, ShaderFileError> {
+    let format = ShaderFormat::from_path(path)
+      .ok_or_else(|| ShaderFileError::UnknownExtension(path.into()))?;
+    let text = |bytes| String::from_utf8(bytes).map_err(|_| ShaderFileError::NotUtf8(path.into()));
+    match format {
+      ShaderFormat::Wgsl => Ok(ShaderFile::Wgsl(text(bytes)?)),
+      ShaderFormat::Glsl(stage) => Ok(ShaderFile::Glsl {
+        source: text(bytes)?,
+        stage,
+      }),
+      ShaderFormat::SpirV => {
+        // wgpu's make_spirv() would panic on these instead
+        if bytes.len() < 4 || !bytes.len().is_multiple_of(4) {
+          return Err(ShaderFileError::NotSpirV(path.into()));
+        }
+        let words: Vec<u32> = bytes
+          .chunks_exact(4)
+          .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+          .collect();
+        match words[0] {
+          SPIRV_MAGIC => Ok(ShaderFile::SpirV(words)),
+          _ => Err(ShaderFileError::NotSpirV(path.into())),
+        }
+      }
+    }
+  }
+
+  pub fn format(&self) -> ShaderFormat {
+    match self {
+      ShaderFile::Wgsl(_) => ShaderFormat::Wgsl,
+      ShaderFile::Glsl { stage, .. } => ShaderFormat::Glsl(*stage),
+      ShaderFile::SpirV(_) => ShaderFormat::SpirV,
+    }
+  }
+
+  pub fn wgsl(&self) -> Option<&str> {
+    match self {
+      ShaderFile::Wgsl(source) => Some(source),
+      _ => None,
+    }
+  }
+
+  // naga's version of the shader, for ShaderReflection
+  pub fn to_module(&self) -> Result<naga::Module, ShaderFileError> {
+    match self {
+      ShaderFile::Wgsl(source) => naga::front::wgsl::parse_str(source)
+        .map_err(|e| ShaderFileError::Parse(vec![e.emit_to_string(source)])),
+      ShaderFile::Glsl { source, stage } => {
+        let options = naga::front::glsl::Options::from(*stage);
+        naga::front::glsl::Parser::default()
+          .parse(&options, source)
+          .map_err(|errors| {
+            let messages = errors.iter().map(|e| {
+              let line = e.meta.location(source).line_number;
+              format!("line {}: {}", line, e.kind)
+            });
+            ShaderFileError::Parse(messages.collect())
+          })
+      }
+      ShaderFile::SpirV(words) => {
+        // what wgpu itself parses SPIR-V with, its y already points up in clip space
+        let options = naga::front::spv::Options {
+          adjust_coordinate_space: false,
+          strict_capabilities: true,
+          block_ctx_dump_prefix: None,
+        };
+        naga::front::spv::Parser::new(words.iter().copied(), &options)
+          .parse()
+          .map_err(|e| ShaderFileError::Parse(vec![e.to_string()]))
+      }
+    }
+  }
+
+  // A WGSL module is only checked by wgpu, like the built in ones: mistakes in it are
+  // validation errors to catch with an error scope. GLSL and SPIR-V that don't parse are an
+  // Err instead, past that they're validated the same way.
+  pub fn create_module(
+    &self,
+    device: &Device,
+    label: &str,
+  ) -> Result<ShaderModule, ShaderFileError> {
+    let source = match self {
+      ShaderFile::Wgsl(source) => wgpu::ShaderSource::Wgsl(Cow::Borrowed(source)),
+      ShaderFile::Glsl { .. } | ShaderFile::SpirV(_) => {
+        wgpu::ShaderSource::Naga(Cow::Owned(self.to_module()?))
+      }
+    };
+    Ok(device.create_shader_module(wgpu::ShaderModuleDescriptor {
+      label: Some(label),
+      source,
+    }))
+  }
+}
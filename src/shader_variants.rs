@@ -0,0 +1,203 @@
+use std::{collections::HashMap, sync::Arc};
+
+use wgpu::{BindGroupLayout, Device, RenderPipeline, TextureFormat};
+
+use crate::{
+  pipeline::{multisample_state, pbr_layout, pbr_pipe, pbr_source, BlendMode, DrawData},
+  preprocessor::ShaderDefs,
+};
+
+// The feature combination a material asks for, each distinct value gets its own pipelines
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MaterialFeatures {
+  // perturbs the normal with the material's tangent space normal map, without one the
+  // mesh's normals are used as they are
+  pub normal_mapping: bool,
+  // the base color's alpha cuts holes, e.g. foliage or chain-link fences. Alpha to coverage
+  // antialiases the edges under MSAA, with a single sample it's an alpha test.
+  pub alpha_cutout: bool,
+}
+
+impl MaterialFeatures {
+  // Every combination pbr.wgsl supports
+  pub fn all() -> Vec<Self> {
+    let mut all = Vec::new();
+    for normal_mapping in [false, true] {
+      for alpha_cutout in [false, true] {
+        all.push(Self {
+          normal_mapping,
+          alpha_cutout,
+        });
+      }
+    }
+    all
+  }
+
+  // e.g. "normal_mapping+cutout", "base" when nothing is enabled
+  pub fn name(&self) -> String {
+    let mut parts = Vec::new();
+    if self.normal_mapping {
+      parts.push("normal_mapping");
+    }
+    if self.alpha_cutout {
+      parts.push("cutout");
+    }
+    if parts.is_empty() {
+      "base".to_string()
+    } else {
+      parts.join("+")
+    }
+  }
+
+  // Adds the features' defines to `defs`
+  pub fn defs(&self, mut defs: ShaderDefs) -> ShaderDefs {
+    if self.normal_mapping {
+      defs = defs.flag("NORMAL_MAPPING");
+    }
+    if self.alpha_cutout {
+      defs = defs.flag("ALPHA_CUTOUT");
+    }
+    defs
+  }
+}
+
+// Compiles the PBR pipeline's variants on first use, one for every feature combination
+// and blend mode, and hands out the same pipeline afterwards. They all share the layout,
+// so switching between them keeps the bind groups.
+pub struct ShaderVariants {
+  format: TextureFormat,
+  draw_data: DrawData,
+  bindless: Option<u32>,
+  sample_count: u32,
+  layout: wgpu::PipelineLayout,
+  pipelines: HashMap<(MaterialFeatures, BlendMode), Arc<RenderPipeline>>,
+}
+
+impl ShaderVariants {
+  // `bind_group_layouts` are the PBR pipeline's four groups, see pbr_pipe(). `format` and
+  // `sample_count` are the target's, e.g. HDR_FORMAT and HdrPipeline::sample_count().
+  pub fn new(
+    device: &Device,
+    format: TextureFormat,
+    sample_count: u32,
+    bind_group_layouts: &[&BindGroupLayout; 4],
+    draw_data: DrawData,
+    bindless: Option<u32>,
+  ) -> Self {
+    Self {
+      format,
+      draw_data,
+      bindless,
+      sample_count,
+      layout: pbr_layout(device, bind_group_layouts, draw_data),
+      pipelines: HashMap::new(),
+    }
+  }
+
+  // The WGSL that `features` compiles from
+  pub fn source(&self, features: MaterialFeatures) -> String {
+    pbr_source(
+      self.draw_data,
+      self.bindless,
+      features,
+      self.alpha_to_coverage(features),
+    )
+  }
+
+  // Whether `features` cut out with alpha to coverage rather than an alpha test
+  pub fn alpha_to_coverage(&self, features: MaterialFeatures) -> bool {
+    self.multisample(features).alpha_to_coverage_enabled
+  }
+
+  // The multisample state `features` compile with
+  pub fn multisample(&self, features: MaterialFeatures) -> wgpu::MultisampleState {
+    multisample_state(self.sample_count, features.alpha_cutout)
+  }
+
+  pub fn is_cached(&self, features: MaterialFeatures, blend: BlendMode) -> bool {
+    self.pipelines.contains_key(&(features, blend))
+  }
+
+  pub fn len(&self) -> usize {
+    self.pipelines.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.pipelines.is_empty()
+  }
+
+  pub fn get(
+    &mut self,
+    device: &Device,
+    features: MaterialFeatures,
+    blend: BlendMode,
+  ) -> Arc<RenderPipeline> {
+    if let Some(pipeline) = self.pipelines.get(&(features, blend)) {
+      return pipeline.clone();
+    }
+    let pipeline = Arc::new(pbr_pipe(
+      device,
+      self.format,
+      &self.layout,
+      &self.source(features),
+      self.multisample(features),
+      blend,
+    ));
+    log::info!(
+      "compiled material variant {} ({:?})",
+      features.name(),
+      blend
+    );
+    self.pipelines.insert((features, blend), pipeline.clone());
+    pipeline
+  }
+
+  // Warms the cache so nothing compiles mid-frame later
+  pub fn compile_all(&mut self, device: &Device) {
+    for features in MaterialFeatures::all() {
+      for blend in BlendMode::ALL {
+        self.get(device, features, blend);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{hdr::HDR_FORMAT, sampler::Samplers, scene::Scene};
+
+  // Cutouts antialias with alpha to coverage at 4 samples, skipped without an adapter
+  #[test]
+  fn cutouts_use_alpha_to_coverage_under_msaa() {
+    let instance = wgpu::Instance::default();
+    let Some(adapter) = pollster::block_on(instance.request_adapter(&Default::default())) else {
+      eprintln!("no adapter, skipping the MSAA pipelines");
+      return;
+    };
+    let (device, queue) = pollster::block_on(crate::adapter::request_device(&adapter)).unwrap();
+    if crate::hdr::msaa_sample_count(&adapter, &device, 4) != 4 {
+      eprintln!("no 4x MSAA, skipping the MSAA pipelines");
+      return;
+    }
+    let samplers = Samplers::new(&adapter);
+    let draw_data = DrawData::pick(&adapter, &device, false);
+    let mut scene = Scene::new(&device, &queue, &samplers, HDR_FORMAT, 4, 256, draw_data);
+    let variants = scene.pbr_mut().variants_mut();
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    for features in MaterialFeatures::all() {
+      variants.get(&device, features, BlendMode::Opaque);
+      let multisample = variants.multisample(features);
+      assert_eq!(multisample.count, 4);
+      assert_eq!(
+        multisample.alpha_to_coverage_enabled,
+        features.alpha_cutout,
+        "{}",
+        features.name()
+      );
+    }
+    if let Some(e) = pollster::block_on(device.pop_error_scope()) {
+      panic!("the 4x pipelines don't build: {}", e);
+    }
+  }
+}
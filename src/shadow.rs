@@ -0,0 +1,167 @@
+use wgpu::{CommandEncoder, Device, RenderPass, TextureView};
+
+use crate::{
+  gpu_memory::Tracked,
+  math::{self, Mat4, Vec3},
+  sampler::SamplerSettings,
+};
+
+pub const SHADOW_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+// Depth state for the light's depth-only pass. The bias pushes the stored depth back a
+// little so surfaces don't shadow themselves (shadow acne), more on steep slopes.
+pub fn shadow_depth_state() -> wgpu::DepthStencilState {
+  wgpu::DepthStencilState {
+    format: SHADOW_FORMAT,
+    depth_write_enabled: true,
+    depth_compare: wgpu::CompareFunction::LessEqual,
+    stencil: wgpu::StencilState::default(),
+    bias: wgpu::DepthBiasState {
+      constant: 2,
+      slope_scale: 2.0,
+      clamp: 0.0,
+    },
+  }
+}
+
+// Orthographic view-projection of a directional light shining along `-towards_light`,
+// fitted around the bounding sphere of everything that should cast or receive shadows
+pub fn light_view_proj(towards_light: Vec3, center: Vec3, radius: f32) -> Mat4 {
+  let direction = math::normalize(towards_light);
+  let eye = math::add(center, math::scale(direction, radius * 2.0));
+  // look_at can't use +Y as up when the light is straight overhead
+  let up = if direction[1].abs() > 0.99 {
+    [0.0, 0.0, 1.0]
+  } else {
+    [0.0, 1.0, 0.0]
+  };
+  let view = math::look_at(eye, center, up);
+  let projection = math::orthographic(-radius, radius, -radius, radius, radius, radius * 3.0);
+  math::mul_mat4(&projection, &view)
+}
+
+// The depth texture the light renders into, plus the bind group the lit pass samples it with
+pub struct ShadowMap {
+  view: Tracked<TextureView>,
+  layout: wgpu::BindGroupLayout,
+  sampler: wgpu::Sampler,
+  bind_group: wgpu::BindGroup,
+  size: u32,
+}
+
+impl ShadowMap {
+  pub fn new(device: &Device, size: u32) -> Self {
+    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Shadow Bind Group Layout"),
+      entries: &[
+        wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Depth,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+          },
+          count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+          binding: 1,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+          count: None,
+        },
+      ],
+    });
+    // comparison sampler: each tap returns how much of the 2x2 footprint is lit
+    let sampler =
+      device.create_sampler(&SamplerSettings::shadow().descriptor(Some("Shadow Sampler")));
+    let view = create_view(device, size);
+    let bind_group = create_bind_group(device, &layout, &view, &sampler);
+    Self {
+      view,
+      layout,
+      sampler,
+      bind_group,
+      size,
+    }
+  }
+
+  // Recreates the texture when the quality tier asks for another resolution
+  pub fn resize(&mut self, device: &Device, size: u32) {
+    if size == self.size {
+      return;
+    }
+    self.view = create_view(device, size);
+    self.bind_group = create_bind_group(device, &self.layout, &self.view, &self.sampler);
+    self.size = size;
+  }
+
+  pub fn layout(&self) -> &wgpu::BindGroupLayout {
+    &self.layout
+  }
+
+  pub fn bind_group(&self) -> &wgpu::BindGroup {
+    &self.bind_group
+  }
+
+  pub fn size(&self) -> u32 {
+    self.size
+  }
+
+  // Depth-only pass from the light, cleared to the far plane
+  pub fn begin_pass<'a>(&'a self, encoder: &'a mut CommandEncoder) -> RenderPass<'a> {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+      label: Some("Shadow Pass"),
+      color_attachments: &[],
+      depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+        view: &self.view,
+        depth_ops: Some(wgpu::Operations {
+          load: wgpu::LoadOp::Clear(1.0),
+          store: true,
+        }),
+        stencil_ops: None,
+      }),
+    })
+  }
+}
+
+fn create_view(device: &Device, size: u32) -> Tracked<TextureView> {
+  let texture = device.create_texture(&wgpu::TextureDescriptor {
+    label: Some("Shadow Map"),
+    size: wgpu::Extent3d {
+      width: size,
+      height: size,
+      depth_or_array_layers: 1,
+    },
+    mip_level_count: 1,
+    sample_count: 1,
+    dimension: wgpu::TextureDimension::D2,
+    format: SHADOW_FORMAT,
+    usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+    view_formats: &[],
+  });
+  let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+  Tracked::view(&texture, view)
+}
+
+fn create_bind_group(
+  device: &Device,
+  layout: &wgpu::BindGroupLayout,
+  view: &TextureView,
+  sampler: &wgpu::Sampler,
+) -> wgpu::BindGroup {
+  device.create_bind_group(&wgpu::BindGroupDescriptor {
+    label: Some("Shadow Bind Group"),
+    layout,
+    entries: &[
+      wgpu::BindGroupEntry {
+        binding: 0,
+        resource: wgpu::BindingResource::TextureView(view),
+      },
+      wgpu::BindGroupEntry {
+        binding: 1,
+        resource: wgpu::BindingResource::Sampler(sampler),
+      },
+    ],
+  })
+}
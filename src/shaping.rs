@@ -0,0 +1,257 @@
+// Just enough of what a shaper like HarfBuzz does for the overlays to show more than ASCII:
+// Arabic letters take their joined forms, combining marks stay on their letters and right
+// to left runs are put in display order. Everything works on chars, the fonts only need the
+// presentation forms of Unicode rather than OpenType shaping tables.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+  LeftToRight,
+  RightToLeft,
+}
+
+// The direction `c` sets, None for the neutral ones like spaces and punctuation that go
+// with the text around them. Digits count as left to right so numbers keep their order.
+pub fn direction(c: char) -> Option<Direction> {
+  match c as u32 {
+    _ if is_mark(c) => None,
+    // Arabic-Indic digits
+    0x0660..=0x0669 | 0x06F0..=0x06F9 => Some(Direction::LeftToRight),
+    // Hebrew, Arabic, Syriac, Thaana, NKo and the rest up to Devanagari, their presentation
+    // forms and the historic right to left scripts
+    0x0590..=0x08FF | 0xFB1D..=0xFDFF | 0xFE70..=0xFEFF | 0x10800..=0x10FFF | 0x1E800..=0x1EFFF => {
+      Some(Direction::RightToLeft)
+    }
+    _ if c.is_alphanumeric() => Some(Direction::LeftToRight),
+    _ => None,
+  }
+}
+
+// Combining marks, drawn over or under the letter before them
+pub fn is_mark(c: char) -> bool {
+  matches!(c as u32,
+    0x0300..=0x036F
+    | 0x0483..=0x0489
+    | 0x0591..=0x05BD
+    | 0x05BF
+    | 0x05C1..=0x05C2
+    | 0x05C4..=0x05C5
+    | 0x05C7
+    | 0x0610..=0x061A
+    | 0x064B..=0x065F
+    | 0x0670
+    | 0x06D6..=0x06DC
+    | 0x06DF..=0x06E4
+    | 0x06E7..=0x06E8
+    | 0x06EA..=0x06ED
+    | 0x1AB0..=0x1AFF
+    | 0x1DC0..=0x1DFF
+    | 0x20D0..=0x20FF
+    | 0xFE20..=0xFE2F)
+}
+
+// The bracket facing the other way, for brackets in right to left text
+pub fn mirror(c: char) -> char {
+  match c {
+    '(' => ')',
+    ')' => '(',
+    '[' => ']',
+    ']' => '[',
+    '{' => '}',
+    '}' => '{',
+    '<' => '>',
+    '>' => '<',
+    '«' => '»',
+    '»' => '«',
+    _ => c,
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Joining {
+  // joins the letter before it only, like alef
+  Right,
+  // joins both sides
+  Dual,
+}
+
+// The isolated form of an Arabic letter in Arabic Presentation Forms-B, the final, initial
+// and medial ones follow it for the letters that have them
+fn arabic_forms(c: char) -> Option<(u32, Joining)> {
+  use Joining::{Dual, Right};
+  let forms = match c {
+    '\u{0622}' => (0xFE81, Right),
+    '\u{0623}' => (0xFE83, Right),
+    '\u{0624}' => (0xFE85, Right),
+    '\u{0625}' => (0xFE87, Right),
+    '\u{0626}' => (0xFE89, Dual),
+    '\u{0627}' => (0xFE8D, Right),
+    '\u{0628}' => (0xFE8F, Dual),
+    '\u{0629}' => (0xFE93, Right),
+    '\u{062A}' => (0xFE95, Dual),
+    '\u{062B}' => (0xFE99, Dual),
+    '\u{062C}' => (0xFE9D, Dual),
+    '\u{062D}' => (0xFEA1, Dual),
+    '\u{062E}' => (0xFEA5, Dual),
+    '\u{062F}' => (0xFEA9, Right),
+    '\u{0630}' => (0xFEAB, Right),
+    '\u{0631}' => (0xFEAD, Right),
+    '\u{0632}' => (0xFEAF, Right),
+    '\u{0633}' => (0xFEB1, Dual),
+    '\u{0634}' => (0xFEB5, Dual),
+    '\u{0635}' => (0xFEB9, Dual),
+    '\u{0636}' => (0xFEBD, Dual),
+    '\u{0637}' => (0xFEC1, Dual),
+    '\u{0638}' => (0xFEC5, Dual),
+    '\u{0639}' => (0xFEC9, Dual),
+    '\u{063A}' => (0xFECD, Dual),
+    '\u{0641}' => (0xFED1, Dual),
+    '\u{0642}' => (0xFED5, Dual),
+    '\u{0643}' => (0xFED9, Dual),
+    '\u{0644}' => (0xFEDD, Dual),
+    '\u{0645}' => (0xFEE1, Dual),
+    '\u{0646}' => (0xFEE5, Dual),
+    '\u{0647}' => (0xFEE9, Dual),
+    '\u{0648}' => (0xFEED, Right),
+    '\u{0649}' => (0xFEEF, Right),
+    '\u{064A}' => (0xFEF1, Dual),
+    _ => return None,
+  };
+  Some(forms)
+}
+
+// Tatweel only stretches the join, it has no forms of its own
+const TATWEEL: char = '\u{0640}';
+const LAM: char = '\u{0644}';
+
+// The isolated lam-alef ligature for the alef after a lam, the final form follows it
+fn lam_alef(alef: char) -> Option<u32> {
+  match alef {
+    '\u{0622}' => Some(0xFEF5),
+    '\u{0623}' => Some(0xFEF7),
+    '\u{0625}' => Some(0xFEF9),
+    '\u{0627}' => Some(0xFEFB),
+    _ => None,
+  }
+}
+
+fn joins_next(c: char) -> bool {
+  c == TATWEEL || matches!(arabic_forms(c), Some((_, Joining::Dual)))
+}
+
+fn joins_previous(c: char) -> bool {
+  c == TATWEEL || arabic_forms(c).is_some()
+}
+
+// Arabic letters in the form for their neighbours, and lam-alef pairs as one ligature. The
+// rest goes through as it is, `T` is whatever the caller keeps with each char (a ligature
+// keeps the lam's).
+pub fn shape<T: Copy>(chars: &[(char, T)]) -> Vec<(char, T)> {
+  // the letters either side of `i`, marks in between don't break the join
+  let previous = |i: usize| {
+    chars[..i]
+      .iter()
+      .rev()
+      .map(|&(c, _)| c)
+      .find(|&c| !is_mark(c))
+  };
+  let next = |i: usize| {
+    chars[i + 1..]
+      .iter()
+      .map(|&(c, _)| c)
+      .find(|&c| !is_mark(c))
+  };
+  let mut shaped = Vec::with_capacity(chars.len());
+  let mut i = 0;
+  while i < chars.len() {
+    let (c, data) = chars[i];
+    let joined_before = previous(i).is_some_and(joins_next) && joins_previous(c);
+    if c == LAM {
+      if let Some(ligature) = chars.get(i + 1).and_then(|&(alef, _)| lam_alef(alef)) {
+        let form = ligature + joined_before as u32;
+        shaped.push((char::from_u32(form).unwrap_or(c), data));
+        i += 2;
+        continue;
+      }
+    }
+    let form = match arabic_forms(c) {
+      Some((isolated, joining)) => {
+        let joined_after = joining == Joining::Dual && next(i).is_some_and(joins_previous);
+        let offset = match (joined_before, joined_after) {
+          (false, false) => 0,
+          (true, false) => 1,
+          (false, true) => 2,
+          (true, true) => 3,
+        };
+        char::from_u32(isolated + offset).unwrap_or(c)
+      }
+      None => c,
+    };
+    shaped.push((form, data));
+    i += 1;
+  }
+  shaped
+}
+
+// The order to draw a line's chars in from left to right, as indices into `chars` with the
+// direction each ended up with. A simplified bidi algorithm: the first strong char sets the
+// line's direction, neutrals take the direction of the chars around them when both sides
+// agree and the line's otherwise, and then every run is reversed as often as it's nested.
+// Marks stay after their letter.
+pub fn visual_order(chars: &[char]) -> Vec<(usize, Direction)> {
+  let strong: Vec<_> = chars.iter().map(|&c| direction(c)).collect();
+  let base = strong
+    .iter()
+    .find_map(|&d| d)
+    .unwrap_or(Direction::LeftToRight);
+
+  // letters with their marks, as ranges of chars, with their direction and nesting level
+  let mut clusters: Vec<(std::ops::Range<usize>, Direction, u8)> = Vec::new();
+  for (i, &c) in chars.iter().enumerate() {
+    if is_mark(c) {
+      if let Some((range, _, _)) = clusters.last_mut() {
+        range.end = i + 1;
+        continue;
+      }
+    }
+    let resolved = strong[i].unwrap_or_else(|| {
+      let before = strong[..i].iter().rev().find_map(|&d| d);
+      let after = strong[i + 1..].iter().find_map(|&d| d);
+      match (before, after) {
+        (Some(before), Some(after)) if before == after => before,
+        _ => base,
+      }
+    });
+    let level = match (base, resolved) {
+      (Direction::LeftToRight, Direction::LeftToRight) => 0,
+      (_, Direction::RightToLeft) => 1,
+      (Direction::RightToLeft, Direction::LeftToRight) => 2,
+    };
+    clusters.push((i..i + 1, resolved, level));
+  }
+
+  // from the highest level down, reverse every run at that level or above
+  let highest = clusters
+    .iter()
+    .map(|&(_, _, level)| level)
+    .max()
+    .unwrap_or(0);
+  for level in (1..=highest).rev() {
+    let mut start = 0;
+    while start < clusters.len() {
+      if clusters[start].2 < level {
+        start += 1;
+        continue;
+      }
+      let end = clusters[start..]
+        .iter()
+        .position(|&(_, _, l)| l < level)
+        .map_or(clusters.len(), |n| start + n);
+      clusters[start..end].reverse();
+      start = end;
+    }
+  }
+  clusters
+    .into_iter()
+    .flat_map(|(range, direction, _)| range.map(move |i| (i, direction)))
+    .collect()
+}
@@ -0,0 +1,172 @@
+use crate::clock::Timeline;
+
+// Simulation time step, 60 ticks per simulated second
+pub const DEFAULT_TICK: f32 = 1.0 / 60.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepMode {
+  // Ticks follow wall time through an accumulator, so the tick count per frame varies
+  RealTime,
+  // Exactly one tick per rendered frame no matter how long the frame took,
+  // runs are reproducible because they only depend on the number of frames
+  Deterministic,
+}
+
+// Decides how many fixed-size ticks the compute simulations advance each frame
+pub struct SimulationStepper {
+  mode: StepMode,
+  tick: f32,
+  accumulator: f32,
+  fast_forward: u32,
+  total_ticks: u64,
+}
+
+impl SimulationStepper {
+  pub fn new(mode: StepMode, tick: f32) -> Self {
+    Self {
+      mode,
+      tick,
+      accumulator: 0.0,
+      fast_forward: 0,
+      total_ticks: 0,
+    }
+  }
+
+  pub fn mode(&self) -> StepMode {
+    self.mode
+  }
+
+  pub fn set_mode(&mut self, mode: StepMode) {
+    self.mode = mode;
+    self.accumulator = 0.0;
+  }
+
+  pub fn toggle_mode(&mut self) {
+    self.set_mode(match self.mode {
+      StepMode::RealTime => StepMode::Deterministic,
+      StepMode::Deterministic => StepMode::RealTime,
+    });
+    log::info!("simulation stepping: {:?}", self.mode);
+  }
+
+  // Seconds of simulated time per tick
+  pub fn tick(&self) -> f32 {
+    self.tick
+  }
+
+  // Ticks simulated since startup, the same count means the same simulation state
+  pub fn total_ticks(&self) -> u64 {
+    self.total_ticks
+  }
+
+  // Simulated seconds since startup, for whatever plays back at the simulation's pace
+  pub fn time(&self) -> f64 {
+    self.total_ticks as f64 * self.tick as f64
+  }
+
+  // How far wall time is between the last tick and the next one, 0..1. Rendering blends the
+  // state before the last tick into the one after by this much, so motion stays smooth when
+  // the frame rate and the tick rate don't line up.
+  pub fn alpha(&self) -> f32 {
+    match self.mode {
+      // every frame gets its own tick, there's nothing in between to show
+      StepMode::Deterministic => 1.0,
+      StepMode::RealTime => (self.accumulator / self.tick).min(1.0),
+    }
+  }
+
+  // Runs `ticks` extra ticks on the next frame, even while paused
+  pub fn fast_forward(&mut self, ticks: u32) {
+    self.fast_forward += ticks;
+    log::info!("fast forwarding {} ticks", ticks);
+  }
+
+  // Number of ticks to run this loop, called once per update(). Real time follows the
+  // simulation's timeline, sped up or slowed down with it. Deterministic steps come from
+  // advance_frame() instead, only fast forwarding ticks here.
+  pub fn advance(&mut self, time: &Timeline) -> u32 {
+    let mut ticks = std::mem::take(&mut self.fast_forward);
+    if !time.is_paused() && self.mode == StepMode::RealTime {
+      // cap the catch up so a long hitch doesn't stall the next frames
+      self.accumulator = (self.accumulator + time.dt()).min(self.tick * 8.0);
+      let steps = (self.accumulator / self.tick) as u32;
+      self.accumulator -= steps as f32 * self.tick;
+      ticks += steps;
+    }
+    self.total_ticks += ticks as u64;
+    ticks
+  }
+
+  // Number of ticks for a frame the primary window drew, one when deterministic and not
+  // paused. Loops that draw nothing (throttled, a lost surface) don't tick, so N frames are
+  // N ticks whatever the frame rate.
+  pub fn advance_frame(&mut self, time: &Timeline) -> u32 {
+    let ticks = (self.mode == StepMode::Deterministic && !time.is_paused()) as u32;
+    self.total_ticks += ticks as u64;
+    ticks
+  }
+}
+
+impl Default for SimulationStepper {
+  fn default() -> Self {
+    Self::new(StepMode::RealTime, DEFAULT_TICK)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{cli::CliArgs, clock::Clock};
+  use clap::Parser;
+
+  // The main loop of `--headless --frames N --deterministic`: update() on every loop, the
+  // primary window drawing on some of them
+  fn run(args: &[&str], drawn: impl Fn(u32) -> bool) -> SimulationStepper {
+    let cli =
+      CliArgs::try_parse_from(std::iter::once("wgpu-learn").chain(args.iter().copied())).unwrap();
+    let mut stepper = SimulationStepper::default();
+    if cli.deterministic {
+      stepper.set_mode(StepMode::Deterministic);
+    }
+    let mut clock = Clock::new();
+    let limit = cli.frame_limit().unwrap();
+    let mut frames = 0;
+    let mut loops = 0;
+    while frames < limit {
+      clock.advance(0.004 * (loops % 7 + 1) as f32);
+      stepper.advance(clock.simulation());
+      if drawn(loops) {
+        stepper.advance_frame(clock.simulation());
+        frames += 1;
+      }
+      loops += 1;
+    }
+    stepper
+  }
+
+  #[test]
+  fn deterministic_ticks_are_frames() {
+    for frames in [1, 2, 30, 100] {
+      let args = [
+        "--headless",
+        "--deterministic",
+        "--frames",
+        &frames.to_string(),
+      ];
+      assert_eq!(run(&args, |_| true).total_ticks(), frames as u64);
+      // with loops that draw nothing in between
+      assert_eq!(run(&args, |i| i % 3 != 1).total_ticks(), frames as u64);
+    }
+  }
+
+  #[test]
+  fn real_time_ignores_frames() {
+    let stepper = run(&["--headless", "--frames", "60"], |_| true);
+    // 60 loops of 4 to 28 ms, about a second
+    assert!(
+      (50..70).contains(&stepper.total_ticks()),
+      "{}",
+      stepper.total_ticks()
+    );
+  }
+}
@@ -0,0 +1,609 @@
+// Skinned meshes in the scene, U toggles them. Every model keeps its skeleton, clips and
+// AnimationPlayer on the CPU and uploads the joint matrices of its pose into a storage
+// buffer each frame, which the skinning vertex shader blends per vertex. Y plays and pauses,
+// Z cross fades to the next clip and D turns looping on and off.
+//
+// A model can have morph targets (blend shapes) too: per vertex offsets in a storage
+// buffer that the shader adds in by the weights the clips key, before skinning like glTF.
+//
+// Only the forward passes draw them, not the shadow, deferred or picking ones.
+
+use wgpu::{util::DeviceExt, BindGroupLayout, Device, RenderPass, TextureFormat};
+
+use crate::{
+  animation::{
+    AnimationClip, AnimationPlayer, Channel, Interpolation, Joint, Keys, MorphChannel, Skeleton,
+    SkinError, Transform,
+  },
+  gpu_memory::Tracked,
+  math::{self, Mat4, Vec3},
+  mesh::Mesh,
+  pipeline::skinned_pipe,
+  scene::{GpuMesh, SceneInstance},
+  uploader::Upload,
+};
+
+// Joints per skeleton, the size of every joint buffer
+pub const MAX_JOINTS: usize = 64;
+// Morph targets per mesh, matches the weights array of `Morph` in skinning.wgsl
+pub const MAX_MORPH_TARGETS: usize = 8;
+// seconds Z takes to blend into the next clip
+const CROSS_FADE: f32 = 0.5;
+
+// The joints a vertex follows and how much, the weights add up to 1. Goes in a vertex
+// buffer of its own next to the mesh's, one per vertex.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SkinVertex {
+  pub joints: [u32; 4],
+  pub weights: [f32; 4],
+}
+
+impl SkinVertex {
+  const ATTRIBUTES: [wgpu::VertexAttribute; 2] =
+    wgpu::vertex_attr_array![10 => Uint32x4, 11 => Float32x4];
+
+  pub fn layout() -> wgpu::VertexBufferLayout<'static> {
+    wgpu::VertexBufferLayout {
+      array_stride: std::mem::size_of::<SkinVertex>() as wgpu::BufferAddress,
+      step_mode: wgpu::VertexStepMode::Vertex,
+      attributes: &Self::ATTRIBUTES,
+    }
+  }
+}
+
+// A blend shape: how far each vertex of the mesh moves at weight 1, and its normal with it
+#[derive(Debug, Clone, Default)]
+pub struct MorphTarget {
+  pub name: String,
+  pub positions: Vec<Vec3>,
+  // empty keeps the normals
+  pub normals: Vec<Vec3>,
+}
+
+// Matches `Morph` in skinning.wgsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct MorphUniform {
+  // x: targets, y: vertices per target
+  counts: [u32; 4],
+  weights: [f32; MAX_MORPH_TARGETS],
+}
+
+pub struct SkinnedModel {
+  name: String,
+  mesh: GpuMesh,
+  vertex_count: usize,
+  skin_buffer: Tracked<wgpu::Buffer>,
+  // a single SceneInstance
+  instance_buffer: Tracked<wgpu::Buffer>,
+  joint_buffer: Tracked<wgpu::Buffer>,
+  morph_buffer: Tracked<wgpu::Buffer>,
+  // the morph targets' position and normal deltas, target after target
+  delta_buffer: Tracked<wgpu::Buffer>,
+  bind_group: wgpu::BindGroup,
+  skeleton: Skeleton,
+  clips: Vec<AnimationClip>,
+  player: AnimationPlayer,
+  // the mesh's own, where no clip keys them
+  default_weights: Vec<f32>,
+}
+
+impl SkinnedModel {
+  pub fn skeleton(&self) -> &Skeleton {
+    &self.skeleton
+  }
+
+  pub fn clips(&self) -> &[AnimationClip] {
+    &self.clips
+  }
+
+  pub fn player(&self) -> &AnimationPlayer {
+    &self.player
+  }
+
+  pub fn player_mut(&mut self) -> &mut AnimationPlayer {
+    &mut self.player
+  }
+
+  pub fn morph_target_count(&self) -> usize {
+    self.default_weights.len()
+  }
+
+  // Each target's weight now
+  pub fn weights(&self) -> Vec<f32> {
+    self.player.weights(&self.default_weights, &self.clips)
+  }
+}
+
+pub struct Skinning {
+  pipeline: wgpu::RenderPipeline,
+  joints_layout: BindGroupLayout,
+  models: Vec<SkinnedModel>,
+  enabled: bool,
+}
+
+impl Skinning {
+  // With a swaying tentacle and a breathing blob to show it off
+  pub fn new(
+    device: &Device,
+    format: TextureFormat,
+    sample_count: u32,
+    globals_layout: &BindGroupLayout,
+    shadow_layout: &BindGroupLayout,
+  ) -> Self {
+    let joints_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Joints Layout"),
+      entries: &[
+        buffer_entry(0, wgpu::BufferBindingType::Storage { read_only: true }),
+        buffer_entry(1, wgpu::BufferBindingType::Uniform),
+        buffer_entry(2, wgpu::BufferBindingType::Storage { read_only: true }),
+      ],
+    });
+    let pipeline = skinned_pipe(
+      device,
+      format,
+      sample_count,
+      globals_layout,
+      shadow_layout,
+      &joints_layout,
+    );
+    let mut skinning = Self {
+      pipeline,
+      joints_layout,
+      models: Vec::new(),
+      enabled: false,
+    };
+    let (mesh, skin, skeleton, clips) = tentacle();
+    let instance = SceneInstance {
+      model: math::translation([-2.0, 0.0, -2.0]),
+      color: [0.6, 0.3, 0.8, 1.0],
+      material: [0.6, 48.0, 0.0, 0.0],
+    };
+    skinning
+      .add(device, &mesh, &skin, skeleton, clips, instance)
+      .expect("the tentacle's skin fits");
+    let (mesh, skin, skeleton, clips, targets) = blob();
+    let instance = SceneInstance {
+      model: math::translation([-3.5, 0.0, -0.5]),
+      color: [0.9, 0.5, 0.3, 1.0],
+      material: [0.4, 24.0, 0.0, 0.0],
+    };
+    let blob = skinning
+      .add(device, &mesh, &skin, skeleton, clips, instance)
+      .expect("the blob's skin fits");
+    skinning
+      .set_morph_targets(device, blob, &targets, &[0.0; 2])
+      .expect("the blob's morph targets fit");
+    skinning
+  }
+
+  pub fn is_enabled(&self) -> bool {
+    self.enabled
+  }
+
+  pub fn toggle(&mut self) -> bool {
+    self.enabled = !self.enabled;
+    self.enabled
+  }
+
+  // `skin` pairs up with the mesh's vertices. Plays the first clip, if there is one.
+  // Returns the model's index.
+  pub fn add(
+    &mut self,
+    device: &Device,
+    mesh: &Mesh,
+    skin: &[SkinVertex],
+    skeleton: Skeleton,
+    clips: Vec<AnimationClip>,
+    instance: SceneInstance,
+  ) -> Result<usize, SkinError> {
+    if skin.len() != mesh.vertices.len() {
+      return Err(SkinError::VertexCount {
+        mesh: mesh.vertices.len(),
+        skin: skin.len(),
+      });
+    }
+    if skeleton.len() > MAX_JOINTS {
+      return Err(SkinError::TooManyJoints {
+        joints: skeleton.len(),
+        max: MAX_JOINTS,
+      });
+    }
+    let skin_buffer = Tracked::buffer(device.create_buffer_init(
+      &wgpu::util::BufferInitDescriptor {
+        label: Some(&format!("{} Skin Buffer", mesh.name)),
+        contents: bytemuck::cast_slice(skin),
+        usage: wgpu::BufferUsages::VERTEX,
+      },
+    ));
+    let instance_buffer = Tracked::buffer(device.create_buffer_init(
+      &wgpu::util::BufferInitDescriptor {
+        label: Some(&format!("{} Instance Buffer", mesh.name)),
+        contents: bytemuck::bytes_of(&instance),
+        usage: wgpu::BufferUsages::VERTEX,
+      },
+    ));
+    // the rest pose until the first upload
+    let mut joints = skeleton.joint_matrices(&skeleton.rest_pose());
+    joints.resize(MAX_JOINTS, math::IDENTITY);
+    let joint_buffer = Tracked::buffer(device.create_buffer_init(
+      &wgpu::util::BufferInitDescriptor {
+        label: Some(&format!("{} Joint Buffer", mesh.name)),
+        contents: bytemuck::cast_slice(&joints),
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+      },
+    ));
+    let morph_buffer = Tracked::buffer(device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some(&format!("{} Morph Buffer", mesh.name)),
+      size: std::mem::size_of::<MorphUniform>() as wgpu::BufferAddress,
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    }));
+    // without targets the shader never reads it, but a binding can't be empty
+    let delta_buffer = create_delta_buffer(device, &mesh.name, &[[0.0; 4]; 2]);
+    let bind_group = self.create_bind_group(
+      device,
+      &mesh.name,
+      &joint_buffer,
+      &morph_buffer,
+      &delta_buffer,
+    );
+    self.models.push(SkinnedModel {
+      name: mesh.name.clone(),
+      mesh: GpuMesh::new(device, mesh),
+      vertex_count: mesh.vertices.len(),
+      skin_buffer,
+      instance_buffer,
+      joint_buffer,
+      morph_buffer,
+      delta_buffer,
+      bind_group,
+      skeleton,
+      clips,
+      player: AnimationPlayer::new(0),
+      default_weights: Vec::new(),
+    });
+    Ok(self.models.len() - 1)
+  }
+
+  // Gives the `model`th model morph targets, replacing any it had, with the weights they
+  // have where the clips don't key them
+  pub fn set_morph_targets(
+    &mut self,
+    device: &Device,
+    model: usize,
+    targets: &[MorphTarget],
+    weights: &[f32],
+  ) -> Result<(), SkinError> {
+    let Some(skinned) = self.models.get(model) else {
+      return Ok(());
+    };
+    if targets.len() > MAX_MORPH_TARGETS {
+      return Err(SkinError::TooManyMorphTargets {
+        targets: targets.len(),
+        max: MAX_MORPH_TARGETS,
+      });
+    }
+    let vertex_count = skinned.vertex_count;
+    let mut deltas = Vec::with_capacity(targets.len() * vertex_count * 2);
+    for target in targets {
+      let normals_fit = target.normals.is_empty() || target.normals.len() == vertex_count;
+      if target.positions.len() != vertex_count || !normals_fit {
+        return Err(SkinError::VertexCount {
+          mesh: vertex_count,
+          skin: target.positions.len(),
+        });
+      }
+      for (i, &[x, y, z]) in target.positions.iter().enumerate() {
+        let [nx, ny, nz] = target.normals.get(i).copied().unwrap_or_default();
+        deltas.extend([[x, y, z, 0.0], [nx, ny, nz, 0.0]]);
+      }
+    }
+    if deltas.is_empty() {
+      deltas = vec![[0.0; 4]; 2];
+    }
+    let delta_buffer = create_delta_buffer(device, &skinned.name, &deltas);
+    let bind_group = self.create_bind_group(
+      device,
+      &skinned.name,
+      &skinned.joint_buffer,
+      &skinned.morph_buffer,
+      &delta_buffer,
+    );
+    let mut default_weights = weights.to_vec();
+    default_weights.resize(targets.len(), 0.0);
+    let skinned = &mut self.models[model];
+    skinned.delta_buffer = delta_buffer;
+    skinned.bind_group = bind_group;
+    skinned.default_weights = default_weights;
+    Ok(())
+  }
+
+  fn create_bind_group(
+    &self,
+    device: &Device,
+    name: &str,
+    joint_buffer: &wgpu::Buffer,
+    morph_buffer: &wgpu::Buffer,
+    delta_buffer: &wgpu::Buffer,
+  ) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some(&format!("{} Joints Bind Group", name)),
+      layout: &self.joints_layout,
+      entries: &[
+        wgpu::BindGroupEntry {
+          binding: 0,
+          resource: joint_buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+          binding: 1,
+          resource: morph_buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+          binding: 2,
+          resource: delta_buffer.as_entire_binding(),
+        },
+      ],
+    })
+  }
+
+  pub fn models(&self) -> &[SkinnedModel] {
+    &self.models
+  }
+
+  pub fn models_mut(&mut self) -> &mut [SkinnedModel] {
+    &mut self.models
+  }
+
+  // Whether a shown model is moving
+  pub fn is_playing(&self) -> bool {
+    self.enabled && self.models.iter().any(|model| model.player.is_playing())
+  }
+
+  // Handles the skinning actions in keymap.rs, returns whether it was one of them
+  pub fn action(&mut self, action: &str) -> bool {
+    match action {
+      "toggle_skinning" => {
+        let enabled = self.toggle();
+        log::info!("skinned meshes {}", if enabled { "on" } else { "off" });
+      }
+      "play_animation" => {
+        for model in &mut self.models {
+          let player = &mut model.player;
+          // a clip that stopped at its end plays again from the start
+          let ended = model
+            .clips
+            .get(player.clip())
+            .is_some_and(|clip| player.time() >= clip.duration());
+          if !player.is_playing() && !player.is_looping() && ended {
+            player.seek(0.0);
+          }
+          let playing = player.toggle();
+          log::info!("animation {}", if playing { "playing" } else { "paused" });
+        }
+      }
+      "next_animation" => {
+        for model in &mut self.models {
+          if model.clips.is_empty() {
+            continue;
+          }
+          let next = (model.player.clip() + 1) % model.clips.len();
+          model.player.cross_fade(next, CROSS_FADE);
+          model.player.play();
+          log::info!("animation {}", model.clips[next].name);
+        }
+      }
+      "loop_animation" => {
+        for model in &mut self.models {
+          let looping = !model.player.is_looping();
+          model.player.set_looping(looping);
+          log::info!("animation looping {}", if looping { "on" } else { "off" });
+        }
+      }
+      _ => return false,
+    }
+    true
+  }
+
+  // Moves the players on by `dt` seconds, see Clock::animation
+  pub fn advance(&mut self, dt: f32) {
+    if !self.enabled {
+      return;
+    }
+    for model in &mut self.models {
+      model.player.advance(&model.clips, dt);
+    }
+  }
+
+  // Uploads every model's joint matrices for its pose now, and its morph target weights
+  pub fn upload(&self, upload: &mut Upload) {
+    if !self.enabled {
+      return;
+    }
+    for model in &self.models {
+      let pose = model.player.pose(&model.skeleton, &model.clips);
+      let joints: Vec<Mat4> = model.skeleton.joint_matrices(&pose);
+      upload.write(&model.joint_buffer, 0, bytemuck::cast_slice(&joints));
+      let mut morph = MorphUniform {
+        counts: [
+          model.morph_target_count() as u32,
+          model.vertex_count as u32,
+          0,
+          0,
+        ],
+        weights: [0.0; MAX_MORPH_TARGETS],
+      };
+      for (weight, value) in morph.weights.iter_mut().zip(model.weights()) {
+        *weight = value;
+      }
+      upload.write(&model.morph_buffer, 0, bytemuck::bytes_of(&morph));
+    }
+  }
+
+  // With the scene's groups 0 and 1 set, switches to the skinned pipeline
+  pub fn draw<'a>(&'a self, pass: &mut RenderPass<'a>) {
+    if !self.enabled || self.models.is_empty() {
+      return;
+    }
+    pass.set_pipeline(&self.pipeline);
+    for model in &self.models {
+      pass.set_bind_group(2, &model.bind_group, &[]);
+      pass.set_vertex_buffer(1, model.instance_buffer.slice(..));
+      pass.set_vertex_buffer(2, model.skin_buffer.slice(..));
+      model.mesh.draw(pass, 1);
+    }
+  }
+}
+
+fn buffer_entry(binding: u32, ty: wgpu::BufferBindingType) -> wgpu::BindGroupLayoutEntry {
+  wgpu::BindGroupLayoutEntry {
+    binding,
+    visibility: wgpu::ShaderStages::VERTEX,
+    ty: wgpu::BindingType::Buffer {
+      ty,
+      has_dynamic_offset: false,
+      min_binding_size: None,
+    },
+    count: None,
+  }
+}
+
+fn create_delta_buffer(device: &Device, name: &str, deltas: &[[f32; 4]]) -> Tracked<wgpu::Buffer> {
+  Tracked::buffer(
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some(&format!("{} Morph Delta Buffer", name)),
+      contents: bytemuck::cast_slice(deltas),
+      usage: wgpu::BufferUsages::STORAGE,
+    }),
+  )
+}
+
+// Joints up the tentacle, the first at its root
+const TENTACLE_JOINTS: usize = 4;
+const TENTACLE_HEIGHT: f32 = 2.0;
+// between two joints
+const BONE_LENGTH: f32 = 0.5;
+
+// A capsule standing on a chain of joints, with a clip swaying it side to side and one
+// curling it forwards
+fn tentacle() -> (Mesh, Vec<SkinVertex>, Skeleton, Vec<AnimationClip>) {
+  let mut mesh = Mesh::segmented_capsule(0.15, TENTACLE_HEIGHT, 16, 16);
+  mesh.name = "tentacle".to_string();
+  // blended between the two joints around each vertex
+  let skin = mesh
+    .vertices
+    .iter()
+    .map(|vertex| {
+      let along = (vertex.position[1] / BONE_LENGTH).clamp(0.0, (TENTACLE_JOINTS - 1) as f32);
+      let joint = (along.floor() as usize).min(TENTACLE_JOINTS - 2);
+      let t = along - joint as f32;
+      SkinVertex {
+        joints: [joint as u32, joint as u32 + 1, 0, 0],
+        weights: [1.0 - t, t, 0.0, 0.0],
+      }
+    })
+    .collect();
+  let joints = (0..TENTACLE_JOINTS)
+    .map(|i| Joint {
+      name: format!("tentacle_{}", i),
+      parent: i.checked_sub(1),
+      rest: Transform::from_translation([0.0, if i == 0 { 0.0 } else { BONE_LENGTH }, 0.0]),
+    })
+    .collect();
+  let skeleton = Skeleton::from_rest(joints).expect("parents come first");
+
+  // every joint but the root bends by the same angle, so the bends add up towards the tip
+  let bend = |axis: [f32; 3], times: &[f32], angles: &[f32]| -> Vec<Channel> {
+    (1..TENTACLE_JOINTS)
+      .map(|joint| Channel {
+        joint,
+        times: times.to_vec(),
+        keys: Keys::Rotation(
+          angles
+            .iter()
+            .map(|&angle| math::quat_axis_angle(axis, angle))
+            .collect(),
+        ),
+        interpolation: Interpolation::Linear,
+      })
+      .collect()
+  };
+  let sway = AnimationClip::new(
+    "sway",
+    bend(
+      [0.0, 0.0, 1.0],
+      &[0.0, 0.75, 1.5, 2.25, 3.0],
+      &[0.0, 0.35, 0.0, -0.35, 0.0],
+    ),
+  );
+  let mut curl = bend([1.0, 0.0, 0.0], &[0.0, 1.0, 2.0], &[0.0, 0.5, 0.0]);
+  // the root squashes as it curls
+  curl.push(Channel {
+    joint: 0,
+    times: vec![0.0, 1.0, 2.0],
+    keys: Keys::Scale(vec![[1.0; 3], [1.15, 0.9, 1.15], [1.0; 3]]),
+    interpolation: Interpolation::Linear,
+  });
+  let curl = AnimationClip::new("curl", curl);
+  (mesh, skin, skeleton, vec![sway, curl])
+}
+
+// A capsule on a single joint, breathing in and out between a squashed and a bulging
+// morph target
+fn blob() -> (
+  Mesh,
+  Vec<SkinVertex>,
+  Skeleton,
+  Vec<AnimationClip>,
+  Vec<MorphTarget>,
+) {
+  let mut mesh = Mesh::segmented_capsule(0.4, 1.2, 24, 8);
+  mesh.name = "blob".to_string();
+  let skin = vec![
+    SkinVertex {
+      joints: [0; 4],
+      weights: [1.0, 0.0, 0.0, 0.0],
+    };
+    mesh.vertices.len()
+  ];
+  let root = Joint {
+    name: "blob".to_string(),
+    parent: None,
+    rest: Transform::IDENTITY,
+  };
+  let skeleton = Skeleton::from_rest(vec![root]).expect("a single root");
+  // lower and wider
+  let squash = MorphTarget {
+    name: "squash".to_string(),
+    positions: mesh
+      .vertices
+      .iter()
+      .map(|v| {
+        let [x, y, z] = v.position;
+        [0.3 * x, -0.4 * y, 0.3 * z]
+      })
+      .collect(),
+    normals: Vec::new(),
+  };
+  // out along the normal, most around the middle
+  let bulge = MorphTarget {
+    name: "bulge".to_string(),
+    positions: mesh
+      .vertices
+      .iter()
+      .map(|v| {
+        let middle = (v.position[1] / 1.2 * std::f32::consts::PI).sin();
+        math::scale([v.normal[0], 0.0, v.normal[2]], 0.2 * middle)
+      })
+      .collect(),
+    normals: Vec::new(),
+  };
+  let breathe = AnimationClip::new("breathe", Vec::new()).with_morph(MorphChannel {
+    times: vec![0.0, 1.0, 2.0, 3.0],
+    targets: 2,
+    weights: vec![0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0],
+    interpolation: Interpolation::Linear,
+  });
+  (mesh, skin, skeleton, vec![breathe], vec![squash, bulge])
+}
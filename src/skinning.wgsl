@@ -0,0 +1,73 @@
+// The scene's Blinn-Phong shading for skinned meshes: each vertex first moves by the
+// weighted offsets of the mesh's morph targets, then blends up to four joint matrices from
+// group 2 before the instance's model matrix places it
+
+#include "lighting.wgsl"
+
+// the joint matrices of the mesh's skeleton, see Skeleton::joint_matrices()
+@group(2) @binding(0)
+var<storage, read> joints: array<mat4x4<f32>>;
+
+struct Morph {
+    // x: targets, y: vertices per target
+    counts: vec4<u32>,
+    // MAX_MORPH_TARGETS weights, four to a vec4
+    weights: array<vec4<f32>, 2>,
+};
+@group(2) @binding(1)
+var<uniform> morph: Morph;
+// a position then a normal offset per vertex, target after target
+@group(2) @binding(2)
+var<storage, read> morph_deltas: array<vec4<f32>>;
+
+struct SkinInput {
+    @location(10) joint_indices: vec4<u32>,
+    @location(11) joint_weights: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) light_position: vec4<f32>,
+    @location(3) color: vec4<f32>,
+    @location(4) material: vec2<f32>,
+};
+
+@vertex
+fn vs_main(
+    vertex: VertexInput,
+    instance: InstanceInput,
+    skin_input: SkinInput,
+    @builtin(vertex_index) vertex_index: u32,
+) -> VertexOutput {
+    var position = vertex.position;
+    var normal = vertex.normal;
+    for (var i = 0u; i < morph.counts.x; i += 1u) {
+        let weight = morph.weights[i / 4u][i % 4u];
+        let delta = (i * morph.counts.y + vertex_index) * 2u;
+        position += weight * morph_deltas[delta].xyz;
+        normal += weight * morph_deltas[delta + 1u].xyz;
+    }
+    let skin = joints[skin_input.joint_indices.x] * skin_input.joint_weights.x
+        + joints[skin_input.joint_indices.y] * skin_input.joint_weights.y
+        + joints[skin_input.joint_indices.z] * skin_input.joint_weights.z
+        + joints[skin_input.joint_indices.w] * skin_input.joint_weights.w;
+    let model = instance_model(instance) * skin;
+    let world = model * vec4<f32>(position, 1.0);
+    var out: VertexOutput;
+    out.clip_position = globals.view_proj * world;
+    out.world_position = world.xyz;
+    // joints that only rotate and translate keep this right
+    out.world_normal = (model * vec4<f32>(normal, 0.0)).xyz;
+    out.light_position = globals.light_view_proj * world;
+    out.color = instance.color;
+    out.material = instance.material.xy;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = shade_blinn_phong(in.world_position, normalize(in.world_normal), in.color.rgb, in.material, in.light_position, 1.0);
+    return vec4<f32>(color, in.color.a);
+}
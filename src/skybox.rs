@@ -0,0 +1,549 @@
+use wgpu::{Device, Queue};
+
+use crate::{
+  assets::{AssetError, Assets},
+  camera::Tile,
+  gpu_memory::Tracked,
+  hdr::{HdrPipeline, DEPTH_FORMAT, HDR_FORMAT},
+  sampler::SamplerSettings,
+  stats::count_draw,
+  uploader::Upload,
+};
+
+// Equirectangular Radiance HDR panorama, converted to a cube on the GPU
+pub const PANORAMA_ASSET: &str = "skybox/sky.hdr";
+// Six face images in cube map order, `skybox/px.png` etc.
+pub const FACE_NAMES: [&str; 6] = ["px", "nx", "py", "ny", "pz", "nz"];
+pub const FIELD_OF_VIEW_Y: f32 = std::f32::consts::FRAC_PI_3;
+
+const PANORAMA_FACE_SIZE: u32 = 1024;
+const GRADIENT_FACE_SIZE: u32 = 64;
+const WORKGROUP_SIZE: u32 = 8;
+
+#[derive(Debug)]
+pub enum SkyboxError {
+  Asset(AssetError),
+  Decode(String, image::ImageError),
+  // every face has to be the same square size
+  FaceSize(String),
+}
+
+impl std::fmt::Display for SkyboxError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      SkyboxError::Asset(e) => write!(f, "{}", e),
+      SkyboxError::Decode(path, e) => write!(f, "couldn't decode {}: {}", path, e),
+      SkyboxError::FaceSize(path) => {
+        write!(f, "{} isn't square or doesn't match the other faces", path)
+      }
+    }
+  }
+}
+
+impl std::error::Error for SkyboxError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      SkyboxError::Asset(e) => Some(e),
+      SkyboxError::Decode(_, e) => Some(e),
+      SkyboxError::FaceSize(_) => None,
+    }
+  }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct SkyCamera {
+  right: [f32; 4],
+  up: [f32; 4],
+  forward: [f32; 4],
+  projection: [f32; 4],
+}
+
+impl SkyCamera {
+  // Only the rotation matters for the sky, the camera always sits in the middle of the cube
+  fn new(yaw: f32, pitch: f32, tile: &Tile) -> Self {
+    let forward = [
+      yaw.sin() * pitch.cos(),
+      pitch.sin(),
+      yaw.cos() * pitch.cos(),
+    ];
+    // right = up x forward and up = forward x right, with world up along +Y
+    let right = normalize([forward[2], 0.0, -forward[0]]);
+    let up = [
+      forward[1] * right[2] - forward[2] * right[1],
+      forward[2] * right[0] - forward[0] * right[2],
+      forward[0] * right[1] - forward[1] * right[0],
+    ];
+    // how far right and up the tile's NDC reach at a distance of 1, and where its middle is
+    let tan_half_fov = (FIELD_OF_VIEW_Y / 2.0).tan();
+    let (width, height) = (tan_half_fov * tile.aspect, tan_half_fov);
+    let [x, y] = tile.center;
+    let [half_width, half_height] = tile.half_size;
+    Self {
+      right: [right[0], right[1], right[2], 0.0],
+      up: [up[0], up[1], up[2], 0.0],
+      forward: [forward[0], forward[1], forward[2], 0.0],
+      projection: [
+        width * half_width,
+        height * half_height,
+        width * x,
+        height * y,
+      ],
+    }
+  }
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+  let len = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2])
+    .sqrt()
+    .max(f32::EPSILON);
+  [v[0] / len, v[1] / len, v[2] / len]
+}
+
+// Same mapping as equirect.wgsl, `uv` in -1..1 with v pointing down the face
+fn face_direction(face: usize, u: f32, v: f32) -> [f32; 3] {
+  let dir = match face {
+    0 => [1.0, -v, -u],
+    1 => [-1.0, -v, u],
+    2 => [u, 1.0, v],
+    3 => [u, -1.0, -v],
+    4 => [u, -v, 1.0],
+    _ => [-u, -v, -1.0],
+  };
+  normalize(dir)
+}
+
+fn create_cube(
+  device: &Device,
+  label: &str,
+  size: u32,
+  format: wgpu::TextureFormat,
+  usage: wgpu::TextureUsages,
+) -> wgpu::Texture {
+  device.create_texture(&wgpu::TextureDescriptor {
+    label: Some(label),
+    size: wgpu::Extent3d {
+      width: size,
+      height: size,
+      depth_or_array_layers: 6,
+    },
+    mip_level_count: 1,
+    sample_count: 1,
+    dimension: wgpu::TextureDimension::D2,
+    format,
+    usage,
+    view_formats: &[],
+  })
+}
+
+fn cube_view(texture: &wgpu::Texture) -> Tracked<wgpu::TextureView> {
+  let view = texture.create_view(&wgpu::TextureViewDescriptor {
+    dimension: Some(wgpu::TextureViewDimension::Cube),
+    ..Default::default()
+  });
+  Tracked::view(texture, view)
+}
+
+// Uploads six tightly packed RGBA8 faces of `size` x `size`
+fn upload_faces(
+  device: &Device,
+  queue: &Queue,
+  size: u32,
+  faces: &[Vec<u8>],
+) -> Tracked<wgpu::TextureView> {
+  let texture = create_cube(
+    device,
+    "Skybox Cube",
+    size,
+    wgpu::TextureFormat::Rgba8UnormSrgb,
+    wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+  );
+  for (layer, pixels) in faces.iter().enumerate() {
+    queue.write_texture(
+      wgpu::ImageCopyTexture {
+        texture: &texture,
+        mip_level: 0,
+        origin: wgpu::Origin3d {
+          x: 0,
+          y: 0,
+          z: layer as u32,
+        },
+        aspect: wgpu::TextureAspect::All,
+      },
+      pixels,
+      wgpu::ImageDataLayout {
+        offset: 0,
+        bytes_per_row: std::num::NonZeroU32::new(size * 4),
+        rows_per_image: None,
+      },
+      wgpu::Extent3d {
+        width: size,
+        height: size,
+        depth_or_array_layers: 1,
+      },
+    );
+  }
+  cube_view(&texture)
+}
+
+// A plain horizon-to-zenith gradient, used when there's no sky in the assets
+pub fn gradient_cube(device: &Device, queue: &Queue) -> Tracked<wgpu::TextureView> {
+  let size = GRADIENT_FACE_SIZE;
+  let horizon = [0.75, 0.82, 0.9];
+  let zenith = [0.2, 0.4, 0.75];
+  let ground = [0.25, 0.22, 0.2];
+  let faces: Vec<Vec<u8>> = (0..6)
+    .map(|face| {
+      let mut pixels = Vec::with_capacity((size * size * 4) as usize);
+      for y in 0..size {
+        for x in 0..size {
+          let u = (x as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+          let v = (y as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+          let height = face_direction(face, u, v)[1];
+          let (to, t) = if height >= 0.0 {
+            (zenith, height.sqrt())
+          } else {
+            (ground, (-height * 4.0).min(1.0))
+          };
+          for c in 0..3 {
+            let linear = horizon[c] + (to[c] - horizon[c]) * t;
+            // the cube is sRGB, so encode roughly to keep the gradient smooth
+            pixels.push((linear.powf(1.0 / 2.2) * 255.0) as u8);
+          }
+          pixels.push(255);
+        }
+      }
+      pixels
+    })
+    .collect();
+  upload_faces(device, queue, size, &faces)
+}
+
+// Decodes the six face images, see FACE_NAMES
+pub async fn load_faces(
+  device: &Device,
+  queue: &Queue,
+  assets: &Assets,
+  extension: &str,
+) -> Result<Tracked<wgpu::TextureView>, SkyboxError> {
+  let mut size = None;
+  let mut faces = Vec::with_capacity(6);
+  for name in FACE_NAMES {
+    let path = format!("skybox/{}.{}", name, extension);
+    let bytes = assets.read(&path).await.map_err(SkyboxError::Asset)?;
+    let image = image::load_from_memory(&bytes)
+      .map_err(|e| SkyboxError::Decode(path.clone(), e))?
+      .to_rgba8();
+    let face_size = *size.get_or_insert(image.width());
+    if image.width() != face_size || image.height() != face_size {
+      return Err(SkyboxError::FaceSize(path));
+    }
+    faces.push(image.into_raw());
+  }
+  Ok(upload_faces(device, queue, size.unwrap_or(1), &faces))
+}
+
+// Converts an equirectangular .hdr panorama into an Rgba16Float cube with a compute pass
+pub fn panorama_to_cube(
+  device: &Device,
+  queue: &Queue,
+  bytes: &[u8],
+) -> Result<Tracked<wgpu::TextureView>, SkyboxError> {
+  let panorama = image::load_from_memory_with_format(bytes, image::ImageFormat::Hdr)
+    .map_err(|e| SkyboxError::Decode(PANORAMA_ASSET.to_string(), e))?
+    .to_rgba32f();
+  let (width, height) = panorama.dimensions();
+  let source_size = wgpu::Extent3d {
+    width,
+    height,
+    depth_or_array_layers: 1,
+  };
+  let source = device.create_texture(&wgpu::TextureDescriptor {
+    label: Some("Skybox Panorama"),
+    size: source_size,
+    mip_level_count: 1,
+    sample_count: 1,
+    dimension: wgpu::TextureDimension::D2,
+    format: wgpu::TextureFormat::Rgba32Float,
+    usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+    view_formats: &[],
+  });
+  queue.write_texture(
+    wgpu::ImageCopyTexture {
+      texture: &source,
+      mip_level: 0,
+      origin: wgpu::Origin3d::ZERO,
+      aspect: wgpu::TextureAspect::All,
+    },
+    bytemuck::cast_slice(panorama.as_raw()),
+    wgpu::ImageDataLayout {
+      offset: 0,
+      bytes_per_row: std::num::NonZeroU32::new(width * 16),
+      rows_per_image: None,
+    },
+    source_size,
+  );
+
+  // a quarter of the panorama's width covers 90 degrees, same as one face
+  let size = (width / 4)
+    .min(PANORAMA_FACE_SIZE)
+    .min(device.limits().max_texture_dimension_2d)
+    .max(1);
+  let cube = create_cube(
+    device,
+    "Skybox Cube",
+    size,
+    HDR_FORMAT,
+    wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+  );
+
+  let shader = device.create_shader_module(wgpu::include_wgsl!("equirect.wgsl"));
+  let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+    label: Some("Equirect Bind Group Layout"),
+    entries: &[
+      wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Texture {
+          sample_type: wgpu::TextureSampleType::Float { filterable: false },
+          view_dimension: wgpu::TextureViewDimension::D2,
+          multisampled: false,
+        },
+        count: None,
+      },
+      wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::StorageTexture {
+          access: wgpu::StorageTextureAccess::WriteOnly,
+          format: HDR_FORMAT,
+          view_dimension: wgpu::TextureViewDimension::D2Array,
+        },
+        count: None,
+      },
+    ],
+  });
+  let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+    label: Some("Equirect Pipeline Layout"),
+    bind_group_layouts: &[&layout],
+    push_constant_ranges: &[],
+  });
+  let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+    label: Some("Equirect Pipeline"),
+    layout: Some(&pipeline_layout),
+    module: &shader,
+    entry_point: "cs_main",
+  });
+  let source_view = source.create_view(&wgpu::TextureViewDescriptor::default());
+  let faces_view = cube.create_view(&wgpu::TextureViewDescriptor {
+    dimension: Some(wgpu::TextureViewDimension::D2Array),
+    ..Default::default()
+  });
+  let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+    label: Some("Equirect Bind Group"),
+    layout: &layout,
+    entries: &[
+      wgpu::BindGroupEntry {
+        binding: 0,
+        resource: wgpu::BindingResource::TextureView(&source_view),
+      },
+      wgpu::BindGroupEntry {
+        binding: 1,
+        resource: wgpu::BindingResource::TextureView(&faces_view),
+      },
+    ],
+  });
+
+  let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+    label: Some("Equirect Encoder"),
+  });
+  {
+    let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+      label: Some("Equirect Pass"),
+    });
+    pass.set_pipeline(&pipeline);
+    pass.set_bind_group(0, &bind_group, &[]);
+    let groups = size.div_ceil(WORKGROUP_SIZE);
+    pass.dispatch_workgroups(groups, groups, 6);
+  }
+  queue.submit(std::iter::once(encoder.finish()));
+  Ok(cube_view(&cube))
+}
+
+// Draws a cube map behind everything in the scene pass
+pub struct Skybox {
+  cube: Tracked<wgpu::TextureView>,
+  camera_buffer: Tracked<wgpu::Buffer>,
+  bind_group: wgpu::BindGroup,
+  pipeline: wgpu::RenderPipeline,
+}
+
+impl Skybox {
+  // The panorama if there is one, then the six faces (png, then jpg), then a gradient
+  pub async fn load(device: &Device, queue: &Queue, assets: &Assets) -> Self {
+    let cube = match Self::load_cube(device, queue, assets).await {
+      Ok(cube) => cube,
+      Err(e) => {
+        log::info!("no skybox loaded ({}), using a gradient", e);
+        gradient_cube(device, queue)
+      }
+    };
+    Self::new(device, cube)
+  }
+
+  async fn load_cube(
+    device: &Device,
+    queue: &Queue,
+    assets: &Assets,
+  ) -> Result<Tracked<wgpu::TextureView>, SkyboxError> {
+    match assets.read(PANORAMA_ASSET).await {
+      Ok(bytes) => return panorama_to_cube(device, queue, &bytes),
+      Err(AssetError::NotFound(_)) => {}
+      Err(e) => return Err(SkyboxError::Asset(e)),
+    }
+    match load_faces(device, queue, assets, "png").await {
+      Err(SkyboxError::Asset(AssetError::NotFound(_))) => {
+        load_faces(device, queue, assets, "jpg").await
+      }
+      result => result,
+    }
+  }
+
+  pub fn new(device: &Device, cube: Tracked<wgpu::TextureView>) -> Self {
+    let camera_buffer = Tracked::buffer(device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Skybox Camera Buffer"),
+      size: std::mem::size_of::<SkyCamera>() as u64,
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    }));
+    let sampler =
+      device.create_sampler(&SamplerSettings::linear().descriptor(Some("Skybox Sampler")));
+
+    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Skybox Bind Group Layout"),
+      entries: &[
+        wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+          },
+          count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+          binding: 1,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::Cube,
+            multisampled: false,
+          },
+          count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+          binding: 2,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+          count: None,
+        },
+      ],
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Skybox Bind Group"),
+      layout: &layout,
+      entries: &[
+        wgpu::BindGroupEntry {
+          binding: 0,
+          resource: camera_buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+          binding: 1,
+          resource: wgpu::BindingResource::TextureView(&cube),
+        },
+        wgpu::BindGroupEntry {
+          binding: 2,
+          resource: wgpu::BindingResource::Sampler(&sampler),
+        },
+      ],
+    });
+
+    let shader = device.create_shader_module(wgpu::include_wgsl!("skybox.wgsl"));
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Skybox Pipeline Layout"),
+      bind_group_layouts: &[&layout],
+      push_constant_ranges: &[],
+    });
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+      label: Some("Skybox Pipeline"),
+      layout: Some(&pipeline_layout),
+      vertex: wgpu::VertexState {
+        module: &shader,
+        entry_point: "vs_main",
+        buffers: &[],
+      },
+      fragment: Some(wgpu::FragmentState {
+        module: &shader,
+        entry_point: "fs_main",
+        targets: &[Some(HDR_FORMAT.into())],
+      }),
+      primitive: wgpu::PrimitiveState::default(),
+      // drawn at depth 1.0 against a depth buffer cleared to 1.0: only pixels nothing
+      // else covered pass, and the sky never writes depth itself
+      depth_stencil: Some(wgpu::DepthStencilState {
+        format: DEPTH_FORMAT,
+        depth_write_enabled: false,
+        depth_compare: wgpu::CompareFunction::LessEqual,
+        stencil: wgpu::StencilState::default(),
+        bias: wgpu::DepthBiasState::default(),
+      }),
+      multisample: wgpu::MultisampleState::default(),
+      multiview: None,
+    });
+
+    Self {
+      cube,
+      camera_buffer,
+      bind_group,
+      pipeline,
+    }
+  }
+
+  // The sky's radiance, also what PbrPipeline::bake_environment lights models with
+  pub fn cube(&self) -> &wgpu::TextureView {
+    &self.cube
+  }
+
+  // Runs after the scene passes, on top of their color and depth
+  pub fn render(&self, upload: &mut Upload, hdr: &HdrPipeline, yaw: f32, tile: &Tile) {
+    let camera = SkyCamera::new(yaw, 0.0, tile);
+    upload.write(&self.camera_buffer, 0, bytemuck::bytes_of(&camera));
+
+    let mut pass = upload
+      .encoder()
+      .begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Skybox Pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+          view: hdr.view(),
+          resolve_target: None,
+          ops: wgpu::Operations {
+            load: wgpu::LoadOp::Load,
+            store: true,
+          },
+        })],
+        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+          view: hdr.depth_view(),
+          depth_ops: Some(wgpu::Operations {
+            load: wgpu::LoadOp::Load,
+            store: true,
+          }),
+          stencil_ops: None,
+        }),
+      });
+    pass.set_pipeline(&self.pipeline);
+    pass.set_bind_group(0, &self.bind_group, &[]);
+    count_draw();
+    pass.draw(0..3, 0..1);
+  }
+}
@@ -0,0 +1,40 @@
+// Fullscreen triangle on the far plane, every pixel looks up the cube map along its view ray
+
+struct SkyCamera {
+    right: vec4<f32>,
+    up: vec4<f32>,
+    forward: vec4<f32>,
+    // x: tan(fov_y / 2) * aspect, y: tan(fov_y / 2), both scaled to the window's tile, and
+    // zw: the middle of the tile in the same units
+    projection: vec4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: SkyCamera;
+@group(0) @binding(1)
+var sky: texture_cube<f32>;
+@group(0) @binding(2)
+var sky_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) idx: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((idx << 1u) & 2u), f32(idx & 2u));
+    let ndc = uv * 2.0 - 1.0;
+    var out: VertexOutput;
+    // z = w puts it exactly at depth 1.0, so it only survives where nothing else was drawn
+    out.clip_position = vec4<f32>(ndc, 1.0, 1.0);
+    out.ndc = ndc;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let dir = camera.forward.xyz
+        + (in.ndc.x * camera.projection.x + camera.projection.z) * camera.right.xyz
+        + (in.ndc.y * camera.projection.y + camera.projection.w) * camera.up.xyz;
+    return vec4<f32>(textureSample(sky, sky_sampler, normalize(dir)).rgb, 1.0);
+}
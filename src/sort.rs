@@ -0,0 +1,360 @@
+// Sorting u32 keys and the u32 values that go with them on the GPU, e.g. particle indices
+// by depth for blending back to front or instances by material. A bitonic sort: a fixed
+// network of compare and swap steps that doesn't depend on the data, one dispatch each,
+// log2(n) * (log2(n) + 1) / 2 of them for n keys rounded up to a power of two. It isn't
+// stable, values with equal keys come out in any order.
+
+use wgpu::{util::DeviceExt, BindGroupLayout, Buffer, CommandEncoder, Device};
+
+use crate::gpu_memory::Tracked;
+
+// Keys per sort, at most 2^MAX_LEVELS
+const MAX_LEVELS: u32 = 22;
+// Pairs compared per workgroup, keep in sync with sort.wgsl
+const PAIRS_PER_GROUP: u32 = 64;
+
+// Matches `SortStep` in sort.wgsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, bytemuck::Pod, bytemuck::Zeroable)]
+struct SortStep {
+  half: u32,
+  flip: u32,
+  _padding: [u32; 2],
+}
+
+// Every step sorting 2^levels keys takes, in order. Sorting twice as many runs the same
+// steps and then some, so one table covers every count.
+fn steps(levels: u32) -> impl Iterator<Item = SortStep> {
+  (1..=levels).flat_map(|level| {
+    let step = |half, flip| SortStep {
+      half,
+      flip: flip as u32,
+      _padding: [0; 2],
+    };
+    // the flip makes a block of two sorted halves bitonic, the disperse steps after it sort
+    // the block
+    let flip = std::iter::once(step(1 << (level - 1), true));
+    flip.chain((0..level - 1).rev().map(move |j| step(1 << j, false)))
+  })
+}
+
+// Levels of the network for `count` keys
+fn levels(count: u32) -> u32 {
+  count.max(1).next_power_of_two().trailing_zeros()
+}
+
+// The order preserving u32 of a float, for sorting by depth or distance. Negative floats
+// have their bits flipped so they count down, positive ones get the sign bit set to go
+// after them.
+pub fn float_key(value: f32) -> u32 {
+  let bits = value.to_bits();
+  if bits >> 31 == 1 {
+    !bits
+  } else {
+    bits | 1 << 31
+  }
+}
+
+#[derive(Debug)]
+pub enum SortError {
+  // more keys than MAX_LEVELS allows
+  TooMany(u32),
+}
+
+impl std::fmt::Display for SortError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      SortError::TooMany(count) => write!(
+        f,
+        "can't sort {} keys on the GPU, {} at most",
+        count,
+        1u32 << MAX_LEVELS
+      ),
+    }
+  }
+}
+
+impl std::error::Error for SortError {}
+
+// Keys and values bound for GpuSorter::sort()
+pub struct SortBuffers {
+  bind_group: wgpu::BindGroup,
+  count: u32,
+}
+
+impl SortBuffers {
+  pub fn count(&self) -> u32 {
+    self.count
+  }
+}
+
+pub struct GpuSorter {
+  pipeline: wgpu::ComputePipeline,
+  // every step of steps(MAX_LEVELS), `stride` apart for the dynamic offset
+  step_bind_group: wgpu::BindGroup,
+  stride: u32,
+  layout: BindGroupLayout,
+}
+
+impl GpuSorter {
+  pub fn new(device: &Device) -> Self {
+    let stride = device.limits().min_uniform_buffer_offset_alignment;
+    let mut table = Vec::new();
+    for step in steps(MAX_LEVELS) {
+      let offset = table.len();
+      table.resize(offset + stride as usize, 0u8);
+      table[offset..offset + std::mem::size_of::<SortStep>()]
+        .copy_from_slice(bytemuck::bytes_of(&step));
+    }
+    let step_buffer = Tracked::buffer(device.create_buffer_init(
+      &wgpu::util::BufferInitDescriptor {
+        label: Some("Sort Step Buffer"),
+        contents: &table,
+        usage: wgpu::BufferUsages::UNIFORM,
+      },
+    ));
+    let step_size = wgpu::BufferSize::new(std::mem::size_of::<SortStep>() as u64);
+    let step_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Sort Step Bind Group Layout"),
+      entries: &[wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+          ty: wgpu::BufferBindingType::Uniform,
+          has_dynamic_offset: true,
+          min_binding_size: step_size,
+        },
+        count: None,
+      }],
+    });
+    let step_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Sort Step Bind Group"),
+      layout: &step_layout,
+      entries: &[wgpu::BindGroupEntry {
+        binding: 0,
+        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+          buffer: &step_buffer,
+          offset: 0,
+          size: step_size,
+        }),
+      }],
+    });
+
+    let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility: wgpu::ShaderStages::COMPUTE,
+      ty: wgpu::BindingType::Buffer {
+        ty: wgpu::BufferBindingType::Storage { read_only: false },
+        has_dynamic_offset: false,
+        min_binding_size: None,
+      },
+      count: None,
+    };
+    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Sort Bind Group Layout"),
+      entries: &[storage_entry(0), storage_entry(1)],
+    });
+    let shader = device.create_shader_module(wgpu::include_wgsl!("sort.wgsl"));
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Sort Pipeline Layout"),
+      bind_group_layouts: &[&step_layout, &layout],
+      push_constant_ranges: &[],
+    });
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+      label: Some("Sort Pipeline"),
+      layout: Some(&pipeline_layout),
+      module: &shader,
+      entry_point: "cs_main",
+    });
+
+    Self {
+      pipeline,
+      step_bind_group,
+      stride,
+      layout,
+    }
+  }
+
+  // Binds the first `count` u32s of `keys` and `values`, both need STORAGE usage
+  pub fn buffers(
+    &self,
+    device: &Device,
+    keys: &Buffer,
+    values: &Buffer,
+    count: u32,
+  ) -> Result<SortBuffers, SortError> {
+    if count > 1 << MAX_LEVELS {
+      return Err(SortError::TooMany(count));
+    }
+    // an empty binding isn't allowed, sort() does nothing for fewer than 2 anyway
+    let size = wgpu::BufferSize::new(count.max(1) as u64 * 4);
+    let entry = |binding, buffer| wgpu::BindGroupEntry {
+      binding,
+      resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+        buffer,
+        offset: 0,
+        size,
+      }),
+    };
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Sort Bind Group"),
+      layout: &self.layout,
+      entries: &[entry(0, keys), entry(1, values)],
+    });
+    Ok(SortBuffers { bind_group, count })
+  }
+
+  // Records the sort of `buffers` by key, smallest first
+  pub fn sort(&self, encoder: &mut CommandEncoder, buffers: &SortBuffers) {
+    if buffers.count < 2 {
+      return;
+    }
+    let levels = levels(buffers.count);
+    let pairs = (1u32 << levels) / 2;
+    let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+      label: Some("Sort Pass"),
+    });
+    pass.set_pipeline(&self.pipeline);
+    pass.set_bind_group(1, &buffers.bind_group, &[]);
+    for index in 0..steps(levels).count() as u32 {
+      pass.set_bind_group(0, &self.step_bind_group, &[index * self.stride]);
+      pass.dispatch_workgroups(pairs.div_ceil(PAIRS_PER_GROUP), 1, 1);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::math::next_random;
+
+  // cs_main in sort.wgsl, one pair at a time
+  fn sort_on_cpu(keys: &mut [u32], values: &mut [u32]) {
+    let count = keys.len() as u32;
+    for step in steps(levels(count)) {
+      for pair in 0..(1 << levels(count)) / 2 {
+        let (block, offset) = (pair / step.half, pair % step.half);
+        let low = block * 2 * step.half + offset;
+        let high = match step.flip {
+          0 => low + step.half,
+          _ => (block + 1) * 2 * step.half - 1 - offset,
+        };
+        if high < count && keys[low as usize] > keys[high as usize] {
+          keys.swap(low as usize, high as usize);
+          values.swap(low as usize, high as usize);
+        }
+      }
+    }
+  }
+
+  fn random_keys(count: usize, seed: u32) -> Vec<u32> {
+    let mut state = seed;
+    // few enough distinct keys that there are plenty of equal ones
+    (0..count)
+      .map(|_| (next_random(&mut state) * 500.0) as u32)
+      .collect()
+  }
+
+  // Checks `keys` are `original` sorted and every value still has its key
+  fn assert_sorted(original: &[u32], keys: &[u32], values: &[u32]) {
+    let mut expected = original.to_vec();
+    expected.sort_unstable();
+    assert_eq!(keys, expected);
+    for (key, value) in keys.iter().zip(values) {
+      assert_eq!(original[*value as usize], *key);
+    }
+  }
+
+  // The steps sort any count, powers of two or not
+  #[test]
+  fn network_sorts() {
+    for count in [0, 1, 2, 3, 5, 64, 100, 257, 1000] {
+      let original = random_keys(count, 0x1234_5678 + count as u32);
+      let mut keys = original.clone();
+      let mut values: Vec<u32> = (0..count as u32).collect();
+      sort_on_cpu(&mut keys, &mut values);
+      assert_sorted(&original, &keys, &values);
+    }
+  }
+
+  // What sort() relies on to share one table between counts
+  #[test]
+  fn fewer_levels_are_a_prefix() {
+    let all: Vec<_> = steps(MAX_LEVELS).collect();
+    for levels in 0..MAX_LEVELS {
+      let count = (levels * (levels + 1) / 2) as usize;
+      assert!(steps(levels).eq(all[..count].iter().copied()), "{}", levels);
+    }
+  }
+
+  #[test]
+  fn float_keys_keep_the_order() {
+    let floats = [
+      f32::NEG_INFINITY,
+      -3.5,
+      -1.0,
+      -0.0,
+      0.0,
+      1e-20,
+      0.5,
+      2.0,
+      1e30,
+    ];
+    for pair in floats.windows(2) {
+      assert!(float_key(pair[0]) <= float_key(pair[1]), "{:?}", pair);
+    }
+  }
+
+  // The real thing against the CPU's sort, skipped without an adapter to run it on
+  #[test]
+  fn gpu_matches_cpu() {
+    let instance = wgpu::Instance::default();
+    let Some(adapter) = pollster::block_on(instance.request_adapter(&Default::default())) else {
+      eprintln!("no adapter, skipping the GPU sort");
+      return;
+    };
+    if !adapter
+      .get_downlevel_capabilities()
+      .flags
+      .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS)
+    {
+      eprintln!("no compute shaders, skipping the GPU sort");
+      return;
+    }
+    let (device, queue) = pollster::block_on(crate::adapter::request_device(&adapter)).unwrap();
+    let sorter = GpuSorter::new(&device);
+    for count in [2, 3, 100, 1000, 4096] {
+      let original = random_keys(count, 0x9e37_79b9 + count as u32);
+      let values: Vec<u32> = (0..count as u32).collect();
+      let create = |label, contents: &[u32]| {
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+          label: Some(label),
+          contents: bytemuck::cast_slice(contents),
+          usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        })
+      };
+      let keys_buffer = create("Keys", &original);
+      let values_buffer = create("Values", &values);
+      let size = count as u64 * 4;
+      let readback = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Readback"),
+        size: size * 2,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+      });
+      let buffers = sorter
+        .buffers(&device, &keys_buffer, &values_buffer, count as u32)
+        .unwrap();
+      let mut encoder = device.create_command_encoder(&Default::default());
+      sorter.sort(&mut encoder, &buffers);
+      encoder.copy_buffer_to_buffer(&keys_buffer, 0, &readback, 0, size);
+      encoder.copy_buffer_to_buffer(&values_buffer, 0, &readback, size, size);
+      queue.submit(Some(encoder.finish()));
+      readback.slice(..).map_async(wgpu::MapMode::Read, |_| {});
+      device.poll(wgpu::Maintain::Wait);
+      let data = readback.slice(..).get_mapped_range();
+      let sorted: &[u32] = bytemuck::cast_slice(&data);
+      assert_sorted(&original, &sorted[..count], &sorted[count..]);
+    }
+  }
+}
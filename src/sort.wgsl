@@ -0,0 +1,44 @@
+// One step of a bitonic sort, see sort.rs. Every invocation compares one pair of keys and
+// swaps them, and their values, if they're the wrong way round. The lower index always
+// ends up with the smaller key, which lets the padding up to a power of two be left out.
+
+// keep in sync with SortStep in sort.rs
+struct SortStep {
+    // the distance between the pairs of a disperse step, half the block of a flip step
+    half: u32,
+    // 1 for a flip step: a block's first half is compared with its second half mirrored
+    flip: u32,
+};
+@group(0) @binding(0)
+var<uniform> step: SortStep;
+
+// the whole bindings are sorted, their length is the count
+@group(1) @binding(0)
+var<storage, read_write> keys: array<u32>;
+@group(1) @binding(1)
+var<storage, read_write> values: array<u32>;
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let pair = global_invocation_id.x;
+    let block = pair / step.half;
+    let offset = pair % step.half;
+    let low = block * 2u * step.half + offset;
+    var high = low + step.half;
+    if step.flip != 0u {
+        high = (block + 1u) * 2u * step.half - 1u - offset;
+    }
+    // past the end is the padding, keys bigger than any there are that stay where they are
+    if high >= arrayLength(&keys) {
+        return;
+    }
+    let low_key = keys[low];
+    let high_key = keys[high];
+    if low_key > high_key {
+        keys[low] = high_key;
+        keys[high] = low_key;
+        let low_value = values[low];
+        values[low] = values[high];
+        values[high] = low_value;
+    }
+}
@@ -0,0 +1,131 @@
+// Split screen: the scene drawn once per player into their own part of the window, F5
+// toggles it. The first player's camera is the scene's, with all of its controls, the
+// second one orbits with the numpad. Every player gets a SceneView of their own, so their
+// camera and light clusters are uploaded and culled for them, and the regions are shares of
+// the window that follow it when it's resized.
+
+use wgpu::{CommandEncoder, Device};
+
+use crate::{
+  camera::{OrbitCamera, Tile},
+  keymap::InputMap,
+  render_region::{Region, RegionPass},
+  scene::{steer_camera, Scene, SceneView},
+  uploader::Upload,
+};
+
+pub const MAX_PLAYERS: usize = 4;
+
+// The second player's CAMERA_ACTIONS
+const PLAYER_2_ACTIONS: [&str; 6] = [
+  "p2_orbit_left",
+  "p2_orbit_right",
+  "p2_orbit_up",
+  "p2_orbit_down",
+  "p2_move_forward",
+  "p2_move_back",
+];
+
+struct Player {
+  camera: OrbitCamera,
+  region: Region,
+  view: SceneView,
+}
+
+pub struct SplitScreen {
+  players: Vec<Player>,
+}
+
+impl SplitScreen {
+  // 2 to MAX_PLAYERS players, the ones after the first start around the scene from it
+  pub fn new(device: &Device, scene: &Scene, players: usize) -> Self {
+    let count = players.clamp(2, MAX_PLAYERS);
+    let players = layout(count)
+      .into_iter()
+      .enumerate()
+      .map(|(i, region)| {
+        let mut camera = *scene.camera();
+        camera.orbit(std::f32::consts::TAU * i as f32 / count as f32, 0.0);
+        Player {
+          camera,
+          region,
+          view: scene.create_view(device),
+        }
+      })
+      .collect();
+    Self { players }
+  }
+
+  pub fn len(&self) -> usize {
+    self.players.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.players.is_empty()
+  }
+
+  pub fn camera(&self, player: usize) -> Option<&OrbitCamera> {
+    self.players.get(player).map(|player| &player.camera)
+  }
+
+  // The first player's camera follows the scene's, setting it does nothing
+  pub fn camera_mut(&mut self, player: usize) -> Option<&mut OrbitCamera> {
+    self
+      .players
+      .get_mut(player)
+      .map(|player| &mut player.camera)
+  }
+
+  // The second player's half of what Scene::move_camera() does for the first
+  pub fn move_cameras(&mut self, input: &InputMap, dt: f32) {
+    if let Some(player) = self.players.get_mut(1) {
+      steer_camera(&mut player.camera, input, &PLAYER_2_ACTIONS, dt);
+    }
+  }
+
+  // Uploads every player's camera for their region of a target of `size`, after
+  // Scene::update()
+  pub fn update(&mut self, scene: &Scene, upload: &mut Upload, size: (u32, u32)) {
+    for (i, player) in self.players.iter_mut().enumerate() {
+      if i == 0 {
+        player.camera = *scene.camera();
+      }
+      let tile = Tile::whole(player.region.to_pixels(size).aspect());
+      scene.update_view(upload, &mut player.view, &player.camera, &tile);
+    }
+  }
+
+  // Scene::cull_lights() for every player
+  pub fn cull_lights(&self, scene: &Scene, encoder: &mut CommandEncoder) {
+    for player in &self.players {
+      scene.cull_view_lights(encoder, &player.view);
+    }
+  }
+
+  // Scene::render() and the grid in every player's region, then the whole target again
+  pub fn render<'a>(&'a self, scene: &'a Scene, pass: &mut RegionPass<'a, '_>) {
+    for player in &self.players {
+      // a window too small to split
+      if let Err(e) = pass.set_region(player.region) {
+        log::debug!("split screen: {}", e);
+        continue;
+      }
+      scene.render_view(pass, &player.view);
+    }
+    pass.reset();
+  }
+}
+
+// Side by side for two, a quarter each for more
+pub fn layout(players: usize) -> Vec<Region> {
+  match players {
+    0 | 1 => vec![Region::WHOLE],
+    2 => vec![
+      Region::new(0.0, 0.0, 0.5, 1.0),
+      Region::new(0.5, 0.0, 0.5, 1.0),
+    ],
+    _ => (0..players.min(MAX_PLAYERS))
+      .map(|i| Region::new(0.5 * (i % 2) as f32, 0.5 * (i / 2) as f32, 0.5, 0.5))
+      .collect(),
+  }
+}
@@ -0,0 +1,230 @@
+use wgpu::{
+  util::DeviceExt, BindGroup, BindGroupLayout, CommandEncoder, Device, Queue, TextureFormat,
+  TextureView,
+};
+
+use crate::{
+  deferred::GBuffer,
+  gpu_memory::Tracked,
+  math::{self, next_random},
+  pipeline::{ssao_blur_pipe, ssao_pipe},
+  stats::count_draw,
+};
+
+// One channel of occlusion, 1 is fully lit
+pub const SSAO_FORMAT: TextureFormat = TextureFormat::R8Unorm;
+// Samples per pixel, keep in sync with ssao.wgsl
+const KERNEL_SIZE: usize = 16;
+// Side of the random rotation tile, keep in sync with ssao_blur.wgsl
+const NOISE_SIZE: u32 = 4;
+// Same seed every run so the noise doesn't crawl between runs
+const SEED: u32 = 0x3c6e_f372;
+
+// Matches `SsaoParams` in ssao.wgsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct SsaoParams {
+  samples: [[f32; 4]; KERNEL_SIZE],
+  radius: f32,
+  bias: f32,
+  intensity: f32,
+  _padding: f32,
+}
+
+// Points in the unit hemisphere around +Z, pulled in towards the middle so close
+// geometry counts more than far
+fn hemisphere_kernel(rng: &mut u32) -> [[f32; 4]; KERNEL_SIZE] {
+  let mut samples = [[0.0; 4]; KERNEL_SIZE];
+  for (i, sample) in samples.iter_mut().enumerate() {
+    let direction = math::normalize([
+      next_random(rng) * 2.0 - 1.0,
+      next_random(rng) * 2.0 - 1.0,
+      // keeps the samples off the surface itself
+      next_random(rng).max(0.05),
+    ]);
+    let t = i as f32 / KERNEL_SIZE as f32;
+    let [x, y, z] = math::scale(direction, next_random(rng) * (0.1 + 0.9 * t * t));
+    *sample = [x, y, z, 0.0];
+  }
+  samples
+}
+
+// Screen space ambient occlusion from the G-buffer's normals and the scene depth, then
+// blurred into the G-buffer's occlusion target for the deferred lighting pass
+pub struct SsaoRenderer {
+  params_buffer: Tracked<wgpu::Buffer>,
+  noise_view: TextureView,
+  layout: BindGroupLayout,
+  blur_layout: BindGroupLayout,
+  pipeline: wgpu::RenderPipeline,
+  blur_pipeline: wgpu::RenderPipeline,
+}
+
+impl SsaoRenderer {
+  // `globals_layout` and `shadow_layout` are groups 0 and 1 of the scene pipeline
+  pub fn new(
+    device: &Device,
+    queue: &Queue,
+    globals_layout: &BindGroupLayout,
+    shadow_layout: &BindGroupLayout,
+  ) -> Self {
+    let mut rng = SEED;
+    let params = SsaoParams {
+      samples: hemisphere_kernel(&mut rng),
+      radius: 0.5,
+      bias: 0.025,
+      intensity: 1.0,
+      _padding: 0.0,
+    };
+    let params_buffer = Tracked::buffer(device.create_buffer_init(
+      &wgpu::util::BufferInitDescriptor {
+        label: Some("Ssao Params Buffer"),
+        contents: bytemuck::bytes_of(&params),
+        usage: wgpu::BufferUsages::UNIFORM,
+      },
+    ));
+    // random directions in the XY plane, stored 0..1
+    let noise: Vec<u8> = (0..NOISE_SIZE * NOISE_SIZE)
+      .flat_map(|_| {
+        let mut channel = || (next_random(&mut rng) * 255.0) as u8;
+        [channel(), channel(), 128, 255]
+      })
+      .collect();
+    let noise_view = device
+      .create_texture_with_data(
+        queue,
+        &wgpu::TextureDescriptor {
+          label: Some("Ssao Noise"),
+          size: wgpu::Extent3d {
+            width: NOISE_SIZE,
+            height: NOISE_SIZE,
+            depth_or_array_layers: 1,
+          },
+          mip_level_count: 1,
+          sample_count: 1,
+          dimension: wgpu::TextureDimension::D2,
+          format: TextureFormat::Rgba8Unorm,
+          usage: wgpu::TextureUsages::TEXTURE_BINDING,
+          view_formats: &[],
+        },
+        &noise,
+      )
+      .create_view(&wgpu::TextureViewDescriptor::default());
+
+    let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility: wgpu::ShaderStages::FRAGMENT,
+      ty: wgpu::BindingType::Texture {
+        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+        view_dimension: wgpu::TextureViewDimension::D2,
+        multisampled: false,
+      },
+      count: None,
+    };
+    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Ssao Layout"),
+      entries: &[
+        texture_entry(0),
+        texture_entry(1),
+        texture_entry(2),
+        wgpu::BindGroupLayoutEntry {
+          binding: 3,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+          },
+          count: None,
+        },
+      ],
+    });
+    let blur_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Ssao Blur Layout"),
+      entries: &[texture_entry(0)],
+    });
+
+    Self {
+      params_buffer,
+      noise_view,
+      pipeline: ssao_pipe(device, &[globals_layout, shadow_layout, &layout]),
+      blur_pipeline: ssao_blur_pipe(device, &blur_layout),
+      layout,
+      blur_layout,
+    }
+  }
+
+  // Fills `gbuffer`'s occlusion after its pass ran. `globals` and `shadow` are the scene's
+  // groups 0 and 1, the shadow map isn't read but the layout has it.
+  pub fn render(
+    &self,
+    device: &Device,
+    encoder: &mut CommandEncoder,
+    gbuffer: &GBuffer,
+    depth_view: &TextureView,
+    globals: &BindGroup,
+    shadow: &BindGroup,
+  ) {
+    let texture = |binding, view| wgpu::BindGroupEntry {
+      binding,
+      resource: wgpu::BindingResource::TextureView(view),
+    };
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Ssao Bind Group"),
+      layout: &self.layout,
+      entries: &[
+        texture(0, gbuffer.normal_view()),
+        texture(1, depth_view),
+        texture(2, &self.noise_view),
+        wgpu::BindGroupEntry {
+          binding: 3,
+          resource: self.params_buffer.as_entire_binding(),
+        },
+      ],
+    });
+    let blur_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Ssao Blur Bind Group"),
+      layout: &self.blur_layout,
+      entries: &[texture(0, gbuffer.raw_occlusion_view())],
+    });
+
+    {
+      let mut pass = begin_pass(encoder, "Ssao Pass", gbuffer.raw_occlusion_view());
+      pass.set_pipeline(&self.pipeline);
+      pass.set_bind_group(0, globals, &[]);
+      pass.set_bind_group(1, shadow, &[]);
+      pass.set_bind_group(2, &bind_group, &[]);
+      count_draw();
+      pass.draw(0..3, 0..1);
+    }
+    let mut pass = begin_pass(encoder, "Ssao Blur Pass", gbuffer.occlusion_view());
+    pass.set_pipeline(&self.blur_pipeline);
+    pass.set_bind_group(0, &blur_bind_group, &[]);
+    count_draw();
+    pass.draw(0..3, 0..1);
+  }
+
+  // What the lighting reads with ssao off, nothing occluded
+  pub fn clear(&self, encoder: &mut CommandEncoder, gbuffer: &GBuffer) {
+    begin_pass(encoder, "Ssao Clear Pass", gbuffer.occlusion_view());
+  }
+}
+
+fn begin_pass<'a>(
+  encoder: &'a mut CommandEncoder,
+  label: &str,
+  view: &'a TextureView,
+) -> wgpu::RenderPass<'a> {
+  encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+    label: Some(label),
+    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+      view,
+      resolve_target: None,
+      ops: wgpu::Operations {
+        load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+        store: true,
+      },
+    })],
+    depth_stencil_attachment: None,
+  })
+}
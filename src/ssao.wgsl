@@ -0,0 +1,92 @@
+// Screen space ambient occlusion for the deferred path: every pixel tests a hemisphere of
+// samples around its G-buffer normal against the depth buffer, the more of them end up
+// behind other geometry the less ambient light reaches it
+
+// lighting.wgsl for the clusters' near and far planes
+#include "lighting.wgsl"
+
+// keep in sync with KERNEL_SIZE in ssao.rs
+const KERNEL_SIZE: u32 = 16u;
+
+struct SsaoParams {
+    // points in the unit hemisphere around +Z, denser towards the middle
+    samples: array<vec4<f32>, 16>,
+    // world units the samples reach out to
+    radius: f32,
+    // keeps flat surfaces from occluding themselves
+    bias: f32,
+    intensity: f32,
+};
+
+@group(2) @binding(0)
+var normal_map: texture_2d<f32>;
+// bound as a plain float texture, GLSL has no textureLoad for depth textures
+@group(2) @binding(1)
+var depth_map: texture_2d<f32>;
+// a random rotation of the kernel per pixel of a small tile, blurred out afterwards
+@group(2) @binding(2)
+var noise_map: texture_2d<f32>;
+@group(2) @binding(3)
+var<uniform> params: SsaoParams;
+
+struct FullscreenOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+};
+
+// One triangle that covers the whole screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> FullscreenOutput {
+    var out: FullscreenOutput;
+    let x = f32(i32(in_vertex_index & 1u) * 4 - 1);
+    let y = f32(i32(in_vertex_index >> 1u) * 4 - 1);
+    out.clip_position = vec4<f32>(x, y, 0.0, 1.0);
+    out.ndc = vec2<f32>(x, y);
+    return out;
+}
+
+// Distance along the view direction of a depth buffer value
+fn view_depth(depth: f32) -> f32 {
+    let near = clusters.near;
+    let far = clusters.far;
+    return near * far / (far - depth * (far - near));
+}
+
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(in.clip_position.xy);
+    let depth = textureLoad(depth_map, pixel, 0).r;
+    if depth >= 1.0 {
+        return vec4<f32>(1.0);
+    }
+    let world = globals.inverse_view_proj * vec4<f32>(in.ndc, depth, 1.0);
+    let position = world.xyz / world.w;
+    let normal = normalize(textureLoad(normal_map, pixel, 0).xyz);
+
+    // Gram-Schmidt the random vector into a tangent, the kernel turns with it around the normal
+    let noise_size = vec2<i32>(textureDimensions(noise_map));
+    let random = textureLoad(noise_map, pixel % noise_size, 0).xyz * 2.0 - 1.0;
+    let tangent = normalize(random - normal * dot(random, normal));
+    let tbn = mat3x3<f32>(tangent, cross(normal, tangent), normal);
+
+    let size = vec2<f32>(textureDimensions(depth_map));
+    let depth_here = view_depth(depth);
+    var occlusion = 0.0;
+    for (var i = 0u; i < KERNEL_SIZE; i += 1u) {
+        let sample = position + tbn * params.samples[i].xyz * params.radius;
+        let clip = globals.view_proj * vec4<f32>(sample, 1.0);
+        // clip space y points up, texture v points down
+        let uv = clip.xy / clip.w * vec2<f32>(0.5, -0.5) + 0.5;
+        if any(uv < vec2<f32>(0.0)) || any(uv >= vec2<f32>(1.0)) {
+            continue;
+        }
+        let scene_depth = view_depth(textureLoad(depth_map, vec2<i32>(uv * size), 0).r);
+        // geometry far in front of the sample is something else entirely, not an occluder
+        let in_range = smoothstep(0.0, 1.0, params.radius / abs(depth_here - scene_depth));
+        if scene_depth <= clip.w - params.bias {
+            occlusion += in_range;
+        }
+    }
+    let ao = 1.0 - occlusion / f32(KERNEL_SIZE) * params.intensity;
+    return vec4<f32>(saturate(ao));
+}
@@ -0,0 +1,30 @@
+// Box blur over the noise tile of ssao.wgsl, so the per pixel kernel rotations average out
+// instead of showing as a pattern
+
+// keep in sync with NOISE_SIZE in ssao.rs
+const NOISE_SIZE: i32 = 4;
+
+@group(0) @binding(0)
+var occlusion_map: texture_2d<f32>;
+
+// One triangle that covers the whole screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> @builtin(position) vec4<f32> {
+    let x = f32(i32(in_vertex_index & 1u) * 4 - 1);
+    let y = f32(i32(in_vertex_index >> 1u) * 4 - 1);
+    return vec4<f32>(x, y, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(position.xy);
+    let last = vec2<i32>(textureDimensions(occlusion_map)) - 1;
+    var sum = 0.0;
+    for (var y = 0; y < NOISE_SIZE; y += 1) {
+        for (var x = 0; x < NOISE_SIZE; x += 1) {
+            let offset = vec2<i32>(x, y) - NOISE_SIZE / 2;
+            sum += textureLoad(occlusion_map, clamp(pixel + offset, vec2<i32>(0), last), 0).r;
+        }
+    }
+    return vec4<f32>(sum / f32(NOISE_SIZE * NOISE_SIZE));
+}
@@ -1,186 +1,1449 @@
-use crate::pipeline::render_pipe;
-use log::error;
-use winit::{event::*, window::Window};
+use std::{collections::HashMap, time::Duration};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{hot_reload::HotReload, scripting::ScriptHost};
+
+use crate::{
+  adapter::{backends_from_env, request_device, AdapterPicker},
+  assets::Assets,
+  audio::Audio,
+  boids::{Boids, Collision},
+  bridge::BridgeCommand,
+  camera::Tile,
+  cli::CliArgs,
+  clock::Clock,
+  config::Config,
+  ecs,
+  fog::FogSettings,
+  frame_graph::{save_to_files, FrameGraphRecorder},
+  gamepad::Gamepads,
+  hdr::msaa_sample_count,
+  hdr::HDR_FORMAT,
+  inspector::Inspector,
+  keymap::{self, InputError, InputMap, KeyContext, Trigger},
+  latency::LatencyProbe,
+  net_sync::NetworkSync,
+  orrery::Orrery,
+  params::Params,
+  pass_toggles::PassToggles,
+  picking::Entity,
+  pipeline::{DrawData, PipelineCache},
+  plants::PlantSettings,
+  power::{FramePacing, PowerSaver},
+  profiler::{GpuProfiler, PipelineStatistics},
+  quality::QualitySettings,
+  raycast::PickMethod,
+  render_region::RegionPass,
+  render_settings::{AntiAliasing, RenderPath},
+  sampler::Samplers,
+  scene::Scene,
+  scene_file::{SceneFile, SCENE_PATH},
+  screenshot::{save_to_file, Screenshot, ScreenshotReply},
+  security_camera::SecurityCamera,
+  simulation::{SimulationStepper, StepMode},
+  skybox::Skybox,
+  split_screen::SplitScreen,
+  stats::{count_draw, FrameStats, StatsOverlay},
+  storage::SettingsStorage,
+  terrain::{Heightmap, TerrainSettings},
+  text::{load_fallback_fonts, load_font_from_settings, Paragraph, TextRenderer, TextSpan},
+  touch::{Gesture, TouchGestures},
+  uploader::Uploader,
+  viewport::{Viewport, SHADER_VARIANTS},
+  water::WaterSettings,
+};
+use winit::{
+  dpi::PhysicalSize,
+  event::*,
+  window::{CursorGrabMode, Window, WindowId},
+};
+
+// Things that happened that the code around State may want to react to, see take_events()
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateEvent {
+  // a click in the shadowed scene landed on this
+  Picked(Entity),
+  // the quit action
+  Quit,
+  // the new_window action, State can't open windows itself
+  NewWindow,
+}
+
+#[derive(Debug)]
+pub enum StateError {
+  WindowCreation(winit::error::OsError),
+  InvalidBackend(String),
+  SurfaceCreation(wgpu::CreateSurfaceError),
+  AdapterNotFound { backends: wgpu::Backends },
+  DeviceRequestFailed(wgpu::RequestDeviceError),
+  NoSupportedFormat,
+  NoPresentMode,
+  NoAlphaMode,
+}
+
+impl std::fmt::Display for StateError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      StateError::WindowCreation(e) => write!(f, "couldn't open a window: {}", e),
+      StateError::InvalidBackend(message) => write!(f, "{}", message),
+      StateError::SurfaceCreation(e) => {
+        write!(f, "couldn't create a surface for the window: {}", e)
+      }
+      StateError::AdapterNotFound { backends } => write!(
+        f,
+        "no GPU adapter for {:?} can present to this window, \
+         try another --backend / WGPU_LEARN_BACKEND or update your graphics drivers",
+        backends
+      ),
+      StateError::DeviceRequestFailed(e) => write!(
+        f,
+        "the adapter couldn't create a device ({}), it may not support the requested limits",
+        e
+      ),
+      StateError::NoSupportedFormat => write!(
+        f,
+        "the surface reports no supported formats for this adapter, try another --adapter"
+      ),
+      StateError::NoPresentMode => write!(
+        f,
+        "the surface reports no present modes for this adapter, try another --adapter"
+      ),
+      StateError::NoAlphaMode => write!(
+        f,
+        "the surface reports no alpha modes for this adapter, try another --adapter"
+      ),
+    }
+  }
+}
+
+impl std::error::Error for StateError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      StateError::WindowCreation(e) => Some(e),
+      StateError::SurfaceCreation(e) => Some(e),
+      StateError::DeviceRequestFailed(e) => Some(e),
+      _ => None,
+    }
+  }
+}
+
+// Ticks run by the fast forward key
+const FAST_FORWARD_TICKS: u32 = 600;
+// radians per second
+const SKY_SPIN: f32 = 0.05;
+// Radians the camera orbits per logical pixel a finger drags
+const TOUCH_ORBIT: f32 = 0.01;
+// Radians the camera turns per unit of raw mouse motion while the cursor is grabbed, about
+// a pixel on most mice
+const MOUSE_LOOK: f32 = 0.003;
+// How often the power saver still comes round to poll a connected gamepad
+const GAMEPAD_POLL: Duration = Duration::from_millis(50);
+// and to check for what other instances sent, or send them what changed
+const SYNC_POLL: Duration = Duration::from_millis(20);
+// and to bring in assets that finished loading or changed on disk
+#[cfg(not(target_arch = "wasm32"))]
+const ASSET_POLL: Duration = Duration::from_millis(250);
 
 pub struct State {
-  surface: wgpu::Surface,
+  // viewports hold surfaces created from the instance, so they're dropped first
+  viewports: HashMap<WindowId, Viewport>,
+  // the window the stats are published to, closing it quits the app
+  primary: WindowId,
+  instance: wgpu::Instance,
+  adapter: wgpu::Adapter,
   device: wgpu::Device,
   queue: wgpu::Queue,
-  config: wgpu::SurfaceConfiguration,
-  pub size: winit::dpi::PhysicalSize<u32>,
-  window: Window,
-  color: wgpu::Color,
-  click: bool,
-  main_pipe: wgpu::RenderPipeline,
+  settings: Config,
+  // where changes made at runtime get saved to
+  storage: SettingsStorage,
+  assets: Assets,
+  quality: QualitySettings,
+  samplers: Samplers,
+  profiler: GpuProfiler,
+  pipeline_stats: PipelineStatistics,
+  stats: StatsOverlay,
+  // None when no font could be loaded
+  text: Option<TextRenderer>,
+  stepper: SimulationStepper,
+  // ticks decided in update() and the primary window's render(), run by the compute passes
+  // in render()
+  sim_ticks: u32,
+  boids: Boids,
+  scene: Scene,
+  // F5, None while the scene is seen through its own camera alone
+  split_screen: Option<SplitScreen>,
+  // J, a monitor in the scene showing another camera
+  security_camera: Option<SecurityCamera>,
+  // 1, models moved by keyframed nodes
+  orrery: Option<Orrery>,
+  // 2, the root of a few entities
+  carousel: Option<ecs::Entity>,
+  // 3 drops cubes onto the ground
+  #[cfg(feature = "physics")]
+  physics: crate::physics::Physics,
+  // 4 for music, the brackets for the volume
+  audio: Audio,
+  skybox: Skybox,
+  // the per frame uniform writes, shared by everything drawn into the frame's encoder
+  uploader: Uploader,
+  // the windows' main and comparison pipelines, shared between them
+  pipelines: PipelineCache,
+  power: PowerSaver,
+  // radians, the sky slowly turns so it's obvious it isn't a flat background
+  sky_yaw: f32,
+  clock: Clock,
+  // taken from the next frame of the primary window
+  screenshot_requests: Vec<ScreenshotReply>,
+  // F9 dumps the next frame's passes
+  frame_graph: FrameGraphRecorder,
+  pass_toggles: PassToggles,
+  // `, the entities and what they're made of
+  inspector: Inspector,
+  // F8, click to present latency
+  latency: LatencyProbe,
+  // F1, the keymap in the overlay
+  show_help: bool,
+  input_map: InputMap,
+  gamepads: Option<Gamepads>,
+  touch: TouchGestures,
+  // the window the cursor is held in and hidden, mouse motion turns the scene's camera
+  grabbed: Option<WindowId>,
+  // params and the scene's camera shared with other instances, see net_sync.rs
+  sync: Option<NetworkSync>,
+  #[cfg(not(target_arch = "wasm32"))]
+  hot_reload: HotReload,
+  // --script's demo, see scripting.rs
+  #[cfg(not(target_arch = "wasm32"))]
+  script: Option<ScriptHost>,
+  // a click waiting for the next frame of its window to pick what's under it
+  pick_request: Option<(WindowId, [f64; 2])>,
+  events: Vec<StateEvent>,
+  screenshots_in_flight: Vec<(Screenshot, ScreenshotReply)>,
+  // named values set from the config, the web page and the like, see params.rs
+  params: Params,
 }
 
 impl State {
   // Creating some of the wgpu types requires async code
-  pub async fn new(window: Window) -> Self {
-    let size = window.inner_size();
-
+  pub async fn new(
+    window: Window,
+    settings: Config,
+    storage: SettingsStorage,
+    cli: &CliArgs,
+  ) -> Result<Self, StateError> {
     // The instance is a handle to our GPU
     // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
+    // unless restricted with --backend / WGPU_LEARN_BACKEND
+    let backends = backends_from_env(cli.backend.as_deref(), settings.render.backend.as_deref())
+      .map_err(StateError::InvalidBackend)?;
     let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-      backends: wgpu::Backends::all(),
+      backends,
       dx12_shader_compiler: Default::default(),
     });
 
     // # Safety
     //
     // The surface needs to live as long as the window that created it.
-    // State owns the window so this should be safe.
-    let surface = unsafe { instance.create_surface(&window) }.unwrap();
-
-    let adapter = instance
-      .request_adapter(&wgpu::RequestAdapterOptions {
-        power_preference: wgpu::PowerPreference::default(),
-        compatible_surface: Some(&surface),
-        force_fallback_adapter: false,
-      })
+    // The viewport owns both so this should be safe.
+    let surface =
+      unsafe { instance.create_surface(&window) }.map_err(StateError::SurfaceCreation)?;
+
+    let adapter = AdapterPicker::from_env(backends, cli.adapter.as_deref(), cli.high_performance)
+      .pick(&instance, &surface)
       .await
-      .unwrap();
-
-    let (device, queue) = adapter
-      .request_device(
-        &wgpu::DeviceDescriptor {
-          features: wgpu::Features::empty(),
-          // WebGL doesn't support all of wgpu's features, so if
-          // we're building for the web we'll have to disable some.
-          limits: if cfg!(target_arch = "wasm32") {
-            wgpu::Limits::downlevel_webgl2_defaults()
-          } else {
-            wgpu::Limits::default()
-          },
-          label: None,
-        },
-        None, // Trace path
-      )
+      .ok_or(StateError::AdapterNotFound { backends })?;
+
+    let (device, queue) = request_device(&adapter)
       .await
-      .unwrap();
-
-    let surface_caps = surface.get_capabilities(&adapter);
-    // Shader code in this tutorial assumes an sRGB surface texture. Using a different
-    // one will result all the colors coming out darker. If you want to support non
-    // sRGB surfaces, you'll need to account for that when drawing to the frame.
-    let surface_format = surface_caps
-      .formats
-      .iter()
-      .copied()
-      .filter(|f| f.describe().srgb)
-      .next()
-      .unwrap_or(surface_caps.formats[0]);
-
-    let config = wgpu::SurfaceConfiguration {
-      usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-      format: surface_format,
-      width: size.width,
-      height: size.height,
-      present_mode: surface_caps.present_modes[0],
-      alpha_mode: surface_caps.alpha_modes[0],
-      view_formats: vec![],
-    };
-    surface.configure(&device, &config);
-
-    let color = wgpu::Color::BLUE;
-    let click = false;
-
-    let main_pipe = render_pipe(&device, &config, "main".to_string());
-    Self {
+      .map_err(StateError::DeviceRequestFailed)?;
+
+    let quality =
+      QualitySettings::auto_configure(&adapter.get_info(), &device.limits(), &settings.quality);
+    let sample_count = msaa_sample_count(&adapter, &device, settings.render.msaa_samples);
+    if sample_count > 1 && settings.render.path == RenderPath::Deferred {
+      log::warn!("the deferred path has no MSAA, drawing forward");
+    }
+
+    // --shader for this run only, or pick up where the last run left off
+    let variant = cli
+      .shader
+      .or_else(|| {
+        let last = settings.demo.last.as_deref()?;
+        SHADER_VARIANTS.iter().find(|&&v| v == last).copied()
+      })
+      .unwrap_or(SHADER_VARIANTS[0]);
+    let samplers = Samplers::new(&adapter);
+
+    let mut pipelines = PipelineCache::new();
+    let mut viewport = Viewport::new(
       window,
       surface,
+      &adapter,
+      &device,
+      &queue,
+      &mut pipelines,
+      variant,
+      sample_count,
+    )?;
+    let power = PowerSaver::new(settings.power.clone());
+    // --headless, nothing waits for a hidden window's refresh
+    viewport.set_vsync(
+      &adapter,
+      &device,
+      (settings.window.vsync || power.is_saving()) && settings.window.visible,
+    );
+    viewport.set_color(settings.window.clear_color());
+    let primary = viewport.id();
+    let viewports = HashMap::from([(primary, viewport)]);
+
+    let profiler = GpuProfiler::new(&device, &queue);
+    let pipeline_stats = PipelineStatistics::new(&device);
+    let stats = StatsOverlay::new(&settings.window.title, &settings.stats);
+    let assets = Assets::open().await;
+    let text = match load_font_from_settings(&settings.text, &assets).await {
+      Ok(font) => {
+        let mut text = TextRenderer::new(&device, font, settings.text.size);
+        for font in load_fallback_fonts(&settings.text) {
+          text.add_fallback_font(font);
+        }
+        Some(text)
+      }
+      Err(e) => {
+        log::warn!("text rendering disabled: {}", e);
+        None
+      }
+    };
+    let mut stepper = SimulationStepper::default();
+    if cli.deterministic {
+      stepper.set_mode(StepMode::Deterministic);
+    }
+    let skybox = Skybox::load(&device, &queue, &assets).await;
+    let boids = Boids::new(
+      &device,
+      HDR_FORMAT,
+      sample_count,
+      stepper.tick(),
+      quality.particle_count,
+    );
+    let mut scene = Scene::new(
+      &device,
+      &queue,
+      &samplers,
+      HDR_FORMAT,
+      sample_count,
+      quality.shadow_map_size,
+      DrawData::pick(&adapter, &device, settings.render.storage_instances),
+    );
+    scene
+      .pbr_mut()
+      .bake_environment(&device, &queue, skybox.cube(), 1.0);
+    if settings.plants != PlantSettings::default() {
+      if let Err(e) = scene.terrain_mut().set_plants(&device, &settings.plants) {
+        log::warn!("{}, keeping the default plants", e);
+      }
+    }
+    let terrain = settings.terrain.from_args(cli.heightmap.as_deref());
+    if terrain != TerrainSettings::default() {
+      match Heightmap::from_settings(&terrain) {
+        Ok(heightmap) => scene.terrain_mut().set_heightmap(&queue, &heightmap),
+        Err(e) => log::warn!("{}, keeping the generated terrain", e),
+      }
+    }
+    scene.water_mut().set_settings(&queue, &settings.water);
+    scene.fog_mut().set_settings(&queue, &settings.fog);
+    #[cfg(not(target_arch = "wasm32"))]
+    let hot_reload = HotReload::new(
+      &mut scene,
+      &device,
+      &queue,
+      &samplers,
+      &assets,
+      quality.max_texture_size,
+      cli,
+    )
+    .await;
+    // applied with the first update()
+    let mut params = Params::default();
+    params.set_all(&settings.params);
+    let mut input_map = InputMap::new(&settings.input);
+    let gamepads = Gamepads::new(&mut input_map);
+    let audio = Audio::new(&settings.audio);
+    let sync_settings = settings
+      .sync
+      .from_args(cli.broadcast.as_deref(), cli.follow.as_deref());
+    let sync = NetworkSync::start(&sync_settings).unwrap_or_else(|e| {
+      log::warn!("couldn't start syncing with other instances: {}", e);
+      None
+    });
+    let mut state = Self {
+      viewports,
+      primary,
+      instance,
+      adapter,
       device,
       queue,
-      config,
-      size,
-      color,
-      click,
-      main_pipe,
+      settings,
+      storage,
+      assets,
+      quality,
+      samplers,
+      profiler,
+      pipeline_stats,
+      stats,
+      text,
+      stepper,
+      sim_ticks: 0,
+      boids,
+      scene,
+      split_screen: None,
+      security_camera: None,
+      orrery: None,
+      carousel: None,
+      #[cfg(feature = "physics")]
+      physics: Default::default(),
+      audio,
+      skybox,
+      uploader: Uploader::new(),
+      pipelines,
+      power,
+      sky_yaw: 0.0,
+      clock: Clock::new(),
+      screenshot_requests: Vec::new(),
+      frame_graph: FrameGraphRecorder::default(),
+      pass_toggles: PassToggles::default(),
+      inspector: Inspector::default(),
+      latency: LatencyProbe::default(),
+      show_help: false,
+      input_map,
+      gamepads,
+      touch: TouchGestures::default(),
+      grabbed: None,
+      sync,
+      #[cfg(not(target_arch = "wasm32"))]
+      hot_reload,
+      #[cfg(not(target_arch = "wasm32"))]
+      script: cli.script.as_ref().map(ScriptHost::new),
+      pick_request: None,
+      events: Vec::new(),
+      screenshots_in_flight: Vec::new(),
+      params,
+    };
+    if let Some(path) = &cli.scene {
+      state.load_scene(path);
+    }
+    Ok(state)
+  }
+
+  // Adds another window sharing the device, drawn with the next shader variant
+  pub fn add_window(&mut self, window: Window) -> Result<WindowId, StateError> {
+    let variant = SHADER_VARIANTS[self.viewports.len() % SHADER_VARIANTS.len()];
+    window.set_title(&format!("{} ({})", self.settings.window.title, variant));
+    // # Safety
+    //
+    // Same as in new(), the viewport owns the window and its surface
+    let surface =
+      unsafe { self.instance.create_surface(&window) }.map_err(StateError::SurfaceCreation)?;
+    let mut viewport = Viewport::new(
+      window,
+      surface,
+      &self.adapter,
+      &self.device,
+      &self.queue,
+      &mut self.pipelines,
+      variant,
+      self.scene.sample_count(),
+    )?;
+    let vsync = self.settings.window.vsync || self.power.is_saving();
+    viewport.set_vsync(&self.adapter, &self.device, vsync);
+    viewport.set_color(self.settings.window.clear_color());
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(source) = self.hot_reload.shader_source() {
+      viewport.set_shader(&self.device, &mut self.pipelines, Some(source));
+    }
+    let id = viewport.id();
+    log::info!("opened window {:?} with the {} shader", id, variant);
+    self.viewports.insert(id, viewport);
+    Ok(id)
+  }
+
+  pub fn close_window(&mut self, window_id: WindowId) {
+    if self.grabbed == Some(window_id) {
+      self.grabbed = None;
     }
+    self.viewports.remove(&window_id);
+    self.power.window_closed(window_id);
+  }
+
+  pub fn is_primary(&self, window_id: WindowId) -> bool {
+    window_id == self.primary
   }
 
   pub fn window(&self) -> &Window {
-    &self.window
+    self.viewports[&self.primary].window()
+  }
+
+  pub fn has_window(&self, window_id: WindowId) -> bool {
+    self.viewports.contains_key(&window_id)
+  }
+
+  pub fn viewport(&self, window_id: WindowId) -> Option<&Viewport> {
+    self.viewports.get(&window_id)
+  }
+
+  pub fn viewports(&self) -> impl Iterator<Item = &Viewport> {
+    self.viewports.values()
+  }
+
+  pub fn settings(&self) -> &Config {
+    &self.settings
+  }
+
+  pub fn assets(&self) -> &Assets {
+    &self.assets
+  }
+
+  pub fn quality(&self) -> &QualitySettings {
+    &self.quality
+  }
+
+  pub fn samplers(&self) -> &Samplers {
+    &self.samplers
   }
 
-  pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
-    if new_size.width > 0 && new_size.height > 0 {
-      self.size = new_size;
-      self.config.width = new_size.width;
-      self.config.height = new_size.height;
-      self.surface.configure(&self.device, &self.config);
+  pub fn profiler(&self) -> &GpuProfiler {
+    &self.profiler
+  }
+
+  pub fn stats(&self) -> &FrameStats {
+    self.stats.stats()
+  }
+
+  pub fn resize(&mut self, window_id: WindowId, new_size: PhysicalSize<u32>) {
+    if let Some(viewport) = self.viewports.get_mut(&window_id) {
+      viewport.resize(&self.device, new_size);
+    }
+  }
+
+  // After the surface was lost or outdated, which is also when its format may have changed
+  pub fn reconfigure(&mut self, window_id: WindowId) {
+    if let Some(viewport) = self.viewports.get_mut(&window_id) {
+      if !viewport.update_format(&self.adapter, &self.device) {
+        viewport.reconfigure(&self.device);
+      }
+    }
+  }
+
+  pub fn request_redraw(&self) {
+    for viewport in self.viewports.values() {
+      viewport.window().request_redraw();
     }
   }
 
-  pub fn input(&mut self, event: &WindowEvent) -> bool {
+  pub fn input(&mut self, window_id: WindowId, event: &WindowEvent) -> bool {
     // if the method returns true, the main loop won't process the event any further.
     // false
 
+    // the cursor can't stay held by a window that's in the background
+    if let (WindowEvent::Focused(false), Some(grabbed)) = (event, self.grabbed) {
+      if grabbed == window_id {
+        self.release_cursor();
+      }
+    }
     match event {
-      WindowEvent::CursorEntered { .. } => {
-        self.color = wgpu::Color::GREEN;
-        true
+      WindowEvent::Focused(focused) => self.power.set_focused(window_id, *focused),
+      // anything else may change what's on screen
+      _ => self.power.activity(),
+    }
+    // another monitor may want another surface format
+    if matches!(
+      event,
+      WindowEvent::Moved(_) | WindowEvent::ScaleFactorChanged { .. }
+    ) {
+      if let Some(viewport) = self.viewports.get_mut(&window_id) {
+        viewport.update_format(&self.adapter, &self.device);
       }
+    }
 
-      WindowEvent::CursorLeft { .. } => {
-        self.click = false;
-        self.color = wgpu::Color::BLACK;
-        true
-      }
+    // measured whatever else the click does
+    if let WindowEvent::MouseInput {
+      state: ElementState::Pressed,
+      ..
+    } = event
+    {
+      self.latency.click(window_id);
+    }
 
-      WindowEvent::MouseInput { button, .. } => {
-        if MouseButton::Left.eq(button) {
-          self.click = true;
-        } else {
-          self.click = false;
+    if let WindowEvent::Touch(touch) = event {
+      let position = [touch.location.x, touch.location.y];
+      return self.touch(window_id, touch.id, touch.phase, position);
+    }
+    let actions = self.input_map.event(event);
+    if self.dispatch(window_id, actions) {
+      return true;
+    }
+    let Some(viewport) = self.viewports.get_mut(&window_id) else {
+      return false;
+    };
+    let color = viewport.target_color();
+    let handled = viewport.input(event);
+    if viewport.target_color() != color {
+      self.audio.play("click");
+    }
+    handled
+  }
+
+  // Keys and buttons set off the actions they're bound to, the first one that's handled gets
+  // the press. Returns whether any was handled.
+  fn dispatch(&mut self, window_id: WindowId, actions: Vec<(&'static str, ElementState)>) -> bool {
+    let mut handled = false;
+    for (action, state) in actions {
+      if self.action(window_id, action, state) {
+        handled = true;
+        if state == ElementState::Pressed {
+          break;
         }
+      }
+    }
+    handled
+  }
+
+  // Gamepad buttons act on the primary window, they don't belong to any
+  fn poll_gamepads(&mut self) {
+    let Some(gamepads) = &mut self.gamepads else {
+      return;
+    };
+    let actions = gamepads.poll(&mut self.input_map);
+    if !actions.is_empty() {
+      self.power.activity();
+      self.dispatch(self.primary, actions);
+    }
+  }
 
-        false
+  // One finger orbits the scene's camera and two pinch to zoom, returns whether the scene
+  // took the touch
+  fn touch(&mut self, window_id: WindowId, id: u64, phase: TouchPhase, position: [f64; 2]) -> bool {
+    let gesture = self.touch.touch(id, phase, position);
+    if !self.scene.is_enabled() {
+      return false;
+    }
+    let scale = self
+      .viewports
+      .get(&window_id)
+      .map_or(1.0, |v| v.window().scale_factor() as f32);
+    let camera = self.scene.camera_mut();
+    match gesture {
+      // the scene turns with the finger
+      Some(Gesture::Drag([x, y])) => {
+        camera.orbit(-x / scale * TOUCH_ORBIT, y / scale * TOUCH_ORBIT)
       }
+      Some(Gesture::Pinch(ratio)) => camera.dolly(1.0 / ratio - 1.0),
+      None => {}
+    }
+    true
+  }
 
-      WindowEvent::CursorMoved { position, .. } => {
-        // error!("{:?}", self.click);
-        if self.click {
-          self.color = wgpu::Color {
-            r: position.x as f64 / self.size.width as f64,
-            g: position.y as f64 / self.size.height as f64,
-            b: 1.0,
-            a: 1.0,
-          };
-          self.click = false;
-          true
-        } else {
-          false
+  // Raw mouse motion from DeviceEvent::MouseMotion, which keeps coming when the cursor is
+  // against the edge of the window or locked in place. Looks around like an FPS camera while
+  // the cursor is grabbed and does nothing otherwise.
+  pub fn mouse_motion(&mut self, delta: (f64, f64)) {
+    if self.grabbed.is_none() || !self.scene.is_enabled() {
+      return;
+    }
+    self.power.activity();
+    // moving the eye the other way round the target turns the view with the mouse
+    let (x, y) = (delta.0 as f32, delta.1 as f32);
+    self
+      .scene
+      .camera_mut()
+      .orbit(-x * MOUSE_LOOK, y * MOUSE_LOOK);
+  }
+
+  // Confines and hides the cursor of `window_id`. Where it can't be confined (macOS, the web)
+  // it's locked in place instead, raw motion works either way.
+  fn grab_cursor(&mut self, window_id: WindowId) {
+    let Some(viewport) = self.viewports.get(&window_id) else {
+      return;
+    };
+    let window = viewport.window();
+    let grab = window
+      .set_cursor_grab(CursorGrabMode::Confined)
+      .or_else(|_| window.set_cursor_grab(CursorGrabMode::Locked));
+    match grab {
+      Ok(()) => {
+        window.set_cursor_visible(false);
+        self.grabbed = Some(window_id);
+        log::info!("cursor grabbed, Escape lets go");
+      }
+      Err(e) => log::warn!("couldn't grab the cursor: {}", e),
+    }
+  }
+
+  fn release_cursor(&mut self) {
+    let Some(window_id) = self.grabbed.take() else {
+      return;
+    };
+    if let Some(viewport) = self.viewports.get(&window_id) {
+      let window = viewport.window();
+      if let Err(e) = window.set_cursor_grab(CursorGrabMode::None) {
+        log::warn!("couldn't let go of the cursor: {}", e);
+      }
+      window.set_cursor_visible(true);
+    }
+  }
+
+  fn is_active(&self, context: KeyContext) -> bool {
+    match context {
+      KeyContext::CursorGrab => self.grabbed.is_some(),
+      KeyContext::Global => true,
+      KeyContext::Scene => self.scene.is_enabled(),
+      KeyContext::PassList => self.pass_toggles.is_visible(),
+      KeyContext::Inspector => self.inspector.is_visible(),
+    }
+  }
+
+  // Does `action` from keymap.rs for an event of `window_id`, returns whether anything took
+  // it. The held ones are read in update() instead.
+  fn action(&mut self, window_id: WindowId, action: &'static str, state: ElementState) -> bool {
+    if !keymap::find(action).is_some_and(|a| self.is_active(a.context)) {
+      return false;
+    }
+    if state == ElementState::Released {
+      return match self.viewports.get_mut(&window_id) {
+        Some(viewport) => viewport.action(&self.device, &mut self.pipelines, action, state),
+        None => false,
+      };
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(script) = &mut self.script {
+      script.action(action);
+    }
+    match action {
+      "help" => self.show_help = !self.show_help,
+      "quit" => self.events.push(StateEvent::Quit),
+      "new_window" => self.events.push(StateEvent::NewWindow),
+      "swap_shader" => self.next_variant(window_id),
+      // the viewport still gets the click, to drag the comparison
+      "pick" => {
+        self.pick(window_id);
+        return false;
+      }
+      "toggle_scene" => {
+        let enabled = self.scene.toggle();
+        // there's nothing to look around at
+        if !enabled {
+          self.release_cursor();
+        }
+        log::info!("shadowed scene {}", if enabled { "on" } else { "off" });
+      }
+      "toggle_boids" => self.boids.toggle(),
+      "step_mode" => self.stepper.toggle_mode(),
+      "fast_forward" => self.stepper.fast_forward(FAST_FORWARD_TICKS),
+      "pause_simulation" => {
+        let paused = self.clock.simulation_mut().toggle_pause();
+        log::info!("simulation {}", if paused { "paused" } else { "running" });
+      }
+      "pause_animation" => {
+        let paused = self.clock.animation_mut().toggle_pause();
+        log::info!("animation {}", if paused { "paused" } else { "running" });
+      }
+      "render_path" => self.next_render_path(),
+      "toggle_ssao" => self.toggle_ssao(),
+      "color_filter" => self.next_color_filter(),
+      "high_contrast" => {
+        let high_contrast = !self.settings.accessibility.high_contrast;
+        self.settings.accessibility.high_contrast = high_contrast;
+        log::info!("high contrast {}", if high_contrast { "on" } else { "off" });
+        self.save_settings();
+      }
+      "antialiasing" => self.next_antialiasing(),
+      "vsync" => self.toggle_vsync(),
+      "quality_tier" => self.next_quality_tier(),
+      "toggle_anisotropy" => {
+        let enabled = self.samplers.toggle_anisotropy();
+        log::info!(
+          "anisotropic filtering {}",
+          if enabled { "on" } else { "off" }
+        );
+      }
+      "screenshot" => self.request_screenshot(save_to_file()),
+      "frame_graph" => self.frame_graph.request(),
+      "pass_list" => {
+        self.pass_toggles.toggle_visible();
+      }
+      "inspector" => {
+        self.inspector.toggle_visible();
+      }
+      "latency_probe" => {
+        let mode = self.latency.next_mode();
+        log::info!("latency probe: {:?}", mode);
+      }
+      "grab_cursor" => self.grab_cursor(window_id),
+      "release_cursor" => self.release_cursor(),
+      "wireframe" => {
+        let enabled = self.scene.toggle_wireframe(&self.device);
+        log::info!("wireframe {}", if enabled { "on" } else { "off" });
+      }
+      "orbit_left" | "orbit_right" | "orbit_up" | "orbit_down" | "move_forward" | "move_back" => {}
+      "p2_orbit_left" | "p2_orbit_right" | "p2_orbit_up" | "p2_orbit_down" | "p2_move_forward"
+      | "p2_move_back" => {}
+      "split_screen" => self.toggle_split_screen(),
+      "security_camera" => self.toggle_security_camera(),
+      "orrery" => self.toggle_orrery(),
+      "carousel" => self.toggle_carousel(),
+      "drop_cubes" => self.toggle_physics_demo(),
+      "save_scene" => self.save_scene(SCENE_PATH),
+      "load_scene" => self.load_scene(SCENE_PATH),
+      _ if self.audio.action(action) => {
+        self.settings.audio = self.audio.settings();
+        self.save_settings();
+      }
+      _ if self.scene.action(action) || self.pass_toggles.action(action) => {}
+      _ if self.inspector.action(&mut self.scene, action) => {}
+      _ => {
+        return match self.viewports.get_mut(&window_id) {
+          Some(viewport) => viewport.action(&self.device, &mut self.pipelines, action, state),
+          None => false,
         }
       }
+    }
+    true
+  }
+
+  // Picks what's under the cursor of `window_id` in the shadowed scene
+  fn pick(&mut self, window_id: WindowId) {
+    let cursor = self.viewports.get(&window_id).and_then(|v| v.cursor());
+    if let (true, Some(cursor)) = (self.scene.is_enabled(), cursor) {
+      match self.settings.picking.method {
+        PickMethod::IdBuffer => self.pick_request = Some((window_id, cursor)),
+        PickMethod::RayCast => self.ray_cast_pick(window_id, cursor),
+      }
+    }
+  }
+
+  // Makes `window_id` show `tile` of a picture spanning several windows, with the primary
+  // window's shader so they look like one
+  pub fn set_tile(&mut self, window_id: WindowId, tile: Tile) {
+    let Some(viewport) = self.viewports.get_mut(&window_id) else {
+      return;
+    };
+    viewport.set_tile(Some(tile));
+    let variant = self.viewports[&self.primary].variant();
+    if window_id != self.primary {
+      self.set_variant(window_id, variant);
+    }
+  }
+
+  // Cycles the window through the shader variants, the primary one is remembered for next time
+  fn next_variant(&mut self, window_id: WindowId) {
+    let Some(viewport) = self.viewports.get_mut(&window_id) else {
+      return;
+    };
+    let current = SHADER_VARIANTS
+      .iter()
+      .position(|&v| v == viewport.variant())
+      .unwrap_or(0);
+    let variant = SHADER_VARIANTS[(current + 1) % SHADER_VARIANTS.len()];
+    self.set_variant(window_id, variant);
+  }
+
+  fn set_variant(&mut self, window_id: WindowId, variant: &'static str) {
+    let Some(viewport) = self.viewports.get_mut(&window_id) else {
+      return;
+    };
+    viewport.set_variant(&self.device, &mut self.pipelines, variant);
+    viewport
+      .window()
+      .set_title(&format!("{} ({})", self.settings.window.title, variant));
+
+    if window_id == self.primary {
+      self.settings.demo.last = Some(variant.to_string());
+      self.save_settings();
+    }
+  }
+
+  // Switches between forward and deferred lighting of the scene, remembered for next time
+  fn next_render_path(&mut self) {
+    if self.scene.sample_count() > 1 {
+      log::warn!("the deferred path has no MSAA, set msaa_samples to 1 for it");
+      return;
+    }
+    let path = self.settings.render.path.next();
+    self.settings.render.path = path;
+    log::info!("{:?} rendering", path);
+    self.save_settings();
+  }
+
+  fn next_color_filter(&mut self) {
+    let filter = self.settings.accessibility.color_filter.next();
+    self.settings.accessibility.color_filter = filter;
+    log::info!("color filter: {:?}", filter);
+    self.save_settings();
+  }
+
+  fn next_antialiasing(&mut self) {
+    let antialiasing = self.settings.render.antialiasing.next();
+    self.settings.render.antialiasing = antialiasing;
+    log::info!("anti-aliasing: {:?}", antialiasing);
+    self.save_settings();
+  }
+
+  fn toggle_ssao(&mut self) {
+    let ssao = !self.settings.render.ssao;
+    self.settings.render.ssao = ssao;
+    if ssao && self.settings.render.path != RenderPath::Deferred {
+      log::info!("ssao on, it shows on the deferred path (G)");
+    } else {
+      log::info!("ssao {}", if ssao { "on" } else { "off" });
+    }
+    self.save_settings();
+  }
+
+  // Apart from the shadow map, resources already created keep their size and the new
+  // tier applies fully on the next start
+  fn next_quality_tier(&mut self) {
+    let tier = self.quality.tier.next();
+    self.settings.quality.tier = Some(tier);
+    self.quality = QualitySettings::auto_configure(
+      &self.adapter.get_info(),
+      &self.device.limits(),
+      &self.settings.quality,
+    );
+    self
+      .scene
+      .set_shadow_map_size(&self.device, self.quality.shadow_map_size);
+    log::info!(
+      "quality tier set to {:?}, restart to resize everything",
+      tier
+    );
+    self.save_settings();
+  }
+
+  // Whether anything moves without input
+  pub fn is_animating(&self) -> bool {
+    #[cfg(not(target_arch = "wasm32"))]
+    if self.script.as_ref().is_some_and(ScriptHost::is_animating) {
+      return true;
+    }
+    // pending screenshots and picks need the loop to keep polling the device
+    self.boids.is_enabled()
+      || self.scene.is_crowd_walking()
+      || ((self.scene.is_animating() || self.security_camera.is_some())
+        && !self.clock.animation().is_paused())
+      || self.scene.terrain().is_eroding()
+      || self.is_physics_running()
+      || self.viewports.values().any(Viewport::is_fading)
+      || !self.power.is_saving()
+      || !self.screenshots_in_flight.is_empty()
+      || self.pick_request.is_some()
+      || self.scene.is_picking()
+  }
+
+  pub fn pacing(&self) -> FramePacing {
+    self.power.pacing(self.is_animating())
+  }
+
+  // How long the event loop may wait for events without redrawing. Gamepads and the network
+  // don't wake it up, it has to come round to poll them while there's any.
+  pub fn idle_wait(&self) -> Option<Duration> {
+    let gamepad = self.input_map.gamepads().next().map(|_| GAMEPAD_POLL);
+    let sync = self.sync.as_ref().map(|_| SYNC_POLL);
+    #[cfg(not(target_arch = "wasm32"))]
+    let sync = sync
+      .into_iter()
+      .chain((!self.hot_reload.is_empty() || self.script.is_some()).then_some(ASSET_POLL));
+    gamepad.into_iter().chain(sync).min()
+  }
+
+  // Requests a redraw in the power saving modes, for changes that didn't come in as input
+  pub fn invalidate(&mut self) {
+    self.power.invalidate();
+  }
 
-      WindowEvent::KeyboardInput {
-        input:
-          KeyboardInput {
-            state,
-            virtual_keycode: Some(VirtualKeyCode::Space),
-            ..
-          },
-        ..
+  // Commands from bridge::take_commands
+  pub fn apply(&mut self, command: BridgeCommand) {
+    self.power.activity();
+    match command {
+      BridgeCommand::SetParam { name, value } => self.set_param(&name, value),
+      BridgeCommand::LoadScene(name) => match SHADER_VARIANTS.iter().find(|&&v| v == name) {
+        Some(variant) => self.set_variant(self.primary, variant),
+        None => log::warn!("unknown scene {}", name),
+      },
+      BridgeCommand::Screenshot(reply) => self.request_screenshot(reply),
+      BridgeCommand::SetPlantRules(rules) => {
+        let mut plants = self.settings.plants.clone();
+        plants.rules = rules;
+        self.set_plants(plants);
+      }
+      BridgeCommand::Bind { action, triggers } => {
+        if let Err(e) = self.bind(&action, triggers) {
+          log::warn!("{}", e);
+        }
+      }
+      BridgeCommand::Touch {
+        id,
+        phase,
+        position,
       } => {
-        if *state == ElementState::Released {
-          self.main_pipe = render_pipe(&self.device, &self.config, "rainbow".to_string());
-          true
-        } else {
-          self.main_pipe = render_pipe(&self.device, &self.config, "main".to_string());
-          true
+        self.touch(self.primary, id, phase, position);
+      }
+    }
+  }
+
+  // Regrows the terrain's plants and remembers the settings if they worked
+  fn set_plants(&mut self, plants: PlantSettings) {
+    match self.scene.terrain_mut().set_plants(&self.device, &plants) {
+      Ok(()) => {
+        self.settings.plants = plants;
+        self.save_settings();
+      }
+      Err(e) => log::warn!("{}", e),
+    }
+  }
+
+  fn set_param(&mut self, name: &str, value: f64) {
+    if let Err(e) = self.params.set(name, value) {
+      log::warn!("{}", e);
+    }
+  }
+
+  // Rebinds `action` to `triggers` and remembers it in the settings
+  pub fn bind(&mut self, action: &str, triggers: Vec<Trigger>) -> Result<(), InputError> {
+    self.input_map.bind(action, triggers.clone())?;
+    log::info!(
+      "{} bound to {}",
+      action,
+      triggers
+        .iter()
+        .map(Trigger::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+    );
+    self
+      .settings
+      .input
+      .bindings
+      .insert(action.to_string(), triggers);
+    self.save_settings();
+    Ok(())
+  }
+
+  pub fn input_map(&self) -> &InputMap {
+    &self.input_map
+  }
+
+  pub fn params(&self) -> &Params {
+    &self.params
+  }
+
+  pub fn params_mut(&mut self) -> &mut Params {
+    &mut self.params
+  }
+
+  // Acts on the params set since the last frame, then reads back the ones mirroring state
+  // that can change without them, like the exposure keys
+  fn update_params(&mut self) {
+    for name in self.params.take_changed() {
+      self.apply_param(name);
+    }
+    if let Some(viewport) = self.viewports.get(&self.primary) {
+      let compensation = viewport.exposure().settings().compensation;
+      self.params.sync("exposure", compensation as f64);
+    }
+    self
+      .params
+      .sync("boids", self.boids.is_enabled() as u8 as f64);
+    self.params.sync(
+      "anisotropy",
+      self.samplers.anisotropy_enabled() as u8 as f64,
+    );
+    self.params.sync_plants(&self.settings.plants);
+    self.params.sync_water(self.scene.water().settings());
+    self.params.sync_fog(self.scene.fog().settings());
+    let (simulation, animation) = (self.clock.simulation(), self.clock.animation());
+    self
+      .params
+      .sync("simulation_paused", simulation.is_paused() as u8 as f64);
+    self
+      .params
+      .sync("animation_paused", animation.is_paused() as u8 as f64);
+  }
+
+  fn apply_param(&mut self, name: &str) {
+    let params = &self.params;
+    match name {
+      "exposure" => {
+        if let Some(viewport) = self.viewports.get_mut(&self.primary) {
+          viewport.exposure_mut().settings_mut().compensation = params.float(name);
         }
       }
-      _ => false,
+      "boids" if self.boids.is_enabled() != params.flag(name) => self.boids.toggle(),
+      "boids_cohesion" | "boids_separation" | "boids_alignment" => self.boids.set_rule_scales(
+        &self.queue,
+        [
+          params.float("boids_cohesion"),
+          params.float("boids_separation"),
+          params.float("boids_alignment"),
+        ],
+      ),
+      "boids_collision" | "boids_restitution" => self.boids.set_collision(
+        &self.queue,
+        Collision::ALL[params.int("boids_collision") as usize],
+        params.float("boids_restitution"),
+      ),
+      "erosion" => self.scene.terrain_mut().erode(params.int(name)),
+      "anisotropy" if self.samplers.anisotropy_enabled() != params.flag(name) => {
+        self.samplers.toggle_anisotropy();
+      }
+      "plant_angle" | "plant_iterations" | "plant_count" => {
+        let plants = PlantSettings {
+          angle: params.float("plant_angle"),
+          iterations: params.int("plant_iterations"),
+          count: params.int("plant_count"),
+          ..self.settings.plants.clone()
+        };
+        self.set_plants(plants);
+      }
+      "wave_scale" | "wave_speed" | "wave_strength" => {
+        let water = WaterSettings {
+          wave_scale: params.float("wave_scale"),
+          wave_speed: params.float("wave_speed"),
+          wave_strength: params.float("wave_strength"),
+          ..self.scene.water().settings().clone()
+        };
+        self.scene.water_mut().set_settings(&self.queue, &water);
+      }
+      "fog_density" | "fog_scattering" => {
+        let fog = FogSettings {
+          density: params.float("fog_density"),
+          scattering: params.float("fog_scattering"),
+          ..self.scene.fog().settings().clone()
+        };
+        self.scene.fog_mut().set_settings(&self.queue, &fog);
+      }
+      "simulation_speed" => self.clock.simulation_mut().set_speed(params.float(name)),
+      "animation_speed" => self.clock.animation_mut().set_speed(params.float(name)),
+      "simulation_paused" => self.clock.simulation_mut().set_paused(params.flag(name)),
+      "animation_paused" => self.clock.animation_mut().set_paused(params.flag(name)),
+      // already as asked, or read every frame by whatever uses it
+      _ => {}
+    }
+  }
+
+  // `reply` gets the PNG of the primary window's next frame, without the text overlay
+  pub fn request_screenshot(&mut self, reply: ScreenshotReply) {
+    self.screenshot_requests.push(reply);
+    self.power.invalidate();
+  }
+
+  // Hands finished screenshots to whoever asked for them
+  fn finish_screenshots(&mut self) {
+    if self.screenshots_in_flight.is_empty() {
+      return;
+    }
+    self.device.poll(wgpu::Maintain::Poll);
+    let mut i = 0;
+    while i < self.screenshots_in_flight.len() {
+      match self.screenshots_in_flight[i].0.try_finish() {
+        Some(result) => {
+          let (_, reply) = self.screenshots_in_flight.remove(i);
+          reply(result);
+        }
+        None => i += 1,
+      }
+    }
+  }
+
+  // Picks what's under `cursor` right away, with a ray from the camera
+  fn ray_cast_pick(&mut self, window_id: WindowId, cursor: [f64; 2]) {
+    let Some(viewport) = self.viewports.get(&window_id) else {
+      return;
+    };
+    let size = viewport.size;
+    let ndc = [
+      (cursor[0] / size.width as f64 * 2.0 - 1.0) as f32,
+      (1.0 - cursor[1] / size.height as f64 * 2.0) as f32,
+    ];
+    let ray = self.scene.camera_ray(&viewport.tile(), ndc);
+    match self.scene.ray_cast(&ray) {
+      Some((entity, _)) => self.picked(entity),
+      None => log::info!("nothing picked"),
+    }
+  }
+
+  fn picked(&mut self, entity: Entity) {
+    let model = match entity {
+      Entity::Model(i) => self.scene.model_id(i as usize),
+      _ => None,
+    };
+    self.scene.select(model);
+    self.inspector.picked(&self.scene, model);
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(script) = &mut self.script {
+      script.picked(entity);
+    }
+    self.events.push(StateEvent::Picked(entity));
+  }
+
+  // Turns the finished pick readback into an event
+  fn finish_pick(&mut self) {
+    if !self.scene.is_picking() {
+      return;
+    }
+    self.device.poll(wgpu::Maintain::Poll);
+    match self.scene.pick_result() {
+      Some(Some(entity)) => self.picked(entity),
+      Some(None) => log::info!("nothing picked"),
+      None => {}
+    }
+  }
+
+  // What happened since the last call, oldest first
+  pub fn take_events(&mut self) -> Vec<StateEvent> {
+    std::mem::take(&mut self.events)
+  }
+
+  // F5, the scene's camera and a second one side by side
+  fn toggle_split_screen(&mut self) {
+    self.split_screen = match self.split_screen.take() {
+      Some(_) => None,
+      None => Some(SplitScreen::new(&self.device, &self.scene, 2)),
+    };
+    let enabled = self.split_screen.is_some();
+    log::info!("split screen {}", if enabled { "on" } else { "off" });
+  }
+
+  // J, the monitor goes in and out of the scene's models
+  fn toggle_security_camera(&mut self) {
+    self.security_camera = match self.security_camera.take() {
+      Some(camera) => {
+        camera.remove(&mut self.scene);
+        None
+      }
+      None => Some(SecurityCamera::new(&self.device, &mut self.scene)),
+    };
+    let enabled = self.security_camera.is_some();
+    log::info!("security camera {}", if enabled { "on" } else { "off" });
+  }
+
+  // 1, its models go in and out of the scene's and their animation with them
+  fn toggle_orrery(&mut self) {
+    self.orrery = match self.orrery.take() {
+      Some(orrery) => {
+        orrery.remove(&mut self.scene);
+        None
+      }
+      None => Some(Orrery::new(&self.device, &mut self.scene)),
+    };
+    log::info!(
+      "orrery {}",
+      if self.orrery.is_some() { "on" } else { "off" }
+    );
+  }
+
+  // 2, despawning the root takes the rest of the entities with it
+  fn toggle_carousel(&mut self) {
+    self.carousel = match self.carousel.take() {
+      Some(root) => {
+        self.scene.despawn(root);
+        None
+      }
+      None => Some(ecs::spawn_carousel(&self.device, &mut self.scene)),
+    };
+    let enabled = self.carousel.is_some();
+    log::info!("carousel {}", if enabled { "on" } else { "off" });
+  }
+
+  // Home, the camera, lighting and entities
+  fn save_scene(&self, path: &str) {
+    match SceneFile::capture(&self.scene).save(path) {
+      Ok(()) => log::info!("saved the scene to {}", path),
+      Err(e) => log::warn!("couldn't save the scene to {}: {}", path, e),
     }
   }
 
+  // End and --scene, in place of the scene's entities and the demos made of them
+  fn load_scene(&mut self, path: &str) {
+    let file = match SceneFile::load(path) {
+      Ok(file) => file,
+      Err(e) => {
+        log::warn!("couldn't load the scene from {}: {}", path, e);
+        return;
+      }
+    };
+    match file.apply(&self.device, &mut self.scene) {
+      Ok(entities) => log::info!("loaded {} entities from {}", entities.len(), path),
+      Err(e) => {
+        log::warn!("couldn't load the scene from {}: {}", path, e);
+        return;
+      }
+    }
+    // their entities went with the rest
+    self.carousel = None;
+    #[cfg(feature = "physics")]
+    if self.physics.has_demo() {
+      self.physics.toggle_demo(&self.device, &mut self.scene);
+    }
+  }
+
+  // 3, only with the physics feature
+  fn toggle_physics_demo(&mut self) {
+    #[cfg(feature = "physics")]
+    self.physics.toggle_demo(&self.device, &mut self.scene);
+    #[cfg(not(feature = "physics"))]
+    log::warn!("built without the physics feature");
+  }
+
+  // Whether bodies are moving and need simulation ticks
+  fn is_physics_running(&self) -> bool {
+    #[cfg(feature = "physics")]
+    let running = self.scene.is_enabled() && self.physics.is_running();
+    #[cfg(not(feature = "physics"))]
+    let running = false;
+    running
+  }
+
+  // Whether anything takes simulation ticks
+  fn is_simulating(&self) -> bool {
+    self.boids.is_enabled() || self.scene.is_crowd_walking() || self.is_physics_running()
+  }
+
+  // The bodies move on the CPU in update(), so run_systems() uploads where they went the
+  // same frame. Deterministic ticks move them in render(), they're uploaded the frame after.
+  // The compute simulations take their ticks in render().
+  fn step_physics(&mut self, ticks: u32) {
+    #[cfg(feature = "physics")]
+    if self.pass_toggles.enabled("simulation") && self.is_physics_running() {
+      self.physics.step(ticks, self.stepper.tick());
+      self
+        .physics
+        .sync(self.scene.world_mut(), self.stepper.alpha());
+    }
+    #[cfg(not(feature = "physics"))]
+    let _ = ticks;
+  }
+
+  // F6, saved for next time. The power saver keeps Fifo whatever it's set to.
+  fn toggle_vsync(&mut self) {
+    let vsync = !self.settings.window.vsync;
+    self.settings.window.vsync = vsync;
+    log::info!("vsync {}", if vsync { "on" } else { "off" });
+    if self.power.is_saving() {
+      log::info!("the power saver keeps vsync on");
+    }
+    for viewport in self.viewports.values_mut() {
+      viewport.set_vsync(&self.adapter, &self.device, vsync || self.power.is_saving());
+    }
+    self.save_settings();
+  }
+
+  fn save_settings(&self) {
+    match self.settings.save(&self.storage) {
+      Ok(()) => log::info!("saved settings to {}", self.storage.describe()),
+      Err(e) => log::warn!("{}", e),
+    }
+  }
+
+  // Called once per event loop iteration, before the windows are redrawn
   pub fn update(&mut self) {
-    // todo!()
+    // wall time, the UI, camera and stats go on while the simulation or animation is paused
+    let dt = self.clock.tick();
+
+    self.poll_gamepads();
+    self.finish_screenshots();
+    self.finish_pick();
+    #[cfg(not(target_arch = "wasm32"))]
+    if self.hot_reload.update(
+      &mut self.scene,
+      &self.device,
+      &mut self.viewports,
+      &mut self.pipelines,
+    ) {
+      self.power.invalidate();
+    }
+    // before update_params, so the params it sets are applied this frame
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(script) = &mut self.script {
+      let time = self.clock.animation();
+      if script.update(
+        &mut self.scene,
+        &self.device,
+        &self.queue,
+        &mut self.params,
+        time,
+      ) {
+        self.power.invalidate();
+      }
+    }
+    // before update_params so params that came in are applied this frame
+    if let Some(sync) = &mut self.sync {
+      if sync.update(&mut self.params, self.scene.camera_mut(), dt) {
+        self.power.activity();
+      }
+    }
+    self.update_params();
+    // a turning sky would keep the power saver from ever going idle
+    if !self.power.is_saving() {
+      let spin = SKY_SPIN * self.clock.animation().dt();
+      self.sky_yaw = (self.sky_yaw + spin) % std::f32::consts::TAU;
+    }
+    for viewport in self.viewports.values_mut() {
+      viewport.update(&self.queue, dt);
+    }
+    if self.scene.is_enabled() {
+      self.scene.move_camera(&self.input_map, dt);
+      if let Some(split_screen) = &mut self.split_screen {
+        split_screen.move_cameras(&self.input_map, dt);
+      }
+    }
+    self.scene.update_debug();
+    if self.is_simulating() {
+      let ticks = self.stepper.advance(self.clock.simulation());
+      self.sim_ticks += ticks;
+      self.step_physics(ticks);
+    }
+    self
+      .scene
+      .animate(&self.device, &self.queue, self.clock.animation().elapsed());
+    self
+      .scene
+      .advance_animations(&self.queue, self.clock.animation().dt());
+    // culled for the primary window's view of the scene
+    let tile = self
+      .viewports
+      .get(&self.primary)
+      .map_or(Tile::whole(1.0), Viewport::tile);
+    self.scene.run_systems(
+      &self.device,
+      &self.queue,
+      &tile,
+      self.clock.animation().dt(),
+    );
+
+    self.stats.record_frame(dt);
+    self
+      .stats
+      .record_gpu(self.profiler.last_frame(), self.pipeline_stats.last_frame());
+    if let Some(viewport) = self.viewports.get(&self.primary) {
+      self.stats.publish(viewport.window());
+    }
+    self.input_map.end_frame();
   }
 
-  pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-    let output = self.surface.get_current_texture()?;
+  pub fn render(&mut self, window_id: WindowId) -> Result<(), wgpu::SurfaceError> {
+    let Some(viewport) = self.viewports.get(&window_id) else {
+      return Ok(());
+    };
+    let output = viewport.surface().get_current_texture()?;
+    // deterministic ticks go one per frame of the primary window, like --frames counts them
+    if window_id == self.primary && self.is_simulating() {
+      let ticks = self.stepper.advance_frame(self.clock.simulation());
+      self.sim_ticks += ticks;
+      self.step_physics(ticks);
+    }
+    let viewport = self.viewports.get_mut(&window_id).unwrap();
+    self.profiler.begin_frame(&self.device);
+    self.pipeline_stats.begin_frame(&self.device);
+    self.frame_graph.begin_frame();
     let view = output
       .texture
       .create_view(&wgpu::TextureViewDescriptor::default());
@@ -191,30 +1454,527 @@ impl State {
         label: Some("Render Encoder"),
       });
 
-    // the {} block borrows encoder mutably aka &mut self
+    // whichever window draws first runs the pending ticks, the rest draw the same state.
+    // Switched off the ticks are dropped, so it pauses instead of catching up later.
+    let ticks = std::mem::take(&mut self.sim_ticks);
+    if self.pass_toggles.enabled("simulation") {
+      let sim_scope = self.profiler.begin_pass(&mut encoder, "simulation");
+      self.boids.step(
+        &self.device,
+        &mut encoder,
+        ticks,
+        viewport.hdr().depth_sample_view(),
+      );
+      self.scene.step_crowd(
+        &self.queue,
+        ticks,
+        self.stepper.tick(),
+        self.stepper.alpha(),
+      );
+      self.scene.terrain_mut().step(&mut encoder);
+      self.profiler.end_pass(&mut encoder, sim_scope);
+      self.frame_graph.pass(
+        "simulation",
+        &["particles", "terrain cells", "depth"],
+        &["particles", "terrain cells", "terrain mesh"],
+      );
+    }
+
+    let show_scene = self.scene.is_enabled() && !viewport.compare().is_active();
+    let (width, height) = viewport.hdr().size();
+    let tile = viewport.tile();
+    let split = show_scene && self.split_screen.is_some();
+    // only the shadowed scene is jittered, TAA has nothing to do without it. Its history
+    // and motion vectors are of one camera, so it's off in split screen as well.
+    let taa = show_scene
+      && !split
+      && self.settings.render.antialiasing == AntiAliasing::Taa
+      && self.pass_toggles.enabled("taa");
+    let jitter = if taa {
+      let view_proj = self.scene.view_proj(&tile);
+      Some(viewport.taa_mut().begin_frame(
+        &mut self.uploader.with(&self.device, &mut encoder),
+        view_proj,
+        self.params.float("taa_history"),
+      ))
+    } else {
+      viewport.taa_mut().reset();
+      None
+    };
+
+    let hdr = viewport.hdr();
+    let compare = viewport.compare();
+    // the wireframe, split screen and MSAA are only in the forward pipeline
+    let deferred = show_scene
+      && !split
+      && self.settings.render.path == RenderPath::Deferred
+      && !self.scene.is_wireframe()
+      && hdr.sample_count() == 1;
+    if show_scene {
+      let mut upload = self.uploader.with(&self.device, &mut encoder);
+      self.scene.update(&mut upload, &tile, jitter.as_ref());
+      if let Some(split_screen) = self.split_screen.as_mut().filter(|_| split) {
+        split_screen.update(&self.scene, &mut upload, (width, height));
+      }
+      if let Some(camera) = &mut self.security_camera {
+        let time = self.clock.animation().elapsed();
+        camera.update(&self.scene, &mut upload, time);
+      }
+      if self.pass_toggles.enabled("light culling") {
+        let cull_scope = self.profiler.begin_pass(&mut encoder, "light culling");
+        self.scene.cull_lights(&mut encoder);
+        if let Some(split_screen) = self.split_screen.as_ref().filter(|_| split) {
+          split_screen.cull_lights(&self.scene, &mut encoder);
+        }
+        self.profiler.end_pass(&mut encoder, cull_scope);
+        self
+          .frame_graph
+          .pass("light culling", &["point lights"], &["light clusters"]);
+      }
+      let gpu_culling =
+        self.settings.render.gpu_culling && self.pass_toggles.enabled("object culling");
+      if gpu_culling {
+        // last frame's draw commands into the prepass, this frame's culled against it
+        let hiz = (self.settings.render.occlusion_culling
+          && self.pass_toggles.enabled("occlusion culling"))
+        .then(|| viewport.hiz());
+        if let Some(hiz) = hiz {
+          let prepass_scope = self.profiler.begin_pass(&mut encoder, "depth prepass");
+          self.scene.render_depth_prepass(&mut encoder, hiz);
+          self.profiler.end_pass(&mut encoder, prepass_scope);
+          let hiz_scope = self.profiler.begin_pass(&mut encoder, "hi-z");
+          hiz.build(&mut encoder);
+          self.profiler.end_pass(&mut encoder, hiz_scope);
+          self
+            .frame_graph
+            .pass("depth prepass", &["draw commands"], &["prepass depth"]);
+          self.frame_graph.pass("hi-z", &["prepass depth"], &["hi-z"]);
+        }
+        let cull_scope = self.profiler.begin_pass(&mut encoder, "object culling");
+        self.scene.cull_objects(&self.device, &mut encoder, hiz);
+        self.profiler.end_pass(&mut encoder, cull_scope);
+        let reads: &[&str] = if hiz.is_some() { &["hi-z"] } else { &[] };
+        self
+          .frame_graph
+          .pass("object culling", reads, &["draw commands"]);
+      }
+      self.scene.set_gpu_culling(gpu_culling);
+      if self.pass_toggles.enabled("shadow") {
+        let shadow_scope = self.profiler.begin_pass(&mut encoder, "shadow");
+        self.scene.render_shadows(&mut encoder);
+        self.profiler.end_pass(&mut encoder, shadow_scope);
+        self
+          .frame_graph
+          .pass("shadow", &["terrain mesh"], &["shadow map"]);
+      }
+      // into the texture the main pass draws the monitor with
+      if let Some(camera) = &self.security_camera {
+        let camera_scope = self.profiler.begin_pass(&mut encoder, "security camera");
+        camera.render(&self.scene, &mut encoder, viewport.color());
+        self.profiler.end_pass(&mut encoder, camera_scope);
+        self.frame_graph.pass(
+          "security camera",
+          &["shadow map", "light clusters"],
+          &["security camera"],
+        );
+      }
+    }
+    // a click this window's frame doesn't show the scene for is dropped
+    let pick = match self.pick_request {
+      Some((id, cursor)) if id == window_id => {
+        self.pick_request = None;
+        show_scene.then_some(cursor)
+      }
+      _ => None,
+    };
+    if let Some(cursor) = pick {
+      // the cursor is in window pixels, the scene may be rendered at another scale
+      let pixel = (
+        ((cursor[0] * width as f64 / viewport.size.width as f64) as u32).min(width - 1),
+        ((cursor[1] * height as f64 / viewport.size.height as f64) as u32).min(height - 1),
+      );
+      let pick_scope = self.profiler.begin_pass(&mut encoder, "picking");
+      self
+        .scene
+        .pick(&self.device, &mut encoder, (width, height), pixel);
+      self.profiler.end_pass(&mut encoder, pick_scope);
+      self
+        .frame_graph
+        .pass("picking", &["terrain mesh"], &["object ids"]);
+    }
+
+    if self.pass_toggles.enabled("main") {
+      let main_scope = self.profiler.begin_pass(&mut encoder, "main");
+      if deferred {
+        self.scene.render_deferred(
+          &self.device,
+          &mut encoder,
+          viewport.gbuffer(),
+          hdr,
+          viewport.color(),
+          self.settings.render.ssao,
+        );
+        self.frame_graph.pass(
+          "gbuffer",
+          &["terrain mesh", "draw commands"],
+          &["gbuffer", "depth"],
+        );
+        if self.settings.render.ssao {
+          self
+            .frame_graph
+            .pass("ssao", &["gbuffer", "depth"], &["occlusion"]);
+          self
+            .frame_graph
+            .pass("ssao blur", &["occlusion"], &["occlusion"]);
+        }
+        self.frame_graph.pass(
+          "deferred lighting",
+          &[
+            "gbuffer",
+            "depth",
+            "occlusion",
+            "shadow map",
+            "light clusters",
+          ],
+          &["hdr color"],
+        );
+      }
+      if compare.is_active() {
+        for side in 0..2 {
+          let mut pass = compare.begin_capture(&mut encoder, side, viewport.color());
+          pass.set_pipeline(compare.pipeline(side));
+          count_draw();
+          pass.draw(0..3, 0..1);
+        }
+        compare.composite(&mut encoder, hdr.view());
+        self
+          .frame_graph
+          .pass("compare captures", &[], &["compare sides"]);
+        self
+          .frame_graph
+          .pass("compare composite", &["compare sides"], &["hdr color"]);
+      } else {
+        // the {} block borrows encoder mutably aka &mut self
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+          label: Some("Render Pass"),
+          color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            // This is what @location(0) in the fragment shader targets
+            view: hdr.scene_view(),
+            resolve_target: hdr.scene_resolve_target(),
+            ops: wgpu::Operations {
+              // the deferred path already lit the scene meshes into it
+              load: if deferred {
+                wgpu::LoadOp::Load
+              } else {
+                wgpu::LoadOp::Clear(viewport.color())
+              },
+              store: true,
+            },
+          })],
+          depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+            view: hdr.scene_depth_view(),
+            depth_ops: Some(wgpu::Operations {
+              load: if deferred {
+                wgpu::LoadOp::Load
+              } else {
+                wgpu::LoadOp::Clear(1.0)
+              },
+              store: true,
+            }),
+            stencil_ops: Some(wgpu::Operations {
+              load: if deferred {
+                wgpu::LoadOp::Load
+              } else {
+                wgpu::LoadOp::Clear(0)
+              },
+              store: true,
+            }),
+          }),
+        });
+        let stats_scope = self.pipeline_stats.begin_pass(&mut render_pass);
+        // render_pipeline
+
+        if deferred {
+          // the PBR models only have a forward shader
+          self.scene.render_models(&mut render_pass);
+        } else if let Some(split_screen) = self.split_screen.as_ref().filter(|_| split) {
+          let mut pass = RegionPass::new(&mut render_pass, (width, height));
+          split_screen.render(&self.scene, &mut pass);
+        } else if show_scene {
+          self.scene.render(&mut render_pass);
+        } else {
+          render_pass.set_pipeline(viewport.main_pipe());
+          // draw something with 3 vertices, and 1 instance. This is where @builtin(vertex_index) comes from.
+          count_draw();
+          render_pass.draw(0..3, 0..1);
+        }
+        // drawn with the scene's camera over the whole window, split screen has the grid
+        if show_scene && !split {
+          self.scene.render_grid(&mut render_pass);
+          self.scene.render_ghosts(&mut render_pass);
+        }
+        if !split {
+          self.boids.render(&mut render_pass);
+        }
+        PipelineStatistics::end_pass(&mut render_pass, stats_scope);
+        drop(render_pass);
+        hdr.resolve_depth(&mut encoder);
+        let reads: &[&str] = match (deferred, show_scene) {
+          (true, _) => &[
+            "hdr color",
+            "depth",
+            "shadow map",
+            "light clusters",
+            "particles",
+          ],
+          (false, true) => &["shadow map", "light clusters", "draw commands", "particles"],
+          (false, false) => &["particles"],
+        };
+        let mut reads = reads.to_vec();
+        if show_scene && self.security_camera.is_some() {
+          reads.push("security camera");
+        }
+        self
+          .frame_graph
+          .pass("main", &reads, &["hdr color", "depth"]);
+      }
+      self.profiler.end_pass(&mut encoder, main_scope);
+    }
+
+    // the comparison captures have no depth to test against, they keep their clear color
+    if !compare.is_active() && self.pass_toggles.enabled("skybox") {
+      let sky_scope = self.profiler.begin_pass(&mut encoder, "skybox");
+      let mut upload = self.uploader.with(&self.device, &mut encoder);
+      self.skybox.render(&mut upload, hdr, self.sky_yaw, &tile);
+      self.profiler.end_pass(&mut encoder, sky_scope);
+      self
+        .frame_graph
+        .pass("skybox", &["hdr color", "depth"], &["hdr color"]);
+    }
+
+    // over the sky too, which it reflects, and before TAA so the waves get antialiased
+    if show_scene && !split && self.scene.water().is_enabled() && self.pass_toggles.enabled("water")
     {
-      let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-        label: Some("Render Pass"),
-        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-          // This is what @location(0) in the fragment shader targets
-          view: &view,
-          resolve_target: None,
-          ops: wgpu::Operations {
-            load: wgpu::LoadOp::Clear(self.color),
-            store: true,
-          },
-        })],
-        depth_stencil_attachment: None,
-      });
-      // render_pipeline
+      let water_scope = self.profiler.begin_pass(&mut encoder, "water");
+      self.scene.render_water(&self.device, &mut encoder, hdr);
+      self.profiler.end_pass(&mut encoder, water_scope);
+      self
+        .frame_graph
+        .pass("water", &["hdr color", "depth"], &["hdr color", "depth"]);
+    }
+
+    // over the water and the sky, so both fade into it with distance
+    if show_scene && !split && self.scene.fog().is_enabled() && self.pass_toggles.enabled("fog") {
+      let fog_scope = self.profiler.begin_pass(&mut encoder, "fog");
+      self.scene.render_fog(&self.device, &mut encoder, hdr);
+      self.profiler.end_pass(&mut encoder, fog_scope);
+      self
+        .frame_graph
+        .pass("fog", &["hdr color", "depth", "shadow map"], &["hdr color"]);
+    }
+
+    if taa {
+      let taa_scope = self.profiler.begin_pass(&mut encoder, "taa");
+      self.scene.render_velocity(
+        &mut encoder,
+        viewport.taa().velocity_view(),
+        hdr.depth_view(),
+      );
+      viewport.taa().resolve(&self.device, &mut encoder, hdr);
+      self.profiler.end_pass(&mut encoder, taa_scope);
+      self.frame_graph.pass("velocity", &["depth"], &["velocity"]);
+      self.frame_graph.pass(
+        "taa resolve",
+        &["hdr color", "taa history", "velocity"],
+        &["taa history", "hdr color"],
+      );
+    }
+
+    // after TAA, so the lines stay sharp instead of being blended over frames
+    if show_scene && !self.scene.debug().is_empty() && self.pass_toggles.enabled("debug lines") {
+      let debug_scope = self.profiler.begin_pass(&mut encoder, "debug lines");
+      self.scene.render_debug(
+        &self.device,
+        &self.queue,
+        &mut encoder,
+        hdr.view(),
+        hdr.depth_view(),
+      );
+      self.profiler.end_pass(&mut encoder, debug_scope);
+      self
+        .frame_graph
+        .pass("debug lines", &["depth"], &["hdr color"]);
+    }
+
+    if self.pass_toggles.enabled("exposure") {
+      let exposure_scope = self.profiler.begin_pass(&mut encoder, "exposure");
+      viewport.exposure().meter(&mut encoder, hdr);
+      self.profiler.end_pass(&mut encoder, exposure_scope);
+      self
+        .frame_graph
+        .pass("exposure", &["hdr color", "exposure"], &["exposure"]);
+    }
+
+    let fxaa = (self.settings.render.antialiasing == AntiAliasing::Fxaa).then(|| viewport.fxaa());
+    hdr.set_color_filter(&self.queue, self.settings.accessibility.color_filter);
+    if self.pass_toggles.enabled("tonemap") {
+      let tonemap_scope = self.profiler.begin_pass(&mut encoder, "tonemap");
+      hdr.tonemap(&mut encoder, fxaa.map_or(&view, |fxaa| fxaa.input_view()));
+      self.profiler.end_pass(&mut encoder, tonemap_scope);
+      let output = if fxaa.is_some() {
+        "ldr color"
+      } else {
+        "surface"
+      };
+      self
+        .frame_graph
+        .pass("tonemap", &["hdr color", "exposure"], &[output]);
+    }
+    if let Some(fxaa) = fxaa.filter(|_| self.pass_toggles.enabled("fxaa")) {
+      let fxaa_scope = self.profiler.begin_pass(&mut encoder, "fxaa");
+      fxaa.render(&mut encoder, &view);
+      self.profiler.end_pass(&mut encoder, fxaa_scope);
+      self.frame_graph.pass("fxaa", &["ldr color"], &["surface"]);
+    }
 
-      render_pass.set_pipeline(&self.main_pipe);
-      // draw something with 3 vertices, and 1 instance. This is where @builtin(vertex_index) comes from.
-      render_pass.draw(0..3, 0..1);
+    let screenshots: Vec<_> = if window_id == self.primary {
+      std::mem::take(&mut self.screenshot_requests)
+        .into_iter()
+        .map(|reply| {
+          let capture =
+            Screenshot::record(&self.device, &mut encoder, hdr, fxaa, viewport.format());
+          (capture, reply)
+        })
+        .collect()
+    } else {
+      Vec::new()
+    };
+    if !screenshots.is_empty() {
+      let screenshot_reads: &[&str] = match fxaa {
+        Some(_) => &["ldr color"],
+        None => &["hdr color", "exposure"],
+      };
+      self
+        .frame_graph
+        .pass("screenshot", screenshot_reads, &["screenshot"]);
     }
 
-    self.queue.submit(std::iter::once(encoder.finish()));
+    // drawn after tonemapping so the overlay keeps its exact colors
+    if let Some(text) = &mut self.text {
+      let mut overlay = format!("\n{}", self.stats.stats().summary());
+      if self.pass_toggles.is_visible() {
+        overlay += "\n";
+        overlay += &self.pass_toggles.overlay(&self.stats.stats().gpu_passes);
+      }
+      if self.scene.terrain().is_enabled() {
+        overlay += "\n";
+        overlay += &self.scene.terrain().summary();
+      }
+      if self.inspector.is_visible() {
+        overlay += "\n";
+        overlay += &self.inspector.overlay(&self.scene);
+      }
+      if self.latency.is_active() {
+        overlay += "\n";
+        overlay += &self.latency.overlay();
+      }
+      let high_contrast = self.settings.accessibility.high_contrast;
+      let (title_color, color) = if high_contrast {
+        ([1.0, 1.0, 0.0, 1.0], [1.0, 1.0, 0.0, 1.0])
+      } else {
+        ([0.5, 0.8, 1.0, 1.0], [1.0, 1.0, 1.0, 1.0])
+      };
+      let size = text.default_size();
+      let spans = [
+        TextSpan {
+          text: viewport.variant(),
+          size: size * 1.25,
+          color: title_color,
+        },
+        TextSpan {
+          text: &overlay,
+          size,
+          color,
+        },
+      ];
+      // long lines like the pass list wrap instead of running off narrow windows
+      let paragraph = Paragraph {
+        max_width: Some((viewport.size.width as f32 - 16.0).max(size)),
+        ..Paragraph::new(&spans, [8.0, 8.0])
+      };
+      let mut layouts = vec![text.layout(&paragraph)];
+      // the keymap is long, it gets the right half of the window to itself
+      if self.show_help {
+        let help = self.input_map.help(|context| match context {
+          KeyContext::Global => true,
+          KeyContext::Scene => self.scene.is_enabled(),
+          KeyContext::PassList => self.pass_toggles.is_visible(),
+          KeyContext::Inspector => self.inspector.is_visible(),
+          KeyContext::CursorGrab => self.grabbed.is_some(),
+        });
+        let spans = [TextSpan {
+          text: &help,
+          size,
+          color,
+        }];
+        let half = viewport.size.width as f32 * 0.5;
+        let paragraph = Paragraph {
+          max_width: Some((half - 8.0).max(size)),
+          ..Paragraph::new(&spans, [half, 8.0])
+        };
+        layouts.push(text.layout(&paragraph));
+      }
+      for layout in &layouts {
+        if high_contrast {
+          text.queue_layout_outlined(&self.queue, layout, size, [0.0, 0.0, 0.0, 1.0]);
+        } else {
+          text.queue_layout(&self.queue, layout, [0.0; 2], None);
+        }
+      }
+      let text_scope = self.profiler.begin_pass(&mut encoder, "text");
+      text.render(
+        &self.device,
+        &self.queue,
+        &mut encoder,
+        &view,
+        viewport.format(),
+        (viewport.size.width, viewport.size.height),
+      );
+      self.profiler.end_pass(&mut encoder, text_scope);
+      self.frame_graph.pass("text", &["surface"], &["surface"]);
+    }
+    // after everything else, so nothing draws over it
+    if self.latency.render(
+      &self.device,
+      &mut encoder,
+      window_id,
+      &view,
+      viewport.format(),
+      (viewport.size.width, viewport.size.height),
+    ) {
+      self
+        .frame_graph
+        .pass("latency flash", &["surface"], &["surface"]);
+    }
+    self.profiler.resolve(&mut encoder);
+    self.pipeline_stats.resolve(&mut encoder);
+
+    self.uploader.submit(&self.queue, encoder);
+    if pick.is_some() {
+      self.scene.map_pick();
+    }
+    for (capture, _) in &screenshots {
+      capture.map();
+    }
+    self.screenshots_in_flight.extend(screenshots);
+    self.profiler.end_frame();
+    self.pipeline_stats.end_frame();
+    self.power.frame_drawn();
     output.present();
+    self.latency.presented(window_id);
+    if let Some(graph) = self.frame_graph.finish_frame() {
+      save_to_files(&graph);
+    }
 
     Ok(())
   }
@@ -0,0 +1,173 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use serde::{Deserialize, Serialize};
+use winit::window::Window;
+
+use crate::{
+  frame_graph::json_string,
+  gpu_memory::{self, GpuMemory},
+  profiler::{PassTiming, PipelineCounters},
+};
+
+// How often the published numbers refresh, in seconds
+const PUBLISH_INTERVAL: f32 = 1.0;
+
+// Draw calls since the last record_frame(), from every window
+static DRAW_CALLS: AtomicU32 = AtomicU32::new(0);
+
+// Call next to every draw, draw_indexed and indirect draw on a render pass
+pub fn count_draw() {
+  DRAW_CALLS.fetch_add(1, Ordering::Relaxed);
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FrameStats {
+  pub fps: f32,
+  pub frame_ms: f32,
+  pub gpu_passes: Vec<PassTiming>,
+  pub pipeline: Option<PipelineCounters>,
+  // averaged over the frames since the last snapshot
+  pub draw_calls: u32,
+  // what's allocated when the snapshot is taken
+  pub memory: GpuMemory,
+}
+
+impl FrameStats {
+  pub fn gpu_ms(&self) -> f64 {
+    self.gpu_passes.iter().map(|p| p.gpu_ms).sum()
+  }
+
+  // One line summary, used for the window title and logs
+  pub fn summary(&self) -> String {
+    let mut line = format!(
+      "{:.0} fps ({:.2}ms) | {} draws | buf {:.1}MB tex {:.1}MB",
+      self.fps,
+      self.frame_ms,
+      self.draw_calls,
+      megabytes(self.memory.buffer_bytes),
+      megabytes(self.memory.texture_bytes)
+    );
+    if !self.gpu_passes.is_empty() {
+      line += &format!(" | gpu {:.2}ms", self.gpu_ms());
+    }
+    if let Some(counters) = self.pipeline {
+      line += &format!(
+        " | vs {} prim {} fs {}",
+        counters.vertex_invocations, counters.clipper_primitives, counters.fragment_invocations
+      );
+    }
+    line
+  }
+
+  // The same numbers as one line of JSON, for scripts and screen readers
+  pub fn json_line(&self) -> String {
+    let passes: Vec<String> = self
+      .gpu_passes
+      .iter()
+      .map(|p| {
+        format!(
+          "{{\"name\":{},\"gpu_ms\":{:.4}}}",
+          json_string(&p.label),
+          p.gpu_ms
+        )
+      })
+      .collect();
+    let pipeline = match self.pipeline {
+      Some(counters) => format!(
+        "{{\"vertex_invocations\":{},\"clipper_primitives\":{},\"fragment_invocations\":{}}}",
+        counters.vertex_invocations, counters.clipper_primitives, counters.fragment_invocations
+      ),
+      None => "null".to_string(),
+    };
+    format!(
+      "{{\"fps\":{:.2},\"frame_ms\":{:.3},\"gpu_ms\":{:.4},\"draw_calls\":{},\"buffer_bytes\":{},\"texture_bytes\":{},\"passes\":[{}],\"pipeline\":{}}}",
+      self.fps,
+      self.frame_ms,
+      self.gpu_ms(),
+      self.draw_calls,
+      self.memory.buffer_bytes,
+      self.memory.texture_bytes,
+      passes.join(","),
+      pipeline
+    )
+  }
+}
+
+fn megabytes(bytes: u64) -> f64 {
+  bytes as f64 / (1024.0 * 1024.0)
+}
+
+// The `[stats]` section of settings.toml
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StatsSettings {
+  // prints every published snapshot as a JSON line on stdout (the log on the web),
+  // next to the overlay and the window title
+  pub json_lines: bool,
+}
+
+// Accumulates per frame numbers and publishes an averaged snapshot once per interval
+pub struct StatsOverlay {
+  title: String,
+  json_lines: bool,
+  frames: u32,
+  elapsed: f32,
+  draw_calls: u32,
+  current: FrameStats,
+  published: FrameStats,
+}
+
+impl StatsOverlay {
+  pub fn new(title: &str, settings: &StatsSettings) -> Self {
+    Self {
+      title: title.to_string(),
+      json_lines: settings.json_lines,
+      frames: 0,
+      elapsed: 0.0,
+      draw_calls: 0,
+      current: FrameStats::default(),
+      published: FrameStats::default(),
+    }
+  }
+
+  pub fn record_frame(&mut self, dt: f32) {
+    self.frames += 1;
+    self.elapsed += dt;
+    self.draw_calls += DRAW_CALLS.swap(0, Ordering::Relaxed);
+  }
+
+  pub fn record_gpu(&mut self, passes: &[PassTiming], pipeline: Option<PipelineCounters>) {
+    self.current.gpu_passes = passes.to_vec();
+    self.current.pipeline = pipeline;
+  }
+
+  // Latest averaged numbers
+  pub fn stats(&self) -> &FrameStats {
+    &self.published
+  }
+
+  // Returns true when a new snapshot was published this frame
+  pub fn publish(&mut self, window: &Window) -> bool {
+    if self.elapsed < PUBLISH_INTERVAL || self.frames == 0 {
+      return false;
+    }
+    self.current.fps = self.frames as f32 / self.elapsed;
+    self.current.frame_ms = self.elapsed * 1000.0 / self.frames as f32;
+    self.current.draw_calls = self.draw_calls / self.frames;
+    self.current.memory = gpu_memory::allocated();
+    self.published = self.current.clone();
+    self.frames = 0;
+    self.elapsed = 0.0;
+    self.draw_calls = 0;
+
+    window.set_title(&format!("{} | {}", self.title, self.published.summary()));
+    if self.json_lines {
+      let line = self.published.json_line();
+      #[cfg(target_arch = "wasm32")]
+      log::info!("{}", line);
+      #[cfg(not(target_arch = "wasm32"))]
+      println!("{}", line);
+    }
+    true
+  }
+}
@@ -0,0 +1,100 @@
+use std::path::PathBuf;
+
+// localStorage key the settings live under on the web
+pub const STORAGE_KEY: &str = "wgpu-learn.settings";
+
+#[derive(Debug)]
+pub enum StorageError {
+  Io(PathBuf, std::io::Error),
+  // no localStorage (private browsing, sandboxed iframes) or it refused the write
+  Unavailable(String),
+}
+
+impl std::fmt::Display for StorageError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      StorageError::Io(path, e) => write!(f, "couldn't write {}: {}", path.display(), e),
+      StorageError::Unavailable(message) => write!(f, "local storage unavailable: {}", message),
+    }
+  }
+}
+
+impl std::error::Error for StorageError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      StorageError::Io(_, e) => Some(e),
+      _ => None,
+    }
+  }
+}
+
+// Where the settings text is kept between runs, the config file on native and
+// localStorage in the browser so a refresh doesn't reset everything
+pub enum SettingsStorage {
+  File(PathBuf),
+  #[cfg(target_arch = "wasm32")]
+  LocalStorage(String),
+}
+
+impl SettingsStorage {
+  pub fn platform_default() -> Self {
+    #[cfg(target_arch = "wasm32")]
+    {
+      SettingsStorage::LocalStorage(STORAGE_KEY.to_string())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+      SettingsStorage::File(PathBuf::from(crate::config::CONFIG_PATH))
+    }
+  }
+
+  // Where the settings are read from, for log messages
+  pub fn describe(&self) -> String {
+    match self {
+      SettingsStorage::File(path) => path.display().to_string(),
+      #[cfg(target_arch = "wasm32")]
+      SettingsStorage::LocalStorage(key) => format!("localStorage[{}]", key),
+    }
+  }
+
+  // None when nothing was saved yet (or it can't be read)
+  pub fn load(&self) -> Option<String> {
+    match self {
+      SettingsStorage::File(path) => std::fs::read_to_string(path).ok(),
+      #[cfg(target_arch = "wasm32")]
+      SettingsStorage::LocalStorage(key) => web::local_storage().ok()?.get_item(key).ok()?,
+    }
+  }
+
+  pub fn save(&self, text: &str) -> Result<(), StorageError> {
+    match self {
+      SettingsStorage::File(path) => {
+        std::fs::write(path, text).map_err(|e| StorageError::Io(path.clone(), e))
+      }
+      #[cfg(target_arch = "wasm32")]
+      SettingsStorage::LocalStorage(key) => web::local_storage()?
+        .set_item(key, text)
+        .map_err(web::unavailable),
+    }
+  }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod web {
+  use wasm_bindgen::JsValue;
+
+  use super::StorageError;
+
+  pub fn unavailable(value: JsValue) -> StorageError {
+    StorageError::Unavailable(value.as_string().unwrap_or_else(|| format!("{:?}", value)))
+  }
+
+  pub fn local_storage() -> Result<web_sys::Storage, StorageError> {
+    web_sys::window()
+      .ok_or_else(|| StorageError::Unavailable("no window".to_string()))?
+      .local_storage()
+      .map_err(unavailable)?
+      .ok_or_else(|| StorageError::Unavailable("disabled by the browser".to_string()))
+  }
+}
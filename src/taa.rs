@@ -0,0 +1,298 @@
+use wgpu::{CommandEncoder, Device, TextureFormat, TextureView};
+
+use crate::{
+  gpu_memory::Tracked,
+  hdr::{HdrPipeline, HDR_FORMAT},
+  math::Mat4,
+  sampler::SamplerSettings,
+  scene::CameraJitter,
+  stats::count_draw,
+  uploader::Upload,
+};
+
+// Screen space motion in texture coordinates, written by velocity.wgsl
+pub const VELOCITY_FORMAT: TextureFormat = TextureFormat::Rg16Float;
+// Jitter positions before the pattern repeats
+const JITTER_SAMPLES: u32 = 8;
+// Share of the history in every resolved pixel, higher is smoother but slower to react. The
+// "taa_history" param starts at it.
+pub const HISTORY_WEIGHT: f32 = 0.9;
+
+// Matches `TaaParams` in taa.wgsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct TaaParams {
+  history_weight: f32,
+  _padding: [f32; 3],
+}
+
+// Element `index` of the Halton sequence in `base`, evenly spread points in 0..1
+fn halton(mut index: u32, base: u32) -> f32 {
+  let mut result = 0.0;
+  let mut fraction = 1.0;
+  while index > 0 {
+    fraction /= base as f32;
+    result += fraction * (index % base) as f32;
+    index /= base;
+  }
+  result
+}
+
+struct Targets {
+  // resolved colors of the last two frames, read one and write the other
+  history: [(Tracked<wgpu::Texture>, TextureView); 2],
+  velocity: Tracked<TextureView>,
+}
+
+// Temporal anti-aliasing: every frame the camera shifts by a different fraction of a pixel
+// and resolve() averages the results over time, following the motion vectors
+pub struct Taa {
+  targets: Targets,
+  sampler: wgpu::Sampler,
+  params_buffer: Tracked<wgpu::Buffer>,
+  layout: wgpu::BindGroupLayout,
+  pipeline: wgpu::RenderPipeline,
+  // the history resolve() writes this frame, the other one holds the last frame
+  current: usize,
+  history_valid: bool,
+  frame: u32,
+  previous_view_proj: Option<Mat4>,
+  width: u32,
+  height: u32,
+}
+
+impl Taa {
+  pub fn new(device: &Device, width: u32, height: u32) -> Self {
+    let sampler = device.create_sampler(&SamplerSettings::linear().descriptor(Some("Taa Sampler")));
+    let params_buffer = Tracked::buffer(device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Taa Params Buffer"),
+      size: std::mem::size_of::<TaaParams>() as wgpu::BufferAddress,
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    }));
+
+    let texture_entry = |binding, filterable| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility: wgpu::ShaderStages::FRAGMENT,
+      ty: wgpu::BindingType::Texture {
+        sample_type: wgpu::TextureSampleType::Float { filterable },
+        view_dimension: wgpu::TextureViewDimension::D2,
+        multisampled: false,
+      },
+      count: None,
+    };
+    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Taa Bind Group Layout"),
+      entries: &[
+        texture_entry(0, false),
+        texture_entry(1, true),
+        wgpu::BindGroupLayoutEntry {
+          binding: 2,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+          count: None,
+        },
+        texture_entry(3, false),
+        wgpu::BindGroupLayoutEntry {
+          binding: 4,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+          },
+          count: None,
+        },
+      ],
+    });
+
+    let shader = device.create_shader_module(wgpu::include_wgsl!("taa.wgsl"));
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Taa Pipeline Layout"),
+      bind_group_layouts: &[&layout],
+      push_constant_ranges: &[],
+    });
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+      label: Some("Taa Pipeline"),
+      layout: Some(&pipeline_layout),
+      vertex: wgpu::VertexState {
+        module: &shader,
+        entry_point: "vs_main",
+        buffers: &[],
+      },
+      fragment: Some(wgpu::FragmentState {
+        module: &shader,
+        entry_point: "fs_main",
+        targets: &[Some(wgpu::ColorTargetState {
+          format: HDR_FORMAT,
+          blend: Some(wgpu::BlendState::REPLACE),
+          write_mask: wgpu::ColorWrites::ALL,
+        })],
+      }),
+      primitive: wgpu::PrimitiveState::default(),
+      depth_stencil: None,
+      multisample: wgpu::MultisampleState::default(),
+      multiview: None,
+    });
+
+    Self {
+      targets: create_targets(device, width, height),
+      sampler,
+      params_buffer,
+      layout,
+      pipeline,
+      current: 0,
+      history_valid: false,
+      frame: 0,
+      previous_view_proj: None,
+      width,
+      height,
+    }
+  }
+
+  // The history is thrown away, it doesn't fit the new size
+  pub fn resize(&mut self, device: &Device, width: u32, height: u32) {
+    self.targets = create_targets(device, width, height);
+    self.width = width;
+    self.height = height;
+    self.reset();
+  }
+
+  // Starts over without history, for frames that skipped TAA or cut to something else
+  pub fn reset(&mut self) {
+    self.history_valid = false;
+    self.previous_view_proj = None;
+  }
+
+  // Starts a frame resolved with TAA: returns its projection shift given the camera's
+  // unjittered `view_proj`, which becomes the previous one of the next frame.
+  // `history_weight` is the share of the history, see HISTORY_WEIGHT.
+  pub fn begin_frame(
+    &mut self,
+    upload: &mut Upload,
+    view_proj: Mat4,
+    history_weight: f32,
+  ) -> CameraJitter {
+    let params = TaaParams {
+      history_weight: if self.history_valid {
+        history_weight
+      } else {
+        0.0
+      },
+      _padding: [0.0; 3],
+    };
+    upload.write(&self.params_buffer, 0, bytemuck::bytes_of(&params));
+    self.current = 1 - self.current;
+    self.history_valid = true;
+
+    self.frame = (self.frame + 1) % JITTER_SAMPLES;
+    // -0.5..0.5 pixels, skipping the first Halton points which sit on the axes
+    let index = self.frame + 1;
+    let pixels = [halton(index, 2) - 0.5, halton(index, 3) - 0.5];
+    let previous_view_proj = self
+      .previous_view_proj
+      .replace(view_proj)
+      .unwrap_or(view_proj);
+    CameraJitter {
+      offset: [
+        pixels[0] * 2.0 / self.width as f32,
+        pixels[1] * 2.0 / self.height as f32,
+      ],
+      previous_view_proj,
+    }
+  }
+
+  // Target of Scene::render_velocity()
+  pub fn velocity_view(&self) -> &TextureView {
+    &self.targets.velocity
+  }
+
+  // Blends `hdr`'s color into the history and copies the result back into it, after the
+  // velocity pass and before anything reads the color
+  pub fn resolve(&self, device: &Device, encoder: &mut CommandEncoder, hdr: &HdrPipeline) {
+    let previous = &self.targets.history[1 - self.current].1;
+    let (next_texture, next_view) = &self.targets.history[self.current];
+    let texture = |binding, view| wgpu::BindGroupEntry {
+      binding,
+      resource: wgpu::BindingResource::TextureView(view),
+    };
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Taa Bind Group"),
+      layout: &self.layout,
+      entries: &[
+        texture(0, hdr.view()),
+        texture(1, previous),
+        wgpu::BindGroupEntry {
+          binding: 2,
+          resource: wgpu::BindingResource::Sampler(&self.sampler),
+        },
+        texture(3, &self.targets.velocity),
+        wgpu::BindGroupEntry {
+          binding: 4,
+          resource: self.params_buffer.as_entire_binding(),
+        },
+      ],
+    });
+
+    {
+      let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Taa Resolve Pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+          view: next_view,
+          resolve_target: None,
+          ops: wgpu::Operations {
+            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+            store: true,
+          },
+        })],
+        depth_stencil_attachment: None,
+      });
+      pass.set_pipeline(&self.pipeline);
+      pass.set_bind_group(0, &bind_group, &[]);
+      count_draw();
+      pass.draw(0..3, 0..1);
+    }
+    encoder.copy_texture_to_texture(
+      next_texture.as_image_copy(),
+      hdr.texture().as_image_copy(),
+      wgpu::Extent3d {
+        width: self.width,
+        height: self.height,
+        depth_or_array_layers: 1,
+      },
+    );
+  }
+}
+
+fn create_targets(device: &Device, width: u32, height: u32) -> Targets {
+  let create = |label, format, usage| {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+      label: Some(label),
+      size: wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+      },
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format,
+      usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING | usage,
+      view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, view)
+  };
+  let (velocity, velocity_view) = create(
+    "Taa Velocity",
+    VELOCITY_FORMAT,
+    wgpu::TextureUsages::empty(),
+  );
+  Targets {
+    history: ["Taa History A", "Taa History B"].map(|label| {
+      let (texture, view) = create(label, HDR_FORMAT, wgpu::TextureUsages::COPY_SRC);
+      (Tracked::texture(texture), view)
+    }),
+    velocity: Tracked::view(&velocity, velocity_view),
+  }
+}
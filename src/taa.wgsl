@@ -0,0 +1,70 @@
+// TAA resolve: blends this frame's jittered color into the history reprojected along the
+// motion vectors. Clamping the history to the colors around the pixel throws out what
+// doesn't belong there anymore instead of leaving ghosts.
+
+struct TaaParams {
+    // share of the history in the result, 0 while there is none
+    history_weight: f32,
+};
+
+@group(0) @binding(0)
+var current_map: texture_2d<f32>;
+@group(0) @binding(1)
+var history_map: texture_2d<f32>;
+@group(0) @binding(2)
+var history_sampler: sampler;
+@group(0) @binding(3)
+var velocity_map: texture_2d<f32>;
+@group(0) @binding(4)
+var<uniform> params: TaaParams;
+
+// One triangle that covers the whole screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> @builtin(position) vec4<f32> {
+    let x = f32(i32(in_vertex_index & 1u) * 4 - 1);
+    let y = f32(i32(in_vertex_index >> 1u) * 4 - 1);
+    return vec4<f32>(x, y, 0.0, 1.0);
+}
+
+fn luma(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+// Squashes hdr colors into 0..1 so a single bright pixel can't dominate the blend
+fn compress(color: vec3<f32>) -> vec3<f32> {
+    return color / (1.0 + luma(color));
+}
+
+fn uncompress(color: vec3<f32>) -> vec3<f32> {
+    return color / max(1.0 - luma(color), 0.0001);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(position.xy);
+    let last = vec2<i32>(textureDimensions(current_map)) - 1;
+    let current = compress(textureLoad(current_map, pixel, 0).rgb);
+
+    var low = current;
+    var high = current;
+    for (var y = -1; y <= 1; y += 1) {
+        for (var x = -1; x <= 1; x += 1) {
+            let neighbor = clamp(pixel + vec2<i32>(x, y), vec2<i32>(0), last);
+            let color = compress(textureLoad(current_map, neighbor, 0).rgb);
+            low = min(low, color);
+            high = max(high, color);
+        }
+    }
+
+    let size = vec2<f32>(textureDimensions(current_map));
+    let uv = position.xy / size;
+    let previous_uv = uv - textureLoad(velocity_map, pixel, 0).xy;
+    var weight = params.history_weight;
+    // came from off screen, there's no history for it
+    if any(previous_uv < vec2<f32>(0.0)) || any(previous_uv > vec2<f32>(1.0)) {
+        weight = 0.0;
+    }
+    let history = textureSampleLevel(history_map, history_sampler, previous_uv, 0.0).rgb;
+    let clamped = clamp(compress(history), low, high);
+    return vec4<f32>(uncompress(mix(current, clamped, weight)), 1.0);
+}
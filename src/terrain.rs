@@ -0,0 +1,823 @@
+// Terrain from a heightmap, either fractal Perlin noise or the brightness of an image, as a
+// grid mesh split into square chunks so the ones out of view aren't drawn. The scene shaders
+// color it by height and steepness, see terrain_color() in common.wgsl. T shows it in place
+// of the cubes and plane, R erodes it.
+
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+use wgpu::{util::DeviceExt, CommandEncoder, Device, Queue, RenderPass};
+
+use crate::{
+  gpu_memory::Tracked,
+  lsystem::LSystemError,
+  math::{self, Mat4, Vec3},
+  mesh::{Mesh, MeshVertex},
+  plants::{PlantSettings, Plants},
+  raycast::Aabb,
+  scene::{GpuMesh, SceneInstance},
+  stats::count_draw,
+};
+
+// Cells per side, each one a vertex of the terrain mesh. One more than a multiple of
+// CHUNK_QUADS so the chunks fit exactly.
+const RESOLUTION: u32 = 129;
+// Same size as the scene's ground plane
+const HALF_EXTENT: f32 = 5.0;
+const MAX_HEIGHT: f32 = 2.5;
+const OCTAVES: u32 = 5;
+// Same seed every run so the terrain is reproducible
+const SEED: u32 = 0x9e37_79b9;
+// Quads per side of a chunk at full detail
+const CHUNK_QUADS: u32 = 16;
+// Levels of detail, each with half as many quads per side as the one before. Keep in sync
+// with LODS in erosion.wgsl.
+const LODS: u32 = 4;
+// Chunks closer to the camera than this are drawn at full detail, each LOD after that
+// reaches twice as far as the one before
+const LOD_DISTANCE: f32 = 4.0;
+// Room above and below a chunk's heights in its bounds, for what erosion moves around
+const BOUNDS_MARGIN: f32 = 0.25;
+// Iterations per frame while eroding, low enough to watch the terrain change
+const ITERATIONS_PER_FRAME: u32 = 10;
+const CELLS_PER_GROUP: u32 = 8;
+
+// Matches `Params` in erosion.wgsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct ErosionParams {
+  resolution: u32,
+  cell_size: f32,
+  half_extent: f32,
+  dt: f32,
+  rain: f32,
+  pipe: f32,
+  capacity: f32,
+  dissolve: f32,
+  deposit: f32,
+  evaporation: f32,
+  talus: f32,
+  thermal: f32,
+}
+
+// Matches `Cell` in erosion.wgsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Cell {
+  height: f32,
+  water: f32,
+  sediment: f32,
+  _padding: f32,
+}
+
+// Integer hash of a lattice point, 0..1
+fn lattice(x: i32, z: i32, seed: u32) -> f32 {
+  let mut h = (x as u32).wrapping_mul(0x8da6_b343) ^ (z as u32).wrapping_mul(0xd816_3841) ^ seed;
+  h ^= h >> 13;
+  h = h.wrapping_mul(0x5bd1_e995);
+  h ^= h >> 15;
+  h as f32 / u32::MAX as f32
+}
+
+// Perlin gradient noise, roughly -1..1 and 0 on every lattice point. Each point gets a
+// gradient pointing a random way, in between they're blended with the quintic fade.
+fn perlin(x: f32, z: f32, seed: u32) -> f32 {
+  let (x0, z0) = (x.floor(), z.floor());
+  let fade = |t: f32| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+  let (fx, fz) = (x - x0, z - z0);
+  let (x0, z0) = (x0 as i32, z0 as i32);
+  let corner = |dx: i32, dz: i32| {
+    let angle = lattice(x0 + dx, z0 + dz, seed) * std::f32::consts::TAU;
+    angle.cos() * (fx - dx as f32) + angle.sin() * (fz - dz as f32)
+  };
+  let (tx, tz) = (fade(fx), fade(fz));
+  let top = corner(0, 0) + (corner(1, 0) - corner(0, 0)) * tx;
+  let bottom = corner(0, 1) + (corner(1, 1) - corner(0, 1)) * tx;
+  // the unit gradients reach at most half the diagonal
+  (top + (bottom - top) * tz) * std::f32::consts::SQRT_2
+}
+
+// Fractal noise hills, pushed down towards the edges so the terrain sits in a basin
+pub fn generate_heights(resolution: u32, seed: u32) -> Vec<f32> {
+  (0..resolution * resolution)
+    .map(|i| {
+      let u = (i % resolution) as f32 / (resolution - 1) as f32;
+      let v = (i / resolution) as f32 / (resolution - 1) as f32;
+      let (mut amplitude, mut frequency, mut sum) = (0.5, 4.0, 0.0);
+      for octave in 0..OCTAVES {
+        let noise = 0.5 + 0.5 * perlin(u * frequency, v * frequency, seed.wrapping_add(octave));
+        sum += amplitude * noise;
+        amplitude *= 0.5;
+        frequency *= 2.0;
+      }
+      let edge = (u.min(1.0 - u).min(v).min(1.0 - v) * 4.0).min(1.0);
+      sum.clamp(0.0, 1.0) * edge * MAX_HEIGHT
+    })
+    .collect()
+}
+
+// The `[terrain]` section of the config file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TerrainSettings {
+  // a grayscale image to take the heights from instead of the noise, white is the highest
+  pub heightmap: Option<String>,
+  // of the noise
+  pub seed: u32,
+}
+
+impl Default for TerrainSettings {
+  fn default() -> Self {
+    Self {
+      heightmap: None,
+      seed: SEED,
+    }
+  }
+}
+
+impl TerrainSettings {
+  // With `--heightmap path` from the command line over the file's
+  pub fn from_args(&self, heightmap: Option<&str>) -> Self {
+    Self {
+      heightmap: heightmap
+        .map(str::to_string)
+        .or_else(|| self.heightmap.clone()),
+      ..self.clone()
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum TerrainError {
+  Image(String, image::ImageError),
+}
+
+impl std::fmt::Display for TerrainError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      TerrainError::Image(path, e) => write!(f, "couldn't load the heightmap {}: {}", path, e),
+    }
+  }
+}
+
+impl std::error::Error for TerrainError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      TerrainError::Image(_, e) => Some(e),
+    }
+  }
+}
+
+// Heights of a `resolution` by `resolution` grid over the terrain, a row at a time along +x
+#[derive(Debug, Clone, PartialEq)]
+pub struct Heightmap {
+  resolution: u32,
+  heights: Vec<f32>,
+}
+
+impl Heightmap {
+  pub fn generate(resolution: u32, seed: u32) -> Self {
+    Self {
+      resolution,
+      heights: generate_heights(resolution, seed),
+    }
+  }
+
+  // The image's brightness resampled to `resolution` per side, black is 0 and white
+  // MAX_HEIGHT
+  pub fn load(path: &str, resolution: u32) -> Result<Self, TerrainError> {
+    let image = image::open(path)
+      .map_err(|e| TerrainError::Image(path.to_string(), e))?
+      .into_luma16();
+    let (width, height) = image.dimensions();
+    let texel = |x: u32, y: u32| {
+      let [luma] = image.get_pixel(x.min(width - 1), y.min(height - 1)).0;
+      luma as f32 / u16::MAX as f32
+    };
+    let heights = (0..resolution * resolution)
+      .map(|i| {
+        // bilinear, so a small image doesn't come out in steps
+        let u = (i % resolution) as f32 / (resolution - 1) as f32 * (width - 1) as f32;
+        let v = (i / resolution) as f32 / (resolution - 1) as f32 * (height - 1) as f32;
+        let (x, y) = (u.floor() as u32, v.floor() as u32);
+        let (tx, ty) = (u.fract(), v.fract());
+        let top = texel(x, y) + (texel(x + 1, y) - texel(x, y)) * tx;
+        let bottom = texel(x, y + 1) + (texel(x + 1, y + 1) - texel(x, y + 1)) * tx;
+        (top + (bottom - top) * ty) * MAX_HEIGHT
+      })
+      .collect();
+    Ok(Self {
+      resolution,
+      heights,
+    })
+  }
+
+  // The image in `settings` if there is one, otherwise the noise
+  pub fn from_settings(settings: &TerrainSettings) -> Result<Self, TerrainError> {
+    match &settings.heightmap {
+      Some(path) => Self::load(path, RESOLUTION),
+      None => Ok(Self::generate(RESOLUTION, settings.seed)),
+    }
+  }
+
+  pub fn resolution(&self) -> u32 {
+    self.resolution
+  }
+
+  pub fn heights(&self) -> &[f32] {
+    &self.heights
+  }
+
+  // Clamped to the edges
+  fn height(&self, x: i32, z: i32) -> f32 {
+    let last = self.resolution as i32 - 1;
+    self.heights[(z.clamp(0, last) * (last + 1) + x.clamp(0, last)) as usize]
+  }
+
+  // The grid mesh's vertices, the same as cs_mesh in erosion.wgsl makes from the cells. The
+  // uv isn't a texture coordinate, it's what the vertex shader needs to morph the vertex
+  // between LODs, see morph().
+  pub fn vertices(&self) -> Vec<MeshVertex> {
+    let resolution = self.resolution;
+    let cell_size = 2.0 * HALF_EXTENT / (resolution - 1) as f32;
+    (0..resolution * resolution)
+      .map(|i| {
+        let (x, z) = ((i % resolution) as i32, (i / resolution) as i32);
+        let gradient = [
+          self.height(x + 1, z) - self.height(x - 1, z),
+          self.height(x, z + 1) - self.height(x, z - 1),
+        ]
+        .map(|d| d / (2.0 * cell_size));
+        let [tx, ty, tz] = math::normalize([1.0, gradient[0], 0.0]);
+        MeshVertex {
+          position: [
+            x as f32 * cell_size - HALF_EXTENT,
+            self.height(x, z),
+            z as f32 * cell_size - HALF_EXTENT,
+          ],
+          normal: math::normalize([-gradient[0], 1.0, -gradient[1]]),
+          uv: self.morph(x, z),
+          // v runs along +z, which is cross(tangent, normal)
+          tangent: [tx, ty, tz, -1.0],
+        }
+      })
+      .collect()
+  }
+
+  // The last LOD the vertex at `x`, `z` is part of, and how far up it has to move to lie on
+  // the next LOD's triangles. Vertices of the coarsest LOD don't move.
+  fn morph(&self, x: i32, z: i32) -> [f32; 2] {
+    let lod = (0..LODS - 1)
+      .take_while(|lod| (x | z) & (1 << lod) == 0)
+      .count() as i32;
+    if lod == LODS as i32 - 1 {
+      return [lod as f32, 0.0];
+    }
+    let step = 1 << lod;
+    let coarse = 2 * step;
+    // halfway along the next LOD's edge, or its quad's diagonal from +x to +z
+    let target = match (x % coarse != 0, z % coarse != 0) {
+      (true, false) => self.height(x - step, z) + self.height(x + step, z),
+      (false, true) => self.height(x, z - step) + self.height(x, z + step),
+      _ => self.height(x + step, z - step) + self.height(x - step, z + step),
+    } / 2.0;
+    [lod as f32, target - self.height(x, z)]
+  }
+}
+
+// A square of CHUNK_QUADS quads a side, drawn at one LOD or skipped as a whole
+struct Chunk {
+  // its vertex with the smallest x and z, the patterns' indices count from there
+  origin: u32,
+  bounds: Aabb,
+}
+
+// The chunks of the grid, a row at a time along +x, with bounds around `heightmap`
+fn chunk_grid(heightmap: &Heightmap) -> Vec<Chunk> {
+  let resolution = heightmap.resolution;
+  let quads = resolution - 1;
+  assert_eq!(quads % CHUNK_QUADS, 0, "the chunks have to fit the grid");
+  let cell_size = 2.0 * HALF_EXTENT / quads as f32;
+  let corner = |i: u32| i as f32 * cell_size - HALF_EXTENT;
+  let mut chunks = Vec::new();
+  for chunk_z in (0..quads).step_by(CHUNK_QUADS as usize) {
+    for chunk_x in (0..quads).step_by(CHUNK_QUADS as usize) {
+      let (mut low, mut high) = (f32::MAX, f32::MIN);
+      for z in chunk_z..=chunk_z + CHUNK_QUADS {
+        for x in chunk_x..=chunk_x + CHUNK_QUADS {
+          let height = heightmap.heights[(z * resolution + x) as usize];
+          low = low.min(height);
+          high = high.max(height);
+        }
+      }
+      chunks.push(Chunk {
+        origin: chunk_z * resolution + chunk_x,
+        bounds: Aabb {
+          min: [corner(chunk_x), low - BOUNDS_MARGIN, corner(chunk_z)],
+          max: [
+            corner(chunk_x + CHUNK_QUADS),
+            high + BOUNDS_MARGIN,
+            corner(chunk_z + CHUNK_QUADS),
+          ],
+        },
+      });
+    }
+  }
+  chunks
+}
+
+// A chunk's sides, for which of them are stitched to a coarser neighbour
+const SIDE_NEAR_Z: u32 = 1;
+const SIDE_FAR_X: u32 = 2;
+const SIDE_FAR_Z: u32 = 4;
+const SIDE_NEAR_X: u32 = 8;
+const SIDE_COMBINATIONS: usize = 16;
+
+// The triangles of a chunk at every LOD with every combination of stitched sides, one after
+// another in one index buffer. The ranges are at `lod * SIDE_COMBINATIONS + sides`.
+fn lod_patterns(resolution: u32) -> (Vec<u32>, Vec<Range<u32>>) {
+  let mut indices = Vec::new();
+  let mut patterns = Vec::new();
+  for lod in 0..LODS {
+    for sides in 0..SIDE_COMBINATIONS as u32 {
+      let start = indices.len() as u32;
+      indices.extend(lod_pattern(resolution, lod, sides));
+      patterns.push(start..indices.len() as u32);
+    }
+  }
+  (indices, patterns)
+}
+
+// Two counter clockwise triangles per quad of the LOD, seen from above. Along the `sides`
+// that meet a neighbour one LOD coarser, every other vertex is moved onto the one before it,
+// so those sides only use the neighbour's vertices and no cracks open between them.
+fn lod_pattern(resolution: u32, lod: u32, sides: u32) -> Vec<u32> {
+  let step = 1 << lod;
+  let coarse = 2 * step;
+  let stitched = |side: u32| sides & side != 0;
+  let vertex = |mut x: u32, mut z: u32| {
+    if (z == 0 && stitched(SIDE_NEAR_Z)) || (z == CHUNK_QUADS && stitched(SIDE_FAR_Z)) {
+      x -= x % coarse;
+    }
+    if (x == 0 && stitched(SIDE_NEAR_X)) || (x == CHUNK_QUADS && stitched(SIDE_FAR_X)) {
+      z -= z % coarse;
+    }
+    [x as i32, z as i32]
+  };
+  let mut indices = Vec::new();
+  for z in (0..CHUNK_QUADS).step_by(step as usize) {
+    for x in (0..CHUNK_QUADS).step_by(step as usize) {
+      let (i, below) = (vertex(x, z), vertex(x, z + step));
+      let (right, diagonal) = (vertex(x + step, z), vertex(x + step, z + step));
+      for [a, b, c] in [[i, below, right], [right, below, diagonal]] {
+        // the moved vertices leave some of them with no area
+        let area = (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0]);
+        if area != 0 {
+          indices.extend([a, b, c].map(|[x, z]| z as u32 * resolution + x as u32));
+        }
+      }
+    }
+  }
+  indices
+}
+
+// Procedural heightmap shaped by a hydraulic and thermal erosion simulation on the GPU. The
+// simulation writes the vertices directly, so the scene draws it like any other mesh.
+pub struct Terrain {
+  // the index buffer holds lod_patterns(), not the whole grid
+  mesh: GpuMesh,
+  patterns: Vec<Range<u32>>,
+  chunks: Vec<Chunk>,
+  // of the chunks as of the last cull(), whether they're drawn and with which pattern
+  visible: Vec<bool>,
+  chunk_patterns: Vec<usize>,
+  instance_buffer: Tracked<wgpu::Buffer>,
+  // the heights erosion starts from, copied back in on reset
+  initial_buffer: Tracked<wgpu::Buffer>,
+  // cells[0] holds the state between iterations
+  cell_buffers: [Tracked<wgpu::Buffer>; 2],
+  flux_buffer: Tracked<wgpu::Buffer>,
+  // bind group i reads cells[i] and writes the other one
+  bind_groups: [wgpu::BindGroup; 2],
+  flux_pipeline: wgpu::ComputePipeline,
+  water_pipeline: wgpu::ComputePipeline,
+  transport_pipeline: wgpu::ComputePipeline,
+  mesh_pipeline: wgpu::ComputePipeline,
+  // grow on the terrain's vertices and move with them
+  plants: Plants,
+  pending: u32,
+  iterations: u32,
+  reset: bool,
+  mesh_dirty: bool,
+  enabled: bool,
+}
+
+impl Terrain {
+  pub fn new(device: &Device) -> Self {
+    let cell_size = 2.0 * HALF_EXTENT / (RESOLUTION - 1) as f32;
+    let params = ErosionParams {
+      resolution: RESOLUTION,
+      cell_size,
+      half_extent: HALF_EXTENT,
+      dt: 0.05,
+      rain: 0.002,
+      pipe: 20.0,
+      capacity: 0.02,
+      dissolve: 0.5,
+      deposit: 0.5,
+      evaporation: 0.5,
+      // about 40 degrees
+      talus: 0.8 * cell_size,
+      thermal: 0.5,
+    };
+    let params_buffer = Tracked::buffer(device.create_buffer_init(
+      &wgpu::util::BufferInitDescriptor {
+        label: Some("Erosion Params Buffer"),
+        contents: bytemuck::bytes_of(&params),
+        usage: wgpu::BufferUsages::UNIFORM,
+      },
+    ));
+
+    let heightmap = Heightmap::generate(RESOLUTION, SEED);
+    let cells = cells(&heightmap);
+    let initial_buffer = Tracked::buffer(device.create_buffer_init(
+      &wgpu::util::BufferInitDescriptor {
+        label: Some("Terrain Initial Buffer"),
+        contents: bytemuck::cast_slice(&cells),
+        usage: wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
+      },
+    ));
+    let cell_buffers = [0, 1].map(|i| {
+      Tracked::buffer(
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+          label: Some(&format!("Terrain Cell Buffer {}", i)),
+          contents: bytemuck::cast_slice(&cells),
+          usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        }),
+      )
+    });
+    let flux_buffer = Tracked::buffer(device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Terrain Flux Buffer"),
+      size: (RESOLUTION * RESOLUTION) as wgpu::BufferAddress * 16,
+      usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    }));
+
+    // cs_mesh writes the vertices again whenever erosion changes the heights
+    let chunks = chunk_grid(&heightmap);
+    let (indices, patterns) = lod_patterns(RESOLUTION);
+    let grid = Mesh {
+      name: "terrain".to_string(),
+      vertices: heightmap.vertices(),
+      lods: vec![indices],
+    };
+    let mesh = GpuMesh::with_usage(
+      device,
+      &grid,
+      wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+    );
+    let instance = SceneInstance {
+      model: math::IDENTITY,
+      color: [0.55, 0.5, 0.4, 1.0],
+      // colored by height up to MAX_HEIGHT, morphed between LODs LOD_DISTANCE apart
+      material: [0.05, 8.0, MAX_HEIGHT, LOD_DISTANCE],
+    };
+    let instance_buffer = Tracked::buffer(device.create_buffer_init(
+      &wgpu::util::BufferInitDescriptor {
+        label: Some("Terrain Instance Buffer"),
+        contents: bytemuck::bytes_of(&instance),
+        usage: wgpu::BufferUsages::VERTEX,
+      },
+    ));
+
+    let shader = device.create_shader_module(wgpu::include_wgsl!("erosion.wgsl"));
+    let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility: wgpu::ShaderStages::COMPUTE,
+      ty: wgpu::BindingType::Buffer {
+        ty: wgpu::BufferBindingType::Storage { read_only },
+        has_dynamic_offset: false,
+        min_binding_size: None,
+      },
+      count: None,
+    };
+    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Erosion Bind Group Layout"),
+      entries: &[
+        wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::COMPUTE,
+          ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+          },
+          count: None,
+        },
+        storage_entry(1, true),
+        storage_entry(2, false),
+        storage_entry(3, false),
+        storage_entry(4, false),
+      ],
+    });
+    let bind_groups = [0, 1].map(|i| {
+      device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some(&format!("Erosion Bind Group {}", i)),
+        layout: &layout,
+        entries: &[
+          wgpu::BindGroupEntry {
+            binding: 0,
+            resource: params_buffer.as_entire_binding(),
+          },
+          wgpu::BindGroupEntry {
+            binding: 1,
+            resource: cell_buffers[i].as_entire_binding(),
+          },
+          wgpu::BindGroupEntry {
+            binding: 2,
+            resource: cell_buffers[1 - i].as_entire_binding(),
+          },
+          wgpu::BindGroupEntry {
+            binding: 3,
+            resource: flux_buffer.as_entire_binding(),
+          },
+          wgpu::BindGroupEntry {
+            binding: 4,
+            resource: mesh.vertex_buffer().as_entire_binding(),
+          },
+        ],
+      })
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Erosion Pipeline Layout"),
+      bind_group_layouts: &[&layout],
+      push_constant_ranges: &[],
+    });
+    let pipeline = |entry_point: &str| {
+      device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some(&format!("Erosion {} Pipeline", entry_point)),
+        layout: Some(&pipeline_layout),
+        module: &shader,
+        entry_point,
+      })
+    };
+
+    let plants = Plants::new(
+      device,
+      mesh.vertex_buffer(),
+      RESOLUTION,
+      &PlantSettings::default(),
+    )
+    .expect("the default plant rules are valid");
+
+    Self {
+      visible: vec![true; chunks.len()],
+      // full detail until the first cull()
+      chunk_patterns: vec![0; chunks.len()],
+      mesh,
+      patterns,
+      chunks,
+      instance_buffer,
+      initial_buffer,
+      cell_buffers,
+      flux_buffer,
+      bind_groups,
+      flux_pipeline: pipeline("cs_flux"),
+      water_pipeline: pipeline("cs_water"),
+      transport_pipeline: pipeline("cs_transport"),
+      mesh_pipeline: pipeline("cs_mesh"),
+      plants,
+      pending: 0,
+      iterations: 0,
+      reset: false,
+      mesh_dirty: true,
+      enabled: false,
+    }
+  }
+
+  pub fn is_enabled(&self) -> bool {
+    self.enabled
+  }
+
+  pub fn toggle(&mut self) -> bool {
+    self.enabled = !self.enabled;
+    self.enabled
+  }
+
+  // Queues `iterations` more erosion iterations, they run over the next frames
+  pub fn erode(&mut self, iterations: u32) {
+    self.pending += iterations;
+  }
+
+  pub fn is_eroding(&self) -> bool {
+    self.enabled && self.pending > 0
+  }
+
+  // Iterations run since the last reset
+  pub fn iterations(&self) -> u32 {
+    self.iterations
+  }
+
+  // Back to the uneroded heights on the next step
+  pub fn reset(&mut self) {
+    self.pending = 0;
+    self.iterations = 0;
+    self.reset = true;
+  }
+
+  // Starts the terrain over from `heightmap`, which has to be RESOLUTION a side like
+  // Heightmap::from_settings() makes them
+  pub fn set_heightmap(&mut self, queue: &Queue, heightmap: &Heightmap) {
+    assert_eq!(heightmap.resolution, RESOLUTION);
+    queue.write_buffer(
+      &self.initial_buffer,
+      0,
+      bytemuck::cast_slice(&cells(heightmap)),
+    );
+    queue.write_buffer(
+      self.mesh.vertex_buffer(),
+      0,
+      bytemuck::cast_slice(&heightmap.vertices()),
+    );
+    self.chunks = chunk_grid(heightmap);
+    self.plants.follow_terrain();
+    self.reset();
+  }
+
+  // Keeps the chunks inside the frustum of `view_proj` for the next draw(.., true), and
+  // picks each one's LOD by how far it is from `eye`
+  pub fn cull(&mut self, view_proj: &Mat4, eye: Vec3) {
+    self.visible = self
+      .chunks
+      .iter()
+      .map(|chunk| chunk.bounds.in_view(view_proj))
+      .collect();
+
+    let mut lods: Vec<u32> = self
+      .chunks
+      .iter()
+      .map(|chunk| {
+        let closest: Vec3 = std::array::from_fn(|axis| {
+          eye[axis].clamp(chunk.bounds.min[axis], chunk.bounds.max[axis])
+        });
+        let distance = math::length(math::sub(eye, closest));
+        (0..LODS - 1)
+          .take_while(|&lod| distance >= LOD_DISTANCE * (1 << lod) as f32)
+          .count() as u32
+      })
+      .collect();
+    // stitching only works against a neighbour one LOD coarser, so no chunk can be more
+    // than one coarser than any of its neighbours
+    let per_side = ((RESOLUTION - 1) / CHUNK_QUADS) as usize;
+    let neighbour = |i: usize, side: u32| {
+      let (x, z) = (i % per_side, i / per_side);
+      match side {
+        SIDE_NEAR_Z => z.checked_sub(1).map(|z| z * per_side + x),
+        SIDE_FAR_X => (x + 1 < per_side).then_some(i + 1),
+        SIDE_FAR_Z => (z + 1 < per_side).then_some(i + per_side),
+        _ => x.checked_sub(1).map(|_| i - 1),
+      }
+    };
+    const SIDES: [u32; 4] = [SIDE_NEAR_Z, SIDE_FAR_X, SIDE_FAR_Z, SIDE_NEAR_X];
+    let mut changed = true;
+    while changed {
+      changed = false;
+      for i in 0..lods.len() {
+        for side in SIDES {
+          if let Some(j) = neighbour(i, side) {
+            if lods[i] > lods[j] + 1 {
+              lods[i] = lods[j] + 1;
+              changed = true;
+            }
+          }
+        }
+      }
+    }
+
+    self.chunk_patterns = (0..lods.len())
+      .map(|i| {
+        let sides = SIDES
+          .iter()
+          .filter(|&&side| neighbour(i, side).is_some_and(|j| lods[j] > lods[i]))
+          .fold(0, |sides, side| sides | side);
+        lods[i] as usize * SIDE_COMBINATIONS + sides as usize
+      })
+      .collect();
+  }
+
+  // Of all of them, as of the last cull()
+  pub fn visible_chunks(&self) -> (usize, usize) {
+    let visible = self.visible.iter().filter(|&&visible| visible).count();
+    (visible, self.chunks.len())
+  }
+
+  // The triangles the visible chunks are drawn with, and how many there'd be without LODs
+  pub fn triangles(&self) -> (u32, u32) {
+    let full = self.patterns[0].len() as u32 / 3;
+    self
+      .chunk_patterns
+      .iter()
+      .zip(&self.visible)
+      .filter(|(_, &visible)| visible)
+      .fold((0, 0), |(drawn, all), (&pattern, _)| {
+        (drawn + self.patterns[pattern].len() as u32 / 3, all + full)
+      })
+  }
+
+  // For the overlay
+  pub fn summary(&self) -> String {
+    let (visible, chunks) = self.visible_chunks();
+    let (drawn, full) = self.triangles();
+    format!(
+      "terrain: {}/{} chunks, {} triangles ({} at full detail)",
+      visible, chunks, drawn, full
+    )
+  }
+
+  // Regrows the plants, the old ones are kept if the rules don't parse
+  pub fn set_plants(
+    &mut self,
+    device: &Device,
+    settings: &PlantSettings,
+  ) -> Result<(), LSystemError> {
+    self.plants = Plants::new(device, self.mesh.vertex_buffer(), RESOLUTION, settings)?;
+    Ok(())
+  }
+
+  // Runs this frame's share of the pending iterations and rebuilds the mesh if anything
+  // changed
+  pub fn step(&mut self, encoder: &mut CommandEncoder) {
+    if !self.enabled {
+      return;
+    }
+    if std::mem::take(&mut self.reset) {
+      let size = self.initial_buffer.size();
+      encoder.copy_buffer_to_buffer(&self.initial_buffer, 0, &self.cell_buffers[0], 0, size);
+      encoder.clear_buffer(&self.flux_buffer, 0, None);
+      self.mesh_dirty = true;
+    }
+    let iterations = self.pending.min(ITERATIONS_PER_FRAME);
+    if iterations > 0 || self.mesh_dirty {
+      self.erode_step(encoder, iterations);
+      self.plants.follow_terrain();
+    }
+    self.plants.place(encoder);
+  }
+
+  fn erode_step(&mut self, encoder: &mut CommandEncoder, iterations: u32) {
+    let groups = RESOLUTION.div_ceil(CELLS_PER_GROUP);
+    let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+      label: Some("Erosion Compute Pass"),
+    });
+    for _ in 0..iterations {
+      for (pipeline, bind_group) in [
+        (&self.flux_pipeline, &self.bind_groups[0]),
+        (&self.water_pipeline, &self.bind_groups[0]),
+        (&self.transport_pipeline, &self.bind_groups[1]),
+      ] {
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, bind_group, &[]);
+        pass.dispatch_workgroups(groups, groups, 1);
+      }
+    }
+    pass.set_pipeline(&self.mesh_pipeline);
+    pass.set_bind_group(0, &self.bind_groups[0], &[]);
+    pass.dispatch_workgroups(groups, groups, 1);
+
+    self.pending -= iterations;
+    self.iterations += iterations;
+    self.mesh_dirty = false;
+  }
+
+  // With the scene pipeline or the shadow pipeline bound, each chunk at the LOD the last
+  // cull() picked. `culled` leaves out the chunks it didn't keep.
+  pub fn draw<'a>(&'a self, pass: &mut RenderPass<'a>, culled: bool) {
+    pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+    self.mesh.bind(pass);
+    let stride = std::mem::size_of::<MeshVertex>() as wgpu::BufferAddress;
+    for (i, chunk) in self.chunks.iter().enumerate() {
+      if culled && !self.visible[i] {
+        continue;
+      }
+      // offsetting the binding instead of the base vertex, WebGL has no base vertex
+      let offset = chunk.origin as wgpu::BufferAddress * stride;
+      pass.set_vertex_buffer(0, self.mesh.vertex_buffer().slice(offset..));
+      count_draw();
+      pass.draw_indexed(self.patterns[self.chunk_patterns[i]].clone(), 0, 0..1);
+    }
+    self.plants.draw(pass);
+  }
+}
+
+// Dry cells at `heightmap`'s heights, for erosion to start from
+fn cells(heightmap: &Heightmap) -> Vec<Cell> {
+  heightmap
+    .heights
+    .iter()
+    .map(|&height| Cell {
+      height,
+      ..bytemuck::Zeroable::zeroed()
+    })
+    .collect()
+}
@@ -0,0 +1,826 @@
+use std::{
+  collections::HashMap,
+  path::{Path, PathBuf},
+};
+
+use ab_glyph::{Font, FontVec, GlyphId, PxScale, PxScaleFont, ScaleFont};
+use serde::{Deserialize, Serialize};
+use wgpu::{CommandEncoder, Device, Queue, TextureFormat, TextureView};
+
+use crate::{
+  assets::Assets,
+  gpu_memory::Tracked,
+  sampler::SamplerSettings,
+  shaping::{self, Direction},
+  stats::count_draw,
+  texture_atlas::RectPacker,
+};
+
+const ATLAS_SIZE: u32 = 1024;
+// empty pixels around each glyph so linear filtering doesn't bleed in the neighbours
+const GLYPH_PADDING: u32 = 1;
+const INITIAL_CAPACITY: usize = 256;
+
+// Looked up in the assets when settings.toml doesn't name a font
+pub const FONT_ASSET: &str = "fonts/font.ttf";
+// Tried in order after that
+const FALLBACK_FONTS: &[&str] = &[
+  "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+  "/usr/share/fonts/TTF/DejaVuSans.ttf",
+  "/System/Library/Fonts/Supplemental/Arial.ttf",
+  "/Library/Fonts/Arial.ttf",
+  "C:\\Windows\\Fonts\\arial.ttf",
+];
+
+// Fonts with the scripts the usual ones lack, for the characters the main font doesn't have
+const FALLBACK_SCRIPT_FONTS: &[&str] = &[
+  "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+  "/usr/share/fonts/truetype/noto/NotoSansArabic-Regular.ttf",
+  "/usr/share/fonts/truetype/noto/NotoSansHebrew-Regular.ttf",
+  "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+  "/System/Library/Fonts/Supplemental/Arial Unicode.ttf",
+  "C:\\Windows\\Fonts\\segoeui.ttf",
+  "C:\\Windows\\Fonts\\msyh.ttc",
+];
+
+// The `[text]` section of settings.toml
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextSettings {
+  pub font: Option<PathBuf>,
+  pub size: f32,
+  // tried in order for characters the font doesn't have, the ones that don't exist are
+  // skipped
+  pub fallback_fonts: Vec<PathBuf>,
+}
+
+impl Default for TextSettings {
+  fn default() -> Self {
+    Self {
+      font: None,
+      size: 16.0,
+      fallback_fonts: FALLBACK_SCRIPT_FONTS.iter().map(PathBuf::from).collect(),
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum TextError {
+  Io(PathBuf, std::io::Error),
+  InvalidFont(PathBuf),
+  NoFont,
+}
+
+impl std::fmt::Display for TextError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      TextError::Io(path, e) => write!(f, "couldn't read {}: {}", path.display(), e),
+      TextError::InvalidFont(path) => write!(f, "{} isn't a TTF/OTF font", path.display()),
+      TextError::NoFont => write!(f, "no font found, set `font` in the [text] settings"),
+    }
+  }
+}
+
+impl std::error::Error for TextError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      TextError::Io(_, e) => Some(e),
+      _ => None,
+    }
+  }
+}
+
+pub fn load_font(path: impl AsRef<Path>) -> Result<FontVec, TextError> {
+  let path = path.as_ref();
+  let bytes = std::fs::read(path).map_err(|e| TextError::Io(path.to_path_buf(), e))?;
+  FontVec::try_from_vec(bytes).map_err(|_| TextError::InvalidFont(path.to_path_buf()))
+}
+
+// The configured font, then the bundled one, then the first system font that exists
+pub async fn load_font_from_settings(
+  settings: &TextSettings,
+  assets: &Assets,
+) -> Result<FontVec, TextError> {
+  if let Some(path) = &settings.font {
+    return load_font(path);
+  }
+  if let Ok(bytes) = assets.read(FONT_ASSET).await {
+    return FontVec::try_from_vec(bytes).map_err(|_| TextError::InvalidFont(FONT_ASSET.into()));
+  }
+  FALLBACK_FONTS
+    .iter()
+    .map(Path::new)
+    .find(|path| path.exists())
+    .ok_or(TextError::NoFont)
+    .and_then(load_font)
+}
+
+// The settings' fallback fonts that exist and load, for TextRenderer::add_fallback_font()
+pub fn load_fallback_fonts(settings: &TextSettings) -> Vec<FontVec> {
+  settings
+    .fallback_fonts
+    .iter()
+    .filter(|path| path.exists())
+    .filter_map(|path| match load_font(path) {
+      Ok(font) => Some(font),
+      Err(e) => {
+        log::warn!("{}", e);
+        None
+      }
+    })
+    .collect()
+}
+
+// A run of text, `position` is the top left corner in physical pixels
+#[derive(Debug, Clone, Copy)]
+pub struct TextSection<'a> {
+  pub text: &'a str,
+  pub position: [f32; 2],
+  pub size: f32,
+  pub color: [f32; 4],
+}
+
+impl<'a> TextSection<'a> {
+  pub fn span(&self) -> TextSpan<'a> {
+    TextSpan {
+      text: self.text,
+      size: self.size,
+      color: self.color,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Align {
+  #[default]
+  Left,
+  Center,
+  Right,
+}
+
+impl Align {
+  // Share of the free space that goes to the left of a line
+  fn factor(self) -> f32 {
+    match self {
+      Align::Left => 0.0,
+      Align::Center => 0.5,
+      Align::Right => 1.0,
+    }
+  }
+}
+
+// Part of a paragraph with its own size and color
+#[derive(Debug, Clone, Copy)]
+pub struct TextSpan<'a> {
+  pub text: &'a str,
+  pub size: f32,
+  pub color: [f32; 4],
+}
+
+// Spans laid out one after the other, `position` is the top left corner in physical pixels.
+// With a `max_width` lines wrap between words to fit it and are aligned within it, without
+// one they're only broken at newlines and aligned to the widest.
+#[derive(Debug, Clone, Copy)]
+pub struct Paragraph<'a> {
+  pub spans: &'a [TextSpan<'a>],
+  pub position: [f32; 2],
+  pub max_width: Option<f32>,
+  pub align: Align,
+}
+
+impl<'a> Paragraph<'a> {
+  pub fn new(spans: &'a [TextSpan<'a>], position: [f32; 2]) -> Self {
+    Self {
+      spans,
+      position,
+      max_width: None,
+      align: Align::Left,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PlacedGlyph {
+  // index into TextRenderer's fonts
+  font: usize,
+  id: GlyphId,
+  size: f32,
+  color: [f32; 4],
+  // pen position on the baseline
+  origin: [f32; 2],
+}
+
+// A paragraph's glyphs with their positions, from TextRenderer::layout(). Can be queued as
+// many times as needed without laying it out again.
+#[derive(Debug, Clone, Default)]
+pub struct TextLayout {
+  glyphs: Vec<PlacedGlyph>,
+  // width and height of the lines
+  pub size: [f32; 2],
+}
+
+// The characters of a paragraph with the span each belongs to, grouped into what can't be
+// broken apart
+enum Token {
+  Word(Vec<(char, usize)>),
+  Space(Vec<(char, usize)>),
+  Newline(usize),
+}
+
+fn tokenize(chars: &[(char, usize)]) -> Vec<Token> {
+  let mut tokens: Vec<Token> = Vec::new();
+  for &(c, span) in chars {
+    match (c, tokens.last_mut()) {
+      ('\n', _) => tokens.push(Token::Newline(span)),
+      (c, Some(Token::Space(chars))) if c.is_whitespace() => chars.push((c, span)),
+      (c, _) if c.is_whitespace() => tokens.push(Token::Space(vec![(c, span)])),
+      (c, Some(Token::Word(chars))) => chars.push((c, span)),
+      (c, _) => tokens.push(Token::Word(vec![(c, span)])),
+    }
+  }
+  tokens
+}
+
+#[derive(Debug, Clone, Copy)]
+struct LineGlyph {
+  c: char,
+  font: usize,
+  id: GlyphId,
+  span: usize,
+  advance: f32,
+}
+
+// The glyphs of a line in the order of the text, put in display order by place()
+#[derive(Default)]
+struct Line {
+  glyphs: Vec<LineGlyph>,
+  pen: f32,
+  // glyphs up to the end of the last word, trailing spaces don't count
+  ink: usize,
+  ascent: f32,
+  height: f32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct GlyphInstance {
+  rect: [f32; 4],
+  uv_rect: [f32; 4],
+  color: [f32; 4],
+}
+
+#[derive(Debug, Clone, Copy)]
+struct AtlasEntry {
+  // offset of the bitmap from the pen position on the baseline
+  offset: [f32; 2],
+  size: [f32; 2],
+  uv_rect: [f32; 4],
+}
+
+// Coverage bitmaps of rasterized glyphs packed into one R8 texture
+struct GlyphAtlas {
+  texture: Tracked<wgpu::Texture>,
+  // font, glyph and pixel size, None for glyphs without an outline like spaces
+  entries: HashMap<(usize, GlyphId, u32), Option<AtlasEntry>>,
+  packer: RectPacker,
+}
+
+impl GlyphAtlas {
+  fn new(device: &Device) -> Self {
+    let texture = Tracked::texture(device.create_texture(&wgpu::TextureDescriptor {
+      label: Some("Glyph Atlas"),
+      size: wgpu::Extent3d {
+        width: ATLAS_SIZE,
+        height: ATLAS_SIZE,
+        depth_or_array_layers: 1,
+      },
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format: TextureFormat::R8Unorm,
+      usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+      view_formats: &[],
+    }));
+    Self {
+      texture,
+      entries: HashMap::new(),
+      packer: RectPacker::new(ATLAS_SIZE, ATLAS_SIZE),
+    }
+  }
+
+  fn glyph(
+    &mut self,
+    queue: &Queue,
+    fonts: &[FontVec],
+    font: usize,
+    id: GlyphId,
+    size: f32,
+  ) -> Option<AtlasEntry> {
+    let key = (font, id, size.round() as u32);
+    if let Some(entry) = self.entries.get(&key) {
+      return *entry;
+    }
+    let entry = self.rasterize(queue, &fonts[font], id, size);
+    self.entries.insert(key, entry);
+    entry
+  }
+
+  fn rasterize(
+    &mut self,
+    queue: &Queue,
+    font: &FontVec,
+    id: GlyphId,
+    size: f32,
+  ) -> Option<AtlasEntry> {
+    let outline = font.outline_glyph(id.with_scale(PxScale::from(size.round())))?;
+    let bounds = outline.px_bounds();
+    let (width, height) = (bounds.width() as u32, bounds.height() as u32);
+    if width == 0 || height == 0 {
+      return None;
+    }
+
+    let Some([x, y]) = self
+      .packer
+      .pack(width + GLYPH_PADDING, height + GLYPH_PADDING)
+    else {
+      log::warn!("glyph atlas is full, some text won't be drawn");
+      return None;
+    };
+
+    let mut pixels = vec![0u8; (width * height) as usize];
+    outline.draw(|px, py, coverage| {
+      pixels[(py * width + px) as usize] = (coverage * 255.0) as u8;
+    });
+    queue.write_texture(
+      wgpu::ImageCopyTexture {
+        texture: &self.texture,
+        mip_level: 0,
+        origin: wgpu::Origin3d { x, y, z: 0 },
+        aspect: wgpu::TextureAspect::All,
+      },
+      &pixels,
+      wgpu::ImageDataLayout {
+        offset: 0,
+        bytes_per_row: std::num::NonZeroU32::new(width),
+        rows_per_image: None,
+      },
+      wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+      },
+    );
+
+    let atlas = ATLAS_SIZE as f32;
+    Some(AtlasEntry {
+      offset: [bounds.min.x, bounds.min.y],
+      size: [width as f32, height as f32],
+      uv_rect: [
+        x as f32 / atlas,
+        y as f32 / atlas,
+        width as f32 / atlas,
+        height as f32 / atlas,
+      ],
+    })
+  }
+}
+
+// Draws queued text in its own pass on top of whatever is already in the target
+pub struct TextRenderer {
+  // the main font first, then the fallbacks for what it doesn't have
+  fonts: Vec<FontVec>,
+  default_size: f32,
+  atlas: GlyphAtlas,
+  screen_buffer: Tracked<wgpu::Buffer>,
+  layout: wgpu::BindGroupLayout,
+  bind_group: wgpu::BindGroup,
+  shader: wgpu::ShaderModule,
+  // one pipeline per target format, windows can have different surface formats
+  pipelines: HashMap<TextureFormat, wgpu::RenderPipeline>,
+  instances: Vec<GlyphInstance>,
+  instance_buffer: Tracked<wgpu::Buffer>,
+  capacity: usize,
+}
+
+impl TextRenderer {
+  pub fn new(device: &Device, font: FontVec, default_size: f32) -> Self {
+    let atlas = GlyphAtlas::new(device);
+    let atlas_view = atlas
+      .texture
+      .create_view(&wgpu::TextureViewDescriptor::default());
+    let sampler =
+      device.create_sampler(&SamplerSettings::linear().descriptor(Some("Glyph Atlas Sampler")));
+    let screen_buffer = Tracked::buffer(device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Text Screen Buffer"),
+      size: 16,
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    }));
+
+    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Text Bind Group Layout"),
+      entries: &[
+        wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::VERTEX,
+          ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+          },
+          count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+          binding: 1,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+          },
+          count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+          binding: 2,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+          count: None,
+        },
+      ],
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Text Bind Group"),
+      layout: &layout,
+      entries: &[
+        wgpu::BindGroupEntry {
+          binding: 0,
+          resource: screen_buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+          binding: 1,
+          resource: wgpu::BindingResource::TextureView(&atlas_view),
+        },
+        wgpu::BindGroupEntry {
+          binding: 2,
+          resource: wgpu::BindingResource::Sampler(&sampler),
+        },
+      ],
+    });
+
+    let shader = device.create_shader_module(wgpu::include_wgsl!("text.wgsl"));
+    Self {
+      fonts: vec![font],
+      default_size,
+      atlas,
+      screen_buffer,
+      layout,
+      bind_group,
+      shader,
+      pipelines: HashMap::new(),
+      instances: Vec::new(),
+      instance_buffer: create_instance_buffer(device, INITIAL_CAPACITY),
+      capacity: INITIAL_CAPACITY,
+    }
+  }
+
+  // Characters the fonts so far don't have are looked up in `font`
+  pub fn add_fallback_font(&mut self, font: FontVec) {
+    self.fonts.push(font);
+  }
+
+  pub fn default_size(&self) -> f32 {
+    self.default_size
+  }
+
+  // Height of one line of text at `size`
+  pub fn line_height(&self, size: f32) -> f32 {
+    let font = self.scaled(0, size);
+    font.height() + font.line_gap()
+  }
+
+  fn scaled(&self, font: usize, size: f32) -> PxScaleFont<&FontVec> {
+    self.fonts[font].as_scaled(PxScale::from(size))
+  }
+
+  // The first font that has `c`, with the main font's missing glyph box if none does
+  fn glyph_id(&self, c: char) -> (usize, GlyphId) {
+    self
+      .fonts
+      .iter()
+      .enumerate()
+      .map(|(font, f)| (font, f.glyph_id(c)))
+      .find(|&(_, id)| id.0 != 0)
+      .unwrap_or_else(|| (0, self.fonts[0].glyph_id(c)))
+  }
+
+  // The glyphs of `line` from left to right with their x from its start, and its width.
+  // Right to left runs are reversed here, after the line was filled in text order.
+  fn place(&self, line: &Line, spans: &[TextSpan]) -> (Vec<(LineGlyph, f32)>, f32) {
+    // trailing spaces aren't drawn and would end up in front of right to left lines
+    let glyphs = &line.glyphs[..line.ink];
+    let chars: Vec<_> = glyphs.iter().map(|g| g.c).collect();
+    let mut placed = Vec::with_capacity(glyphs.len());
+    let mut pen = 0.0;
+    let mut previous: Option<LineGlyph> = None;
+    for (i, direction) in shaping::visual_order(&chars) {
+      let mut glyph = glyphs[i];
+      if direction == Direction::RightToLeft && shaping::mirror(glyph.c) != glyph.c {
+        glyph.id = self.fonts[glyph.font].glyph_id(shaping::mirror(glyph.c));
+      }
+      if let Some(previous) = previous.filter(|p| p.font == glyph.font && p.span == glyph.span) {
+        pen += self
+          .scaled(glyph.font, spans[glyph.span].size)
+          .kern(previous.id, glyph.id);
+      }
+      placed.push((glyph, pen));
+      pen += glyph.advance;
+      previous = Some(glyph);
+    }
+    (placed, pen)
+  }
+
+  // Where every glyph of `paragraph` goes. Arabic is shaped and right to left text put in
+  // display order, characters missing from the main font come from the fallback fonts.
+  pub fn layout(&self, paragraph: &Paragraph) -> TextLayout {
+    let spans = paragraph.spans;
+    if spans.is_empty() {
+      return TextLayout::default();
+    }
+    let max_width = paragraph.max_width.unwrap_or(f32::INFINITY);
+    let glyph = |c: char, span: usize| {
+      let (font, id) = self.glyph_id(c);
+      let advance = self.scaled(font, spans[span].size).h_advance(id);
+      LineGlyph {
+        c,
+        font,
+        id,
+        span,
+        advance,
+      }
+    };
+    let start_line = |span: usize| Line {
+      ascent: self.scaled(0, spans[span].size).ascent(),
+      height: self.line_height(spans[span].size),
+      ..Line::default()
+    };
+    let chars: Vec<_> = spans
+      .iter()
+      .enumerate()
+      .flat_map(|(span, s)| s.text.chars().map(move |c| (c, span)))
+      .collect();
+
+    let mut lines = vec![start_line(0)];
+    // whether the current line started because the last one was full, then it doesn't
+    // start with the spaces the break replaced
+    let mut wrapped = false;
+    for token in tokenize(&shaping::shape(&chars)) {
+      let chars = match token {
+        Token::Newline(span) => {
+          lines.push(start_line(span));
+          wrapped = false;
+          continue;
+        }
+        Token::Space(chars) => {
+          let line = lines.last().unwrap();
+          if wrapped && line.glyphs.is_empty() {
+            continue;
+          }
+          chars
+        }
+        Token::Word(chars) => {
+          let width: f32 = chars.iter().map(|&(c, span)| glyph(c, span).advance).sum();
+          let line = lines.last().unwrap();
+          if !line.glyphs.is_empty() && line.pen + width > max_width {
+            lines.push(start_line(chars[0].1));
+            wrapped = true;
+          }
+          chars
+        }
+      };
+      let is_word = !chars[0].0.is_whitespace();
+      for (c, span) in chars {
+        let glyph = glyph(c, span);
+        let line = lines.last_mut().unwrap();
+        // a word longer than a whole line is broken wherever it has to
+        if is_word && !line.glyphs.is_empty() && line.pen + glyph.advance > max_width {
+          lines.push(start_line(span));
+          wrapped = true;
+        }
+        let line = lines.last_mut().unwrap();
+        line.glyphs.push(glyph);
+        line.pen += glyph.advance;
+        if is_word {
+          line.ink = line.glyphs.len();
+        }
+        let font = self.scaled(glyph.font, spans[span].size);
+        line.ascent = line.ascent.max(font.ascent());
+        line.height = line.height.max(self.line_height(spans[span].size));
+      }
+    }
+
+    let placed: Vec<_> = lines.iter().map(|line| self.place(line, spans)).collect();
+    let width = paragraph
+      .max_width
+      .unwrap_or_else(|| placed.iter().map(|(_, w)| *w).fold(0.0, f32::max));
+    let mut layout = TextLayout::default();
+    let mut top = paragraph.position[1];
+    for (line, (glyphs, line_width)) in lines.iter().zip(&placed) {
+      let left = paragraph.position[0] + (width - line_width).max(0.0) * paragraph.align.factor();
+      let baseline = top + line.ascent;
+      layout
+        .glyphs
+        .extend(glyphs.iter().map(|&(glyph, x)| PlacedGlyph {
+          font: glyph.font,
+          id: glyph.id,
+          size: spans[glyph.span].size,
+          color: spans[glyph.span].color,
+          origin: [left + x, baseline],
+        }));
+      top += line.height;
+    }
+    layout.size = [width, top - paragraph.position[1]];
+    layout
+  }
+
+  // Keeps the glyphs of `layout` until the next render(), moved by `offset` and in `color`
+  // instead of their own if given. New glyphs are uploaded to the atlas right away.
+  pub fn queue_layout(
+    &mut self,
+    queue: &Queue,
+    layout: &TextLayout,
+    offset: [f32; 2],
+    color: Option<[f32; 4]>,
+  ) {
+    for glyph in &layout.glyphs {
+      // snapping to whole pixels keeps the small sizes sharp
+      let origin = [
+        (glyph.origin[0] + offset[0]).round(),
+        (glyph.origin[1] + offset[1]).round(),
+      ];
+      if let Some(entry) = self
+        .atlas
+        .glyph(queue, &self.fonts, glyph.font, glyph.id, glyph.size)
+      {
+        self.instances.push(GlyphInstance {
+          rect: [
+            origin[0] + entry.offset[0],
+            origin[1] + entry.offset[1],
+            entry.size[0],
+            entry.size[1],
+          ],
+          uv_rect: entry.uv_rect,
+          color: color.unwrap_or(glyph.color),
+        });
+      }
+    }
+  }
+
+  // Lays out `paragraph` and keeps it until the next render()
+  pub fn queue_paragraph(&mut self, queue: &Queue, paragraph: &Paragraph) {
+    let layout = self.layout(paragraph);
+    self.queue_layout(queue, &layout, [0.0; 2], None);
+  }
+
+  // queue_layout() with a `outline` colored border around every glyph, readable on any
+  // background. `size` is the text size the border width follows.
+  pub fn queue_layout_outlined(
+    &mut self,
+    queue: &Queue,
+    layout: &TextLayout,
+    size: f32,
+    outline: [f32; 4],
+  ) {
+    let width = (size / 16.0).round().max(1.0);
+    for (x, y) in [
+      (-1, -1),
+      (0, -1),
+      (1, -1),
+      (-1, 0),
+      (1, 0),
+      (-1, 1),
+      (0, 1),
+      (1, 1),
+    ] {
+      let offset = [x as f32 * width, y as f32 * width];
+      self.queue_layout(queue, layout, offset, Some(outline));
+    }
+    self.queue_layout(queue, layout, [0.0; 2], None);
+  }
+
+  // A single span broken only at newlines, keeps it until the next render()
+  pub fn queue(&mut self, queue: &Queue, section: &TextSection) {
+    let spans = [section.span()];
+    self.queue_paragraph(queue, &Paragraph::new(&spans, section.position));
+  }
+
+  // queue() with a `outline` colored border around every glyph
+  pub fn queue_outlined(&mut self, queue: &Queue, section: &TextSection, outline: [f32; 4]) {
+    let spans = [section.span()];
+    let layout = self.layout(&Paragraph::new(&spans, section.position));
+    self.queue_layout_outlined(queue, &layout, section.size, outline);
+  }
+
+  // Draws and clears everything queued since the last call onto `target`
+  pub fn render(
+    &mut self,
+    device: &Device,
+    queue: &Queue,
+    encoder: &mut CommandEncoder,
+    target: &TextureView,
+    format: TextureFormat,
+    size: (u32, u32),
+  ) {
+    if self.instances.is_empty() {
+      return;
+    }
+    if self.instances.len() > self.capacity {
+      self.capacity = self.instances.len().next_power_of_two();
+      self.instance_buffer = create_instance_buffer(device, self.capacity);
+    }
+    queue.write_buffer(
+      &self.instance_buffer,
+      0,
+      bytemuck::cast_slice(&self.instances),
+    );
+    queue.write_buffer(
+      &self.screen_buffer,
+      0,
+      bytemuck::cast_slice(&[size.0 as f32, size.1 as f32, 0.0, 0.0]),
+    );
+
+    if !self.pipelines.contains_key(&format) {
+      let pipeline = create_pipeline(device, &self.layout, &self.shader, format);
+      self.pipelines.insert(format, pipeline);
+    }
+    let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+      label: Some("Text Pass"),
+      color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+        view: target,
+        resolve_target: None,
+        ops: wgpu::Operations {
+          load: wgpu::LoadOp::Load,
+          store: true,
+        },
+      })],
+      depth_stencil_attachment: None,
+    });
+    pass.set_pipeline(&self.pipelines[&format]);
+    pass.set_bind_group(0, &self.bind_group, &[]);
+    pass.set_vertex_buffer(0, self.instance_buffer.slice(..));
+    count_draw();
+    pass.draw(0..4, 0..self.instances.len() as u32);
+    drop(pass);
+    self.instances.clear();
+  }
+}
+
+fn create_instance_buffer(device: &Device, capacity: usize) -> Tracked<wgpu::Buffer> {
+  Tracked::buffer(device.create_buffer(&wgpu::BufferDescriptor {
+    label: Some("Text Instance Buffer"),
+    size: (capacity * std::mem::size_of::<GlyphInstance>()) as u64,
+    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+    mapped_at_creation: false,
+  }))
+}
+
+fn create_pipeline(
+  device: &Device,
+  layout: &wgpu::BindGroupLayout,
+  shader: &wgpu::ShaderModule,
+  format: TextureFormat,
+) -> wgpu::RenderPipeline {
+  let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+    label: Some("Text Pipeline Layout"),
+    bind_group_layouts: &[layout],
+    push_constant_ranges: &[],
+  });
+  device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+    label: Some("Text Pipeline"),
+    layout: Some(&pipeline_layout),
+    vertex: wgpu::VertexState {
+      module: shader,
+      entry_point: "vs_main",
+      buffers: &[wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<GlyphInstance>() as u64,
+        step_mode: wgpu::VertexStepMode::Instance,
+        attributes: &wgpu::vertex_attr_array![0 => Float32x4, 1 => Float32x4, 2 => Float32x4],
+      }],
+    },
+    fragment: Some(wgpu::FragmentState {
+      module: shader,
+      entry_point: "fs_main",
+      targets: &[Some(wgpu::ColorTargetState {
+        format,
+        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+        write_mask: wgpu::ColorWrites::ALL,
+      })],
+    }),
+    primitive: wgpu::PrimitiveState {
+      topology: wgpu::PrimitiveTopology::TriangleStrip,
+      ..Default::default()
+    },
+    depth_stencil: None,
+    multisample: wgpu::MultisampleState::default(),
+    multiview: None,
+  })
+}
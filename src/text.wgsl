@@ -0,0 +1,49 @@
+// Screen space glyph quads sampled from the coverage atlas
+
+struct Screen {
+    size: vec2<f32>,
+    _padding: vec2<f32>,
+};
+@group(0) @binding(0)
+var<uniform> screen: Screen;
+@group(0) @binding(1)
+var atlas: texture_2d<f32>;
+@group(0) @binding(2)
+var atlas_sampler: sampler;
+
+struct GlyphInstance {
+    // x, y, width, height in pixels from the top left corner
+    @location(0) rect: vec4<f32>,
+    @location(1) uv_rect: vec4<f32>,
+    @location(2) color: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+// 4 vertex triangle strip per glyph
+@vertex
+fn vs_main(@builtin(vertex_index) idx: u32, glyph: GlyphInstance) -> VertexOutput {
+    let corner = vec2<f32>(f32(idx & 1u), f32(idx >> 1u));
+    let pixel = glyph.rect.xy + corner * glyph.rect.zw;
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(
+        pixel.x / screen.size.x * 2.0 - 1.0,
+        1.0 - pixel.y / screen.size.y * 2.0,
+        0.0,
+        1.0
+    );
+    out.uv = glyph.uv_rect.xy + corner * glyph.uv_rect.zw;
+    out.color = glyph.color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let coverage = textureSample(atlas, atlas_sampler, in.uv).r;
+    return vec4<f32>(in.color.rgb, in.color.a * coverage);
+}
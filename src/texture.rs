@@ -0,0 +1,148 @@
+use image::GenericImageView;
+use wgpu::{Device, Queue};
+
+use crate::{
+  gpu_memory::Tracked,
+  mipmap::{mip_level_count, MipmapGenerator},
+  quality::fit_texture,
+  sampler::{SamplerSettings, Samplers, MAX_ANISOTROPY},
+};
+
+pub struct Texture {
+  pub texture: Tracked<wgpu::Texture>,
+  pub view: wgpu::TextureView,
+  pub sampler: wgpu::Sampler,
+  pub sampler_settings: SamplerSettings,
+  pub mip_level_count: u32,
+}
+
+impl Texture {
+  // Decodes a PNG/JPEG, e.g. from Assets::read
+  pub fn from_bytes(
+    device: &Device,
+    queue: &Queue,
+    mipmaps: &mut MipmapGenerator,
+    samplers: &Samplers,
+    bytes: &[u8],
+    max_size: u32,
+    label: &str,
+  ) -> Result<Self, image::ImageError> {
+    let image = image::load_from_memory(bytes)?;
+    Ok(Self::from_image(
+      device, queue, mipmaps, samplers, &image, max_size, label,
+    ))
+  }
+
+  // Uploads the image as sRGB and fills in the whole mip chain, sampled trilinear with as
+  // much anisotropy as `samplers` allows. Images larger than `max_size` on a side are
+  // downscaled first, see QualitySettings::max_texture_size.
+  pub fn from_image(
+    device: &Device,
+    queue: &Queue,
+    mipmaps: &mut MipmapGenerator,
+    samplers: &Samplers,
+    image: &image::DynamicImage,
+    max_size: u32,
+    label: &str,
+  ) -> Self {
+    let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+    let image = fit_texture(image, max_size);
+    Self::upload(device, queue, mipmaps, samplers, &image, format, label)
+  }
+
+  // Same as from_image but without the sRGB decode, for data like normal or roughness maps
+  pub fn from_image_linear(
+    device: &Device,
+    queue: &Queue,
+    mipmaps: &mut MipmapGenerator,
+    samplers: &Samplers,
+    image: &image::DynamicImage,
+    max_size: u32,
+    label: &str,
+  ) -> Self {
+    let format = wgpu::TextureFormat::Rgba8Unorm;
+    let image = fit_texture(image, max_size);
+    Self::upload(device, queue, mipmaps, samplers, &image, format, label)
+  }
+
+  fn upload(
+    device: &Device,
+    queue: &Queue,
+    mipmaps: &mut MipmapGenerator,
+    samplers: &Samplers,
+    image: &image::DynamicImage,
+    format: wgpu::TextureFormat,
+    label: &str,
+  ) -> Self {
+    let rgba = image.to_rgba8();
+    let (width, height) = image.dimensions();
+    let size = wgpu::Extent3d {
+      width,
+      height,
+      depth_or_array_layers: 1,
+    };
+    let mip_level_count = mip_level_count(width, height);
+    let texture = Tracked::texture(device.create_texture(&wgpu::TextureDescriptor {
+      label: Some(label),
+      size,
+      mip_level_count,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format,
+      // RENDER_ATTACHMENT so the mip levels can be rendered into
+      usage: wgpu::TextureUsages::TEXTURE_BINDING
+        | wgpu::TextureUsages::COPY_DST
+        | wgpu::TextureUsages::RENDER_ATTACHMENT,
+      view_formats: &[],
+    }));
+
+    queue.write_texture(
+      wgpu::ImageCopyTexture {
+        texture: &texture,
+        mip_level: 0,
+        origin: wgpu::Origin3d::ZERO,
+        aspect: wgpu::TextureAspect::All,
+      },
+      &rgba,
+      wgpu::ImageDataLayout {
+        offset: 0,
+        bytes_per_row: std::num::NonZeroU32::new(4 * width),
+        rows_per_image: std::num::NonZeroU32::new(height),
+      },
+      size,
+    );
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+      label: Some("Mipmap Encoder"),
+    });
+    mipmaps.generate(device, &mut encoder, &texture, format, mip_level_count);
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let sampler_settings = SamplerSettings::anisotropic(MAX_ANISOTROPY);
+    let sampler = samplers.create(device, label, &sampler_settings);
+    Self {
+      texture,
+      view,
+      sampler,
+      sampler_settings,
+      mip_level_count,
+    }
+  }
+
+  pub fn set_sampler_settings(
+    &mut self,
+    device: &Device,
+    samplers: &Samplers,
+    settings: SamplerSettings,
+  ) {
+    self.sampler_settings = settings;
+    self.refresh_sampler(device, samplers);
+  }
+
+  // Recreates the sampler after Samplers::toggle_anisotropy, bind groups using the old
+  // one have to be rebuilt too
+  pub fn refresh_sampler(&mut self, device: &Device, samplers: &Samplers) {
+    self.sampler = samplers.create(device, "Texture Sampler", &self.sampler_settings);
+  }
+}
@@ -0,0 +1,387 @@
+use std::collections::HashMap;
+
+use wgpu::{Device, Queue};
+
+use crate::{gpu_memory::Tracked, sampler::SamplerSettings};
+
+// empty pixels between entries so linear filtering doesn't bleed in the neighbours
+const PADDING: u32 = 1;
+
+// Skyline rectangle packer: tracks the top edge of everything placed so far as a list of
+// horizontal segments and drops each new rectangle at the lowest spot it fits
+pub struct RectPacker {
+  width: u32,
+  height: u32,
+  // (x, y, width) segments covering the whole width, sorted by x
+  skyline: Vec<(u32, u32, u32)>,
+}
+
+impl RectPacker {
+  pub fn new(width: u32, height: u32) -> Self {
+    Self {
+      width,
+      height,
+      skyline: vec![(0, 0, width)],
+    }
+  }
+
+  pub fn clear(&mut self) {
+    self.skyline = vec![(0, 0, self.width)];
+  }
+
+  // Top left corner of the placed rectangle, None when it doesn't fit anymore
+  pub fn pack(&mut self, width: u32, height: u32) -> Option<[u32; 2]> {
+    if width == 0 || height == 0 || width > self.width || height > self.height {
+      return None;
+    }
+
+    // lowest resulting top edge, ties go to the narrowest segment
+    let mut best: Option<(usize, u32, u32)> = None;
+    for (i, &(x, _, segment_width)) in self.skyline.iter().enumerate() {
+      if x + width > self.width {
+        break;
+      }
+      let Some(y) = self.fit(i, width) else {
+        continue;
+      };
+      if y + height > self.height {
+        continue;
+      }
+      if best.is_none_or(|(_, best_y, best_width)| {
+        y < best_y || (y == best_y && segment_width < best_width)
+      }) {
+        best = Some((i, y, segment_width));
+      }
+    }
+
+    let (index, y, _) = best?;
+    let x = self.skyline[index].0;
+    self.place(index, x, y + height, width);
+    Some([x, y])
+  }
+
+  // Height a rectangle starting at segment `index` would rest at
+  fn fit(&self, index: usize, width: u32) -> Option<u32> {
+    let x = self.skyline[index].0;
+    let mut y = 0;
+    for &(segment_x, segment_y, segment_width) in &self.skyline[index..] {
+      if segment_x >= x + width {
+        break;
+      }
+      y = y.max(segment_y);
+      if segment_x + segment_width >= x + width {
+        return Some(y);
+      }
+    }
+    // ran off the end of the skyline
+    (x + width <= self.width).then_some(y)
+  }
+
+  fn place(&mut self, index: usize, x: u32, top: u32, width: u32) {
+    self.skyline.insert(index, (x, top, width));
+    // shrink or remove the segments now underneath the new one
+    let end = x + width;
+    let i = index + 1;
+    while i < self.skyline.len() {
+      let (segment_x, segment_y, segment_width) = self.skyline[i];
+      if segment_x >= end {
+        break;
+      }
+      let segment_end = segment_x + segment_width;
+      if segment_end <= end {
+        self.skyline.remove(i);
+      } else {
+        self.skyline[i] = (end, segment_y, segment_end - end);
+        break;
+      }
+    }
+    // merge neighbours at the same height
+    let mut i = 0;
+    while i + 1 < self.skyline.len() {
+      if self.skyline[i].1 == self.skyline[i + 1].1 {
+        self.skyline[i].2 += self.skyline[i + 1].2;
+        self.skyline.remove(i + 1);
+      } else {
+        i += 1;
+      }
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum AtlasError {
+  Full {
+    name: String,
+    width: u32,
+    height: u32,
+  },
+  SizeMismatch {
+    name: String,
+  },
+  Decode {
+    name: String,
+    error: image::ImageError,
+  },
+}
+
+impl std::fmt::Display for AtlasError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      AtlasError::Full {
+        name,
+        width,
+        height,
+      } => write!(
+        f,
+        "no room left in the atlas for {} ({}x{})",
+        name, width, height
+      ),
+      AtlasError::SizeMismatch { name } => {
+        write!(f, "the pixels of {} don't match its size", name)
+      }
+      AtlasError::Decode { name, error } => write!(f, "couldn't decode {}: {}", name, error),
+    }
+  }
+}
+
+impl std::error::Error for AtlasError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      AtlasError::Decode { error, .. } => Some(error),
+      _ => None,
+    }
+  }
+}
+
+// Where an image ended up, `uv` is (u, v, width, height) in 0..1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AtlasRegion {
+  pub x: u32,
+  pub y: u32,
+  pub width: u32,
+  pub height: u32,
+  pub uv: [f32; 4],
+}
+
+// Many small images in one texture, so everything drawn from it shares a bind group
+pub struct TextureAtlas {
+  texture: Tracked<wgpu::Texture>,
+  view: wgpu::TextureView,
+  sampler: wgpu::Sampler,
+  packer: RectPacker,
+  regions: HashMap<String, AtlasRegion>,
+  size: u32,
+}
+
+impl TextureAtlas {
+  pub fn new(device: &Device, size: u32) -> Self {
+    let texture = Tracked::texture(device.create_texture(&wgpu::TextureDescriptor {
+      label: Some("Texture Atlas"),
+      size: wgpu::Extent3d {
+        width: size,
+        height: size,
+        depth_or_array_layers: 1,
+      },
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format: wgpu::TextureFormat::Rgba8UnormSrgb,
+      usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+      view_formats: &[],
+    }));
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let sampler =
+      device.create_sampler(&SamplerSettings::linear().descriptor(Some("Texture Atlas Sampler")));
+    Self {
+      texture,
+      view,
+      sampler,
+      packer: RectPacker::new(size, size),
+      regions: HashMap::new(),
+      size,
+    }
+  }
+
+  pub fn view(&self) -> &wgpu::TextureView {
+    &self.view
+  }
+
+  pub fn sampler(&self) -> &wgpu::Sampler {
+    &self.sampler
+  }
+
+  pub fn size(&self) -> u32 {
+    self.size
+  }
+
+  pub fn len(&self) -> usize {
+    self.regions.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.regions.is_empty()
+  }
+
+  pub fn get(&self, name: &str) -> Option<&AtlasRegion> {
+    self.regions.get(name)
+  }
+
+  pub fn uv(&self, name: &str) -> Option<[f32; 4]> {
+    self.get(name).map(|region| region.uv)
+  }
+
+  // Adds tightly packed RGBA8 pixels under `name`. Inserting a name again returns
+  // the existing region without uploading anything.
+  pub fn insert(
+    &mut self,
+    queue: &Queue,
+    name: &str,
+    width: u32,
+    height: u32,
+    rgba: &[u8],
+  ) -> Result<AtlasRegion, AtlasError> {
+    if let Some(region) = self.regions.get(name) {
+      return Ok(*region);
+    }
+    if rgba.len() != (width * height * 4) as usize {
+      return Err(AtlasError::SizeMismatch {
+        name: name.to_string(),
+      });
+    }
+    let [x, y] = self
+      .packer
+      .pack(width + PADDING, height + PADDING)
+      .ok_or_else(|| AtlasError::Full {
+        name: name.to_string(),
+        width,
+        height,
+      })?;
+
+    queue.write_texture(
+      wgpu::ImageCopyTexture {
+        texture: &self.texture,
+        mip_level: 0,
+        origin: wgpu::Origin3d { x, y, z: 0 },
+        aspect: wgpu::TextureAspect::All,
+      },
+      rgba,
+      wgpu::ImageDataLayout {
+        offset: 0,
+        bytes_per_row: std::num::NonZeroU32::new(width * 4),
+        rows_per_image: None,
+      },
+      wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+      },
+    );
+
+    let size = self.size as f32;
+    let region = AtlasRegion {
+      x,
+      y,
+      width,
+      height,
+      uv: [
+        x as f32 / size,
+        y as f32 / size,
+        width as f32 / size,
+        height as f32 / size,
+      ],
+    };
+    self.regions.insert(name.to_string(), region);
+    Ok(region)
+  }
+
+  // Decodes a PNG/JPEG (e.g. from Assets::read) and adds it
+  pub fn insert_image(
+    &mut self,
+    queue: &Queue,
+    name: &str,
+    bytes: &[u8],
+  ) -> Result<AtlasRegion, AtlasError> {
+    let image = image::load_from_memory(bytes)
+      .map_err(|error| AtlasError::Decode {
+        name: name.to_string(),
+        error,
+      })?
+      .to_rgba8();
+    self.insert(queue, name, image.width(), image.height(), &image)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::math::next_random;
+
+  // [x, y, width, height]
+  fn overlap(a: [u32; 4], b: [u32; 4]) -> bool {
+    a[0] < b[0] + b[2] && b[0] < a[0] + a[2] && a[1] < b[1] + b[3] && b[1] < a[1] + a[3]
+  }
+
+  #[test]
+  fn placement() {
+    let mut packer = RectPacker::new(16, 16);
+    assert_eq!(packer.pack(4, 4), Some([0, 0]));
+    // next to the first, that's the lowest spot
+    assert_eq!(packer.pack(8, 2), Some([4, 0]));
+    assert_eq!(packer.pack(4, 4), Some([12, 0]));
+    // only fits across the whole width, on top of the tallest part under it
+    assert_eq!(packer.pack(16, 1), Some([0, 4]));
+    // which leaves a flat skyline to fill up exactly
+    assert_eq!(packer.pack(16, 11), Some([0, 5]));
+  }
+
+  #[test]
+  fn ties_go_to_the_narrowest_segment() {
+    let mut packer = RectPacker::new(16, 16);
+    assert_eq!(packer.pack(4, 2), Some([0, 0]));
+    assert_eq!(packer.pack(4, 6), Some([4, 0]));
+    assert_eq!(packer.pack(8, 2), Some([8, 0]));
+    // both x = 0 and x = 8 rest at y = 2, the 4 wide one is a tighter fit
+    assert_eq!(packer.pack(4, 1), Some([0, 2]));
+  }
+
+  #[test]
+  fn overflow() {
+    let mut packer = RectPacker::new(8, 8);
+    assert_eq!(packer.pack(9, 1), None);
+    assert_eq!(packer.pack(1, 9), None);
+    assert_eq!(packer.pack(0, 4), None);
+    for i in 0..4 {
+      assert_eq!(packer.pack(4, 4), Some([i % 2 * 4, i / 2 * 4]));
+    }
+    assert_eq!(packer.pack(1, 1), None);
+    packer.clear();
+    assert_eq!(packer.pack(8, 8), Some([0, 0]));
+  }
+
+  // What TextureAtlas::insert() does: every image gets PADDING empty pixels to its right
+  // and below, so no two images touch and none reach past the edge
+  #[test]
+  fn padding() {
+    let size = 128;
+    let mut packer = RectPacker::new(size, size);
+    let mut state = 7;
+    let mut placed: Vec<[u32; 4]> = Vec::new();
+    for _ in 0..200 {
+      let width = 1 + (next_random(&mut state) * 15.0) as u32;
+      let height = 1 + (next_random(&mut state) * 15.0) as u32;
+      let Some([x, y]) = packer.pack(width + PADDING, height + PADDING) else {
+        continue;
+      };
+      assert!(x + width + PADDING <= size && y + height + PADDING <= size);
+      let padded = [x, y, width + PADDING, height + PADDING];
+      for &other in &placed {
+        assert!(!overlap(
+          padded,
+          [other[0], other[1], other[2] + PADDING, other[3] + PADDING]
+        ));
+      }
+      placed.push([x, y, width, height]);
+    }
+    assert!(placed.len() > 50, "only {} fit", placed.len());
+  }
+}
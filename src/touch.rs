@@ -0,0 +1,97 @@
+use winit::event::TouchPhase;
+
+// What the fingers on a touch screen did with their last move
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Gesture {
+  // one finger moved this many physical pixels
+  Drag([f32; 2]),
+  // two fingers moved apart, the new distance between them over the old one
+  Pinch(f32),
+}
+
+// Turns touches into gestures. A second finger turns a drag into a pinch, any more than
+// two are ignored until one of the first two lifts.
+#[derive(Debug, Default)]
+pub struct TouchGestures {
+  // ids and where they are now, in the order they went down
+  touches: Vec<(u64, [f64; 2])>,
+}
+
+impl TouchGestures {
+  // `position` in physical pixels
+  pub fn touch(&mut self, id: u64, phase: TouchPhase, position: [f64; 2]) -> Option<Gesture> {
+    let index = self.touches.iter().position(|&(other, _)| other == id);
+    match (phase, index) {
+      (TouchPhase::Started, None) => {
+        self.touches.push((id, position));
+        None
+      }
+      (TouchPhase::Moved, Some(index)) => {
+        let spread = self.spread();
+        let [x, y] = self.touches[index].1;
+        self.touches[index].1 = position;
+        match (self.touches.len(), spread, self.spread()) {
+          (1, _, _) => Some(Gesture::Drag([
+            (position[0] - x) as f32,
+            (position[1] - y) as f32,
+          ])),
+          // a third finger moving changes nothing
+          _ if index > 1 => None,
+          (_, Some(before), Some(after)) if before > 0.0 => Some(Gesture::Pinch(after / before)),
+          _ => None,
+        }
+      }
+      (TouchPhase::Ended | TouchPhase::Cancelled, Some(index)) => {
+        self.touches.remove(index);
+        None
+      }
+      _ => None,
+    }
+  }
+
+  // Distance between the first two fingers
+  fn spread(&self) -> Option<f32> {
+    match self.touches[..] {
+      [(_, a), (_, b), ..] => Some((a[0] - b[0]).hypot(a[1] - b[1]) as f32),
+      _ => None,
+    }
+  }
+}
+
+// winit only reports touches on the web as mouse events, so the canvas' touch events are
+// sent through the bridge instead, see BridgeCommand::Touch
+#[cfg(target_arch = "wasm32")]
+pub fn listen(canvas: &web_sys::HtmlCanvasElement) {
+  use wasm_bindgen::{prelude::*, JsCast};
+  use web_sys::TouchEvent;
+
+  use crate::bridge::{self, BridgeCommand};
+
+  // the page would scroll and zoom instead
+  let _ = canvas.style().set_property("touch-action", "none");
+  for (name, phase) in [
+    ("touchstart", TouchPhase::Started),
+    ("touchmove", TouchPhase::Moved),
+    ("touchend", TouchPhase::Ended),
+    ("touchcancel", TouchPhase::Cancelled),
+  ] {
+    let target = canvas.clone();
+    let on_touch = Closure::<dyn FnMut(TouchEvent)>::new(move |event: TouchEvent| {
+      let rect = target.get_bounding_client_rect();
+      let scale = web_sys::window().map_or(1.0, |w| w.device_pixel_ratio());
+      let touches = event.changed_touches();
+      for touch in (0..touches.length()).filter_map(|i| touches.get(i)) {
+        bridge::send(BridgeCommand::Touch {
+          id: touch.identifier() as u64,
+          phase,
+          position: [
+            (touch.client_x() as f64 - rect.left()) * scale,
+            (touch.client_y() as f64 - rect.top()) * scale,
+          ],
+        });
+      }
+    });
+    let _ = canvas.add_event_listener_with_callback(name, on_touch.as_ref().unchecked_ref());
+    on_touch.forget();
+  }
+}
@@ -0,0 +1,347 @@
+// Moving a value smoothly from one setting to another over some seconds, instead of jumping.
+// A Tween goes from a start to an end value along an Easing curve as update() is fed the
+// frame times, and can call back once it gets there. Tweened is a property that's always
+// got a value: setting it starts a tween from wherever it is now, so changing it again
+// halfway turns around without a jump.
+
+use std::fmt;
+
+// The share of the way a tween has gone at a share of its time, 0 and 1 at either end.
+// In speeds up from the start, Out slows down into the end, InOut does both.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Easing {
+  #[default]
+  Linear,
+  QuadIn,
+  QuadOut,
+  QuadInOut,
+  CubicIn,
+  CubicOut,
+  CubicInOut,
+  SineInOut,
+  ExpoOut,
+  // overshoots the end a little and comes back
+  BackOut,
+  // drops onto the end and bounces a few times
+  BounceOut,
+}
+
+impl Easing {
+  // `t` from 0 to 1, clamped
+  pub fn apply(self, t: f32) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    match self {
+      Easing::Linear => t,
+      Easing::QuadIn => t * t,
+      Easing::QuadOut => 1.0 - (1.0 - t) * (1.0 - t),
+      Easing::QuadInOut if t < 0.5 => 2.0 * t * t,
+      Easing::QuadInOut => 1.0 - 2.0 * (1.0 - t) * (1.0 - t),
+      Easing::CubicIn => t * t * t,
+      Easing::CubicOut => 1.0 - (1.0 - t).powi(3),
+      Easing::CubicInOut if t < 0.5 => 4.0 * t * t * t,
+      Easing::CubicInOut => 1.0 - 4.0 * (1.0 - t).powi(3),
+      Easing::SineInOut => 0.5 - 0.5 * (std::f32::consts::PI * t).cos(),
+      Easing::ExpoOut if t >= 1.0 => 1.0,
+      Easing::ExpoOut => 1.0 - 2f32.powf(-10.0 * t),
+      Easing::BackOut => {
+        // the usual amount of overshoot, about 10%
+        let (c1, u) = (1.70158, t - 1.0);
+        1.0 + (c1 + 1.0) * u * u * u + c1 * u * u
+      }
+      Easing::BounceOut => bounce_out(t),
+    }
+  }
+}
+
+// Robert Penner's, a parabola for the drop and one for every bounce
+fn bounce_out(t: f32) -> f32 {
+  const N: f32 = 7.5625;
+  const D: f32 = 2.75;
+  if t < 1.0 / D {
+    N * t * t
+  } else if t < 2.0 / D {
+    let t = t - 1.5 / D;
+    N * t * t + 0.75
+  } else if t < 2.5 / D {
+    let t = t - 2.25 / D;
+    N * t * t + 0.9375
+  } else {
+    let t = t - 2.625 / D;
+    N * t * t + 0.984375
+  }
+}
+
+// What a tween can move: `t` of the way from `a` to `b`. Eased values of `t` go a little
+// outside 0 to 1 with BackOut.
+pub trait Lerp: Copy {
+  fn lerp(a: Self, b: Self, t: f32) -> Self;
+}
+
+impl Lerp for f32 {
+  fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+  }
+}
+
+impl Lerp for f64 {
+  fn lerp(a: f64, b: f64, t: f32) -> f64 {
+    a + (b - a) * t as f64
+  }
+}
+
+// Vec3 positions, colors and the like
+impl<const N: usize> Lerp for [f32; N] {
+  fn lerp(a: Self, b: Self, t: f32) -> Self {
+    std::array::from_fn(|i| f32::lerp(a[i], b[i], t))
+  }
+}
+
+impl Lerp for wgpu::Color {
+  fn lerp(a: Self, b: Self, t: f32) -> Self {
+    wgpu::Color {
+      r: f64::lerp(a.r, b.r, t),
+      g: f64::lerp(a.g, b.g, t),
+      b: f64::lerp(a.b, b.b, t),
+      a: f64::lerp(a.a, b.a, t),
+    }
+  }
+}
+
+pub struct Tween<T: Lerp> {
+  from: T,
+  to: T,
+  // seconds
+  duration: f32,
+  elapsed: f32,
+  easing: Easing,
+  // called once by the update() that reaches the end
+  on_complete: Option<Box<dyn FnOnce()>>,
+}
+
+impl<T: Lerp> Tween<T> {
+  // Linear, see with_easing()
+  pub fn new(from: T, to: T, duration: f32) -> Self {
+    Self {
+      from,
+      to,
+      duration: duration.max(0.0),
+      elapsed: 0.0,
+      easing: Easing::Linear,
+      on_complete: None,
+    }
+  }
+
+  pub fn with_easing(mut self, easing: Easing) -> Self {
+    self.easing = easing;
+    self
+  }
+
+  pub fn on_complete(mut self, callback: impl FnOnce() + 'static) -> Self {
+    self.on_complete = Some(Box::new(callback));
+    self
+  }
+
+  pub fn from(&self) -> T {
+    self.from
+  }
+
+  pub fn to(&self) -> T {
+    self.to
+  }
+
+  pub fn easing(&self) -> Easing {
+    self.easing
+  }
+
+  // Share of its time gone, 0 to 1. One that takes no time is done at once.
+  pub fn progress(&self) -> f32 {
+    if self.duration > 0.0 {
+      (self.elapsed / self.duration).min(1.0)
+    } else {
+      1.0
+    }
+  }
+
+  pub fn is_finished(&self) -> bool {
+    self.progress() >= 1.0
+  }
+
+  pub fn value(&self) -> T {
+    if self.is_finished() {
+      // exactly the end, whatever the easing does with rounding
+      return self.to;
+    }
+    T::lerp(self.from, self.to, self.easing.apply(self.progress()))
+  }
+
+  // Moves on `dt` seconds and returns the value there, calling back if that's the end
+  pub fn update(&mut self, dt: f32) -> T {
+    self.elapsed += dt.max(0.0);
+    if self.is_finished() {
+      if let Some(callback) = self.on_complete.take() {
+        callback();
+      }
+    }
+    self.value()
+  }
+}
+
+impl<T: Lerp + fmt::Debug> fmt::Debug for Tween<T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Tween")
+      .field("from", &self.from)
+      .field("to", &self.to)
+      .field("duration", &self.duration)
+      .field("elapsed", &self.elapsed)
+      .field("easing", &self.easing)
+      .field("on_complete", &self.on_complete.is_some())
+      .finish()
+  }
+}
+
+// A property that eases to the values it's set to, see the top of the file
+#[derive(Debug)]
+pub struct Tweened<T: Lerp> {
+  value: T,
+  tween: Option<Tween<T>>,
+}
+
+impl<T: Lerp> Tweened<T> {
+  pub fn new(value: T) -> Self {
+    Self { value, tween: None }
+  }
+
+  pub fn get(&self) -> T {
+    self.value
+  }
+
+  // Where it's heading, what it is when it's not moving
+  pub fn target(&self) -> T {
+    self.tween.as_ref().map_or(self.value, Tween::to)
+  }
+
+  // At once, dropping a tween on the way without calling it back
+  pub fn set(&mut self, value: T) {
+    self.value = value;
+    self.tween = None;
+  }
+
+  // From where it is now to `value` over `duration` seconds. The tween replaced on the way
+  // doesn't call back.
+  pub fn ease_to(&mut self, value: T, duration: f32, easing: Easing) {
+    self.start(Tween::new(self.value, value, duration).with_easing(easing));
+  }
+
+  // A tween of its own, e.g. with a callback. It starts from where it is now rather than
+  // `tween`'s start.
+  pub fn start(&mut self, tween: Tween<T>) {
+    self.tween = Some(Tween {
+      from: self.value,
+      ..tween
+    });
+  }
+
+  pub fn is_animating(&self) -> bool {
+    self.tween.is_some()
+  }
+
+  // Moves on `dt` seconds and returns the value there
+  pub fn update(&mut self, dt: f32) -> T {
+    if let Some(tween) = &mut self.tween {
+      self.value = tween.update(dt);
+      if tween.is_finished() {
+        self.tween = None;
+      }
+    }
+    self.value
+  }
+}
+
+impl<T: Lerp + Default> Default for Tweened<T> {
+  fn default() -> Self {
+    Self::new(T::default())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{cell::Cell, rc::Rc};
+
+  use super::*;
+
+  const EASINGS: [Easing; 11] = [
+    Easing::Linear,
+    Easing::QuadIn,
+    Easing::QuadOut,
+    Easing::QuadInOut,
+    Easing::CubicIn,
+    Easing::CubicOut,
+    Easing::CubicInOut,
+    Easing::SineInOut,
+    Easing::ExpoOut,
+    Easing::BackOut,
+    Easing::BounceOut,
+  ];
+
+  // Counts the calls of the callback it hands out
+  fn counter() -> (Rc<Cell<u32>>, impl FnOnce() + 'static) {
+    let calls = Rc::new(Cell::new(0));
+    let counted = calls.clone();
+    (calls, move || counted.set(counted.get() + 1))
+  }
+
+  #[test]
+  fn easing_endpoints() {
+    for easing in EASINGS {
+      assert!(easing.apply(0.0).abs() < 1e-3, "{:?} at 0", easing);
+      assert!((easing.apply(1.0) - 1.0).abs() < 1e-3, "{:?} at 1", easing);
+      // clamped outside 0 to 1
+      assert_eq!(easing.apply(-1.0), easing.apply(0.0), "{:?}", easing);
+      assert_eq!(easing.apply(2.0), easing.apply(1.0), "{:?}", easing);
+    }
+    assert_eq!(Easing::QuadInOut.apply(0.5), 0.5);
+    assert!(Easing::BackOut.apply(0.7) > 1.0);
+  }
+
+  #[test]
+  fn on_complete_fires_once() {
+    let (calls, callback) = counter();
+    let mut tween = Tween::new(0.0, 10.0, 1.0).on_complete(callback);
+    assert_eq!(tween.update(0.5), 5.0);
+    assert_eq!(calls.get(), 0);
+    assert_eq!(tween.update(0.75), 10.0);
+    assert_eq!(calls.get(), 1);
+    tween.update(1.0);
+    assert!(tween.is_finished());
+    assert_eq!(calls.get(), 1);
+  }
+
+  #[test]
+  fn zero_duration_finishes_at_once() {
+    let (calls, callback) = counter();
+    let mut tween = Tween::new(1.0, 2.0, 0.0).on_complete(callback);
+    assert_eq!(tween.value(), 2.0);
+    assert_eq!(tween.update(0.0), 2.0);
+    assert_eq!(calls.get(), 1);
+  }
+
+  #[test]
+  fn tweened_turns_around_without_a_jump() {
+    let mut value = Tweened::new(0.0);
+    let (replaced, callback) = counter();
+    value.start(Tween::new(0.0, 10.0, 1.0).on_complete(callback));
+    assert_eq!(value.update(0.5), 5.0);
+    // the new one starts at 5 and the replaced one never calls back
+    let (calls, callback) = counter();
+    value.start(Tween::new(100.0, 0.0, 1.0).on_complete(callback));
+    assert_eq!(value.get(), 5.0);
+    assert_eq!(value.target(), 0.0);
+    assert_eq!(value.update(0.5), 2.5);
+    assert_eq!(value.update(0.5), 0.0);
+    assert!(!value.is_animating());
+    assert_eq!(calls.get(), 1);
+    value.update(1.0);
+    assert_eq!(calls.get(), 1);
+    assert_eq!(replaced.get(), 0);
+  }
+}
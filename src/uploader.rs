@@ -0,0 +1,86 @@
+use std::num::NonZeroU64;
+
+use wgpu::{util::StagingBelt, Buffer, CommandEncoder, Device, Queue, SubmissionIndex};
+
+// Bytes per staging buffer, enough for a frame's uniforms. A bigger write gets a buffer of
+// its own.
+const CHUNK_SIZE: u64 = 64 * 1024;
+
+// Per frame buffer writes through a staging belt: the data goes into mapped staging
+// buffers that are reused frame after frame instead of the queue allocating for every
+// write, and the copy into the target is recorded in the encoder. Unlike
+// queue.write_buffer(), which runs every write before the whole submit, a write lands in
+// order with the passes recorded around it.
+pub struct Uploader {
+  belt: StagingBelt,
+}
+
+impl Uploader {
+  pub fn new() -> Self {
+    Self {
+      belt: StagingBelt::new(CHUNK_SIZE),
+    }
+  }
+
+  // `target` needs COPY_DST and `offset` and the length of `data` have to be multiples of
+  // 4, like for queue.write_buffer(). Writing nothing does nothing.
+  pub fn write(
+    &mut self,
+    device: &Device,
+    encoder: &mut CommandEncoder,
+    target: &Buffer,
+    offset: u64,
+    data: &[u8],
+  ) {
+    let Some(size) = NonZeroU64::new(data.len() as u64) else {
+      return;
+    };
+    self
+      .belt
+      .write_buffer(encoder, target, offset, size, device)
+      .copy_from_slice(data);
+  }
+
+  // The writes of one encoder, to hand down to whatever uploads into it
+  pub fn with<'a>(&'a mut self, device: &'a Device, encoder: &'a mut CommandEncoder) -> Upload<'a> {
+    Upload {
+      uploader: self,
+      device,
+      encoder,
+    }
+  }
+
+  // Submits `encoder` with the writes recorded into it. The staging buffers come back for
+  // the next frame's writes once the GPU has copied out of them.
+  pub fn submit(&mut self, queue: &Queue, encoder: CommandEncoder) -> SubmissionIndex {
+    self.belt.finish();
+    let index = queue.submit(std::iter::once(encoder.finish()));
+    self.belt.recall();
+    index
+  }
+}
+
+impl Default for Uploader {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+pub struct Upload<'a> {
+  uploader: &'a mut Uploader,
+  device: &'a Device,
+  encoder: &'a mut CommandEncoder,
+}
+
+impl Upload<'_> {
+  pub fn write(&mut self, target: &Buffer, offset: u64, data: &[u8]) {
+    self
+      .uploader
+      .write(self.device, self.encoder, target, offset, data);
+  }
+
+  // For passes that use what was just written
+  pub fn encoder(&mut self) -> &mut CommandEncoder {
+    self.encoder
+  }
+}
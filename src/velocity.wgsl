@@ -0,0 +1,30 @@
+// Motion vectors for TAA: how far each pixel moved on screen since last frame, drawn over
+// the finished scene depth. Only the camera moves things between frames, the instances are
+// where they were.
+
+#include "common.wgsl"
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) current: vec4<f32>,
+    @location(1) previous: vec4<f32>,
+};
+
+@vertex
+fn vs_main(vertex: VertexInput, instance: InstanceInput) -> VertexOutput {
+    let world = instance_model(instance) * vec4<f32>(model_position(vertex, instance), 1.0);
+    var out: VertexOutput;
+    // jittered like the passes that wrote the depth, so the depth test matches them
+    out.clip_position = globals.view_proj * world;
+    out.current = globals.unjittered_view_proj * world;
+    out.previous = globals.previous_view_proj * world;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let current = in.current.xy / in.current.w;
+    let previous = in.previous.xy / in.previous.w;
+    // in texture coordinates, where v points down
+    return vec4<f32>((current - previous) * vec2<f32>(0.5, -0.5), 0.0, 0.0);
+}
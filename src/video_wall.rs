@@ -0,0 +1,71 @@
+use winit::{
+  dpi::{PhysicalPosition, PhysicalSize},
+  error::OsError,
+  event_loop::EventLoopWindowTarget,
+  monitor::MonitorHandle,
+  window::{Fullscreen, Window, WindowBuilder},
+};
+
+use crate::{camera::Tile, window_settings::WindowSettings};
+
+// The tile of one picture each monitor shows, the picture being the rectangle around all of
+// them as the OS arranges them. Monitors are taken to have the same pixel size, gaps
+// between them aren't left out of the picture.
+pub fn tiles(monitors: &[(PhysicalPosition<i32>, PhysicalSize<u32>)]) -> Vec<Tile> {
+  let left = monitors.iter().map(|(p, _)| p.x).min().unwrap_or(0);
+  let top = monitors.iter().map(|(p, _)| p.y).min().unwrap_or(0);
+  let right = monitors
+    .iter()
+    .map(|(p, s)| p.x + s.width as i32)
+    .max()
+    .unwrap_or(1);
+  let bottom = monitors
+    .iter()
+    .map(|(p, s)| p.y + s.height as i32)
+    .max()
+    .unwrap_or(1);
+  let (width, height) = ((right - left).max(1) as f32, (bottom - top).max(1) as f32);
+  monitors
+    .iter()
+    .map(|(position, size)| {
+      let half_size = [size.width as f32 / width, size.height as f32 / height];
+      // 0..1 from the top left of the picture to its NDC
+      let x = (position.x - left) as f32 / width + half_size[0] / 2.0;
+      let y = (position.y - top) as f32 / height + half_size[1] / 2.0;
+      Tile {
+        aspect: width / height,
+        center: [x * 2.0 - 1.0, 1.0 - y * 2.0],
+        half_size,
+      }
+    })
+    .collect()
+}
+
+// A borderless fullscreen window on every monitor with the tile it shows, for
+// WindowSettings::span_monitors. Empty where winit doesn't list the monitors (the web).
+pub fn open<T>(
+  settings: &WindowSettings,
+  target: &EventLoopWindowTarget<T>,
+) -> Result<Vec<(Window, Tile)>, OsError> {
+  let monitors: Vec<MonitorHandle> = target.available_monitors().collect();
+  let layout: Vec<_> = monitors.iter().map(|m| (m.position(), m.size())).collect();
+  let tiles = tiles(&layout);
+  let mut windows = Vec::with_capacity(monitors.len());
+  for (monitor, tile) in monitors.into_iter().zip(tiles) {
+    log::info!(
+      "{} at {:?}, {:?}",
+      monitor.name().unwrap_or_else(|| "monitor".to_string()),
+      monitor.position(),
+      monitor.size()
+    );
+    let window = WindowBuilder::new()
+      .with_title(&settings.title)
+      .with_position(monitor.position())
+      .with_inner_size(monitor.size())
+      .with_decorations(false)
+      .with_fullscreen(Some(Fullscreen::Borderless(Some(monitor))))
+      .build(target)?;
+    windows.push((window, tile));
+  }
+  Ok(windows)
+}
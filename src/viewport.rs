@@ -0,0 +1,457 @@
+use std::sync::Arc;
+
+use wgpu::{Adapter, Device, Queue};
+use winit::{
+  dpi::PhysicalSize,
+  event::*,
+  window::{Window, WindowId},
+};
+
+use crate::{
+  camera::Tile,
+  compare::FrameCompare,
+  deferred::GBuffer,
+  exposure::Exposure,
+  fxaa::Fxaa,
+  hdr::HdrPipeline,
+  hiz::HiZ,
+  pipeline::{main_pipe, PipelineCache},
+  state::StateError,
+  taa::Taa,
+  tween::{Easing, Tweened},
+  window_settings::{toggle_fullscreen_mode, FullscreenMode},
+};
+
+// Entry point pairs in shader.wgsl, new windows take the next one in the list
+pub const SHADER_VARIANTS: [&str; 2] = ["main", "rainbow"];
+// seconds the clear color takes to change
+const COLOR_FADE: f32 = 0.4;
+
+// The format we'd pick for the surface right now, it can change when the window moves
+// to another monitor
+pub fn preferred_format(
+  surface: &wgpu::Surface,
+  adapter: &Adapter,
+) -> Result<wgpu::TextureFormat, StateError> {
+  // Shader code in this tutorial assumes an sRGB surface texture. Using a different
+  // one will result all the colors coming out darker. If you want to support non
+  // sRGB surfaces, you'll need to account for that when drawing to the frame.
+  let formats = surface.get_capabilities(adapter).formats;
+  formats
+    .iter()
+    .copied()
+    .find(|f| f.describe().srgb)
+    .or_else(|| formats.first().copied())
+    .ok_or(StateError::NoSupportedFormat)
+}
+
+// Configuration for a freshly created surface
+pub fn surface_config(
+  surface: &wgpu::Surface,
+  adapter: &Adapter,
+  size: PhysicalSize<u32>,
+) -> Result<wgpu::SurfaceConfiguration, StateError> {
+  let surface_caps = surface.get_capabilities(adapter);
+  let surface_format = preferred_format(surface, adapter)?;
+
+  Ok(wgpu::SurfaceConfiguration {
+    usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+    format: surface_format,
+    width: size.width,
+    height: size.height,
+    present_mode: *surface_caps
+      .present_modes
+      .first()
+      .ok_or(StateError::NoPresentMode)?,
+    alpha_mode: *surface_caps
+      .alpha_modes
+      .first()
+      .ok_or(StateError::NoAlphaMode)?,
+    view_formats: vec![],
+  })
+}
+
+// Everything that belongs to a single window, the device and queue are shared through State
+pub struct Viewport {
+  surface: wgpu::Surface,
+  config: wgpu::SurfaceConfiguration,
+  pub size: PhysicalSize<u32>,
+  window: Window,
+  // shader variant drawn while space isn't held
+  variant: &'static str,
+  main_pipe: Arc<wgpu::RenderPipeline>,
+  // shader.wgsl as edited since startup, None for the one built in
+  shader: Option<Arc<str>>,
+  // eases to what the cursor changes it to
+  color: Tweened<wgpu::Color>,
+  click: bool,
+  // in physical pixels, None while it's outside the window
+  cursor: Option<[f64; 2]>,
+  // the part of a picture spanning several windows this one shows, None for all of it
+  tile: Option<Tile>,
+  hdr: HdrPipeline,
+  gbuffer: GBuffer,
+  hiz: HiZ,
+  exposure: Exposure,
+  fxaa: Fxaa,
+  taa: Taa,
+  compare: FrameCompare,
+}
+
+impl Viewport {
+  // `surface` has to be created from `window`, the viewport keeps both alive together.
+  // `sample_count` is the main pass's, see hdr::msaa_sample_count().
+  #[allow(clippy::too_many_arguments)]
+  pub fn new(
+    window: Window,
+    surface: wgpu::Surface,
+    adapter: &Adapter,
+    device: &Device,
+    queue: &Queue,
+    pipelines: &mut PipelineCache,
+    variant: &'static str,
+    sample_count: u32,
+  ) -> Result<Self, StateError> {
+    let size = window.inner_size();
+
+    let config = surface_config(&surface, adapter, size)?;
+    surface.configure(device, &config);
+
+    let hdr = HdrPipeline::new(device, &config, sample_count);
+    let main_pipe = main_pipe(device, pipelines, variant, hdr.sample_count(), None);
+    let (width, height) = hdr.size();
+    let gbuffer = GBuffer::new(device, width, height);
+    let hiz = HiZ::new(device, width, height);
+    let exposure = Exposure::new(device, queue, &hdr);
+    let fxaa = Fxaa::new(device, config.format, width, height);
+    let taa = Taa::new(device, width, height);
+    let compare = FrameCompare::new(device, pipelines, &config, SHADER_VARIANTS);
+    Ok(Self {
+      surface,
+      config,
+      size,
+      window,
+      variant,
+      main_pipe,
+      shader: None,
+      color: Tweened::new(wgpu::Color::BLUE),
+      click: false,
+      cursor: None,
+      tile: None,
+      hdr,
+      gbuffer,
+      hiz,
+      exposure,
+      fxaa,
+      taa,
+      compare,
+    })
+  }
+
+  pub fn window(&self) -> &Window {
+    &self.window
+  }
+
+  pub fn id(&self) -> WindowId {
+    self.window.id()
+  }
+
+  pub fn variant(&self) -> &'static str {
+    self.variant
+  }
+
+  // Switches the shader drawn while space isn't held
+  pub fn set_variant(
+    &mut self,
+    device: &Device,
+    pipelines: &mut PipelineCache,
+    variant: &'static str,
+  ) {
+    self.variant = variant;
+    self.main_pipe = self.main_pipe_of(device, pipelines, variant);
+  }
+
+  // Rebuilds the main pipeline from another shader.wgsl, None goes back to the built in one
+  pub fn set_shader(
+    &mut self,
+    device: &Device,
+    pipelines: &mut PipelineCache,
+    source: Option<Arc<str>>,
+  ) {
+    self.shader = source;
+    self.main_pipe = self.main_pipe_of(device, pipelines, self.variant);
+  }
+
+  // `variant`'s pipeline from the shader.wgsl in use, for the hdr target
+  fn main_pipe_of(
+    &self,
+    device: &Device,
+    pipelines: &mut PipelineCache,
+    variant: &str,
+  ) -> Arc<wgpu::RenderPipeline> {
+    let (sample_count, shader) = (self.hdr.sample_count(), self.shader.as_ref());
+    main_pipe(device, pipelines, variant, sample_count, shader)
+  }
+
+  pub fn surface(&self) -> &wgpu::Surface {
+    &self.surface
+  }
+
+  pub fn format(&self) -> wgpu::TextureFormat {
+    self.config.format
+  }
+
+  pub fn color(&self) -> wgpu::Color {
+    self.color.get()
+  }
+
+  // The clear color it's easing to, or is
+  pub fn target_color(&self) -> wgpu::Color {
+    self.color.target()
+  }
+
+  // Whether the clear color is still easing to a new one
+  pub fn is_fading(&self) -> bool {
+    self.color.is_animating()
+  }
+
+  pub fn cursor(&self) -> Option<[f64; 2]> {
+    self.cursor
+  }
+
+  // What the scene's camera shows in this window, the whole picture at its size unless
+  // set_tile() said otherwise
+  pub fn tile(&self) -> Tile {
+    let (width, height) = self.hdr.size();
+    self
+      .tile
+      .unwrap_or_else(|| Tile::whole(width as f32 / height.max(1) as f32))
+  }
+
+  pub fn set_tile(&mut self, tile: Option<Tile>) {
+    self.tile = tile;
+  }
+
+  pub fn main_pipe(&self) -> &wgpu::RenderPipeline {
+    &self.main_pipe
+  }
+
+  pub fn hdr(&self) -> &HdrPipeline {
+    &self.hdr
+  }
+
+  // Only drawn into on the deferred render path
+  pub fn gbuffer(&self) -> &GBuffer {
+    &self.gbuffer
+  }
+
+  // Only drawn into with gpu and occlusion culling on
+  pub fn hiz(&self) -> &HiZ {
+    &self.hiz
+  }
+
+  pub fn exposure(&self) -> &Exposure {
+    &self.exposure
+  }
+
+  pub fn exposure_mut(&mut self) -> &mut Exposure {
+    &mut self.exposure
+  }
+
+  // Only drawn through with anti-aliasing set to fxaa
+  pub fn fxaa(&self) -> &Fxaa {
+    &self.fxaa
+  }
+
+  // Only drawn through with anti-aliasing set to taa
+  pub fn taa(&self) -> &Taa {
+    &self.taa
+  }
+
+  pub fn taa_mut(&mut self) -> &mut Taa {
+    &mut self.taa
+  }
+
+  pub fn compare(&self) -> &FrameCompare {
+    &self.compare
+  }
+
+  pub fn resize(&mut self, device: &Device, new_size: PhysicalSize<u32>) {
+    if new_size.width > 0 && new_size.height > 0 {
+      self.size = new_size;
+      self.config.width = new_size.width;
+      self.config.height = new_size.height;
+      self.surface.configure(device, &self.config);
+      self.hdr.resize(device, new_size.width, new_size.height);
+      self.gbuffer.resize(device, new_size.width, new_size.height);
+      self.hiz.resize(device, new_size.width, new_size.height);
+      self.exposure.resize(device, &self.hdr);
+      self.fxaa.resize(device, new_size.width, new_size.height);
+      self.taa.resize(device, new_size.width, new_size.height);
+      self.compare.resize(device, new_size.width, new_size.height);
+    }
+  }
+
+  // Only Fifo is guaranteed to be supported, check the surface capabilities for the others
+  pub fn set_present_mode(&mut self, device: &Device, present_mode: wgpu::PresentMode) {
+    self.config.present_mode = present_mode;
+    self.surface.configure(device, &self.config);
+  }
+
+  // Fifo with vsync, without it Mailbox or Immediate if the surface has either (Mailbox
+  // doesn't tear) and Fifo if it has neither
+  pub fn set_vsync(&mut self, adapter: &Adapter, device: &Device, vsync: bool) {
+    let modes = self.surface.get_capabilities(adapter).present_modes;
+    let present_mode = [wgpu::PresentMode::Mailbox, wgpu::PresentMode::Immediate]
+      .into_iter()
+      .find(|mode| !vsync && modes.contains(mode))
+      .unwrap_or(wgpu::PresentMode::Fifo);
+    self.set_present_mode(device, present_mode);
+  }
+
+  // What the window is cleared to until the cursor changes it
+  pub fn set_color(&mut self, color: wgpu::Color) {
+    self.color.set(color);
+  }
+
+  // Switches to the surface's current preferred format if it changed, e.g. after moving to
+  // an HDR monitor, and rebuilds the pipelines drawing to it. Returns whether it changed.
+  pub fn update_format(&mut self, adapter: &Adapter, device: &Device) -> bool {
+    let format = match preferred_format(&self.surface, adapter) {
+      Ok(format) if format != self.config.format => format,
+      // a surface reporting nothing is most likely minimized, keep what we have
+      _ => return false,
+    };
+    log::info!(
+      "surface format of {:?} changed from {:?} to {:?}",
+      self.id(),
+      self.config.format,
+      format
+    );
+    self.config.format = format;
+    self.surface.configure(device, &self.config);
+    // the text overlay and screenshots pick their pipeline by format every frame, the
+    // tonemapper and fxaa are the only ones built for a single format
+    self.hdr.set_output_format(device, format);
+    let (width, height) = self.hdr.size();
+    self.fxaa.set_format(device, format, width, height);
+    true
+  }
+
+  // Reconfigures the surface after it was lost or outdated
+  pub fn reconfigure(&mut self, device: &Device) {
+    self.resize(device, self.size);
+  }
+
+  pub fn update(&mut self, queue: &Queue, dt: f32) {
+    self.color.update(dt);
+    self.exposure.update(queue, &self.hdr, dt);
+    self.compare.update(queue, self.size.width);
+  }
+
+  // Handles the events that only affect this window, see State::input for the shared ones
+  #[allow(
+    clippy::collapsible_match,
+    clippy::needless_bool_assign,
+    clippy::unnecessary_cast
+  )]
+  pub fn input(&mut self, event: &WindowEvent) -> bool {
+    match event {
+      WindowEvent::CursorMoved { position, .. } => self.cursor = Some([position.x, position.y]),
+      WindowEvent::CursorLeft { .. } => self.cursor = None,
+      _ => {}
+    }
+    match event {
+      WindowEvent::CursorEntered { .. } => {
+        self
+          .color
+          .ease_to(wgpu::Color::GREEN, COLOR_FADE, Easing::QuadOut);
+        true
+      }
+
+      WindowEvent::CursorLeft { .. } => {
+        self.click = false;
+        self
+          .color
+          .ease_to(wgpu::Color::BLACK, COLOR_FADE, Easing::QuadOut);
+        true
+      }
+
+      WindowEvent::MouseInput { button, state, .. } => {
+        if MouseButton::Left.eq(button) {
+          self.click = true;
+        } else {
+          self.click = false;
+        }
+        self
+          .compare
+          .set_dragging(self.click && *state == ElementState::Pressed);
+        false
+      }
+
+      WindowEvent::CursorMoved { position, .. } if self.compare.is_dragging() => {
+        self
+          .compare
+          .set_split(position.x as f32 / self.size.width as f32);
+        true
+      }
+
+      WindowEvent::CursorMoved { position, .. } => {
+        // error!("{:?}", self.click);
+        if self.click {
+          let color = wgpu::Color {
+            r: position.x as f64 / self.size.width as f64,
+            g: position.y as f64 / self.size.height as f64,
+            b: 1.0,
+            a: 1.0,
+          };
+          self.color.ease_to(color, COLOR_FADE, Easing::SineInOut);
+          self.click = false;
+          true
+        } else {
+          false
+        }
+      }
+
+      _ => false,
+    }
+  }
+
+  // The actions in keymap.rs that only affect this window, returns whether `action` is one
+  pub fn action(
+    &mut self,
+    device: &Device,
+    pipelines: &mut PipelineCache,
+    action: &str,
+    state: ElementState,
+  ) -> bool {
+    let pressed = state == ElementState::Pressed;
+    match action {
+      "rainbow_shader" => {
+        if pressed {
+          self.main_pipe = self.main_pipe_of(device, pipelines, self.variant);
+        } else {
+          self.main_pipe = self.main_pipe_of(device, pipelines, "rainbow");
+        }
+      }
+      // the rest only happen on the way down
+      _ if !pressed => return false,
+      "toggle_exclusive_fullscreen" | "toggle_fullscreen" => {
+        let mode = if action == "toggle_exclusive_fullscreen" {
+          FullscreenMode::Exclusive
+        } else {
+          FullscreenMode::Borderless
+        };
+        toggle_fullscreen_mode(&self.window, mode);
+        // winit sends Resized as well, but not on every platform for exclusive mode changes
+        self.resize(device, self.window.inner_size());
+      }
+      "exposure_mode" => self.exposure.toggle_mode(),
+      "compare_mode" => self.compare.cycle_mode(),
+      "exposure_up" => self.exposure.settings_mut().compensation += 0.5,
+      "exposure_down" => self.exposure.settings_mut().compensation -= 0.5,
+      _ => return false,
+    }
+    true
+  }
+}
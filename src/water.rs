@@ -0,0 +1,403 @@
+// A water plane drawn over the finished opaque scene with a pipeline of its own. Two copies
+// of a normal map scroll across it in different directions to make the waves. Where the
+// surface faces the camera you see through it, the scene color shifted along the normals.
+// At grazing angles the sky reflects off it instead, with the Fresnel term blending the two.
+// The pass can't read the target it draws into, so it copies the scene color first.
+
+use serde::{Deserialize, Serialize};
+use wgpu::{util::DeviceExt, BindGroup, BindGroupLayout, CommandEncoder, Device, Queue};
+
+use crate::{
+  gpu_memory::Tracked,
+  hdr::{HdrPipeline, HDR_FORMAT},
+  math,
+  mipmap::{mip_level_count, MipmapGenerator},
+  pipeline::water_pipe,
+  sampler::SamplerSettings,
+  stats::count_draw,
+};
+
+// Texels per side of the normal map
+const NORMAL_MAP_SIZE: u32 = 256;
+// Sine waves summed into the normal map's heights
+const WAVE_COUNT: usize = 24;
+const WAVE_SEED: u32 = 0x68e3_1da4;
+
+// The `[water]` section of the config file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WaterSettings {
+  // whether it's shown when the app starts
+  pub enabled: bool,
+  // y of the surface
+  pub height: f32,
+  // of the square it covers around the origin
+  pub half_extent: f32,
+  // normal map repeats per world unit
+  pub wave_scale: f32,
+  // normal map widths per second the waves scroll by
+  pub wave_speed: f32,
+  // how far the normals tilt, 0 is a mirror flat surface
+  pub wave_strength: f32,
+  // how far what's under the water shifts, in fractions of the screen
+  pub refraction: f32,
+  // linear rgb the water tints what's seen through it with
+  pub color: [f32; 3],
+}
+
+impl Default for WaterSettings {
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      height: 0.35,
+      half_extent: 5.0,
+      wave_scale: 0.4,
+      wave_speed: 0.03,
+      wave_strength: 0.6,
+      refraction: 0.03,
+      color: [0.45, 0.7, 0.75],
+    }
+  }
+}
+
+// Matches `WaterParams` in water.wgsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct WaterUniform {
+  // rgb: the tint, a: unused
+  color: [f32; 4],
+  // x: height, y: half extent, z: refraction, w: seconds
+  surface: [f32; 4],
+  // x: scale, y: speed, z: strength, w: unused
+  waves: [f32; 4],
+}
+
+impl WaterUniform {
+  fn new(settings: &WaterSettings, time: f32) -> Self {
+    let [r, g, b] = settings.color;
+    Self {
+      color: [r, g, b, 1.0],
+      surface: [
+        settings.height,
+        settings.half_extent,
+        settings.refraction,
+        time,
+      ],
+      waves: [
+        settings.wave_scale,
+        settings.wave_speed,
+        settings.wave_strength,
+        0.0,
+      ],
+    }
+  }
+}
+
+// The copy of the hdr color the water refracts, the size of the hdr target
+struct SceneColor {
+  texture: Tracked<wgpu::Texture>,
+  bind_group: BindGroup,
+  size: (u32, u32),
+}
+
+pub struct Water {
+  settings: WaterSettings,
+  // seconds, scrolls the normal maps
+  time: f32,
+  buffer: Tracked<wgpu::Buffer>,
+  normal_map: Tracked<wgpu::TextureView>,
+  normal_sampler: wgpu::Sampler,
+  scene_sampler: wgpu::Sampler,
+  layout: BindGroupLayout,
+  pipeline: wgpu::RenderPipeline,
+  // made on the first render(), again whenever the hdr target is resized
+  scene_color: Option<SceneColor>,
+}
+
+impl Water {
+  // `globals_layout` is group 0 of the scene pipelines and `environment_layout` the PBR
+  // environment's, the sky it reflects
+  pub fn new(
+    device: &Device,
+    queue: &Queue,
+    mipmaps: &mut MipmapGenerator,
+    format: wgpu::TextureFormat,
+    globals_layout: &BindGroupLayout,
+    environment_layout: &BindGroupLayout,
+  ) -> Self {
+    let settings = WaterSettings::default();
+    let buffer = Tracked::buffer(
+      device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Water Buffer"),
+        contents: bytemuck::bytes_of(&WaterUniform::new(&settings, 0.0)),
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+      }),
+    );
+
+    let mip_count = mip_level_count(NORMAL_MAP_SIZE, NORMAL_MAP_SIZE);
+    let size = wgpu::Extent3d {
+      width: NORMAL_MAP_SIZE,
+      height: NORMAL_MAP_SIZE,
+      depth_or_array_layers: 1,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+      label: Some("Water Normal Map"),
+      size,
+      mip_level_count: mip_count,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format: wgpu::TextureFormat::Rgba8Unorm,
+      usage: wgpu::TextureUsages::TEXTURE_BINDING
+        | wgpu::TextureUsages::RENDER_ATTACHMENT
+        | wgpu::TextureUsages::COPY_DST,
+      view_formats: &[],
+    });
+    queue.write_texture(
+      texture.as_image_copy(),
+      &wave_normals(NORMAL_MAP_SIZE),
+      wgpu::ImageDataLayout {
+        offset: 0,
+        bytes_per_row: std::num::NonZeroU32::new(4 * NORMAL_MAP_SIZE),
+        rows_per_image: std::num::NonZeroU32::new(NORMAL_MAP_SIZE),
+      },
+      size,
+    );
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+      label: Some("Water Mipmap Encoder"),
+    });
+    mipmaps.generate(
+      device,
+      &mut encoder,
+      &texture,
+      wgpu::TextureFormat::Rgba8Unorm,
+      mip_count,
+    );
+    queue.submit(Some(encoder.finish()));
+
+    let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility: wgpu::ShaderStages::FRAGMENT,
+      ty: wgpu::BindingType::Texture {
+        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        view_dimension: wgpu::TextureViewDimension::D2,
+        multisampled: false,
+      },
+      count: None,
+    };
+    let sampler_entry = |binding| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility: wgpu::ShaderStages::FRAGMENT,
+      ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+      count: None,
+    };
+    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Water Bind Group Layout"),
+      entries: &[
+        wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+          ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+          },
+          count: None,
+        },
+        texture_entry(1),
+        sampler_entry(2),
+        texture_entry(3),
+        sampler_entry(4),
+      ],
+    });
+    let pipeline = water_pipe(
+      device,
+      format,
+      &[globals_layout, &layout, environment_layout],
+    );
+
+    Self {
+      settings,
+      time: 0.0,
+      buffer,
+      normal_map: Tracked::view(
+        &texture,
+        texture.create_view(&wgpu::TextureViewDescriptor::default()),
+      ),
+      normal_sampler: device
+        .create_sampler(&SamplerSettings::trilinear().descriptor(Some("Water Normal Sampler"))),
+      scene_sampler: device
+        .create_sampler(&SamplerSettings::linear().descriptor(Some("Water Scene Sampler"))),
+      layout,
+      pipeline,
+      scene_color: None,
+    }
+  }
+
+  pub fn is_enabled(&self) -> bool {
+    self.settings.enabled
+  }
+
+  pub fn toggle(&mut self) -> bool {
+    self.settings.enabled = !self.settings.enabled;
+    self.settings.enabled
+  }
+
+  pub fn settings(&self) -> &WaterSettings {
+    &self.settings
+  }
+
+  pub fn set_settings(&mut self, queue: &Queue, settings: &WaterSettings) {
+    self.settings = settings.clone();
+    self.upload(queue);
+  }
+
+  // Scrolls the waves to where they are `time` seconds in
+  pub fn set_time(&mut self, queue: &Queue, time: f32) {
+    self.time = time;
+    if self.settings.enabled {
+      self.upload(queue);
+    }
+  }
+
+  fn upload(&self, queue: &Queue) {
+    let uniform = WaterUniform::new(&self.settings, self.time);
+    queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&uniform));
+  }
+
+  // Copies `hdr`'s color and draws the water over it, tested against its depth.
+  // `globals` and `environment` are the bind groups of the layouts new() was given.
+  pub fn render(
+    &mut self,
+    device: &Device,
+    encoder: &mut CommandEncoder,
+    hdr: &HdrPipeline,
+    globals: &BindGroup,
+    environment: &BindGroup,
+  ) {
+    let size = hdr.size();
+    if self.scene_color.as_ref().map(|color| color.size) != Some(size) {
+      self.scene_color = Some(self.create_scene_color(device, size));
+    }
+    let scene_color = self.scene_color.as_ref().unwrap();
+    encoder.copy_texture_to_texture(
+      hdr.texture().as_image_copy(),
+      scene_color.texture.as_image_copy(),
+      wgpu::Extent3d {
+        width: size.0,
+        height: size.1,
+        depth_or_array_layers: 1,
+      },
+    );
+
+    let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+      label: Some("Water Pass"),
+      color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+        view: hdr.view(),
+        resolve_target: None,
+        ops: wgpu::Operations {
+          load: wgpu::LoadOp::Load,
+          store: true,
+        },
+      })],
+      depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+        view: hdr.depth_view(),
+        depth_ops: Some(wgpu::Operations {
+          load: wgpu::LoadOp::Load,
+          store: true,
+        }),
+        stencil_ops: None,
+      }),
+    });
+    pass.set_pipeline(&self.pipeline);
+    pass.set_bind_group(0, globals, &[]);
+    pass.set_bind_group(1, &scene_color.bind_group, &[]);
+    pass.set_bind_group(2, environment, &[]);
+    // two triangles, made up in the vertex shader
+    count_draw();
+    pass.draw(0..6, 0..1);
+  }
+
+  fn create_scene_color(&self, device: &Device, (width, height): (u32, u32)) -> SceneColor {
+    let texture = Tracked::texture(device.create_texture(&wgpu::TextureDescriptor {
+      label: Some("Water Scene Color"),
+      size: wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+      },
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format: HDR_FORMAT,
+      usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+      view_formats: &[],
+    }));
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Water Bind Group"),
+      layout: &self.layout,
+      entries: &[
+        wgpu::BindGroupEntry {
+          binding: 0,
+          resource: self.buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+          binding: 1,
+          resource: wgpu::BindingResource::TextureView(&self.normal_map),
+        },
+        wgpu::BindGroupEntry {
+          binding: 2,
+          resource: wgpu::BindingResource::Sampler(&self.normal_sampler),
+        },
+        wgpu::BindGroupEntry {
+          binding: 3,
+          resource: wgpu::BindingResource::TextureView(&view),
+        },
+        wgpu::BindGroupEntry {
+          binding: 4,
+          resource: wgpu::BindingResource::Sampler(&self.scene_sampler),
+        },
+      ],
+    });
+    SceneColor {
+      texture,
+      bind_group,
+      size: (width, height),
+    }
+  }
+}
+
+// Rgba8 normals of ripples that tile, xy along the surface and z out of it. The heights are
+// sine waves with a whole number of crests across the map, so it repeats without seams.
+fn wave_normals(size: u32) -> Vec<u8> {
+  let mut state = WAVE_SEED;
+  let waves: Vec<([f32; 2], f32, f32)> = (0..WAVE_COUNT)
+    .map(|_| {
+      let mut crests = || (math::next_random(&mut state) * 12.0).round() - 6.0;
+      let (mut kx, kz) = (crests(), crests());
+      if kx == 0.0 && kz == 0.0 {
+        kx = 1.0;
+      }
+      // the longer waves are the higher ones
+      let amplitude = 0.04 / (kx * kx + kz * kz).sqrt();
+      let phase = math::next_random(&mut state) * std::f32::consts::TAU;
+      ([kx, kz], amplitude, phase)
+    })
+    .collect();
+  (0..size * size)
+    .flat_map(|i| {
+      let u = (i % size) as f32 / size as f32;
+      let v = (i / size) as f32 / size as f32;
+      // the slope of the heights along u and v, per map width
+      let mut slope = [0.0, 0.0];
+      for &([kx, kz], amplitude, phase) in &waves {
+        let angle = std::f32::consts::TAU * (kx * u + kz * v) + phase;
+        let d = amplitude * std::f32::consts::TAU * angle.cos();
+        slope[0] += d * kx;
+        slope[1] += d * kz;
+      }
+      let [x, y, z] = math::normalize([-slope[0], -slope[1], 1.0]);
+      [x, y, z, 1.0].map(|c| ((c * 0.5 + 0.5) * 255.0).round() as u8)
+    })
+    .collect()
+}
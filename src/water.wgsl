@@ -0,0 +1,84 @@
+// The water plane, see water.rs. Two scrolling copies of a normal map make the waves, the
+// Fresnel term blends between the sky reflecting off them and the scene seen through them,
+// shifted along the normals.
+
+#include "common.wgsl"
+
+// keep in sync with WaterUniform in water.rs
+struct WaterParams {
+    // rgb: the tint of what's under the surface
+    color: vec4<f32>,
+    // x: height, y: half extent, z: refraction, w: seconds
+    surface: vec4<f32>,
+    // x: normal map repeats per unit, y: scroll speed, z: strength
+    waves: vec4<f32>,
+};
+@group(1) @binding(0)
+var<uniform> water: WaterParams;
+@group(1) @binding(1)
+var normal_map: texture_2d<f32>;
+@group(1) @binding(2)
+var normal_sampler: sampler;
+// the hdr color of the scene before the water went in
+@group(1) @binding(3)
+var scene_color: texture_2d<f32>;
+@group(1) @binding(4)
+var scene_sampler: sampler;
+
+// PbrPipeline's environment, only the parts the reflection needs
+struct Environment {
+    // x: intensity, y: mip count of the specular cube
+    params: vec4<f32>,
+};
+@group(2) @binding(0)
+var<uniform> environment: Environment;
+@group(2) @binding(2)
+var specular_map: texture_cube<f32>;
+@group(2) @binding(3)
+var environment_sampler: sampler;
+
+// reflectance looking straight down at water
+const WATER_F0: f32 = 0.02;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+};
+
+// Two triangles covering the square, the corners' x and z are picked out of the bits
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
+    let x = f32((0x16u >> in_vertex_index) & 1u) * 2.0 - 1.0;
+    let z = f32((0x34u >> in_vertex_index) & 1u) * 2.0 - 1.0;
+    let extent = water.surface.y;
+    let world = vec3<f32>(x * extent, water.surface.x, z * extent);
+    var out: VertexOutput;
+    out.clip_position = globals.view_proj * vec4<f32>(world, 1.0);
+    out.world_position = world;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // the two layers scroll different ways at different sizes so they never line up
+    let uv = in.world_position.xz * water.waves.x;
+    let scroll = water.waves.y * water.surface.w;
+    let a = textureSample(normal_map, normal_sampler, uv + vec2<f32>(1.0, 0.6) * scroll).xyz;
+    let b = textureSample(normal_map, normal_sampler, uv * 1.7 - vec2<f32>(0.4, 1.0) * scroll).xyz;
+    // the map's xy lie along the surface, world x and z
+    let tilt = ((a.xy + b.xy) - 1.0) * water.waves.z;
+    let normal = normalize(vec3<f32>(tilt.x, 1.0, tilt.y));
+
+    let view = normalize(globals.camera_position.xyz - in.world_position);
+    let fresnel = WATER_F0 + (1.0 - WATER_F0) * pow(1.0 - max(dot(normal, view), 0.0), 5.0);
+
+    let reflected = reflect(-view, normal);
+    let sky = textureSampleLevel(specular_map, environment_sampler, reflected, 0.0).rgb
+        * environment.params.x;
+
+    let screen = in.clip_position.xy / vec2<f32>(textureDimensions(scene_color));
+    let refracted = clamp(screen + normal.xz * water.surface.z, vec2<f32>(0.0), vec2<f32>(1.0));
+    let below = textureSample(scene_color, scene_sampler, refracted).rgb * water.color.rgb;
+
+    return vec4<f32>(mix(below, sky, fresnel), 1.0);
+}
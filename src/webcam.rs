@@ -0,0 +1,185 @@
+use std::{
+  io,
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+  },
+};
+
+use image::RgbaImage;
+use v4l::{
+  buffer::Type, io::traits::CaptureStream, prelude::MmapStream, video::Capture, Device, Format,
+  FourCC,
+};
+use wgpu::Queue;
+
+use crate::gpu_memory::Tracked;
+
+// What's asked of the camera, it answers with the closest it has
+const WIDTH: u32 = 640;
+const HEIGHT: u32 = 480;
+// frames the driver fills while the last one is converted
+const BUFFERS: u32 = 4;
+
+// The pixel formats converted here, nearly every webcam has one of them
+#[derive(Debug, Clone, Copy)]
+enum PixelFormat {
+  // 4:2:2, two pixels in four bytes
+  Yuyv,
+  // every frame a JPEG
+  Mjpeg,
+}
+
+// A V4L2 camera streaming into a texture. Frames are captured and converted on a thread of
+// their own, update() uploads the newest one if there's been one since, and ones that came
+// in between are dropped. Like AnimatedTexture it's the same texture the whole time, so
+// bind groups made with its view see the video, and it's TEXTURE_BINDING for compute passes
+// to read.
+pub struct Webcam {
+  texture: Tracked<wgpu::Texture>,
+  latest: Arc<Mutex<Option<RgbaImage>>>,
+  // cleared when this is dropped, the capture thread ends after the frame it's waiting for
+  running: Arc<AtomicBool>,
+}
+
+impl Webcam {
+  // Opens /dev/video`index`
+  pub fn open(device: &wgpu::Device, index: usize) -> io::Result<Self> {
+    let camera = Device::new(index)?;
+    let (format, pixels) = negotiate(&camera)?;
+    log::info!(
+      "webcam {}: {}x{} {}",
+      index,
+      format.width,
+      format.height,
+      format.fourcc
+    );
+    let texture = Tracked::texture(device.create_texture(&wgpu::TextureDescriptor {
+      label: Some("Webcam Texture"),
+      size: wgpu::Extent3d {
+        width: format.width,
+        height: format.height,
+        depth_or_array_layers: 1,
+      },
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format: wgpu::TextureFormat::Rgba8UnormSrgb,
+      usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+      view_formats: &[],
+    }));
+
+    let latest = Arc::new(Mutex::new(None));
+    let running = Arc::new(AtomicBool::new(true));
+    let (frames, keep_running) = (latest.clone(), running.clone());
+    std::thread::spawn(move || {
+      let mut stream = match MmapStream::with_buffers(&camera, Type::VideoCapture, BUFFERS) {
+        Ok(stream) => stream,
+        Err(e) => {
+          log::warn!("webcam {}: {}", index, e);
+          return;
+        }
+      };
+      while keep_running.load(Ordering::Relaxed) {
+        let (data, metadata) = match stream.next() {
+          Ok(frame) => frame,
+          Err(e) => {
+            log::warn!("webcam {}: {}", index, e);
+            return;
+          }
+        };
+        let data = &data[..(metadata.bytesused as usize).min(data.len())];
+        if let Some(image) = convert(data, &format, pixels) {
+          *frames.lock().unwrap() = Some(image);
+        }
+      }
+    });
+    Ok(Self {
+      texture,
+      latest,
+      running,
+    })
+  }
+
+  pub fn create_view(&self) -> wgpu::TextureView {
+    self
+      .texture
+      .create_view(&wgpu::TextureViewDescriptor::default())
+  }
+
+  // Uploads the newest frame, returns whether there was one since the last call
+  pub fn update(&self, queue: &Queue) -> bool {
+    let Some(image) = self.latest.lock().unwrap().take() else {
+      return false;
+    };
+    let (width, height) = image.dimensions();
+    queue.write_texture(
+      self.texture.as_image_copy(),
+      &image,
+      wgpu::ImageDataLayout {
+        offset: 0,
+        bytes_per_row: std::num::NonZeroU32::new(4 * width),
+        rows_per_image: std::num::NonZeroU32::new(height),
+      },
+      self.texture.size(),
+    );
+    true
+  }
+}
+
+impl Drop for Webcam {
+  fn drop(&mut self) {
+    self.running.store(false, Ordering::Relaxed);
+  }
+}
+
+// Asks for YUYV, then MJPEG, at WIDTH x HEIGHT
+fn negotiate(camera: &Device) -> io::Result<(Format, PixelFormat)> {
+  let wanted = [(b"YUYV", PixelFormat::Yuyv), (b"MJPG", PixelFormat::Mjpeg)];
+  for (fourcc, pixels) in wanted {
+    let format = camera.set_format(&Format::new(WIDTH, HEIGHT, FourCC::new(fourcc)))?;
+    if format.fourcc == FourCC::new(fourcc) {
+      return Ok((format, pixels));
+    }
+  }
+  let format = camera.format()?;
+  Err(io::Error::new(
+    io::ErrorKind::Unsupported,
+    format!("the camera sends {}, not YUYV or MJPEG", format.fourcc),
+  ))
+}
+
+// A frame as RGBA, None if it's cut short or doesn't decode
+fn convert(data: &[u8], format: &Format, pixels: PixelFormat) -> Option<RgbaImage> {
+  let (width, height) = (format.width, format.height);
+  match pixels {
+    PixelFormat::Mjpeg => {
+      let image = image::load_from_memory_with_format(data, image::ImageFormat::Jpeg).ok()?;
+      let image = image.to_rgba8();
+      (image.dimensions() == (width, height)).then_some(image)
+    }
+    PixelFormat::Yuyv => {
+      // rows can be padded past their two bytes a pixel
+      let stride = (format.stride as usize).max(width as usize * 2);
+      if data.len() < stride * (height as usize - 1) + width as usize * 2 {
+        return None;
+      }
+      let mut rgba = Vec::with_capacity((width * height * 4) as usize);
+      for row in data.chunks(stride).take(height as usize) {
+        for quad in row[..width as usize * 2].chunks_exact(4) {
+          let (u, v) = (quad[1] as f32 - 128.0, quad[3] as f32 - 128.0);
+          for y in [quad[0], quad[2]] {
+            let y = y as f32;
+            rgba.extend([
+              (y + 1.402 * v) as u8,
+              (y - 0.344 * u - 0.714 * v) as u8,
+              (y + 1.772 * u) as u8,
+              255,
+            ]);
+          }
+        }
+      }
+      RgbaImage::from_raw(width, height, rgba)
+    }
+  }
+}
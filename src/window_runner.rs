@@ -1,67 +1,198 @@
+use std::{cell::Cell, rc::Rc};
+
+use instant::Instant;
 use winit::{
   event::*,
-  event_loop::{ControlFlow, EventLoop},
-  window::WindowBuilder,
+  event_loop::{ControlFlow, EventLoop, EventLoopWindowTarget},
+};
+
+use crate::{
+  bridge,
+  cli::CliArgs,
+  config::Config,
+  power::FramePacing,
+  screenshot,
+  state::{State, StateError, StateEvent},
+  storage::SettingsStorage,
+  video_wall,
 };
 
-use crate::state::State;
+fn open_window<T>(state: &mut State, target: &EventLoopWindowTarget<T>) {
+  let opened = state
+    .settings()
+    .window
+    .build(target)
+    .map_err(StateError::WindowCreation)
+    .and_then(|window| state.add_window(window));
+  if let Err(e) = opened {
+    log::error!("couldn't open another window: {}", e);
+  }
+}
 
-pub async fn run() {
+// Only returns if the renderer couldn't be set up, the event loop takes over the thread otherwise
+pub async fn run() -> Result<(), StateError> {
   env_logger::init();
 
+  // prints --help or what's wrong with the options and quits
+  let cli = CliArgs::from_env().unwrap_or_else(|e| e.exit());
+  let storage = SettingsStorage::platform_default();
+  let mut settings = Config::load(&storage);
+  cli.apply(&mut settings);
   let event_loop = EventLoop::new();
-  let window = WindowBuilder::new().build(&event_loop).unwrap();
+  bridge::set_waker(event_loop.create_proxy());
+  let mut wall = Vec::new();
+  if settings.window.span_monitors && !cli.headless {
+    wall = video_wall::open(&settings.window, &event_loop).map_err(StateError::WindowCreation)?;
+    if wall.is_empty() {
+      log::warn!("no monitors to span, opening a window instead");
+    }
+  }
+  let (window, tile) = match wall.is_empty() {
+    true => {
+      let window = cli
+        .window_settings(&settings)
+        .build(&event_loop)
+        .map_err(StateError::WindowCreation)?;
+      (window, None)
+    }
+    false => {
+      let (window, tile) = wall.remove(0);
+      (window, Some(tile))
+    }
+  };
 
-  let mut state = State::new(window).await;
+  // the wall's windows take the place of the extra ones
+  let extra_windows = match tile {
+    Some(_) => 0,
+    None if cli.headless => 0,
+    None => settings.window.windows.saturating_sub(1),
+  };
+  let mut state = State::new(window, settings, storage, &cli).await?;
+  for _ in 0..extra_windows {
+    open_window(&mut state, &event_loop);
+  }
+  if let Some(tile) = tile {
+    state.set_tile(state.window().id(), tile);
+  }
+  for (window, tile) in wall {
+    match state.add_window(window) {
+      Ok(id) => state.set_tile(id, tile),
+      Err(e) => log::error!("couldn't open a window on another monitor: {}", e),
+    }
+  }
 
-  event_loop.run(move |event, _, control_flow| {
+  // earliest time for the next redraw while throttled
+  let mut next_frame = Instant::now();
+  // --frames: frames of the primary window drawn so far, and whether the last one was, with
+  // the --out screenshot saved or not once it's done
+  let frame_limit = cli.frame_limit();
+  let mut frames = 0;
+  let mut finishing = false;
+  let saved = Rc::new(Cell::new(None));
+  event_loop.run(move |event, target, control_flow| {
     match event {
+      #[allow(clippy::collapsible_match)]
       Event::WindowEvent {
         ref event,
         window_id,
-      } if window_id == state.window().id() => {
-        if !state.input(event) {
+      } if state.has_window(window_id) => {
+        if !state.input(window_id, event) {
           // UPDATED!
           match event {
-            WindowEvent::CloseRequested
-            | WindowEvent::KeyboardInput {
-              input:
-                KeyboardInput {
-                  state: ElementState::Pressed,
-                  virtual_keycode: Some(VirtualKeyCode::Escape),
-                  ..
-                },
-              ..
-            } => *control_flow = ControlFlow::Exit,
+            // closing the primary window quits, the others just go away
+            WindowEvent::CloseRequested if state.is_primary(window_id) => {
+              *control_flow = ControlFlow::Exit
+            }
+            WindowEvent::CloseRequested => state.close_window(window_id),
             WindowEvent::Resized(physical_size) => {
-              state.resize(*physical_size);
+              state.resize(window_id, *physical_size);
             }
             WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
               // new_inner_size is &&mut so w have to dereference it twice
-              state.resize(**new_inner_size);
+              state.resize(window_id, **new_inner_size);
             }
             _ => {}
           }
         }
       }
-      Event::RedrawRequested(window_id) if window_id == state.window().id() => {
-        log::info!("started ! ");
+      Event::DeviceEvent {
+        event: DeviceEvent::MouseMotion { delta },
+        ..
+      } => state.mouse_motion(delta),
+      Event::MainEventsCleared => {
+        for command in bridge::take_commands() {
+          state.apply(command);
+        }
+        // the simulation and stats advance once per loop, however many windows there are
         state.update();
-        match state.render() {
+        if frame_limit.is_some_and(|limit| frames >= limit) && !finishing {
+          finishing = true;
+          match &cli.out {
+            Some(path) => {
+              let saved = saved.clone();
+              state.request_screenshot(screenshot::save_to(path.clone(), move |ok| {
+                saved.set(Some(ok))
+              }));
+            }
+            None => saved.set(Some(true)),
+          }
+        }
+        match saved.get() {
+          Some(true) => *control_flow = ControlFlow::Exit,
+          Some(false) => *control_flow = ControlFlow::ExitWithCode(1),
+          None => {}
+        }
+        for event in state.take_events() {
+          match event {
+            StateEvent::Picked(entity) => log::info!("picked {:?}", entity),
+            StateEvent::Quit => *control_flow = ControlFlow::Exit,
+            StateEvent::NewWindow => open_window(&mut state, target),
+          }
+        }
+      }
+      Event::RedrawRequested(window_id) if state.has_window(window_id) => {
+        log::info!("started ! ");
+        match state.render(window_id) {
+          Ok(_) if state.is_primary(window_id) => frames += 1,
           Ok(_) => {}
           // Reconfigure the surface if it's lost or outdated
-          Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => state.resize(state.size),
+          Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+            state.reconfigure(window_id)
+          }
           // The system is out of memory, we should probably quit
           Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
 
           Err(wgpu::SurfaceError::Timeout) => log::warn!("Surface timeout"),
         }
       }
-      Event::RedrawEventsCleared => {
-        // RedrawRequested will only trigger once, unless we manually
-        // request it.
-        state.window().request_redraw();
+      // --frames draws them as fast as it can, whatever the power saver would do
+      Event::RedrawEventsCleared if frame_limit.is_some() => {
+        *control_flow = ControlFlow::Poll;
+        state.request_redraw();
       }
+      Event::RedrawEventsCleared => match state.pacing() {
+        FramePacing::Continuous => {
+          // RedrawRequested will only trigger once, unless we manually
+          // request it.
+          *control_flow = ControlFlow::Poll;
+          state.request_redraw();
+        }
+        FramePacing::Throttled(interval) => {
+          let now = Instant::now();
+          if now >= next_frame {
+            state.request_redraw();
+            next_frame = now + interval;
+          }
+          *control_flow = ControlFlow::WaitUntil(next_frame);
+        }
+        // input, resizes and bridge commands wake the loop up again
+        FramePacing::OnDemand => {
+          *control_flow = match state.idle_wait() {
+            Some(wait) => ControlFlow::WaitUntil(Instant::now() + wait),
+            None => ControlFlow::Wait,
+          }
+        }
+      },
       _ => {}
     }
   });
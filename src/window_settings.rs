@@ -0,0 +1,137 @@
+use serde::{Deserialize, Serialize};
+use winit::{
+  dpi::LogicalSize,
+  error::OsError,
+  event_loop::EventLoopWindowTarget,
+  window::{Fullscreen, Window, WindowBuilder},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FullscreenMode {
+  Windowed,
+  // fullscreen window on the current monitor, fast to toggle
+  Borderless,
+  // takes over the display with a video mode change
+  Exclusive,
+}
+
+// The `[window]` section of settings.toml
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowSettings {
+  pub title: String,
+  pub width: u32,
+  pub height: u32,
+  pub resizable: bool,
+  pub min_width: u32,
+  pub min_height: u32,
+  pub fullscreen: FullscreenMode,
+  // windows opened at startup, each one draws the next shader variant
+  pub windows: u32,
+  // a borderless window on every monitor instead, together showing one picture of the
+  // scene as big as all of them, e.g. for a video wall
+  pub span_monitors: bool,
+  // waits for the display's refresh, off shows frames as soon as they're done where the
+  // surface can. The power saver keeps it on either way. F6 toggles it.
+  pub vsync: bool,
+  // what the windows are cleared to before the shader variants draw, RGBA from 0 to 1
+  pub clear_color: [f64; 4],
+  // false for --headless, never saved
+  #[serde(skip)]
+  pub visible: bool,
+}
+
+impl Default for WindowSettings {
+  fn default() -> Self {
+    Self {
+      title: "wgpu-learn".to_string(),
+      width: 800,
+      height: 600,
+      resizable: true,
+      min_width: 320,
+      min_height: 240,
+      fullscreen: FullscreenMode::Windowed,
+      windows: 1,
+      span_monitors: false,
+      vsync: true,
+      clear_color: [0.0, 0.0, 1.0, 1.0],
+      visible: true,
+    }
+  }
+}
+
+impl WindowSettings {
+  pub fn clear_color(&self) -> wgpu::Color {
+    let [r, g, b, a] = self.clear_color;
+    wgpu::Color { r, g, b, a }
+  }
+
+  pub fn build<T>(&self, event_loop: &EventLoopWindowTarget<T>) -> Result<Window, OsError> {
+    let window = WindowBuilder::new()
+      .with_title(&self.title)
+      .with_inner_size(LogicalSize::new(self.width, self.height))
+      .with_min_inner_size(LogicalSize::new(self.min_width, self.min_height))
+      .with_resizable(self.resizable)
+      .with_visible(self.visible)
+      .build(event_loop)?;
+    set_fullscreen_mode(&window, self.fullscreen);
+
+    // winit creates the canvas but leaves putting it on the page to us
+    #[cfg(target_arch = "wasm32")]
+    {
+      use winit::platform::web::WindowExtWebSys;
+      web_sys::window()
+        .and_then(|page| page.document())
+        .and_then(|document| document.body())
+        .and_then(|body| body.append_child(&window.canvas()).ok());
+      crate::touch::listen(&window.canvas());
+    }
+    Ok(window)
+  }
+}
+
+pub fn fullscreen_mode(window: &Window) -> FullscreenMode {
+  match window.fullscreen() {
+    None => FullscreenMode::Windowed,
+    Some(Fullscreen::Borderless(_)) => FullscreenMode::Borderless,
+    Some(Fullscreen::Exclusive(_)) => FullscreenMode::Exclusive,
+  }
+}
+
+pub fn set_fullscreen_mode(window: &Window, mode: FullscreenMode) {
+  let monitor = window.current_monitor();
+  let fullscreen = match mode {
+    FullscreenMode::Windowed => None,
+    FullscreenMode::Borderless => Some(Fullscreen::Borderless(monitor)),
+    FullscreenMode::Exclusive => {
+      // the monitor's native resolution at the highest refresh rate it offers
+      let video_mode = monitor.as_ref().and_then(|monitor| {
+        let native = monitor.size();
+        monitor
+          .video_modes()
+          .filter(|m| m.size() == native)
+          .max_by_key(|m| (m.refresh_rate_millihertz(), m.bit_depth()))
+      });
+      match video_mode {
+        Some(video_mode) => Some(Fullscreen::Exclusive(video_mode)),
+        None => {
+          log::warn!("no exclusive video mode available, using borderless fullscreen");
+          Some(Fullscreen::Borderless(monitor))
+        }
+      }
+    }
+  };
+  window.set_fullscreen(fullscreen);
+}
+
+// Switches to `mode`, or back to windowed if the window is already in it
+pub fn toggle_fullscreen_mode(window: &Window, mode: FullscreenMode) {
+  let next = if fullscreen_mode(window) == mode {
+    FullscreenMode::Windowed
+  } else {
+    mode
+  };
+  log::info!("window mode: {:?}", next);
+  set_fullscreen_mode(window, next);
+}
@@ -0,0 +1,439 @@
+// Rendering from a web worker into an OffscreenCanvas, so heavy frames don't block the
+// page's own UI.
+//
+// winit needs the DOM for its event loop, which workers don't have. So in worker mode
+// the main thread only owns the <canvas>: it transfers control of it to the worker and
+// forwards resizes, keys and bridge calls as messages. The worker drives an
+// OffscreenRenderer from requestAnimationFrame. It draws the scene, sky, exposure and
+// tonemap passes, but not the text overlay, boids or extra windows, which still need State.
+//
+// Worker mode needs OffscreenCanvas, transferControlToOffscreen and a page that opts in
+// by setting `window.wgpuLearnWorker` to the URL of a module worker like
+//
+//   import init, { worker_main } from "./pkg/wgpu_learn.js";
+//   self.onmessage = async (event) => {
+//     await init();
+//     worker_main(event.data.canvas, event.data.width, event.data.height);
+//   };
+//
+// Everywhere else run() renders on the main thread as before.
+
+use std::sync::Arc;
+
+use winit::dpi::PhysicalSize;
+
+use crate::{
+  adapter::request_device,
+  camera::Tile,
+  exposure::Exposure,
+  hdr::HdrPipeline,
+  pipeline::{main_pipe, PipelineCache},
+  skybox::Skybox,
+  state::StateError,
+  stats::count_draw,
+  uploader::Uploader,
+  viewport::{surface_config, SHADER_VARIANTS},
+};
+
+// radians per second, same as the windowed renderer
+const SKY_SPIN: f32 = 0.05;
+
+// What the main thread posts to the worker, see web::post for the JS shape
+#[derive(Debug, Clone, PartialEq)]
+pub enum WorkerMessage {
+  Resize { width: u32, height: u32 },
+  NextScene,
+  LoadScene(String),
+  SetParam { name: String, value: f64 },
+}
+
+// A windowless subset of State drawing to a surface made from an OffscreenCanvas
+pub struct OffscreenRenderer {
+  surface: wgpu::Surface,
+  config: wgpu::SurfaceConfiguration,
+  device: wgpu::Device,
+  queue: wgpu::Queue,
+  hdr: HdrPipeline,
+  exposure: Exposure,
+  skybox: Skybox,
+  variant: &'static str,
+  main_pipe: Arc<wgpu::RenderPipeline>,
+  pipelines: PipelineCache,
+  uploader: Uploader,
+  sky_yaw: f32,
+}
+
+impl OffscreenRenderer {
+  pub async fn new(
+    instance: &wgpu::Instance,
+    surface: wgpu::Surface,
+    width: u32,
+    height: u32,
+  ) -> Result<Self, StateError> {
+    let adapter = instance
+      .request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::HighPerformance,
+        compatible_surface: Some(&surface),
+        force_fallback_adapter: false,
+      })
+      .await
+      .ok_or(StateError::AdapterNotFound {
+        backends: wgpu::Backends::all(),
+      })?;
+    let (device, queue) = request_device(&adapter)
+      .await
+      .map_err(StateError::DeviceRequestFailed)?;
+
+    let config = surface_config(
+      &surface,
+      &adapter,
+      PhysicalSize::new(width.max(1), height.max(1)),
+    )?;
+    surface.configure(&device, &config);
+
+    let hdr = HdrPipeline::new(&device, &config, 1);
+    let exposure = Exposure::new(&device, &queue, &hdr);
+    // there's no asset reader in the worker yet, the sky is the built in gradient
+    let skybox = Skybox::new(&device, crate::skybox::gradient_cube(&device, &queue));
+    let variant = SHADER_VARIANTS[0];
+    let mut pipelines = PipelineCache::new();
+    let main_pipe = main_pipe(&device, &mut pipelines, variant, hdr.sample_count(), None);
+    Ok(Self {
+      surface,
+      config,
+      device,
+      queue,
+      hdr,
+      exposure,
+      skybox,
+      variant,
+      main_pipe,
+      pipelines,
+      uploader: Uploader::new(),
+      sky_yaw: 0.0,
+    })
+  }
+
+  pub fn resize(&mut self, width: u32, height: u32) {
+    if width == 0 || height == 0 {
+      return;
+    }
+    self.config.width = width;
+    self.config.height = height;
+    self.surface.configure(&self.device, &self.config);
+    self.hdr.resize(&self.device, width, height);
+    self.exposure.resize(&self.device, &self.hdr);
+  }
+
+  fn set_variant(&mut self, variant: &'static str) {
+    self.variant = variant;
+    self.main_pipe = main_pipe(
+      &self.device,
+      &mut self.pipelines,
+      variant,
+      self.hdr.sample_count(),
+      None,
+    );
+  }
+
+  pub fn handle(&mut self, message: WorkerMessage) {
+    match message {
+      WorkerMessage::Resize { width, height } => self.resize(width, height),
+      WorkerMessage::NextScene => {
+        let current = SHADER_VARIANTS
+          .iter()
+          .position(|&v| v == self.variant)
+          .unwrap_or(0);
+        self.set_variant(SHADER_VARIANTS[(current + 1) % SHADER_VARIANTS.len()]);
+      }
+      WorkerMessage::LoadScene(name) => match SHADER_VARIANTS.iter().find(|&&v| v == name) {
+        Some(variant) => self.set_variant(variant),
+        None => log::warn!("unknown scene {}", name),
+      },
+      WorkerMessage::SetParam { name, value } if name == "exposure" => {
+        self.exposure.settings_mut().compensation = value as f32;
+      }
+      WorkerMessage::SetParam { name, .. } => {
+        log::warn!("{} can't be changed while rendering in a worker", name)
+      }
+    }
+  }
+
+  pub fn frame(&mut self, dt: f32) -> Result<(), wgpu::SurfaceError> {
+    self.sky_yaw = (self.sky_yaw + SKY_SPIN * dt) % std::f32::consts::TAU;
+    self.exposure.update(&self.queue, &self.hdr, dt);
+
+    let output = self.surface.get_current_texture()?;
+    let view = output
+      .texture
+      .create_view(&wgpu::TextureViewDescriptor::default());
+    let mut encoder = self
+      .device
+      .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Worker Render Encoder"),
+      });
+    {
+      let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Worker Render Pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+          view: self.hdr.view(),
+          resolve_target: None,
+          ops: wgpu::Operations {
+            load: wgpu::LoadOp::Clear(wgpu::Color::BLUE),
+            store: true,
+          },
+        })],
+        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+          view: self.hdr.depth_view(),
+          depth_ops: Some(wgpu::Operations {
+            load: wgpu::LoadOp::Clear(1.0),
+            store: true,
+          }),
+          stencil_ops: None,
+        }),
+      });
+      pass.set_pipeline(&self.main_pipe);
+      count_draw();
+      pass.draw(0..3, 0..1);
+    }
+    let (width, height) = self.hdr.size();
+    let tile = Tile::whole(width as f32 / height.max(1) as f32);
+    let mut upload = self.uploader.with(&self.device, &mut encoder);
+    self
+      .skybox
+      .render(&mut upload, &self.hdr, self.sky_yaw, &tile);
+    self.exposure.meter(&mut encoder, &self.hdr);
+    self.hdr.tonemap(&mut encoder, &view);
+
+    self.uploader.submit(&self.queue, encoder);
+    output.present();
+    Ok(())
+  }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub use web::{forward, requested_script, spawn, supported, worker_main};
+
+#[cfg(target_arch = "wasm32")]
+mod web {
+  use std::{cell::RefCell, rc::Rc};
+
+  use js_sys::{Array, Object, Reflect};
+  use wasm_bindgen::{prelude::*, JsCast};
+  use web_sys::{
+    DedicatedWorkerGlobalScope, HtmlCanvasElement, KeyboardEvent, MessageEvent, OffscreenCanvas,
+    Worker, WorkerOptions, WorkerType,
+  };
+
+  use super::{OffscreenRenderer, WorkerMessage};
+  use crate::{bridge::BridgeCommand, screenshot::ScreenshotError};
+
+  // Global the page sets to the worker script's URL to opt in
+  const SCRIPT_GLOBAL: &str = "wgpuLearnWorker";
+
+  thread_local! {
+    // the render worker, when the main thread handed rendering off to one
+    static WORKER: RefCell<Option<Worker>> = const { RefCell::new(None) };
+  }
+
+  fn has_global(name: &str) -> bool {
+    Reflect::has(&js_sys::global(), &name.into()).unwrap_or(false)
+  }
+
+  // OffscreenCanvas exists and canvases can hand their control over to one
+  pub fn supported() -> bool {
+    has_global("OffscreenCanvas")
+      && has_global("Worker")
+      && js_sys::eval("'transferControlToOffscreen' in HTMLCanvasElement.prototype")
+        .ok()
+        .and_then(|value| value.as_bool())
+        .unwrap_or(false)
+  }
+
+  pub fn requested_script() -> Option<String> {
+    Reflect::get(&js_sys::global(), &SCRIPT_GLOBAL.into())
+      .ok()?
+      .as_string()
+  }
+
+  fn message(message: &WorkerMessage) -> Object {
+    let object = Object::new();
+    let set = |key: &str, value: JsValue| {
+      let _ = Reflect::set(&object, &key.into(), &value);
+    };
+    match message {
+      WorkerMessage::Resize { width, height } => {
+        set("kind", "resize".into());
+        set("width", (*width).into());
+        set("height", (*height).into());
+      }
+      WorkerMessage::NextScene => set("kind", "next_scene".into()),
+      WorkerMessage::LoadScene(name) => {
+        set("kind", "scene".into());
+        set("name", name.into());
+      }
+      WorkerMessage::SetParam { name, value } => {
+        set("kind", "param".into());
+        set("name", name.into());
+        set("value", (*value).into());
+      }
+    }
+    object
+  }
+
+  fn parse(data: &JsValue) -> Option<WorkerMessage> {
+    let get = |key: &str| Reflect::get(data, &key.into()).ok();
+    let number = |key: &str| get(key).and_then(|v| v.as_f64());
+    match get("kind")?.as_string()?.as_str() {
+      "resize" => Some(WorkerMessage::Resize {
+        width: number("width")? as u32,
+        height: number("height")? as u32,
+      }),
+      "next_scene" => Some(WorkerMessage::NextScene),
+      "scene" => Some(WorkerMessage::LoadScene(get("name")?.as_string()?)),
+      "param" => Some(WorkerMessage::SetParam {
+        name: get("name")?.as_string()?,
+        value: number("value")?,
+      }),
+      _ => None,
+    }
+  }
+
+  fn post(message: &WorkerMessage) {
+    WORKER.with(|worker| {
+      if let Some(worker) = worker.borrow().as_ref() {
+        let _ = worker.post_message(&self::message(message));
+      }
+    });
+  }
+
+  // Sends bridge commands on to the worker when there is one, hands them back otherwise
+  pub fn forward(command: BridgeCommand) -> Option<BridgeCommand> {
+    if WORKER.with(|worker| worker.borrow().is_none()) {
+      return Some(command);
+    }
+    match command {
+      BridgeCommand::SetParam { name, value } => post(&WorkerMessage::SetParam { name, value }),
+      BridgeCommand::LoadScene(name) => post(&WorkerMessage::LoadScene(name)),
+      BridgeCommand::Screenshot(reply) => reply(Err(ScreenshotError::Unavailable(
+        "screenshots aren't supported while rendering in a worker",
+      ))),
+      BridgeCommand::SetPlantRules(_) => {
+        log::warn!("plant rules can't be changed while rendering in a worker")
+      }
+      BridgeCommand::Bind { .. } => {
+        log::warn!("keys can't be rebound while rendering in a worker")
+      }
+      // the worker only draws the shader, there's no camera to move
+      BridgeCommand::Touch { .. } => {}
+    }
+    None
+  }
+
+  fn canvas_size(canvas: &HtmlCanvasElement) -> (u32, u32) {
+    let scale = web_sys::window().map_or(1.0, |w| w.device_pixel_ratio());
+    (
+      (canvas.client_width() as f64 * scale) as u32,
+      (canvas.client_height() as f64 * scale) as u32,
+    )
+  }
+
+  // Main thread side: creates the canvas, transfers it to a new worker running `script`
+  // and forwards resizes and keys to it
+  pub fn spawn(script: &str) -> Result<(), JsValue> {
+    let page = web_sys::window().ok_or("no window")?;
+    let document = page.document().ok_or("no document")?;
+    let canvas: HtmlCanvasElement = document.create_element("canvas")?.unchecked_into();
+    canvas.style().set_property("width", "100%")?;
+    canvas.style().set_property("height", "100%")?;
+    document.body().ok_or("no body")?.append_child(&canvas)?;
+
+    let offscreen = canvas.transfer_control_to_offscreen()?;
+    let (width, height) = canvas_size(&canvas);
+    let options = WorkerOptions::new();
+    options.set_type(WorkerType::Module);
+    let worker = Worker::new_with_options(script, &options)?;
+
+    let init = Object::new();
+    Reflect::set(&init, &"canvas".into(), &offscreen)?;
+    Reflect::set(&init, &"width".into(), &width.into())?;
+    Reflect::set(&init, &"height".into(), &height.into())?;
+    worker.post_message_with_transfer(&init, &Array::of1(&offscreen))?;
+    WORKER.with(|slot| *slot.borrow_mut() = Some(worker));
+
+    let on_resize = Closure::<dyn FnMut()>::new(move || {
+      let (width, height) = canvas_size(&canvas);
+      post(&WorkerMessage::Resize { width, height });
+    });
+    page.add_event_listener_with_callback("resize", on_resize.as_ref().unchecked_ref())?;
+    on_resize.forget();
+
+    let on_key = Closure::<dyn FnMut(KeyboardEvent)>::new(|event: KeyboardEvent| {
+      if event.code() == "Tab" {
+        event.prevent_default();
+        post(&WorkerMessage::NextScene);
+      }
+    });
+    page.add_event_listener_with_callback("keydown", on_key.as_ref().unchecked_ref())?;
+    on_key.forget();
+
+    log::info!("rendering in a worker");
+    Ok(())
+  }
+
+  // Worker side entry point, called by the worker script with the transferred canvas
+  #[wasm_bindgen]
+  pub async fn worker_main(
+    canvas: OffscreenCanvas,
+    width: u32,
+    height: u32,
+  ) -> Result<(), JsValue> {
+    let scope: DedicatedWorkerGlobalScope = js_sys::global().unchecked_into();
+    let instance = wgpu::Instance::default();
+    let surface = instance
+      .create_surface_from_offscreen_canvas(&canvas)
+      .map_err(|e| e.to_string())?;
+    let renderer = OffscreenRenderer::new(&instance, surface, width, height)
+      .await
+      .map_err(|e| e.to_string())?;
+    let renderer = Rc::new(RefCell::new(renderer));
+
+    let on_message = {
+      let renderer = renderer.clone();
+      Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+        match parse(&event.data()) {
+          Some(message) => renderer.borrow_mut().handle(message),
+          None => log::warn!("ignoring unknown worker message"),
+        }
+      })
+    };
+    scope.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+    on_message.forget();
+
+    // requestAnimationFrame loop, the closure has to hold on to itself to reschedule
+    let frame: Rc<RefCell<Option<Closure<dyn FnMut(f64)>>>> = Rc::new(RefCell::new(None));
+    let next = frame.clone();
+    let mut last = None;
+    *frame.borrow_mut() = Some(Closure::new(move |now: f64| {
+      let dt = last.map_or(0.0, |last: f64| ((now - last) / 1000.0) as f32);
+      last = Some(now);
+      match renderer.borrow_mut().frame(dt) {
+        Ok(()) => {}
+        Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+          let mut renderer = renderer.borrow_mut();
+          let (width, height) = (renderer.config.width, renderer.config.height);
+          renderer.resize(width, height);
+        }
+        Err(e) => log::warn!("{:?}", e),
+      }
+      let scope: DedicatedWorkerGlobalScope = js_sys::global().unchecked_into();
+      if let Some(callback) = next.borrow().as_ref() {
+        let _ = scope.request_animation_frame(callback.as_ref().unchecked_ref());
+      }
+    }));
+    if let Some(callback) = frame.borrow().as_ref() {
+      scope.request_animation_frame(callback.as_ref().unchecked_ref())?;
+    }
+    Ok(())
+  }
+}